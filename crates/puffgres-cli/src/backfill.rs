@@ -2,23 +2,109 @@
 //!
 //! Scans existing table data and syncs to turbopuffer.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::future::Future;
 use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use tokio::sync::oneshot;
-use tracing::{debug, info, warn};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn, Instrument};
 
+use puffgres_core::doc_size::action_size;
 use puffgres_core::{
-    extract_id, Action, BatchConfig, Batcher, DocumentId, IdentityTransformer, JsTransformer,
-    Mapping, TransformType, Transformer, Value, WriteRequest,
+    extract_id, Action, BatchConfig, Batcher, DocumentId, DocumentValidator, IdentityTransformer,
+    JsTransformer, Mapping, MembershipConfig, OversizeAction, Predicate, RowEvent, SizeStats,
+    TransformType, Transformer, Value, VersioningMode, WriteRequest, VECTOR_ATTRIBUTE,
 };
-use puffgres_pg::{BackfillConfig, BackfillScanProgress, BackfillScanner, PostgresStateStore};
+use puffgres_pg::{
+    BackfillConfig, BackfillObserver, BackfillScanProgress, BackfillScanner, IdRange,
+};
+use puffgres_state::{BackfillPartition, StateStore};
 
 use crate::config::ProjectConfig;
-use crate::env::{get_max_retries, get_transform_batch_size, get_upload_batch_size};
+use crate::embeddings::{embed_request, EmbeddingClient};
+use crate::env::{
+    get_backfill_copy, get_backfill_progress_interval, get_backfill_progress_rows, get_max_retries,
+    get_transform_batch_size, get_upload_batch_size, get_upload_concurrency,
+};
+use crate::run_id;
+use crate::runner::{chunk_rows, row_sizes};
+use crate::sinks::SinkWriters;
+use crate::strict::{StrictCheck, StrictMode};
+use crate::tp::TurbopufferClient;
+
+/// Uploads backfill chunks with a bounded number of writes in flight.
+///
+/// Each chunk is still a single `TurbopufferClient::write` with its own
+/// retries. Backfill only upserts and every row is read once, so the order
+/// chunks land in doesn't matter; callers drain the pool before saving
+/// progress so a resume never skips rows that weren't written.
+struct UploadPool {
+    client: Arc<TurbopufferClient>,
+    permits: Arc<Semaphore>,
+    tasks: JoinSet<Result<usize>>,
+}
+
+impl UploadPool {
+    fn new(client: Arc<TurbopufferClient>, concurrency: usize) -> Self {
+        Self {
+            client,
+            permits: Arc::new(Semaphore::new(concurrency)),
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Start writing a chunk once a slot is free. Returns the rows upserted
+    /// or patched by writes that finished in the meantime.
+    async fn submit(&mut self, namespace: String, params: rs_puff::WriteParams) -> Result<usize> {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .context("Upload pool closed")?;
+        let client = Arc::clone(&self.client);
+        let rows = params.upsert_rows.as_ref().map_or(0, |r| r.len())
+            + params.patch_rows.as_ref().map_or(0, |r| r.len());
+        self.tasks.spawn(
+            async move {
+                client.write(&namespace, params).await?;
+                drop(permit);
+                Ok(rows)
+            }
+            .in_current_span(),
+        );
+
+        let mut upserted = 0;
+        while let Some(result) = self.tasks.try_join_next() {
+            upserted += result.context("Upload task panicked")??;
+        }
+        Ok(upserted)
+    }
+
+    /// Another pool sharing this one's client and slots, so writes from
+    /// both together stay within the concurrency.
+    fn sibling(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            permits: Arc::clone(&self.permits),
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Wait for every write in flight. Returns the rows they upserted.
+    async fn drain(&mut self) -> Result<usize> {
+        let mut upserted = 0;
+        while let Some(result) = self.tasks.join_next().await {
+            upserted += result.context("Upload task panicked")??;
+        }
+        Ok(upserted)
+    }
+}
 
 /// Shared state for the progress spinner.
 struct SpinnerState {
@@ -26,6 +112,190 @@ struct SpinnerState {
     done: bool,
 }
 
+/// A progress line, followed by the throttle when one is set.
+fn progress_line(progress: &BackfillScanProgress, limits: &BackfillLimits, frame: usize) -> String {
+    if limits.is_unlimited() {
+        progress.format(frame)
+    } else {
+        format!("{} (throttled to {})", progress.format(frame), limits)
+    }
+}
+
+/// Draws backfill progress as a spinner line on stdout.
+///
+/// A background task redraws the line until the backfill completes or the
+/// observer is dropped.
+pub struct TerminalProgress {
+    state: Arc<Mutex<SpinnerState>>,
+    limits: BackfillLimits,
+}
+
+impl TerminalProgress {
+    /// Start drawing, showing `limits` next to the progress. Nothing is shown
+    /// until the first progress update.
+    pub fn start(limits: BackfillLimits) -> Self {
+        let state = Arc::new(Mutex::new(SpinnerState {
+            progress: None,
+            done: false,
+        }));
+        let spinner_state = Arc::clone(&state);
+        tokio::spawn(async move {
+            let mut spinner_frame: usize = 0;
+            loop {
+                tokio::time::sleep(Duration::from_millis(80)).await;
+                let state = spinner_state.lock().unwrap();
+                if state.done {
+                    break;
+                }
+                if let Some(ref progress) = state.progress {
+                    print!("\r{}", progress_line(progress, &limits, spinner_frame));
+                    io::stdout().flush().ok();
+                    spinner_frame = spinner_frame.wrapping_add(1);
+                }
+            }
+        });
+        Self { state, limits }
+    }
+}
+
+impl BackfillObserver for TerminalProgress {
+    fn on_progress(&self, progress: &BackfillScanProgress) {
+        self.state.lock().unwrap().progress = Some(progress.clone());
+    }
+
+    fn on_complete(&self, progress: &BackfillScanProgress) {
+        self.state.lock().unwrap().done = true;
+        println!("\r✓ {}", progress_line(progress, &self.limits, 0));
+        println!("\nBackfill complete!");
+    }
+}
+
+impl Drop for TerminalProgress {
+    fn drop(&mut self) {
+        // A backfill that failed leaves the spinner line unfinished
+        let mut state = self.state.lock().unwrap();
+        if !state.done && state.progress.is_some() {
+            println!();
+        }
+        state.done = true;
+    }
+}
+
+/// Decides when backfill progress is written to the state store.
+///
+/// Saving after every scanner batch costs a Postgres round trip per batch, so
+/// progress is saved once enough time has passed or enough rows have been read.
+struct ProgressSaver {
+    interval: Duration,
+    rows: i64,
+    last_saved_at: Instant,
+    last_saved_rows: i64,
+}
+
+impl ProgressSaver {
+    fn new(interval: Duration, rows: i64, processed_rows: i64, now: Instant) -> Self {
+        Self {
+            interval,
+            rows,
+            last_saved_at: now,
+            last_saved_rows: processed_rows,
+        }
+    }
+
+    /// Whether progress should be saved at `processed_rows`.
+    fn is_due(&self, processed_rows: i64, now: Instant) -> bool {
+        processed_rows - self.last_saved_rows >= self.rows
+            || now.duration_since(self.last_saved_at) >= self.interval
+    }
+
+    /// Record that progress was saved.
+    fn saved(&mut self, processed_rows: i64, now: Instant) {
+        self.last_saved_at = now;
+        self.last_saved_rows = processed_rows;
+    }
+}
+
+/// Caps on how fast a backfill reads, so a large one doesn't saturate the
+/// primary. Rows are written as they're read, so the row cap paces uploads
+/// too.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BackfillLimits {
+    /// Most rows read per second.
+    pub max_rows_per_sec: Option<u32>,
+    /// Most scanner queries per second.
+    pub max_read_qps: Option<u32>,
+}
+
+impl BackfillLimits {
+    /// Whether no cap is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_rows_per_sec.is_none() && self.max_read_qps.is_none()
+    }
+
+    /// Each of `parts` scans' share of the limits.
+    fn split(&self, parts: usize) -> Self {
+        let share = |limit: u32| (limit / parts.max(1) as u32).max(1);
+        Self {
+            max_rows_per_sec: self.max_rows_per_sec.map(share),
+            max_read_qps: self.max_read_qps.map(share),
+        }
+    }
+}
+
+impl fmt::Display for BackfillLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut limits = Vec::new();
+        if let Some(rows) = self.max_rows_per_sec {
+            limits.push(format!("{} rows/s", rows));
+        }
+        if let Some(qps) = self.max_read_qps {
+            limits.push(format!("{} reads/s", qps));
+        }
+        write!(f, "{}", limits.join(", "))
+    }
+}
+
+/// Spaces scanner reads out to stay within [`BackfillLimits`].
+///
+/// Each read pushes the next one back by as long as its rows and the query
+/// itself are allowed to take. Time spent transforming and uploading counts
+/// towards that wait, but a slow stretch doesn't build up credit for a burst
+/// of reads afterwards.
+struct Throttle {
+    limits: BackfillLimits,
+    next_read: Option<Instant>,
+}
+
+impl Throttle {
+    fn new(limits: BackfillLimits) -> Self {
+        Self {
+            limits,
+            next_read: None,
+        }
+    }
+
+    /// How long to wait before reading at `now`.
+    fn delay(&self, now: Instant) -> Duration {
+        self.next_read
+            .map_or(Duration::ZERO, |next| next.saturating_duration_since(now))
+    }
+
+    /// Record a read of `rows` rows that started at `started`.
+    fn read(&mut self, rows: usize, started: Instant) {
+        if self.limits.is_unlimited() {
+            return;
+        }
+        let mut interval = Duration::ZERO;
+        if let Some(max_rows) = self.limits.max_rows_per_sec {
+            interval = interval.max(Duration::from_secs_f64(rows as f64 / max_rows as f64));
+        }
+        if let Some(qps) = self.limits.max_read_qps {
+            interval = interval.max(Duration::from_secs_f64(1.0 / qps as f64));
+        }
+        self.next_read = Some(started + interval);
+    }
+}
+
 /// Wrapper for different transformer types.
 enum MappingTransformer {
     Identity(IdentityTransformer),
@@ -44,57 +314,115 @@ impl MappingTransformer {
     }
 }
 
+/// Create an identity transformer for a mapping's selected, renamed, typed
+/// and flattened columns, computed attributes and vector column.
+fn identity_transformer(mapping: &Mapping) -> IdentityTransformer {
+    IdentityTransformer::new(mapping.columns.clone())
+        .with_renames(mapping.renames.clone())
+        .with_types(mapping.types.clone())
+        .with_flatten(mapping.flatten.clone())
+        .with_computed(mapping.computed.clone())
+        .with_vector(mapping.vector.clone())
+}
+
+/// Create a JS transformer that knows which mapping it runs for.
+fn js_transformer(mapping: &Mapping, path: &str) -> JsTransformer {
+    JsTransformer::new(path).with_migration(
+        &mapping.name,
+        &mapping.namespace,
+        &mapping.source.describe(),
+    )
+}
+
 /// Create the appropriate transformer for a mapping.
 fn create_transformer(mapping: &Mapping) -> MappingTransformer {
     match &mapping.transform {
         Some(config) if config.transform_type == TransformType::Js => {
             if let Some(path) = &config.path {
-                MappingTransformer::Js(JsTransformer::new(path))
+                MappingTransformer::Js(js_transformer(mapping, path))
             } else {
                 // No path specified, use identity
-                MappingTransformer::Identity(IdentityTransformer::new(mapping.columns.clone()))
+                MappingTransformer::Identity(identity_transformer(mapping))
             }
         }
-        _ => MappingTransformer::Identity(IdentityTransformer::new(mapping.columns.clone())),
+        _ => MappingTransformer::Identity(identity_transformer(mapping)),
     }
 }
 
-/// Run the backfill for a specific mapping.
+/// Run the backfill for a specific mapping, reading through `snapshot` if
+/// one was exported, and reporting progress to `observer`.
+///
+/// With `partitions` above 1, the ID's key space is split into that many
+/// ranges, scanned concurrently and saved separately, so each resumes on
+/// its own. Resuming a partitioned backfill reuses its saved ranges.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_backfill(
     config: &ProjectConfig,
+    state_store: Arc<dyn StateStore>,
     mapping: &Mapping,
     batch_size: u32,
     resume: bool,
+    concurrency: Option<usize>,
+    partitions: usize,
+    limits: &BackfillLimits,
+    strict: &StrictMode,
+    snapshot: Option<&str>,
+    observer: &dyn BackfillObserver,
 ) -> Result<()> {
     // Load batch and retry configuration from environment
     let transform_batch_size = get_transform_batch_size();
     let upload_batch_size = get_upload_batch_size();
+    let concurrency = concurrency.unwrap_or_else(get_upload_concurrency);
     let max_retries = get_max_retries();
+    let progress_interval = get_backfill_progress_interval();
+    let progress_rows = get_backfill_progress_rows();
+
+    if !strict.is_empty() {
+        info!(checks = %strict, "Strict mode: treating these warnings as errors");
+    }
+
+    // A page larger than a second's worth of rows would be read in one burst
+    let batch_size = match limits.max_rows_per_sec {
+        Some(max_rows) if max_rows < batch_size => {
+            info!(
+                batch_size,
+                max_rows_per_sec = max_rows,
+                "Reducing the batch size to the row limit"
+            );
+            max_rows
+        }
+        _ => batch_size,
+    };
+    if !limits.is_unlimited() {
+        info!(limits = %limits, "Throttling backfill reads");
+    }
 
     info!(
         mapping = %mapping.name,
         namespace = %mapping.namespace,
-        table = format!("{}.{}", mapping.source.schema, mapping.source.table),
+        table = %mapping.source.describe(),
         batch_size,
         transform_batch_size,
         upload_batch_size,
+        concurrency,
+        partitions,
         max_retries,
+        progress_interval_secs = progress_interval.as_secs(),
+        progress_rows,
         resume,
         "Starting backfill"
     );
 
-    // Connect to state store
-    let state_store = PostgresStateStore::connect(&config.postgres_connection_string()?)
-        .await
-        .context("Failed to connect to state store")?;
-
     // Check for existing progress if resuming
-    let existing_progress = if resume {
-        state_store.get_backfill_progress(&mapping.name).await?
+    let (existing_progress, existing_partitions) = if resume {
+        (
+            state_store.get_backfill_progress(&mapping.name).await?,
+            state_store.get_backfill_partitions(&mapping.name).await?,
+        )
     } else {
         // Clear any existing progress
         state_store.clear_backfill_progress(&mapping.name).await?;
-        None
+        (None, vec![])
     };
 
     // Configure backfill scanner
@@ -103,200 +431,606 @@ pub async fn run_backfill(
         connection_string: config.postgres_connection_string()?,
         schema: mapping.source.schema.clone(),
         table: mapping.source.table.clone(),
-        id_column: mapping.id.column.clone(),
+        id_columns: mapping.id.columns().to_vec(),
         columns: get_backfill_columns(mapping),
         batch_size,
+        filter: get_backfill_filter(mapping),
+        snapshot: snapshot.map(str::to_string),
+        text_columns: get_backfill_text_columns(mapping),
+        query: mapping.source.query.clone(),
+        range: IdRange::default(),
+        copy: get_backfill_copy(),
     };
 
-    let mut scanner = BackfillScanner::new(backfill_config)
-        .await
-        .context("Failed to create backfill scanner")?;
-
-    // Resume from checkpoint if available
-    if let Some(progress) = existing_progress {
-        if let Some(last_id) = progress.last_id {
+    let parts: Vec<ScanPart> = if !existing_partitions.is_empty() {
+        if partitions > 1 && partitions != existing_partitions.len() {
             info!(
-                last_id = %last_id,
-                processed = progress.processed_rows,
-                "Resuming from checkpoint"
+                partitions = existing_partitions.len(),
+                "Resuming with the ranges of the interrupted backfill"
             );
-            scanner.resume_from(last_id, progress.processed_rows);
         }
-    }
+        existing_partitions.iter().map(ScanPart::resumed).collect()
+    } else if partitions > 1 {
+        plan_partitions(state_store.as_ref(), mapping, &backfill_config, partitions).await?
+    } else {
+        let mut part = ScanPart::whole(IdRange::default());
+        if let Some(progress) = existing_progress {
+            if let Some(last_id) = progress.last_id {
+                part.last_id = Some(last_id);
+                part.processed_rows = progress.processed_rows;
+                part.upserted_rows = progress.upserted_rows;
+            }
+        }
+        vec![part]
+    };
 
     // Initialize turbopuffer client
-    let tp_client = rs_puff::Client::new(config.turbopuffer_api_key()?);
+    let tp_client = Arc::new(TurbopufferClient::new(
+        config.turbopuffer_api_key()?,
+        max_retries,
+    ));
+    let uploads = UploadPool::new(Arc::clone(&tp_client), concurrency);
+    let sinks = SinkWriters::start(config, std::slice::from_ref(mapping), upload_batch_size)?;
+    let embedder =
+        EmbeddingClient::for_mappings(config, std::slice::from_ref(mapping), max_retries)?;
+
+    let ctx = ScanContext {
+        mapping,
+        backfill_config,
+        state_store: state_store.as_ref(),
+        embedder: embedder.as_ref(),
+        sinks: &sinks,
+        strict,
+        limits: limits.split(parts.len()),
+        transform_batch_size,
+        upload_batch_size,
+        progress_interval,
+        progress_rows,
+        observer,
+        progress: Mutex::new(parts.iter().map(ScanPart::progress).collect()),
+        stop: AtomicBool::new(false),
+    };
+
+    // Scans run side by side on this task, sharing the upload slots
+    let scans = parts
+        .iter()
+        .enumerate()
+        .filter(|(_, part)| !part.completed)
+        .map(|(index, part)| scan_part(&ctx, index, part, uploads.sibling()));
+    let mut doc_sizes = SizeStats::new();
+    let mut result = Ok(());
+    for scanned in join_all(scans.collect()).await {
+        match scanned {
+            Ok(sizes) => doc_sizes.merge(&sizes),
+            Err(e) if result.is_ok() => result = Err(e),
+            Err(e) => warn!(mapping = %mapping.name, error = %e, "Backfill scan failed"),
+        }
+    }
+
+    // Errors are held until the last safe progress of every scan has been
+    // saved, so a failed backfill resumes from where it got to
+    if let Err(e) = result {
+        if let Err(save_err) = ctx.save_summary("failed").await {
+            warn!(error = %save_err, "Failed to save backfill progress");
+        }
+        return Err(e);
+    }
+
+    // The sinks have every row once their queues are written
+    let final_progress = ctx.combined();
+    sinks.finish(None).await;
+
+    // Mark as complete
+    state_store
+        .save_backfill_progress(&final_progress.to_record(
+            &mapping.name,
+            "completed",
+            run_id::current(),
+        ))
+        .await?;
+
+    observer.on_complete(&final_progress);
+
+    let writes = tp_client.stats();
+    info!(
+        mapping = %mapping.name,
+        writes = writes.writes,
+        rows_upserted = writes.rows_upserted,
+        retries = writes.retries,
+        rate_limited = writes.rate_limited,
+        requests = writes.requests,
+        "Backfill writes"
+    );
+    info!(
+        mapping = %mapping.name,
+        documents = doc_sizes.count(),
+        doc_bytes_mean = doc_sizes.mean(),
+        doc_bytes_p50 = doc_sizes.quantile(0.5),
+        doc_bytes_p99 = doc_sizes.quantile(0.99),
+        doc_bytes_max = doc_sizes.max(),
+        "Backfill document sizes"
+    );
+
+    Ok(())
+}
+
+/// Split the key space into ranges and save them, so an interrupted
+/// backfill resumes with the same ones. Falls back to a single scan when
+/// there's nothing to split on.
+async fn plan_partitions(
+    state_store: &dyn StateStore,
+    mapping: &Mapping,
+    backfill_config: &BackfillConfig,
+    partitions: usize,
+) -> Result<Vec<ScanPart>> {
+    let ranges = BackfillScanner::new(backfill_config.clone())
+        .await
+        .context("Failed to create backfill scanner")?
+        .partition_ranges(partitions)
+        .await
+        .context("Failed to split the table into ranges")?;
+    if ranges.len() < partitions {
+        warn!(
+            mapping = %mapping.name,
+            partitions,
+            ranges = ranges.len(),
+            "Found fewer ranges than partitions; ANALYZE the table if its statistics are missing"
+        );
+    }
+    if ranges.len() == 1 {
+        return Ok(vec![ScanPart::whole(IdRange::default())]);
+    }
+
+    let mut parts = Vec::with_capacity(ranges.len());
+    for (i, range) in ranges.into_iter().enumerate() {
+        let mut part = ScanPart::whole(range);
+        part.partition = Some(i as i32 + 1);
+        state_store
+            .save_backfill_partition(&part.record(&mapping.name, &part.progress(), "pending"))
+            .await?;
+        parts.push(part);
+    }
+    info!(mapping = %mapping.name, partitions = parts.len(), "Split the backfill into ranges");
+    Ok(parts)
+}
+
+/// One scan of a backfill: the whole table, or one range of a partitioned
+/// backfill.
+struct ScanPart {
+    /// Position of the range, or `None` when the scan covers everything.
+    partition: Option<i32>,
+    range: IdRange,
+    /// Where an earlier run got to.
+    last_id: Option<String>,
+    processed_rows: i64,
+    upserted_rows: i64,
+    completed: bool,
+}
+
+impl ScanPart {
+    fn whole(range: IdRange) -> Self {
+        Self {
+            partition: None,
+            range,
+            last_id: None,
+            processed_rows: 0,
+            upserted_rows: 0,
+            completed: false,
+        }
+    }
+
+    fn resumed(saved: &BackfillPartition) -> Self {
+        Self {
+            partition: Some(saved.partition),
+            range: IdRange {
+                start: saved.range_start.clone(),
+                end: saved.range_end.clone(),
+            },
+            last_id: saved.last_id.clone(),
+            processed_rows: saved.processed_rows,
+            upserted_rows: saved.upserted_rows,
+            completed: saved.status == "completed",
+        }
+    }
+
+    /// Progress as of the earlier run, before scanning.
+    fn progress(&self) -> BackfillScanProgress {
+        BackfillScanProgress {
+            last_id: self.last_id.clone(),
+            total_rows: None,
+            processed_rows: self.processed_rows,
+            upserted_rows: self.upserted_rows,
+            rows_per_second: 0.0,
+            upserts_per_second: 0.0,
+            percent_complete: 0.0,
+            elapsed_secs: 0.0,
+            eta_secs: None,
+        }
+    }
+
+    /// The record saved for a partition.
+    fn record(
+        &self,
+        mapping_name: &str,
+        progress: &BackfillScanProgress,
+        status: &str,
+    ) -> BackfillPartition {
+        BackfillPartition {
+            mapping_name: mapping_name.to_string(),
+            partition: self.partition.unwrap_or_default(),
+            range_start: self.range.start.clone(),
+            range_end: self.range.end.clone(),
+            last_id: progress.last_id.clone(),
+            processed_rows: progress.processed_rows,
+            upserted_rows: progress.upserted_rows,
+            status: status.to_string(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+}
+
+/// What the scans of one backfill share.
+struct ScanContext<'a> {
+    mapping: &'a Mapping,
+    backfill_config: BackfillConfig,
+    state_store: &'a dyn StateStore,
+    embedder: Option<&'a EmbeddingClient>,
+    sinks: &'a SinkWriters,
+    strict: &'a StrictMode,
+    /// Each scan's share of the read limits.
+    limits: BackfillLimits,
+    transform_batch_size: usize,
+    upload_batch_size: usize,
+    progress_interval: Duration,
+    progress_rows: i64,
+    observer: &'a dyn BackfillObserver,
+    /// Each part's progress as of its last fully written batch.
+    progress: Mutex<Vec<BackfillScanProgress>>,
+    /// Set when a scan fails, so the others stop too.
+    stop: AtomicBool,
+}
+
+impl ScanContext<'_> {
+    /// Record a part's progress. Returns the backfill's as a whole.
+    fn record(&self, index: usize, progress: &BackfillScanProgress) -> BackfillScanProgress {
+        let mut parts = self.progress.lock().unwrap();
+        parts[index] = progress.clone();
+        combine_progress(&parts)
+    }
+
+    fn combined(&self) -> BackfillScanProgress {
+        combine_progress(&self.progress.lock().unwrap())
+    }
+
+    /// Save a part's progress, and the backfill's as a whole.
+    async fn save(
+        &self,
+        part: &ScanPart,
+        progress: &BackfillScanProgress,
+        status: &str,
+    ) -> Result<()> {
+        if part.partition.is_some() {
+            self.state_store
+                .save_backfill_partition(&part.record(&self.mapping.name, progress, status))
+                .await?;
+        }
+        self.save_summary("in_progress").await
+    }
+
+    /// Save the backfill's progress as a whole.
+    async fn save_summary(&self, status: &str) -> Result<()> {
+        self.state_store
+            .save_backfill_progress(&self.combined().to_record(
+                &self.mapping.name,
+                status,
+                run_id::current(),
+            ))
+            .await?;
+        Ok(())
+    }
+}
+
+/// The progress of a backfill from that of its parts. A single part's is
+/// its own.
+fn combine_progress(parts: &[BackfillScanProgress]) -> BackfillScanProgress {
+    if let [part] = parts {
+        return part.clone();
+    }
+    let processed_rows: i64 = parts.iter().map(|p| p.processed_rows).sum();
+    let rows_per_second: f64 = parts.iter().map(|p| p.rows_per_second).sum();
+    // Every part estimates the whole table
+    let total_rows = parts.iter().find_map(|p| p.total_rows);
+    let percent_complete = match total_rows {
+        Some(total) if total > 0 => processed_rows as f64 / total as f64 * 100.0,
+        _ => 0.0,
+    };
+    let eta_secs = match total_rows {
+        Some(total) if rows_per_second > 0.0 => {
+            Some((total - processed_rows).max(0) as f64 / rows_per_second)
+        }
+        _ => None,
+    };
+    BackfillScanProgress {
+        last_id: None,
+        total_rows,
+        processed_rows,
+        upserted_rows: parts.iter().map(|p| p.upserted_rows).sum(),
+        rows_per_second,
+        upserts_per_second: parts.iter().map(|p| p.upserts_per_second).sum(),
+        percent_complete,
+        elapsed_secs: parts.iter().map(|p| p.elapsed_secs).fold(0.0, f64::max),
+        eta_secs,
+    }
+}
+
+/// Poll `futures` together on the current task until all of them are done.
+async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    std::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_some() {
+                continue;
+            }
+            match future.as_mut().poll(cx) {
+                Poll::Ready(value) => *output = Some(value),
+                Poll::Pending => pending = true,
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+    outputs.into_iter().map(Option::unwrap).collect()
+}
+
+/// Scan one part to its end, writing its rows. Returns the sizes of the
+/// documents it wrote.
+async fn scan_part(
+    ctx: &ScanContext<'_>,
+    index: usize,
+    part: &ScanPart,
+    mut uploads: UploadPool,
+) -> Result<SizeStats> {
+    let mapping = ctx.mapping;
+    let strict = ctx.strict;
+    let mut scanner = BackfillScanner::new(BackfillConfig {
+        range: part.range.clone(),
+        ..ctx.backfill_config.clone()
+    })
+    .await
+    .context("Failed to create backfill scanner")?;
+
+    // Resume from checkpoint if available
+    let mut upserted_rows = part.upserted_rows;
+    if let Some(last_id) = &part.last_id {
+        info!(
+            partition = ?part.partition,
+            last_id = %last_id,
+            processed = part.processed_rows,
+            upserted = part.upserted_rows,
+            "Resuming from checkpoint"
+        );
+        scanner.resume_from(last_id.clone(), part.processed_rows);
+    }
 
     // Create transformer - uses JS transform if configured, otherwise identity
     let transformer = create_transformer(mapping);
 
-    // Create batcher with transform batch size from environment
-    let batch_config = BatchConfig::with_max_rows(transform_batch_size);
+    // Create batcher with transform batch size from environment, unless the
+    // mapping sets its own
+    let batch_config = mapping
+        .batch
+        .apply(BatchConfig::with_max_rows(ctx.transform_batch_size));
     let mut batcher = Batcher::new(batch_config);
+    let mut validator = DocumentValidator::new();
+    let mut doc_sizes = SizeStats::new();
+
+    // Progress tracking. `safe_progress` is the last point where every row
+    // read so far has been written, which is what's safe to resume from.
+    let mut safe_progress = scanner.progress(upserted_rows);
+    ctx.record(index, &safe_progress);
+    let mut saver = ProgressSaver::new(
+        ctx.progress_interval,
+        ctx.progress_rows,
+        safe_progress.processed_rows,
+        Instant::now(),
+    );
 
-    // Progress tracking
-    let mut upserted_rows: i64 = 0;
-
-    // Shared state for spinner
-    let spinner_state = Arc::new(Mutex::new(SpinnerState {
-        progress: None,
-        done: false,
-    }));
-
-    // Spawn background spinner task
-    let (spinner_stop_tx, spinner_stop_rx) = oneshot::channel::<()>();
-    let spinner_state_clone = Arc::clone(&spinner_state);
-    let spinner_handle = tokio::spawn(async move {
-        let mut spinner_frame: usize = 0;
-        let mut stop_rx = spinner_stop_rx;
-        loop {
-            tokio::select! {
-                _ = &mut stop_rx => break,
-                _ = tokio::time::sleep(Duration::from_millis(80)) => {
-                    let state = spinner_state_clone.lock().unwrap();
-                    if state.done {
-                        break;
-                    }
-                    if let Some(ref progress) = state.progress {
-                        print!("\r{}", progress.format(spinner_frame));
-                        io::stdout().flush().ok();
-                        spinner_frame = spinner_frame.wrapping_add(1);
-                    }
-                }
-            }
-        }
-    });
+    let mut throttle = Throttle::new(ctx.limits);
 
     // Batch size for sending to JS transform (500 rows at a time)
     const JS_TRANSFORM_BATCH_SIZE: usize = 500;
 
-    // Main backfill loop
-    loop {
-        let events = scanner.next_batch().await?;
+    // Main backfill loop. Errors are held until the last safe progress has
+    // been saved, so a failed backfill resumes from where it got to. Returns
+    // whether the scan reached the end, rather than stopping for another
+    // scan's failure.
+    let result: Result<bool> = async {
+        loop {
+            if ctx.stop.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            let delay = throttle.delay(Instant::now());
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let read_started = Instant::now();
+            let events = scanner.next_batch().await?;
+            throttle.read(events.len(), read_started);
 
-        if events.is_empty() {
-            // Done!
-            break;
-        }
+            if events.is_empty() {
+                // Done!
+                break;
+            }
+            ctx.observer.on_batch(events.len());
+
+            // Rows already filtered by Postgres don't need the membership check
+            let server_filtered = scanner.is_filtered();
 
-        // Collect events with their IDs for batch processing
-        let mut transform_input: Vec<(&puffgres_core::RowEvent, DocumentId)> = Vec::new();
+            // Collect events with their IDs for batch processing
+            let mut transform_input: Vec<(&puffgres_core::RowEvent, DocumentId)> = Vec::new();
 
-        for event in &events {
-            let id = match extract_id(event, &mapping.id.column, mapping.id.id_type) {
-                Ok(id) => id,
-                Err(e) => {
-                    warn!(
-                        mapping = %mapping.name,
-                        error = %e,
-                        "Failed to extract ID during backfill"
-                    );
+            for event in &events {
+                if !server_filtered && !is_member(mapping, event) {
                     continue;
                 }
-            };
 
-            transform_input.push((event, id));
+                let id = match extract_id(event, &mapping.id) {
+                    Ok(id) => id,
+                    Err(e) if strict.enabled(StrictCheck::Id) => {
+                        anyhow::bail!(
+                            "Mapping '{}' failed to extract the ID of a row (strict mode): {}",
+                            mapping.name,
+                            e
+                        );
+                    }
+                    Err(e) => {
+                        warn!(
+                            mapping = %mapping.name,
+                            error = %e,
+                            "Failed to extract ID during backfill"
+                        );
+                        continue;
+                    }
+                };
+
+                transform_input.push((event, id));
+
+                // When we have enough rows, process a batch
+                if transform_input.len() >= JS_TRANSFORM_BATCH_SIZE {
+                    upserted_rows += process_transform_batch(
+                        &transformer,
+                        &transform_input,
+                        mapping,
+                        &mut batcher,
+                        &mut validator,
+                        &mut doc_sizes,
+                        &mut uploads,
+                        ctx.sinks,
+                        ctx.embedder,
+                        ctx.upload_batch_size,
+                        ctx.state_store,
+                        strict,
+                    )
+                    .await? as i64;
+                    transform_input.clear();
+                }
+            }
 
-            // When we have enough rows, process a batch
-            if transform_input.len() >= JS_TRANSFORM_BATCH_SIZE {
+            // Process any remaining rows
+            if !transform_input.is_empty() {
                 upserted_rows += process_transform_batch(
                     &transformer,
                     &transform_input,
-                    &mapping,
+                    mapping,
                     &mut batcher,
-                    &tp_client,
-                    upload_batch_size,
-                    max_retries,
+                    &mut validator,
+                    &mut doc_sizes,
+                    &mut uploads,
+                    ctx.sinks,
+                    ctx.embedder,
+                    ctx.upload_batch_size,
+                    ctx.state_store,
+                    strict,
                 )
                 .await? as i64;
-                transform_input.clear();
             }
-        }
 
-        // Process any remaining rows
-        if !transform_input.is_empty() {
-            upserted_rows += process_transform_batch(
-                &transformer,
-                &transform_input,
-                &mapping,
-                &mut batcher,
-                &tp_client,
-                upload_batch_size,
-                max_retries,
-            )
-            .await? as i64;
+            // Flush any remaining items in the batcher, and wait for every
+            // write before this batch's progress can be saved
+            for batch in batcher.flush_all() {
+                let request = WriteRequest::from_batch(batch);
+                upserted_rows += flush_batch(
+                    &mut uploads,
+                    ctx.sinks,
+                    ctx.embedder,
+                    mapping,
+                    request,
+                    ctx.upload_batch_size,
+                )
+                .await? as i64;
+            }
+            upserted_rows += uploads.drain().await? as i64;
+
+            // Save progress in the database every so often
+            let progress = scanner.progress(upserted_rows);
+            let combined = ctx.record(index, &progress);
+            let now = Instant::now();
+            if saver.is_due(progress.processed_rows, now) {
+                ctx.save(part, &progress, "in_progress").await?;
+                saver.saved(progress.processed_rows, now);
+            }
+            ctx.observer.on_progress(&combined);
+            safe_progress = progress;
         }
 
-        // Flush any remaining items in the batcher
+        // Final flush
         for batch in batcher.flush_all() {
             let request = WriteRequest::from_batch(batch);
-            upserted_rows +=
-                flush_batch(&tp_client, &request, upload_batch_size, max_retries).await? as i64;
-        }
-
-        // Update progress in database
-        let progress = scanner.progress(upserted_rows);
-        state_store
-            .update_backfill_progress(
-                &mapping.name,
-                progress.last_id.as_deref(),
-                progress.total_rows,
-                progress.processed_rows,
-                "in_progress",
+            upserted_rows += flush_batch(
+                &mut uploads,
+                ctx.sinks,
+                ctx.embedder,
+                mapping,
+                request,
+                ctx.upload_batch_size,
             )
-            .await?;
-
-        // Update shared progress state (spinner task handles display)
-        {
-            let mut state = spinner_state.lock().unwrap();
-            state.progress = Some(progress);
+            .await? as i64;
         }
-    }
+        upserted_rows += uploads.drain().await? as i64;
 
-    // Final flush
-    for batch in batcher.flush_all() {
-        let request = WriteRequest::from_batch(batch);
-        upserted_rows +=
-            flush_batch(&tp_client, &request, upload_batch_size, max_retries).await? as i64;
+        Ok(true)
     }
-
-    // Stop the spinner task
-    {
-        let mut state = spinner_state.lock().unwrap();
-        state.done = true;
+    .await;
+
+    match result {
+        Ok(true) => {
+            let progress = scanner.progress(upserted_rows);
+            ctx.record(index, &progress);
+            if part.partition.is_some() {
+                ctx.save(part, &progress, "completed").await?;
+            }
+        }
+        // Another scan failed; this one resumes from its last save
+        Ok(false) => {
+            if part.partition.is_some() {
+                ctx.save(part, &safe_progress, "failed").await?;
+            }
+        }
+        Err(e) => {
+            ctx.stop.store(true, Ordering::Relaxed);
+            if part.partition.is_some() {
+                if let Err(save_err) = ctx.save(part, &safe_progress, "failed").await {
+                    warn!(error = %save_err, "Failed to save backfill progress");
+                }
+            }
+            return Err(e);
+        }
     }
-    let _ = spinner_stop_tx.send(());
-    let _ = spinner_handle.await;
-
-    // Mark as complete
-    let final_progress = scanner.progress(upserted_rows);
-    state_store
-        .update_backfill_progress(
-            &mapping.name,
-            final_progress.last_id.as_deref(),
-            final_progress.total_rows,
-            final_progress.processed_rows,
-            "completed",
-        )
-        .await?;
-
-    // Print final status with checkmark
-    println!("\r✓ {}", final_progress.format(0));
-    println!("\nBackfill complete!");
-
-    Ok(())
+    Ok(doc_sizes)
 }
 
 /// Process a batch of rows through the transform.
 /// Returns the number of rows upserted to turbopuffer.
+#[allow(clippy::too_many_arguments)]
 async fn process_transform_batch(
     transformer: &MappingTransformer,
     rows: &[(&puffgres_core::RowEvent, DocumentId)],
     mapping: &Mapping,
     batcher: &mut Batcher,
-    tp_client: &rs_puff::Client,
+    validator: &mut DocumentValidator,
+    doc_sizes: &mut SizeStats,
+    uploads: &mut UploadPool,
+    sinks: &SinkWriters,
+    embedder: Option<&EmbeddingClient>,
     upload_batch_size: usize,
-    max_retries: u32,
+    state_store: &dyn StateStore,
+    strict: &StrictMode,
 ) -> Result<usize> {
     if rows.is_empty() {
         return Ok(0);
@@ -310,6 +1044,14 @@ async fn process_transform_batch(
 
     let actions = match transformer.transform_batch(rows) {
         Ok(actions) => actions,
+        Err(e) if strict.enabled(StrictCheck::Transform) => {
+            anyhow::bail!(
+                "Mapping '{}' transform failed on a batch of {} rows (strict mode): {}",
+                mapping.name,
+                rows.len(),
+                e
+            );
+        }
         Err(e) => {
             warn!(
                 mapping = %mapping.name,
@@ -322,38 +1064,122 @@ async fn process_transform_batch(
     };
 
     let mut upserted = 0;
-    for action in actions {
+    for ((event, _), mut action) in rows.iter().zip(actions) {
         if !action.requires_write() {
             continue;
         }
 
+        let namespace = match mapping.namespace_for(event) {
+            Ok(namespace) => namespace,
+            Err(e) if strict.enabled(StrictCheck::Id) => {
+                anyhow::bail!(
+                    "Mapping '{}' couldn't resolve the namespace for a row (strict mode): {}",
+                    mapping.name,
+                    e
+                );
+            }
+            Err(e) => {
+                warn!(mapping = %mapping.name, error = %e, "Skipping row without a namespace");
+                continue;
+            }
+        };
+
+        if let Err(e) = validator.check(&namespace, &mapping.id.id_type, &action) {
+            if strict.enabled(StrictCheck::Transform) {
+                anyhow::bail!(
+                    "Mapping '{}' transform returned an invalid document (strict mode): {}",
+                    mapping.name,
+                    e
+                );
+            }
+            warn!(mapping = %mapping.name, error = %e, "Skipping row with invalid document");
+            continue;
+        }
+
+        if let Some(size) = action_size(&action) {
+            doc_sizes.record(size);
+        }
+        if let Err(e) = mapping.limits.enforce(&mut action) {
+            if mapping.limits.on_oversize == OversizeAction::Fail {
+                anyhow::bail!(
+                    "Mapping '{}' produced an oversized document (on_oversize = \"fail\"): {}",
+                    mapping.name,
+                    e
+                );
+            }
+            warn!(mapping = %mapping.name, error = %e, "Document over the size limit");
+            state_store
+                .add_to_dlq(
+                    &mapping.name,
+                    event.lsn,
+                    &serde_json::to_value(event)?,
+                    &e.to_string(),
+                    e.kind().as_str(),
+                    Some(run_id::current()),
+                )
+                .await
+                .context("Failed to write to the dead letter queue")?;
+            continue;
+        }
+
+        // Backfilled documents carry a hash too, like those the runner writes.
+        // Hashed after truncation, so it matches what's stored
+        if matches!(mapping.versioning, VersioningMode::ContentHash) {
+            action = action.with_content_hash();
+        }
+        if mapping.run_id {
+            action = action.with_run_id(run_id::current());
+        }
+
         // Add to batcher
-        if let Some(batch) = batcher.add(&mapping.namespace, action, 0) {
+        if let Some(batch) = batcher.add(&namespace, action, 0) {
             let request = WriteRequest::from_batch(batch);
-            upserted += flush_batch(tp_client, &request, upload_batch_size, max_retries).await?;
+            upserted += flush_batch(
+                uploads,
+                sinks,
+                embedder,
+                mapping,
+                request,
+                upload_batch_size,
+            )
+            .await?;
         }
     }
 
     Ok(upserted)
 }
 
-/// Flush a batch to turbopuffer with chunking and retry logic.
-/// Returns the number of rows upserted.
+/// Queue a batch for upload in chunks of `upload_batch_size` rows that stay
+/// under turbopuffer's request size limit, generating its vectors first when
+/// the mapping embeds attributes.
+/// Returns the number of rows upserted by writes that have finished so far.
 async fn flush_batch(
-    client: &rs_puff::Client,
-    request: &WriteRequest,
+    uploads: &mut UploadPool,
+    sinks: &SinkWriters,
+    embedder: Option<&EmbeddingClient>,
+    mapping: &Mapping,
+    mut request: WriteRequest,
     upload_batch_size: usize,
-    max_retries: u32,
 ) -> Result<usize> {
     if request.is_empty() {
         return Ok(0);
     }
 
+    embed_request(embedder, mapping, &mut request).await?;
+
     debug!(
         namespace = %request.namespace,
         upserts = request.upserts.len(),
+        patches = request.patches.len(),
         "Flushing backfill batch"
     );
+    sinks.send(&mapping.name, &request).await;
+
+    let backfilled = |row: &mut HashMap<String, serde_json::Value>| {
+        if let Some(attribute) = &mapping.backfill_attribute {
+            row.insert(attribute.clone(), serde_json::Value::Bool(true));
+        }
+    };
 
     // Build all upsert rows
     let all_upsert_rows: Vec<HashMap<String, serde_json::Value>> = request
@@ -365,68 +1191,60 @@ async fn flush_batch(
                 .iter()
                 .map(|(k, v)| (k.clone(), convert_value_to_json(v)))
                 .collect();
+            if let Some(vector) = &doc.vector {
+                row.insert(VECTOR_ATTRIBUTE.to_string(), serde_json::json!(vector));
+            }
             row.insert("id".to_string(), convert_doc_id_to_json(&doc.id));
-            row.insert("__backfill".to_string(), serde_json::Value::Bool(true));
+            backfilled(&mut row);
             row
         })
         .collect();
 
-    let total_upserted = all_upsert_rows.len();
+    // Build all patch rows
+    let all_patch_rows: Vec<HashMap<String, serde_json::Value>> = request
+        .patches
+        .iter()
+        .map(|patch| {
+            let mut row: HashMap<String, serde_json::Value> = patch
+                .attributes
+                .iter()
+                .map(|(k, v)| (k.clone(), convert_value_to_json(v)))
+                .collect();
+            row.insert("id".to_string(), convert_doc_id_to_json(&patch.id));
+            backfilled(&mut row);
+            row
+        })
+        .collect();
 
-    // Upload in chunks (backfill is upserts-only, no deletes)
-    for chunk in all_upsert_rows.chunks(upload_batch_size) {
+    // Upload in chunks (backfill writes no deletes)
+    let (upsert_sizes, patch_sizes) = row_sizes(&request);
+    let mut upserted = 0;
+    for chunk in chunk_rows(&all_upsert_rows, &upsert_sizes, upload_batch_size) {
         let params = rs_puff::WriteParams {
             upsert_rows: Some(chunk.to_vec()),
             deletes: None,
             distance_metric: request.distance_metric,
             ..Default::default()
         };
-        write_with_retry(client, &request.namespace, params, max_retries).await?;
+        upserted += uploads.submit(request.namespace.clone(), params).await?;
     }
-
-    Ok(total_upserted)
-}
-
-/// Write to turbopuffer with exponential backoff retry.
-async fn write_with_retry(
-    client: &rs_puff::Client,
-    namespace: &str,
-    params: rs_puff::WriteParams,
-    max_retries: u32,
-) -> Result<()> {
-    let base_delay_ms = 100u64;
-
-    for attempt in 0..=max_retries {
-        match client.namespace(namespace).write(params.clone()).await {
-            Ok(_) => return Ok(()),
-            Err(e) => {
-                if attempt == max_retries {
-                    return Err(e).context("Failed to write to turbopuffer after all retries");
-                }
-
-                let delay_ms = base_delay_ms * (1 << attempt);
-                warn!(
-                    namespace = namespace,
-                    attempt = attempt + 1,
-                    max_retries,
-                    delay_ms,
-                    error = %e,
-                    "Turbopuffer write failed, retrying"
-                );
-
-                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
-            }
-        }
+    for chunk in chunk_rows(&all_patch_rows, &patch_sizes, upload_batch_size) {
+        let params = rs_puff::WriteParams {
+            patch_rows: Some(chunk.to_vec()),
+            ..Default::default()
+        };
+        upserted += uploads.submit(request.namespace.clone(), params).await?;
     }
 
-    unreachable!()
+    Ok(upserted)
 }
 
 fn convert_doc_id_to_json(id: &DocumentId) -> serde_json::Value {
     match id {
         DocumentId::Uint(u) => serde_json::Value::Number((*u).into()),
         DocumentId::Int(i) => serde_json::Value::Number((*i).into()),
-        DocumentId::Uuid(s) | DocumentId::String(s) => serde_json::Value::String(s.clone()),
+        DocumentId::String(s) => serde_json::Value::String(s.clone()),
+        DocumentId::Uuid(_) | DocumentId::Bytes(_) => serde_json::Value::String(id.to_string()),
     }
 }
 
@@ -462,18 +1280,70 @@ pub fn has_custom_transform(mapping: &Mapping) -> bool {
 
 /// Get the columns to fetch from Postgres for a mapping.
 /// Returns empty vec (meaning all columns) when a custom transform is configured.
+/// Columns named in a partitioned namespace or read by computed attributes,
+/// and the vector column, are always fetched.
 pub fn get_backfill_columns(mapping: &Mapping) -> Vec<String> {
-    if has_custom_transform(mapping) {
-        vec![] // Empty = fetch all columns
-    } else {
-        mapping.columns.clone()
+    if has_custom_transform(mapping) || mapping.columns.is_empty() {
+        return vec![]; // Empty = fetch all columns
+    }
+
+    let computed_columns: BTreeSet<&str> = mapping
+        .computed
+        .values()
+        .flat_map(|expr| expr.columns())
+        .collect();
+    let mut columns = mapping.columns.clone();
+    let mut required = mapping.namespace_columns();
+    required.extend(computed_columns);
+    required.extend(mapping.vector.as_ref().map(|v| v.column.as_str()));
+    for column in required {
+        if !columns.iter().any(|c| c == column) {
+            columns.push(column.to_string());
+        }
+    }
+    columns
+}
+
+/// Get the columns the mapping types as `string`, which are read as text so
+/// numbers keep every digit.
+pub fn get_backfill_text_columns(mapping: &Mapping) -> Vec<String> {
+    let mut columns: Vec<String> = mapping
+        .types
+        .iter()
+        .filter(|(_, column_type)| column_type.reads_text())
+        .map(|(column, _)| column.clone())
+        .collect();
+    columns.sort();
+    columns
+}
+
+/// The mapping's membership predicate, for the scanner to push down.
+///
+/// The scanner only filters in Postgres when the predicate selects the same
+/// rows there; the backfill still checks membership in Rust.
+pub fn get_backfill_filter(mapping: &Mapping) -> Option<Predicate> {
+    match &mapping.membership {
+        MembershipConfig::Dsl(predicate) => Some(predicate.clone()),
+        MembershipConfig::All | MembershipConfig::View => None,
+    }
+}
+
+/// Check a backfilled row against the mapping's membership predicate.
+pub(crate) fn is_member(mapping: &Mapping, event: &RowEvent) -> bool {
+    match &mapping.membership {
+        MembershipConfig::Dsl(predicate) => event
+            .new
+            .as_ref()
+            .map(|row| predicate.evaluate(row))
+            .unwrap_or(false),
+        MembershipConfig::All | MembershipConfig::View => true,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use puffgres_core::{IdType, TransformConfig};
+    use puffgres_core::{Expression, IdType, Literal, TransformConfig, VectorColumn};
 
     fn make_mapping_without_transform() -> Mapping {
         Mapping::builder("test")
@@ -540,6 +1410,62 @@ mod tests {
         assert_eq!(columns, vec!["id", "name", "email"]);
     }
 
+    #[test]
+    fn test_get_backfill_columns_adds_namespace_columns() {
+        let mapping = Mapping::builder("test")
+            .namespace("users_{tenant_id}")
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .columns(vec!["id".into(), "name".into()])
+            .build()
+            .unwrap();
+        assert_eq!(
+            get_backfill_columns(&mapping),
+            vec!["id", "name", "tenant_id"]
+        );
+    }
+
+    #[test]
+    fn test_get_backfill_columns_adds_computed_columns() {
+        let computed = [(
+            "full_name".to_string(),
+            Expression::parse("concat(name, ' ', surname)").unwrap(),
+        )]
+        .into_iter()
+        .collect();
+        let mapping = Mapping::builder("test")
+            .namespace("users")
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .columns(vec!["id".into(), "name".into()])
+            .computed(computed)
+            .build()
+            .unwrap();
+        assert_eq!(
+            get_backfill_columns(&mapping),
+            vec!["id", "name", "surname"]
+        );
+    }
+
+    #[test]
+    fn test_get_backfill_columns_adds_vector_column() {
+        let mapping = Mapping::builder("test")
+            .namespace("docs")
+            .source("public", "docs")
+            .id("id", IdType::Uint)
+            .columns(vec!["id".into(), "title".into()])
+            .vector(VectorColumn {
+                column: "embedding".into(),
+                distance_metric: None,
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            get_backfill_columns(&mapping),
+            vec!["id", "title", "embedding"]
+        );
+    }
+
     #[test]
     fn test_get_backfill_columns_returns_empty_with_transform() {
         let mapping = make_mapping_with_transform();
@@ -553,4 +1479,157 @@ mod tests {
         let columns = get_backfill_columns(&mapping);
         assert_eq!(columns, vec!["id", "name", "email"], "Should use columns when transform has no path");
     }
+
+    #[test]
+    fn test_get_backfill_filter_compiles_membership() {
+        let mapping = Mapping::builder("test")
+            .namespace("test")
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .membership(MembershipConfig::dsl("status = 'active'").unwrap())
+            .build()
+            .unwrap();
+        let active = Predicate::Eq("status".into(), Literal::String("active".into()));
+        assert_eq!(get_backfill_filter(&mapping), Some(active));
+
+        let mapping = make_mapping_without_transform();
+        assert!(get_backfill_filter(&mapping).is_none());
+    }
+
+    #[test]
+    fn test_is_member_evaluates_predicate() {
+        let mapping = Mapping::builder("test")
+            .namespace("test")
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .membership(MembershipConfig::dsl("status = 'active'").unwrap())
+            .build()
+            .unwrap();
+
+        let event = |status: &str| RowEvent {
+            op: puffgres_core::Operation::Insert,
+            schema: "public".into(),
+            table: "users".into(),
+            new: Some(HashMap::from([
+                ("id".to_string(), Value::Int(1)),
+                ("status".to_string(), Value::String(status.into())),
+            ])),
+            old: None,
+            lsn: 0,
+            txid: None,
+            timestamp: None,
+        };
+
+        assert!(is_member(&mapping, &event("active")));
+        assert!(!is_member(&mapping, &event("archived")));
+        assert!(is_member(
+            &make_mapping_without_transform(),
+            &event("archived")
+        ));
+    }
+
+    #[test]
+    fn test_progress_saver_by_rows_or_time() {
+        let start = Instant::now();
+        let mut saver = ProgressSaver::new(Duration::from_secs(5), 10_000, 0, start);
+
+        assert!(!saver.is_due(9_999, start + Duration::from_secs(1)));
+        assert!(saver.is_due(10_000, start + Duration::from_secs(1)));
+        assert!(saver.is_due(100, start + Duration::from_secs(5)));
+
+        saver.saved(10_000, start + Duration::from_secs(1));
+        assert!(!saver.is_due(15_000, start + Duration::from_secs(2)));
+        assert!(saver.is_due(20_000, start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_throttle_spaces_reads_by_rows_and_queries() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(BackfillLimits {
+            max_rows_per_sec: Some(1_000),
+            max_read_qps: Some(4),
+        });
+        assert_eq!(throttle.delay(start), Duration::ZERO);
+
+        // 500 rows take half a second at 1000 rows/s
+        throttle.read(500, start);
+        assert_eq!(throttle.delay(start), Duration::from_millis(500));
+        assert_eq!(
+            throttle.delay(start + Duration::from_millis(200)),
+            Duration::from_millis(300)
+        );
+        assert_eq!(throttle.delay(start + Duration::from_secs(1)), Duration::ZERO);
+
+        // A small read is held to 4 queries a second
+        throttle.read(10, start);
+        assert_eq!(throttle.delay(start), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_throttle_unlimited_never_waits() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(BackfillLimits::default());
+        throttle.read(1_000_000, start);
+        assert_eq!(throttle.delay(start), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_backfill_limits_display() {
+        let limits = BackfillLimits {
+            max_rows_per_sec: Some(500),
+            max_read_qps: Some(2),
+        };
+        assert_eq!(limits.to_string(), "500 rows/s, 2 reads/s");
+        assert!(BackfillLimits::default().is_unlimited());
+    }
+
+    #[test]
+    fn test_backfill_limits_split() {
+        let limits = BackfillLimits {
+            max_rows_per_sec: Some(500),
+            max_read_qps: Some(2),
+        };
+        let share = limits.split(4);
+        assert_eq!(share.max_rows_per_sec, Some(125));
+        assert_eq!(share.max_read_qps, Some(1));
+        assert_eq!(limits.split(1), limits);
+        assert!(BackfillLimits::default().split(4).is_unlimited());
+    }
+
+    #[test]
+    fn test_combine_progress() {
+        let part = |processed_rows, rows_per_second, elapsed_secs| BackfillScanProgress {
+            last_id: Some("7".to_string()),
+            total_rows: Some(1_000),
+            processed_rows,
+            upserted_rows: processed_rows,
+            rows_per_second,
+            upserts_per_second: rows_per_second,
+            percent_complete: 0.0,
+            elapsed_secs,
+            eta_secs: None,
+        };
+
+        // A single scan's progress is its own, resume point and all
+        let single = combine_progress(&[part(100, 10.0, 10.0)]);
+        assert_eq!(single.last_id.as_deref(), Some("7"));
+
+        let combined = combine_progress(&[part(100, 10.0, 10.0), part(300, 30.0, 12.0)]);
+        assert_eq!(combined.last_id, None);
+        assert_eq!(combined.processed_rows, 400);
+        assert_eq!(combined.upserted_rows, 400);
+        assert_eq!(combined.rows_per_second, 40.0);
+        assert_eq!(combined.percent_complete, 40.0);
+        assert_eq!(combined.elapsed_secs, 12.0);
+        assert_eq!(combined.eta_secs, Some(15.0));
+    }
+
+    #[test]
+    fn test_progress_saver_counts_from_resume_point() {
+        let start = Instant::now();
+        let saver = ProgressSaver::new(Duration::from_secs(5), 1_000, 50_000, start);
+
+        assert!(!saver.is_due(50_500, start));
+        assert!(saver.is_due(51_000, start));
+    }
 }
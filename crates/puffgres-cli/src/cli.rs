@@ -1,3 +1,6 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
@@ -5,7 +8,8 @@ use clap::{Parser, Subcommand};
 #[command(about = "Mirror Postgres data to turbopuffer")]
 #[command(version)]
 pub struct Cli {
-    /// Environment to load (loads .env.{ENV} instead of .env)
+    /// Environment to load (loads .env.{ENV} instead of .env, and the
+    /// [env.{ENV}] profile from puffgres.toml)
     #[arg(short, long, global = true)]
     pub env: Option<String>,
 
@@ -18,27 +22,66 @@ pub enum Commands {
     /// Initialize puffgres in the current directory (creates config files)
     Init,
 
-    /// Set up database tables and replication slot
-    Setup,
+    /// Set up database tables and the publication for mapped tables
+    Setup {
+        /// Publication name for logical replication
+        #[arg(long, default_value = "puffgres_pub")]
+        publication: String,
+
+        /// Change the replica identity of mapped tables that don't log
+        /// enough of the old row, after confirming each change
+        #[arg(long, alias = "fix")]
+        fix_replica_identity: bool,
+
+        /// Apply replica identity changes without asking
+        #[arg(long, short = 'y', requires = "fix_replica_identity")]
+        yes: bool,
+
+        /// Add a trigger to each mapped table that notifies `puffgres tail`
+        /// of changes, so it reads them without waiting for its next poll
+        #[arg(long)]
+        notify: bool,
+    },
 
     /// Create a new migration
     New {
         /// Optional name for the migration (will prompt if not provided)
         name: Option<String>,
+
+        /// Scaffold from an existing table's columns and primary key
+        #[arg(long, value_name = "SCHEMA.TABLE")]
+        from_table: Option<String>,
+
+        /// Pick the table, columns, id and predicate from the database, and
+        /// preview the migration before writing it
+        #[arg(long, short = 'i', conflicts_with = "from_table")]
+        interactive: bool,
     },
 
     /// Apply pending migrations
     Migrate {
+        #[command(subcommand)]
+        command: Option<MigrateCommands>,
+
         /// Show what would be applied without actually applying
         #[arg(long)]
         dry_run: bool,
+
+        /// Publication to add newly mapped tables to
+        #[arg(long, default_value = "puffgres_pub")]
+        publication: String,
+
+        /// Don't add newly mapped tables to the publication; only warn about
+        /// the ones it's missing
+        #[arg(long)]
+        no_alter_publication: bool,
     },
 
     /// Start the CDC replication loop
     Run {
-        /// Replication slot name
-        #[arg(long, default_value = "puffgres")]
-        slot: String,
+        /// Replication slot name (default: the profile's slot, or puffgres)
+        #[arg(long)]
+        slot: Option<String>,
 
         /// Publication name for logical replication
         #[arg(long, default_value = "puffgres_pub")]
@@ -48,18 +91,99 @@ pub enum Commands {
         #[arg(long, default_value = "true")]
         create_slot: bool,
 
+        /// Don't add mapped tables to an existing publication; fail if it's
+        /// missing any
+        #[arg(long)]
+        no_alter_publication: bool,
+
         /// Skip auto-applying pending migrations
         #[arg(long)]
         skip_migrate: bool,
+
+        /// Inject faults for resilience testing (requires PUFFGRES_ENABLE_FAULT_INJECTION=1)
+        #[arg(long, hide = true)]
+        fault_inject: Option<String>,
+
+        /// Treat warnings as errors: all checks, or a comma-separated list of
+        /// id, transform, decode, truncate, replica_identity (e.g. --strict=id,transform)
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "all")]
+        strict: Option<String>,
+
+        /// Development only: reload transforms when their files change and
+        /// skip the check that applied transforms are unmodified
+        #[arg(long)]
+        dev: bool,
+
+        /// Stop the runner currently streaming from the slot and take over
+        #[arg(long)]
+        force_takeover: bool,
+
+        /// Write transactions that delete more documents of a mapping than
+        /// PUFFGRES_MASS_DELETE_THRESHOLD (default 10000) instead of stopping
+        #[arg(long)]
+        allow_mass_delete: bool,
+
+        /// Serve /healthz and /readyz on this address (e.g. 0.0.0.0:8081)
+        #[arg(long)]
+        health_addr: Option<SocketAddr>,
+
+        /// Transform changes and print each batch as a line of JSON instead
+        /// of writing it, without applying migrations or acknowledging the
+        /// slot (--dry-run=null to print nothing)
+        #[arg(
+            long,
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "stdout",
+            value_parser = ["stdout", "null"]
+        )]
+        dry_run: Option<String>,
     },
 
     /// Show current sync status
-    Status,
+    Status {
+        /// Replication slot name (default: the profile's slot, or puffgres)
+        #[arg(long)]
+        slot: Option<String>,
+
+        /// Publication to check for missing mapped tables
+        #[arg(long, default_value = "puffgres_pub")]
+        publication: String,
+
+        /// Print a JSON document instead of tables
+        #[arg(long)]
+        json: bool,
+
+        /// Refresh every SECS seconds (default 5) until interrupted. With
+        /// --json, prints one document per line
+        #[arg(
+            long,
+            value_name = "SECS",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "5",
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        watch: Option<u64>,
+    },
 
     /// Backfill existing table data to turbopuffer
     Backfill {
         /// Mapping name to backfill
-        mapping: String,
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        mapping: Option<String>,
+
+        /// Backfill every mapping, skipping ones whose backfill already completed
+        #[arg(long)]
+        all: bool,
+
+        /// With --all, backfill up to this many mappings at once (default: 1)
+        #[arg(long, requires = "all", value_parser = clap::value_parser!(u16).range(1..))]
+        parallel: Option<u16>,
+
+        /// With --all, also backfill mappings whose backfill already completed
+        #[arg(long, requires = "all")]
+        force: bool,
 
         /// Batch size for processing
         #[arg(long, default_value = "1000")]
@@ -68,6 +192,207 @@ pub enum Commands {
         /// Resume from previous checkpoint
         #[arg(long)]
         resume: bool,
+
+        /// Concurrent turbopuffer writes (default: PUFFGRES_UPLOAD_CONCURRENCY or 4)
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+        concurrency: Option<u16>,
+
+        /// Split the table into this many ID ranges and scan them concurrently
+        #[arg(
+            long,
+            default_value = "1",
+            conflicts_with = "all",
+            value_parser = clap::value_parser!(u16).range(1..)
+        )]
+        partitions: u16,
+
+        /// Read at most this many rows per second (also paces uploads)
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_rows_per_sec: Option<u32>,
+
+        /// Run at most this many scanner queries per second
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_read_qps: Option<u32>,
+
+        /// Treat warnings as errors: all checks, or a comma-separated list of
+        /// id, transform, decode, truncate, replica_identity (e.g. --strict=id,transform)
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "all")]
+        strict: Option<String>,
+
+        /// Once the backfill completes, compare row and document counts and
+        /// this many sampled documents (default 100) with their rows
+        #[arg(
+            long,
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "100",
+            conflicts_with = "all",
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        verify: Option<u32>,
+    },
+
+    /// Backfill a mapping, then stream changes made during the backfill and keep running
+    Sync {
+        /// Mapping name to backfill
+        mapping: String,
+
+        /// Replication slot name (default: the profile's slot, or puffgres)
+        #[arg(long)]
+        slot: Option<String>,
+
+        /// Publication name for logical replication
+        #[arg(long, default_value = "puffgres_pub")]
+        publication: String,
+
+        /// Batch size for processing
+        #[arg(long, default_value = "1000")]
+        batch_size: u32,
+
+        /// Concurrent turbopuffer writes (default: PUFFGRES_UPLOAD_CONCURRENCY or 4)
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+        concurrency: Option<u16>,
+
+        /// Treat warnings as errors: all checks, or a comma-separated list of
+        /// id, transform, decode, truncate, replica_identity (e.g. --strict=id,transform)
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "all")]
+        strict: Option<String>,
+    },
+
+    /// Live-view decoded CDC events without writing to turbopuffer
+    Tail {
+        /// Only show changes to this mapping's source table
+        #[arg(long)]
+        mapping: Option<String>,
+
+        /// Only show changes to this table (schema.table)
+        #[arg(long)]
+        table: Option<String>,
+
+        /// Peek this existing slot instead of creating a temporary one
+        #[arg(long)]
+        slot: Option<String>,
+
+        /// Publication name for logical replication
+        #[arg(long, default_value = "puffgres_pub")]
+        publication: String,
+    },
+
+    /// Check migrations for mistakes without connecting to anything
+    Lint {
+        /// Fail on warnings as well as errors
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+
+    /// Check migrations against the database schema and suggest fixes
+    Doctor {
+        /// Write suggested revisions to migrations/
+        #[arg(long)]
+        write: bool,
+    },
+
+    /// Compare a sample of rows with their documents in turbopuffer
+    Check {
+        /// Mapping name to check
+        mapping: String,
+
+        /// Number of random rows to check
+        #[arg(long, default_value = "100", value_parser = clap::value_parser!(u32).range(1..))]
+        sample: u32,
+    },
+
+    /// Run a mapping's transform on sample rows and print what it would write
+    TestTransform {
+        /// Mapping name to test
+        mapping: String,
+
+        /// Number of rows to read from the table
+        #[arg(long, default_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+        limit: u32,
+
+        /// Read rows from this JSON file (an array of objects keyed by
+        /// column) instead of the table
+        #[arg(long, conflicts_with = "limit")]
+        fixture: Option<PathBuf>,
+    },
+
+    /// Diff every row of a mapping against its namespace, rewrite missing and
+    /// stale documents and delete orphans
+    Reconcile {
+        /// Mapping name to reconcile
+        mapping: String,
+
+        /// Batch size for processing
+        #[arg(long, default_value = "1000")]
+        batch_size: u32,
+
+        /// Report what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Re-run a query mapping's query: upsert its rows and delete documents
+    /// whose rows are gone
+    Refresh {
+        /// Mapping name to refresh
+        mapping: String,
+
+        /// Batch size for processing
+        #[arg(long, default_value = "1000")]
+        batch_size: u32,
+    },
+
+    /// Stop writing a mapping's changes; the slot keeps them until it resumes
+    Pause {
+        /// Mapping name to pause
+        mapping: String,
+    },
+
+    /// Resume a paused mapping, replaying the changes it skipped
+    Resume {
+        /// Mapping name to resume
+        mapping: String,
+    },
+
+    /// Make a mapping's shadow revision current once it's backfilled
+    Promote {
+        /// Mapping name to promote
+        mapping: String,
+
+        /// Promote even if the shadow namespace hasn't finished backfilling
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Write a range of changes the slot still holds again, e.g. after fixing a transform
+    Replay {
+        /// First LSN to replay (e.g. 0/16B3748)
+        #[arg(long)]
+        from_lsn: String,
+
+        /// Last LSN to replay
+        #[arg(long)]
+        to_lsn: String,
+
+        /// Only replay changes for this mapping
+        #[arg(long)]
+        mapping: Option<String>,
+
+        /// Replication slot name (default: the profile's slot, or puffgres)
+        #[arg(long)]
+        slot: Option<String>,
+
+        /// Publication name for logical replication
+        #[arg(long, default_value = "puffgres_pub")]
+        publication: String,
+    },
+
+    /// Serve a read-only web dashboard of sync state
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
     },
 
     /// Manage the dead letter queue
@@ -76,6 +401,19 @@ pub enum Commands {
         command: DlqCommands,
     },
 
+    /// Copy puffgres state (migrations, checkpoints, backfill progress, DLQ)
+    /// to a file and into another database
+    State {
+        #[command(subcommand)]
+        command: StateCommands,
+    },
+
+    /// Map obfuscated document ids to primary keys and back
+    Id {
+        #[command(subcommand)]
+        command: IdCommands,
+    },
+
     /// Reset local config from database state
     Reset,
 
@@ -86,6 +424,28 @@ pub enum Commands {
     DangerouslyResetTurbopuffer,
 }
 
+#[derive(Subcommand)]
+pub enum MigrateCommands {
+    /// Roll back the latest applied migration, or every one after --to
+    Down {
+        /// Keep migrations up to and including this version
+        #[arg(long)]
+        to: Option<i32>,
+
+        /// Show what would be rolled back without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also delete the rolled-back mappings' turbopuffer namespaces
+        #[arg(long)]
+        delete_namespace: bool,
+
+        /// Roll back without asking
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum DlqCommands {
     /// List DLQ entries
@@ -105,15 +465,19 @@ pub enum DlqCommands {
         id: i32,
     },
 
-    /// Retry DLQ entries
+    /// Replay DLQ entries through their mapping's transform and write them to turbopuffer
     Retry {
         /// Retry a specific entry by ID
-        #[arg(long)]
+        #[arg(long, conflicts_with_all = ["mapping", "all"])]
         id: Option<i32>,
 
         /// Retry all entries for a mapping
-        #[arg(long)]
+        #[arg(long, conflicts_with = "all")]
         mapping: Option<String>,
+
+        /// Retry every entry, grouped by mapping
+        #[arg(long)]
+        all: bool,
     },
 
     /// Clear DLQ entries
@@ -128,6 +492,42 @@ pub enum DlqCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum StateCommands {
+    /// Write every __puffgres_* state table to a JSON file
+    Export {
+        /// File to write
+        path: PathBuf,
+    },
+
+    /// Load a file written by `state export` into this database
+    Import {
+        /// File to read
+        path: PathBuf,
+
+        /// Overwrite state already in this database
+        #[arg(long)]
+        replace: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum IdCommands {
+    /// Print the document id a primary key is written as
+    Encode {
+        /// Primary key values
+        #[arg(required = true)]
+        ids: Vec<u64>,
+    },
+
+    /// Print the primary key behind a document id
+    Decode {
+        /// Document ids, as found in turbopuffer
+        #[arg(required = true)]
+        ids: Vec<u64>,
+    },
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,395 @@
+//! `puffgres backfill --all`: backfill every mapping, several at a time.
+//!
+//! Mappings that write to the same namespace depend on each other: a later
+//! version may overwrite documents an earlier one wrote, so they run one
+//! after another in version order. Mappings with different namespaces run
+//! concurrently, up to `--parallel`. Progress is shown as one table row per
+//! mapping.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, IsTerminal, Write};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use colored::{Color, Colorize};
+use puffgres_core::Mapping;
+use puffgres_pg::{BackfillObserver, BackfillScanProgress, PostgresStateStore};
+use tokio::task::{JoinSet, LocalSet};
+
+use crate::backfill::{run_backfill, BackfillLimits};
+use crate::config::ProjectConfig;
+use crate::strict::StrictMode;
+use crate::validation::validate_transforms;
+
+/// How often the progress table is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Where one mapping's backfill stands.
+#[derive(Debug, Clone)]
+enum RowState {
+    Queued,
+    /// Completed by an earlier run, and not forced.
+    Skipped,
+    Running(Option<BackfillScanProgress>),
+    Done(Option<BackfillScanProgress>),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+struct TableRow {
+    mapping: String,
+    state: RowState,
+}
+
+type Table = Arc<Mutex<Vec<TableRow>>>;
+
+/// Records a mapping's progress in its table row.
+struct RowProgress {
+    table: Table,
+    index: usize,
+}
+
+impl RowProgress {
+    fn set(&self, state: RowState) {
+        self.table.lock().unwrap()[self.index].state = state;
+    }
+}
+
+impl BackfillObserver for RowProgress {
+    fn on_progress(&self, progress: &BackfillScanProgress) {
+        self.set(RowState::Running(Some(progress.clone())));
+    }
+
+    fn on_complete(&self, progress: &BackfillScanProgress) {
+        self.set(RowState::Done(Some(progress.clone())));
+    }
+}
+
+/// Settings shared by every backfill in the run.
+struct Shared {
+    config: ProjectConfig,
+    batch_size: u32,
+    resume: bool,
+    force: bool,
+    concurrency: Option<usize>,
+    limits: BackfillLimits,
+    strict: StrictMode,
+    table: Table,
+    queue: Mutex<VecDeque<Vec<(usize, Mapping)>>>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn cmd_backfill_all(
+    config: ProjectConfig,
+    batch_size: u32,
+    resume: bool,
+    force: bool,
+    parallel: usize,
+    concurrency: Option<usize>,
+    limits: &BackfillLimits,
+    strict: &StrictMode,
+) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    if let Err(e) = validate_transforms(&config, &store).await {
+        bail!(
+            "{}\nApplied migrations have been modified locally; run `puffgres reset`.",
+            e
+        );
+    }
+    let mappings = config.load_active_migrations(&store).await?;
+    drop(store);
+
+    if mappings.is_empty() {
+        println!("No migrations found in migrations/");
+        return Ok(());
+    }
+
+    let table: Table = Arc::new(Mutex::new(
+        mappings
+            .iter()
+            .map(|m| TableRow {
+                mapping: m.name.clone(),
+                state: RowState::Queued,
+            })
+            .collect(),
+    ));
+    let chains = namespace_chains(&mappings);
+    let workers = parallel.clamp(1, chains.len().max(1));
+    let shared = Rc::new(Shared {
+        config,
+        batch_size,
+        resume,
+        force,
+        concurrency,
+        limits: *limits,
+        strict: strict.clone(),
+        table: Arc::clone(&table),
+        queue: Mutex::new(chains.into_iter().collect()),
+    });
+
+    let interactive = io::stdout().is_terminal();
+    let redraw = interactive.then(|| {
+        let table = Arc::clone(&table);
+        let mut drawn = draw(&table, 0);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(REDRAW_INTERVAL).await;
+                drawn = draw(&table, drawn);
+            }
+        })
+    });
+
+    // Backfills aren't Send, so the workers share this thread
+    let local = LocalSet::new();
+    local
+        .run_until(async {
+            let mut tasks = JoinSet::new();
+            for _ in 0..workers {
+                tasks.spawn_local(worker(Rc::clone(&shared)));
+            }
+            while tasks.join_next().await.is_some() {}
+        })
+        .await;
+
+    if let Some(redraw) = redraw {
+        redraw.abort();
+        let _ = redraw.await;
+    }
+    // Redraw over the live table, or print it once when output isn't a terminal
+    let lines = table.lock().unwrap().len() + 1;
+    draw(&table, if interactive { lines } else { 0 });
+
+    let rows = table.lock().unwrap();
+    let failed = rows
+        .iter()
+        .filter(|r| matches!(r.state, RowState::Failed(_)))
+        .count();
+    let skipped = rows
+        .iter()
+        .filter(|r| matches!(r.state, RowState::Skipped))
+        .count();
+    if skipped > 0 {
+        println!(
+            "\n{} completed backfill(s) skipped; pass --force to run them again.",
+            skipped
+        );
+    }
+    if failed > 0 {
+        bail!("{} of {} backfill(s) failed", failed, rows.len());
+    }
+    println!("\n{}", "All backfills complete!".green());
+    Ok(())
+}
+
+/// Mappings grouped by namespace, each group in version order, with each
+/// mapping's index in `mappings`.
+fn namespace_chains(mappings: &[Mapping]) -> Vec<Vec<(usize, Mapping)>> {
+    let mut chains: BTreeMap<&str, Vec<(usize, Mapping)>> = BTreeMap::new();
+    for (index, mapping) in mappings.iter().enumerate() {
+        chains
+            .entry(mapping.namespace.as_str())
+            .or_default()
+            .push((index, mapping.clone()));
+    }
+    let mut chains: Vec<_> = chains.into_values().collect();
+    for chain in &mut chains {
+        chain.sort_by_key(|(_, m)| m.version);
+    }
+    chains
+}
+
+/// Take chains off the queue and backfill their mappings in order until the
+/// queue is empty. A failed mapping doesn't stop the rest of its chain.
+async fn worker(shared: Rc<Shared>) {
+    loop {
+        let Some(chain) = shared.queue.lock().unwrap().pop_front() else {
+            return;
+        };
+        for (index, mapping) in chain {
+            let progress = RowProgress {
+                table: Arc::clone(&shared.table),
+                index,
+            };
+            let state = match backfill_one(&shared, &mapping, &progress).await {
+                Ok(Some(state)) => state,
+                Ok(None) => continue,
+                Err(e) => RowState::Failed(format!("{:#}", e)),
+            };
+            progress.set(state);
+        }
+    }
+}
+
+/// Backfill one mapping. Returns the row's final state, or `None` when the
+/// observer already recorded it.
+async fn backfill_one(
+    shared: &Shared,
+    mapping: &Mapping,
+    progress: &RowProgress,
+) -> Result<Option<RowState>> {
+    let store = PostgresStateStore::connect(&shared.config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+
+    let previous = store.get_backfill_progress(&mapping.name).await?;
+    if !shared.force && previous.is_some_and(|p| p.status == "completed") {
+        return Ok(Some(RowState::Skipped));
+    }
+    let (schema, table) = (&mapping.source.schema, &mapping.source.table);
+    if !mapping.source.is_query() && !store.table_exists(schema, table).await? {
+        bail!("table '{}.{}' does not exist", schema, table);
+    }
+
+    progress.set(RowState::Running(None));
+    run_backfill(
+        &shared.config,
+        Arc::new(store),
+        mapping,
+        shared.batch_size,
+        shared.resume,
+        shared.concurrency,
+        1,
+        &shared.limits,
+        &shared.strict,
+        None,
+        progress,
+    )
+    .await?;
+
+    // An empty table completes without a progress update
+    let finished = matches!(
+        shared.table.lock().unwrap()[progress.index].state,
+        RowState::Done(_)
+    );
+    Ok((!finished).then_some(RowState::Done(None)))
+}
+
+/// Draw the table, first moving up over the `drawn` lines drawn last time.
+/// Returns the number of lines drawn.
+fn draw(table: &Table, drawn: usize) -> usize {
+    let lines = render(&table.lock().unwrap());
+    let mut stdout = io::stdout().lock();
+    if drawn > 0 {
+        let _ = write!(stdout, "\x1b[{}A", drawn);
+    }
+    for line in &lines {
+        let _ = writeln!(stdout, "\x1b[2K{}", line);
+    }
+    let _ = stdout.flush();
+    lines.len()
+}
+
+/// The table as lines: a header, then one line per mapping.
+fn render(rows: &[TableRow]) -> Vec<String> {
+    let width = rows
+        .iter()
+        .map(|r| r.mapping.len())
+        .max()
+        .unwrap_or(0)
+        .max("MAPPING".len());
+
+    let mut lines = vec![format!(
+        "{:<width$}  {:<9}  PROGRESS",
+        "MAPPING",
+        "STATUS",
+        width = width
+    )];
+    for row in rows {
+        let (status, color, detail) = match &row.state {
+            RowState::Queued => ("queued", Color::BrightBlack, String::new()),
+            RowState::Skipped => (
+                "skipped",
+                Color::BrightBlack,
+                "already completed".to_string(),
+            ),
+            RowState::Running(progress) => ("running", Color::Yellow, describe(progress.as_ref())),
+            RowState::Done(progress) => ("done", Color::Green, describe(progress.as_ref())),
+            RowState::Failed(error) => ("failed", Color::Red, error.clone()),
+        };
+        // Pad before coloring, since escape codes would count towards the width
+        let status = format!("{:<9}", status).color(color);
+        lines.push(format!(
+            "{:<width$}  {}  {}",
+            row.mapping,
+            status,
+            detail,
+            width = width
+        ));
+    }
+    lines
+}
+
+fn describe(progress: Option<&BackfillScanProgress>) -> String {
+    let Some(progress) = progress else {
+        return "0 read".to_string();
+    };
+    match progress.total_rows {
+        Some(total) => format!(
+            "{:>5.1}% {}/{} read, {} upserted ({:.0} rows/s)",
+            progress.percent_complete,
+            progress.processed_rows,
+            total,
+            progress.upserted_rows,
+            progress.rows_per_second
+        ),
+        None => format!(
+            "{} read, {} upserted ({:.0} rows/s)",
+            progress.processed_rows, progress.upserted_rows, progress.rows_per_second
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_core::IdType;
+
+    fn mapping(name: &str, namespace: &str, version: u32) -> Mapping {
+        Mapping::builder(name)
+            .version(version)
+            .namespace(namespace)
+            .source("public", name)
+            .id("id", IdType::Uint)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_namespace_chains() {
+        let mappings = vec![
+            mapping("users", "users", 1),
+            mapping("posts", "posts", 2),
+            mapping("users_v2", "users", 3),
+        ];
+        let chains: Vec<Vec<usize>> = namespace_chains(&mappings)
+            .into_iter()
+            .map(|chain| chain.into_iter().map(|(i, _)| i).collect())
+            .collect();
+        assert_eq!(chains, vec![vec![1], vec![0, 2]]);
+    }
+
+    #[test]
+    fn test_render() {
+        let rows = vec![
+            TableRow {
+                mapping: "users".to_string(),
+                state: RowState::Skipped,
+            },
+            TableRow {
+                mapping: "posts".to_string(),
+                state: RowState::Failed("table 'public.posts' does not exist".to_string()),
+            },
+        ];
+        let lines = render(&rows);
+        assert_eq!(lines[0], "MAPPING  STATUS     PROGRESS");
+        assert!(lines[1].starts_with("users    "));
+        assert!(lines[1].contains("skipped"));
+        assert!(lines[1].ends_with("  already completed"));
+        assert!(lines[2].starts_with("posts    "));
+        assert!(lines[2].ends_with("  table 'public.posts' does not exist"));
+    }
+}
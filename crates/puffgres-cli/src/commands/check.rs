@@ -0,0 +1,333 @@
+//! `puffgres check`: spot check a mapping's documents against its table.
+//!
+//! After an incident (a halted runner, a dropped slot, a bad deploy) it's hard
+//! to tell whether turbopuffer still matches Postgres. Check samples random
+//! rows, works out the document each should have, and compares that with what
+//! turbopuffer holds. Sampling rows can't find documents whose rows are gone,
+//! so it also reads a window of documents from turbopuffer and looks up their
+//! rows.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_core::{extract_id, Action, Document, DocumentId, IdType, Mapping, RowEvent};
+use puffgres_pg::{BackfillConfig, BackfillScanner, IdRange};
+use rs_puff::{Filter, IncludeAttributes, QueryParams, RankBy};
+use tracing::warn;
+
+use crate::backfill::{get_backfill_columns, get_backfill_text_columns, is_member};
+use crate::config::ProjectConfig;
+use crate::diff::differing_attributes;
+use crate::env::get_max_retries;
+use crate::runner::{convert_doc_id_to_json, create_transformer, MappingTransformer};
+use crate::tp::TurbopufferClient;
+
+/// How a document differs from its row.
+#[derive(Debug, Clone, PartialEq)]
+enum Drift {
+    /// The row should have a document, but turbopuffer has none.
+    Missing,
+    /// Turbopuffer has a document for a row that was deleted or isn't in the
+    /// mapping.
+    Extra,
+    /// These attributes differ from what the row transforms to.
+    Mismatch(Vec<String>),
+}
+
+/// A document that has drifted from its row.
+pub(super) struct Finding {
+    namespace: String,
+    id: serde_json::Value,
+    drift: Drift,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} id {}: ", self.namespace, self.id)?;
+        match &self.drift {
+            Drift::Missing => write!(f, "missing from turbopuffer"),
+            Drift::Extra => write!(
+                f,
+                "in turbopuffer, but its row is gone or not in the mapping"
+            ),
+            Drift::Mismatch(attributes) => {
+                write!(f, "attributes differ: {}", attributes.join(", "))
+            }
+        }
+    }
+}
+
+/// The document each row should have, if any, grouped by namespace.
+pub(super) type Expected = BTreeMap<String, Vec<(DocumentId, Option<Document>)>>;
+
+pub async fn cmd_check(config: ProjectConfig, mapping_name: &str, sample: u32) -> Result<()> {
+    let mappings = config.load_migrations()?;
+    let mapping = mappings
+        .iter()
+        .find(|m| m.name == mapping_name)
+        .with_context(|| format!("Mapping '{}' not found", mapping_name))?;
+    let scanner = BackfillScanner::new(BackfillConfig {
+        connection_string: config.postgres_connection_string()?,
+        schema: mapping.source.schema.clone(),
+        table: mapping.source.table.clone(),
+        id_columns: mapping.id.columns().to_vec(),
+        columns: get_backfill_columns(mapping),
+        batch_size: sample,
+        filter: None,
+        snapshot: None,
+        text_columns: get_backfill_text_columns(mapping),
+        query: mapping.source.query.clone(),
+        range: IdRange::default(),
+        copy: false,
+    })
+    .await
+    .context("Failed to connect to Postgres")?;
+    let tp = TurbopufferClient::new(config.turbopuffer_api_key()?, get_max_retries());
+    let transformer = create_transformer(mapping);
+
+    println!(
+        "Checking {} random rows of {} against turbopuffer...\n",
+        sample,
+        mapping.source.describe()
+    );
+
+    let rows = scanner.sample(sample).await?;
+    let expected = expected_documents(mapping, &transformer, &rows)?;
+
+    let (checked, mut findings) = compare_documents(&tp, &expected).await?;
+    let mut reported: HashSet<_> = findings
+        .iter()
+        .map(|f| (f.namespace.clone(), f.id.to_string()))
+        .collect();
+
+    // Read the documents after a sampled id in each namespace, and look up
+    // their rows. A composite id can't be turned back into its columns, so
+    // those documents aren't scanned
+    let scan_documents = mapping.id.composite.is_none();
+    let mut scanned = 0;
+    for (namespace, docs) in expected.iter().filter(|_| scan_documents) {
+        let Some((pivot, _)) = docs.first() else {
+            continue;
+        };
+        let params = QueryParams {
+            rank_by: Some(RankBy::asc("id")),
+            top_k: Some(sample as u64),
+            filters: Some(Filter::gte("id", convert_doc_id_to_json(pivot))),
+            ..Default::default()
+        };
+        let ids: Vec<serde_json::Value> = tp
+            .query(namespace, params)
+            .await?
+            .into_iter()
+            .filter_map(|mut row| row.remove("id"))
+            .collect();
+        scanned += ids.len();
+
+        let row_ids: Vec<String> = ids
+            .iter()
+            .filter_map(|id| source_id(mapping.id.id_type, id))
+            .collect();
+        let rows = scanner.fetch_by_ids(&row_ids).await?;
+        let present: HashSet<String> = expected_documents(mapping, &transformer, &rows)?
+            .remove(namespace)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, doc)| doc.is_some())
+            .map(|(id, _)| convert_doc_id_to_json(&id).to_string())
+            .collect();
+
+        for id in ids {
+            let key = (namespace.clone(), id.to_string());
+            if present.contains(&key.1) || !reported.insert(key) {
+                continue;
+            }
+            findings.push(Finding {
+                namespace: namespace.clone(),
+                id,
+                drift: Drift::Extra,
+            });
+        }
+    }
+
+    for finding in &findings {
+        println!("  {} {}", "✗".red(), finding);
+    }
+    println!(
+        "\nChecked {} rows and scanned {} documents in turbopuffer.",
+        checked, scanned
+    );
+
+    if !findings.is_empty() {
+        bail!(
+            "{} document(s) of mapping '{}' don't match {}",
+            findings.len(),
+            mapping.name,
+            mapping.source.describe()
+        );
+    }
+    println!("{}", "No drift found.".green());
+    Ok(())
+}
+
+/// Work out the document each row should have: `None` for rows outside the
+/// mapping and rows the transform skips or deletes.
+pub(super) fn expected_documents(
+    mapping: &Mapping,
+    transformer: &MappingTransformer,
+    rows: &[RowEvent],
+) -> Result<Expected> {
+    let mut expected = Expected::new();
+    let mut members = Vec::new();
+    for event in rows {
+        let id = match extract_id(event, &mapping.id) {
+            Ok(id) => id,
+            Err(e) => {
+                warn!(mapping = %mapping.name, error = %e, "Skipping row without a valid ID");
+                continue;
+            }
+        };
+        let namespace = match mapping.namespace_for(event) {
+            Ok(namespace) => namespace,
+            Err(e) => {
+                warn!(mapping = %mapping.name, error = %e, "Skipping row without a namespace");
+                continue;
+            }
+        };
+
+        if is_member(mapping, event) {
+            members.push((event, id, namespace));
+        } else {
+            expected.entry(namespace).or_default().push((id, None));
+        }
+    }
+
+    if members.is_empty() {
+        return Ok(expected);
+    }
+    let batch: Vec<_> = members
+        .iter()
+        .map(|(event, id, _)| (*event, id.clone()))
+        .collect();
+    let actions = transformer
+        .transform_batch(&batch)
+        .context("Transform failed on the sampled rows")?;
+
+    for ((_, id, namespace), action) in members.into_iter().zip(actions) {
+        let doc = match action {
+            Action::Upsert { doc, .. } => Some(doc),
+            Action::Error { message, .. } => {
+                warn!(mapping = %mapping.name, error = %message, "Skipping row the transform failed on");
+                continue;
+            }
+            // A patch only says what changed, so there's no full document to compare
+            Action::Patch { .. } => continue,
+            _ => None,
+        };
+        expected.entry(namespace).or_default().push((id, doc));
+    }
+
+    Ok(expected)
+}
+
+/// Compare the documents rows should have with what turbopuffer holds.
+/// Returns how many rows were compared and the documents that drifted.
+pub(super) async fn compare_documents(
+    tp: &TurbopufferClient,
+    expected: &Expected,
+) -> Result<(usize, Vec<Finding>)> {
+    let mut findings = Vec::new();
+    let mut checked = 0;
+    for (namespace, docs) in expected {
+        let ids = docs
+            .iter()
+            .map(|(id, _)| convert_doc_id_to_json(id))
+            .collect();
+        let found = fetch_documents(tp, namespace, ids).await?;
+
+        for (id, doc) in docs {
+            checked += 1;
+            let id = convert_doc_id_to_json(id);
+            let drift = match (doc, found.get(&id.to_string())) {
+                (Some(_), None) => Drift::Missing,
+                (None, Some(_)) => Drift::Extra,
+                (None, None) => continue,
+                (Some(doc), Some(row)) => {
+                    let differing = differing_attributes(doc, row);
+                    if differing.is_empty() {
+                        continue;
+                    }
+                    Drift::Mismatch(differing)
+                }
+            };
+            findings.push(Finding {
+                namespace: namespace.clone(),
+                id,
+                drift,
+            });
+        }
+    }
+    Ok((checked, findings))
+}
+
+/// Fetch documents by id, keyed by the id's JSON text.
+async fn fetch_documents(
+    tp: &TurbopufferClient,
+    namespace: &str,
+    ids: Vec<serde_json::Value>,
+) -> Result<HashMap<String, rs_puff::Row>> {
+    let params = QueryParams {
+        rank_by: Some(RankBy::asc("id")),
+        top_k: Some(ids.len() as u64),
+        filters: Some(Filter::r#in("id", ids)),
+        include_attributes: Some(IncludeAttributes::All(true)),
+        ..Default::default()
+    };
+    let rows = tp.query(namespace, params).await?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| Some((row.get("id")?.to_string(), row)))
+        .collect())
+}
+
+/// The id column value of a document's row, as text. `None` when it can't
+/// be recovered, e.g. an obfuscated id without the secret.
+fn source_id(id_type: IdType, id: &serde_json::Value) -> Option<String> {
+    match (id_type, id) {
+        (IdType::ObfuscatedUint(Some(obfuscator)), serde_json::Value::Number(n)) => {
+            n.as_u64().map(|n| obfuscator.decode(n).to_string())
+        }
+        (IdType::ObfuscatedUint(_), _) => None,
+        // Written as hex, read back in bytea's text form
+        (IdType::Bytes, serde_json::Value::String(s)) => Some(format!("\\x{}", s)),
+        (_, serde_json::Value::Number(n)) => Some(n.to_string()),
+        (_, serde_json::Value::String(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_core::IdObfuscator;
+    use serde_json::json;
+
+    #[test]
+    fn test_source_id() {
+        assert_eq!(source_id(IdType::Uint, &json!(42)), Some("42".into()));
+        assert_eq!(source_id(IdType::Uuid, &json!("a-b")), Some("a-b".into()));
+        assert_eq!(
+            source_id(IdType::Bytes, &json!("0102")),
+            Some("\\x0102".into())
+        );
+
+        let obfuscator = IdObfuscator::from_secret("secret");
+        let encoded = json!(obfuscator.encode(42));
+        assert_eq!(
+            source_id(IdType::ObfuscatedUint(Some(obfuscator)), &encoded),
+            Some("42".into())
+        );
+        assert_eq!(source_id(IdType::ObfuscatedUint(None), &encoded), None);
+    }
+}
@@ -1,9 +1,11 @@
+use std::collections::BTreeSet;
 use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
 
 use anyhow::{Context, Result};
 use colored::Colorize;
+use puffgres_core::Mapping;
 use puffgres_pg::PostgresStateStore;
 
 use crate::config::ProjectConfig;
@@ -17,6 +19,8 @@ pub async fn cmd_dangerously_delete_config(config: ProjectConfig) -> Result<()>
     println!("    - __puffgres_checkpoints");
     println!("    - __puffgres_dlq");
     println!("    - __puffgres_backfill");
+    println!("    - __puffgres_backfill_partitions");
+    println!("    - __puffgres_control");
     println!("    - __puffgres_transforms");
     println!("  • Remove local migrations/ and transforms/ directories");
     println!();
@@ -70,8 +74,8 @@ pub async fn cmd_dangerously_reset_turbopuffer(config: ProjectConfig) -> Result<
 
     // Load migrations to find all namespaces
     let mappings = config.load_migrations()?;
-    let namespaces: Vec<&str> = mappings.iter().map(|m| m.namespace.as_str()).collect();
-    let unique_namespaces: std::collections::HashSet<&str> = namespaces.into_iter().collect();
+    let client = rs_puff::Client::new(config.turbopuffer_api_key()?);
+    let unique_namespaces = find_namespaces(&client, &mappings).await?;
 
     println!("This will delete the following turbopuffer namespaces:");
     for ns in &unique_namespaces {
@@ -101,11 +105,8 @@ pub async fn cmd_dangerously_reset_turbopuffer(config: ProjectConfig) -> Result<
 
     println!("\nDeleting turbopuffer namespaces...");
 
-    // Create turbopuffer client
-    let client = rs_puff::Client::new(config.turbopuffer_api_key()?);
-
     for ns in unique_namespaces {
-        match client.namespace(ns).delete_all().await {
+        match client.namespace(&ns).delete_all().await {
             Ok(_) => println!("  ✓ Deleted namespace: {}", ns),
             Err(e) => println!("  ✗ Failed to delete {}: {}", ns, e),
         }
@@ -132,3 +133,44 @@ pub async fn cmd_dangerously_reset_turbopuffer(config: ProjectConfig) -> Result<
     println!("Run 'puffgres backfill <mapping>' to re-sync your data.");
     Ok(())
 }
+
+/// Namespaces written by the mappings. A partitioned mapping's namespaces
+/// are looked up in turbopuffer by the fixed prefix of its template.
+pub(super) async fn find_namespaces(
+    client: &rs_puff::Client,
+    mappings: &[Mapping],
+) -> Result<BTreeSet<String>> {
+    let mut namespaces = BTreeSet::new();
+    for mapping in mappings {
+        let template = mapping.namespace_template()?;
+        if !template.is_partitioned() {
+            namespaces.insert(mapping.namespace.clone());
+            continue;
+        }
+
+        let mut cursor = None;
+        loop {
+            let page = client
+                .namespaces(rs_puff::NamespacesParams {
+                    prefix: Some(template.prefix().to_string()).filter(|p| !p.is_empty()),
+                    cursor,
+                    ..Default::default()
+                })
+                .await
+                .with_context(|| {
+                    format!("Failed to list namespaces for mapping '{}'", mapping.name)
+                })?;
+            namespaces.extend(
+                page.namespaces
+                    .into_iter()
+                    .map(|ns| ns.id)
+                    .filter(|id| template.matches(id)),
+            );
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+    }
+    Ok(namespaces)
+}
@@ -0,0 +1,636 @@
+//! `puffgres doctor`: check migrations against the live schema.
+//!
+//! Dropping or renaming a column doesn't stop replication: the attribute just
+//! stops being written, or a predicate quietly stops matching. Doctor finds
+//! the columns each mapping reads that no longer exist, guesses which were
+//! renamed, and writes out a revised migration that follows the change.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_config::{MembershipMode, MigrationConfig};
+use puffgres_core::{Expression, NamespaceTemplate, Predicate};
+use puffgres_pg::PostgresStateStore;
+
+use crate::config::{parse_migration, ProjectConfig};
+
+/// A column a mapping reads that's missing from its source table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ColumnDrift {
+    pub column: String,
+    /// An unmapped column with a similar name, which it was probably
+    /// renamed to.
+    pub renamed_to: Option<String>,
+}
+
+impl fmt::Display for ColumnDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.renamed_to {
+            Some(new) => write!(
+                f,
+                "column '{}' is gone; it looks like it was renamed to '{}'",
+                self.column, new
+            ),
+            None => write!(f, "column '{}' was dropped", self.column),
+        }
+    }
+}
+
+/// A migration revised to follow schema changes.
+#[derive(Debug)]
+pub(crate) struct Revision {
+    pub mapping_name: String,
+    pub version: i64,
+    pub content: String,
+}
+
+pub async fn cmd_doctor(config: ProjectConfig, write: bool) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+
+    let migrations = migration_files(Path::new("migrations"))?;
+    if migrations.is_empty() {
+        println!("No migrations found in migrations/");
+        return Ok(());
+    }
+
+    let mut next_version = migrations.iter().map(|(_, c)| c.version).max().unwrap_or(0) + 1;
+    let mut problems = 0;
+    let mut revisions = Vec::new();
+
+    println!("Checking migrations against the database schema...\n");
+    for (path, migration) in &migrations {
+        let label = format!("v{} {}", migration.version, migration.mapping_name);
+        if migration.source.query.is_some() {
+            println!("  - {}: reads from a query, skipped", label);
+            continue;
+        }
+        let (schema, table) = (&migration.source.schema, &migration.source.table);
+
+        let columns = store.table_columns(schema, table).await?;
+        if columns.is_empty() {
+            println!(
+                "  ✗ {}: table '{}.{}' does not exist",
+                label.red(),
+                schema,
+                table
+            );
+            problems += 1;
+            continue;
+        }
+
+        let drift = find_drift(migration, &columns);
+        if drift.is_empty() {
+            println!("  ✓ {}", label.green());
+            continue;
+        }
+
+        problems += 1;
+        println!("  ✗ {} ({}.{}):", label.red(), schema, table);
+        for d in &drift {
+            println!("      {}", d);
+        }
+
+        match suggest_revision(migration, &drift, next_version) {
+            Ok(revision) => {
+                let revision_path = PathBuf::from(format!(
+                    "migrations/{:04}_{}.toml",
+                    revision.version, revision.mapping_name
+                ));
+                if write {
+                    fs::write(&revision_path, &revision.content)
+                        .with_context(|| format!("Failed to write {}", revision_path.display()))?;
+                    println!(
+                        "    Wrote suggested revision to {}",
+                        revision_path.display().to_string().cyan()
+                    );
+                } else {
+                    println!(
+                        "    Suggested revision ({}):\n",
+                        revision_path.display().to_string().cyan()
+                    );
+                    for line in revision.content.lines() {
+                        println!("      {}", line);
+                    }
+                    println!();
+                }
+                revisions.push((path.clone(), revision));
+                next_version += 1;
+            }
+            Err(blockers) => {
+                for blocker in blockers {
+                    println!("    {} {}", "Can't fix automatically:".yellow(), blocker);
+                }
+            }
+        }
+    }
+
+    if !revisions.is_empty() {
+        println!("\nTo apply:");
+        if !write {
+            println!("  puffgres doctor --write");
+        }
+        for (path, _) in &revisions {
+            println!("  rm {}", path.display());
+        }
+        println!("  puffgres migrate");
+        for (_, revision) in &revisions {
+            println!("  puffgres backfill {}", revision.mapping_name);
+        }
+        println!(
+            "\nRenamed columns keep their attribute names. The backfill rewrites existing \
+             documents so attributes of dropped columns are removed."
+        );
+    }
+
+    if problems > 0 {
+        bail!("{} migration(s) don't match the database schema", problems);
+    }
+    println!("\n{}", "All migrations match the database schema.".green());
+    Ok(())
+}
+
+/// Parse every migration in `dir`, keeping the file each came from.
+fn migration_files(dir: &Path) -> Result<Vec<(PathBuf, MigrationConfig)>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read migration: {}", path.display()))?;
+            let config = parse_migration(&content)
+                .with_context(|| format!("Failed to parse migration: {}", path.display()))?;
+            Ok((path, config))
+        })
+        .collect()
+}
+
+/// Every source column a migration reads.
+fn mapped_columns(config: &MigrationConfig) -> BTreeSet<String> {
+    let mut columns = BTreeSet::new();
+    columns.extend(config.id.all_columns().iter().cloned());
+    columns.extend(config.columns.include.iter().cloned());
+    columns.extend(config.columns.rename.keys().cloned());
+    columns.extend(config.flatten.columns.iter().cloned());
+    columns.extend(config.versioning.column.iter().cloned());
+    columns.extend(config.vector.iter().map(|v| v.column.clone()));
+
+    if let Ok(template) = NamespaceTemplate::parse(&config.namespace) {
+        columns.extend(template.columns().into_iter().map(str::to_string));
+    }
+    if let Some(predicate) = membership_predicate(config) {
+        columns.extend(predicate.columns().into_iter().map(str::to_string));
+    }
+    for expr in config.computed.values() {
+        if let Ok(expr) = Expression::parse(expr) {
+            columns.extend(expr.columns().into_iter().map(str::to_string));
+        }
+    }
+    columns
+}
+
+fn membership_predicate(config: &MigrationConfig) -> Option<Predicate> {
+    if config.membership.mode != MembershipMode::Dsl {
+        return None;
+    }
+    Predicate::parse(config.membership.predicate.as_deref()?).ok()
+}
+
+/// Compare the columns a migration reads with the table's columns.
+pub(crate) fn find_drift(config: &MigrationConfig, table_columns: &[String]) -> Vec<ColumnDrift> {
+    let mapped = mapped_columns(config);
+    let mut candidates: Vec<&str> = table_columns
+        .iter()
+        .filter(|c| !mapped.contains(*c))
+        .map(String::as_str)
+        .collect();
+
+    let mut drift = Vec::new();
+    for column in &mapped {
+        if table_columns.contains(column) {
+            continue;
+        }
+        let renamed_to = likely_rename(column, &candidates);
+        // Two missing columns can't both have been renamed to one column
+        candidates.retain(|c| Some(*c) != renamed_to);
+        drift.push(ColumnDrift {
+            column: column.clone(),
+            renamed_to: renamed_to.map(str::to_string),
+        });
+    }
+    drift
+}
+
+/// The candidate whose name is clearly closest to `column`, if any.
+fn likely_rename<'a>(column: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let mut best: Option<(usize, &str)> = None;
+    let mut tied = false;
+    for &candidate in candidates {
+        let Some(distance) = rename_distance(column, candidate) else {
+            continue;
+        };
+        match best {
+            Some((best_distance, _)) if distance > best_distance => {}
+            Some((best_distance, _)) if distance == best_distance => tied = true,
+            _ => {
+                best = Some((distance, candidate));
+                tied = false;
+            }
+        }
+    }
+    best.filter(|_| !tied).map(|(_, candidate)| candidate)
+}
+
+/// Edit distance between two column names, if they're similar enough that
+/// one was plausibly renamed to the other: a few characters apart, or one
+/// containing the other (`name` and `full_name`).
+fn rename_distance(old: &str, new: &str) -> Option<usize> {
+    let (old, new) = (old.to_lowercase(), new.to_lowercase());
+    let distance = levenshtein(&old, &new);
+    let shorter = old.len().min(new.len());
+    let longer = old.len().max(new.len());
+
+    let contained = shorter >= 3 && (old.contains(&new) || new.contains(&old));
+    (contained || distance <= (longer / 3).max(1)).then_some(distance)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Revise a migration to follow `drift` as a new migration at `version`.
+///
+/// Renamed columns are substituted everywhere the migration reads them and
+/// keep their attribute names through `[columns.rename]`; dropped columns
+/// are removed from the attributes. Returns the reasons a revision can't be
+/// made when the migration can't work without a dropped column.
+pub(crate) fn suggest_revision(
+    config: &MigrationConfig,
+    drift: &[ColumnDrift],
+    version: i64,
+) -> std::result::Result<Revision, Vec<String>> {
+    let mut revised = config.clone();
+    let mut notes = Vec::new();
+    let mut blockers = Vec::new();
+
+    let namespace_columns: Vec<String> = NamespaceTemplate::parse(&config.namespace)
+        .map(|t| t.columns().into_iter().map(str::to_string).collect())
+        .unwrap_or_default();
+    let predicate_columns: Vec<String> = membership_predicate(config)
+        .map(|p| p.columns().into_iter().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    for d in drift {
+        let old = &d.column;
+        let columns = &mut revised.columns;
+
+        let Some(new) = &d.renamed_to else {
+            if config.id.all_columns().contains(old) {
+                blockers.push(format!("the id column '{}' was dropped", old));
+            }
+            if namespace_columns.contains(old) {
+                blockers.push(format!("the namespace uses dropped column '{}'", old));
+            }
+            if predicate_columns.contains(old) {
+                blockers.push(format!(
+                    "the membership predicate uses dropped column '{}'",
+                    old
+                ));
+            }
+            if config.versioning.column.as_ref() == Some(old) {
+                blockers.push(format!("the versioning column '{}' was dropped", old));
+            }
+            if config.vector.as_ref().is_some_and(|v| v.column == *old) {
+                blockers.push(format!("the vector column '{}' was dropped", old));
+            }
+            for (attribute, expr) in &config.computed {
+                let reads_old = Expression::parse(expr)
+                    .map(|e| e.columns().contains(&old.as_str()))
+                    .unwrap_or(false);
+                if reads_old {
+                    blockers.push(format!(
+                        "computed attribute '{}' uses dropped column '{}'",
+                        attribute, old
+                    ));
+                }
+            }
+            columns.include.retain(|c| c != old);
+            columns.rename.remove(old);
+            revised.flatten.columns.retain(|c| c != old);
+            notes.push(format!("'{}' was dropped", old));
+            continue;
+        };
+
+        // Keep writing the attribute under the name it had
+        let is_vector = config.vector.as_ref().is_some_and(|v| v.column == *old);
+        let written = !config.id.all_columns().contains(old)
+            && !is_vector
+            && (columns.include.is_empty() || columns.include.contains(old));
+        let attribute = columns.rename.remove(old).unwrap_or_else(|| old.clone());
+        if written {
+            columns.rename.insert(new.clone(), attribute.clone());
+        }
+        for column in &mut columns.include {
+            if column == old {
+                column.clone_from(new);
+            }
+        }
+        for column in &mut revised.flatten.columns {
+            if column == old {
+                column.clone_from(new);
+            }
+        }
+        if revised.id.column == *old {
+            revised.id.column.clone_from(new);
+        }
+        for column in &mut revised.id.columns {
+            if column == old {
+                column.clone_from(new);
+            }
+        }
+        if revised.versioning.column.as_ref() == Some(old) {
+            revised.versioning.column = Some(new.clone());
+        }
+        if let Some(vector) = revised.vector.as_mut().filter(|_| is_vector) {
+            vector.column.clone_from(new);
+        }
+        revised.namespace = revised
+            .namespace
+            .replace(&format!("{{{}}}", old), &format!("{{{}}}", new));
+        if let Some(predicate) = &mut revised.membership.predicate {
+            *predicate = rename_identifier(predicate, old, new);
+        }
+        for expr in revised.computed.values_mut() {
+            *expr = rename_identifier(expr, old, new);
+        }
+
+        if written {
+            notes.push(format!(
+                "'{}' was renamed to '{}' (still written as '{}')",
+                old, new, attribute
+            ));
+        } else {
+            notes.push(format!("'{}' was renamed to '{}'", old, new));
+        }
+    }
+
+    if !blockers.is_empty() {
+        return Err(blockers);
+    }
+
+    revised.extends = None;
+    revised.version = version;
+    revised.mapping_name = revision_name(&config.mapping_name, version);
+
+    let body = toml::to_string(&revised).map_err(|e| vec![e.to_string()])?;
+    let mut content = format!(
+        "# Suggested by `puffgres doctor`: revises v{} '{}' for changes to {}.{}\n",
+        config.version, config.mapping_name, config.source.schema, config.source.table
+    );
+    for note in &notes {
+        content.push_str(&format!("# - {}\n", note));
+    }
+    if config.transform.path.is_some() {
+        content.push_str("# Check that the transform reads the new column names.\n");
+    }
+    content.push('\n');
+    content.push_str(&body);
+
+    Ok(Revision {
+        mapping_name: revised.mapping_name,
+        version,
+        content,
+    })
+}
+
+/// Name a revision `<name>_v<version>`, replacing an earlier `_v<n>` suffix.
+fn revision_name(mapping_name: &str, version: i64) -> String {
+    let base = match mapping_name.rsplit_once("_v") {
+        Some((base, n)) if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) => base,
+        _ => mapping_name,
+    };
+    format!("{}_v{}", base, version)
+}
+
+/// Replace the identifier `old` with `new` in a predicate or computed
+/// expression, leaving string literals alone.
+fn rename_identifier(predicate: &str, old: &str, new: &str) -> String {
+    let mut out = String::with_capacity(predicate.len());
+    let mut word = String::new();
+    let mut in_string = false;
+    for c in predicate.chars() {
+        if !in_string && (c.is_ascii_alphanumeric() || c == '_') {
+            word.push(c);
+            continue;
+        }
+        out.push_str(if word == old { new } else { &word });
+        word.clear();
+        if c == '\'' {
+            in_string = !in_string;
+        }
+        out.push(c);
+    }
+    out.push_str(if word == old { new } else { &word });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(extra: &str) -> MigrationConfig {
+        MigrationConfig::parse(&format!(
+            r#"
+version = 3
+mapping_name = "users_public"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+{extra}
+"#
+        ))
+        .unwrap()
+    }
+
+    fn table(columns: &[&str]) -> Vec<String> {
+        columns.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find_drift() {
+        let config = migration(
+            r#"columns = ["id", "full_name", "legacy_score", "email"]
+
+[membership]
+mode = "dsl"
+predicate = "status = 'active'"
+"#,
+        );
+        assert!(find_drift(
+            &config,
+            &table(&["id", "full_name", "legacy_score", "email", "status"])
+        )
+        .is_empty());
+
+        let drift = find_drift(&config, &table(&["id", "name", "email", "status", "bio"]));
+        assert_eq!(
+            drift,
+            vec![
+                ColumnDrift {
+                    column: "full_name".into(),
+                    renamed_to: Some("name".into()),
+                },
+                ColumnDrift {
+                    column: "legacy_score".into(),
+                    renamed_to: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_likely_rename() {
+        assert_eq!(
+            likely_rename("email", &["email_address", "bio"]),
+            Some("email_address")
+        );
+        assert_eq!(likely_rename("colour", &["color", "size"]), Some("color"));
+        assert_eq!(likely_rename("title", &["body", "slug"]), None);
+        // Ambiguous guesses aren't made
+        assert_eq!(likely_rename("name", &["given_name", "first_name"]), None);
+    }
+
+    #[test]
+    fn test_suggest_revision() {
+        let config = migration(
+            r#"
+[columns]
+include = ["id", "full_name", "legacy_score", "status"]
+
+[columns.rename]
+legacy_score = "score"
+
+[membership]
+mode = "dsl"
+predicate = "status = 'full_name' AND full_name IS NOT NULL"
+
+[computed]
+display_name = "coalesce(full_name, 'full_name')"
+"#,
+        );
+        let drift = vec![
+            ColumnDrift {
+                column: "full_name".into(),
+                renamed_to: Some("name".into()),
+            },
+            ColumnDrift {
+                column: "legacy_score".into(),
+                renamed_to: None,
+            },
+        ];
+
+        let revision = suggest_revision(&config, &drift, 7).unwrap();
+        assert_eq!(revision.mapping_name, "users_public_v7");
+        assert!(revision
+            .content
+            .contains("'full_name' was renamed to 'name' (still written as 'full_name')"));
+
+        let revised = MigrationConfig::parse(&revision.content).unwrap();
+        assert_eq!(revised.version, 7);
+        assert_eq!(revised.namespace, "users");
+        assert_eq!(revised.columns.include, vec!["id", "name", "status"]);
+        assert_eq!(revised.columns.rename.len(), 1);
+        assert_eq!(revised.columns.rename["name"], "full_name");
+        assert_eq!(
+            revised.membership.predicate.as_deref(),
+            Some("status = 'full_name' AND name IS NOT NULL")
+        );
+        assert_eq!(
+            revised.computed["display_name"],
+            "coalesce(name, 'full_name')"
+        );
+        puffgres_config::validate_migration(&revised).unwrap();
+    }
+
+    #[test]
+    fn test_dropped_required_column_blocks_revision() {
+        let config = migration(
+            r#"
+[membership]
+mode = "dsl"
+predicate = "deleted_at IS NULL"
+"#,
+        );
+        let drift = vec![ColumnDrift {
+            column: "deleted_at".into(),
+            renamed_to: None,
+        }];
+        let blockers = suggest_revision(&config, &drift, 4).unwrap_err();
+        assert_eq!(
+            blockers,
+            vec!["the membership predicate uses dropped column 'deleted_at'"]
+        );
+    }
+
+    #[test]
+    fn test_renamed_vector_column() {
+        let config = migration(
+            r#"
+[vector]
+column = "embedding"
+"#,
+        );
+        let drift = vec![ColumnDrift {
+            column: "embedding".into(),
+            renamed_to: Some("embedding_v2".into()),
+        }];
+        let revision = suggest_revision(&config, &drift, 4).unwrap();
+        let revised = MigrationConfig::parse(&revision.content).unwrap();
+        assert_eq!(revised.vector.unwrap().column, "embedding_v2");
+        assert!(revised.columns.rename.is_empty());
+
+        let drift = vec![ColumnDrift {
+            column: "embedding".into(),
+            renamed_to: None,
+        }];
+        let blockers = suggest_revision(&config, &drift, 4).unwrap_err();
+        assert_eq!(blockers, vec!["the vector column 'embedding' was dropped"]);
+    }
+
+    #[test]
+    fn test_revision_name() {
+        assert_eq!(revision_name("users_public", 4), "users_public_v4");
+        assert_eq!(revision_name("users_public_v4", 9), "users_public_v9");
+        assert_eq!(revision_name("users_vip", 2), "users_vip_v2");
+    }
+}
@@ -9,10 +9,17 @@ use tracing::info;
 pub async fn cmd_init() -> Result<()> {
     println!("Initializing puffgres project...\n");
 
-    // Create puffgres/ directory with migrations and transforms subdirectories
+    // Create puffgres/ directory with migrations, transforms and templates subdirectories
     fs::create_dir_all("puffgres/migrations")?;
     fs::create_dir_all("puffgres/transforms")?;
-    info!("Created puffgres/migrations/ and puffgres/transforms/");
+    fs::create_dir_all("puffgres/templates")?;
+    info!("Created puffgres/migrations/, puffgres/transforms/ and puffgres/templates/");
+
+    // Keep templates/ in version control even when empty (the Dockerfile copies it)
+    let templates_keep = Path::new("puffgres/templates/.gitkeep");
+    if !templates_keep.exists() {
+        fs::write(templates_keep, "")?;
+    }
 
     // Create or update package.json with required dependencies
     ensure_package_json()?;
@@ -24,6 +31,10 @@ pub async fn cmd_init() -> Result<()> {
 # Postgres connection string (Supabase, Neon, etc.)
 DATABASE_URL=
 
+# Optional: Stream changes from a different server, e.g. a Postgres 16+ physical standby
+# (puffgres state and publications still live on DATABASE_URL, the primary)
+# REPLICATION_DATABASE_URL=
+
 # Turbopuffer API key
 TURBOPUFFER_API_KEY=
 
@@ -34,9 +45,43 @@ TURBOPUFFER_API_KEY=
 # PUFFGRES_TRANSFORM_BATCH_SIZE=100
 # PUFFGRES_UPLOAD_BATCH_SIZE=500
 
+# Optional: Concurrent turbopuffer writes during backfill (default: 4, overridden by --concurrency)
+# PUFFGRES_UPLOAD_CONCURRENCY=4
+
+# Optional: Documents whose hash the runner remembers for content_hash versioning (default: 100000)
+# PUFFGRES_DOC_HASH_CACHE_SIZE=100000
+
+# Required for mappings with `type = "obfuscated_uint"` ids (at least 16 characters)
+# Changing it changes every document id; `puffgres id decode` maps ids back
+# PUFFGRES_ID_SECRET=
+
 # Optional: Maximum retries for failed turbopuffer uploads (default: 5)
 # Uses exponential backoff: 100ms, 200ms, 400ms, 800ms, 1600ms
 # PUFFGRES_MAX_RETRIES=5
+
+# Optional: How often backfill progress is saved, whichever comes first (defaults: 5 seconds, 50000 rows)
+# Progress is always saved when a backfill finishes or fails
+# PUFFGRES_BACKFILL_PROGRESS_INTERVAL_SECS=5
+# PUFFGRES_BACKFILL_PROGRESS_ROWS=50000
+
+# Optional: Database lookups from transforms via ctx.query() / ctx.lookup()
+# Queries run on a read-only session against this URL (default: DATABASE_URL)
+# PUFFGRES_TRANSFORM_DATABASE_URL=
+# Maximum ctx.query() calls per event in a transform batch (default: 5)
+# PUFFGRES_TRANSFORM_QUERY_BUDGET=5
+
+# Optional: Warn in `puffgres status` when the replication slot retains more WAL than this (default: 1024)
+# PUFFGRES_WAL_RETENTION_WARN_MB=1024
+
+# Optional: Warn when a transaction has been open this long, since its changes wait for its commit (default: 600, 0 disables)
+# PUFFGRES_LONG_TRANSACTION_WARN_SECS=600
+
+# Optional: Write to a __puffgres_heartbeat table this often, so the slot keeps advancing on idle databases (default: off)
+# PUFFGRES_HEARTBEAT_INTERVAL_SECS=60
+
+# Optional: Treat warnings as errors, e.g. for CI smoke runs (same as --strict)
+# true for all checks, or a comma-separated list of: id, transform, decode, truncate, replica_identity
+# PUFFGRES_STRICT=true
 "#;
 
     let env_example_path = Path::new("puffgres/.env.example");
@@ -102,9 +147,10 @@ COPY --from=builder /build/npm /opt/puffgres-npm
 
 WORKDIR /app
 
-# Copy puffgres project files (migrations, transforms, package.json)
+# Copy puffgres project files (migrations, transforms, templates, package.json)
 COPY migrations ./migrations
 COPY transforms ./transforms
+COPY templates ./templates
 COPY package.json ./package.json
 
 # Update package.json to use local npm package instead of registry
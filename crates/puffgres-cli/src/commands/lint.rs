@@ -0,0 +1,401 @@
+//! `puffgres lint`: static checks across all migration files.
+//!
+//! Each migration is validated on its own when it is loaded, but problems
+//! that span files (two mappings writing one namespace) or only show up
+//! at runtime (a predicate on a column the backfill never selects) slip
+//! through until a sync misbehaves. Lint reports them without touching the
+//! database, so it can run in CI.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_config::{validate_migration, MembershipMode, MigrationConfig, TransformType};
+use puffgres_core::Predicate;
+
+use crate::config::parse_migration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in a migration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub severity: Severity,
+    pub file: String,
+    pub message: String,
+}
+
+impl LintIssue {
+    fn error(file: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            file: file.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(file: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            file: file.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Error => "error".red().bold(),
+            Severity::Warning => "warning".yellow().bold(),
+        };
+        write!(f, "{}: {}: {}", label, self.file, self.message)
+    }
+}
+
+pub fn cmd_lint(deny_warnings: bool) -> Result<()> {
+    let (migrations, mut issues) = load_migrations(Path::new("migrations"))?;
+    issues.extend(lint_migrations(&migrations, Path::new(".")));
+    issues.sort_by(|a, b| a.file.cmp(&b.file).then(b.severity.cmp(&a.severity)));
+
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    let warnings = issues.len() - errors;
+
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        bail!(
+            "Lint failed: {} error(s), {} warning(s) in {} migration(s)",
+            errors,
+            warnings,
+            migrations.len()
+        );
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Linted {} migration(s): {} warning(s)",
+            migrations.len(),
+            warnings
+        )
+        .green()
+    );
+    Ok(())
+}
+
+/// Parse every migration in `dir`. Files that don't parse are reported as
+/// issues rather than stopping the lint.
+fn load_migrations(dir: &Path) -> Result<(Vec<(String, MigrationConfig)>, Vec<LintIssue>)> {
+    let mut migrations = Vec::new();
+    let mut issues = Vec::new();
+
+    if !dir.exists() {
+        return Ok((migrations, issues));
+    }
+
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let file = path.display().to_string();
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read migration: {}", file))?;
+        match parse_migration(&content) {
+            Ok(config) => migrations.push((file, config)),
+            Err(e) => issues.push(LintIssue::error(&file, format!("{:#}", e))),
+        }
+    }
+
+    Ok((migrations, issues))
+}
+
+/// Check parsed migrations, individually and against each other. Transform
+/// paths are resolved against `project_dir`.
+fn lint_migrations(migrations: &[(String, MigrationConfig)], project_dir: &Path) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+
+    for (file, config) in migrations {
+        lint_migration(file, config, project_dir, &mut issues);
+    }
+
+    // Files with one mapping name are revisions of it, where only the latest
+    // runs, so only different mappings can write over each other
+    let mut by_namespace: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    let mut by_version: BTreeMap<i64, Vec<&str>> = BTreeMap::new();
+    for (file, config) in migrations {
+        let mappings = by_namespace.entry(&config.namespace).or_default();
+        if !mappings
+            .iter()
+            .any(|(_, name)| *name == config.mapping_name)
+        {
+            mappings.push((file, &config.mapping_name));
+        }
+        by_version.entry(config.version).or_default().push(file);
+    }
+
+    for (namespace, mappings) in &by_namespace {
+        let files: Vec<&str> = mappings.iter().map(|(file, _)| *file).collect();
+        for file in files.iter().skip(1) {
+            issues.push(LintIssue::warning(
+                file,
+                format!(
+                    "namespace '{}' is also written by {}; documents with the same id overwrite each other",
+                    namespace, files[0]
+                ),
+            ));
+        }
+    }
+    for (version, files) in &by_version {
+        for file in files.iter().skip(1) {
+            issues.push(LintIssue::error(
+                file,
+                format!("version {} is also used by {}", version, files[0]),
+            ));
+        }
+    }
+
+    let versions: Vec<i64> = by_version.keys().copied().collect();
+    let mut expected = 1;
+    for version in versions {
+        if version > expected {
+            let file = by_version[&version][0];
+            let missing = if version - expected == 1 {
+                format!("version {}", expected)
+            } else {
+                format!("versions {}-{}", expected, version - 1)
+            };
+            issues.push(LintIssue::warning(
+                file,
+                format!("version {} follows a gap: {} missing", version, missing),
+            ));
+        }
+        expected = version + 1;
+    }
+
+    issues
+}
+
+fn lint_migration(
+    file: &str,
+    config: &MigrationConfig,
+    project_dir: &Path,
+    issues: &mut Vec<LintIssue>,
+) {
+    if let Err(e) = validate_migration(config) {
+        issues.push(LintIssue::error(file, e.to_string()));
+    }
+
+    // Backfill selects only the listed columns unless a JS transform needs
+    // the whole row (an empty list selects every column)
+    let include = &config.columns.include;
+    let has_custom_transform =
+        config.transform.transform_type == TransformType::Js && config.transform.path.is_some();
+    let restricts_select = !include.is_empty() && !has_custom_transform;
+
+    for id_column in config.id.all_columns() {
+        if restricts_select && !include.contains(id_column) {
+            issues.push(LintIssue::error(
+                file,
+                format!(
+                    "id column '{}' is not in columns; backfilled rows won't have an id",
+                    id_column
+                ),
+            ));
+        }
+    }
+
+    if restricts_select && config.membership.mode == MembershipMode::Dsl {
+        if let Some(Ok(predicate)) = config.membership.predicate.as_deref().map(Predicate::parse) {
+            let missing: Vec<_> = predicate
+                .columns()
+                .into_iter()
+                .filter(|c| !include.iter().any(|i| i == *c))
+                .collect();
+            if !missing.is_empty() {
+                issues.push(LintIssue::error(
+                    file,
+                    format!(
+                        "membership predicate uses columns not in columns: {}",
+                        missing.join(", ")
+                    ),
+                ));
+            }
+        }
+    }
+
+    match (&config.transform.transform_type, &config.transform.path) {
+        (_, Some(path)) => {
+            if !project_dir.join(path.trim_start_matches("./")).exists() {
+                issues.push(LintIssue::error(
+                    file,
+                    format!("transform path '{}' does not exist", path),
+                ));
+            }
+        }
+        (TransformType::Js, None) => issues.push(LintIssue::error(
+            file,
+            "js transform has no path; rows would pass through untransformed",
+        )),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn migration(
+        version: i64,
+        name: &str,
+        namespace: &str,
+        extra: &str,
+    ) -> (String, MigrationConfig) {
+        let toml = format!(
+            r#"
+version = {version}
+mapping_name = "{name}"
+namespace = "{namespace}"
+
+[source]
+schema = "public"
+table = "{name}"
+
+[id]
+column = "id"
+type = "uint"
+{extra}
+"#
+        );
+        (
+            format!("migrations/{:04}_{}.toml", version, name),
+            MigrationConfig::parse(&toml).unwrap(),
+        )
+    }
+
+    fn messages(issues: &[LintIssue], severity: Severity) -> Vec<&str> {
+        issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .map(|i| i.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_migrations_have_no_issues() {
+        let migrations = vec![
+            migration(1, "users", "users", ""),
+            migration(2, "posts", "posts", r#"columns = ["id", "title"]"#),
+        ];
+        assert!(lint_migrations(&migrations, Path::new(".")).is_empty());
+    }
+
+    #[test]
+    fn test_duplicates_across_files() {
+        let migrations = vec![
+            migration(1, "users", "shared", ""),
+            migration(2, "accounts", "shared", ""),
+            migration(2, "posts", "posts", ""),
+        ];
+        let issues = lint_migrations(&migrations, Path::new("."));
+
+        let errors = messages(&issues, Severity::Error);
+        assert!(errors.iter().any(|m| m.contains("version 2 is also used")));
+        let warnings = messages(&issues, Severity::Warning);
+        assert!(warnings.iter().any(|m| m.contains("namespace 'shared'")));
+    }
+
+    #[test]
+    fn test_revisions_of_one_mapping() {
+        let migrations = vec![
+            migration(1, "users", "users", ""),
+            migration(2, "users", "users", r#"columns = ["id", "name"]"#),
+        ];
+        assert!(lint_migrations(&migrations, Path::new(".")).is_empty());
+    }
+
+    #[test]
+    fn test_version_gaps() {
+        let migrations = vec![
+            migration(2, "users", "users", ""),
+            migration(3, "posts", "posts", ""),
+            migration(6, "tags", "tags", ""),
+        ];
+        let issues = lint_migrations(&migrations, Path::new("."));
+        assert_eq!(
+            messages(&issues, Severity::Warning),
+            vec![
+                "version 2 follows a gap: version 1 missing",
+                "version 6 follows a gap: versions 4-5 missing",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_columns_must_cover_id_and_predicate() {
+        let migrations = vec![migration(
+            1,
+            "users",
+            "users",
+            r#"columns = ["email"]
+
+[membership]
+mode = "dsl"
+predicate = "status = 'active' AND deleted_at IS NULL"
+"#,
+        )];
+        let issues = lint_migrations(&migrations, Path::new("."));
+        let errors = messages(&issues, Severity::Error);
+        assert!(errors.iter().any(|m| m.contains("id column 'id'")));
+        assert!(errors
+            .iter()
+            .any(|m| m.ends_with("not in columns: deleted_at, status")));
+    }
+
+    #[test]
+    fn test_transform_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("transforms")).unwrap();
+        fs::write(temp_dir.path().join("transforms/users.ts"), "").unwrap();
+
+        let migrations = vec![
+            migration(
+                1,
+                "users",
+                "users",
+                "[transform]\ntype = \"js\"\npath = \"./transforms/users.ts\"",
+            ),
+            migration(
+                2,
+                "posts",
+                "posts",
+                "[transform]\ntype = \"js\"\npath = \"./transforms/posts.ts\"",
+            ),
+            migration(3, "tags", "tags", "[transform]\ntype = \"js\""),
+        ];
+        let issues = lint_migrations(&migrations, temp_dir.path());
+        assert_eq!(
+            messages(&issues, Severity::Error),
+            vec![
+                "transform path './transforms/posts.ts' does not exist",
+                "js transform has no path; rows would pass through untransformed",
+            ]
+        );
+    }
+}
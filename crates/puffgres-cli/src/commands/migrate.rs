@@ -1,18 +1,35 @@
 use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::Path;
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use colored::Colorize;
-use puffgres_pg::{MigrationTracker, PostgresStateStore};
-use tracing::info;
+use dialoguer::Confirm;
+use puffgres_core::shadow_name;
+use puffgres_pg::{
+    notify_reload, AppliedMigration, MigrationRevision, MigrationTracker, PostgresStateStore,
+};
+use tracing::{info, warn};
 
-use crate::config::ProjectConfig;
+use super::dangerous::find_namespaces;
+use super::setup::{provision_publication, IdentityFix};
+use crate::backfill::{self, BackfillLimits, TerminalProgress};
+use crate::config::{parse_migration, ProjectConfig};
+use crate::env::{self, get_max_retries};
+use crate::strict::StrictMode;
+use crate::tp::TurbopufferClient;
 use crate::validation::{
     store_transform, validate_id_column_type, validate_no_console_log_in_transforms,
     validate_no_unreferenced_transforms, validate_transforms,
 };
 
-pub async fn cmd_migrate(config: ProjectConfig, dry_run: bool) -> Result<()> {
+pub async fn cmd_migrate(
+    config: ProjectConfig,
+    dry_run: bool,
+    publication: &str,
+    alter_publication: bool,
+) -> Result<()> {
     info!("Checking migrations");
 
     // Connect to Postgres state store
@@ -41,13 +58,17 @@ pub async fn cmd_migrate(config: ProjectConfig, dry_run: bool) -> Result<()> {
 
     // Validate that all referenced tables exist before proceeding
     for migration in &local {
-        let migration_config = puffgres_config::MigrationConfig::parse(&migration.content)
+        let migration_config = parse_migration(&migration.content)
             .with_context(|| {
                 format!(
                     "Failed to parse migration v{} '{}'",
                     migration.version, migration.mapping_name
                 )
             })?;
+        // A query's columns are only known once it runs
+        if migration_config.source.query.is_some() {
+            continue;
+        }
 
         let schema = &migration_config.source.schema;
         let table = &migration_config.source.table;
@@ -68,7 +89,11 @@ pub async fn cmd_migrate(config: ProjectConfig, dry_run: bool) -> Result<()> {
             std::process::exit(1);
         }
 
-        // Validate ID column type matches the data
+        // Validate ID column type matches the data. A composite ID is built
+        // from its columns' text, so their types aren't checked
+        if migration_config.id.is_composite() {
+            continue;
+        }
         if let Err(e) = validate_id_column_type(
             &store,
             schema,
@@ -135,6 +160,19 @@ pub async fn cmd_migrate(config: ProjectConfig, dry_run: bool) -> Result<()> {
     for name in &status.pending {
         println!("  → {}", name.yellow());
     }
+    for revision in &status.revisions {
+        if revision.shadow {
+            println!(
+                "  v{} {} is built beside applied v{} until promoted",
+                revision.version, revision.mapping_name, revision.replaces
+            );
+        } else {
+            println!(
+                "  v{} {} supersedes applied v{}",
+                revision.version, revision.mapping_name, revision.replaces
+            );
+        }
+    }
 
     if dry_run {
         println!("\n(dry run - no changes made)");
@@ -156,7 +194,7 @@ pub async fn cmd_migrate(config: ProjectConfig, dry_run: bool) -> Result<()> {
             .await?;
 
         // Check if this migration has a transform with a path
-        let migration_config = puffgres_config::MigrationConfig::parse(&migration.content)?;
+        let migration_config = parse_migration(&migration.content)?;
         if let Some(path) = &migration_config.transform.path {
             let transform_path = Path::new(path.trim_start_matches("./"));
             if transform_path.exists() {
@@ -176,5 +214,256 @@ pub async fn cmd_migrate(config: ProjectConfig, dry_run: bool) -> Result<()> {
         "\n{}",
         format!("Applied {} migration(s).", applied.len()).green()
     );
+
+    // Make sure newly mapped tables are replicated
+    provision_publication(&config, publication, alter_publication, IdentityFix::Report).await?;
+
+    // Running runners pick the new mappings up without a restart
+    match notify_reload(store.client()).await {
+        Ok(()) => println!("Asked running runners to reload their migrations."),
+        Err(e) => warn!(
+            error = %e,
+            "Failed to notify running runners; restart them to pick up the new migrations"
+        ),
+    }
+
+    if !status.revisions.is_empty() {
+        rebackfill_revisions(&config, store, &status.revisions).await?;
+    }
+
+    Ok(())
+}
+
+/// Offer to backfill each revised mapping, whose existing documents still
+/// follow the migration it superseded. A shadow revision starts out with an
+/// empty namespace instead, which is filled before it's promoted.
+async fn rebackfill_revisions(
+    config: &ProjectConfig,
+    store: PostgresStateStore,
+    revisions: &[MigrationRevision],
+) -> Result<()> {
+    let mappings = config.load_active_migrations(&store).await?;
+    let store = Arc::new(store);
+    let strict = StrictMode::resolve(None, env::get_strict().as_deref())?;
+    let limits = BackfillLimits::default();
+
+    for revision in revisions {
+        let name = if revision.shadow {
+            shadow_name(&revision.mapping_name, revision.version as u32)
+        } else {
+            revision.mapping_name.clone()
+        };
+        let Some(mapping) = mappings.iter().find(|m| m.name == name) else {
+            continue;
+        };
+        if revision.shadow {
+            println!(
+                "
+{} v{} {} is built beside v{} in '{}', which stays empty until it is backfilled. \
+                 Then `puffgres promote {}` makes it current.",
+                "!".yellow(),
+                revision.version,
+                revision.mapping_name,
+                revision.replaces,
+                mapping.namespace,
+                revision.mapping_name
+            );
+        } else {
+            println!(
+                "
+{} v{} {} supersedes v{}; documents already in '{}' keep the old shape until it is backfilled.",
+                "!".yellow(),
+                revision.version,
+                revision.mapping_name,
+                revision.replaces,
+                mapping.namespace
+            );
+        }
+        let prompt = if revision.shadow {
+            format!("Backfill '{}' now?", mapping.name)
+        } else {
+            format!("Re-backfill '{}' now?", mapping.name)
+        };
+        if !io::stdin().is_terminal()
+            || !Confirm::new()
+                .with_prompt(prompt)
+                .default(true)
+                .interact()?
+        {
+            let then = if revision.shadow {
+                "fill it"
+            } else {
+                "rewrite them"
+            };
+            println!("Run `puffgres backfill {}` to {}.", mapping.name, then);
+            continue;
+        }
+
+        let progress = TerminalProgress::start(limits);
+        backfill::run_backfill(
+            config,
+            store.clone(),
+            mapping,
+            1000,
+            false,
+            None,
+            1,
+            &limits,
+            &strict,
+            None,
+            &progress,
+        )
+        .await?;
+    }
     Ok(())
 }
+
+/// Roll back the latest applied migration, or every one after version `to`.
+///
+/// The migration record, its stored content and transform, and the mapping's
+/// checkpoint and backfill progress are removed. The turbopuffer namespace is
+/// kept unless `delete_namespace` is set.
+pub async fn cmd_migrate_down(
+    config: ProjectConfig,
+    to: Option<i32>,
+    dry_run: bool,
+    delete_namespace: bool,
+    yes: bool,
+) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+
+    let applied = store.get_applied_migrations().await?;
+    let Some(latest) = applied.iter().map(|m| m.version).max() else {
+        println!("No migrations have been applied.");
+        return Ok(());
+    };
+    let to = to.unwrap_or(latest - 1);
+    // Newest first
+    let rollback: Vec<&AppliedMigration> =
+        applied.iter().rev().filter(|m| m.version > to).collect();
+    if rollback.is_empty() {
+        println!("No migrations applied after v{}.", to);
+        return Ok(());
+    }
+
+    let client = rs_puff::Client::new(config.turbopuffer_api_key()?);
+    let mut plan = Vec::new();
+    println!("Rolling back:");
+    for migration in rollback {
+        println!(
+            "  ← v{} {}",
+            migration.version,
+            migration.mapping_name.yellow()
+        );
+        let namespaces = if delete_namespace {
+            migration_namespaces(&config, &store, &client, migration).await?
+        } else {
+            vec![]
+        };
+        for namespace in &namespaces {
+            println!("      delete namespace {}", namespace);
+        }
+        plan.push((migration, namespaces));
+    }
+    println!("\nTheir checkpoints and backfill progress are cleared.");
+    if !delete_namespace {
+        println!("Their turbopuffer namespaces are kept (--delete-namespace to delete them).");
+    }
+
+    if dry_run {
+        println!("\n(dry run - no changes made)");
+        return Ok(());
+    }
+    if !yes
+        && !Confirm::new()
+            .with_prompt(format!("Roll back {} migration(s)?", plan.len()))
+            .default(false)
+            .interact()?
+    {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let tp = TurbopufferClient::new(config.turbopuffer_api_key()?, get_max_retries());
+    for (migration, namespaces) in &plan {
+        for namespace in namespaces {
+            tp.clear(namespace).await?;
+            println!("  ✓ Deleted namespace {}", namespace);
+        }
+        store
+            .remove_migration(migration.version, &migration.mapping_name)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to roll back v{} '{}'",
+                    migration.version, migration.mapping_name
+                )
+            })?;
+        println!(
+            "  ✓ Rolled back v{} {}",
+            migration.version, migration.mapping_name
+        );
+    }
+
+    // A migration file left in place is pending again
+    let local = config.load_local_migrations()?;
+    let pending: Vec<_> = local
+        .iter()
+        .filter(|l| {
+            plan.iter()
+                .any(|(m, _)| m.version == l.version && m.mapping_name == l.mapping_name)
+        })
+        .collect();
+    if !pending.is_empty() {
+        println!(
+            "\n{} Still in migrations/, so the next `puffgres migrate` or `puffgres run` applies them again:",
+            "!".yellow()
+        );
+        for migration in pending {
+            println!("  v{} {}", migration.version, migration.mapping_name);
+        }
+        println!("Delete or revise them to keep them rolled back.");
+    }
+
+    println!(
+        "\n{}",
+        format!("Rolled back {} migration(s).", plan.len()).green()
+    );
+    Ok(())
+}
+
+/// Namespaces an applied migration writes to, read from its stored content
+/// or else the local file.
+async fn migration_namespaces(
+    config: &ProjectConfig,
+    store: &PostgresStateStore,
+    client: &rs_puff::Client,
+    migration: &AppliedMigration,
+) -> Result<Vec<String>> {
+    let content = match store
+        .get_migration_content(migration.version, &migration.mapping_name)
+        .await?
+    {
+        Some(content) => content,
+        None => match config
+            .load_local_migrations()?
+            .into_iter()
+            .find(|l| l.version == migration.version && l.mapping_name == migration.mapping_name)
+        {
+            Some(local) => local.content,
+            None => anyhow::bail!(
+                "Can't find the content of v{} '{}' to look up its namespace; roll back without --delete-namespace",
+                migration.version,
+                migration.mapping_name
+            ),
+        },
+    };
+    let mut mapping = puffgres_config::to_mapping(&parse_migration(&content)?)?;
+    mapping.namespace = config.apply_namespace_prefix(&mapping.namespace);
+    Ok(find_namespaces(client, &[mapping])
+        .await?
+        .into_iter()
+        .collect())
+}
@@ -1,17 +1,45 @@
+mod backfill_all;
+mod check;
 mod dangerous;
+mod doctor;
 mod init;
+mod lint;
 mod migrate;
 mod new;
+mod pause;
+mod promote;
+mod reconcile;
+mod refresh;
+mod replay;
 mod reset;
 mod run;
+mod serve;
 mod setup;
+mod state;
 mod status;
+mod tail;
+mod test_transform;
+mod verify;
 
+pub use backfill_all::cmd_backfill_all;
+pub use check::cmd_check;
 pub use dangerous::{cmd_dangerously_delete_config, cmd_dangerously_reset_turbopuffer};
+pub use doctor::cmd_doctor;
 pub use init::cmd_init;
-pub use migrate::cmd_migrate;
+pub use lint::cmd_lint;
+pub use migrate::{cmd_migrate, cmd_migrate_down};
 pub use new::cmd_new;
+pub use pause::cmd_pause;
+pub use promote::cmd_promote;
+pub use reconcile::cmd_reconcile;
+pub use refresh::cmd_refresh;
+pub use replay::cmd_replay;
 pub use reset::cmd_reset;
 pub use run::cmd_run;
-pub use setup::cmd_setup;
+pub use serve::cmd_serve;
+pub use setup::{cmd_setup, IdentityFix};
+pub use state::cmd_state;
 pub use status::cmd_status;
+pub use tail::cmd_tail;
+pub use test_transform::cmd_test_transform;
+pub use verify::verify_backfill;
@@ -1,23 +1,237 @@
 use std::fs;
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
-use dialoguer::{Confirm, Input};
+use dialoguer::{Confirm, Input, MultiSelect, Select};
+use puffgres_config::IdTypeConfig;
+use puffgres_core::Predicate;
+use puffgres_pg::PostgresStateStore;
+
+use crate::config::ProjectConfig;
+use crate::validation::infer_id_type;
+
+/// The source table a migration is scaffolded for.
+struct SourceTable {
+    schema: String,
+    table: String,
+    /// The primary key. Several columns make a composite id.
+    id_columns: Vec<String>,
+    id_type: IdTypeConfig,
+    columns: Vec<String>,
+    /// Membership predicate, if only some rows are synced.
+    predicate: Option<String>,
+}
+
+impl SourceTable {
+    /// The placeholder table for a migration named `name`.
+    fn placeholder(name: &str) -> Self {
+        Self {
+            schema: "public".to_string(),
+            table: name.to_string(),
+            id_columns: vec!["id".to_string()],
+            id_type: IdTypeConfig::Uint,
+            columns: vec!["id".into(), "name".into(), "created_at".into()],
+            predicate: None,
+        }
+    }
+
+    /// Read a table's columns and primary key, and infer its id type from
+    /// a sample of keys.
+    async fn introspect(config: &ProjectConfig, qualified: &str) -> Result<Self> {
+        let (schema, table) = parse_table_name(qualified);
+        let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+            .await
+            .context("Failed to connect to Postgres")?;
+
+        let columns = store.table_columns(&schema, &table).await?;
+        if columns.is_empty() {
+            anyhow::bail!("Table '{}.{}' does not exist", schema, table);
+        }
+        let id_columns = store.primary_key_columns(&schema, &table).await?;
+        let id_type = match id_columns.as_slice() {
+            [] => anyhow::bail!(
+                "Table '{}.{}' has no primary key; puffgres needs one to build document ids from",
+                schema,
+                table
+            ),
+            [id_column] => {
+                let sample = store
+                    .sample_id_column(&schema, &table, id_column, 5)
+                    .await
+                    .context("Failed to sample ID column")?;
+                infer_id_type(&sample)
+            }
+            // Composite keys are joined into string ids
+            _ => IdTypeConfig::String,
+        };
+
+        Ok(Self {
+            schema,
+            table,
+            id_columns,
+            id_type,
+            columns,
+            predicate: None,
+        })
+    }
+
+    /// Ask which table to sync, which of its columns, what to build the id
+    /// from (the primary key by default) and, optionally, which rows.
+    async fn choose(config: &ProjectConfig) -> Result<Self> {
+        let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+            .await
+            .context("Failed to connect to Postgres")?;
 
-pub async fn cmd_new(name: Option<String>) -> Result<()> {
+        let tables = store.list_tables().await?;
+        if tables.is_empty() {
+            anyhow::bail!("No tables found in the database");
+        }
+        let labels: Vec<String> = tables
+            .iter()
+            .map(|(schema, table)| format!("{}.{}", schema, table))
+            .collect();
+        let picked = Select::new()
+            .with_prompt("Which table should this migration sync?")
+            .items(&labels)
+            .default(0)
+            .interact()?;
+        let (schema, table) = tables[picked].clone();
+
+        let all_columns = store.table_columns(&schema, &table).await?;
+        let primary_key = store.primary_key_columns(&schema, &table).await?;
+        let picked = MultiSelect::new()
+            .with_prompt("Columns to sync (space to toggle, enter to confirm)")
+            .items(&all_columns)
+            .defaults(&vec![true; all_columns.len()])
+            .interact()?;
+        let mut columns: Vec<String> = picked.into_iter().map(|i| all_columns[i].clone()).collect();
+
+        // A composite primary key is offered as one choice
+        let mut choices: Vec<Vec<String>> = Vec::new();
+        if primary_key.len() > 1 {
+            choices.push(primary_key.clone());
+        }
+        choices.extend(all_columns.iter().map(|c| vec![c.clone()]));
+        let labels: Vec<String> = choices
+            .iter()
+            .map(|c| {
+                if *c == primary_key {
+                    format!("{} (primary key)", c.join(", "))
+                } else {
+                    c.join(", ")
+                }
+            })
+            .collect();
+        let picked = Select::new()
+            .with_prompt("Which column is the document id?")
+            .items(&labels)
+            .default(choices.iter().position(|c| *c == primary_key).unwrap_or(0))
+            .interact()?;
+        let id_columns = choices.swap_remove(picked);
+        for column in id_columns.iter().rev() {
+            if !columns.contains(column) {
+                columns.insert(0, column.clone());
+            }
+        }
+
+        let id_type = match id_columns.as_slice() {
+            [id_column] => {
+                let sample = store
+                    .sample_id_column(&schema, &table, id_column, 5)
+                    .await
+                    .context("Failed to sample ID column")?;
+                infer_id_type(&sample)
+            }
+            _ => IdTypeConfig::String,
+        };
+
+        let filter = Confirm::new()
+            .with_prompt("Only sync rows matching a predicate?")
+            .default(false)
+            .interact()?;
+        let predicate = if filter {
+            let predicate: String = Input::new()
+                .with_prompt("Predicate (e.g. status = 'active' AND deleted_at IS NULL)")
+                .validate_with(|input: &String| {
+                    Predicate::parse(input)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                })
+                .interact_text()?;
+            Some(predicate)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            schema,
+            table,
+            id_columns,
+            id_type,
+            columns,
+            predicate,
+        })
+    }
+
+    /// The body of the migration's `[id]` table.
+    fn id_toml(&self) -> String {
+        let id_type = format!("{:?}", self.id_type).to_lowercase();
+        match self.id_columns.as_slice() {
+            [column] => format!("column = \"{}\"\ntype = \"{}\"", column, id_type),
+            columns => format!("columns = {}\ntype = \"{}\"", toml_array(columns), id_type),
+        }
+    }
+
+    /// The migration's `[membership]` table, or a commented-out example.
+    fn membership_toml(&self) -> String {
+        match &self.predicate {
+            Some(predicate) => format!(
+                "# Only sync rows matching the predicate\n[membership]\nmode = \"dsl\"\npredicate = {}",
+                toml::Value::String(predicate.clone())
+            ),
+            None => "# Optional: filter which rows to sync\n# [membership]\n# mode = \"dsl\"\n# predicate = \"status = 'active'\"".to_string(),
+        }
+    }
+}
+
+/// Split `schema.table` into its parts, defaulting to the public schema.
+fn parse_table_name(qualified: &str) -> (String, String) {
+    match qualified.split_once('.') {
+        Some((schema, table)) => (schema.to_string(), table.to_string()),
+        None => ("public".to_string(), qualified.to_string()),
+    }
+}
+
+/// Render a list of names as a TOML array.
+fn toml_array(items: &[String]) -> String {
+    toml::Value::Array(items.iter().cloned().map(toml::Value::String).collect()).to_string()
+}
+
+pub async fn cmd_new(
+    config: &ProjectConfig,
+    name: Option<String>,
+    from_table: Option<&str>,
+    interactive: bool,
+) -> Result<()> {
     // Check that puffgres is initialized (validated by main.rs, but double-check)
     if !Path::new("migrations").exists() {
         anyhow::bail!("Not in a puffgres project directory. Run 'puffgres init' first.");
     }
 
-    // Get the migration name
-    let migration_name = if let Some(name) = name {
-        name
-    } else {
-        Input::new()
+    let source = match from_table {
+        _ if interactive => Some(SourceTable::choose(config).await?),
+        Some(qualified) => Some(SourceTable::introspect(config, qualified).await?),
+        None => None,
+    };
+
+    // Get the migration name, defaulting to the table's when scaffolding from one
+    let migration_name = match (name, &source) {
+        (Some(name), _) => name,
+        (None, Some(source)) => source.table.clone(),
+        (None, None) => Input::new()
             .with_prompt("What would you like to name this migration?")
-            .interact_text()?
+            .interact_text()?,
     };
 
     // Ask if they want a custom transform
@@ -51,27 +265,50 @@ pub async fn cmd_new(name: Option<String>) -> Result<()> {
     }
     let next_version = max_version + 1;
 
+    let source = source.unwrap_or_else(|| SourceTable::placeholder(&safe_name));
+    let mapping_name = format!("{}_{}", safe_name, source.schema);
+
     // Create the migration file based on transform choice
     let migration = if use_custom_transform {
         format!(
             r#"# Migration for {name} table
 version = {version}
-mapping_name = "{name}_public"
+mapping_name = "{mapping_name}"
 namespace = "{name}"
 
+# Optional: store each row's source commit time in __event_time
+# event_time = true
+
+# Optional: store the id of the run that wrote each document in __run_id
+# run_id = true
+
 [source]
-schema = "public"
-table = "{name}"
+schema = "{schema}"
+table = "{table}"
+# What a TRUNCATE does to the namespace: "ignore" (default), "clear" or "error"
+# on_truncate = "clear"
 
 [id]
-column = "id"
-type = "uint"
+{id}
+
+{membership}
 
-# Optional: filter which rows to sync
-# [membership]
-# mode = "dsl"
-# predicate = "status = 'active'"
+# Optional: what to do with rows that fail to transform ("skip", "dlq" or "halt")
+# [errors]
+# on_transform_error = "dlq"
+# max_consecutive_errors = 100
 
+# Optional: limit document size ("truncate", "dlq" or "fail" when over)
+# [limits]
+# max_document_bytes = 65536
+# on_oversize = "dlq"
+
+# Optional: batch limits; max_wait_ms holds batches across transactions
+# [batch]
+# max_rows = 500
+# max_wait_ms = 2000
+
+# Set mode = "content_hash" to skip updates that leave the document unchanged
 [versioning]
 mode = "source_lsn"
 
@@ -80,40 +317,100 @@ type = "js"
 path = "./transforms/{name}.ts"
 "#,
             name = safe_name,
-            version = next_version
+            version = next_version,
+            mapping_name = mapping_name,
+            schema = source.schema,
+            table = source.table,
+            id = source.id_toml(),
+            membership = source.membership_toml(),
         )
     } else {
         format!(
             r#"# Migration for {name} table
 version = {version}
-mapping_name = "{name}_public"
+mapping_name = "{mapping_name}"
 namespace = "{name}"
 
+# Optional: store each row's source commit time in __event_time
+# event_time = true
+
+# Optional: store the id of the run that wrote each document in __run_id
+# run_id = true
+
 # Columns to sync to turbopuffer
-columns = ["id", "name", "created_at"]
+columns = {columns}
 
 [source]
-schema = "public"
-table = "{name}"
+schema = "{schema}"
+table = "{table}"
+# What a TRUNCATE does to the namespace: "ignore" (default), "clear" or "error"
+# on_truncate = "clear"
 
 [id]
-column = "id"
-type = "uint"
+{id}
 
-# Optional: filter which rows to sync
-# [membership]
-# mode = "dsl"
-# predicate = "status = 'active'"
+{membership}
 
+# Optional: what to do with rows that fail to transform ("skip", "dlq" or "halt")
+# [errors]
+# on_transform_error = "dlq"
+# max_consecutive_errors = 100
+
+# Optional: limit document size ("truncate", "dlq" or "fail" when over)
+# [limits]
+# max_document_bytes = 65536
+# on_oversize = "dlq"
+
+# Optional: batch limits; max_wait_ms holds batches across transactions
+# [batch]
+# max_rows = 500
+# max_wait_ms = 2000
+
+# Optional: force how columns are written ("string", "int", "float", "bool" or "unix_ms")
+# [types]
+# created_at = "unix_ms"
+
+# Optional: attributes computed from the row
+# [computed]
+# full_name = "concat(first_name, ' ', last_name)"
+
+# Optional: write a pgvector column as the document vector
+# [vector]
+# column = "embedding"
+# distance_metric = "cosine_distance"
+
+# Optional: generate the vector from attributes with [providers.embeddings]
+# [embed]
+# columns = ["title", "body"]
+
+# Set mode = "content_hash" to skip updates that leave the document unchanged
 [versioning]
 mode = "source_lsn"
 "#,
             name = safe_name,
-            version = next_version
+            version = next_version,
+            mapping_name = mapping_name,
+            schema = source.schema,
+            table = source.table,
+            id = source.id_toml(),
+            membership = source.membership_toml(),
+            columns = toml_array(&source.columns),
         )
     };
 
     let migration_path = format!("migrations/{:04}_{}.toml", next_version, safe_name);
+    if interactive {
+        println!("\n{}\n", migration_path.bold());
+        println!("{}", migration);
+        if !Confirm::new()
+            .with_prompt(format!("Write {}?", migration_path))
+            .default(true)
+            .interact()?
+        {
+            println!("Nothing written.");
+            return Ok(());
+        }
+    }
     fs::write(&migration_path, &migration)?;
     println!("{}", format!("Created {}", migration_path).green());
 
@@ -205,8 +502,8 @@ export default async function transform(
       doc: {{
         id: r.row.id,
         // Add your fields here
-        vector: embeddings[i],
       }},
+      vector: embeddings[i],
       distance_metric: 'cosine_distance' as const,
     }},
   }}));
@@ -234,13 +531,63 @@ export default async function transform(
             safe_name
         );
         println!("  3. Run: puffgres migrate");
-        println!("  4. Run: puffgres backfill {}_public\n", safe_name);
+        println!("  4. Run: puffgres backfill {}\n", mapping_name);
     } else {
         println!("\nNext steps:");
         println!("  1. Edit {} to match your table schema", migration_path);
         println!("  2. Run: puffgres migrate");
-        println!("  3. Run: puffgres backfill {}_public\n", safe_name);
+        println!("  3. Run: puffgres backfill {}\n", mapping_name);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_table_name() {
+        assert_eq!(
+            parse_table_name("app.users"),
+            ("app".to_string(), "users".to_string())
+        );
+        assert_eq!(
+            parse_table_name("users"),
+            ("public".to_string(), "users".to_string())
+        );
+    }
+
+    #[test]
+    fn test_toml_array() {
+        let columns = vec!["id".to_string(), "full name".to_string()];
+        assert_eq!(toml_array(&columns), r#"["id", "full name"]"#);
+    }
+
+    #[test]
+    fn test_membership_toml() {
+        let mut source = SourceTable::placeholder("orders");
+        assert!(source.membership_toml().starts_with("# Optional"));
+
+        source.predicate = Some("status = 'paid'".into());
+        let toml = source.membership_toml();
+        let parsed: toml::Table = toml.parse().unwrap();
+        assert_eq!(
+            parsed["membership"]["predicate"].as_str(),
+            Some("status = 'paid'")
+        );
+    }
+
+    #[test]
+    fn test_id_toml() {
+        let mut source = SourceTable::placeholder("orders");
+        assert_eq!(source.id_toml(), "column = \"id\"\ntype = \"uint\"");
+
+        source.id_columns = vec!["tenant_id".into(), "order_id".into()];
+        source.id_type = IdTypeConfig::String;
+        assert_eq!(
+            source.id_toml(),
+            "columns = [\"tenant_id\", \"order_id\"]\ntype = \"string\""
+        );
+    }
+}
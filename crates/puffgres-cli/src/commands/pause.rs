@@ -0,0 +1,41 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_pg::PostgresStateStore;
+
+use crate::config::ProjectConfig;
+
+/// Pause or resume a mapping in the state table. A running runner picks the
+/// change up on its own; nothing is restarted.
+pub async fn cmd_pause(config: ProjectConfig, mapping_name: &str, paused: bool) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    let mappings = config.load_active_migrations(&store).await?;
+    if !mappings.iter().any(|m| m.name == mapping_name) {
+        bail!("Mapping '{}' not found", mapping_name);
+    }
+    let was_paused = store
+        .get_paused_mappings()
+        .await?
+        .iter()
+        .any(|name| name == mapping_name);
+    store.set_paused(mapping_name, paused).await?;
+
+    match (paused, was_paused) {
+        (true, true) => println!("Mapping '{}' is already paused.", mapping_name),
+        (true, false) => {
+            println!("{}", format!("Paused mapping '{}'.", mapping_name).green());
+            println!(
+                "The runner skips its changes without acknowledging them, so the slot keeps them \
+                 (and the WAL behind them) until `puffgres resume {}`.",
+                mapping_name
+            );
+        }
+        (false, false) => println!("Mapping '{}' isn't paused.", mapping_name),
+        (false, true) => {
+            println!("{}", format!("Resumed mapping '{}'.", mapping_name).green());
+            println!("The runner replays the changes it skipped while the mapping was paused.");
+        }
+    }
+    Ok(())
+}
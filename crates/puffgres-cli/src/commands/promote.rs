@@ -0,0 +1,100 @@
+//! `puffgres promote`: make a shadow revision of a mapping current.
+//!
+//! A migration with `shadow = true` is built in its own namespace,
+//! `{namespace}__v{version}`, while the version before it keeps serving.
+//! Promoting it supersedes that version and hands the shadow's checkpoint and
+//! backfill progress to the mapping, which from then on writes only the
+//! shadow namespace. turbopuffer namespaces can't be renamed, so readers move
+//! to the shadow namespace and the old one is left for them to delete.
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_core::shadow_name;
+use puffgres_pg::PostgresStateStore;
+
+use crate::config::ProjectConfig;
+
+pub async fn cmd_promote(config: ProjectConfig, mapping_name: &str, force: bool) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+
+    let Some(revision) = config
+        .load_local_migrations()?
+        .into_iter()
+        .filter(|m| m.mapping_name == mapping_name)
+        .max_by_key(|m| m.version)
+    else {
+        bail!("Mapping '{}' not found", mapping_name);
+    };
+    if !revision.shadow {
+        bail!(
+            "v{} of '{}' isn't a shadow revision. Only a migration with `shadow = true` is promoted.",
+            revision.version,
+            mapping_name
+        );
+    }
+
+    let applied = store.get_applied_migrations().await?;
+    if !applied
+        .iter()
+        .any(|a| a.mapping_name == mapping_name && a.version == revision.version)
+    {
+        bail!(
+            "v{} of '{}' hasn't been applied. Run `puffgres migrate` first.",
+            revision.version,
+            mapping_name
+        );
+    }
+    let replaces_serving = applied.iter().any(|a| {
+        a.mapping_name == mapping_name && a.version < revision.version && a.superseded_by.is_none()
+    });
+    if !replaces_serving {
+        println!("v{} of '{}' is already current.", revision.version, mapping_name);
+        return Ok(());
+    }
+
+    // Until it's backfilled, the shadow namespace only has the rows changed
+    // since it was applied
+    let shadow = shadow_name(mapping_name, revision.version as u32);
+    let status = store
+        .get_backfill_progress(&shadow)
+        .await?
+        .map(|p| p.status)
+        .unwrap_or_else(|| "not started".to_string());
+    if status != "completed" && !force {
+        bail!(
+            "The backfill of '{}' is {}. Run `puffgres backfill {}` first, or pass --force to promote it anyway.",
+            shadow,
+            status,
+            shadow
+        );
+    }
+
+    let mappings = config.load_active_migrations(&store).await?;
+    let namespace = |name: &str| {
+        mappings
+            .iter()
+            .find(|m| m.name == name)
+            .map(|m| m.namespace.clone())
+            .unwrap_or_default()
+    };
+    let (old_namespace, new_namespace) = (namespace(mapping_name), namespace(&shadow));
+
+    store.promote_migration(mapping_name, revision.version).await?;
+
+    println!(
+        "{}",
+        format!(
+            "Promoted v{} of '{}': it now writes to '{}'.",
+            revision.version, mapping_name, new_namespace
+        )
+        .green()
+    );
+    println!(
+        "'{}' is no longer written. Point readers at '{}', and delete the old namespace once nothing reads it.",
+        old_namespace, new_namespace
+    );
+    println!("Restart `puffgres run` to pick up the change.");
+    Ok(())
+}
@@ -0,0 +1,343 @@
+//! `puffgres reconcile`: repair a mapping's namespace from its table.
+//!
+//! `puffgres check` samples; reconcile goes through everything. It scans
+//! every row of the mapping, works out the document each should have, and
+//! diffs that against an export of the namespace. Documents that are missing
+//! or stale are written again, and documents no row accounts for are deleted.
+//! Exports are held in memory, so a namespace has to fit.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use tracing::warn;
+
+use puffgres_core::{
+    extract_id, Action, Batch, BatchConfig, Batcher, DocumentValidator, Mapping, RowEvent,
+    VersioningMode, WriteRequest,
+};
+use puffgres_pg::{BackfillConfig, BackfillScanner, IdRange, PostgresStateStore};
+
+use crate::backfill::{
+    get_backfill_columns, get_backfill_filter, get_backfill_text_columns, is_member,
+};
+use crate::config::ProjectConfig;
+use crate::diff::{DocumentDiff, NamespaceDiff};
+use crate::embeddings::{embed_request, EmbeddingClient};
+use crate::env::{
+    get_backfill_copy, get_max_retries, get_transform_batch_size, get_upload_batch_size,
+};
+use crate::runner::{
+    convert_doc_id_to_json, create_transformer, write_request, MappingTransformer,
+};
+use crate::tp::TurbopufferClient;
+use crate::validation::validate_transforms;
+
+pub async fn cmd_reconcile(
+    config: ProjectConfig,
+    mapping_name: &str,
+    batch_size: u32,
+    dry_run: bool,
+) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    validate_transforms(&config, &store).await?;
+    let mappings = config.load_active_migrations(&store).await?;
+    drop(store);
+    let mapping = mappings
+        .iter()
+        .find(|m| m.name == mapping_name)
+        .with_context(|| format!("Mapping '{}' not found", mapping_name))?;
+
+    let mut scanner = BackfillScanner::new(BackfillConfig {
+        connection_string: config.postgres_connection_string()?,
+        schema: mapping.source.schema.clone(),
+        table: mapping.source.table.clone(),
+        id_columns: mapping.id.columns().to_vec(),
+        columns: get_backfill_columns(mapping),
+        batch_size,
+        filter: get_backfill_filter(mapping),
+        snapshot: None,
+        text_columns: get_backfill_text_columns(mapping),
+        query: mapping.source.query.clone(),
+        range: IdRange::default(),
+        copy: get_backfill_copy(),
+    })
+    .await
+    .context("Failed to create backfill scanner")?;
+
+    let max_retries = get_max_retries();
+    let mut reconciler = Reconciler {
+        mapping,
+        transformer: create_transformer(mapping),
+        client: TurbopufferClient::new(config.turbopuffer_api_key()?, max_retries),
+        embedder: EmbeddingClient::for_mappings(
+            &config,
+            std::slice::from_ref(mapping),
+            max_retries,
+        )?,
+        upload_batch_size: get_upload_batch_size(),
+        validator: DocumentValidator::new(),
+        batcher: Batcher::new(
+            mapping
+                .batch
+                .apply(BatchConfig::with_max_rows(get_transform_batch_size())),
+        ),
+        namespaces: BTreeMap::new(),
+        dry_run,
+        outcome: ReconcileOutcome::default(),
+    };
+
+    println!(
+        "Reconciling '{}' against {}...",
+        mapping.name,
+        mapping.source.describe()
+    );
+    // The namespace of an unpartitioned mapping is diffed even when it has
+    // no rows
+    if !mapping.is_partitioned() {
+        reconciler.export(&mapping.namespace).await?;
+    }
+    loop {
+        let events = scanner.next_batch().await?;
+        if events.is_empty() {
+            break;
+        }
+        reconciler.reconcile_batch(&events).await?;
+    }
+    let outcome = reconciler.finish().await?;
+
+    let verb = if dry_run { "would be" } else { "were" };
+    println!(
+        "\n{} row(s) scanned: {} document(s) up to date.",
+        outcome.rows, outcome.unchanged
+    );
+    println!(
+        "{} missing and {} stale document(s) {} written, {} orphan(s) {} deleted.",
+        outcome.missing, outcome.stale, verb, outcome.orphans, verb
+    );
+    if outcome.failed > 0 {
+        println!(
+            "{} {} row(s) failed to transform or were rejected and were left as they are; see the warnings above.",
+            "!".yellow(),
+            outcome.failed
+        );
+    }
+    if mapping.is_partitioned() {
+        println!(
+            "{} Namespaces without any rows aren't exported, so their documents aren't deleted.",
+            "!".yellow()
+        );
+    }
+    if !dry_run {
+        println!("{}", format!("Reconciled '{}'.", mapping.name).green());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct ReconcileOutcome {
+    rows: usize,
+    unchanged: usize,
+    missing: usize,
+    stale: usize,
+    orphans: usize,
+    failed: usize,
+}
+
+/// Diffs a mapping's rows against its namespaces and writes the repairs.
+struct Reconciler<'a> {
+    mapping: &'a Mapping,
+    transformer: MappingTransformer,
+    client: TurbopufferClient,
+    embedder: Option<EmbeddingClient>,
+    upload_batch_size: usize,
+    validator: DocumentValidator,
+    batcher: Batcher,
+    /// The documents of each namespace seen so far that no row has
+    /// accounted for yet.
+    namespaces: BTreeMap<String, NamespaceDiff>,
+    dry_run: bool,
+    outcome: ReconcileOutcome,
+}
+
+impl Reconciler<'_> {
+    /// Export a namespace the first time a row maps to it.
+    async fn export(&mut self, namespace: &str) -> Result<()> {
+        if self.namespaces.contains_key(namespace) {
+            return Ok(());
+        }
+        let rows = self
+            .client
+            .export(namespace, true)
+            .await
+            .with_context(|| format!("Failed to export namespace '{}'", namespace))?;
+        println!("Exported {} document(s) from '{}'", rows.len(), namespace);
+        self.namespaces
+            .insert(namespace.to_string(), NamespaceDiff::new(rows));
+        Ok(())
+    }
+
+    async fn reconcile_batch(&mut self, events: &[RowEvent]) -> Result<()> {
+        let mapping = self.mapping;
+        self.outcome.rows += events.len();
+
+        // Rows outside the mapping have no document, so one left in the
+        // namespace for them ends up an orphan
+        let mut members = Vec::new();
+        for event in events {
+            let id = match extract_id(event, &mapping.id) {
+                Ok(id) => id,
+                Err(e) => {
+                    warn!(mapping = %mapping.name, error = %e, "Skipping row without a valid ID");
+                    self.outcome.failed += 1;
+                    continue;
+                }
+            };
+            let namespace = match mapping.namespace_for(event) {
+                Ok(namespace) => namespace,
+                Err(e) => {
+                    warn!(mapping = %mapping.name, error = %e, "Skipping row without a namespace");
+                    self.outcome.failed += 1;
+                    continue;
+                }
+            };
+            self.export(&namespace).await?;
+            if is_member(mapping, event) {
+                members.push((event, id, namespace));
+            }
+        }
+        if members.is_empty() {
+            return Ok(());
+        }
+
+        let batch: Vec<_> = members
+            .iter()
+            .map(|(event, id, _)| (*event, id.clone()))
+            .collect();
+        let actions = self
+            .transformer
+            .transform_batch(&batch)
+            .context("Transform failed")?;
+
+        for ((event, id, namespace), action) in members.into_iter().zip(actions) {
+            let json_id = convert_doc_id_to_json(&id);
+            let action = match self.expected_action(&namespace, action) {
+                Ok(action) => action,
+                Err(e) => {
+                    warn!(
+                        mapping = %mapping.name,
+                        id = %json_id,
+                        error = %e,
+                        "Leaving the document of a row that failed as it is"
+                    );
+                    self.namespaces.get_mut(&namespace).unwrap().keep(&json_id);
+                    self.outcome.failed += 1;
+                    continue;
+                }
+            };
+
+            let diff = self.namespaces.get_mut(&namespace).unwrap();
+            let found = match &action {
+                Action::Upsert { doc, .. } => diff.compare(&json_id, doc),
+                // A patch only says what changed, so there's no full document
+                // to compare, and the document is left as it is
+                Action::Patch { .. } => {
+                    diff.keep(&json_id);
+                    continue;
+                }
+                // A row the transform deletes or skips has no document
+                _ => continue,
+            };
+            match found {
+                DocumentDiff::Unchanged => {
+                    self.outcome.unchanged += 1;
+                    continue;
+                }
+                DocumentDiff::Missing => self.outcome.missing += 1,
+                DocumentDiff::Stale => self.outcome.stale += 1,
+            }
+
+            let mut action = action;
+            if mapping.event_time {
+                action = action.with_event_time(event.timestamp.as_deref());
+            }
+            if mapping.run_id {
+                action = action.with_run_id(crate::run_id::current());
+            }
+            self.repair(&namespace, action).await?;
+        }
+        Ok(())
+    }
+
+    /// The action the runner would write for a row, short of the attributes
+    /// that differ between writes (event time, run id).
+    fn expected_action(
+        &mut self,
+        namespace: &str,
+        action: Action,
+    ) -> puffgres_core::Result<Action> {
+        self.validator
+            .check(namespace, &self.mapping.id.id_type, &action)?;
+        if let Action::Error { message, .. } = &action {
+            return Err(puffgres_core::Error::TransformError(message.clone()));
+        }
+        let mut action = action;
+        self.mapping.limits.enforce(&mut action)?;
+        if matches!(self.mapping.versioning, VersioningMode::ContentHash) {
+            action = action.with_content_hash();
+        }
+        Ok(action)
+    }
+
+    async fn repair(&mut self, namespace: &str, action: Action) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        if let Some(batch) = self.batcher.add(namespace, action, 0) {
+            self.write(batch).await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, batch: Batch) -> Result<()> {
+        let mut request = WriteRequest::from_batch(batch);
+        embed_request(self.embedder.as_ref(), self.mapping, &mut request).await?;
+        write_request(
+            &self.client,
+            &request,
+            self.mapping.source_lsn_attribute.as_deref(),
+            self.upload_batch_size,
+        )
+        .await
+        .with_context(|| format!("Failed to write to namespace '{}'", request.namespace))
+    }
+
+    /// Write the remaining repairs, then delete the orphans. Only reached once
+    /// every row has been scanned, so a failed scan deletes nothing.
+    async fn finish(mut self) -> Result<ReconcileOutcome> {
+        for batch in self.batcher.flush_all() {
+            self.write(batch).await?;
+        }
+        let namespaces = std::mem::take(&mut self.namespaces);
+        for (namespace, diff) in namespaces {
+            let orphans = diff.into_orphans();
+            self.outcome.orphans += orphans.len();
+            if self.dry_run {
+                continue;
+            }
+            for chunk in orphans.chunks(self.upload_batch_size) {
+                let params = rs_puff::WriteParams {
+                    deletes: Some(chunk.to_vec()),
+                    ..Default::default()
+                };
+                self.client
+                    .write(&namespace, params)
+                    .await
+                    .with_context(|| format!("Failed to delete orphans from '{}'", namespace))?;
+            }
+        }
+        Ok(self.outcome)
+    }
+}
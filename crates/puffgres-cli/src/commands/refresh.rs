@@ -0,0 +1,160 @@
+//! `puffgres refresh`: re-sync a mapping whose rows come from a query.
+//!
+//! A query source (a join, an aggregate, a materialized view) has no changes
+//! to replicate. Refresh backfills the query's current rows, then deletes
+//! documents whose rows are no longer in the result set.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_core::{extract_id, Mapping};
+use puffgres_pg::{BackfillConfig, BackfillScanner, IdRange, PostgresStateStore};
+
+use crate::backfill::{
+    self, get_backfill_columns, get_backfill_filter, get_backfill_text_columns, is_member,
+};
+use crate::config::ProjectConfig;
+use crate::env::{self, get_backfill_copy, get_max_retries, get_upload_batch_size};
+use crate::runner::convert_doc_id_to_json;
+use crate::strict::StrictMode;
+use crate::tp::TurbopufferClient;
+use crate::validation::validate_transforms;
+
+pub async fn cmd_refresh(config: ProjectConfig, mapping_name: &str, batch_size: u32) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    validate_transforms(&config, &store).await?;
+
+    let mappings = config.load_active_migrations(&store).await?;
+    let mapping = mappings
+        .iter()
+        .find(|m| m.name == mapping_name)
+        .with_context(|| format!("Mapping '{}' not found", mapping_name))?;
+    if !mapping.source.is_query() {
+        bail!(
+            "Mapping '{}' reads from {}, which `puffgres run` keeps in sync. Use `puffgres backfill` to reload it.",
+            mapping_name,
+            mapping.source.describe()
+        );
+    }
+
+    let strict = StrictMode::resolve(None, env::get_strict().as_deref())?;
+    let limits = backfill::BackfillLimits::default();
+    {
+        let progress = backfill::TerminalProgress::start(limits);
+        backfill::run_backfill(
+            &config,
+            Arc::new(store),
+            mapping,
+            batch_size,
+            false,
+            None,
+            1,
+            &limits,
+            &strict,
+            None,
+            &progress,
+        )
+        .await?;
+    }
+
+    // Read the result set again for its ids. A row that appeared since the
+    // backfill is picked up by the next refresh
+    let expected = expected_ids(&config, mapping, batch_size).await?;
+    let tp = TurbopufferClient::new(config.turbopuffer_api_key()?, get_max_retries());
+    let mut deleted = 0;
+    for (namespace, ids) in &expected {
+        let stale = stale_ids(&tp, namespace, ids).await?;
+        for chunk in stale.chunks(get_upload_batch_size()) {
+            let params = rs_puff::WriteParams {
+                deletes: Some(chunk.to_vec()),
+                ..Default::default()
+            };
+            tp.write(namespace, params).await?;
+        }
+        deleted += stale.len();
+    }
+
+    let rows: usize = expected.values().map(HashSet::len).sum();
+    println!(
+        "{}",
+        format!(
+            "Refreshed '{}': {} row(s) synced, {} stale document(s) deleted.",
+            mapping.name, rows, deleted
+        )
+        .green()
+    );
+    if mapping.is_partitioned() {
+        println!(
+            "{} Namespaces the query no longer returns any rows for aren't cleared.",
+            "!".yellow()
+        );
+    }
+    Ok(())
+}
+
+/// JSON text of the document id of every row in the result set, by namespace.
+async fn expected_ids(
+    config: &ProjectConfig,
+    mapping: &Mapping,
+    batch_size: u32,
+) -> Result<BTreeMap<String, HashSet<String>>> {
+    let mut scanner = BackfillScanner::new(BackfillConfig {
+        connection_string: config.postgres_connection_string()?,
+        schema: mapping.source.schema.clone(),
+        table: mapping.source.table.clone(),
+        id_columns: mapping.id.columns().to_vec(),
+        columns: get_backfill_columns(mapping),
+        batch_size,
+        filter: get_backfill_filter(mapping),
+        snapshot: None,
+        text_columns: get_backfill_text_columns(mapping),
+        query: mapping.source.query.clone(),
+        range: IdRange::default(),
+        copy: get_backfill_copy(),
+    })
+    .await
+    .context("Failed to create backfill scanner")?;
+
+    let mut expected: BTreeMap<String, HashSet<String>> = BTreeMap::new();
+    loop {
+        let events = scanner.next_batch().await?;
+        if events.is_empty() {
+            break;
+        }
+        for event in events {
+            if !is_member(mapping, &event) {
+                continue;
+            }
+            let id = convert_doc_id_to_json(&extract_id(&event, &mapping.id)?);
+            expected
+                .entry(mapping.namespace_for(&event)?)
+                .or_default()
+                .insert(id.to_string());
+        }
+    }
+    // The namespace of an unpartitioned mapping is diffed even when the
+    // query returns nothing
+    if !mapping.is_partitioned() {
+        expected.entry(mapping.namespace.clone()).or_default();
+    }
+    Ok(expected)
+}
+
+/// Ids of documents in `namespace` that aren't in `expected`.
+async fn stale_ids(
+    tp: &TurbopufferClient,
+    namespace: &str,
+    expected: &HashSet<String>,
+) -> Result<Vec<serde_json::Value>> {
+    Ok(tp
+        .export(namespace, false)
+        .await?
+        .into_iter()
+        .filter_map(|mut row| row.remove("id"))
+        .filter(|id| !expected.contains(&id.to_string()))
+        .collect())
+}
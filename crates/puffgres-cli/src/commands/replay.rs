@@ -0,0 +1,327 @@
+//! `puffgres replay`: write a range of past changes again.
+//!
+//! After a bad transform was deployed, the changes it mangled can be run
+//! through the fixed transform without backfilling the whole table. Changes
+//! are read from a temporary copy of the replication slot, so the slot itself
+//! and its position are untouched and a running runner keeps streaming. A
+//! slot only holds the changes its consumer hasn't confirmed, so the range
+//! can't start before the slot's confirmed position.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use tracing::warn;
+
+use puffgres_core::{
+    extract_id, Action, Batch, BatchConfig, Batcher, DocumentValidator, Mapping, Operation, Router,
+    RowEvent, VersioningMode, WriteRequest,
+};
+use puffgres_pg::replication::{get_confirmed_flush_lsn, ChangeTail};
+use puffgres_pg::{connect_postgres, format_lsn, parse_lsn, PostgresStateStore};
+
+use crate::config::ProjectConfig;
+use crate::embeddings::{embed_request, EmbeddingClient};
+use crate::env::{get_max_retries, get_transform_batch_size, get_upload_batch_size};
+use crate::runner::{
+    create_transformer, replication_groups, write_request, MappingTransformer, ReplicationGroup,
+};
+use crate::sinks::SinkWriters;
+use crate::tp::TurbopufferClient;
+use crate::validation::validate_transforms;
+
+pub async fn cmd_replay(
+    config: ProjectConfig,
+    from_lsn: &str,
+    to_lsn: &str,
+    mapping_name: Option<&str>,
+    slot: &str,
+    publication: &str,
+) -> Result<()> {
+    let from = parse_lsn(from_lsn).context("Invalid --from-lsn")?;
+    let to = parse_lsn(to_lsn).context("Invalid --to-lsn")?;
+    if from > to {
+        bail!("--from-lsn {} is after --to-lsn {}", from_lsn, to_lsn);
+    }
+
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    validate_transforms(&config, &store).await?;
+
+    // Query sources have no changes to replay
+    let mut mappings: Vec<Mapping> = config
+        .load_active_migrations(&store)
+        .await?
+        .into_iter()
+        .filter(|m| !m.source.is_query())
+        .collect();
+    drop(store);
+    if let Some(name) = mapping_name {
+        mappings.retain(|m| m.name == name);
+        if mappings.is_empty() {
+            bail!("Mapping '{}' not found, or it reads from a query", name);
+        }
+    }
+    if mappings.is_empty() {
+        println!("No migrations found in migrations/");
+        return Ok(());
+    }
+
+    let max_retries = get_max_retries();
+    let mut replayer = Replayer {
+        client: TurbopufferClient::new(config.turbopuffer_api_key()?, max_retries),
+        embedder: EmbeddingClient::for_mappings(&config, &mappings, max_retries)?,
+        sinks: SinkWriters::start(&config, &mappings, get_upload_batch_size())?,
+        upload_batch_size: get_upload_batch_size(),
+        validator: DocumentValidator::new(),
+        batchers: HashMap::new(),
+        outcome: ReplayOutcome::default(),
+    };
+
+    for group in replication_groups(mappings, slot, publication) {
+        replay_group(&config, &mut replayer, &group, from, to).await?;
+    }
+    let Replayer { sinks, outcome, .. } = replayer;
+    sinks.finish(None).await;
+
+    println!(
+        "{}",
+        format!(
+            "Replayed {} change(s) from {} to {}: {} document(s) written.",
+            outcome.changes,
+            format_lsn(from),
+            format_lsn(to),
+            outcome.written
+        )
+        .green()
+    );
+    if outcome.failed > 0 {
+        println!(
+            "{} {} change(s) failed to transform or were rejected; see the warnings above.",
+            "!".yellow(),
+            outcome.failed
+        );
+    }
+    if outcome.truncates > 0 {
+        println!(
+            "{} {} truncate(s) in the range were skipped; replay doesn't clear namespaces.",
+            "!".yellow(),
+            outcome.truncates
+        );
+    }
+    Ok(())
+}
+
+/// Replay one replication group's changes from a copy of its slot.
+async fn replay_group(
+    config: &ProjectConfig,
+    replayer: &mut Replayer,
+    group: &ReplicationGroup,
+    from: u64,
+    to: u64,
+) -> Result<()> {
+    let client = connect_postgres(&config.replication_connection_string()?)
+        .await
+        .context("Failed to connect to replication server")?;
+    let confirmed = get_confirmed_flush_lsn(&client, &group.slot)
+        .await?
+        .with_context(|| format!("Replication slot '{}' does not exist", group.slot))?;
+    let confirmed = parse_lsn(&confirmed)?;
+    if from < confirmed {
+        bail!(
+            "Slot '{}' has confirmed changes up to {}, and Postgres no longer decodes the ones before. \
+             Replay from {} or later, or backfill the mapping instead.",
+            group.slot,
+            format_lsn(confirmed),
+            format_lsn(confirmed)
+        );
+    }
+
+    let mut tail = ChangeTail::copy_of(client, &group.slot, &group.publication)
+        .await
+        .with_context(|| format!("Failed to copy replication slot '{}'", group.slot))?;
+    println!(
+        "Replaying {} to {} from a copy of slot '{}'...",
+        format_lsn(from),
+        format_lsn(to),
+        group.slot
+    );
+
+    let router = Router::new(group.mappings.clone());
+    let transformers: Vec<(String, MappingTransformer)> = group
+        .mappings
+        .iter()
+        .map(|m| (m.name.clone(), create_transformer(m)))
+        .collect();
+
+    // Changes of transactions that committed by `to`
+    while let Some(events) = tail
+        .poll_until(to.saturating_add(1))
+        .await
+        .context("Failed to read changes")?
+    {
+        for event in events.iter().filter(|e| (from..=to).contains(&e.lsn)) {
+            replayer.outcome.changes += 1;
+            for routed in router.route_to_namespaces(event) {
+                let mapping = routed.mapping;
+                if event.op == Operation::Truncate {
+                    replayer.outcome.truncates += 1;
+                    continue;
+                }
+                let transformer = transformers
+                    .iter()
+                    .find(|(name, _)| name == &mapping.name)
+                    .map(|(_, t)| t)
+                    .unwrap();
+
+                let writes = match replay_writes(
+                    mapping,
+                    transformer,
+                    &mut replayer.validator,
+                    event,
+                    routed.namespace,
+                    routed.moved_from,
+                    routed.left_membership,
+                ) {
+                    Ok(writes) => writes,
+                    Err(e) => {
+                        warn!(
+                            mapping = %mapping.name,
+                            lsn = %format_lsn(event.lsn),
+                            error = %e,
+                            "Failed to replay change"
+                        );
+                        replayer.outcome.failed += 1;
+                        continue;
+                    }
+                };
+                for (namespace, action) in writes {
+                    replayer.add(mapping, &namespace, action, event.lsn).await?;
+                }
+            }
+        }
+    }
+
+    for mapping in &group.mappings {
+        replayer.flush(mapping).await?;
+    }
+    Ok(())
+}
+
+/// The writes a change makes: its document's new version, or a delete when
+/// it left the mapping, plus a delete in the namespace a row moved out of.
+fn replay_writes(
+    mapping: &Mapping,
+    transformer: &MappingTransformer,
+    validator: &mut DocumentValidator,
+    event: &RowEvent,
+    namespace: puffgres_core::Result<String>,
+    moved_from: Option<String>,
+    left_membership: bool,
+) -> puffgres_core::Result<Vec<(String, Action)>> {
+    let id = extract_id(event, &mapping.id)?;
+    let namespace = namespace?;
+
+    let mut writes = Vec::new();
+    if let Some(previous) = moved_from {
+        writes.push((previous, Action::Delete { id: id.clone() }));
+    }
+    let mut action = if left_membership {
+        Action::delete(id)
+    } else {
+        let action = transformer.transform(event, id)?;
+        validator.check(&namespace, &mapping.id.id_type, &action)?;
+        action
+    };
+    if let Action::Error { message, .. } = &action {
+        return Err(puffgres_core::Error::TransformError(message.clone()));
+    }
+    mapping.limits.enforce(&mut action)?;
+
+    if matches!(mapping.versioning, VersioningMode::ContentHash) {
+        action = action.with_content_hash();
+    }
+    if mapping.event_time {
+        action = action.with_event_time(event.timestamp.as_deref());
+    }
+    if mapping.run_id {
+        action = action.with_run_id(crate::run_id::current());
+    }
+    if action.requires_write() {
+        writes.push((namespace, action));
+    }
+    Ok(writes)
+}
+
+#[derive(Debug, Default)]
+struct ReplayOutcome {
+    changes: usize,
+    written: usize,
+    failed: usize,
+    truncates: usize,
+}
+
+/// Batches replayed writes per mapping and writes them like the runner does.
+struct Replayer {
+    client: TurbopufferClient,
+    embedder: Option<EmbeddingClient>,
+    sinks: SinkWriters,
+    upload_batch_size: usize,
+    validator: DocumentValidator,
+    batchers: HashMap<String, Batcher>,
+    outcome: ReplayOutcome,
+}
+
+impl Replayer {
+    async fn add(
+        &mut self,
+        mapping: &Mapping,
+        namespace: &str,
+        action: Action,
+        lsn: u64,
+    ) -> Result<()> {
+        let batcher = self
+            .batchers
+            .entry(mapping.name.clone())
+            .or_insert_with(|| {
+                Batcher::new(
+                    mapping
+                        .batch
+                        .apply(BatchConfig::with_max_rows(get_transform_batch_size())),
+                )
+            });
+        if let Some(batch) = batcher.add(namespace, action, lsn) {
+            self.write(mapping, batch).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self, mapping: &Mapping) -> Result<()> {
+        let batches = match self.batchers.get_mut(&mapping.name) {
+            Some(batcher) => batcher.flush_all(),
+            None => return Ok(()),
+        };
+        for batch in batches {
+            self.write(mapping, batch).await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, mapping: &Mapping, batch: Batch) -> Result<()> {
+        let mut request = WriteRequest::from_batch(batch);
+        embed_request(self.embedder.as_ref(), mapping, &mut request).await?;
+        write_request(
+            &self.client,
+            &request,
+            mapping.source_lsn_attribute.as_deref(),
+            self.upload_batch_size,
+        )
+        .await
+        .with_context(|| format!("Failed to write to namespace '{}'", request.namespace))?;
+        self.sinks.send(&mapping.name, &request).await;
+        self.outcome.written +=
+            request.upserts.len() + request.patches.len() + request.deletes.len();
+        Ok(())
+    }
+}
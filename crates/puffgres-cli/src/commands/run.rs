@@ -1,24 +1,55 @@
 use std::fs;
+use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use colored::Colorize;
-use puffgres_pg::{MigrationTracker, PostgresStateStore};
-use tracing::info;
+use puffgres_core::{shadow_name, Mapping};
+use puffgres_pg::{ControlListener, MigrationStatus, MigrationTracker, PostgresStateStore};
+use puffgres_state::{SqliteStateStore, StateStore};
+use tokio::sync::watch;
+use tracing::{info, warn};
 
-use crate::config::ProjectConfig;
-use crate::runner;
+use super::doctor::find_drift;
+use crate::config::{parse_migration, ProjectConfig};
+use crate::env::get_health_stall_timeout;
+use crate::faults::FaultInjector;
+use crate::health::{self, Health};
+use crate::runner::{self, DryRun, LoopExit};
+use crate::strict::StrictMode;
 use crate::validation::{store_transform, validate_transforms};
 
+#[allow(clippy::too_many_arguments)]
 pub async fn cmd_run(
     config: ProjectConfig,
     slot: &str,
     publication: &str,
     create_slot: bool,
+    alter_publication: bool,
     skip_migrate: bool,
+    fault_inject: Option<&str>,
+    strict: &StrictMode,
+    dev: bool,
+    force_takeover: bool,
+    allow_mass_delete: bool,
+    health_addr: Option<SocketAddr>,
+    dry_run: Option<DryRun>,
 ) -> Result<()> {
     info!("Starting puffgres CDC replication");
 
+    let faults = Arc::new(FaultInjector::from_flag(fault_inject)?);
+
+    // Served from the start, so probes see the runner as alive but not ready
+    // while it applies migrations and connects
+    let health = Arc::new(Health::new(get_health_stall_timeout()));
+    let _health_server = match health_addr {
+        Some(addr) => Some(runner::AbortOnDrop(
+            health::spawn_server(health.clone(), addr).await?,
+        )),
+        None => None,
+    };
+
     // Connect to Postgres state store (this auto-creates __puffgres_* tables if they don't exist)
     let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
         .await
@@ -32,13 +63,17 @@ pub async fn cmd_run(
 
     // Validate that all referenced tables exist before proceeding
     for migration in &local {
-        let migration_config = puffgres_config::MigrationConfig::parse(&migration.content)
+        let migration_config = parse_migration(&migration.content)
             .with_context(|| {
                 format!(
                     "Failed to parse migration v{} '{}'",
                     migration.version, migration.mapping_name
                 )
             })?;
+        // Query sources are synced by `puffgres refresh`, not replication
+        if migration_config.source.query.is_some() {
+            continue;
+        }
 
         let schema = &migration_config.source.schema;
         let table = &migration_config.source.table;
@@ -58,10 +93,41 @@ pub async fn cmd_run(
             );
             std::process::exit(1);
         }
+
+        // Renamed or dropped columns don't stop replication, they just stop
+        // being synced
+        let columns = store.table_columns(schema, table).await?;
+        let drift = find_drift(&migration_config, &columns);
+        if !drift.is_empty() {
+            let issues: Vec<String> = drift.iter().map(ToString::to_string).collect();
+            warn!(
+                mapping = %migration.mapping_name,
+                "{}.{} no longer matches the migration: {}. Run `puffgres doctor` for a suggested revision.",
+                schema,
+                table,
+                issues.join("; ")
+            );
+        }
     }
 
-    // Validate transforms haven't been modified
-    if let Err(e) = validate_transforms(&config, &store).await {
+    // Validate transforms haven't been modified. Dev mode exists to iterate on
+    // transforms, so it skips this check, as does a dry run, which writes
+    // nothing
+    if dry_run.is_some() {
+        eprintln!(
+            "{}",
+            "Dry run: migrations aren't applied and run as they are locally; nothing is written to turbopuffer, checkpointed or acknowledged."
+                .yellow()
+                .bold()
+        );
+    } else if dev {
+        eprintln!(
+            "{}",
+            "Dev mode: transforms are reloaded on change and not checked against applied versions. Don't use --dev in production."
+                .yellow()
+                .bold()
+        );
+    } else if let Err(e) = validate_transforms(&config, &store).await {
         eprintln!("{}", format!("Error: {}", e).red());
         eprintln!(
             "{}",
@@ -73,8 +139,18 @@ pub async fn cmd_run(
 
     let tracker = MigrationTracker::new(&store);
 
-    // Auto-apply pending migrations unless --skip-migrate is set
-    if !skip_migrate {
+    // Auto-apply pending migrations unless --skip-migrate is set. A dry run
+    // leaves them pending
+    if dry_run.is_some() {
+        let status = tracker.validate(&local).await?;
+        if !status.pending.is_empty() {
+            println!(
+                "{} {} pending migration(s) run without being applied.",
+                "!".yellow(),
+                status.pending.len()
+            );
+        }
+    } else if !skip_migrate {
         let status = tracker.validate(&local).await?;
 
         // Check for mismatches
@@ -109,7 +185,7 @@ pub async fn cmd_run(
                     )
                     .await?;
 
-                let migration_config = puffgres_config::MigrationConfig::parse(&migration.content)?;
+                let migration_config = parse_migration(&migration.content)?;
                 if let Some(path) = &migration_config.transform.path {
                     let transform_path = Path::new(path.trim_start_matches("./"));
                     if transform_path.exists() {
@@ -129,16 +205,222 @@ pub async fn cmd_run(
                 "{}",
                 format!("Applied {} migration(s).", applied.len()).green()
             );
+            for revision in &status.revisions {
+                if revision.shadow {
+                    let shadow = shadow_name(&revision.mapping_name, revision.version as u32);
+                    println!(
+                        "{} v{} {} is built beside v{}; run `puffgres backfill {}`, then `puffgres promote {}`.",
+                        "!".yellow(),
+                        revision.version,
+                        revision.mapping_name,
+                        revision.replaces,
+                        shadow,
+                        revision.mapping_name
+                    );
+                    continue;
+                }
+                println!(
+                    "{} v{} {} supersedes v{}; run `puffgres backfill {}` to rewrite existing documents.",
+                    "!".yellow(),
+                    revision.version,
+                    revision.mapping_name,
+                    revision.replaces,
+                    revision.mapping_name
+                );
+            }
         }
     } else {
         // Just validate, don't apply
         tracker.validate_or_fail(&local, true).await?;
     }
 
-    // Load migrations as Mappings
-    let migrations = config.load_migrations()?;
+    let mut mappings = replicated_mappings(&config, &store).await?;
+
+    // A dry run keeps its checkpoints and dead letters in memory, so it
+    // streams from where the slot is and leaves the real ones alone
+    let store = Arc::new(store);
+    let state_store: Arc<dyn StateStore> = match dry_run {
+        Some(_) => Arc::new(SqliteStateStore::in_memory()?),
+        None => store.clone(),
+    };
+
+    // `puffgres migrate` asks running runners to reload once it has applied
+    // migrations
+    let (reload_sender, mut reload) = watch::channel(());
+    let _control = match ControlListener::connect(&config.postgres_connection_string()?).await {
+        Ok(listener) => Some(runner::AbortOnDrop(tokio::spawn(forward_reloads(
+            listener,
+            reload_sender,
+        )))),
+        Err(e) => {
+            warn!(
+                error = %e,
+                "Failed to listen for reload requests; restart the runner to pick up new migrations"
+            );
+            None
+        }
+    };
+
+    // Run a CDC loop per replication group, starting them again with the
+    // reloaded mappings whenever they stop for a reload
+    loop {
+        let exit = runner::run_replication_groups(
+            &config,
+            state_store.clone(),
+            mappings.clone(),
+            slot,
+            publication,
+            create_slot,
+            alter_publication,
+            &faults,
+            strict,
+            dev,
+            force_takeover,
+            allow_mass_delete,
+            dry_run,
+            &reload,
+            &health,
+        )
+        .await?;
+        if exit == LoopExit::Stopped {
+            return Ok(());
+        }
+
+        // Requests from here on are for migrations applied after this load
+        reload.borrow_and_update();
+        match reload_mappings(&config, &store, dev || dry_run.is_some()).await {
+            Ok(reloaded) => {
+                for mapping in &reloaded {
+                    match mappings.iter().find(|m| m.name == mapping.name) {
+                        None => info!(mapping = %mapping.name, "Adding mapping"),
+                        Some(old) if old.version != mapping.version => info!(
+                            mapping = %mapping.name,
+                            version = mapping.version,
+                            "Switching mapping to a new version"
+                        ),
+                        Some(_) => {}
+                    }
+                }
+                mappings = reloaded;
+            }
+            Err(e) => warn!(
+                "Failed to reload migrations, carrying on with the current ones: {:#}",
+                e
+            ),
+        }
+    }
+}
+
+/// The active mappings replication streams, leaving out the ones synced
+/// from a query.
+async fn replicated_mappings(
+    config: &ProjectConfig,
+    store: &PostgresStateStore,
+) -> Result<Vec<Mapping>> {
+    let (migrations, queries): (Vec<_>, Vec<_>) = config
+        .load_active_migrations(store)
+        .await?
+        .into_iter()
+        .partition(|m| !m.source.is_query());
     info!(count = migrations.len(), "Loaded migrations");
+    for mapping in &queries {
+        info!(
+            mapping = %mapping.name,
+            "Skipping query mapping; run `puffgres refresh` to sync it"
+        );
+    }
+    if migrations.is_empty() {
+        anyhow::bail!(
+            "Every mapping reads from a query, so there is nothing to replicate. Run `puffgres refresh <mapping>` instead."
+        );
+    }
+    Ok(migrations)
+}
+
+/// Load the mappings again once `puffgres migrate` has applied migrations.
+/// Unless `unchecked` (dev mode or a dry run), the local migrations must
+/// all be applied and unmodified, as they must be at startup, since the
+/// runner reads them from its own migrations/ directory.
+async fn reload_mappings(
+    config: &ProjectConfig,
+    store: &PostgresStateStore,
+    unchecked: bool,
+) -> Result<Vec<Mapping>> {
+    if !unchecked {
+        let local = config.load_local_migrations()?;
+        check_reloadable(&MigrationTracker::new(store).validate(&local).await?)?;
+        validate_transforms(config, store).await?;
+    }
+
+    let mappings = replicated_mappings(config, store).await?;
+    for mapping in &mappings {
+        let source = &mapping.source;
+        if !store.table_exists(&source.schema, &source.table).await? {
+            anyhow::bail!(
+                "table '{}.{}' of mapping '{}' does not exist",
+                source.schema,
+                source.table,
+                mapping.name
+            );
+        }
+    }
+    Ok(mappings)
+}
 
-    // Run the CDC loop
-    runner::run_cdc_loop(&config, migrations, slot, publication, create_slot).await
+/// Fail unless every local migration is applied and unmodified.
+fn check_reloadable(status: &MigrationStatus) -> Result<()> {
+    if !status.mismatched.is_empty() {
+        anyhow::bail!("applied migrations have been modified locally");
+    }
+    if !status.pending.is_empty() {
+        anyhow::bail!(
+            "{} local migration(s) aren't applied; was `puffgres migrate` run against this runner's migrations?",
+            status.pending.len()
+        );
+    }
+    Ok(())
+}
+
+/// Pass reload requests on to the CDC loops until the listening session ends.
+async fn forward_reloads(mut listener: ControlListener, reload: watch::Sender<()>) {
+    while listener.reload_requested().await {
+        info!("Reload requested");
+        reload.send_replace(());
+    }
+    warn!("Stopped listening for reload requests; restart the runner to pick up new migrations");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_pg::migrations::MigrationMismatch;
+
+    fn status() -> MigrationStatus {
+        MigrationStatus {
+            applied: vec!["v1 users".into()],
+            pending: Vec::new(),
+            mismatched: Vec::new(),
+            revisions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_check_reloadable() {
+        check_reloadable(&status()).unwrap();
+
+        let mut pending = status();
+        pending.pending.push("v2 orders".into());
+        let err = check_reloadable(&pending).unwrap_err();
+        assert!(err.to_string().contains("1 local migration(s)"), "{}", err);
+
+        let mut modified = status();
+        modified.mismatched.push(MigrationMismatch {
+            version: 1,
+            mapping_name: "users".into(),
+            expected_hash: "a".into(),
+            actual_hash: "b".into(),
+        });
+        let err = check_reloadable(&modified).unwrap_err();
+        assert!(err.to_string().contains("modified"), "{}", err);
+    }
 }
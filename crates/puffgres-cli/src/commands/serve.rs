@@ -0,0 +1,277 @@
+//! `puffgres serve`: a read-only web view of sync state.
+//!
+//! Serves the checkpoints, dead letter queue, backfill progress and migration
+//! status from the state tables as JSON, plus a page that renders them, so
+//! sync health can be checked from a browser.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use puffgres_pg::{format_lsn, AppliedMigration, LocalMigration, PostgresStateStore};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+use tracing::info;
+
+use crate::config::ProjectConfig;
+
+/// DLQ entries returned when the request doesn't ask for a number.
+const DEFAULT_DLQ_LIMIT: i64 = 50;
+
+/// Most DLQ entries returned by one request.
+const MAX_DLQ_LIMIT: i64 = 500;
+
+const INDEX_HTML: &str = include_str!("serve.html");
+
+struct ServeState {
+    config: ProjectConfig,
+    /// Reconnected on the next request after the connection drops.
+    store: Mutex<Option<PostgresStateStore>>,
+}
+
+impl ServeState {
+    /// The state store, reconnecting if the last connection was lost.
+    async fn store(&self) -> Result<MappedMutexGuard<'_, PostgresStateStore>> {
+        let mut store = self.store.lock().await;
+        if store.as_ref().is_none_or(|s| s.client().is_closed()) {
+            let url = self.config.postgres_connection_string()?;
+            let connected = PostgresStateStore::connect(&url)
+                .await
+                .context("Failed to connect to Postgres")?;
+            *store = Some(connected);
+        }
+        Ok(MutexGuard::map(store, |s| {
+            s.as_mut().expect("connected above")
+        }))
+    }
+}
+
+/// An error returned to the client as a 500 with a JSON body.
+struct ApiError(anyhow::Error);
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError {
+    fn from(e: E) -> Self {
+        Self(e.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "error": format!("{:#}", self.0) }));
+        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+    }
+}
+
+type ApiResult = std::result::Result<Json<Value>, ApiError>;
+
+pub async fn cmd_serve(config: ProjectConfig, bind: SocketAddr) -> Result<()> {
+    // Connect up front so a bad connection string fails here, not per request
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    let state = Arc::new(ServeState {
+        config,
+        store: Mutex::new(Some(store)),
+    });
+
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/api/checkpoints", get(checkpoints))
+        .route("/api/dlq", get(dlq))
+        .route("/api/backfill", get(backfill))
+        .route("/api/migrations", get(migrations))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .with_context(|| format!("Failed to listen on {}", bind))?;
+    info!(address = %bind, "Serving sync status");
+    println!("Serving sync status on http://{}", bind);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("Server failed")?;
+    Ok(())
+}
+
+async fn index() -> Html<&'static str> {
+    Html(INDEX_HTML)
+}
+
+async fn checkpoints(State(state): State<Arc<ServeState>>) -> ApiResult {
+    let store = state.store().await?;
+    let checkpoints = store.get_all_checkpoints().await?;
+
+    let checkpoints: Vec<Value> = checkpoints
+        .into_iter()
+        .map(|(mapping, checkpoint)| {
+            json!({
+                "mapping_name": mapping,
+                "lsn": format_lsn(checkpoint.lsn),
+                "events_processed": checkpoint.events_processed,
+                "updated_at": checkpoint.updated_at,
+                "reconnects": checkpoint.reconnects,
+                "last_reconnect_at": checkpoint.last_reconnect_at,
+            })
+        })
+        .collect();
+    Ok(Json(Value::Array(checkpoints)))
+}
+
+#[derive(Deserialize)]
+struct DlqQuery {
+    mapping: Option<String>,
+    limit: Option<i64>,
+}
+
+async fn dlq(State(state): State<Arc<ServeState>>, Query(query): Query<DlqQuery>) -> ApiResult {
+    let store = state.store().await?;
+    let entries = store
+        .get_dlq_entries(query.mapping.as_deref(), dlq_limit(query.limit))
+        .await?;
+    Ok(Json(serde_json::to_value(entries)?))
+}
+
+/// The number of DLQ entries to return for a requested limit.
+fn dlq_limit(requested: Option<i64>) -> i64 {
+    requested
+        .unwrap_or(DEFAULT_DLQ_LIMIT)
+        .clamp(1, MAX_DLQ_LIMIT)
+}
+
+async fn backfill(State(state): State<Arc<ServeState>>) -> ApiResult {
+    let store = state.store().await?;
+    let progress = store.get_all_backfill_progress().await?;
+    Ok(Json(serde_json::to_value(progress)?))
+}
+
+async fn migrations(State(state): State<Arc<ServeState>>) -> ApiResult {
+    let local = state.config.load_local_migrations()?;
+    let store = state.store().await?;
+    let applied = store.get_applied_migrations().await?;
+    Ok(Json(Value::Array(migration_statuses(&local, &applied))))
+}
+
+/// Each migration with whether it's applied, superseded by a later version of
+/// its mapping, pending, modified since it was applied, or applied but no
+/// longer in `migrations/`.
+fn migration_statuses(local: &[LocalMigration], applied: &[AppliedMigration]) -> Vec<Value> {
+    let mut statuses: Vec<(i32, Value)> = Vec::new();
+
+    for migration in local {
+        let existing = applied
+            .iter()
+            .find(|a| a.version == migration.version && a.mapping_name == migration.mapping_name);
+        let status = match existing {
+            Some(a) if a.content_hash != migration.content_hash() => "modified",
+            Some(a) if a.superseded_by.is_some() => "superseded",
+            Some(_) => "applied",
+            None => "pending",
+        };
+        statuses.push((
+            migration.version,
+            json!({
+                "version": migration.version,
+                "mapping_name": migration.mapping_name,
+                "status": status,
+                "applied_at": existing.map(|a| a.applied_at),
+            }),
+        ));
+    }
+
+    for a in applied {
+        let is_local = local
+            .iter()
+            .any(|m| m.version == a.version && m.mapping_name == a.mapping_name);
+        if !is_local {
+            statuses.push((
+                a.version,
+                json!({
+                    "version": a.version,
+                    "mapping_name": a.mapping_name,
+                    "status": "missing",
+                    "applied_at": a.applied_at,
+                }),
+            ));
+        }
+    }
+
+    statuses.sort_by_key(|(version, _)| *version);
+    statuses.into_iter().map(|(_, status)| status).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_pg::compute_content_hash;
+
+    fn local(version: i32, name: &str, content: &str) -> LocalMigration {
+        LocalMigration {
+            version,
+            mapping_name: name.to_string(),
+            content: content.to_string(),
+            shadow: false,
+            templates: Vec::new(),
+        }
+    }
+
+    fn applied(version: i32, name: &str, content: &str) -> AppliedMigration {
+        AppliedMigration {
+            id: version,
+            version,
+            mapping_name: name.to_string(),
+            content_hash: compute_content_hash(content),
+            applied_at: "2024-01-15T10:30:00Z".parse().unwrap(),
+            superseded_by: None,
+        }
+    }
+
+    #[test]
+    fn test_migration_statuses() {
+        let local = vec![
+            local(1, "users", "a"),
+            local(2, "posts", "b"),
+            local(4, "tags", "d"),
+            local(5, "users", "e"),
+        ];
+        let mut applied = vec![
+            applied(1, "users", "a"),
+            applied(2, "posts", "changed"),
+            applied(3, "comments", "c"),
+            applied(5, "users", "e"),
+        ];
+        applied[0].superseded_by = Some(5);
+
+        let statuses: Vec<(Value, Value)> = migration_statuses(&local, &applied)
+            .into_iter()
+            .map(|s| (s["version"].clone(), s["status"].clone()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (json!(1), json!("superseded")),
+                (json!(2), json!("modified")),
+                (json!(3), json!("missing")),
+                (json!(4), json!("pending")),
+                (json!(5), json!("applied")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dlq_limit() {
+        assert_eq!(dlq_limit(None), DEFAULT_DLQ_LIMIT);
+        assert_eq!(dlq_limit(Some(10)), 10);
+        assert_eq!(dlq_limit(Some(0)), 1);
+        assert_eq!(dlq_limit(Some(100_000)), MAX_DLQ_LIMIT);
+    }
+}
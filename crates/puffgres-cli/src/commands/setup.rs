@@ -1,10 +1,36 @@
+use std::collections::{BTreeMap, HashMap};
+
 use anyhow::{Context, Result};
 use colored::Colorize;
-use puffgres_pg::PostgresStateStore;
+use dialoguer::Confirm;
+use puffgres_core::{Mapping, SqlType};
+use puffgres_pg::replication::{
+    check_replica_identity, find_replica_identity_index, get_column_types, get_replica_identity,
+    install_notify_trigger, require_publication_tables, set_replica_identity, sync_publication,
+    sync_row_filters, ReplicaIdentityFix, NOTIFY_CHANNEL, ROW_FILTER_MIN_VERSION,
+};
+use puffgres_pg::{connect_postgres, PgError, PostgresStateStore};
+use tokio_postgres::Client;
 
 use crate::config::ProjectConfig;
 
-pub async fn cmd_setup(config: ProjectConfig) -> Result<()> {
+/// What `puffgres setup` does about replica identities that are too narrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityFix {
+    /// Report them.
+    Report,
+    /// Propose a change for each and apply it once confirmed.
+    Confirm,
+    /// Apply the changes without asking.
+    Apply,
+}
+
+pub async fn cmd_setup(
+    config: ProjectConfig,
+    publication: &str,
+    fix: IdentityFix,
+    notify: bool,
+) -> Result<()> {
     println!("Setting up puffgres database tables...\n");
 
     println!("The following tables will be created:");
@@ -12,6 +38,8 @@ pub async fn cmd_setup(config: ProjectConfig) -> Result<()> {
     println!("  - __puffgres_checkpoints - stores CDC replication state");
     println!("  - __puffgres_dlq         - dead letter queue for failed events");
     println!("  - __puffgres_backfill    - tracks backfill progress");
+    println!("  - __puffgres_backfill_partitions - tracks each range of a partitioned backfill");
+    println!("  - __puffgres_control     - records paused mappings");
     println!("  - __puffgres_transforms  - stores versioned transform code");
     println!("  - __puffgres_migration_content - stores migration content");
     println!();
@@ -22,6 +50,12 @@ pub async fn cmd_setup(config: ProjectConfig) -> Result<()> {
         .context("Failed to connect to Postgres")?;
 
     println!("{}", "Database tables created successfully!".green());
+
+    provision_publication(&config, publication, true, fix).await?;
+    if notify {
+        install_notify_triggers(&config).await?;
+    }
+
     println!("\nNext steps:");
     println!("  1. Run: puffgres new <table_name>");
     println!("  2. Run: puffgres migrate");
@@ -30,3 +64,377 @@ pub async fn cmd_setup(config: ProjectConfig) -> Result<()> {
 
     Ok(())
 }
+
+/// Make the publication cover every table referenced by a migration and
+/// check that each table's replica identity logs the old-row columns the
+/// mappings need. Without `alter`, the publication is left as is and the
+/// tables it's missing are reported. Tables whose replica identity falls
+/// short are handled as `fix` says. With `alter`, each table's row filter is
+/// then set from the predicates of mappings that ask for one.
+///
+/// Publications live on the primary, even when replication reads from a standby.
+pub(crate) async fn provision_publication(
+    config: &ProjectConfig,
+    publication: &str,
+    alter: bool,
+    fix: IdentityFix,
+) -> Result<()> {
+    let tables = mapped_tables(config)?;
+    if tables.is_empty() {
+        println!("\nNo migrations yet; the publication will be set up once you add one.");
+        return Ok(());
+    }
+
+    let client = connect_postgres(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+
+    for (publication, table_names) in publication_tables(config, publication)? {
+        let publication = publication.as_str();
+        if alter {
+            report_publication_sync(&client, publication, &table_names).await?;
+            continue;
+        }
+        match require_publication_tables(&client, publication, &table_names).await {
+            Ok(()) => println!(
+                "\nPublication '{}' already covers all mapped tables.",
+                publication
+            ),
+            Err(e @ PgError::PublicationMissingTables { .. }) => {
+                println!("\n{} {}", "!".yellow(), e.to_string().yellow());
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to check publication '{}'", publication))
+            }
+        }
+    }
+
+    println!("\nReplica identity:");
+    for table in &tables {
+        let name = table.qualified_name();
+        match check_replica_identity(&client, &table.schema, &table.table, table.needs_full_row)
+            .await
+        {
+            Ok(identity) => println!("  ✓ {} ({})", name, identity.identity.as_sql()),
+            Err(e @ PgError::ReplicaIdentity { .. }) => {
+                println!("  {} {}", "!".yellow(), e.to_string().yellow());
+                if fix == IdentityFix::Report {
+                    println!("    Rerun with --fix-replica-identity to change it.");
+                    continue;
+                }
+                fix_replica_identity(&client, table, fix).await?;
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to check replica identity of {}", name))
+            }
+        }
+    }
+
+    if alter {
+        let mappings = config.load_migrations()?;
+        let column_types = filtered_column_types(&client, &mappings).await?;
+        for (publication, filters) in publication_row_filters(&mappings, publication, &column_types)
+        {
+            report_row_filter_sync(&client, &publication, &filters).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add the change notification trigger to every mapped table. Triggers are
+/// per table, so tables mapped later need another `puffgres setup --notify`.
+async fn install_notify_triggers(config: &ProjectConfig) -> Result<()> {
+    let tables = mapped_tables(config)?;
+    if tables.is_empty() {
+        return Ok(());
+    }
+
+    let client = connect_postgres(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    println!("\nChange notifications (channel '{}'):", NOTIFY_CHANNEL);
+    for table in &tables {
+        let name = table.qualified_name();
+        install_notify_trigger(&client, &name)
+            .await
+            .with_context(|| format!("Failed to add the notification trigger to {}", name))?;
+        println!("  ✓ {}", name);
+    }
+    Ok(())
+}
+
+/// Propose the narrowest replica identity that logs what `table`'s mappings
+/// need, and apply it unless the user declines.
+async fn fix_replica_identity(client: &Client, table: &MappedTable, fix: IdentityFix) -> Result<()> {
+    let name = table.qualified_name();
+    let current = get_replica_identity(client, &table.schema, &table.table).await?;
+    let index = find_replica_identity_index(client, &table.schema, &table.table)
+        .await
+        .with_context(|| format!("Failed to look up unique indexes of {}", name))?;
+    let change = ReplicaIdentityFix::choose(&current, table.needs_full_row, index.as_deref());
+
+    let statement = format!("ALTER TABLE {} REPLICA IDENTITY {}", name, change.as_sql());
+    if change == ReplicaIdentityFix::Full {
+        println!("    FULL logs the whole old row on every update and delete, so WAL volume grows.");
+    }
+    if fix == IdentityFix::Confirm
+        && !Confirm::new()
+            .with_prompt(format!("    Run {}?", statement))
+            .default(false)
+            .interact()?
+    {
+        println!("    Skipped.");
+        return Ok(());
+    }
+
+    set_replica_identity(client, &table.schema, &table.table, &change)
+        .await
+        .with_context(|| format!("Failed to run {}", statement))?;
+    println!("  ✓ {} ({}, {})", name, change.as_sql(), "fixed".green());
+    Ok(())
+}
+
+/// Create the publication or add the mapped tables it's missing, and say
+/// what changed.
+async fn report_publication_sync(
+    client: &Client,
+    publication: &str,
+    table_names: &[String],
+) -> Result<()> {
+    let sync = sync_publication(client, publication, table_names)
+        .await
+        .with_context(|| format!("Failed to set up publication '{}'", publication))?;
+
+    if sync.created {
+        println!(
+            "\n{}",
+            format!(
+                "Created publication '{}' for {} table(s).",
+                publication,
+                table_names.len()
+            )
+            .green()
+        );
+    } else if sync.all_tables {
+        println!("\nPublication '{}' covers all tables.", publication);
+    } else if !sync.added_tables.is_empty() {
+        println!(
+            "\n{}",
+            format!(
+                "Added {} to publication '{}'.",
+                sync.added_tables.join(", "),
+                publication
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "\nPublication '{}' already covers all mapped tables.",
+            publication
+        );
+    }
+
+    if !sync.unmapped_tables.is_empty() {
+        println!(
+            "{} Publication '{}' also includes {}, which no migration maps; their changes are \
+             streamed and discarded. If nothing else uses them, remove them with \
+             ALTER PUBLICATION {} DROP TABLE ...",
+            "!".yellow(),
+            publication,
+            sync.unmapped_tables.join(", "),
+            publication
+        );
+    }
+
+    Ok(())
+}
+
+/// A source table referenced by local migrations.
+struct MappedTable {
+    schema: String,
+    table: String,
+    /// Some mapping routes deletes on old-row columns besides the ID.
+    needs_full_row: bool,
+}
+
+impl MappedTable {
+    fn qualified_name(&self) -> String {
+        format!("{}.{}", self.schema, self.table)
+    }
+}
+
+/// Source tables of local migrations by the publication that streams them:
+/// `publication`, or its variant for a mapping's replication group.
+fn publication_tables(
+    config: &ProjectConfig,
+    publication: &str,
+) -> Result<BTreeMap<String, Vec<String>>> {
+    let mut publications: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for mapping in config.load_migrations()? {
+        if mapping.source.is_query() {
+            continue;
+        }
+        let tables = publications
+            .entry(mapping.replication_name(publication))
+            .or_default();
+        let table = format!("{}.{}", mapping.source.schema, mapping.source.table);
+        if !tables.contains(&table) {
+            tables.push(table);
+        }
+    }
+    Ok(publications)
+}
+
+/// Column types by table, for the tables of mappings that set `row_filter`.
+async fn filtered_column_types(
+    client: &Client,
+    mappings: &[Mapping],
+) -> Result<HashMap<String, HashMap<String, SqlType>>> {
+    let mut tables = HashMap::new();
+    for mapping in mappings {
+        let table = format!("{}.{}", mapping.source.schema, mapping.source.table);
+        if !mapping.row_filter || mapping.source.is_query() || tables.contains_key(&table) {
+            continue;
+        }
+        let types = get_column_types(client, &mapping.source.schema, &mapping.source.table)
+            .await
+            .with_context(|| format!("Failed to read the columns of {}", table))?;
+        let types = types
+            .into_iter()
+            .map(|(column, type_name)| (column, SqlType::from_type_name(&type_name)))
+            .collect();
+        tables.insert(table, types);
+    }
+    Ok(tables)
+}
+
+/// The row filter of each table by the publication that streams it: the
+/// predicates of the table's mappings joined with OR, when every one of them
+/// sets `row_filter` and its predicate can be written as SQL for the table's
+/// `column_types`. Any other mapping needs all of the table's rows, so the
+/// table is left unfiltered.
+fn publication_row_filters(
+    mappings: &[Mapping],
+    publication: &str,
+    column_types: &HashMap<String, HashMap<String, SqlType>>,
+) -> BTreeMap<String, Vec<(String, Option<String>)>> {
+    let mut publications: BTreeMap<String, BTreeMap<String, Option<Vec<String>>>> = BTreeMap::new();
+    for mapping in mappings {
+        if mapping.source.is_query() {
+            continue;
+        }
+        let table = format!("{}.{}", mapping.source.schema, mapping.source.table);
+        let types = column_types.get(&table);
+        let filter = mapping.row_filter_sql(|col| types?.get(col).copied());
+        if mapping.row_filter && filter.is_none() {
+            println!(
+                "  {} Mapping '{}' can't filter {} in Postgres: its predicate compares columns of types Postgres would match differently. It gets every row.",
+                "!".yellow(),
+                mapping.name,
+                table
+            );
+        }
+        let predicates = publications
+            .entry(mapping.replication_name(publication))
+            .or_default()
+            .entry(table)
+            .or_insert_with(|| Some(Vec::new()));
+        match (predicates, filter) {
+            (Some(predicates), Some(sql)) => {
+                if !predicates.contains(&sql) {
+                    predicates.push(sql);
+                }
+            }
+            (predicates, _) => *predicates = None,
+        }
+    }
+
+    publications
+        .into_iter()
+        .map(|(publication, tables)| {
+            let filters = tables
+                .into_iter()
+                .map(|(table, predicates)| {
+                    let filter = predicates.map(|predicates| match predicates.as_slice() {
+                        [predicate] => predicate.clone(),
+                        _ => predicates
+                            .iter()
+                            .map(|predicate| format!("({})", predicate))
+                            .collect::<Vec<_>>()
+                            .join(" OR "),
+                    });
+                    (table, filter)
+                })
+                .collect();
+            (publication, filters)
+        })
+        .collect()
+}
+
+/// Set the row filters of a publication's tables, and say what changed.
+async fn report_row_filter_sync(
+    client: &Client,
+    publication: &str,
+    filters: &[(String, Option<String>)],
+) -> Result<()> {
+    let sync = sync_row_filters(client, publication, filters)
+        .await
+        .with_context(|| format!("Failed to set row filters of publication '{}'", publication))?;
+
+    if sync.unsupported {
+        println!(
+            "{} Row filters need Postgres {}+, so the runner checks membership instead.",
+            "!".yellow(),
+            ROW_FILTER_MIN_VERSION / 10000
+        );
+    }
+    for (table, filter) in &sync.filtered {
+        println!(
+            "{}",
+            format!(
+                "Publication '{}' only streams rows of {} where {}.",
+                publication, table, filter
+            )
+            .green()
+        );
+    }
+    for table in &sync.unfiltered {
+        println!(
+            "Removed the row filter of {} from publication '{}'.",
+            table, publication
+        );
+    }
+    if !sync.needs_full_identity.is_empty() {
+        println!(
+            "{} A row filter needs REPLICA IDENTITY FULL, so {} aren't filtered and the runner \
+             checks membership instead. Run puffgres setup --fix-replica-identity to change it.",
+            "!".yellow(),
+            sync.needs_full_identity.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Source tables of all local migrations. Query sources have none.
+fn mapped_tables(config: &ProjectConfig) -> Result<Vec<MappedTable>> {
+    let mut tables: BTreeMap<(String, String), bool> = BTreeMap::new();
+    for mapping in config.load_migrations()? {
+        if mapping.source.is_query() {
+            continue;
+        }
+        let key = (mapping.source.schema.clone(), mapping.source.table.clone());
+        *tables.entry(key).or_default() |= mapping.needs_full_old_row();
+    }
+
+    Ok(tables
+        .into_iter()
+        .map(|((schema, table), needs_full_row)| MappedTable {
+            schema,
+            table,
+            needs_full_row,
+        })
+        .collect())
+}
@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use puffgres_pg::{PostgresStateStore, StateSnapshot};
+
+use crate::cli::StateCommands;
+use crate::config::ProjectConfig;
+
+pub async fn cmd_state(config: ProjectConfig, command: StateCommands) -> Result<()> {
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+
+    match command {
+        StateCommands::Export { path } => export(&store, &path).await,
+        StateCommands::Import { path, replace } => import(&store, &path, replace).await,
+    }
+}
+
+async fn export(store: &PostgresStateStore, path: &Path) -> Result<()> {
+    let snapshot = store
+        .export_state()
+        .await
+        .context("Failed to read puffgres state")?;
+    let json = serde_json::to_string_pretty(&snapshot)?;
+    fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    for (table, rows) in &snapshot.tables {
+        println!("  {} {} row(s)", table, rows.len());
+    }
+    println!(
+        "\n{}",
+        format!("Exported puffgres state to {}", path.display()).green()
+    );
+    Ok(())
+}
+
+async fn import(store: &PostgresStateStore, path: &Path, replace: bool) -> Result<()> {
+    let json =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let snapshot: StateSnapshot = serde_json::from_str(&json)
+        .with_context(|| format!("{} is not a puffgres state export", path.display()))?;
+
+    let imported = store.import_state(&snapshot, replace).await?;
+
+    for (table, rows) in &imported {
+        println!("  {} {} row(s)", table, rows);
+    }
+    println!(
+        "\n{}",
+        format!(
+            "Imported puffgres state exported at {}",
+            snapshot.exported_at.format("%Y-%m-%d %H:%M:%S UTC")
+        )
+        .green()
+    );
+    if snapshot
+        .tables
+        .get("__puffgres_checkpoints")
+        .is_some_and(|rows| !rows.is_empty())
+    {
+        println!(
+            "{} Checkpoints are WAL positions of the database they were exported from. They \
+             carry over when this database continues its WAL (a promoted Neon branch or \
+             replica); otherwise run a backfill before `puffgres run`.",
+            "!".yellow()
+        );
+    }
+    Ok(())
+}
@@ -1,32 +1,477 @@
+use std::collections::{BTreeSet, HashSet};
+use std::time::Duration;
+
 use anyhow::{Context, Result};
-use puffgres_pg::PostgresStateStore;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use puffgres_pg::replication::publication::{
+    get_publication_tables, missing_tables, publication_exists,
+};
+use puffgres_pg::replication::{
+    find_open_transactions, get_server_info, get_slot_lag, OpenTransaction, ServerInfo, SlotLag,
+};
+use puffgres_pg::{connect_postgres, format_lsn, BackfillProgress, Checkpoint, PostgresStateStore};
+use serde_json::{json, Value};
 
 use crate::config::ProjectConfig;
+use crate::env::{get_long_transaction_warn_age, get_wal_retention_warn_bytes};
 
-pub async fn cmd_status(config: ProjectConfig) -> Result<()> {
-    // Connect to Postgres state store
-    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
-        .await
-        .context("Failed to connect to Postgres")?;
+pub async fn cmd_status(
+    config: ProjectConfig,
+    slot: &str,
+    publication: &str,
+    json: bool,
+    watch: Option<Duration>,
+) -> Result<()> {
+    let Some(interval) = watch else {
+        let status = Status::collect(&config, slot, publication).await?;
+        status.print(json);
+        return Ok(());
+    };
 
-    let checkpoints = store.get_all_checkpoints().await?;
+    loop {
+        let status = Status::collect(&config, slot, publication).await?;
+        if !json {
+            // Redraw in place rather than scrolling
+            print!("\x1b[2J\x1b[H");
+        }
+        status.print(json);
 
-    if checkpoints.is_empty() {
-        println!("No sync state found. Run 'puffgres run' to start syncing.");
-        return Ok(());
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
     }
+}
+
+/// Everything `puffgres status` reports, read once per refresh.
+struct Status {
+    slot: String,
+    checkpoints: Vec<(String, Checkpoint)>,
+    /// Mappings paused with `puffgres pause`.
+    paused: Vec<String>,
+    server: ServerInfo,
+    slot_lag: Option<SlotLag>,
+    retention_threshold: u64,
+    backfills: Vec<BackfillProgress>,
+    dlq_counts: Vec<(String, i64)>,
+    publication: PublicationHealth,
+    /// `None` when the check is turned off.
+    open_transactions: Option<Result<Vec<OpenTransaction>, String>>,
+}
+
+/// Whether the publication covers every mapped table.
+struct PublicationHealth {
+    name: String,
+    exists: bool,
+    tables: Vec<String>,
+    missing_tables: Vec<String>,
+}
 
-    println!("\nSync Status:");
-    println!("{:<30} {:>15} {:>15}", "Mapping", "LSN", "Events");
-    println!("{:-<60}", "");
+impl Status {
+    async fn collect(config: &ProjectConfig, slot: &str, publication: &str) -> Result<Self> {
+        // Connect to Postgres state store
+        let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+            .await
+            .context("Failed to connect to Postgres")?;
 
-    for (name, checkpoint) in checkpoints {
+        let checkpoints = store.get_all_checkpoints().await?;
+        let paused = store.get_paused_mappings().await?;
+        let backfills = store.get_all_backfill_progress().await?;
+        let dlq_counts = store.count_dlq_entries().await?;
+
+        // The slot lives on the replication server, which may be a standby
+        let replication_client = connect_postgres(&config.replication_connection_string()?)
+            .await
+            .context("Failed to connect to replication server")?;
+        let server = get_server_info(&replication_client)
+            .await
+            .context("Failed to query replication server")?;
+        let slot_lag = get_slot_lag(&replication_client, slot)
+            .await
+            .context("Failed to query replication slot")?;
+
+        // Publications are managed on the primary
+        let mapped: Vec<String> = config
+            .load_migrations()?
+            .iter()
+            .filter(|m| !m.source.is_query())
+            .map(|m| format!("{}.{}", m.source.schema, m.source.table))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let exists = publication_exists(store.client(), publication)
+            .await
+            .context("Failed to query publication")?;
+        let current: HashSet<String> = if exists {
+            get_publication_tables(store.client(), publication)
+                .await
+                .context("Failed to query publication tables")?
+        } else {
+            HashSet::new()
+        };
+        let mut tables: Vec<String> = current.iter().cloned().collect();
+        tables.sort();
+        let publication = PublicationHealth {
+            name: publication.to_string(),
+            exists,
+            tables,
+            missing_tables: missing_tables(&current, &mapped),
+        };
+
+        // Open transactions live on the primary even when streaming from a standby
+        let warn_age = get_long_transaction_warn_age();
+        let open_transactions = if warn_age.is_zero() {
+            None
+        } else {
+            Some(
+                find_open_transactions(store.client(), slot, warn_age)
+                    .await
+                    .map_err(|e| e.to_string()),
+            )
+        };
+
+        Ok(Self {
+            slot: slot.to_string(),
+            checkpoints,
+            paused,
+            server,
+            slot_lag,
+            retention_threshold: get_wal_retention_warn_bytes(),
+            backfills,
+            dlq_counts,
+            publication,
+            open_transactions,
+        })
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            println!("{}", self.to_json());
+        } else {
+            self.print_text();
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mappings: Vec<Value> = self
+            .checkpoints
+            .iter()
+            .map(|(name, checkpoint)| {
+                json!({
+                    "mapping_name": name,
+                    "paused": self.paused.contains(name),
+                    "lsn": format_lsn(checkpoint.lsn),
+                    "events_processed": checkpoint.events_processed,
+                    "lag_bytes": self.slot_lag.as_ref().map(|s| s.lag_bytes(checkpoint.lsn)),
+                    "updated_at": checkpoint.updated_at,
+                    "reconnects": checkpoint.reconnects,
+                    "last_reconnect_at": checkpoint.last_reconnect_at,
+                })
+            })
+            .collect();
+
+        let slot = match &self.slot_lag {
+            Some(lag) => json!({
+                "name": self.slot,
+                "exists": true,
+                "active": lag.active,
+                "current_wal_lsn": format_lsn(lag.current_wal_lsn),
+                "restart_lsn": lag.restart_lsn.map(format_lsn),
+                "confirmed_flush_lsn": lag.confirmed_flush_lsn.map(format_lsn),
+                "retained_bytes": lag.retained_bytes,
+                "retention_warning": lag.retained_bytes > self.retention_threshold,
+            }),
+            None => json!({ "name": self.slot, "exists": false }),
+        };
+
+        let dlq: serde_json::Map<String, Value> = self
+            .dlq_counts
+            .iter()
+            .map(|(name, count)| (name.clone(), json!(count)))
+            .collect();
+
+        let open_transactions = match &self.open_transactions {
+            None => Value::Null,
+            Some(Ok(open)) => json!(open.iter().map(|t| t.describe()).collect::<Vec<_>>()),
+            Some(Err(e)) => json!({ "error": e }),
+        };
+
+        json!({
+            "mappings": mappings,
+            "server": {
+                "role": self.server_role(),
+                "timeline_id": self.server.timeline_id,
+            },
+            "slot": slot,
+            "last_reconnect_at": self.last_reconnect(),
+            "publication": {
+                "name": self.publication.name,
+                "exists": self.publication.exists,
+                "tables": self.publication.tables,
+                "missing_tables": self.publication.missing_tables,
+            },
+            "backfills": self.backfills,
+            "dlq": dlq,
+            "open_transactions": open_transactions,
+        })
+    }
+
+    fn server_role(&self) -> &'static str {
+        if self.server.in_recovery {
+            "standby"
+        } else {
+            "primary"
+        }
+    }
+
+    fn last_reconnect(&self) -> Option<DateTime<Utc>> {
+        self.checkpoints
+            .iter()
+            .filter_map(|(_, c)| c.last_reconnect_at)
+            .max()
+    }
+
+    fn print_text(&self) {
+        if self.checkpoints.is_empty() {
+            println!("No sync state found. Run 'puffgres run' to start syncing.");
+            return;
+        }
+
+        println!("\nSync Status:");
         println!(
-            "{:<30} {:>15} {:>15}",
-            name, checkpoint.lsn, checkpoint.events_processed
+            "{:<30} {:>18} {:>15} {:>12} {:>11}",
+            "Mapping", "LSN", "Events", "Lag", "Reconnects"
         );
+        println!("{:-<90}", "");
+
+        for (name, checkpoint) in &self.checkpoints {
+            let lag = self
+                .slot_lag
+                .as_ref()
+                .map(|s| format_bytes(s.lag_bytes(checkpoint.lsn)))
+                .unwrap_or_else(|| "-".to_string());
+            let name = if self.paused.contains(name) {
+                format!("{} (paused)", name)
+            } else {
+                name.clone()
+            };
+
+            println!(
+                "{:<30} {:>18} {:>15} {:>12} {:>11}",
+                name,
+                format_lsn(checkpoint.lsn),
+                checkpoint.events_processed,
+                lag,
+                checkpoint.reconnects
+            );
+        }
+
+        println!("\nReplication Slot '{}':", self.slot);
+        println!(
+            "  Server:            {}{}",
+            self.server_role(),
+            self.server
+                .timeline_id
+                .map(|tli| format!(" (timeline {})", tli))
+                .unwrap_or_default()
+        );
+        println!(
+            "  Last reconnect:    {}",
+            self.last_reconnect()
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "-".to_string())
+        );
+        match &self.slot_lag {
+            Some(lag) => {
+                println!("  Current WAL LSN:   {}", format_lsn(lag.current_wal_lsn));
+                println!(
+                    "  Restart LSN:       {}",
+                    lag.restart_lsn
+                        .map(format_lsn)
+                        .unwrap_or_else(|| "-".to_string())
+                );
+                println!(
+                    "  Confirmed flush:   {}",
+                    lag.confirmed_flush_lsn
+                        .map(format_lsn)
+                        .unwrap_or_else(|| "-".to_string())
+                );
+                println!("  Active:            {}", lag.active);
+                println!("  Retained WAL:      {}", format_bytes(lag.retained_bytes));
+
+                if lag.retained_bytes > self.retention_threshold {
+                    println!(
+                        "\n{}",
+                        format!(
+                            "Warning: slot '{}' is retaining {} of WAL (threshold {}).",
+                            self.slot,
+                            format_bytes(lag.retained_bytes),
+                            format_bytes(self.retention_threshold)
+                        )
+                        .yellow()
+                    );
+                    println!(
+                        "{}",
+                        "Make sure 'puffgres run' is running, or drop the slot if it is no longer used."
+                            .yellow()
+                    );
+                }
+            }
+            None => {
+                println!("  {}", "Slot not found.".yellow());
+            }
+        }
+
+        if !self.publication.exists {
+            println!(
+                "\n{}",
+                format!(
+                    "Warning: publication '{}' does not exist. Run 'puffgres setup'.",
+                    self.publication.name
+                )
+                .yellow()
+            );
+        } else if !self.publication.missing_tables.is_empty() {
+            println!(
+                "\n{}",
+                format!(
+                    "Warning: publication '{}' doesn't include {}, so their changes aren't replicated.",
+                    self.publication.name,
+                    self.publication.missing_tables.join(", ")
+                )
+                .yellow()
+            );
+        }
+
+        let dlq_total: i64 = self.dlq_counts.iter().map(|(_, count)| count).sum();
+        if dlq_total > 0 {
+            println!(
+                "\n{}",
+                format!(
+                    "{} event(s) in the dead letter queue. Run 'puffgres dlq list' to inspect them.",
+                    dlq_total
+                )
+                .yellow()
+            );
+        }
+
+        match &self.open_transactions {
+            Some(Err(e)) => println!(
+                "\n{}",
+                format!("Could not check for long-running transactions: {}", e).yellow()
+            ),
+            Some(Ok(open)) if !open.is_empty() => {
+                println!(
+                    "\n{}",
+                    "Warning: long-running transactions are holding back replication.".yellow()
+                );
+                println!(
+                    "{}",
+                    "Their changes sync once they commit or roll back; the slot retains WAL meanwhile."
+                        .yellow()
+                );
+                for txn in open {
+                    println!("  {}", txn.describe());
+                }
+            }
+            _ => {}
+        }
+
+        println!();
+    }
+}
+
+/// Format a byte count for display (e.g., 1536 -> "1.5 KB").
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
     }
 
-    println!();
-    Ok(())
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(1024 * 1024), "1.0 MB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+
+    fn status(slot_lag: Option<SlotLag>) -> Status {
+        Status {
+            slot: "puffgres".to_string(),
+            checkpoints: vec![(
+                "users".to_string(),
+                Checkpoint {
+                    lsn: 0x100,
+                    events_processed: 7,
+                    ..Default::default()
+                },
+            )],
+            paused: vec!["users".to_string()],
+            server: ServerInfo {
+                version_num: 160002,
+                in_recovery: false,
+                timeline_id: Some(1),
+            },
+            slot_lag,
+            retention_threshold: 1024,
+            backfills: vec![],
+            dlq_counts: vec![("users".to_string(), 3)],
+            publication: PublicationHealth {
+                name: "puffgres_pub".to_string(),
+                exists: true,
+                tables: vec!["public.users".to_string()],
+                missing_tables: vec!["public.posts".to_string()],
+            },
+            open_transactions: None,
+        }
+    }
+
+    #[test]
+    fn test_status_json() {
+        let json = status(Some(SlotLag {
+            restart_lsn: Some(0x80),
+            confirmed_flush_lsn: Some(0x100),
+            current_wal_lsn: 0x900,
+            retained_bytes: 0x880,
+            active: true,
+        }))
+        .to_json();
+
+        assert_eq!(json["mappings"][0]["mapping_name"], "users");
+        assert_eq!(json["mappings"][0]["paused"], true);
+        assert_eq!(json["mappings"][0]["lsn"], "0/100");
+        assert_eq!(json["mappings"][0]["lag_bytes"], 0x800);
+        assert_eq!(json["server"]["role"], "primary");
+        assert_eq!(json["slot"]["exists"], true);
+        assert_eq!(json["slot"]["retention_warning"], true);
+        assert_eq!(json["publication"]["missing_tables"], json!(["public.posts"]));
+        assert_eq!(json["dlq"], json!({ "users": 3 }));
+        assert_eq!(json["open_transactions"], Value::Null);
+    }
+
+    #[test]
+    fn test_status_json_without_slot() {
+        let json = status(None).to_json();
+
+        assert_eq!(json["slot"], json!({ "name": "puffgres", "exists": false }));
+        assert_eq!(json["mappings"][0]["lag_bytes"], Value::Null);
+    }
 }
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_core::{Operation, Router, RowEvent, RowMap, Source};
+use puffgres_pg::replication::{ChangeListener, ChangeTail, TailSource};
+use puffgres_pg::{connect_postgres, format_lsn};
+use tracing::warn;
+
+use crate::config::ProjectConfig;
+
+/// How long to wait between polls when no changes are pending, unless the
+/// profile sets `poll_interval_ms` or a change notification comes first.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub async fn cmd_tail(
+    config: ProjectConfig,
+    mapping: Option<&str>,
+    table: Option<&str>,
+    slot: Option<&str>,
+    publication: &str,
+) -> Result<()> {
+    let mappings = config.load_migrations()?;
+
+    let filter = match (mapping, table) {
+        (Some(_), Some(_)) => bail!("Use either --mapping or --table, not both"),
+        (Some(name), None) => {
+            let mapping = mappings
+                .iter()
+                .find(|m| m.name == name)
+                .with_context(|| format!("Unknown mapping '{}'", name))?;
+            Some(mapping.source.clone())
+        }
+        (None, Some(table)) => Some(parse_table(table)),
+        (None, None) => None,
+    };
+
+    let client = connect_postgres(&config.replication_connection_string()?)
+        .await
+        .context("Failed to connect to replication server")?;
+    let mut tail = match slot {
+        Some(slot) => ChangeTail::peek(client, slot, publication).await?,
+        None => ChangeTail::temporary(client, publication).await?,
+    };
+
+    match tail.source() {
+        TailSource::Temporary(slot) => println!(
+            "Tailing publication '{}' via temporary slot '{}'. Press Ctrl-C to stop.\n",
+            publication, slot
+        ),
+        TailSource::Peek(slot) => println!(
+            "Peeking slot '{}' (changes are not consumed). Press Ctrl-C to stop.\n",
+            slot
+        ),
+    }
+
+    // Tables with the trigger from `puffgres setup --notify` announce their
+    // commits, so there's no need to wait out the interval after one
+    let mut listener = match ChangeListener::connect(&config.postgres_connection_string()?).await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            warn!(error = %e, "Can't listen for change notifications; polling on the interval");
+            None
+        }
+    };
+
+    let poll_interval = config.poll_interval().unwrap_or(POLL_INTERVAL);
+    let router = Router::new(mappings);
+    loop {
+        let events = tail.poll().await.context("Failed to read changes")?;
+        for event in &events {
+            if let Some(source) = &filter {
+                if !source.matches(&event.schema, &event.table) {
+                    continue;
+                }
+            }
+            print_event(event, &router);
+        }
+
+        if events.is_empty() {
+            match &mut listener {
+                Some(listener) => {
+                    listener.wait(poll_interval).await;
+                }
+                None => tokio::time::sleep(poll_interval).await,
+            }
+        }
+    }
+}
+
+/// Parse `schema.table`, defaulting to the `public` schema.
+fn parse_table(table: &str) -> Source {
+    match table.split_once('.') {
+        Some((schema, table)) => Source::new(schema, table),
+        None => Source::new("public", table),
+    }
+}
+
+fn print_event(event: &RowEvent, router: &Router) {
+    let op = match event.op {
+        Operation::Insert => "INSERT".green(),
+        Operation::Update => "UPDATE".yellow(),
+        Operation::Delete => "DELETE".red(),
+        Operation::Truncate => "TRUNCATE".red(),
+    };
+    let mut header = format!(
+        "{} {} {}.{}",
+        format_lsn(event.lsn).as_str().dimmed(),
+        op.bold(),
+        event.schema,
+        event.table
+    );
+    if let Some(txid) = event.txid {
+        header.push_str(&format!(" txid={}", txid));
+    }
+    if let Some(timestamp) = &event.timestamp {
+        header.push_str(&format!(" at {}", timestamp));
+    }
+    println!("{}", header);
+
+    let matched: Vec<_> = router
+        .route(event)
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect();
+    if matched.is_empty() {
+        println!("  {}", "no mapping matched".dimmed());
+    } else {
+        println!("  mappings: {}", matched.join(", ").as_str().cyan());
+    }
+
+    if let Some(old) = &event.old {
+        println!("  old: {}", format_row(old));
+    }
+    if let Some(new) = &event.new {
+        println!("  new: {}", format_row(new));
+    }
+    println!();
+}
+
+fn format_row(row: &RowMap) -> String {
+    // Sort columns so the same row always prints the same way
+    let sorted: std::collections::BTreeMap<_, _> = row.iter().collect();
+    serde_json::to_string(&sorted).unwrap_or_else(|e| format!("<unprintable: {}>", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_table() {
+        assert!(parse_table("app.users").matches("app", "users"));
+        assert!(parse_table("users").matches("public", "users"));
+    }
+}
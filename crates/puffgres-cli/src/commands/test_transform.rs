@@ -0,0 +1,302 @@
+//! `puffgres test-transform`: run a mapping's transform on sample rows and
+//! print what it would write, without writing anything.
+//!
+//! Rows come from the mapping's table (the first `--limit` in id order) or
+//! from a JSON fixture file holding an array of rows, each an object keyed
+//! by column name. Every row goes through id extraction, the membership
+//! predicate, the transform and the checks the runner makes before a write,
+//! so a transform can be tried out before `migrate` or `backfill` run it
+//! against production.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_core::{
+    extract_id, Action, DocumentValidator, Mapping, Operation, RowEvent, RowMap, Value,
+};
+use puffgres_pg::{BackfillConfig, BackfillScanner, IdRange};
+
+use crate::backfill::{
+    get_backfill_columns, get_backfill_filter, get_backfill_text_columns, is_member,
+};
+use crate::config::ProjectConfig;
+use crate::runner::{convert_doc_id_to_json, create_transformer};
+
+pub async fn cmd_test_transform(
+    config: ProjectConfig,
+    mapping_name: &str,
+    limit: u32,
+    fixture: Option<&Path>,
+) -> Result<()> {
+    let mappings = config.load_migrations()?;
+    let mapping = mappings
+        .iter()
+        .find(|m| m.name == mapping_name)
+        .with_context(|| format!("Mapping '{}' not found", mapping_name))?;
+
+    let events = match fixture {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let rows = parse_fixture(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            println!(
+                "Transforming {} row(s) from {} with '{}'...\n",
+                rows.len(),
+                path.display(),
+                mapping.name
+            );
+            rows.into_iter()
+                .map(|row| row_event(mapping, row))
+                .collect()
+        }
+        None => {
+            let mut scanner = BackfillScanner::new(BackfillConfig {
+                connection_string: config.postgres_connection_string()?,
+                schema: mapping.source.schema.clone(),
+                table: mapping.source.table.clone(),
+                id_columns: mapping.id.columns().to_vec(),
+                columns: get_backfill_columns(mapping),
+                batch_size: limit,
+                filter: get_backfill_filter(mapping),
+                snapshot: None,
+                text_columns: get_backfill_text_columns(mapping),
+                query: mapping.source.query.clone(),
+                range: IdRange::default(),
+                copy: false,
+            })
+            .await
+            .context("Failed to connect to Postgres")?;
+            println!(
+                "Transforming the first {} row(s) of {} with '{}'...\n",
+                limit,
+                mapping.source.describe(),
+                mapping.name
+            );
+            scanner.next_batch().await?
+        }
+    };
+
+    let outcome = test_transform(mapping, &events)?;
+    println!(
+        "{} row(s): {} upsert(s), {} patch(es), {} delete(s), {} skipped, {} error(s)",
+        events.len(),
+        outcome.upserts,
+        outcome.patches,
+        outcome.deletes,
+        outcome.skipped,
+        outcome.errors
+    );
+    if mapping.embed.is_some() {
+        println!(
+            "{} Embeddings aren't computed, so documents are shown without their vector.",
+            "!".yellow()
+        );
+    }
+    if outcome.errors > 0 {
+        bail!(
+            "{} row(s) would fail in mapping '{}'",
+            outcome.errors,
+            mapping.name
+        );
+    }
+    Ok(())
+}
+
+/// What the rows came to.
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    upserts: usize,
+    patches: usize,
+    deletes: usize,
+    skipped: usize,
+    errors: usize,
+}
+
+/// Run rows through the mapping and print the action each comes to.
+fn test_transform(mapping: &Mapping, events: &[RowEvent]) -> Result<Outcome> {
+    let mut outcome = Outcome::default();
+    let mut members = Vec::new();
+    for (index, event) in events.iter().enumerate() {
+        let id = match extract_id(event, &mapping.id) {
+            Ok(id) => id,
+            Err(e) => {
+                println!("{} row {}: {}\n", "✗".red(), index + 1, e);
+                outcome.errors += 1;
+                continue;
+            }
+        };
+        if !is_member(mapping, event) {
+            println!(
+                "{} id {}: not a member, skipped\n",
+                "-".dimmed(),
+                convert_doc_id_to_json(&id)
+            );
+            outcome.skipped += 1;
+            continue;
+        }
+        members.push((event, id));
+    }
+    if members.is_empty() {
+        return Ok(outcome);
+    }
+
+    let actions = create_transformer(mapping)
+        .transform_batch(&members)
+        .context("Transform failed")?;
+    let mut validator = DocumentValidator::new();
+    for ((event, id), action) in members.iter().zip(actions) {
+        let id = convert_doc_id_to_json(id);
+        let checked = mapping.namespace_for(event).and_then(|namespace| {
+            validator.check(&namespace, &mapping.id.id_type, &action)?;
+            let mut action = action;
+            mapping.limits.enforce(&mut action)?;
+            Ok((namespace, action))
+        });
+        let (namespace, action) = match checked {
+            Ok(checked) => checked,
+            Err(e) => {
+                println!("{} id {}: {}\n", "✗".red(), id, e);
+                outcome.errors += 1;
+                continue;
+            }
+        };
+
+        let label = match &action {
+            Action::Upsert { .. } => {
+                outcome.upserts += 1;
+                "UPSERT".green()
+            }
+            Action::Patch { .. } => {
+                outcome.patches += 1;
+                "PATCH".yellow()
+            }
+            Action::Delete { .. } => {
+                outcome.deletes += 1;
+                "DELETE".red()
+            }
+            Action::ClearNamespace => {
+                outcome.deletes += 1;
+                "CLEAR".red()
+            }
+            Action::Skip => {
+                outcome.skipped += 1;
+                "SKIP".dimmed()
+            }
+            Action::Error { .. } => {
+                outcome.errors += 1;
+                "ERROR".red()
+            }
+        };
+        println!("{} {} id {}", label.bold(), namespace, id);
+        if !matches!(action, Action::Skip) {
+            println!("{}", format_action(&action));
+        }
+        println!();
+    }
+    Ok(outcome)
+}
+
+/// An action as indented JSON. Going through a JSON value sorts the
+/// attributes, so the same row always prints the same way.
+fn format_action(action: &Action) -> String {
+    serde_json::to_value(action)
+        .and_then(|json| serde_json::to_string_pretty(&json))
+        .unwrap_or_else(|e| format!("<unprintable: {}>", e))
+        .lines()
+        .map(|line| format!("  {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parse a fixture: a JSON array of rows, each an object keyed by column.
+fn parse_fixture(content: &str) -> Result<Vec<RowMap>> {
+    let json: serde_json::Value = serde_json::from_str(content)?;
+    let serde_json::Value::Array(rows) = json else {
+        bail!("expected an array of rows");
+    };
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, row)| match row {
+            serde_json::Value::Object(columns) => Ok(columns
+                .into_iter()
+                .map(|(column, value)| (column, Value::from(value)))
+                .collect()),
+            _ => bail!("row {} isn't an object of columns", index + 1),
+        })
+        .collect()
+}
+
+/// A fixture row as an insert into the mapping's table.
+fn row_event(mapping: &Mapping, row: RowMap) -> RowEvent {
+    RowEvent {
+        op: Operation::Insert,
+        schema: mapping.source.schema.clone(),
+        table: mapping.source.table.clone(),
+        new: Some(row),
+        old: None,
+        lsn: 0,
+        txid: None,
+        timestamp: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_core::IdType;
+
+    fn mapping() -> Mapping {
+        Mapping::builder("users")
+            .namespace("users")
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .membership_dsl("status = 'active'")
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_fixture() {
+        let rows = parse_fixture(r#"[{"id": 1, "name": "a", "tags": ["x"]}]"#).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("id"), Some(&Value::Int(1)));
+        assert_eq!(
+            rows[0].get("tags"),
+            Some(&Value::Array(vec![Value::String("x".into())]))
+        );
+
+        assert!(parse_fixture(r#"{"id": 1}"#).is_err());
+        assert!(parse_fixture("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_transform_counts_outcomes() {
+        let mapping = mapping();
+        let rows = parse_fixture(
+            r#"[
+                {"id": 1, "status": "active", "name": "a"},
+                {"id": 2, "status": "banned", "name": "b"},
+                {"status": "active", "name": "c"}
+            ]"#,
+        )
+        .unwrap();
+        let events: Vec<_> = rows
+            .into_iter()
+            .map(|row| row_event(&mapping, row))
+            .collect();
+
+        let outcome = test_transform(&mapping, &events).unwrap();
+        assert_eq!(
+            outcome,
+            Outcome {
+                upserts: 1,
+                skipped: 1,
+                errors: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
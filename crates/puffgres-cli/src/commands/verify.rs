@@ -0,0 +1,164 @@
+//! `puffgres backfill --verify`: check a finished backfill against its table.
+//!
+//! Counts the rows the mapping selects and the documents in its namespace,
+//! then compares a random sample of rows attribute by attribute the way
+//! `puffgres check` does. The outcome is recorded on the mapping's row of
+//! `__puffgres_backfill`, so it can be looked up after the fact.
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use puffgres_core::{Mapping, MembershipConfig};
+use puffgres_pg::{
+    BackfillConfig, BackfillScanner, BackfillVerification, IdRange, PostgresStateStore,
+};
+
+use super::check::{compare_documents, expected_documents};
+use crate::backfill::{get_backfill_columns, get_backfill_filter, get_backfill_text_columns};
+use crate::config::ProjectConfig;
+use crate::env::get_max_retries;
+use crate::runner::create_transformer;
+use crate::tp::TurbopufferClient;
+
+/// Verify a mapping's backfill, sampling `sample` rows, and record the
+/// outcome. Fails when the counts or any sampled document don't match.
+pub async fn verify_backfill(
+    config: &ProjectConfig,
+    store: &PostgresStateStore,
+    mapping: &Mapping,
+    sample: u32,
+) -> Result<()> {
+    let scanner = BackfillScanner::new(BackfillConfig {
+        connection_string: config.postgres_connection_string()?,
+        schema: mapping.source.schema.clone(),
+        table: mapping.source.table.clone(),
+        id_columns: mapping.id.columns().to_vec(),
+        columns: get_backfill_columns(mapping),
+        batch_size: sample,
+        filter: get_backfill_filter(mapping),
+        snapshot: None,
+        text_columns: get_backfill_text_columns(mapping),
+        query: mapping.source.query.clone(),
+        range: IdRange::default(),
+        copy: false,
+    })
+    .await
+    .context("Failed to connect to Postgres")?;
+    let countable = counts_comparable(mapping, scanner.is_filtered());
+    let tp = TurbopufferClient::new(config.turbopuffer_api_key()?, get_max_retries());
+
+    println!("\nVerifying the backfill of '{}'...", mapping.name);
+
+    let (source_rows, documents) = if countable {
+        let source_rows = scanner
+            .count_rows()
+            .await
+            .context("Failed to count source rows")?;
+        let documents = tp.export(&mapping.namespace, false).await?.len() as i64;
+        let matched = if source_rows == documents {
+            "✓".green()
+        } else {
+            "✗".red()
+        };
+        println!(
+            "  {} {} row(s) in {}, {} document(s) in '{}'",
+            matched,
+            source_rows,
+            mapping.source.describe(),
+            documents,
+            mapping.namespace
+        );
+        (Some(source_rows), Some(documents))
+    } else {
+        println!(
+            "  {} Rows and documents aren't counted: the mapping's namespaces or predicate can't be counted in Postgres.",
+            "!".yellow()
+        );
+        (None, None)
+    };
+
+    let rows = scanner.sample(sample).await?;
+    let expected = expected_documents(mapping, &create_transformer(mapping), &rows)?;
+    let (sampled, findings) = compare_documents(&tp, &expected).await?;
+    for finding in &findings {
+        println!("  {} {}", "✗".red(), finding);
+    }
+    println!(
+        "  {} of {} sampled document(s) match their rows",
+        sampled - findings.len(),
+        sampled
+    );
+
+    let counts_match = source_rows == documents;
+    let passed = counts_match && findings.is_empty();
+    store
+        .save_backfill_verification(&BackfillVerification {
+            mapping_name: mapping.name.clone(),
+            status: if passed { "passed" } else { "failed" }.to_string(),
+            source_rows,
+            documents,
+            sampled: sampled as i64,
+            mismatches: findings.len() as i64,
+        })
+        .await
+        .context("Failed to record the verification")?;
+
+    if !counts_match {
+        bail!(
+            "Backfill of '{}' failed verification: {} row(s) but {} document(s)",
+            mapping.name,
+            source_rows.unwrap_or_default(),
+            documents.unwrap_or_default()
+        );
+    }
+    if !findings.is_empty() {
+        bail!(
+            "Backfill of '{}' failed verification: {} of {} sampled document(s) don't match their rows",
+            mapping.name,
+            findings.len(),
+            sampled
+        );
+    }
+    println!("{}", "Backfill verified.".green());
+    Ok(())
+}
+
+/// Whether the rows the scanner counts should number the documents in the
+/// mapping's namespace. Not when rows spread over several namespaces, or
+/// when the scanner couldn't run the predicate in Postgres (`filtered` is
+/// false) and the count would include rows outside the mapping.
+fn counts_comparable(mapping: &Mapping, filtered: bool) -> bool {
+    let filtered = filtered || !matches!(mapping.membership, MembershipConfig::Dsl(_));
+    filtered && !mapping.is_partitioned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_core::{IdType, Literal, Predicate};
+
+    fn mapping(namespace: &str, predicate: Option<Predicate>) -> Mapping {
+        let membership = predicate.map_or(MembershipConfig::All, MembershipConfig::Dsl);
+        Mapping::builder("users")
+            .namespace(namespace)
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .membership(membership)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_counts_comparable() {
+        assert!(counts_comparable(&mapping("users", None), false));
+
+        let active = Predicate::Eq("status".into(), Literal::String("active".into()));
+        let active = mapping("users", Some(active));
+        assert!(counts_comparable(&active, true));
+
+        // Without the predicate in Postgres, the count includes every row
+        assert!(!counts_comparable(&active, false));
+
+        let partitioned = mapping("users_{tenant_id}", None);
+        assert!(!counts_comparable(&partitioned, false));
+    }
+}
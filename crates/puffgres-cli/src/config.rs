@@ -1,14 +1,33 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::Deserialize;
 
-use puffgres_config::MigrationConfig;
-use puffgres_core::Mapping;
-use puffgres_pg::LocalMigration;
+use puffgres_config::{check_env_vars, check_keys, template_sources, KeySchema, MigrationConfig};
+use puffgres_core::{shadow_name, IdType, Mapping, Sink};
+use puffgres_pg::{LocalMigration, PostgresStateStore};
 
-use crate::env::warn_if_pooler_url;
+use crate::env::{get_id_obfuscator, warn_if_pooler_url};
+
+/// Directory containing shared migration templates (referenced via `extends`).
+const TEMPLATES_DIR: &str = "templates";
+
+/// Optional project file holding per-environment profiles.
+pub const PROJECT_FILE: &str = "puffgres.toml";
+
+/// Replication slot used when neither `--slot` nor the profile names one.
+pub const DEFAULT_SLOT: &str = "puffgres";
+
+/// Parse a migration file, resolving any `extends` template from `templates/`.
+pub fn parse_migration(content: &str) -> Result<MigrationConfig> {
+    Ok(MigrationConfig::parse_with_templates(
+        content,
+        Path::new(TEMPLATES_DIR),
+    )?)
+}
 
 /// Project configuration from puffgres.toml
 #[derive(Debug, Deserialize)]
@@ -17,13 +36,147 @@ pub struct ProjectConfig {
     pub turbopuffer: TurbopufferConfig,
     /// Optional embedding providers configuration.
     #[serde(default)]
-    #[allow(dead_code)]
     pub providers: ProvidersConfig,
+    /// Overrides for the environment selected with `--env`.
+    #[serde(default)]
+    pub profile: EnvProfile,
+}
+
+/// Overrides for one environment, from an `[env.<name>]` table in
+/// puffgres.toml:
+///
+/// ```toml
+/// [env.staging]
+/// slot = "puffgres_staging"
+/// base_namespace = "STAGING"
+/// poll_interval_ms = 500
+/// database_url_var = "STAGING_DATABASE_URL"
+/// ```
+///
+/// The profile is chosen by the same `--env` name that loads `.env.{env}`,
+/// so values may reference variables from that file as `${VAR}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvProfile {
+    /// Replication slot, unless `--slot` is given.
+    pub slot: Option<String>,
+    /// Namespace prefix, instead of `PUFFGRES_BASE_NAMESPACE`.
+    pub base_namespace: Option<String>,
+    /// How often `puffgres tail` polls for changes and `puffgres run`
+    /// reports its position to Postgres.
+    pub poll_interval_ms: Option<u64>,
+    /// Variable holding the Postgres connection string, instead of
+    /// `DATABASE_URL`.
+    pub database_url_var: Option<String>,
+}
+
+/// The parts of puffgres.toml the CLI reads.
+#[derive(Debug, Default, Deserialize)]
+struct ProjectFile {
+    #[serde(default)]
+    env: BTreeMap<String, EnvProfile>,
+    #[serde(default)]
+    providers: ProvidersConfig,
+}
+
+/// The keys of puffgres.toml. Kept in step with [`ProjectFile`].
+const PROJECT_KEYS: KeySchema = KeySchema::Table(&[
+    ("env", KeySchema::Map(&PROFILE_KEYS)),
+    (
+        "providers",
+        KeySchema::Table(&[("embeddings", EMBEDDING_PROVIDER_KEYS)]),
+    ),
+]);
+
+const PROFILE_KEYS: KeySchema = KeySchema::Table(&[
+    ("slot", KeySchema::Any),
+    ("base_namespace", KeySchema::Any),
+    ("poll_interval_ms", KeySchema::Any),
+    ("database_url_var", KeySchema::Any),
+]);
+
+const EMBEDDING_PROVIDER_KEYS: KeySchema = KeySchema::Table(&[
+    ("type", KeySchema::Any),
+    ("model", KeySchema::Any),
+    ("api_key", KeySchema::Any),
+    ("url", KeySchema::Any),
+    ("batch_size", KeySchema::Any),
+]);
+
+/// Parse puffgres.toml, rejecting unknown keys and malformed `${VAR}`
+/// references.
+fn parse_project_file(content: &str) -> Result<ProjectFile> {
+    let table: toml::Table = toml::from_str(content)?;
+    check_keys(content, &table, &PROJECT_KEYS)?;
+    let file: ProjectFile = toml::from_str(content)?;
+    for (name, profile) in &file.env {
+        for value in [&profile.slot, &profile.base_namespace]
+            .into_iter()
+            .flatten()
+        {
+            check_env_vars(value).map_err(|e| anyhow!("[env.{}]: {}", name, e))?;
+        }
+    }
+    if let Some(embeddings) = &file.providers.embeddings {
+        check_env_vars(&embeddings.api_key)
+            .map_err(|e| anyhow!("[providers.embeddings]: {}", e))?;
+    }
+    Ok(file)
+}
+
+/// Load the profile for `env_name` from puffgres.toml in `dir`.
+///
+/// Without `--env`, or without a puffgres.toml, there are no overrides. An
+/// `--env` that matches no profile is only an error when the file defines
+/// some, since `--env` on its own still selects `.env.{env}`.
+pub fn load_profile(dir: &Path, env_name: Option<&str>) -> Result<EnvProfile> {
+    let Some(env_name) = env_name else {
+        return Ok(EnvProfile::default());
+    };
+    let path = dir.join(PROJECT_FILE);
+    if !path.exists() {
+        return Ok(EnvProfile::default());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_profile(&content, env_name).with_context(|| format!("Invalid {}", path.display()))
+}
+
+/// Load the `[providers]` tables from puffgres.toml in `dir`, if it exists.
+pub fn load_providers(dir: &Path) -> Result<ProvidersConfig> {
+    let path = dir.join(PROJECT_FILE);
+    if !path.exists() {
+        return Ok(ProvidersConfig::default());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file =
+        parse_project_file(&content).with_context(|| format!("Invalid {}", path.display()))?;
+    Ok(file.providers)
+}
+
+fn parse_profile(content: &str, env_name: &str) -> Result<EnvProfile> {
+    let mut file = parse_project_file(content)?;
+    if file.env.is_empty() {
+        return Ok(EnvProfile::default());
+    }
+    match file.env.remove(env_name) {
+        Some(profile) => Ok(profile),
+        None => bail!(
+            "No [env.{}] profile. Defined profiles: {}",
+            env_name,
+            file.env.keys().cloned().collect::<Vec<_>>().join(", ")
+        ),
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PostgresConfig {
     pub connection_string: String,
+    /// Optional server to stream changes from, e.g. a Postgres 16+ standby.
+    /// State tables and publications always live on `connection_string`.
+    #[serde(default)]
+    pub replication_connection_string: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,16 +189,21 @@ pub struct TurbopufferConfig {
 }
 
 /// Configuration for external providers (embeddings, etc.)
-#[derive(Debug, Default, Deserialize)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct ProvidersConfig {
     /// Embedding provider configuration.
     pub embeddings: Option<EmbeddingProviderConfig>,
 }
 
-/// Embedding provider configuration.
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
+/// Embedding provider configuration, from `[providers.embeddings]`:
+///
+/// ```toml
+/// [providers.embeddings]
+/// type = "together"
+/// model = "BAAI/bge-base-en-v1.5"
+/// api_key = "${TOGETHER_API_KEY}"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
 pub struct EmbeddingProviderConfig {
     /// Provider type: "together", "openai", etc.
     #[serde(rename = "type")]
@@ -54,9 +212,41 @@ pub struct EmbeddingProviderConfig {
     pub model: String,
     /// API key (supports ${ENV_VAR} syntax).
     pub api_key: String,
+    /// Embeddings endpoint, for OpenAI-compatible providers other than
+    /// the built-in ones.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Texts sent per request.
+    #[serde(default)]
+    pub batch_size: Option<usize>,
 }
 
 impl ProjectConfig {
+    /// The project's configuration, read from environment variables that the
+    /// profile may rename.
+    pub fn from_env(profile: &EnvProfile, providers: &ProvidersConfig) -> Self {
+        let database_url_var = profile
+            .database_url_var
+            .as_deref()
+            .unwrap_or("DATABASE_URL");
+        let base_namespace = profile
+            .base_namespace
+            .clone()
+            .unwrap_or_else(|| "${PUFFGRES_BASE_NAMESPACE}".to_string());
+        ProjectConfig {
+            postgres: PostgresConfig {
+                connection_string: format!("${{{}}}", database_url_var),
+                replication_connection_string: Some("${REPLICATION_DATABASE_URL}".to_string()),
+            },
+            turbopuffer: TurbopufferConfig {
+                api_key: "${TURBOPUFFER_API_KEY}".to_string(),
+                base_namespace: Some(base_namespace),
+            },
+            providers: providers.clone(),
+            profile: profile.clone(),
+        }
+    }
+
     /// Resolve environment variables in a string.
     /// Supports ${VAR_NAME} syntax.
     pub fn resolve_env(&self, s: &str) -> String {
@@ -84,17 +274,63 @@ impl ProjectConfig {
     /// Get the resolved Postgres connection string.
     /// Returns an error if required environment variables are not set.
     pub fn postgres_connection_string(&self) -> Result<String> {
-        let url = self.resolve_env_required(&self.postgres.connection_string, "DATABASE_URL")?;
+        let hint_var = self
+            .profile
+            .database_url_var
+            .as_deref()
+            .unwrap_or("DATABASE_URL");
+        let url = self.resolve_env_required(&self.postgres.connection_string, hint_var)?;
         warn_if_pooler_url(&url);
         Ok(url)
     }
 
+    /// Get the resolved connection string for the replication stream.
+    /// Falls back to the main connection string when no replication server is configured.
+    pub fn replication_connection_string(&self) -> Result<String> {
+        let url = self
+            .postgres
+            .replication_connection_string
+            .as_ref()
+            .map(|s| self.resolve_env(s))
+            .filter(|s| !s.is_empty());
+
+        match url {
+            Some(url) => {
+                warn_if_pooler_url(&url);
+                Ok(url)
+            }
+            None => self.postgres_connection_string(),
+        }
+    }
+
     /// Get the resolved Turbopuffer API key.
     /// Returns an error if required environment variables are not set.
     pub fn turbopuffer_api_key(&self) -> Result<String> {
         self.resolve_env_required(&self.turbopuffer.api_key, "TURBOPUFFER_API_KEY")
     }
 
+    /// Get the resolved API key of the embedding provider.
+    pub fn embedding_api_key(&self, provider: &EmbeddingProviderConfig) -> Result<String> {
+        let hint_var = provider
+            .api_key
+            .strip_prefix("${")
+            .and_then(|s| s.strip_suffix('}'))
+            .unwrap_or("TOGETHER_API_KEY");
+        self.resolve_env_required(&provider.api_key, hint_var)
+    }
+
+    /// Get the resolved API key of a sink: its own, or the project's.
+    pub fn sink_api_key(&self, sink: &Sink) -> Result<String> {
+        let Some(api_key) = &sink.api_key else {
+            return self.turbopuffer_api_key();
+        };
+        let hint_var = api_key
+            .strip_prefix("${")
+            .and_then(|s| s.strip_suffix('}'))
+            .unwrap_or("TURBOPUFFER_API_KEY");
+        self.resolve_env_required(api_key, hint_var)
+    }
+
     /// Resolve environment variables in a string, returning an error if any are missing.
     fn resolve_env_required(&self, s: &str, hint_var: &str) -> Result<String> {
         let mut result = s.to_string();
@@ -158,6 +394,17 @@ impl ProjectConfig {
             .filter(|ns| !ns.is_empty())
     }
 
+    /// The replication slot to use: `--slot` if given, then the profile's.
+    pub fn slot_name(&self, flag: Option<String>) -> String {
+        flag.or_else(|| self.profile.slot.clone())
+            .unwrap_or_else(|| DEFAULT_SLOT.to_string())
+    }
+
+    /// The profile's poll interval, if it sets one.
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.profile.poll_interval_ms.map(Duration::from_millis)
+    }
+
     /// Apply the base namespace prefix to a namespace name.
     pub fn apply_namespace_prefix(&self, namespace: &str) -> String {
         if let Some(prefix) = self.base_namespace() {
@@ -167,9 +414,31 @@ impl ProjectConfig {
         }
     }
 
-    /// Load all migrations from the migrations directory.
-    /// Applies the base namespace prefix if configured.
+    /// Load all migrations from the migrations directory, keeping the latest
+    /// version of each mapping. A shadow revision runs beside the version
+    /// before it, as if it hadn't been promoted; see
+    /// [`Self::load_active_migrations`].
+    /// Applies the base namespace prefix if configured, to sinks too, and the
+    /// id secret to mappings with obfuscated ids.
     pub fn load_migrations(&self) -> Result<Vec<Mapping>> {
+        self.load_migrations_promoted(&BTreeSet::new())
+    }
+
+    /// Load the migrations as they run against `store`, where shadow
+    /// revisions that were promoted replace the versions before them.
+    pub async fn load_active_migrations(&self, store: &PostgresStateStore) -> Result<Vec<Mapping>> {
+        let promoted = store
+            .get_applied_migrations()
+            .await?
+            .into_iter()
+            .filter_map(|m| Some((m.mapping_name, m.superseded_by? as u32)))
+            .collect();
+        self.load_migrations_promoted(&promoted)
+    }
+
+    /// Load all migrations, given the (mapping, version) revisions that
+    /// superseded earlier ones.
+    fn load_migrations_promoted(&self, promoted: &BTreeSet<(String, u32)>) -> Result<Vec<Mapping>> {
         let migrations_dir = Path::new("migrations");
 
         if !migrations_dir.exists() {
@@ -186,7 +455,7 @@ impl ProjectConfig {
                 let content = fs::read_to_string(&path)
                     .with_context(|| format!("Failed to read migration: {}", path.display()))?;
 
-                let config = MigrationConfig::parse(&content)
+                let config = parse_migration(&content)
                     .with_context(|| format!("Failed to parse migration: {}", path.display()))?;
 
                 let mut mapping = puffgres_config::to_mapping(&config)
@@ -194,15 +463,24 @@ impl ProjectConfig {
 
                 // Apply base namespace prefix if configured
                 mapping.namespace = self.apply_namespace_prefix(&mapping.namespace);
+                // A sink that interpolates {namespace} inherits the prefix
+                for sink in &mut mapping.sinks {
+                    if !sink.namespace.contains("{namespace}") {
+                        sink.namespace = self.apply_namespace_prefix(&sink.namespace);
+                    }
+                }
+
+                if let IdType::ObfuscatedUint(obfuscator) = &mut mapping.id.id_type {
+                    *obfuscator = Some(get_id_obfuscator().with_context(|| {
+                        format!("Mapping '{}' uses obfuscated_uint ids", mapping.name)
+                    })?);
+                }
 
-                mappings.push(mapping);
+                mappings.push((mapping, config.shadow));
             }
         }
 
-        // Sort by version
-        mappings.sort_by_key(|m| m.version);
-
-        Ok(mappings)
+        Ok(current_mappings(mappings, promoted))
     }
 
     /// Load all local migration files with their content for hashing.
@@ -223,13 +501,18 @@ impl ProjectConfig {
                 let content = fs::read_to_string(&path)
                     .with_context(|| format!("Failed to read migration: {}", path.display()))?;
 
-                let config = MigrationConfig::parse(&content)
+                let config = parse_migration(&content)
+                    .with_context(|| format!("Failed to parse migration: {}", path.display()))?;
+
+                let templates = template_sources(&content, Path::new(TEMPLATES_DIR))
                     .with_context(|| format!("Failed to parse migration: {}", path.display()))?;
 
                 migrations.push(LocalMigration {
                     version: config.version as i32,
                     mapping_name: config.mapping_name.clone(),
                     content,
+                    shadow: config.shadow,
+                    templates,
                 });
             }
         }
@@ -241,6 +524,38 @@ impl ProjectConfig {
     }
 }
 
+/// Keep the latest version of each mapping, given whether each is a shadow
+/// revision. Shadow revisions write to their shadow namespaces, and one that
+/// wasn't `promoted` runs beside the version before it under its shadow name,
+/// so the two keep separate checkpoints and backfill progress.
+fn current_mappings(
+    mut mappings: Vec<(Mapping, bool)>,
+    promoted: &BTreeSet<(String, u32)>,
+) -> Vec<Mapping> {
+    mappings.sort_by_key(|(m, _)| m.version);
+
+    // Per mapping name, whether the version before a shadow is still wanted
+    let mut seen: BTreeMap<String, bool> = BTreeMap::new();
+    let mut current = Vec::new();
+    for (mut mapping, shadow) in mappings.into_iter().rev() {
+        let beside = match seen.get(&mapping.name) {
+            None => shadow && !promoted.contains(&(mapping.name.clone(), mapping.version)),
+            Some(true) => false,
+            Some(false) => continue,
+        };
+        seen.insert(mapping.name.clone(), beside);
+        if shadow {
+            mapping.write_to_shadow();
+        }
+        if beside {
+            mapping.name = shadow_name(&mapping.name, mapping.version);
+        }
+        current.push(mapping);
+    }
+    current.reverse();
+    current
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -252,12 +567,14 @@ mod tests {
         let config = ProjectConfig {
             postgres: PostgresConfig {
                 connection_string: "postgres://${TEST_VAR}".to_string(),
+                replication_connection_string: None,
             },
             turbopuffer: TurbopufferConfig {
                 api_key: "key".to_string(),
                 base_namespace: None,
             },
             providers: ProvidersConfig::default(),
+            profile: EnvProfile::default(),
         };
 
         assert_eq!(config.resolve_env("${TEST_VAR}"), "hello");
@@ -267,4 +584,154 @@ mod tests {
         );
         assert_eq!(config.resolve_env("no_vars"), "no_vars");
     }
+
+    #[test]
+    fn test_replication_connection_string_falls_back_to_primary() {
+        std::env::set_var("TEST_PRIMARY_URL", "postgres://primary/db");
+        std::env::set_var("TEST_STANDBY_URL", "postgres://standby/db");
+
+        let mut config = ProjectConfig {
+            postgres: PostgresConfig {
+                connection_string: "${TEST_PRIMARY_URL}".to_string(),
+                replication_connection_string: Some("${TEST_UNSET_STANDBY_URL}".to_string()),
+            },
+            turbopuffer: TurbopufferConfig {
+                api_key: "key".to_string(),
+                base_namespace: None,
+            },
+            providers: ProvidersConfig::default(),
+            profile: EnvProfile::default(),
+        };
+        assert_eq!(
+            config.replication_connection_string().unwrap(),
+            "postgres://primary/db"
+        );
+
+        config.postgres.replication_connection_string = Some("${TEST_STANDBY_URL}".to_string());
+        assert_eq!(
+            config.replication_connection_string().unwrap(),
+            "postgres://standby/db"
+        );
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let content = r#"
+[env.staging]
+slot = "puffgres_staging"
+base_namespace = "STAGING"
+poll_interval_ms = 500
+
+[env.production]
+database_url_var = "PROD_DATABASE_URL"
+"#;
+        let staging = parse_profile(content, "staging").unwrap();
+        assert_eq!(staging.slot.as_deref(), Some("puffgres_staging"));
+        assert_eq!(staging.base_namespace.as_deref(), Some("STAGING"));
+        assert_eq!(staging.poll_interval_ms, Some(500));
+        assert!(staging.database_url_var.is_none());
+
+        let production = parse_profile(content, "production").unwrap();
+        assert_eq!(
+            production.database_url_var.as_deref(),
+            Some("PROD_DATABASE_URL")
+        );
+
+        let err = parse_profile(content, "stagign").unwrap_err();
+        assert!(err.to_string().contains("production, staging"));
+
+        // A file without profiles leaves --env to the .env files
+        assert!(parse_profile("", "staging").unwrap().slot.is_none());
+        let err = parse_profile("[env.staging]\nslots = \"x\"\n", "staging").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown key 'env.staging.slots' at line 2, column 1; did you mean 'slot'?"
+        );
+        assert!(parse_profile("[env.staging]\nslot = \"${SLOT\"\n", "staging").is_err());
+    }
+
+    #[test]
+    fn test_load_providers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(load_providers(dir.path()).unwrap().embeddings.is_none());
+
+        fs::write(
+            dir.path().join(PROJECT_FILE),
+            r#"
+[env.staging]
+slot = "puffgres_staging"
+
+[providers.embeddings]
+type = "together"
+model = "BAAI/bge-base-en-v1.5"
+api_key = "${TOGETHER_API_KEY}"
+batch_size = 32
+"#,
+        )
+        .unwrap();
+        let embeddings = load_providers(dir.path()).unwrap().embeddings.unwrap();
+        assert_eq!(embeddings.provider_type, "together");
+        assert_eq!(embeddings.model, "BAAI/bge-base-en-v1.5");
+        assert_eq!(embeddings.batch_size, Some(32));
+        assert!(embeddings.url.is_none());
+    }
+
+    #[test]
+    fn test_slot_name_prefers_flag_then_profile() {
+        let mut config = ProjectConfig {
+            postgres: PostgresConfig {
+                connection_string: "postgres://localhost/db".to_string(),
+                replication_connection_string: None,
+            },
+            turbopuffer: TurbopufferConfig {
+                api_key: "key".to_string(),
+                base_namespace: None,
+            },
+            providers: ProvidersConfig::default(),
+            profile: EnvProfile::default(),
+        };
+        assert_eq!(config.slot_name(None), "puffgres");
+
+        config.profile.slot = Some("puffgres_staging".to_string());
+        assert_eq!(config.slot_name(None), "puffgres_staging");
+        assert_eq!(config.slot_name(Some("other".to_string())), "other");
+    }
+
+    #[test]
+    fn test_current_mappings_runs_shadow_beside_previous() {
+        let mapping = |version| {
+            Mapping::builder("users")
+                .version(version)
+                .namespace("users")
+                .source("public", "users")
+                .id("id", IdType::Uint)
+                .build()
+                .unwrap()
+        };
+        let current = |loaded, promoted: &BTreeSet<(String, u32)>| {
+            current_mappings(loaded, promoted)
+                .into_iter()
+                .map(|m| (m.name, m.namespace))
+                .collect::<Vec<_>>()
+        };
+        let pair = |name: &str, namespace: &str| (name.to_string(), namespace.to_string());
+
+        // Until promoted, v2 is built beside v1 and tracked separately
+        let loaded = || vec![(mapping(2), true), (mapping(1), false)];
+        assert_eq!(
+            current(loaded(), &BTreeSet::new()),
+            vec![pair("users", "users"), pair("users__v2", "users__v2")]
+        );
+
+        let promoted = BTreeSet::from([("users".to_string(), 2)]);
+        assert_eq!(
+            current(loaded(), &promoted),
+            vec![pair("users", "users__v2")]
+        );
+
+        // A later revision replaces the promoted shadow as usual
+        let mut loaded = loaded();
+        loaded.push((mapping(3), false));
+        assert_eq!(current(loaded, &promoted), vec![pair("users", "users")]);
+    }
 }
@@ -0,0 +1,195 @@
+//! Diffing the documents a mapping's rows should have against a namespace.
+//!
+//! A [`NamespaceDiff`] holds an export of a namespace. Each row's document is
+//! compared with the one turbopuffer holds: by content hash when the mapping
+//! stores one (`versioning = "content_hash"`), attribute by attribute
+//! otherwise. Documents no row accounted for are orphans.
+
+use std::collections::HashMap;
+
+use puffgres_core::{Document, Value, DOC_HASH_ATTRIBUTE};
+
+use crate::runner::convert_value_to_json;
+
+/// How a row's document compares with the namespace's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentDiff {
+    /// Turbopuffer has no document with the row's id.
+    Missing,
+    /// Turbopuffer's document differs from what the row transforms to.
+    Stale,
+    /// Turbopuffer's document matches the row.
+    Unchanged,
+}
+
+/// The documents of one namespace that no row has accounted for yet.
+#[derive(Debug, Default)]
+pub struct NamespaceDiff {
+    remaining: HashMap<String, rs_puff::Row>,
+}
+
+impl NamespaceDiff {
+    /// Start from an export of the namespace.
+    pub fn new(rows: Vec<rs_puff::Row>) -> Self {
+        let remaining = rows
+            .into_iter()
+            .filter_map(|row| Some((row.get("id")?.to_string(), row)))
+            .collect();
+        Self { remaining }
+    }
+
+    /// Compare a row's document with the namespace's, and account for its id.
+    pub fn compare(&mut self, id: &serde_json::Value, doc: &Document) -> DocumentDiff {
+        match self.remaining.remove(&id.to_string()) {
+            None => DocumentDiff::Missing,
+            Some(row) if document_matches(doc, &row) => DocumentDiff::Unchanged,
+            Some(_) => DocumentDiff::Stale,
+        }
+    }
+
+    /// Account for an id without comparing it, for rows whose document can't
+    /// be worked out (a failed transform, a patch) but shouldn't be deleted.
+    pub fn keep(&mut self, id: &serde_json::Value) {
+        self.remaining.remove(&id.to_string());
+    }
+
+    /// Ids of the documents no row accounted for, sorted by their JSON text.
+    pub fn into_orphans(self) -> Vec<serde_json::Value> {
+        let mut orphans: Vec<(String, serde_json::Value)> = self
+            .remaining
+            .into_iter()
+            .filter_map(|(key, mut row)| Some((key, row.remove("id")?)))
+            .collect();
+        orphans.sort_by(|a, b| a.0.cmp(&b.0));
+        orphans.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+/// Whether `row` holds `doc`. A document with a content hash matches when the
+/// hashes do, since the hash covers every attribute the transform wrote.
+fn document_matches(doc: &Document, row: &rs_puff::Row) -> bool {
+    match doc.get(DOC_HASH_ATTRIBUTE) {
+        Some(Value::String(hash)) => {
+            row.get(DOC_HASH_ATTRIBUTE).and_then(|h| h.as_str()) == Some(hash.as_str())
+        }
+        _ => differing_attributes(doc, row).is_empty(),
+    }
+}
+
+/// Attributes of `doc` that `row` lacks or holds a different value for.
+pub(crate) fn differing_attributes(doc: &Document, row: &rs_puff::Row) -> Vec<String> {
+    let mut differing: Vec<String> = doc
+        .iter()
+        .filter(|(name, value)| {
+            let expected = convert_value_to_json(value);
+            match row.get(name.as_str()) {
+                Some(actual) => !json_matches(&expected, actual),
+                // Null attributes aren't stored
+                None => !expected.is_null(),
+            }
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    differing.sort();
+    differing
+}
+
+/// Compare JSON values, treating numbers as equal when their values are,
+/// since turbopuffer may return `1` for `1.0`.
+fn json_matches(expected: &serde_json::Value, actual: &serde_json::Value) -> bool {
+    use serde_json::Value as Json;
+    match (expected, actual) {
+        (Json::Number(a), Json::Number(b)) => a.as_f64() == b.as_f64(),
+        (Json::Array(a), Json::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_matches(a, b))
+        }
+        (Json::Object(a), Json::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k).is_some_and(|w| json_matches(v, w)))
+        }
+        _ => expected == actual,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(pairs: &[(&str, serde_json::Value)]) -> rs_puff::Row {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_differing_attributes() {
+        let doc: Document = [
+            ("title".to_string(), Value::String("Hello".into())),
+            ("views".to_string(), Value::Float(3.0)),
+            ("deleted_at".to_string(), Value::Null),
+            ("tags".to_string(), Value::Array(vec![Value::Int(1)])),
+        ]
+        .into_iter()
+        .collect();
+
+        let row = row(&[
+            ("id", json!(1)),
+            ("title", json!("Hello")),
+            ("views", json!(3)),
+            ("tags", json!([1.0])),
+            ("__doc_hash", json!("abc")),
+        ]);
+        assert!(differing_attributes(&doc, &row).is_empty());
+
+        let mut stale = row.clone();
+        stale.insert("title".to_string(), json!("Goodbye"));
+        stale.remove("views");
+        assert_eq!(differing_attributes(&doc, &stale), vec!["title", "views"]);
+    }
+
+    #[test]
+    fn test_namespace_diff() {
+        let mut diff = NamespaceDiff::new(vec![
+            row(&[("id", json!(1)), ("title", json!("a"))]),
+            row(&[("id", json!(2)), ("title", json!("old"))]),
+            row(&[("id", json!(3)), ("title", json!("c"))]),
+            row(&[("id", json!(4)), ("title", json!("d"))]),
+            row(&[("id", json!(5)), ("title", json!("e"))]),
+        ]);
+        let doc = |title: &str| -> Document {
+            [("title".to_string(), Value::String(title.into()))]
+                .into_iter()
+                .collect()
+        };
+
+        assert_eq!(diff.compare(&json!(1), &doc("a")), DocumentDiff::Unchanged);
+        assert_eq!(diff.compare(&json!(2), &doc("new")), DocumentDiff::Stale);
+        assert_eq!(diff.compare(&json!(9), &doc("i")), DocumentDiff::Missing);
+        diff.keep(&json!(4));
+        assert_eq!(diff.into_orphans(), vec![json!(3), json!(5)]);
+    }
+
+    #[test]
+    fn test_compare_by_content_hash() {
+        let mut diff = NamespaceDiff::new(vec![
+            row(&[("id", json!("a")), ("__doc_hash", json!("h1"))]),
+            row(&[("id", json!("b")), ("__doc_hash", json!("h1"))]),
+        ]);
+        let doc: Document = [
+            ("title".to_string(), Value::String("a".into())),
+            (DOC_HASH_ATTRIBUTE.to_string(), Value::String("h1".into())),
+        ]
+        .into_iter()
+        .collect();
+        let mut changed = doc.clone();
+        changed.insert(DOC_HASH_ATTRIBUTE.to_string(), Value::String("h2".into()));
+
+        // The rows hold no other attributes, so these can only match by hash
+        assert_eq!(diff.compare(&json!("a"), &doc), DocumentDiff::Unchanged);
+        assert_eq!(diff.compare(&json!("b"), &changed), DocumentDiff::Stale);
+        assert!(diff.into_orphans().is_empty());
+    }
+}
@@ -1,17 +1,26 @@
 //! Dead Letter Queue command handlers.
 
-use anyhow::{Context, Result};
-use tracing::info;
+use std::collections::BTreeMap;
 
-use puffgres_core::ErrorKind;
-use puffgres_pg::PostgresStateStore;
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use puffgres_core::{
+    extract_id, Action, Batch, BatchConfig, DocumentId, DocumentValidator, ErrorKind, Mapping,
+    RowEvent, VersioningMode, WriteRequest,
+};
+use puffgres_state::{DlqEntry, StateStore};
+
+use crate::config::ProjectConfig;
+use crate::embeddings::{embed_request, EmbeddingClient};
+use crate::env::{get_max_retries, get_transform_batch_size, get_upload_batch_size};
+use crate::run_id;
+use crate::runner::{create_transformer, write_request, TrackingBatcher};
+use crate::sinks::SinkWriters;
+use crate::tp::{error_kind, TurbopufferClient};
 
 /// List DLQ entries.
-pub async fn cmd_dlq_list(
-    store: &PostgresStateStore,
-    mapping: Option<&str>,
-    limit: i64,
-) -> Result<()> {
+pub async fn cmd_dlq_list(store: &dyn StateStore, mapping: Option<&str>, limit: i64) -> Result<()> {
     let entries = store.get_dlq_entries(mapping, limit).await?;
 
     if entries.is_empty() {
@@ -59,7 +68,7 @@ pub async fn cmd_dlq_list(
 }
 
 /// Show a single DLQ entry.
-pub async fn cmd_dlq_show(store: &PostgresStateStore, id: i32) -> Result<()> {
+pub async fn cmd_dlq_show(store: &dyn StateStore, id: i32) -> Result<()> {
     let entry = store
         .get_dlq_entry(id)
         .await?
@@ -85,6 +94,9 @@ pub async fn cmd_dlq_show(store: &PostgresStateStore, id: i32) -> Result<()> {
         }
     );
     println!("Retry Count:  {}", entry.retry_count);
+    if let Some(run_id) = &entry.run_id {
+        println!("Run ID:       {}", run_id);
+    }
     println!(
         "Created:      {}",
         entry.created_at.format("%Y-%m-%d %H:%M:%S %Z")
@@ -101,84 +113,309 @@ pub async fn cmd_dlq_show(store: &PostgresStateStore, id: i32) -> Result<()> {
     Ok(())
 }
 
-/// Retry DLQ entries.
+/// Most entries fetched for one retry. Entries still failing afterwards are
+/// fetched again by the next retry, so a large queue may take several.
+const MAX_RETRY_ENTRIES: i64 = 1000;
+
+/// Retry DLQ entries by replaying their events through the mapping's
+/// transform and writing the results to turbopuffer.
+///
+/// Entries that go through are deleted. Entries that fail again stay in the
+/// queue with their retry count bumped and the new error recorded.
 pub async fn cmd_dlq_retry(
-    store: &PostgresStateStore,
+    config: &ProjectConfig,
+    store: &dyn StateStore,
     id: Option<i32>,
     mapping: Option<&str>,
+    all: bool,
 ) -> Result<()> {
-    if id.is_none() && mapping.is_none() {
-        anyhow::bail!("Either --id or --mapping must be specified");
-    }
+    let entries = match (id, mapping, all) {
+        (Some(entry_id), None, false) => {
+            let entry = store
+                .get_dlq_entry(entry_id)
+                .await?
+                .context(format!("DLQ entry {} not found", entry_id))?;
+
+            let error_kind = ErrorKind::from_str(&entry.error_kind);
+            if !error_kind.is_retryable() {
+                println!(
+                    "Warning: Entry {} has error kind '{}' which is not typically retryable.",
+                    entry_id,
+                    error_kind.description()
+                );
+                println!("It will still be replayed, but it may fail again.");
+            }
+            vec![entry]
+        }
+        (None, Some(_), false) | (None, None, true) => {
+            store.get_dlq_entries(mapping, MAX_RETRY_ENTRIES).await?
+        }
+        _ => anyhow::bail!("Specify exactly one of --id, --mapping or --all"),
+    };
 
-    if let Some(entry_id) = id {
-        // Retry a specific entry
-        let entry = store
-            .get_dlq_entry(entry_id)
-            .await?
-            .context(format!("DLQ entry {} not found", entry_id))?;
+    if entries.is_empty() {
+        match mapping {
+            Some(name) => println!("No DLQ entries for mapping '{}'", name),
+            None => println!("No DLQ entries found."),
+        }
+        return Ok(());
+    }
+    let truncated = entries.len() as i64 == MAX_RETRY_ENTRIES;
+
+    let mut by_mapping: BTreeMap<String, Vec<DlqEntry>> = BTreeMap::new();
+    for entry in entries {
+        by_mapping
+            .entry(entry.mapping_name.clone())
+            .or_default()
+            .push(entry);
+    }
 
-        let error_kind = ErrorKind::from_str(&entry.error_kind);
+    let mappings = config.load_migrations()?;
+    let max_retries = get_max_retries();
+    let client = TurbopufferClient::new(config.turbopuffer_api_key()?, max_retries);
+    let embedder = EmbeddingClient::for_mappings(config, &mappings, max_retries)?;
+    let sinks = SinkWriters::start(config, &mappings, get_upload_batch_size())?;
+    let mut total = ReplayOutcome::default();
 
-        if !error_kind.is_retryable() {
+    for (name, mut entries) in by_mapping {
+        let Some(mapping) = mappings.iter().find(|m| m.name == name) else {
             println!(
-                "Warning: Entry {} has error kind '{}' which is not typically retryable.",
-                entry_id,
-                error_kind.description()
+                "Skipping {} entries for '{}': no migration defines this mapping",
+                entries.len(),
+                name
             );
-            println!("The entry will still be queued for retry, but it may fail again.");
-        }
+            continue;
+        };
 
-        store.increment_dlq_retry(entry_id).await?;
-        info!(id = entry_id, "Marked DLQ entry for retry");
+        // Oldest first, so a row with several failed changes ends up at its latest
+        entries.sort_by_key(|e| (e.lsn, e.id));
+        println!("Retrying {} entries for '{}'...", entries.len(), name);
+
+        let outcome =
+            replay_entries(store, &client, &sinks, embedder.as_ref(), mapping, &entries).await?;
         println!(
-            "Marked entry {} for retry (retry count: {})",
-            entry_id,
-            entry.retry_count + 1
+            "  {} succeeded, {} failed",
+            outcome.succeeded, outcome.failed
         );
+        total.succeeded += outcome.succeeded;
+        total.failed += outcome.failed;
+    }
 
-        // TODO: Actually reprocess the event through the pipeline
-        // For now, we just increment the retry count
-        println!("\nNote: Actual retry processing is not yet implemented.");
-        println!("The entry has been marked for retry but will need manual reprocessing.");
-    } else if let Some(name) = mapping {
-        // Retry all entries for a mapping
-        let entries = store.get_dlq_entries(Some(name), 1000).await?;
+    sinks.finish(None).await;
 
-        if entries.is_empty() {
-            println!("No DLQ entries for mapping '{}'", name);
-            return Ok(());
+    info!(
+        succeeded = total.succeeded,
+        failed = total.failed,
+        "Retried DLQ entries"
+    );
+    println!(
+        "\nRetried {} entries: {} succeeded and were removed, {} failed again",
+        total.succeeded + total.failed,
+        total.succeeded,
+        total.failed
+    );
+    if total.failed > 0 {
+        println!("Run `puffgres dlq list` to see the errors.");
+    }
+    if truncated {
+        println!(
+            "Only the newest {} entries were retried; run the command again for the rest.",
+            MAX_RETRY_ENTRIES
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct ReplayOutcome {
+    succeeded: usize,
+    failed: usize,
+}
+
+/// Replay one mapping's entries: decode, transform in batches, then write
+/// through a batcher that remembers which entries each batch came from.
+async fn replay_entries(
+    store: &dyn StateStore,
+    client: &TurbopufferClient,
+    sinks: &SinkWriters,
+    embedder: Option<&EmbeddingClient>,
+    mapping: &Mapping,
+    entries: &[DlqEntry],
+) -> Result<ReplayOutcome> {
+    let mut outcome = ReplayOutcome::default();
+    let transformer = create_transformer(mapping);
+    let upload_batch_size = get_upload_batch_size();
+    let mut batcher = TrackingBatcher::new(BatchConfig::with_max_rows(get_transform_batch_size()));
+    let mut validator = DocumentValidator::new();
+
+    let mut rows: Vec<(i32, RowEvent, DocumentId)> = Vec::new();
+    for entry in entries {
+        let event: RowEvent = match serde_json::from_value(entry.event_json.clone()) {
+            Ok(event) => event,
+            Err(e) => {
+                let message = format!("Failed to decode the stored event: {}", e);
+                fail(
+                    store,
+                    &mut outcome,
+                    entry.id,
+                    &message,
+                    ErrorKind::InvalidData,
+                )
+                .await?;
+                continue;
+            }
+        };
+        match extract_id(&event, &mapping.id) {
+            Ok(id) => rows.push((entry.id, event, id)),
+            Err(e) => fail(store, &mut outcome, entry.id, &e.to_string(), e.kind()).await?,
         }
+    }
 
-        let retryable_count = entries
+    for chunk in rows.chunks(get_transform_batch_size().max(1)) {
+        let batch: Vec<(&RowEvent, DocumentId)> = chunk
             .iter()
-            .filter(|e| ErrorKind::from_str(&e.error_kind).is_retryable())
-            .count();
-
-        println!(
-            "Found {} DLQ entries for '{}' ({} retryable)",
-            entries.len(),
-            name,
-            retryable_count
-        );
+            .map(|(_, event, id)| (event, id.clone()))
+            .collect();
+        let results: Vec<_> = match transformer.transform_batch(&batch) {
+            Ok(actions) => actions.into_iter().map(Ok).collect(),
+            // Transform rows one at a time to find the ones that still fail
+            Err(_) => batch
+                .iter()
+                .map(|(event, id)| transformer.transform(event, id.clone()))
+                .collect(),
+        };
 
-        for entry in &entries {
-            store.increment_dlq_retry(entry.id).await?;
+        for ((dlq_id, event, _), result) in chunk.iter().zip(results) {
+            // A document still over the size limit stays in the queue
+            let result = result.and_then(|mut action| {
+                let namespace = mapping.namespace_for(event)?;
+                validator.check(&namespace, &mapping.id.id_type, &action)?;
+                mapping.limits.enforce(&mut action)?;
+                Ok((namespace, action))
+            });
+            let (namespace, mut action) = match result {
+                Ok((_, Action::Error { kind, message })) => {
+                    fail(store, &mut outcome, *dlq_id, &message, kind).await?;
+                    continue;
+                }
+                Ok(written) => written,
+                Err(e) => {
+                    fail(store, &mut outcome, *dlq_id, &e.to_string(), e.kind()).await?;
+                    continue;
+                }
+            };
+
+            if !action.requires_write() {
+                store.delete_dlq_entry(*dlq_id).await?;
+                outcome.succeeded += 1;
+                continue;
+            }
+            if matches!(mapping.versioning, VersioningMode::ContentHash) {
+                action = action.with_content_hash();
+            }
+            if mapping.event_time {
+                action = action.with_event_time(event.timestamp.as_deref());
+            }
+            if mapping.run_id {
+                action = action.with_run_id(run_id::current());
+            }
+
+            if let Some((batch, ids)) = batcher.add(&namespace, *dlq_id, action, event.lsn) {
+                write_batch(
+                    store,
+                    client,
+                    sinks,
+                    embedder,
+                    mapping,
+                    batch,
+                    &ids,
+                    upload_batch_size,
+                    &mut outcome,
+                )
+                .await?;
+            }
         }
+    }
 
-        println!("Marked {} entries for retry", entries.len());
-        println!("\nNote: Actual retry processing is not yet implemented.");
+    for (batch, ids) in batcher.flush_all() {
+        write_batch(
+            store,
+            client,
+            sinks,
+            embedder,
+            mapping,
+            batch,
+            &ids,
+            upload_batch_size,
+            &mut outcome,
+        )
+        .await?;
     }
 
+    Ok(outcome)
+}
+
+/// Write a batch and settle the entries it came from.
+#[allow(clippy::too_many_arguments)]
+async fn write_batch(
+    store: &dyn StateStore,
+    client: &TurbopufferClient,
+    sinks: &SinkWriters,
+    embedder: Option<&EmbeddingClient>,
+    mapping: &Mapping,
+    batch: Batch,
+    ids: &[i32],
+    upload_batch_size: usize,
+    outcome: &mut ReplayOutcome,
+) -> Result<()> {
+    let mut request = WriteRequest::from_batch(batch);
+    let written = match embed_request(embedder, mapping, &mut request).await {
+        Ok(()) => {
+            write_request(
+                client,
+                &request,
+                mapping.source_lsn_attribute.as_deref(),
+                upload_batch_size,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    match written {
+        Ok(()) => {
+            sinks.send(&mapping.name, &request).await;
+            for &id in ids {
+                store.delete_dlq_entry(id).await?;
+            }
+            outcome.succeeded += ids.len();
+        }
+        Err(e) => {
+            warn!(namespace = %request.namespace, error = %e, "Failed to write retried DLQ entries");
+            let kind = error_kind(&e);
+            for &id in ids {
+                fail(store, outcome, id, &format!("{:#}", e), kind).await?;
+            }
+        }
+    }
     Ok(())
 }
 
-/// Clear DLQ entries.
-pub async fn cmd_dlq_clear(
-    store: &PostgresStateStore,
-    mapping: Option<&str>,
-    all: bool,
+/// Record that an entry failed again.
+async fn fail(
+    store: &dyn StateStore,
+    outcome: &mut ReplayOutcome,
+    id: i32,
+    message: &str,
+    kind: ErrorKind,
 ) -> Result<()> {
+    store.record_dlq_failure(id, message, kind.as_str()).await?;
+    outcome.failed += 1;
+    Ok(())
+}
+
+/// Clear DLQ entries.
+pub async fn cmd_dlq_clear(store: &dyn StateStore, mapping: Option<&str>, all: bool) -> Result<()> {
     if mapping.is_none() && !all {
         anyhow::bail!("Either --mapping or --all must be specified");
     }
@@ -0,0 +1,302 @@
+//! Embedding generation for mappings with `[embed]`.
+//!
+//! Vectors are generated just before a batch is written, from the document
+//! attributes the mapping names, through the OpenAI-compatible embeddings
+//! endpoint configured in `[providers.embeddings]`. Upserts that already
+//! carry a vector, such as one returned by a transform, are left alone.
+//!
+//! Requests are retried like turbopuffer writes: transient failures and rate
+//! limits back off, and a `Retry-After` from the provider is honoured (up to
+//! the usual maximum delay).
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use puffgres_core::{EmbedConfig, ErrorKind, Mapping, WriteRequest};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::config::{EmbeddingProviderConfig, ProjectConfig};
+use crate::tp::{backoff_delay, classify_status, jitter, MAX_DELAY};
+
+/// Texts sent per request unless the provider config says otherwise.
+const DEFAULT_BATCH_SIZE: usize = 64;
+
+/// How long to wait for an embeddings response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A client for an OpenAI-compatible embeddings endpoint.
+pub struct EmbeddingClient {
+    http: reqwest::Client,
+    url: String,
+    model: String,
+    api_key: String,
+    batch_size: usize,
+    max_retries: u32,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingClient {
+    /// Create a client for a provider, retrying transient failures up to
+    /// `max_retries` times.
+    pub fn new(
+        provider: &EmbeddingProviderConfig,
+        api_key: String,
+        max_retries: u32,
+    ) -> Result<Self> {
+        let url = match (&provider.url, provider.provider_type.as_str()) {
+            (Some(url), _) => url.clone(),
+            (None, "together") => "https://api.together.xyz/v1/embeddings".to_string(),
+            (None, "openai") => "https://api.openai.com/v1/embeddings".to_string(),
+            (None, other) => bail!(
+                "Unknown embedding provider '{}'; use \"together\" or \"openai\", or set url for another OpenAI-compatible provider",
+                other
+            ),
+        };
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create the embeddings HTTP client")?;
+
+        Ok(Self {
+            http,
+            url,
+            model: provider.model.clone(),
+            api_key,
+            batch_size: provider.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1),
+            max_retries,
+        })
+    }
+
+    /// Create a client if any of `mappings` embeds attributes. It's an error
+    /// for one to when no provider is configured.
+    pub fn for_mappings(
+        config: &ProjectConfig,
+        mappings: &[Mapping],
+        max_retries: u32,
+    ) -> Result<Option<Self>> {
+        let Some(mapping) = mappings.iter().find(|m| m.embed.is_some()) else {
+            return Ok(None);
+        };
+        let Some(provider) = &config.providers.embeddings else {
+            bail!(
+                "Mapping '{}' has [embed] but puffgres.toml has no [providers.embeddings]",
+                mapping.name
+            );
+        };
+        let api_key = config.embedding_api_key(provider)?;
+        Self::new(provider, api_key, max_retries).map(Some)
+    }
+
+    /// Generate vectors for the upserts in a batch that don't have one, from
+    /// their `embed` attributes. Documents without any text get no vector.
+    pub async fn embed_request(
+        &self,
+        embed: &EmbedConfig,
+        request: &mut WriteRequest,
+    ) -> Result<()> {
+        let (positions, texts): (Vec<usize>, Vec<String>) = request
+            .upserts
+            .iter()
+            .enumerate()
+            .filter(|(_, doc)| doc.vector.is_none())
+            .filter_map(|(i, doc)| embed.text(&doc.attributes).map(|text| (i, text)))
+            .unzip();
+        if texts.is_empty() {
+            return Ok(());
+        }
+
+        debug!(
+            namespace = %request.namespace,
+            documents = texts.len(),
+            "Generating embeddings"
+        );
+        let vectors = self.embed(&texts).await?;
+        for (i, vector) in positions.into_iter().zip(vectors) {
+            request.upserts[i].vector = Some(vector);
+        }
+        request.distance_metric.get_or_insert(embed.distance_metric);
+        Ok(())
+    }
+
+    /// Embed texts, in requests of at most `batch_size`.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for chunk in texts.chunks(self.batch_size) {
+            vectors.extend(self.embed_chunk(chunk).await?);
+        }
+        Ok(vectors)
+    }
+
+    /// Embed one request's texts, retrying transient failures.
+    async fn embed_chunk(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        for attempt in 0..=self.max_retries {
+            let (kind, error, retry_after) = match self.attempt(texts).await {
+                Ok(vectors) => return Ok(vectors),
+                Err(failure) => failure,
+            };
+
+            if !kind.is_retryable() {
+                return Err(error).context(format!(
+                    "Embedding request failed ({}), not retrying",
+                    kind.description()
+                ));
+            }
+            if attempt == self.max_retries {
+                return Err(error).context("Failed to generate embeddings after all retries");
+            }
+
+            let delay = retry_after
+                .map(|d| d.min(MAX_DELAY))
+                .unwrap_or_else(|| backoff_delay(attempt, kind, jitter()));
+            warn!(
+                attempt = attempt + 1,
+                max_retries = self.max_retries,
+                delay_ms = delay.as_millis() as u64,
+                kind = kind.as_str(),
+                error = %error,
+                "Embedding request failed, retrying"
+            );
+            tokio::time::sleep(delay).await;
+        }
+
+        unreachable!()
+    }
+
+    async fn attempt(
+        &self,
+        texts: &[String],
+    ) -> std::result::Result<Vec<Vec<f32>>, (ErrorKind, anyhow::Error, Option<Duration>)> {
+        let response = self
+            .http
+            .post(&self.url)
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({ "model": self.model, "input": texts }))
+            .send()
+            .await
+            .map_err(|e| (classify_http_error(&e), e.into(), None))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = retry_after(response.headers());
+            let body = response.text().await.unwrap_or_default();
+            return Err((
+                classify_status(status.as_u16()),
+                anyhow!("embeddings provider returned {}: {}", status, body.trim()),
+                retry_after,
+            ));
+        }
+
+        let body: EmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| (ErrorKind::InvalidData, e.into(), None))?;
+        sorted_embeddings(body, texts.len()).map_err(|e| (ErrorKind::InvalidData, e, None))
+    }
+}
+
+/// Classify a request that got no response.
+fn classify_http_error(error: &reqwest::Error) -> ErrorKind {
+    if error.is_timeout() {
+        ErrorKind::Timeout
+    } else {
+        ErrorKind::NetworkError
+    }
+}
+
+/// The delay a `Retry-After` header asks for, when it's given in seconds.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// Order a response's embeddings like the texts they were generated from.
+fn sorted_embeddings(mut response: EmbeddingResponse, expected: usize) -> Result<Vec<Vec<f32>>> {
+    if response.data.len() != expected {
+        bail!(
+            "embeddings provider returned {} embeddings for {} texts",
+            response.data.len(),
+            expected
+        );
+    }
+    response.data.sort_by_key(|d| d.index);
+    Ok(response.data.into_iter().map(|d| d.embedding).collect())
+}
+
+/// Generate the vectors of a batch's upserts when its mapping embeds
+/// attributes.
+pub async fn embed_request(
+    embedder: Option<&EmbeddingClient>,
+    mapping: &Mapping,
+    request: &mut WriteRequest,
+) -> Result<()> {
+    match (&mapping.embed, embedder) {
+        (Some(embed), Some(embedder)) => embedder.embed_request(embed, request).await,
+        (Some(_), None) => bail!(
+            "Mapping '{}' has [embed] but no embedding provider is configured",
+            mapping.name
+        ),
+        (None, _) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(provider_type: &str, url: Option<&str>) -> EmbeddingProviderConfig {
+        EmbeddingProviderConfig {
+            provider_type: provider_type.to_string(),
+            model: "model".to_string(),
+            api_key: "${KEY}".to_string(),
+            url: url.map(str::to_string),
+            batch_size: None,
+        }
+    }
+
+    #[test]
+    fn test_provider_urls() {
+        let client = EmbeddingClient::new(&provider("together", None), "key".into(), 3).unwrap();
+        assert_eq!(client.url, "https://api.together.xyz/v1/embeddings");
+        assert_eq!(client.batch_size, DEFAULT_BATCH_SIZE);
+
+        let custom = provider("voyage", Some("https://example.com/v1/embeddings"));
+        let client = EmbeddingClient::new(&custom, "key".into(), 3).unwrap();
+        assert_eq!(client.url, "https://example.com/v1/embeddings");
+
+        assert!(EmbeddingClient::new(&provider("voyage", None), "key".into(), 3).is_err());
+    }
+
+    #[test]
+    fn test_sorted_embeddings() {
+        let response: EmbeddingResponse = serde_json::from_str(
+            r#"{"data": [{"index": 1, "embedding": [0.2]}, {"index": 0, "embedding": [0.1]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sorted_embeddings(response, 2).unwrap(),
+            vec![vec![0.1], vec![0.2]]
+        );
+
+        let response = EmbeddingResponse { data: vec![] };
+        assert!(sorted_embeddings(response, 1).is_err());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(reqwest::header::RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+    }
+}
@@ -1,4 +1,5 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use puffgres_core::IdObfuscator;
 use tracing::{info, warn};
 
 /// Default batch size for processing transforms (rows per batch).
@@ -42,6 +43,13 @@ pub const DEFAULT_UPLOAD_BATCH_SIZE: usize = 500;
 /// Default maximum retries for failed turbopuffer uploads.
 pub const DEFAULT_MAX_RETRIES: u32 = 5;
 
+/// Default number of concurrent turbopuffer writes during backfill.
+pub const DEFAULT_UPLOAD_CONCURRENCY: usize = 4;
+
+/// Default number of turbopuffer writes the runner keeps in flight per
+/// namespace.
+pub const DEFAULT_WRITE_CONCURRENCY: usize = 4;
+
 /// Warn if the database URL appears to be using a connection pooler.
 /// Logical replication requires a direct connection to Postgres and does not work
 /// through connection poolers like PgBouncer.
@@ -72,6 +80,84 @@ pub fn get_upload_batch_size() -> usize {
         .unwrap_or(DEFAULT_UPLOAD_BATCH_SIZE)
 }
 
+/// Get the backfill upload concurrency from environment or use default.
+/// Zero is ignored.
+pub fn get_upload_concurrency() -> usize {
+    std::env::var("PUFFGRES_UPLOAD_CONCURRENCY")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+}
+
+/// Get how many writes the runner keeps in flight per namespace from
+/// environment or use default. Zero is ignored.
+pub fn get_write_concurrency() -> usize {
+    std::env::var("PUFFGRES_WRITE_CONCURRENCY")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_WRITE_CONCURRENCY)
+}
+
+/// Get how many writes a second each turbopuffer namespace is held to, from
+/// environment. Unset or zero for no limit.
+pub fn get_tp_max_requests_per_sec() -> Option<u32> {
+    std::env::var("PUFFGRES_TP_MAX_REQUESTS_PER_SEC")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+}
+
+/// Get how many writes to each turbopuffer namespace may be sent at once,
+/// from environment. Unset or zero for no limit.
+pub fn get_tp_max_concurrent_writes() -> Option<usize> {
+    std::env::var("PUFFGRES_TP_MAX_CONCURRENT_WRITES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+}
+
+/// Default number of document hashes remembered for `content_hash` versioning.
+pub const DEFAULT_DOC_HASH_CACHE_SIZE: usize = 100_000;
+
+/// Get the number of document hashes the runner remembers to skip unchanged
+/// upserts. Zero disables the cache.
+pub fn get_doc_hash_cache_size() -> usize {
+    std::env::var("PUFFGRES_DOC_HASH_CACHE_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_DOC_HASH_CACHE_SIZE)
+}
+
+/// Default number of a transaction's events held in memory.
+pub const DEFAULT_TXN_SPILL_EVENTS: usize = 100_000;
+
+/// Get the number of a transaction's events held in memory before the rest
+/// spill to a temporary file. Larger transactions are also processed in
+/// batches of this many events. Zero is ignored.
+pub fn get_txn_spill_events() -> usize {
+    std::env::var("PUFFGRES_TXN_SPILL_EVENTS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_TXN_SPILL_EVENTS)
+}
+
+/// Default number of documents a mapping may delete in one transaction
+/// before the runner stops.
+pub const DEFAULT_MASS_DELETE_THRESHOLD: usize = 10_000;
+
+/// Get how many documents a mapping may delete in one transaction before the
+/// runner stops and asks for `--allow-mass-delete`. Zero turns the check off.
+pub fn get_mass_delete_threshold() -> Option<usize> {
+    let threshold = std::env::var("PUFFGRES_MASS_DELETE_THRESHOLD")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MASS_DELETE_THRESHOLD);
+    (threshold > 0).then_some(threshold)
+}
+
 /// Get the max retries from environment or use default.
 pub fn get_max_retries() -> u32 {
     std::env::var("PUFFGRES_MAX_RETRIES")
@@ -80,6 +166,122 @@ pub fn get_max_retries() -> u32 {
         .unwrap_or(DEFAULT_MAX_RETRIES)
 }
 
+/// Get how many requests may be in flight to turbopuffer at once, across all
+/// namespaces, from environment. Unset or zero for no limit.
+pub fn get_tp_max_connections() -> Option<usize> {
+    std::env::var("PUFFGRES_TP_MAX_CONNECTIONS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+}
+
+/// Default number of seconds between backfill progress saves.
+pub const DEFAULT_BACKFILL_PROGRESS_INTERVAL_SECS: u64 = 5;
+
+/// Default number of rows read between backfill progress saves.
+pub const DEFAULT_BACKFILL_PROGRESS_ROWS: i64 = 50_000;
+
+/// Get how often backfill progress is saved from environment or use default.
+pub fn get_backfill_progress_interval() -> std::time::Duration {
+    let secs = std::env::var("PUFFGRES_BACKFILL_PROGRESS_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_BACKFILL_PROGRESS_INTERVAL_SECS);
+    std::time::Duration::from_secs(secs)
+}
+
+/// Get how many rows are read between backfill progress saves from environment or use default.
+pub fn get_backfill_progress_rows() -> i64 {
+    std::env::var("PUFFGRES_BACKFILL_PROGRESS_ROWS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_BACKFILL_PROGRESS_ROWS)
+}
+
+/// Whether backfills stream rows with COPY instead of a SELECT per batch,
+/// from `PUFFGRES_BACKFILL_COPY` (`1` or `true`).
+pub fn get_backfill_copy() -> bool {
+    std::env::var("PUFFGRES_BACKFILL_COPY")
+        .is_ok_and(|s| s == "1" || s.eq_ignore_ascii_case("true"))
+}
+
+/// Default amount of retained WAL (in MB) before `puffgres status` warns.
+pub const DEFAULT_WAL_RETENTION_WARN_MB: u64 = 1024;
+
+/// Get the WAL retention warning threshold (in bytes) from environment or use default.
+pub fn get_wal_retention_warn_bytes() -> u64 {
+    let mb = std::env::var("PUFFGRES_WAL_RETENTION_WARN_MB")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_WAL_RETENTION_WARN_MB);
+    mb * 1024 * 1024
+}
+
+/// Default age (in seconds) at which an open transaction is reported as
+/// holding back replication.
+pub const DEFAULT_LONG_TRANSACTION_WARN_SECS: u64 = 600;
+
+/// Get how long a transaction can stay open before it is reported, from
+/// environment or use default.
+pub fn get_long_transaction_warn_age() -> std::time::Duration {
+    let secs = std::env::var("PUFFGRES_LONG_TRANSACTION_WARN_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_LONG_TRANSACTION_WARN_SECS);
+    std::time::Duration::from_secs(secs)
+}
+
+/// Default time (in seconds) the runner can hold received changes without
+/// acknowledging any before its health check fails.
+pub const DEFAULT_HEALTH_STALL_SECS: u64 = 300;
+
+/// Get how long the runner can go without progress before `/healthz` reports
+/// it stalled, from environment or use default.
+pub fn get_health_stall_timeout() -> std::time::Duration {
+    let secs = std::env::var("PUFFGRES_HEALTH_STALL_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_HEALTH_STALL_SECS);
+    std::time::Duration::from_secs(secs)
+}
+
+/// Get how often the runner writes to the heartbeat table, from environment.
+///
+/// Off by default (unset or 0): heartbeats create a table in the source
+/// database and add it to the publication.
+pub fn get_heartbeat_interval() -> Option<std::time::Duration> {
+    std::env::var("PUFFGRES_HEARTBEAT_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&secs| secs > 0)
+        .map(std::time::Duration::from_secs)
+}
+
+/// Shortest secret accepted for `obfuscated_uint` ids.
+pub const MIN_ID_SECRET_LEN: usize = 16;
+
+/// Build the obfuscator for `obfuscated_uint` ids from PUFFGRES_ID_SECRET.
+///
+/// Changing the secret changes every obfuscated id, so keep it stable for
+/// the life of a namespace.
+pub fn get_id_obfuscator() -> Result<IdObfuscator> {
+    let secret = std::env::var("PUFFGRES_ID_SECRET")
+        .context("PUFFGRES_ID_SECRET must be set to use obfuscated_uint ids")?;
+    if secret.len() < MIN_ID_SECRET_LEN {
+        bail!(
+            "PUFFGRES_ID_SECRET must be at least {} characters",
+            MIN_ID_SECRET_LEN
+        );
+    }
+    Ok(IdObfuscator::from_secret(&secret))
+}
+
+/// Get the strict mode setting from environment, if any. Combined with the
+/// `--strict` flag by `StrictMode::resolve`.
+pub fn get_strict() -> Option<String> {
+    std::env::var("PUFFGRES_STRICT").ok()
+}
+
 /// Load .env files using Next.js-style hierarchical loading.
 ///
 /// Files are loaded in this priority order (highest wins):
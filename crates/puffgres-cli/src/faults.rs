@@ -0,0 +1,276 @@
+//! Fault injection for resilience testing.
+//!
+//! Enabled with the hidden `puffgres run --fault-inject <SPEC>` flag, and only
+//! when `PUFFGRES_ENABLE_FAULT_INJECTION=1` is set so it can never be turned on
+//! by accident in production.
+//!
+//! The spec is a comma-separated list of `key=value` pairs:
+//!
+//! - `seed=<u64>` - seed for the fault schedule (same seed, same faults)
+//! - `write_fail=<0..1>` - probability that a turbopuffer write attempt fails
+//! - `ack_delay=<0..1>` - probability that acknowledging a transaction is delayed
+//! - `ack_delay_ms=<ms>` - how long a delayed acknowledgment waits (default 500)
+//! - `disconnect=<0..1>` - probability that the replication connection drops per transaction
+//! - `crash_flush=<0..1>` - probability that the process exits after writing a batch
+//!   but before saving its checkpoint
+//!
+//! Example: `seed=42,write_fail=0.1,disconnect=0.01,crash_flush=0.005`
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use tracing::warn;
+
+/// Environment variable that must be set to `1` to allow fault injection.
+pub const FAULT_INJECTION_ENV: &str = "PUFFGRES_ENABLE_FAULT_INJECTION";
+
+/// Exit code used when a crash is injected mid-flush.
+pub const INJECTED_CRASH_EXIT_CODE: i32 = 86;
+
+/// Probabilities and parameters for each kind of fault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultConfig {
+    pub seed: u64,
+    pub write_failure_rate: f64,
+    pub ack_delay_rate: f64,
+    pub ack_delay_ms: u64,
+    pub disconnect_rate: f64,
+    pub crash_rate: f64,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            write_failure_rate: 0.0,
+            ack_delay_rate: 0.0,
+            ack_delay_ms: 500,
+            disconnect_rate: 0.0,
+            crash_rate: 0.0,
+        }
+    }
+}
+
+impl FaultConfig {
+    /// Parse a fault spec such as `seed=42,write_fail=0.1`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut config = FaultConfig::default();
+
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part.split_once('=').with_context(|| {
+                format!("Invalid fault spec entry '{}': expected key=value", part)
+            })?;
+
+            match key.trim() {
+                "seed" => config.seed = parse_value(key, value)?,
+                "write_fail" => config.write_failure_rate = parse_rate(key, value)?,
+                "ack_delay" => config.ack_delay_rate = parse_rate(key, value)?,
+                "ack_delay_ms" => config.ack_delay_ms = parse_value(key, value)?,
+                "disconnect" => config.disconnect_rate = parse_rate(key, value)?,
+                "crash_flush" => config.crash_rate = parse_rate(key, value)?,
+                other => bail!(
+                    "Unknown fault '{}'. Expected one of: seed, write_fail, ack_delay, \
+                     ack_delay_ms, disconnect, crash_flush",
+                    other
+                ),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .with_context(|| format!("Invalid value '{}' for fault '{}'", value, key))
+}
+
+fn parse_rate(key: &str, value: &str) -> Result<f64> {
+    let rate: f64 = parse_value(key, value)?;
+    if !(0.0..=1.0).contains(&rate) {
+        bail!("Fault '{}' must be between 0 and 1, got {}", key, rate);
+    }
+    Ok(rate)
+}
+
+/// Seeded fault schedule shared by the CDC loop and the writer.
+///
+/// When disabled, every check returns "no fault" without touching the RNG.
+#[derive(Debug)]
+pub struct FaultInjector {
+    config: Option<FaultConfig>,
+    state: AtomicU64,
+}
+
+impl FaultInjector {
+    /// A fault injector that never injects anything.
+    pub fn disabled() -> Self {
+        Self {
+            config: None,
+            state: AtomicU64::new(0),
+        }
+    }
+
+    /// Create an injector from a parsed config.
+    pub fn new(config: FaultConfig) -> Self {
+        let state = AtomicU64::new(config.seed);
+        Self {
+            config: Some(config),
+            state,
+        }
+    }
+
+    /// Build an injector from the `--fault-inject` flag.
+    ///
+    /// Returns an error if a spec is given but fault injection isn't enabled
+    /// via `PUFFGRES_ENABLE_FAULT_INJECTION=1`.
+    pub fn from_flag(spec: Option<&str>) -> Result<Self> {
+        let Some(spec) = spec else {
+            return Ok(Self::disabled());
+        };
+
+        if std::env::var(FAULT_INJECTION_ENV).as_deref() != Ok("1") {
+            bail!(
+                "--fault-inject is a testing tool and requires {}=1",
+                FAULT_INJECTION_ENV
+            );
+        }
+
+        let config = FaultConfig::parse(spec)?;
+        warn!(
+            ?config,
+            "Fault injection enabled - do not use in production"
+        );
+        Ok(Self::new(config))
+    }
+
+    /// Next value in [0, 1) from the seeded schedule (SplitMix64).
+    fn next_f64(&self) -> f64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn roll(&self, rate: impl Fn(&FaultConfig) -> f64) -> bool {
+        match &self.config {
+            Some(config) => {
+                let rate = rate(config);
+                rate > 0.0 && self.next_f64() < rate
+            }
+            None => false,
+        }
+    }
+
+    /// Should this turbopuffer write attempt fail?
+    pub fn inject_write_failure(&self) -> bool {
+        self.roll(|c| c.write_failure_rate)
+    }
+
+    /// Should the replication connection drop before this transaction is processed?
+    pub fn inject_disconnect(&self) -> bool {
+        self.roll(|c| c.disconnect_rate)
+    }
+
+    /// How long to delay acknowledging this transaction, if at all.
+    pub fn ack_delay(&self) -> Option<Duration> {
+        if self.roll(|c| c.ack_delay_rate) {
+            self.config
+                .as_ref()
+                .map(|c| Duration::from_millis(c.ack_delay_ms))
+        } else {
+            None
+        }
+    }
+
+    /// Should the process crash between writing a batch and saving its checkpoint?
+    pub fn inject_crash(&self) -> bool {
+        self.roll(|c| c.crash_rate)
+    }
+
+    /// Exit the process if a crash is scheduled at this point.
+    pub fn maybe_crash(&self, point: &str) {
+        if self.inject_crash() {
+            warn!(point, "Injected crash");
+            std::process::exit(INJECTED_CRASH_EXIT_CODE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_spec() {
+        let config = FaultConfig::parse(
+            "seed=42, write_fail=0.1,ack_delay=0.5,ack_delay_ms=20,disconnect=0.01,crash_flush=0",
+        )
+        .unwrap();
+        assert_eq!(config.seed, 42);
+        assert_eq!(config.write_failure_rate, 0.1);
+        assert_eq!(config.ack_delay_rate, 0.5);
+        assert_eq!(config.ack_delay_ms, 20);
+        assert_eq!(config.disconnect_rate, 0.01);
+        assert_eq!(config.crash_rate, 0.0);
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_specs() {
+        assert!(FaultConfig::parse("write_fail").is_err());
+        assert!(FaultConfig::parse("write_fail=1.5").is_err());
+        assert!(FaultConfig::parse("explode=0.1").is_err());
+        assert!(FaultConfig::parse("seed=abc").is_err());
+    }
+
+    #[test]
+    fn test_disabled_never_injects() {
+        let faults = FaultInjector::disabled();
+        for _ in 0..1000 {
+            assert!(!faults.inject_write_failure());
+            assert!(!faults.inject_disconnect());
+            assert!(!faults.inject_crash());
+            assert!(faults.ack_delay().is_none());
+        }
+    }
+
+    #[test]
+    fn test_schedule_is_deterministic_for_seed() {
+        let config = FaultConfig {
+            seed: 7,
+            write_failure_rate: 0.3,
+            ..Default::default()
+        };
+        let a = FaultInjector::new(config.clone());
+        let b = FaultInjector::new(config);
+
+        let schedule_a: Vec<bool> = (0..200).map(|_| a.inject_write_failure()).collect();
+        let schedule_b: Vec<bool> = (0..200).map(|_| b.inject_write_failure()).collect();
+        assert_eq!(schedule_a, schedule_b);
+
+        // Roughly matches the configured rate
+        let failures = schedule_a.iter().filter(|f| **f).count();
+        assert!((30..=90).contains(&failures), "failures = {}", failures);
+    }
+
+    #[test]
+    fn test_rate_extremes() {
+        let always = FaultInjector::new(FaultConfig {
+            disconnect_rate: 1.0,
+            ..Default::default()
+        });
+        let never = FaultInjector::new(FaultConfig::default());
+        for _ in 0..100 {
+            assert!(always.inject_disconnect());
+            assert!(!never.inject_disconnect());
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Liveness and readiness endpoints for `puffgres run --health-addr`.
+//!
+//! The runner records its replication connection, the positions it has
+//! received and acknowledged, and each mapping's last flush in a [`Health`].
+//! `/healthz` fails when the runner has stopped making progress and
+//! `/readyz` also fails while it isn't streaming, so an orchestrator can
+//! restart a stuck runner and route around a reconnecting one.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use puffgres_pg::format_lsn;
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// What the runner reports about itself.
+pub struct Health {
+    state: Mutex<HealthState>,
+    /// How long the runner can hold received changes without acknowledging
+    /// any before it counts as stalled.
+    stall_timeout: Duration,
+}
+
+#[derive(Default)]
+struct HealthState {
+    connected: bool,
+    /// Commit position of the last transaction received.
+    received_lsn: u64,
+    /// Position up to which every change has been written.
+    acknowledged_lsn: u64,
+    /// When the runner last made progress while changes were outstanding;
+    /// `None` once it has caught up.
+    waiting_since: Option<Instant>,
+    last_flush: Option<DateTime<Utc>>,
+    mappings: BTreeMap<String, MappingHealth>,
+}
+
+#[derive(Default)]
+struct MappingHealth {
+    /// Position of the last change written for the mapping.
+    flushed_lsn: u64,
+    last_flush: Option<DateTime<Utc>>,
+    /// Paused with `puffgres pause`, which holds acknowledgments back.
+    paused: bool,
+}
+
+impl Health {
+    pub fn new(stall_timeout: Duration) -> Self {
+        Self {
+            state: Mutex::new(HealthState::default()),
+            stall_timeout,
+        }
+    }
+
+    /// Report on these mappings, including ones that haven't flushed yet.
+    pub fn add_mappings<'a>(&self, names: impl IntoIterator<Item = &'a str>) {
+        let mut state = self.state.lock().unwrap();
+        for name in names {
+            state.mappings.entry(name.to_string()).or_default();
+        }
+    }
+
+    /// The replication stream connected or was lost.
+    pub fn set_connected(&self, connected: bool) {
+        self.state.lock().unwrap().connected = connected;
+    }
+
+    /// A transaction committed at `lsn` was received.
+    pub fn received(&self, lsn: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.received_lsn = state.received_lsn.max(lsn);
+        if state.received_lsn > state.acknowledged_lsn && state.waiting_since.is_none() {
+            state.waiting_since = Some(Instant::now());
+        }
+    }
+
+    /// Every change up to `lsn` has been written.
+    pub fn acknowledged(&self, lsn: u64) {
+        let mut state = self.state.lock().unwrap();
+        if lsn <= state.acknowledged_lsn {
+            return;
+        }
+        state.acknowledged_lsn = lsn;
+        state.waiting_since = (state.received_lsn > lsn).then(Instant::now);
+    }
+
+    /// Only these mappings are paused now.
+    pub fn set_paused<'a>(&self, names: impl IntoIterator<Item = &'a str>) {
+        let mut state = self.state.lock().unwrap();
+        for mapping in state.mappings.values_mut() {
+            mapping.paused = false;
+        }
+        for name in names {
+            state.mappings.entry(name.to_string()).or_default().paused = true;
+        }
+    }
+
+    /// A batch of the mapping's changes up to `lsn` was written.
+    pub fn flushed(&self, mapping: &str, lsn: u64) {
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap();
+        state.last_flush = Some(now);
+        let entry = state.mappings.entry(mapping.to_string()).or_default();
+        entry.flushed_lsn = entry.flushed_lsn.max(lsn);
+        entry.last_flush = Some(now);
+    }
+
+    /// How long the runner has held changes without progress, if that's
+    /// past the stall timeout. A paused mapping holds changes on purpose, so
+    /// the runner isn't stalled while one is paused.
+    fn stalled_for(&self, state: &HealthState, now: Instant) -> Option<Duration> {
+        if state.mappings.values().any(|m| m.paused) {
+            return None;
+        }
+        let waited = now.saturating_duration_since(state.waiting_since?);
+        (waited > self.stall_timeout).then_some(waited)
+    }
+
+    /// The status code for a probe, and the report returned with it.
+    fn probe(&self, readiness: bool, now: Instant) -> (StatusCode, Value) {
+        let state = self.state.lock().unwrap();
+        let stalled = self.stalled_for(&state, now);
+        let status = match (stalled, state.connected) {
+            (Some(_), _) => "stalled",
+            (None, false) => "disconnected",
+            (None, true) => "ok",
+        };
+        let healthy = stalled.is_none() && (state.connected || !readiness);
+
+        let mappings: Vec<Value> = state
+            .mappings
+            .iter()
+            .map(|(name, mapping)| {
+                // Everything acknowledged is written for every mapping
+                let lsn = mapping.flushed_lsn.max(state.acknowledged_lsn);
+                json!({
+                    "name": name,
+                    "lsn": format_lsn(lsn),
+                    "lag_bytes": state.received_lsn.saturating_sub(lsn),
+                    "last_flush_at": mapping.last_flush,
+                    "paused": mapping.paused,
+                })
+            })
+            .collect();
+        let report = json!({
+            "status": status,
+            "connected": state.connected,
+            "received_lsn": format_lsn(state.received_lsn),
+            "acknowledged_lsn": format_lsn(state.acknowledged_lsn),
+            "stalled_secs": stalled.map(|d| d.as_secs()),
+            "last_flush_at": state.last_flush,
+            "mappings": mappings,
+        });
+
+        let code = if healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (code, report)
+    }
+}
+
+/// Start serving `/healthz` and `/readyz` on `addr`. Fails if the address
+/// can't be bound; the server runs until the returned task is aborted.
+pub async fn spawn_server(health: Arc<Health>, addr: SocketAddr) -> Result<JoinHandle<()>> {
+    let app = Router::new()
+        .route("/healthz", get(liveness))
+        .route("/readyz", get(readiness))
+        .with_state(health);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen for health checks on {}", addr))?;
+    info!(address = %addr, "Serving health checks");
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!(error = %e, "Health check server stopped");
+        }
+    }))
+}
+
+async fn liveness(State(health): State<Arc<Health>>) -> (StatusCode, Json<Value>) {
+    let (code, report) = health.probe(false, Instant::now());
+    (code, Json(report))
+}
+
+async fn readiness(State(health): State<Arc<Health>>) -> (StatusCode, Json<Value>) {
+    let (code, report) = health.probe(true, Instant::now());
+    (code, Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn connected_health() -> Health {
+        let health = Health::new(TIMEOUT);
+        health.add_mappings(["users", "posts"]);
+        health.set_connected(true);
+        health
+    }
+
+    #[test]
+    fn test_ready_only_while_connected() {
+        let health = Health::new(TIMEOUT);
+        let now = Instant::now();
+
+        let (code, report) = health.probe(true, now);
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(report["status"], "disconnected");
+        // A reconnecting runner is still alive
+        assert_eq!(health.probe(false, now).0, StatusCode::OK);
+
+        health.set_connected(true);
+        let (code, report) = health.probe(true, now);
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(report["status"], "ok");
+    }
+
+    #[test]
+    fn test_stalled_without_progress() {
+        let health = connected_health();
+        health.received(100);
+        let start = Instant::now();
+
+        assert_eq!(health.probe(false, start).0, StatusCode::OK);
+        let later = start + TIMEOUT + Duration::from_secs(1);
+        let (code, report) = health.probe(false, later);
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(report["status"], "stalled");
+        assert_eq!(health.probe(true, later).0, StatusCode::SERVICE_UNAVAILABLE);
+
+        // Catching up clears it, however long ago the changes arrived
+        health.acknowledged(100);
+        assert_eq!(health.probe(false, later).0, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_paused_mapping_is_not_stalled() {
+        let health = connected_health();
+        health.received(100);
+        health.set_paused(["users"]);
+
+        let later = Instant::now() + TIMEOUT * 10;
+        let (code, report) = health.probe(false, later);
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(report["mappings"][1]["paused"], true);
+
+        health.set_paused([]);
+        assert_eq!(
+            health.probe(false, later).0,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn test_idle_runner_is_not_stalled() {
+        let health = connected_health();
+        health.received(100);
+        health.acknowledged(100);
+
+        let much_later = Instant::now() + TIMEOUT * 10;
+        assert_eq!(health.probe(true, much_later).0, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_mapping_lag() {
+        let health = connected_health();
+        health.received(100);
+        health.acknowledged(40);
+        health.flushed("users", 90);
+
+        let (_, report) = health.probe(true, Instant::now());
+        let mappings = report["mappings"].as_array().unwrap();
+        assert_eq!(mappings.len(), 2);
+        // Sorted by name
+        assert_eq!(mappings[0]["name"], "posts");
+        assert_eq!(mappings[0]["lag_bytes"], 60);
+        assert!(mappings[0]["last_flush_at"].is_null());
+        assert_eq!(mappings[1]["name"], "users");
+        assert_eq!(mappings[1]["lag_bytes"], 10);
+        assert!(!mappings[1]["last_flush_at"].is_null());
+        assert!(!report["last_flush_at"].is_null());
+    }
+}
@@ -0,0 +1,18 @@
+pub mod backfill;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod diff;
+pub mod dlq;
+pub mod embeddings;
+pub mod env;
+pub mod faults;
+pub mod health;
+pub mod run_id;
+pub mod runner;
+pub mod sinks;
+pub mod strict;
+pub mod throttle;
+pub mod tp;
+pub mod validation;
+pub mod watch;
@@ -1,31 +1,47 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::{Context, Result};
 use clap::Parser;
+use tracing::Instrument;
 
-mod backfill;
-mod cli;
-mod commands;
-mod config;
-mod dlq;
-mod env;
-mod runner;
-mod validation;
-
-use cli::{Cli, Commands, DlqCommands};
-use config::ProjectConfig;
-use puffgres_pg::PostgresStateStore;
+use puffgres_cli::cli::{Cli, Commands, DlqCommands, IdCommands, MigrateCommands};
+use puffgres_cli::config::{self, EnvProfile, ProjectConfig, ProvidersConfig};
+use puffgres_cli::runner::DryRun;
+use puffgres_cli::strict::StrictMode;
+use puffgres_cli::{backfill, commands, dlq, env, run_id, validation};
+use puffgres_pg::{
+    connect_postgres, format_lsn, PostgresStateStore, ReplicationStream, ReplicationStreamConfig,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing first so we can log .env loading
+    let cli = Cli::parse();
+
+    // Initialize tracing first so we can log .env loading. A dry run prints
+    // batches to stdout, so its logs go to stderr
+    let log_to_stderr = matches!(
+        cli.command,
+        Commands::Run {
+            dry_run: Some(_),
+            ..
+        }
+    );
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::from_default_env()
                 .add_directive("puffgres=info".parse().unwrap()),
         )
+        .with_writer(move || -> Box<dyn std::io::Write> {
+            if log_to_stderr {
+                Box::new(std::io::stderr())
+            } else {
+                Box::new(std::io::stdout())
+            }
+        })
         .init();
 
-    let cli = Cli::parse();
-
     // For most commands, validate we're in a puffgres project directory
     // `init` is the exception - it creates the project structure
     let needs_project_dir = !matches!(cli.command, Commands::Init);
@@ -34,84 +50,327 @@ async fn main() -> Result<()> {
     }
 
     // Load .env file from current directory or any parent directory
-    // For `init` and `new`, try to load but don't require it
-    let env_required = !matches!(cli.command, Commands::Init | Commands::New { .. });
+    // For `init`, `new`, `lint` and transform tests on a fixture, try to
+    // load but don't require it
+    let env_required = !matches!(
+        cli.command,
+        Commands::Init
+            | Commands::New { .. }
+            | Commands::Lint { .. }
+            | Commands::TestTransform {
+                fixture: Some(_),
+                ..
+            }
+    );
     if let Err(e) = env::load_dotenv_from_ancestors(cli.env.as_deref()) {
         if env_required {
             return Err(e);
         }
     }
 
+    let (profile, providers) = if needs_project_dir {
+        (
+            config::load_profile(Path::new("."), cli.env.as_deref())?,
+            config::load_providers(Path::new("."))?,
+        )
+    } else {
+        (EnvProfile::default(), ProvidersConfig::default())
+    };
+
     match cli.command {
         Commands::Init => commands::cmd_init().await,
-        Commands::Setup => {
-            let config = load_config();
-            commands::cmd_setup(config).await
+        Commands::Setup {
+            publication,
+            fix_replica_identity,
+            yes,
+            notify,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            let fix = match (fix_replica_identity, yes) {
+                (false, _) => commands::IdentityFix::Report,
+                (true, false) => commands::IdentityFix::Confirm,
+                (true, true) => commands::IdentityFix::Apply,
+            };
+            commands::cmd_setup(config, &publication, fix, notify).await
         }
-        Commands::New { name } => commands::cmd_new(name).await,
-        Commands::Migrate { dry_run } => {
-            let config = load_config();
-            commands::cmd_migrate(config, dry_run).await
+        Commands::New {
+            name,
+            from_table,
+            interactive,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_new(&config, name, from_table.as_deref(), interactive).await
+        }
+        Commands::Migrate {
+            command,
+            dry_run,
+            publication,
+            no_alter_publication,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            match command {
+                Some(MigrateCommands::Down {
+                    to,
+                    dry_run,
+                    delete_namespace,
+                    yes,
+                }) => commands::cmd_migrate_down(config, to, dry_run, delete_namespace, yes).await,
+                None => {
+                    commands::cmd_migrate(config, dry_run, &publication, !no_alter_publication)
+                        .await
+                }
+            }
         }
         Commands::Run {
             slot,
             publication,
             create_slot,
+            no_alter_publication,
             skip_migrate,
+            fault_inject,
+            strict,
+            dev,
+            force_takeover,
+            allow_mass_delete,
+            health_addr,
+            dry_run,
         } => {
-            let config = load_config();
-            commands::cmd_run(config, &slot, &publication, create_slot, skip_migrate).await
+            let config = ProjectConfig::from_env(&profile, &providers);
+            let slot = config.slot_name(slot);
+            let strict = StrictMode::resolve(strict.as_deref(), env::get_strict().as_deref())?;
+            let dry_run = dry_run.map(|sink| match sink.as_str() {
+                "null" => DryRun::Null,
+                _ => DryRun::Stdout,
+            });
+            commands::cmd_run(
+                config,
+                &slot,
+                &publication,
+                create_slot,
+                !no_alter_publication,
+                skip_migrate,
+                fault_inject.as_deref(),
+                &strict,
+                dev,
+                force_takeover,
+                allow_mass_delete,
+                health_addr,
+                dry_run,
+            )
+            .instrument(run_id::span())
+            .await
         }
-        Commands::Status => {
-            let config = load_config();
-            commands::cmd_status(config).await
+        Commands::Status {
+            slot,
+            publication,
+            json,
+            watch,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            let slot = config.slot_name(slot);
+            let watch = watch.map(Duration::from_secs);
+            commands::cmd_status(config, &slot, &publication, json, watch).await
         }
         Commands::Backfill {
             mapping,
+            all: _,
+            parallel,
+            force,
             batch_size,
             resume,
+            concurrency,
+            partitions,
+            max_rows_per_sec,
+            max_read_qps,
+            strict,
+            verify,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            let strict = StrictMode::resolve(strict.as_deref(), env::get_strict().as_deref())?;
+            let concurrency = concurrency.map(usize::from);
+            let limits = backfill::BackfillLimits {
+                max_rows_per_sec,
+                max_read_qps,
+            };
+            // clap makes --all and a mapping name exclusive
+            match mapping {
+                Some(mapping) => {
+                    cmd_backfill(
+                        &config,
+                        &mapping,
+                        batch_size,
+                        resume,
+                        concurrency,
+                        usize::from(partitions),
+                        &limits,
+                        &strict,
+                        None,
+                        verify,
+                    )
+                    .instrument(run_id::span())
+                    .await
+                }
+                None => {
+                    commands::cmd_backfill_all(
+                        config,
+                        batch_size,
+                        resume,
+                        force,
+                        parallel.map_or(1, usize::from),
+                        concurrency,
+                        &limits,
+                        &strict,
+                    )
+                    .instrument(run_id::span())
+                    .await
+                }
+            }
+        }
+        Commands::Sync {
+            mapping,
+            slot,
+            publication,
+            batch_size,
+            concurrency,
+            strict,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            let slot = config.slot_name(slot);
+            let strict = StrictMode::resolve(strict.as_deref(), env::get_strict().as_deref())?;
+            let concurrency = concurrency.map(usize::from);
+            cmd_sync(
+                config,
+                &mapping,
+                &slot,
+                &publication,
+                batch_size,
+                concurrency,
+                &strict,
+            )
+            .instrument(run_id::span())
+            .await
+        }
+        Commands::Tail {
+            mapping,
+            table,
+            slot,
+            publication,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_tail(
+                config,
+                mapping.as_deref(),
+                table.as_deref(),
+                slot.as_deref(),
+                &publication,
+            )
+            .await
+        }
+        Commands::Lint { deny_warnings } => commands::cmd_lint(deny_warnings),
+        Commands::Doctor { write } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_doctor(config, write).await
+        }
+        Commands::Check { mapping, sample } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_check(config, &mapping, sample).await
+        }
+        Commands::TestTransform {
+            mapping,
+            limit,
+            fixture,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_test_transform(config, &mapping, limit, fixture.as_deref()).await
+        }
+        Commands::Reconcile {
+            mapping,
+            batch_size,
+            dry_run,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_reconcile(config, &mapping, batch_size, dry_run).await
+        }
+        Commands::Refresh {
+            mapping,
+            batch_size,
         } => {
-            let config = load_config();
-            cmd_backfill(config, &mapping, batch_size, resume).await
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_refresh(config, &mapping, batch_size)
+                .instrument(run_id::span())
+                .await
+        }
+        Commands::Pause { mapping } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_pause(config, &mapping, true).await
+        }
+        Commands::Resume { mapping } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_pause(config, &mapping, false).await
+        }
+        Commands::Promote { mapping, force } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_promote(config, &mapping, force).await
+        }
+        Commands::Replay {
+            from_lsn,
+            to_lsn,
+            mapping,
+            slot,
+            publication,
+        } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            let slot = config.slot_name(slot);
+            commands::cmd_replay(
+                config,
+                &from_lsn,
+                &to_lsn,
+                mapping.as_deref(),
+                &slot,
+                &publication,
+            )
+            .await
+        }
+        Commands::Serve { bind } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_serve(config, bind).await
         }
         Commands::Dlq { command } => {
-            let config = load_config();
+            let config = ProjectConfig::from_env(&profile, &providers);
             cmd_dlq(config, command).await
         }
+        Commands::State { command } => {
+            let config = ProjectConfig::from_env(&profile, &providers);
+            commands::cmd_state(config, command).await
+        }
+        Commands::Id { command } => cmd_id(command),
         Commands::Reset => {
-            let config = load_config();
+            let config = ProjectConfig::from_env(&profile, &providers);
             commands::cmd_reset(config).await
         }
         Commands::DangerouslyDeleteConfig => {
-            let config = load_config();
+            let config = ProjectConfig::from_env(&profile, &providers);
             commands::cmd_dangerously_delete_config(config).await
         }
         Commands::DangerouslyResetTurbopuffer => {
-            let config = load_config();
+            let config = ProjectConfig::from_env(&profile, &providers);
             commands::cmd_dangerously_reset_turbopuffer(config).await
         }
     }
 }
 
-fn load_config() -> ProjectConfig {
-    // Always read from environment variables
-    ProjectConfig {
-        postgres: config::PostgresConfig {
-            connection_string: "${DATABASE_URL}".to_string(),
-        },
-        turbopuffer: config::TurbopufferConfig {
-            api_key: "${TURBOPUFFER_API_KEY}".to_string(),
-            base_namespace: Some("${PUFFGRES_BASE_NAMESPACE}".to_string()),
-        },
-        providers: config::ProvidersConfig::default(),
-    }
-}
-
+#[allow(clippy::too_many_arguments)]
 async fn cmd_backfill(
-    config: ProjectConfig,
+    config: &ProjectConfig,
     mapping_name: &str,
     batch_size: u32,
     resume: bool,
+    concurrency: Option<usize>,
+    partitions: usize,
+    limits: &backfill::BackfillLimits,
+    strict: &StrictMode,
+    snapshot: Option<&str>,
+    verify: Option<u32>,
 ) -> Result<()> {
     use colored::Colorize;
 
@@ -121,7 +380,7 @@ async fn cmd_backfill(
         .context("Failed to connect to Postgres")?;
 
     // Validate transforms haven't been modified
-    if let Err(e) = validation::validate_transforms(&config, &store).await {
+    if let Err(e) = validation::validate_transforms(config, &store).await {
         eprintln!("{}", format!("Error: {}", e).red());
         eprintln!(
             "{}",
@@ -132,7 +391,7 @@ async fn cmd_backfill(
     }
 
     // Load migrations to find the mapping
-    let mappings = config.load_migrations()?;
+    let mappings = config.load_active_migrations(&store).await?;
 
     let mapping = mappings
         .iter()
@@ -143,7 +402,7 @@ async fn cmd_backfill(
     let schema = &mapping.source.schema;
     let table = &mapping.source.table;
 
-    if !store.table_exists(schema, table).await? {
+    if !mapping.source.is_query() && !store.table_exists(schema, table).await? {
         eprintln!(
             "{}",
             format!(
@@ -159,7 +418,147 @@ async fn cmd_backfill(
         std::process::exit(1);
     }
 
-    backfill::run_backfill(&config, mapping, batch_size, resume).await
+    let store = Arc::new(store);
+    let progress = backfill::TerminalProgress::start(*limits);
+    backfill::run_backfill(
+        config,
+        store.clone(),
+        mapping,
+        batch_size,
+        resume,
+        concurrency,
+        partitions,
+        limits,
+        strict,
+        snapshot,
+        &progress,
+    )
+    .await?;
+
+    match verify {
+        Some(sample) => commands::verify_backfill(config, &store, mapping, sample).await,
+        None => Ok(()),
+    }
+}
+
+/// Backfill a mapping without losing changes made while it runs.
+///
+/// The slot is set up before the backfill reads anything, so it retains every
+/// change from the recorded LSN on. The mapping's checkpoint is moved to that
+/// LSN and the runner then streams from it, replaying whatever the backfill
+/// may have missed before carrying on as `puffgres run`.
+///
+/// When the slot is created here, it exports a snapshot as of the LSN and the
+/// backfill reads through it, so nothing is replayed twice either.
+async fn cmd_sync(
+    config: ProjectConfig,
+    mapping_name: &str,
+    slot: &str,
+    publication: &str,
+    batch_size: u32,
+    concurrency: Option<usize>,
+    strict: &StrictMode,
+) -> Result<()> {
+    use colored::Colorize;
+
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+
+    let mappings = config.load_active_migrations(&store).await?;
+    let Some(mapping) = mappings.iter().find(|m| m.name == mapping_name) else {
+        anyhow::bail!("Mapping '{}' not found", mapping_name);
+    };
+    if mapping.source.is_query() {
+        anyhow::bail!(
+            "Mapping '{}' reads from a query and isn't replicated. Run `puffgres refresh {}` instead.",
+            mapping_name,
+            mapping_name
+        );
+    }
+
+    // The mapping streams from its replication group's slot
+    let group_slot = mapping.replication_name(slot);
+    let replication_url = config.replication_connection_string()?;
+    let control_client = connect_postgres(&replication_url)
+        .await
+        .context("Failed to connect to replication server")?;
+    let repl_config = ReplicationStreamConfig {
+        connection_string: replication_url,
+        slot_name: group_slot.clone(),
+        publication_name: mapping.replication_name(publication),
+        publication_tables: mappings
+            .iter()
+            .filter(|m| m.replication_group == mapping.replication_group && !m.source.is_query())
+            .map(|m| format!("{}.{}", m.source.schema, m.source.table))
+            .collect(),
+        ..Default::default()
+    };
+    // The snapshot is only usable on the server that exported it, which is
+    // where the backfill reads when no separate replication server is set
+    let (lsn, snapshot) = if repl_config.connection_string == config.postgres_connection_string()? {
+        ReplicationStream::prepare_with_snapshot(&repl_config, &control_client).await
+    } else {
+        ReplicationStream::prepare(&repl_config, &control_client)
+            .await
+            .map(|lsn| (lsn, None))
+    }
+    .context("Failed to set up replication slot and publication")?;
+
+    let mut checkpoint = store
+        .get_checkpoint(mapping_name)
+        .await?
+        .unwrap_or_default();
+    checkpoint.lsn = lsn;
+    store.save_checkpoint(mapping_name, &checkpoint).await?;
+    println!(
+        "Recorded LSN {}; changes from here on are retained by slot '{}'.",
+        format_lsn(lsn),
+        group_slot
+    );
+    if let Some(snapshot) = &snapshot {
+        println!(
+            "Created slot '{}' with snapshot {}; the backfill reads through it.",
+            group_slot, snapshot.name
+        );
+    }
+
+    cmd_backfill(
+        &config,
+        mapping_name,
+        batch_size,
+        false,
+        concurrency,
+        1,
+        &backfill::BackfillLimits::default(),
+        strict,
+        snapshot.as_ref().map(|s| s.name.as_str()),
+        None,
+    )
+    .await?;
+    drop(snapshot);
+
+    println!(
+        "{}",
+        format!(
+            "Backfill complete. Catching up from LSN {} and continuing to stream.",
+            format_lsn(lsn)
+        )
+        .green()
+    );
+    commands::cmd_run(
+        config,
+        slot,
+        publication,
+        true,
+        false,
+        None,
+        strict,
+        false,
+        false,
+        None,
+    )
+    .await
 }
 
 async fn cmd_dlq(config: ProjectConfig, command: DlqCommands) -> Result<()> {
@@ -173,11 +572,28 @@ async fn cmd_dlq(config: ProjectConfig, command: DlqCommands) -> Result<()> {
             dlq::cmd_dlq_list(&store, mapping.as_deref(), limit).await
         }
         DlqCommands::Show { id } => dlq::cmd_dlq_show(&store, id).await,
-        DlqCommands::Retry { id, mapping } => {
-            dlq::cmd_dlq_retry(&store, id, mapping.as_deref()).await
+        DlqCommands::Retry { id, mapping, all } => {
+            dlq::cmd_dlq_retry(&config, &store, id, mapping.as_deref(), all).await
         }
         DlqCommands::Clear { mapping, all } => {
             dlq::cmd_dlq_clear(&store, mapping.as_deref(), all).await
         }
     }
 }
+
+fn cmd_id(command: IdCommands) -> Result<()> {
+    let obfuscator = env::get_id_obfuscator()?;
+    match command {
+        IdCommands::Encode { ids } => {
+            for id in ids {
+                println!("{}", obfuscator.encode(id));
+            }
+        }
+        IdCommands::Decode { ids } => {
+            for id in ids {
+                println!("{}", obfuscator.decode(id));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! A unique id for each invocation of `puffgres run`, `backfill` or `sync`.
+//!
+//! The id is a UUIDv7, so ids sort by the time the process started. It's
+//! attached to every log line through a tracing span, recorded on dead letter
+//! queue entries and backfill progress, and written to `__run_id` on documents
+//! of mappings with `run_id = true`. That ties a document's state or a failed
+//! row back to the process that produced it.
+
+use std::sync::OnceLock;
+
+use tracing::Span;
+use uuid::Uuid;
+
+static RUN_ID: OnceLock<String> = OnceLock::new();
+
+/// The id of this process, generated on first use.
+pub fn current() -> &'static str {
+    RUN_ID.get_or_init(|| Uuid::now_v7().to_string())
+}
+
+/// A span carrying the run id, for instrumenting a command's future.
+pub fn span() -> Span {
+    tracing::info_span!("puffgres", run_id = current())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_id_is_stable_uuid_v7() {
+        let id = current();
+        assert_eq!(current(), id);
+        let parsed = Uuid::parse_str(id).unwrap();
+        assert_eq!(parsed.get_version_num(), 7);
+    }
+}
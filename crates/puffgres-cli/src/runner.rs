@@ -1,26 +1,56 @@
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use tracing::{debug, error, info, warn};
+use tokio::sync::watch;
+use tokio::task::{Id as TaskId, JoinSet};
+use tracing::{debug, error, info, warn, Instrument};
 
+use puffgres_core::doc_size::{action_size, document_size};
 use puffgres_core::{
-    extract_id, Action, BatchConfig, Batcher, DocumentId, IdentityTransformer, JsTransformer,
-    Mapping, Router, TransformType, Transformer, Value, WriteRequest,
+    extract_id, Action, Batch, BatchConfig, Batcher, DocHashCache, DocumentId, DocumentValidator,
+    ErrorKind, IdentityTransformer, InFlightWrites, JsTransformer, Mapping, Operation,
+    OversizeAction, Router, RowEvent, SizeStats, SourceAdapter, TransformErrorAction,
+    TransformType, Transformer, TruncateAction, Value, VersioningMode, WriteRequest, WriteTicket,
+    VECTOR_ATTRIBUTE,
 };
-use puffgres_pg::{format_lsn, PostgresStateStore, ReplicationStream, ReplicationStreamConfig};
+use puffgres_pg::replication::{
+    check_replica_identity, ensure_heartbeat_table, find_open_transactions, heartbeat_table_ref,
+    is_heartbeat, release_slot, write_heartbeat, SlotLock,
+};
+use puffgres_pg::{
+    connect_postgres, format_lsn, PgError, ReplicationStream, ReplicationStreamConfig,
+};
+use puffgres_state::StateStore;
 
 use crate::config::ProjectConfig;
-use crate::env::{get_max_retries, get_transform_batch_size, get_upload_batch_size};
+use crate::embeddings::{embed_request, EmbeddingClient};
+use crate::env::{
+    get_doc_hash_cache_size, get_heartbeat_interval, get_long_transaction_warn_age,
+    get_mass_delete_threshold, get_max_retries, get_transform_batch_size, get_txn_spill_events,
+    get_upload_batch_size, get_write_concurrency,
+};
+use crate::faults::FaultInjector;
+use crate::health::Health;
+use crate::sinks::{SinkWriters, DRAIN_TIMEOUT};
+use crate::strict::{StrictCheck, StrictMode};
+use crate::tp::{error_kind, TurbopufferClient};
+use crate::validation::validate_no_console_log_in_transforms;
+use crate::watch::TransformWatcher;
 
 /// Wrapper for different transformer types.
-enum MappingTransformer {
+pub(crate) enum MappingTransformer {
     Identity(IdentityTransformer),
     Js(JsTransformer),
 }
 
 impl MappingTransformer {
-    fn transform_batch(
+    pub(crate) fn transform_batch(
         &self,
         rows: &[(&puffgres_core::RowEvent, DocumentId)],
     ) -> puffgres_core::Result<Vec<Action>> {
@@ -31,7 +61,7 @@ impl MappingTransformer {
     }
 
     /// Transform a single event (convenience wrapper for CDC loop).
-    fn transform(
+    pub(crate) fn transform(
         &self,
         event: &puffgres_core::RowEvent,
         id: DocumentId,
@@ -43,76 +73,538 @@ impl MappingTransformer {
     }
 }
 
+/// Create an identity transformer for a mapping's selected, renamed, typed
+/// and flattened columns, computed attributes and vector column.
+fn identity_transformer(mapping: &Mapping) -> IdentityTransformer {
+    IdentityTransformer::new(mapping.columns.clone())
+        .with_renames(mapping.renames.clone())
+        .with_types(mapping.types.clone())
+        .with_flatten(mapping.flatten.clone())
+        .with_computed(mapping.computed.clone())
+        .with_vector(mapping.vector.clone())
+}
+
+/// Create a JS transformer that knows which mapping it runs for.
+fn js_transformer(mapping: &Mapping, path: &str) -> JsTransformer {
+    JsTransformer::new(path).with_migration(
+        &mapping.name,
+        &mapping.namespace,
+        &mapping.source.describe(),
+    )
+}
+
 /// Create the appropriate transformer for a mapping.
-fn create_transformer(mapping: &Mapping) -> MappingTransformer {
+pub(crate) fn create_transformer(mapping: &Mapping) -> MappingTransformer {
     match &mapping.transform {
         Some(config) if config.transform_type == TransformType::Js => {
             if let Some(path) = &config.path {
-                MappingTransformer::Js(JsTransformer::new(path))
+                MappingTransformer::Js(js_transformer(mapping, path))
             } else {
                 // No path specified, use identity
-                MappingTransformer::Identity(IdentityTransformer::new(mapping.columns.clone()))
+                MappingTransformer::Identity(identity_transformer(mapping))
+            }
+        }
+        _ => MappingTransformer::Identity(identity_transformer(mapping)),
+    }
+}
+
+/// Rebuild the transformers whose transform file changed on disk.
+fn reload_changed_transforms(
+    watcher: &mut TransformWatcher,
+    mappings: &[Mapping],
+    transformers: &mut [(String, MappingTransformer)],
+) {
+    let changed = watcher.changed();
+    if changed.is_empty() {
+        return;
+    }
+
+    // stdout carries transform results, so catch a stray console.log now
+    // rather than on the next failed batch
+    if let Err(e) = validate_no_console_log_in_transforms() {
+        warn!(error = %e, "Changed transform writes to stdout");
+    }
+
+    for mapping in mappings {
+        let Some(path) = mapping.transform.as_ref().and_then(|t| t.path.as_deref()) else {
+            continue;
+        };
+        let path = Path::new(path.trim_start_matches("./"));
+        if !changed.iter().any(|c| c == path) {
+            continue;
+        }
+
+        if let Some((_, transformer)) = transformers.iter_mut().find(|(n, _)| n == &mapping.name) {
+            *transformer = create_transformer(mapping);
+            info!(mapping = %mapping.name, path = %path.display(), "Reloaded transform");
+        }
+    }
+}
+
+/// How many times to try re-opening a dropped replication connection.
+const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+
+/// Delay before the first reconnect attempt, doubled on each further attempt.
+/// A standby promotion usually completes within a few seconds.
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between reconnect attempts.
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How often to look for long-running transactions.
+const TRANSACTION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often to look for mappings paused or resumed with `puffgres pause`
+/// and `puffgres resume`, at most. Checked as transactions arrive.
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most JSON a write request's rows may come to. Turbopuffer rejects
+/// requests over 256 MiB; half that leaves room for deletes and the rest of
+/// the request.
+const MAX_WRITE_BYTES: usize = 128 * 1024 * 1024;
+
+/// Aborts a background task when dropped.
+pub(crate) struct AbortOnDrop(pub(crate) tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Warn about transactions open longer than `warn_age`, once per transaction.
+///
+/// Runs as its own task because while such a transaction is open the stream
+/// may deliver nothing at all, so the main loop never gets a chance to check.
+fn spawn_transaction_monitor(primary_url: String, slot: String, warn_age: Duration) -> AbortOnDrop {
+    AbortOnDrop(tokio::spawn(
+        async move {
+            let mut client: Option<tokio_postgres::Client> = None;
+            let mut reported: HashSet<(i32, String)> = HashSet::new();
+
+            loop {
+                tokio::time::sleep(TRANSACTION_CHECK_INTERVAL.min(warn_age)).await;
+
+                if client.as_ref().is_none_or(|c| c.is_closed()) {
+                    match connect_postgres(&primary_url).await {
+                        Ok(c) => client = Some(c),
+                        Err(e) => {
+                            debug!(error = %e, "Transaction monitor could not connect");
+                            continue;
+                        }
+                    }
+                }
+                let Some(client) = &client else {
+                    continue;
+                };
+
+                let open = match find_open_transactions(client, &slot, warn_age).await {
+                    Ok(open) => open,
+                    Err(e) => {
+                        debug!(error = %e, "Failed to check for long-running transactions");
+                        continue;
+                    }
+                };
+
+                let current: HashSet<_> = open.iter().map(|t| (t.pid, t.xid.clone())).collect();
+                for txn in &open {
+                    if !reported.contains(&(txn.pid, txn.xid.clone())) {
+                        warn!(
+                            pid = txn.pid,
+                            xid = %txn.xid,
+                            transaction = %txn.describe(),
+                            "Long-running transaction: its changes won't sync until it commits, \
+                             and the slot retains WAL meanwhile"
+                        );
+                    }
+                }
+                for (pid, xid) in reported.difference(&current) {
+                    info!(pid, xid = %xid, "Long-running transaction finished");
+                }
+                reported = current;
+            }
+        }
+        .in_current_span(),
+    ))
+}
+
+/// Update the heartbeat row every `interval`, so the stream has something to
+/// acknowledge even when no mapped table changes.
+///
+/// Heartbeats go to the primary; when streaming from a standby they reach it
+/// through physical replication like any other write.
+fn spawn_heartbeat(primary_url: String, slot: String, interval: Duration) -> AbortOnDrop {
+    AbortOnDrop(tokio::spawn(
+        async move {
+            let mut client: Option<tokio_postgres::Client> = None;
+
+            loop {
+                tokio::time::sleep(interval).await;
+
+                if client.as_ref().is_none_or(|c| c.is_closed()) {
+                    match connect_postgres(&primary_url).await {
+                        Ok(c) => client = Some(c),
+                        Err(e) => {
+                            warn!(error = %e, "Heartbeat could not connect");
+                            continue;
+                        }
+                    }
+                }
+                let Some(client) = &client else {
+                    continue;
+                };
+
+                match write_heartbeat(client, &slot).await {
+                    Ok(()) => debug!("Wrote heartbeat"),
+                    Err(e) => warn!(error = %e, "Failed to write heartbeat"),
+                }
             }
         }
-        _ => MappingTransformer::Identity(IdentityTransformer::new(mapping.columns.clone())),
+        .in_current_span(),
+    ))
+}
+
+/// Columns that some mapping types as `string`, by `schema.table`. The
+/// decoder keeps these in Postgres' text form so no digits are lost.
+fn text_columns(mappings: &[Mapping]) -> HashMap<String, HashSet<String>> {
+    let mut columns: HashMap<String, HashSet<String>> = HashMap::new();
+    for mapping in mappings {
+        let table = format!("{}.{}", mapping.source.schema, mapping.source.table);
+        for (column, column_type) in &mapping.types {
+            if column_type.reads_text() {
+                columns
+                    .entry(table.clone())
+                    .or_default()
+                    .insert(column.clone());
+            }
+        }
+    }
+    columns
+}
+
+/// Where `puffgres run --dry-run` sends the writes it would make.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryRun {
+    /// Print each batch as a line of JSON.
+    Stdout,
+    /// Drop them; the logs still say what was flushed.
+    Null,
+}
+
+/// Why the CDC loops stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopExit {
+    /// The stream ended or the runner was told to stop.
+    Stopped,
+    /// Migrations were applied; start again with them.
+    Reload,
+}
+
+/// Mappings that stream from the same slot and publication.
+#[derive(Debug)]
+pub struct ReplicationGroup {
+    pub slot: String,
+    pub publication: String,
+    pub mappings: Vec<Mapping>,
+}
+
+/// Split mappings by replication group. Mappings without one stream from
+/// `slot` and `publication`; see [`Mapping::replication_name`].
+pub fn replication_groups(
+    mappings: Vec<Mapping>,
+    slot: &str,
+    publication: &str,
+) -> Vec<ReplicationGroup> {
+    let mut groups: Vec<ReplicationGroup> = Vec::new();
+    for mapping in mappings {
+        let slot = mapping.replication_name(slot);
+        match groups.iter_mut().find(|g| g.slot == slot) {
+            Some(group) => group.mappings.push(mapping),
+            None => groups.push(ReplicationGroup {
+                slot,
+                publication: mapping.replication_name(publication),
+                mappings: vec![mapping],
+            }),
+        }
+    }
+    groups
+}
+
+/// Run a CDC loop for each replication group, concurrently on this task.
+///
+/// Each group has its own slot, publication, slot lock and checkpoints (the
+/// furthest-behind mapping in the group), so a backfill or a failing mapping
+/// only holds back WAL for its own group. If one loop fails the others are
+/// dropped before acknowledging anything further, and the error is returned.
+///
+/// When `reload` changes, every loop writes what it holds and stops, and
+/// [`LoopExit::Reload`] is returned so they can be run again with the new
+/// migrations.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_replication_groups(
+    config: &ProjectConfig,
+    state_store: Arc<dyn StateStore>,
+    mappings: Vec<Mapping>,
+    slot: &str,
+    publication: &str,
+    create_slot: bool,
+    alter_publication: bool,
+    faults: &Arc<FaultInjector>,
+    strict: &StrictMode,
+    dev: bool,
+    force_takeover: bool,
+    allow_mass_delete: bool,
+    dry_run: Option<DryRun>,
+    reload: &watch::Receiver<()>,
+    health: &Health,
+) -> Result<LoopExit> {
+    let groups = replication_groups(mappings, slot, publication);
+    let loops: Vec<LoopFuture<'_>> = groups
+        .into_iter()
+        .map(|group| {
+            let span = tracing::info_span!("stream", slot = %group.slot);
+            let state_store = state_store.clone();
+            Box::pin(
+                async move {
+                    run_cdc_loop(
+                        config,
+                        state_store,
+                        group.mappings,
+                        &group.slot,
+                        &group.publication,
+                        create_slot,
+                        alter_publication,
+                        faults,
+                        strict,
+                        dev,
+                        force_takeover,
+                        allow_mass_delete,
+                        dry_run,
+                        reload,
+                        health,
+                    )
+                    .await
+                    .with_context(|| format!("Replication from slot '{}' failed", group.slot))
+                }
+                .instrument(span),
+            ) as LoopFuture<'_>
+        })
+        .collect();
+
+    Ok(combined_exit(&try_join_all(loops).await?))
+}
+
+/// Why the groups' loops stopped, together. Reloading only makes sense once
+/// every loop has stopped for it.
+fn combined_exit(exits: &[LoopExit]) -> LoopExit {
+    if !exits.is_empty() && exits.iter().all(|&exit| exit == LoopExit::Reload) {
+        LoopExit::Reload
+    } else {
+        LoopExit::Stopped
     }
 }
 
+type LoopFuture<'a> = Pin<Box<dyn Future<Output = Result<LoopExit>> + 'a>>;
+
+/// Poll `futures` until all of them succeed or one fails.
+async fn try_join_all(mut futures: Vec<LoopFuture<'_>>) -> Result<Vec<LoopExit>> {
+    let mut exits = Vec::with_capacity(futures.len());
+    std::future::poll_fn(|cx| {
+        let mut i = 0;
+        while i < futures.len() {
+            match futures[i].as_mut().poll(cx) {
+                Poll::Ready(Ok(exit)) => {
+                    exits.push(exit);
+                    futures.swap_remove(i);
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => i += 1,
+            }
+        }
+        if futures.is_empty() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await?;
+    Ok(exits)
+}
+
 /// Run the CDC replication loop using true push-based streaming.
 ///
 /// This uses pgwire-replication to receive changes in real-time via the
 /// PostgreSQL streaming replication protocol. Changes arrive immediately
 /// as they're committed - no polling required.
+///
+/// A dry run hands batches to `dry_run` instead of turbopuffer and its
+/// sinks, and never acknowledges the slot. The loop stops for a reload when
+/// `reload` changes.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_cdc_loop(
     config: &ProjectConfig,
+    state_store: Arc<dyn StateStore>,
     mappings: Vec<Mapping>,
     slot: &str,
     publication: &str,
     create_slot: bool,
-) -> Result<()> {
-    // State is stored in Postgres __puffgres_* tables
-    let state_store = PostgresStateStore::connect(&config.postgres_connection_string()?)
-        .await
-        .context("Failed to connect to state store")?;
+    alter_publication: bool,
+    faults: &Arc<FaultInjector>,
+    strict: &StrictMode,
+    dev: bool,
+    force_takeover: bool,
+    allow_mass_delete: bool,
+    dry_run: Option<DryRun>,
+    reload: &watch::Receiver<()>,
+    health: &Health,
+) -> Result<LoopExit> {
+    // Only one runner may stream from a slot, or each would acknowledge
+    // changes under the other. Standbys can't take the lock, so it's on the
+    // primary
+    let slot_lock =
+        match SlotLock::acquire(&config.postgres_connection_string()?, slot, force_takeover).await
+        {
+            Ok(lock) => lock,
+            Err(e @ PgError::SlotLocked { .. }) => {
+                return Err(e).context(
+                    "Another puffgres runner is streaming from this slot; stop it, or pass --force-takeover to stop it and take over",
+                )
+            }
+            Err(e) => return Err(e).context("Failed to take the slot lock"),
+        };
 
-    // Get checkpoint to resume from
-    let start_lsn = if let Some(mapping) = mappings.first() {
-        state_store
-            .get_checkpoint(&mapping.name)
-            .await?
-            .map(|c| c.lsn)
-    } else {
-        None
-    };
+    // Resume from the mapping that is furthest behind, so none of them miss
+    // changes (e.g. after `puffgres sync` moved one mapping's checkpoint)
+    let mut start_lsn: Option<u64> = None;
+    for mapping in &mappings {
+        if let Some(checkpoint) = state_store.get_checkpoint(&mapping.name).await? {
+            start_lsn = Some(start_lsn.map_or(checkpoint.lsn, |lsn| lsn.min(checkpoint.lsn)));
+        }
+    }
 
     // Build list of tables for publication
-    let publication_tables: Vec<String> = mappings
+    let mut publication_tables: Vec<String> = mappings
         .iter()
         .map(|m| format!("{}.{}", m.source.schema, m.source.table))
         .collect();
 
+    // The heartbeat table is published too, so its writes reach the stream.
+    // A dry run doesn't advance the slot, so it has no use for them
+    let heartbeat_interval = get_heartbeat_interval().filter(|_| dry_run.is_none());
+    if let Some(interval) = heartbeat_interval {
+        let primary = connect_postgres(&config.postgres_connection_string()?)
+            .await
+            .context("Failed to connect to Postgres")?;
+        ensure_heartbeat_table(&primary)
+            .await
+            .context("Failed to create the heartbeat table")?;
+        publication_tables.push(heartbeat_table_ref());
+        info!(
+            interval_secs = interval.as_secs(),
+            "Writing heartbeats to keep the slot advancing"
+        );
+    }
+
+    // Replication may come from a standby; the state store stays on the primary
+    let replication_url = config.replication_connection_string()?;
+    let mut control_client = connect_postgres(&replication_url)
+        .await
+        .context("Failed to connect to replication server")?;
+
+    let router = Router::new(mappings.clone());
+
     // Initialize streaming replication
-    let repl_config = ReplicationStreamConfig {
-        connection_string: config.postgres_connection_string()?,
+    let mut repl_config = ReplicationStreamConfig {
+        connection_string: replication_url.clone(),
         slot_name: slot.to_string(),
         publication_name: publication.to_string(),
         create_slot,
         create_publication: true,
+        alter_publication,
         publication_tables,
         start_lsn,
+        fail_on_decode_error: strict.enabled(StrictCheck::Decode),
+        text_columns: text_columns(&mappings),
+        decoded_columns: router.decoded_columns(),
+        spill_threshold: get_txn_spill_events(),
         ..Default::default()
     };
+    if let Some(interval) = config.poll_interval() {
+        repl_config.status_interval = interval;
+    }
+
+    // The runner that held the lock may still be streaming from the slot
+    if force_takeover {
+        release_slot(&control_client, slot)
+            .await
+            .context("Failed to take over the replication slot")?;
+    }
 
-    // Use state_store's connection for control plane operations (slot/publication setup)
+    // Use a separate connection for control plane operations (slot/publication setup)
     // pgwire-replication handles only the replication plane
-    let mut stream = ReplicationStream::connect(repl_config, state_store.client())
+    let mut stream = ReplicationStream::connect(repl_config, &control_client)
         .await
         .context("Failed to connect for streaming replication")?;
+    health.add_mappings(mappings.iter().map(|m| m.name.as_str()));
+    health.set_connected(true);
 
-    let tp_client = rs_puff::Client::new(config.turbopuffer_api_key()?);
-    let router = Router::new(mappings.clone());
+    // Open transactions live on the primary even when streaming from a standby
+    let long_transaction_age = get_long_transaction_warn_age();
+    let _transaction_monitor = if long_transaction_age.is_zero() {
+        None
+    } else {
+        Some(spawn_transaction_monitor(
+            config.postgres_connection_string()?,
+            slot.to_string(),
+            long_transaction_age,
+        ))
+    };
+    let _heartbeat = match heartbeat_interval {
+        Some(interval) => Some(spawn_heartbeat(
+            config.postgres_connection_string()?,
+            slot.to_string(),
+            interval,
+        )),
+        None => None,
+    };
+
+    // Tables that log no old-row key can't have deletes replicated at all;
+    // warn about ones that don't log the other old-row columns a mapping needs
+    for mapping in &mappings {
+        let (schema, table) = (&mapping.source.schema, &mapping.source.table);
+        match check_replica_identity(&control_client, schema, table, false).await {
+            Ok(_) => {}
+            Err(e @ PgError::ReplicaIdentity { .. }) => {
+                return Err(e).context(format!(
+                    "Mapping '{}' can't replicate deletes; run `puffgres setup --fix-replica-identity`",
+                    mapping.name
+                ));
+            }
+            Err(e) => return Err(e).context("Failed to check replica identity"),
+        }
+        if !mapping.needs_full_old_row() {
+            continue;
+        }
+        match check_replica_identity(&control_client, schema, table, true).await {
+            Ok(_) => {}
+            Err(e @ PgError::ReplicaIdentity { .. })
+                if strict.enabled(StrictCheck::ReplicaIdentity) =>
+            {
+                return Err(e).context(format!(
+                    "Mapping '{}' failed the replica identity check (strict mode)",
+                    mapping.name
+                ));
+            }
+            Err(e @ PgError::ReplicaIdentity { .. }) => warn!(
+                mapping = %mapping.name,
+                error = %e,
+                "Replica identity is too narrow for this mapping; run `puffgres setup --fix-replica-identity`"
+            ),
+            Err(e) => return Err(e).context("Failed to check replica identity"),
+        }
+    }
 
-    let transformers: Vec<_> = mappings
+    let mut transformers: Vec<_> = mappings
         .iter()
         .map(|m| (m.name.clone(), create_transformer(m)))
         .collect();
@@ -121,6 +613,20 @@ pub async fn run_cdc_loop(
     let transform_batch_size = get_transform_batch_size();
     let upload_batch_size = get_upload_batch_size();
     let max_retries = get_max_retries();
+    // A dry run writes nowhere, so it needs no API keys
+    let api_key = match dry_run {
+        Some(_) => String::new(),
+        None => config.turbopuffer_api_key()?,
+    };
+    let tp_client =
+        Arc::new(TurbopufferClient::new(api_key, max_retries).with_faults(Arc::clone(faults)));
+    let (embedder, sinks) = match dry_run {
+        Some(_) => (None, SinkWriters::default()),
+        None => (
+            EmbeddingClient::for_mappings(config, &mappings, max_retries)?.map(Arc::new),
+            SinkWriters::start(config, &mappings, upload_batch_size)?,
+        ),
+    };
 
     info!(
         slot = slot,
@@ -132,28 +638,263 @@ pub async fn run_cdc_loop(
         "Starting push-based streaming CDC"
     );
 
+    if !strict.is_empty() {
+        info!(checks = %strict, "Strict mode: treating these warnings as errors");
+    }
+    if dry_run.is_some() {
+        warn!("Dry run: nothing is written to turbopuffer and the slot isn't acknowledged");
+    }
+
+    let mut watcher = dev.then(|| {
+        info!("Dev mode: reloading transforms from transforms/ when they change");
+        TransformWatcher::new("transforms")
+    });
+
     let mut total_events: u64 = 0;
-    let mut batchers: HashMap<String, Batcher> = HashMap::new();
-    let batch_config = BatchConfig::with_max_rows(transform_batch_size);
+    let mut skipped_unchanged: u64 = 0;
+    let mut oversize_documents: u64 = 0;
+    let mut doc_sizes = SizeStats::new();
+    let mut reconnects: u64 = 0;
+    let mut consecutive_errors: HashMap<String, u32> = HashMap::new();
+    // One batcher per mapping, holding a batch per namespace it writes to and
+    // the events in each, for the dead letter queue if a write fails
+    let mut batchers: HashMap<String, TrackingBatcher<RowEvent>> = HashMap::new();
+    let mut validator = DocumentValidator::new();
+    let mut doc_hashes = DocHashCache::new(get_doc_hash_cache_size());
+    let default_batch = BatchConfig::with_max_rows(transform_batch_size);
+    // Transactions processed but not acknowledged while a batch held by a
+    // mapping's max_wait_ms may still have their changes
+    let mut unacked = PendingAcks::default();
+    let mut pauses = Pauses::default();
+    let mass_delete_threshold = get_mass_delete_threshold().filter(|_| !allow_mass_delete);
+    let mut delete_guard = DeleteGuard::new(mass_delete_threshold);
+    let mut pipeline = WritePipeline {
+        mappings: &mappings,
+        client: Arc::clone(&tp_client),
+        sinks: &sinks,
+        embedder,
+        state_store: state_store.as_ref(),
+        upload_batch_size,
+        faults,
+        health,
+        in_flight: InFlightWrites::new(get_write_concurrency()),
+        tasks: JoinSet::new(),
+        writes: HashMap::new(),
+        dry_run,
+    };
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut shutting_down = false;
+    let mut reload = reload.clone();
+    let mut reloading = false;
 
     // Main streaming loop - events arrive as they happen (no polling)
-    while let Some(batch) = stream.recv_batch().await? {
-        if batch.events.is_empty() {
-            // Empty transaction (e.g., only system tables changed)
-            stream.acknowledge(batch.ack_lsn);
+    loop {
+        // Held batches are written when they're due, even if nothing else
+        // arrives, writes in flight are settled as they land, and a signal
+        // stops the loop between transactions. Waiting for a batch or a
+        // write is cancel safe
+        let deadline = batchers
+            .values()
+            .filter_map(TrackingBatcher::next_deadline)
+            .min();
+        let due = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        let wake = tokio::select! {
+            next = stream.next_batch() => Wake::Batch(next),
+            (write, result) = pipeline.next() => Wake::Written(write, result),
+            _ = due => Wake::FlushDue,
+            _ = &mut shutdown => Wake::Shutdown,
+            Ok(()) = reload.changed() => Wake::Reload,
+        };
+        let next = match wake {
+            Wake::Batch(next) => next,
+            Wake::Written(write, result) => {
+                pipeline.settle(write, result, &mut doc_hashes).await?;
+                acknowledge_written(
+                    &mut stream,
+                    &mut unacked,
+                    &batchers,
+                    &pipeline,
+                    &pauses,
+                    health,
+                );
+                continue;
+            }
+            Wake::FlushDue => {
+                flush_batchers(
+                    &mut batchers,
+                    Some(Instant::now()),
+                    &mut pipeline,
+                    &mut doc_hashes,
+                )
+                .await?;
+                acknowledge_written(
+                    &mut stream,
+                    &mut unacked,
+                    &batchers,
+                    &pipeline,
+                    &pauses,
+                    health,
+                );
+                continue;
+            }
+            Wake::Shutdown => {
+                info!("Shutting down; writing pending batches");
+                health.set_connected(false);
+                shutting_down = true;
+                break;
+            }
+            Wake::Reload => {
+                info!("Reloading migrations; writing pending batches");
+                reloading = true;
+                break;
+            }
+        };
+
+        let batch = match next {
+            Ok(Some(batch)) => batch,
+            Ok(None) => break,
+            // Reconnecting won't help with e.g. a revoked REPLICATION
+            // privilege or a strict-mode failure
+            Err(e) if !e.is_retryable() => return Err(e.into()),
+            Err(e) => {
+                warn!(error = %e, "Replication connection lost");
+                health.set_connected(false);
+                ensure_slot_lock(&slot_lock)?;
+                // Write what's held so the stream resumes after it
+                flush_batchers(&mut batchers, None, &mut pipeline, &mut doc_hashes).await?;
+                pipeline.drain(&mut doc_hashes).await?;
+                acknowledge_written(
+                    &mut stream,
+                    &mut unacked,
+                    &batchers,
+                    &pipeline,
+                    &pauses,
+                    health,
+                );
+                reconnect(&mut stream, &mut control_client, &replication_url).await?;
+                // Everything not acknowledged is sent again
+                unacked = PendingAcks::default();
+                delete_guard.reset();
+                health.set_connected(true);
+                reconnects += 1;
+
+                let mapping_names: Vec<String> = mappings.iter().map(|m| m.name.clone()).collect();
+                if let Err(e) = state_store.record_reconnect(&mapping_names).await {
+                    warn!(error = %e, "Failed to record reconnect");
+                }
+                info!(reconnects, "Resumed streaming after connection loss");
+                continue;
+            }
+        };
+
+        if faults.inject_disconnect() {
+            anyhow::bail!("Injected fault: replication connection dropped");
+        }
+        ensure_slot_lock(&slot_lock)?;
+        health.received(batch.position);
+
+        if pauses
+            .refresh(state_store.as_ref(), &mappings, health, Instant::now())
+            .await
+        {
+            // The changes a resumed mapping skipped are still in the slot.
+            // Reopen the stream from the last acknowledgment to replay them,
+            // this transaction included
+            info!("Replaying the changes skipped while paused");
+            flush_batchers(&mut batchers, None, &mut pipeline, &mut doc_hashes).await?;
+            pipeline.drain(&mut doc_hashes).await?;
+            acknowledge_written(
+                &mut stream,
+                &mut unacked,
+                &batchers,
+                &pipeline,
+                &pauses,
+                health,
+            );
+            if let Err(e) = stream.close(&control_client).await {
+                warn!(error = %e, "Failed to close the replication stream cleanly");
+            }
+            reconnect(&mut stream, &mut control_client, &replication_url).await?;
+            unacked = PendingAcks::default();
+            delete_guard.reset();
+            continue;
+        }
+
+        if batch.events.iter().all(is_heartbeat) {
+            // Empty transaction (e.g., only system tables changed) or a
+            // heartbeat; everything before it has been processed
+            unacked.push(batch.position);
+            acknowledge_written(
+                &mut stream,
+                &mut unacked,
+                &batchers,
+                &pipeline,
+                &pauses,
+                health,
+            );
             continue;
         }
 
         debug!(count = batch.events.len(), "Processing transaction batch");
 
+        // Stop before writing any of a mass delete. The transaction isn't
+        // acknowledged, so it's sent again after a restart
+        if let Some((mapping, deletes)) = delete_guard.count(&router, &pauses, &batch.events) {
+            anyhow::bail!(
+                "Mapping '{}' would delete {} documents in the transaction at LSN {}, more than the mass delete threshold of {}. \
+                 Restart with --allow-mass-delete to write them, or raise PUFFGRES_MASS_DELETE_THRESHOLD.",
+                mapping,
+                deletes,
+                format_lsn(batch.position),
+                mass_delete_threshold.unwrap_or_default()
+            );
+        }
+
+        if let Some(watcher) = &mut watcher {
+            reload_changed_transforms(watcher, &mappings, &mut transformers);
+        }
+
         // Process each event
         for event in &batch.events {
-            let matched = router.route(event);
+            for routed in router.route_to_namespaces(event) {
+                let mapping = routed.mapping;
+                if pauses.is_paused(&mapping.name) {
+                    pauses.hold(&mapping.name, event.lsn);
+                    continue;
+                }
+                let batcher = batchers.entry(mapping.name.clone()).or_insert_with(|| {
+                    TrackingBatcher::new(mapping.batch.apply(default_batch.clone()))
+                });
 
-            for mapping in matched {
-                let batcher = batchers
-                    .entry(mapping.namespace.clone())
-                    .or_insert_with(|| Batcher::new(batch_config.clone()));
+                // A truncate has no rows to transform; the mapping decides
+                // what it does to the namespace
+                if event.op == Operation::Truncate {
+                    if !truncate_clears(mapping, event, strict)? {
+                        continue;
+                    }
+                    let namespace = mapping
+                        .namespace_for(event)
+                        .context("Can't clear a partitioned namespace")?;
+                    let action = Action::clear_namespace();
+                    doc_hashes.observe(&namespace, &action);
+                    batch_write(
+                        batcher,
+                        &namespace,
+                        event,
+                        action,
+                        &mut pipeline,
+                        &mapping.name,
+                        &mut doc_hashes,
+                    )
+                    .await?;
+                    continue;
+                }
 
                 let transformer = transformers
                     .iter()
@@ -161,227 +902,1325 @@ pub async fn run_cdc_loop(
                     .map(|(_, t)| t)
                     .unwrap();
 
-                let id = match extract_id(event, &mapping.id.column, mapping.id.id_type) {
+                let id = match extract_id(event, &mapping.id) {
                     Ok(id) => id,
                     Err(e) => {
                         warn!(mapping = %mapping.name, error = %e, "Failed to extract ID");
+                        handle_row_error(
+                            &state_store,
+                            mapping,
+                            event,
+                            &e,
+                            &mut consecutive_errors,
+                            strict,
+                            StrictCheck::Id,
+                        )
+                        .await?;
+                        continue;
+                    }
+                };
+
+                let namespace = match routed.namespace {
+                    Ok(namespace) => namespace,
+                    Err(e) => {
+                        warn!(mapping = %mapping.name, error = %e, "Failed to resolve namespace");
+                        handle_row_error(
+                            &state_store,
+                            mapping,
+                            event,
+                            &e,
+                            &mut consecutive_errors,
+                            strict,
+                            StrictCheck::Id,
+                        )
+                        .await?;
                         continue;
                     }
                 };
 
-                let action = match transformer.transform(event, id) {
+                // A row whose namespace column changed leaves a copy behind
+                // in its old namespace
+                let mut writes = Vec::new();
+                if let Some(previous) = routed.moved_from {
+                    let delete = Action::Delete { id: id.clone() };
+                    doc_hashes.observe(&previous, &delete);
+                    writes.push((previous, delete));
+                }
+
+                // A row that left the membership predicate isn't transformed,
+                // its document is deleted. A document turbopuffer would
+                // reject is handled like a failed transform
+                let result = if routed.left_membership {
+                    Ok(Action::delete(id))
+                } else {
+                    transformer.transform(event, id).and_then(|action| {
+                        validator.check(&namespace, &mapping.id.id_type, &action)?;
+                        Ok(action)
+                    })
+                };
+                let mut action = match result {
                     Ok(action) => action,
                     Err(e) => {
                         warn!(mapping = %mapping.name, error = %e, "Transform failed");
+                        handle_row_error(
+                            &state_store,
+                            mapping,
+                            event,
+                            &e,
+                            &mut consecutive_errors,
+                            strict,
+                            StrictCheck::Transform,
+                        )
+                        .await?;
                         continue;
                     }
                 };
+                consecutive_errors.remove(&mapping.name);
 
-                if !action.requires_write() {
+                if let Some(size) = action_size(&action) {
+                    doc_sizes.record(size);
+                }
+                if let Err(e) = mapping.limits.enforce(&mut action) {
+                    oversize_documents += 1;
+                    if mapping.limits.on_oversize == OversizeAction::Fail {
+                        anyhow::bail!(
+                            "Mapping '{}' produced an oversized document at LSN {} (on_oversize = \"fail\"): {}",
+                            mapping.name,
+                            format_lsn(event.lsn),
+                            e
+                        );
+                    }
+                    warn!(mapping = %mapping.name, error = %e, "Document over the size limit");
+                    send_to_dlq(&state_store, mapping, event, &e.to_string(), e.kind()).await?;
                     continue;
                 }
 
-                if let Some(full_batch) = batcher.add(&mapping.namespace, action, event.lsn) {
-                    let request = WriteRequest::from_batch(full_batch);
-                    if let Err(e) = flush_batch(
-                        &tp_client,
-                        &state_store,
+                // Hashed before the event time, which differs on every change
+                if matches!(mapping.versioning, VersioningMode::ContentHash) {
+                    action = action.with_content_hash();
+                }
+                if mapping.event_time {
+                    action = action.with_event_time(event.timestamp.as_deref());
+                }
+                if mapping.run_id {
+                    action = action.with_run_id(crate::run_id::current());
+                }
+
+                if action.requires_write() {
+                    if doc_hashes.observe(&namespace, &action) {
+                        debug!(
+                            mapping = %mapping.name,
+                            lsn = event.lsn,
+                            "Skipping unchanged document"
+                        );
+                        skipped_unchanged += 1;
+                    } else {
+                        writes.push((namespace, action));
+                    }
+                }
+
+                for (namespace, action) in writes {
+                    let Some(action) = delete_guard.hold(
+                        &mapping.name,
+                        &namespace,
+                        event,
+                        action,
+                        batch.continues,
+                    ) else {
+                        continue;
+                    };
+                    batch_write(
+                        batcher,
+                        &namespace,
+                        event,
+                        action,
+                        &mut pipeline,
                         &mapping.name,
-                        request,
-                        upload_batch_size,
-                        max_retries,
+                        &mut doc_hashes,
                     )
-                    .await
-                    {
-                        error!(mapping = %mapping.name, error = %e, "Failed to flush batch");
-                    }
+                    .await?;
                 }
             }
         }
 
+        // The whole transaction is under the threshold
+        for held in delete_guard.release(batch.continues) {
+            let Some(mapping) = mappings.iter().find(|m| m.name == held.mapping) else {
+                continue;
+            };
+            let batcher = batchers.entry(mapping.name.clone()).or_insert_with(|| {
+                TrackingBatcher::new(mapping.batch.apply(default_batch.clone()))
+            });
+            batch_write(
+                batcher,
+                &held.namespace,
+                &held.event,
+                held.action,
+                &mut pipeline,
+                &mapping.name,
+                &mut doc_hashes,
+            )
+            .await?;
+        }
+
         total_events += batch.events.len() as u64;
 
-        // Flush all pending batches
-        for (namespace, batcher) in &mut batchers {
-            for full_batch in batcher.flush_all() {
-                let request = WriteRequest::from_batch(full_batch);
-                let mapping_name = mappings
-                    .iter()
-                    .find(|m| &m.namespace == namespace)
-                    .map(|m| m.name.as_str())
-                    .unwrap_or(namespace);
-
-                if let Err(e) = flush_batch(
-                    &tp_client,
-                    &state_store,
-                    mapping_name,
-                    request,
-                    upload_batch_size,
-                    max_retries,
-                )
-                .await
-                {
-                    error!(namespace = %namespace, error = %e, "Failed to flush batch");
-                }
-            }
+        // Flush pending batches, except those still within their max wait
+        flush_batchers(
+            &mut batchers,
+            Some(Instant::now()),
+            &mut pipeline,
+            &mut doc_hashes,
+        )
+        .await?;
+
+        if let Some(delay) = faults.ack_delay() {
+            warn!(delay_ms = delay.as_millis() as u64, "Injected acknowledgment delay");
+            tokio::time::sleep(delay).await;
         }
 
         // Acknowledge after successful processing
-        stream.acknowledge(batch.ack_lsn);
+        unacked.push(batch.position);
+        acknowledge_written(
+            &mut stream,
+            &mut unacked,
+            &batchers,
+            &pipeline,
+            &pauses,
+            health,
+        );
 
         if total_events % 100 == 0 && total_events > 0 {
+            let writes = tp_client.stats();
             info!(
                 total_events = total_events,
                 lsn = format_lsn(stream.ack_lsn()),
+                tp_writes = writes.writes,
+                tp_rows_upserted = writes.rows_upserted,
+                tp_rows_patched = writes.rows_patched,
+                tp_rows_deleted = writes.rows_deleted,
+                tp_retries = writes.retries,
+                tp_rate_limited = writes.rate_limited,
+                tp_failures = writes.failures,
+                tp_requests = writes.requests,
+                skipped_unchanged,
+                doc_bytes_p50 = doc_sizes.quantile(0.5),
+                doc_bytes_p99 = doc_sizes.quantile(0.99),
+                doc_bytes_max = doc_sizes.max(),
+                oversize_documents,
                 "Progress"
             );
         }
     }
 
-    info!("Replication stream ended");
-    Ok(())
-}
+    flush_batchers(&mut batchers, None, &mut pipeline, &mut doc_hashes).await?;
+    pipeline.drain(&mut doc_hashes).await?;
+    acknowledge_written(
+        &mut stream,
+        &mut unacked,
+        &batchers,
+        &pipeline,
+        &pauses,
+        health,
+    );
+    drop(pipeline);
+    sinks.finish(Some(DRAIN_TIMEOUT)).await;
 
-async fn flush_batch(
-    client: &rs_puff::Client,
-    state_store: &PostgresStateStore,
-    mapping_name: &str,
-    request: WriteRequest,
-    upload_batch_size: usize,
-    max_retries: u32,
-) -> Result<()> {
-    let lsn = request.lsn;
-    let count = request.upserts.len() + request.deletes.len();
+    if !shutting_down && !reloading {
+        info!("Replication stream ended");
+        return Ok(LoopExit::Stopped);
+    }
 
-    if request.is_empty() {
-        return Ok(());
+    save_final_checkpoints(&state_store, &mappings, stream.ack_lsn()).await?;
+    if let Err(e) = stream.close(&control_client).await {
+        warn!(error = %e, "Failed to close the replication stream cleanly");
     }
+    if reloading {
+        info!(lsn = %format_lsn(stream.ack_lsn()), "Stopped streaming to reload");
+        return Ok(LoopExit::Reload);
+    }
+    info!(lsn = %format_lsn(stream.ack_lsn()), "Shut down");
+    Ok(LoopExit::Stopped)
+}
 
-    info!(
-        mapping = mapping_name,
-        namespace = %request.namespace,
-        upserts = request.upserts.len(),
-        deletes = request.deletes.len(),
-        lsn = lsn,
-        "Flushing batch"
-    );
+/// What woke the streaming loop.
+enum Wake<T> {
+    Batch(T),
+    Written(PendingWrite, Result<()>),
+    FlushDue,
+    Shutdown,
+    Reload,
+}
 
-    // Build all upsert rows
-    let all_upsert_rows: Vec<HashMap<String, serde_json::Value>> = request
-        .upserts
-        .iter()
-        .map(|doc| {
-            let mut row: HashMap<String, serde_json::Value> = doc
-                .attributes
-                .iter()
-                .map(|(k, v)| (k.clone(), convert_value_to_json(v)))
-                .collect();
-            row.insert("id".to_string(), convert_doc_id_to_json(&doc.id));
-            row.insert(
-                "__source_lsn".to_string(),
-                serde_json::Value::Number(request.lsn.into()),
-            );
-            row
-        })
-        .collect();
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!(error = %e, "Can't listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    // Build all delete IDs
-    let all_deletes: Vec<serde_json::Value> =
-        request.deletes.iter().map(convert_doc_id_to_json).collect();
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!(error = %e, "Can't listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
 
-    // Upload in chunks, combining upserts and deletes in each call
-    // First chunk includes all deletes (they're small - just IDs)
-    let mut upsert_chunks = all_upsert_rows.chunks(upload_batch_size).peekable();
-    let mut deletes_sent = false;
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
 
-    // Handle case with only deletes (no upserts)
-    if all_upsert_rows.is_empty() && !all_deletes.is_empty() {
-        let params = rs_puff::WriteParams {
-            upsert_rows: None,
-            deletes: Some(all_deletes),
-            distance_metric: request.distance_metric,
-            ..Default::default()
+/// Move each mapping's checkpoint up to the last acknowledged LSN, so ones
+/// that had nothing to write lately resume from there too.
+async fn save_final_checkpoints(
+    state_store: &dyn StateStore,
+    mappings: &[Mapping],
+    lsn: u64,
+) -> Result<()> {
+    for mapping in mappings {
+        let Some(mut checkpoint) = state_store.get_checkpoint(&mapping.name).await? else {
+            continue;
         };
-        write_with_retry(client, &request.namespace, params, max_retries).await?;
-    } else {
-        // Send upserts in chunks, include deletes with first chunk
-        while let Some(chunk) = upsert_chunks.next() {
-            let params = rs_puff::WriteParams {
-                upsert_rows: Some(chunk.to_vec()),
-                deletes: if !deletes_sent && !all_deletes.is_empty() {
-                    deletes_sent = true;
-                    Some(all_deletes.clone())
-                } else {
-                    None
-                },
-                distance_metric: request.distance_metric,
-                ..Default::default()
-            };
-            write_with_retry(client, &request.namespace, params, max_retries).await?;
+        if checkpoint.lsn < lsn {
+            checkpoint.lsn = lsn;
+            state_store
+                .save_checkpoint(&mapping.name, &checkpoint)
+                .await
+                .context("Failed to save checkpoint")?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the batches that are due: every batch when `now` is `None`,
+/// otherwise those of mappings without a `max_wait_ms` and those that have
+/// waited it out.
+async fn flush_batchers(
+    batchers: &mut HashMap<String, TrackingBatcher<RowEvent>>,
+    now: Option<Instant>,
+    pipeline: &mut WritePipeline<'_>,
+    doc_hashes: &mut DocHashCache,
+) -> Result<()> {
+    for (mapping_name, batcher) in batchers.iter_mut() {
+        let due = match now {
+            Some(now) => batcher.flush_due(now),
+            None => batcher.flush_all(),
+        };
+        for (full_batch, events) in due {
+            pipeline
+                .submit(mapping_name, full_batch, events, doc_hashes)
+                .await?;
         }
     }
+    Ok(())
+}
 
-    // Update checkpoint
-    let mut checkpoint = state_store
-        .get_checkpoint(mapping_name)
-        .await?
-        .unwrap_or_default();
+/// Acknowledge the transactions whose changes have all been written.
+fn acknowledge_written(
+    stream: &mut ReplicationStream,
+    unacked: &mut PendingAcks,
+    batchers: &HashMap<String, TrackingBatcher<RowEvent>>,
+    pipeline: &WritePipeline<'_>,
+    pauses: &Pauses,
+    health: &Health,
+) {
+    if let Some(position) = written_position(unacked, batchers, pipeline, pauses) {
+        // A dry run leaves the slot where it found it
+        if pipeline.dry_run.is_none() {
+            stream.acknowledge(position);
+        }
+        health.acknowledged(position);
+    }
+}
+
+/// The latest processed position whose changes have all been written, if it
+/// moved: none of its changes is still batched, in flight or held by a pause.
+fn written_position(
+    unacked: &mut PendingAcks,
+    batchers: &HashMap<String, TrackingBatcher<RowEvent>>,
+    pipeline: &WritePipeline<'_>,
+    pauses: &Pauses,
+) -> Option<u64> {
+    let oldest = batchers
+        .values()
+        .filter_map(TrackingBatcher::oldest_lsn)
+        .chain(pipeline.oldest_lsn())
+        .chain(pauses.oldest_held())
+        .min();
+    unacked.release(oldest)
+}
+
+/// Source positions processed but not yet acknowledged, in order.
+#[derive(Default)]
+struct PendingAcks {
+    positions: VecDeque<u64>,
+}
+
+impl PendingAcks {
+    fn push(&mut self, position: u64) {
+        self.positions.push_back(position);
+    }
+
+    /// The latest position that's safe to acknowledge while the earliest
+    /// change still waiting to be written is at `oldest_pending`. A change
+    /// comes before its transaction's commit position, so every position
+    /// before the change's was fully written.
+    fn release(&mut self, oldest_pending: Option<u64>) -> Option<u64> {
+        let mut released = None;
+        while let Some(&position) = self.positions.front() {
+            if oldest_pending.is_some_and(|lsn| position >= lsn) {
+                break;
+            }
+            released = self.positions.pop_front();
+        }
+        released
+    }
+}
+
+/// The mappings paused with `puffgres pause`, as of the last check.
+///
+/// A paused mapping's changes are skipped, and nothing from the first one on
+/// is acknowledged, so the slot keeps them. Once the mapping resumes, the
+/// stream is reopened from the last acknowledgment to replay them.
+#[derive(Default)]
+struct Pauses {
+    paused: HashSet<String>,
+    /// The first change skipped for each paused mapping.
+    held: HashMap<String, u64>,
+    checked_at: Option<Instant>,
+}
+
+impl Pauses {
+    fn is_paused(&self, mapping: &str) -> bool {
+        self.paused.contains(mapping)
+    }
+
+    /// Record that a paused mapping skipped its change at `lsn`.
+    fn hold(&mut self, mapping: &str, lsn: u64) {
+        self.held.entry(mapping.to_string()).or_insert(lsn);
+    }
+
+    /// The first change any paused mapping skipped.
+    fn oldest_held(&self) -> Option<u64> {
+        self.held.values().min().copied()
+    }
+
+    /// Read the paused mappings again once they're due a check at `now`.
+    /// Returns whether a mapping that skipped changes was resumed, so they
+    /// have to be replayed. A failed read keeps the last flags.
+    async fn refresh(
+        &mut self,
+        state_store: &dyn StateStore,
+        mappings: &[Mapping],
+        health: &Health,
+        now: Instant,
+    ) -> bool {
+        if self
+            .checked_at
+            .is_some_and(|at| now.duration_since(at) < PAUSE_CHECK_INTERVAL)
+        {
+            return false;
+        }
+        self.checked_at = Some(now);
+        match state_store.get_paused_mappings().await {
+            Ok(names) => {
+                let replay = self.update(
+                    names
+                        .into_iter()
+                        .filter(|name| mappings.iter().any(|m| &m.name == name))
+                        .collect(),
+                );
+                health.set_paused(self.paused.iter().map(String::as_str));
+                replay
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to check for paused mappings");
+                false
+            }
+        }
+    }
+
+    /// Apply a new set of paused mappings. Returns whether a mapping that
+    /// skipped changes was resumed.
+    fn update(&mut self, paused: HashSet<String>) -> bool {
+        for name in paused.difference(&self.paused) {
+            warn!(mapping = %name, "Mapping paused; skipping its changes until it resumes");
+        }
+        let mut replay = false;
+        for name in self.paused.difference(&paused) {
+            info!(mapping = %name, "Mapping resumed");
+            replay |= self.held.remove(name).is_some();
+        }
+        self.paused = paused;
+        replay
+    }
+}
+
+/// Counts each mapping's deletes in the transaction being processed, to
+/// stop before a mass delete reaches turbopuffer. A large transaction arrives
+/// in several batches, so counts carry over while the transaction id stays
+/// the same, and its deletes are held until the last batch, when the count
+/// for the whole transaction is known.
+#[derive(Debug, Default)]
+struct DeleteGuard {
+    /// `None` when mass deletes are allowed.
+    threshold: Option<usize>,
+    txid: Option<u64>,
+    deletes: HashMap<String, usize>,
+    held: Vec<HeldDelete>,
+}
+
+/// A delete held until the rest of its transaction has been counted.
+#[derive(Debug)]
+struct HeldDelete {
+    mapping: String,
+    namespace: String,
+    event: RowEvent,
+    action: Action,
+}
+
+impl DeleteGuard {
+    fn new(threshold: Option<usize>) -> Self {
+        Self {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Forget the transaction being counted, which is sent again from its
+    /// start after the stream reconnects.
+    fn reset(&mut self) {
+        self.txid = None;
+        self.deletes.clear();
+        self.held.clear();
+    }
+
+    /// Hold `action` if it's a delete in a transaction that continues in a
+    /// later batch. Returns the action when it should be written now. A
+    /// document upserted after its delete was held replaces the delete,
+    /// which would otherwise land after it.
+    fn hold(
+        &mut self,
+        mapping: &str,
+        namespace: &str,
+        event: &RowEvent,
+        action: Action,
+        continues: bool,
+    ) -> Option<Action> {
+        if self.threshold.is_none() || (!continues && self.held.is_empty()) {
+            return Some(action);
+        }
+        match &action {
+            Action::Delete { .. } => {
+                self.held.push(HeldDelete {
+                    mapping: mapping.to_string(),
+                    namespace: namespace.to_string(),
+                    event: event.clone(),
+                    action,
+                });
+                None
+            }
+            Action::Upsert { id, .. } => {
+                self.held.retain(|held| {
+                    held.namespace != namespace
+                        || !matches!(&held.action, Action::Delete { id: deleted } if deleted == id)
+                });
+                Some(action)
+            }
+            _ => Some(action),
+        }
+    }
+
+    /// The deletes held for a transaction, once its last batch has been
+    /// processed.
+    fn release(&mut self, continues: bool) -> Vec<HeldDelete> {
+        if continues {
+            Vec::new()
+        } else {
+            std::mem::take(&mut self.held)
+        }
+    }
+
+    /// Count the deletes a batch makes: deleted rows, and rows an update
+    /// took out of a mapping. Returns a mapping whose deletes in the
+    /// transaction are over the threshold, with their count. Paused
+    /// mappings aren't written, so they're counted once they resume.
+    fn count(
+        &mut self,
+        router: &Router,
+        pauses: &Pauses,
+        events: &[RowEvent],
+    ) -> Option<(String, usize)> {
+        let threshold = self.threshold?;
+        let txid = events.first().and_then(|e| e.txid);
+        if txid.is_none() || txid != self.txid {
+            self.deletes.clear();
+            self.txid = txid;
+        }
+
+        for event in events
+            .iter()
+            .filter(|e| matches!(e.op, Operation::Delete | Operation::Update))
+        {
+            for routed in router.route_to_namespaces(event) {
+                if (event.op == Operation::Delete || routed.left_membership)
+                    && !pauses.is_paused(&routed.mapping.name)
+                {
+                    *self.deletes.entry(routed.mapping.name.clone()).or_default() += 1;
+                }
+            }
+        }
+        self.deletes
+            .iter()
+            .filter(|(_, &deletes)| deletes > threshold)
+            .min_by(|a, b| a.0.cmp(b.0))
+            .map(|(name, &deletes)| (name.clone(), deletes))
+    }
+}
+
+/// Apply a mapping's error policy to a row that couldn't be turned into a
+/// document. Returns an error when the runner should stop; the transaction
+/// isn't acknowledged, so it's replayed after a restart.
+///
+/// Strict mode stops on the first failure of an enabled `check`, before the
+/// mapping's policy is applied.
+async fn handle_row_error(
+    state_store: &dyn StateStore,
+    mapping: &Mapping,
+    event: &RowEvent,
+    error: &puffgres_core::Error,
+    consecutive_errors: &mut HashMap<String, u32>,
+    strict: &StrictMode,
+    check: StrictCheck,
+) -> Result<()> {
+    if strict.enabled(check) {
+        anyhow::bail!(
+            "Mapping '{}' failed on the row at LSN {} (strict mode: {}): {}",
+            mapping.name,
+            format_lsn(event.lsn),
+            check,
+            error
+        );
+    }
 
-    checkpoint.lsn = lsn;
-    checkpoint.events_processed += count as u64;
+    let consecutive = consecutive_errors.entry(mapping.name.clone()).or_default();
+    *consecutive += 1;
+    let consecutive = *consecutive;
 
-    state_store
-        .save_checkpoint(mapping_name, &checkpoint)
+    if mapping.errors.on_transform_error == TransformErrorAction::Dlq {
+        send_to_dlq(
+            state_store,
+            mapping,
+            event,
+            &error.to_string(),
+            error.kind(),
+        )
+        .await?;
+    }
+
+    if mapping.errors.should_halt(consecutive) {
+        if mapping.errors.on_transform_error == TransformErrorAction::Halt {
+            anyhow::bail!(
+                "Mapping '{}' failed on the row at LSN {} (on_transform_error = \"halt\"): {}",
+                mapping.name,
+                format_lsn(event.lsn),
+                error
+            );
+        }
+        anyhow::bail!(
+            "Mapping '{}' failed on {} rows in a row (max_consecutive_errors), last at LSN {}: {}",
+            mapping.name,
+            consecutive,
+            format_lsn(event.lsn),
+            error
+        );
+    }
+
+    Ok(())
+}
+
+/// Apply a mapping's error policy to the events of a batch that couldn't be
+/// written. A failure that's still transient after all retries (turbopuffer
+/// down or rate limiting, a dropped connection) stops the runner without
+/// acknowledging, so the batch is written again after a restart. Otherwise
+/// the events go to the dead letter queue unless the policy is `halt`: unlike
+/// a row that fails to transform, skipping them would lose changes that are
+/// fine.
+async fn handle_write_error(
+    state_store: &dyn StateStore,
+    mapping: &Mapping,
+    events: &[RowEvent],
+    error: &anyhow::Error,
+) -> Result<()> {
+    let kind = error_kind(error);
+    if kind.is_retryable() {
+        anyhow::bail!(
+            "Mapping '{}' failed to write a batch of {} events ({}), stopping without acknowledging it: {:#}",
+            mapping.name,
+            events.len(),
+            kind.as_str(),
+            error
+        );
+    }
+    if mapping.errors.on_transform_error == TransformErrorAction::Halt {
+        anyhow::bail!(
+            "Mapping '{}' failed to write a batch of {} events (on_transform_error = \"halt\"): {:#}",
+            mapping.name,
+            events.len(),
+            error
+        );
+    }
+
+    let message = format!("{:#}", error);
+    for event in events {
+        send_to_dlq(state_store, mapping, event, &message, kind).await?;
+    }
+    Ok(())
+}
+
+/// Record a row in the dead letter queue.
+async fn send_to_dlq(
+    state_store: &dyn StateStore,
+    mapping: &Mapping,
+    event: &RowEvent,
+    message: &str,
+    kind: ErrorKind,
+) -> Result<()> {
+    let event_json = serde_json::to_value(event)?;
+    let dlq_id = state_store
+        .add_to_dlq(
+            &mapping.name,
+            event.lsn,
+            &event_json,
+            message,
+            kind.as_str(),
+            Some(crate::run_id::current()),
+        )
         .await
-        .context("Failed to save checkpoint")?;
+        .context("Failed to write to the dead letter queue")?;
+    info!(
+        mapping = %mapping.name,
+        lsn = event.lsn,
+        dlq_id,
+        "Sent row to the dead letter queue"
+    );
+    Ok(())
+}
 
+/// Stop if another runner has taken the slot lock, before anything else is
+/// written or acknowledged.
+fn ensure_slot_lock(lock: &SlotLock) -> Result<()> {
+    if !lock.is_held() {
+        anyhow::bail!(
+            "Lost the lock on slot '{}', most likely to a runner started with --force-takeover; stopping",
+            lock.slot_name()
+        );
+    }
     Ok(())
 }
 
-/// Write to turbopuffer with exponential backoff retry.
-async fn write_with_retry(
-    client: &rs_puff::Client,
-    namespace: &str,
-    params: rs_puff::WriteParams,
-    max_retries: u32,
+/// Re-open the replication stream after the connection dropped, e.g. when a
+/// standby restarts or is promoted. Resumes from the last acknowledged LSN.
+async fn reconnect(
+    stream: &mut ReplicationStream,
+    control_client: &mut tokio_postgres::Client,
+    replication_url: &str,
 ) -> Result<()> {
-    let base_delay_ms = 100u64;
+    for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+        let delay = reconnect_delay(attempt);
+        debug!(
+            attempt,
+            delay_ms = delay.as_millis() as u64,
+            "Waiting to reconnect"
+        );
+        tokio::time::sleep(delay).await;
 
-    for attempt in 0..=max_retries {
-        match client.namespace(namespace).write(params.clone()).await {
-            Ok(_) => return Ok(()),
-            Err(e) => {
-                if attempt == max_retries {
-                    return Err(e).context("Failed to write to turbopuffer after all retries");
+        if control_client.is_closed() {
+            match connect_postgres(replication_url).await {
+                Ok(client) => *control_client = client,
+                Err(e) if !e.is_retryable() => {
+                    return Err(anyhow::Error::new(e).context("Failed to reconnect to Postgres"));
+                }
+                Err(e) => {
+                    warn!(attempt, error = %e, "Failed to reconnect control connection");
+                    continue;
                 }
+            }
+        }
 
-                let delay_ms = base_delay_ms * (1 << attempt);
+        match stream.reconnect(control_client).await {
+            Ok(change) => {
+                info!(
+                    attempt,
+                    lsn = %format_lsn(stream.ack_lsn()),
+                    timeline_change = ?change,
+                    "Replication stream reconnected"
+                );
+                return Ok(());
+            }
+            // A promoted standby may not have synced the slot yet
+            Err(e) if !e.is_retryable() && !matches!(e, PgError::SlotNotFound(_)) => {
+                return Err(anyhow::Error::new(e).context("Failed to reconnect replication stream"));
+            }
+            Err(e) => {
                 warn!(
-                    namespace = namespace,
-                    attempt = attempt + 1,
-                    max_retries,
-                    delay_ms,
+                    attempt,
+                    max_attempts = MAX_RECONNECT_ATTEMPTS,
                     error = %e,
-                    "Turbopuffer write failed, retrying"
+                    "Failed to reconnect replication stream"
                 );
+            }
+        }
+    }
+
+    anyhow::bail!(
+        "Replication connection lost and could not be re-established after {} attempts",
+        MAX_RECONNECT_ATTEMPTS
+    )
+}
+
+/// Whether a truncate of the mapping's table should clear its namespace.
+/// Fails if the mapping doesn't allow truncates, or in strict mode when the
+/// truncate would be skipped.
+fn truncate_clears(mapping: &Mapping, event: &RowEvent, strict: &StrictMode) -> Result<bool> {
+    let table = format!("{}.{}", event.schema, event.table);
+    match mapping.on_truncate {
+        TruncateAction::Clear => {
+            info!(mapping = %mapping.name, table = %table, "Clearing namespace after TRUNCATE");
+            Ok(true)
+        }
+        TruncateAction::Error => anyhow::bail!(
+            "Mapping '{}' can't replicate the TRUNCATE of {} at LSN {} (on_truncate = \"error\")",
+            mapping.name,
+            table,
+            format_lsn(event.lsn)
+        ),
+        TruncateAction::Ignore if strict.enabled(StrictCheck::Truncate) => anyhow::bail!(
+            "Mapping '{}' skipped the TRUNCATE of {} at LSN {} (strict mode: truncate)",
+            mapping.name,
+            table,
+            format_lsn(event.lsn)
+        ),
+        TruncateAction::Ignore => {
+            warn!(
+                mapping = %mapping.name,
+                table = %table,
+                "Skipping TRUNCATE; truncated rows stay in turbopuffer"
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// Add a write to the mapping's batch for its namespace, writing the batch
+/// once it's full.
+async fn batch_write(
+    batcher: &mut TrackingBatcher<RowEvent>,
+    namespace: &str,
+    event: &RowEvent,
+    action: Action,
+    pipeline: &mut WritePipeline<'_>,
+    mapping_name: &str,
+    doc_hashes: &mut DocHashCache,
+) -> Result<()> {
+    let Some((full_batch, events)) = batcher.add(namespace, event.clone(), action, event.lsn)
+    else {
+        return Ok(());
+    };
+    pipeline
+        .submit(mapping_name, full_batch, events, doc_hashes)
+        .await
+}
+
+/// A batch being written, with the events it holds for the dead letter
+/// queue if it fails.
+struct PendingWrite {
+    ticket: WriteTicket,
+    mapping: String,
+    namespace: String,
+    lsn: u64,
+    count: usize,
+    events: Vec<RowEvent>,
+}
+
+/// Writes batches to turbopuffer with up to `PUFFGRES_WRITE_CONCURRENCY`
+/// writes in flight per namespace, so the next transaction is read and
+/// transformed while earlier batches are on the network. Each write runs as
+/// its own task, so it makes progress while the runner is busy transforming;
+/// dropping the pipeline aborts them. A mapping's checkpoint, and the slot's
+/// acknowledgment, only move past a change once every write holding a
+/// change up to it has landed.
+///
+/// In a dry run nothing is written: each batch is handed to `dry_run` and
+/// lands at once.
+struct WritePipeline<'a> {
+    mappings: &'a [Mapping],
+    client: Arc<TurbopufferClient>,
+    sinks: &'a SinkWriters,
+    embedder: Option<Arc<EmbeddingClient>>,
+    state_store: &'a dyn StateStore,
+    upload_batch_size: usize,
+    faults: &'a FaultInjector,
+    health: &'a Health,
+    in_flight: InFlightWrites,
+    /// The writes in flight. Each returns its request once it has landed,
+    /// for the sinks.
+    tasks: JoinSet<Result<WriteRequest>>,
+    writes: HashMap<TaskId, PendingWrite>,
+    dry_run: Option<DryRun>,
+}
+
+impl<'a> WritePipeline<'a> {
+    /// Start writing a batch once its namespace has room and no write in
+    /// flight touches its documents, settling the writes that land meanwhile.
+    async fn submit(
+        &mut self,
+        mapping_name: &str,
+        batch: Batch,
+        events: Vec<RowEvent>,
+        doc_hashes: &mut DocHashCache,
+    ) -> Result<()> {
+        let mappings = self.mappings;
+        let Some(mapping) = mappings.iter().find(|m| m.name == mapping_name) else {
+            return Ok(());
+        };
+        let mut request = WriteRequest::from_batch(batch);
+        if request.is_empty() {
+            return Ok(());
+        }
+        while !self.in_flight.can_start(&request) {
+            let (write, result) = self.next().await;
+            self.settle(write, result, doc_hashes).await?;
+        }
 
-                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        let pending = PendingWrite {
+            ticket: self.in_flight.start(&request),
+            mapping: mapping.name.clone(),
+            namespace: request.namespace.clone(),
+            lsn: request.lsn,
+            count: request.upserts.len() + request.patches.len() + request.deletes.len(),
+            events,
+        };
+        let (client, embedder) = (Arc::clone(&self.client), self.embedder.clone());
+        let mapping = mapping.clone();
+        let upload_batch_size = self.upload_batch_size;
+        let dry_run = self.dry_run;
+        let write = async move {
+            if dry_run.is_none() {
+                embed_request(embedder.as_deref(), &mapping, &mut request).await?;
+            }
+            info!(
+                mapping = %mapping.name,
+                namespace = %request.namespace,
+                upserts = request.upserts.len(),
+                patches = request.patches.len(),
+                deletes = request.deletes.len(),
+                clear = request.clear,
+                lsn = request.lsn,
+                "Flushing batch"
+            );
+            match dry_run {
+                Some(DryRun::Stdout) => {
+                    println!("{}", dry_run_line(&mapping, &request));
+                    return Ok(request);
+                }
+                Some(DryRun::Null) => return Ok(request),
+                None => {}
             }
+            write_request(
+                &client,
+                &request,
+                mapping.source_lsn_attribute.as_deref(),
+                upload_batch_size,
+            )
+            .await?;
+            Ok(request)
+        };
+        let task = self.tasks.spawn(write.in_current_span());
+        self.writes.insert(task.id(), pending);
+        Ok(())
+    }
+
+    /// Wait for a write in flight to land or fail. Never resolves while
+    /// nothing is in flight.
+    async fn next(&mut self) -> (PendingWrite, Result<WriteRequest>) {
+        let Some(joined) = self.tasks.join_next_with_id().await else {
+            return std::future::pending().await;
+        };
+        let (id, result) = match joined {
+            Ok((id, result)) => (id, result),
+            Err(e) => (
+                e.id(),
+                Err(anyhow::Error::new(e).context("Write task panicked")),
+            ),
+        };
+        let write = self
+            .writes
+            .remove(&id)
+            .expect("every write task has a pending write");
+        (write, result)
+    }
+
+    /// Move the mapping's checkpoint after a write landed, or apply its error
+    /// policy after it failed.
+    async fn settle(
+        &mut self,
+        write: PendingWrite,
+        result: Result<WriteRequest>,
+        doc_hashes: &mut DocHashCache,
+    ) -> Result<()> {
+        self.in_flight.finish(write.ticket);
+        let mappings = self.mappings;
+        let Some(mapping) = mappings.iter().find(|m| m.name == write.mapping) else {
+            return Ok(());
+        };
+        let request = match result {
+            Ok(request) => request,
+            Err(e) => {
+                error!(namespace = %write.namespace, error = %e, "Failed to flush batch");
+                // The documents the cache remembers may not have been written
+                doc_hashes.clear();
+                return handle_write_error(self.state_store, mapping, &write.events, &e).await;
+            }
+        };
+        self.sinks.offer(&mapping.name, &request);
+
+        self.faults
+            .maybe_crash("after turbopuffer write, before checkpoint");
+
+        // The mapping's earlier changes may still be in flight
+        let lsn = self
+            .writes
+            .values()
+            .filter(|pending| pending.mapping == write.mapping)
+            .map(|pending| pending.lsn)
+            .fold(write.lsn, u64::min);
+        let mut checkpoint = self
+            .state_store
+            .get_checkpoint(&mapping.name)
+            .await?
+            .unwrap_or_default();
+        checkpoint.lsn = lsn;
+        checkpoint.events_processed += write.count as u64;
+        self.state_store
+            .save_checkpoint(&mapping.name, &checkpoint)
+            .await
+            .context("Failed to save checkpoint")?;
+        self.health.flushed(&mapping.name, lsn);
+        Ok(())
+    }
+
+    /// Wait for every write in flight and settle it.
+    async fn drain(&mut self, doc_hashes: &mut DocHashCache) -> Result<()> {
+        while !self.writes.is_empty() {
+            let (write, result) = self.next().await;
+            self.settle(write, result, doc_hashes).await?;
+        }
+        Ok(())
+    }
+
+    /// The LSN of the earliest change in flight.
+    fn oldest_lsn(&self) -> Option<u64> {
+        self.in_flight.oldest_lsn()
+    }
+}
+
+/// A [`Batcher`] that tracks where each batched action came from (a row event,
+/// a DLQ entry), so a batch's sources can be settled once it's written or
+/// has failed.
+pub(crate) struct TrackingBatcher<T> {
+    batcher: Batcher,
+    pending: HashMap<String, Vec<T>>,
+}
+
+impl<T> TrackingBatcher<T> {
+    pub(crate) fn new(config: BatchConfig) -> Self {
+        Self {
+            batcher: Batcher::new(config),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Add an action and its source. Returns a full batch and the sources
+    /// of the actions in it.
+    pub(crate) fn add(
+        &mut self,
+        namespace: &str,
+        source: T,
+        action: Action,
+        lsn: u64,
+    ) -> Option<(Batch, Vec<T>)> {
+        let ready = self.batcher.add(namespace, action, lsn);
+        let pending = self.pending.entry(namespace.to_string()).or_default();
+        // A full batch holds everything pending except the action just added
+        let ready = ready.map(|batch| (batch, std::mem::take(pending)));
+        pending.push(source);
+        ready
+    }
+
+    /// Flush the batches due at `now` with the sources of their actions:
+    /// every batch without a `max_wait_ms`, otherwise those that have waited
+    /// that long.
+    pub(crate) fn flush_due(&mut self, now: Instant) -> Vec<(Batch, Vec<T>)> {
+        let batches = match self.batcher.config().max_wait_ms {
+            Some(_) => self.batcher.flush_expired(now),
+            None => self.batcher.flush_all(),
+        };
+        self.with_sources(batches)
+    }
+
+    /// When the oldest held batch is due.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.batcher.next_deadline()
+    }
+
+    /// The LSN of the earliest pending batch.
+    pub(crate) fn oldest_lsn(&self) -> Option<u64> {
+        self.batcher.oldest_lsn()
+    }
+
+    /// Flush every batch with the sources of its actions.
+    pub(crate) fn flush_all(&mut self) -> Vec<(Batch, Vec<T>)> {
+        let batches = self.batcher.flush_all();
+        self.with_sources(batches)
+    }
+
+    fn with_sources(&mut self, batches: Vec<Batch>) -> Vec<(Batch, Vec<T>)> {
+        batches
+            .into_iter()
+            .map(|batch| {
+                let sources = self.pending.remove(&batch.namespace).unwrap_or_default();
+                (batch, sources)
+            })
+            .collect()
+    }
+}
+
+/// Write a batch's upserts, patches and deletes to turbopuffer in chunks of
+/// `upload_batch_size` rows that stay under turbopuffer's request size limit,
+/// without touching any checkpoint. A batch that clears
+/// the namespace does that first. Upserts and patches carry the batch's LSN
+/// in `source_lsn_attribute`, if any.
+pub(crate) async fn write_request(
+    client: &TurbopufferClient,
+    request: &WriteRequest,
+    source_lsn_attribute: Option<&str>,
+    upload_batch_size: usize,
+) -> Result<()> {
+    if request.clear {
+        client.clear(&request.namespace).await?;
+    }
+
+    let RequestRows {
+        upserts: all_upsert_rows,
+        patches: all_patch_rows,
+        deletes: all_deletes,
+    } = RequestRows::new(request, source_lsn_attribute);
+    let (upsert_sizes, patch_sizes) = row_sizes(request);
+
+    // Upload in chunks: upserts first, then patches, so a patch lands on
+    // top of any upsert in the same batch. The first call includes all
+    // deletes (they're small - just IDs)
+    let upserts = chunk_rows(&all_upsert_rows, &upsert_sizes, upload_batch_size)
+        .into_iter()
+        .map(|chunk| rs_puff::WriteParams {
+            upsert_rows: Some(chunk.to_vec()),
+            distance_metric: request.distance_metric,
+            ..Default::default()
+        });
+    let patches = chunk_rows(&all_patch_rows, &patch_sizes, upload_batch_size)
+        .into_iter()
+        .map(|chunk| rs_puff::WriteParams {
+            patch_rows: Some(chunk.to_vec()),
+            ..Default::default()
+        });
+    let mut calls: Vec<rs_puff::WriteParams> = upserts.chain(patches).collect();
+
+    if !all_deletes.is_empty() {
+        match calls.first_mut() {
+            Some(first) => first.deletes = Some(all_deletes),
+            // Only deletes
+            None => calls.push(rs_puff::WriteParams {
+                deletes: Some(all_deletes),
+                distance_metric: request.distance_metric,
+                ..Default::default()
+            }),
+        }
+    }
+
+    for params in calls {
+        client.write(&request.namespace, params).await?;
+    }
+
+    Ok(())
+}
+
+/// Estimated JSON size of each of `request`'s upserts and patches, in the
+/// order they become rows. Measured with [`document_size`], which estimates
+/// vectors, rather than by serialising every row just to size it.
+pub(crate) fn row_sizes(request: &WriteRequest) -> (Vec<usize>, Vec<usize>) {
+    let upserts = request
+        .upserts
+        .iter()
+        .map(|doc| document_size(&doc.attributes, doc.vector.as_deref()))
+        .collect();
+    let patches = request
+        .patches
+        .iter()
+        .map(|patch| document_size(&patch.attributes, None))
+        .collect();
+    (upserts, patches)
+}
+
+/// Split rows into chunks of at most `max_rows`, each under
+/// [`MAX_WRITE_BYTES`] of JSON by the rows' `sizes`. A row over the limit
+/// goes on its own, for turbopuffer to reject.
+pub(crate) fn chunk_rows<'r>(
+    rows: &'r [HashMap<String, serde_json::Value>],
+    sizes: &[usize],
+    max_rows: usize,
+) -> Vec<&'r [HashMap<String, serde_json::Value>]> {
+    split_by_size(rows, sizes, max_rows, MAX_WRITE_BYTES)
+}
+
+/// Split `items` into chunks of at most `max_items`, whose `sizes` add up to
+/// at most `max_bytes`. `sizes` holds the size of each item.
+fn split_by_size<'t, T>(
+    items: &'t [T],
+    sizes: &[usize],
+    max_items: usize,
+    max_bytes: usize,
+) -> Vec<&'t [T]> {
+    debug_assert_eq!(items.len(), sizes.len());
+    let max_items = max_items.max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    for (i, &item_bytes) in sizes.iter().enumerate() {
+        if i > start && (i - start >= max_items || bytes + item_bytes > max_bytes) {
+            chunks.push(&items[start..i]);
+            start = i;
+            bytes = 0;
+        }
+        bytes += item_bytes;
+    }
+    if start < items.len() {
+        chunks.push(&items[start..]);
+    }
+    chunks
+}
+
+/// A write request as turbopuffer rows.
+struct RequestRows {
+    upserts: Vec<HashMap<String, serde_json::Value>>,
+    patches: Vec<HashMap<String, serde_json::Value>>,
+    deletes: Vec<serde_json::Value>,
+}
+
+impl RequestRows {
+    /// The rows `request` writes. Upserts and patches carry the batch's LSN
+    /// in `source_lsn_attribute`, if any.
+    fn new(request: &WriteRequest, source_lsn_attribute: Option<&str>) -> Self {
+        let source_lsn = |row: &mut HashMap<String, serde_json::Value>| {
+            if let Some(attribute) = source_lsn_attribute {
+                row.insert(
+                    attribute.to_string(),
+                    serde_json::Value::Number(request.lsn.into()),
+                );
+            }
+        };
+
+        let upserts = request
+            .upserts
+            .iter()
+            .map(|doc| {
+                let mut row: HashMap<String, serde_json::Value> = doc
+                    .attributes
+                    .iter()
+                    .map(|(k, v)| (k.clone(), convert_value_to_json(v)))
+                    .collect();
+                if let Some(vector) = &doc.vector {
+                    row.insert(VECTOR_ATTRIBUTE.to_string(), serde_json::json!(vector));
+                }
+                row.insert("id".to_string(), convert_doc_id_to_json(&doc.id));
+                source_lsn(&mut row);
+                row
+            })
+            .collect();
+
+        let patches = request
+            .patches
+            .iter()
+            .map(|patch| {
+                let mut row: HashMap<String, serde_json::Value> = patch
+                    .attributes
+                    .iter()
+                    .map(|(k, v)| (k.clone(), convert_value_to_json(v)))
+                    .collect();
+                row.insert("id".to_string(), convert_doc_id_to_json(&patch.id));
+                source_lsn(&mut row);
+                row
+            })
+            .collect();
+
+        Self {
+            upserts,
+            patches,
+            deletes: request.deletes.iter().map(convert_doc_id_to_json).collect(),
         }
     }
+}
+
+/// The line `puffgres run --dry-run` prints for a write request: the rows
+/// it would write, as one JSON object.
+fn dry_run_line(mapping: &Mapping, request: &WriteRequest) -> String {
+    let rows = RequestRows::new(request, mapping.source_lsn_attribute.as_deref());
+    serde_json::json!({
+        "mapping": mapping.name,
+        "namespace": request.namespace,
+        "lsn": format_lsn(request.lsn),
+        "clear": request.clear,
+        "upserts": rows.upserts,
+        "patches": rows.patches,
+        "deletes": rows.deletes,
+    })
+    .to_string()
+}
 
-    unreachable!()
+/// Delay before the given reconnect attempt (1-based): doubles from
+/// `RECONNECT_BASE_DELAY` up to `RECONNECT_MAX_DELAY`.
+fn reconnect_delay(attempt: u32) -> Duration {
+    let factor = 1u32
+        .checked_shl(attempt.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    RECONNECT_BASE_DELAY
+        .checked_mul(factor)
+        .unwrap_or(RECONNECT_MAX_DELAY)
+        .min(RECONNECT_MAX_DELAY)
 }
 
-fn convert_doc_id_to_json(id: &DocumentId) -> serde_json::Value {
+pub(crate) fn convert_doc_id_to_json(id: &DocumentId) -> serde_json::Value {
     match id {
         DocumentId::Uint(u) => serde_json::Value::Number((*u).into()),
         DocumentId::Int(i) => serde_json::Value::Number((*i).into()),
-        DocumentId::Uuid(s) | DocumentId::String(s) => serde_json::Value::String(s.clone()),
+        DocumentId::String(s) => serde_json::Value::String(s.clone()),
+        DocumentId::Uuid(_) | DocumentId::Bytes(_) => serde_json::Value::String(id.to_string()),
     }
 }
 
-fn convert_value_to_json(value: &Value) -> serde_json::Value {
+pub(crate) fn convert_value_to_json(value: &Value) -> serde_json::Value {
     match value {
         Value::Null => serde_json::Value::Null,
         Value::Bool(b) => serde_json::Value::Bool(*b),
@@ -400,3 +2239,570 @@ fn convert_value_to_json(value: &Value) -> serde_json::Value {
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::faults::FaultConfig;
+    use crate::tp::WriteSink;
+
+    fn upsert(id: u64) -> Action {
+        Action::upsert(id, Default::default())
+    }
+
+    #[test]
+    fn test_tracking_batcher_tracks_sources() {
+        let mut batcher = TrackingBatcher::new(BatchConfig::with_max_rows(2));
+        assert!(batcher.add("users", 10, upsert(1), 100).is_none());
+        assert!(batcher.add("orders", 11, upsert(1), 101).is_none());
+        assert!(batcher.add("users", 12, upsert(2), 102).is_none());
+
+        // The third users action fills the batch, which holds the first two
+        let (batch, ids) = batcher.add("users", 13, upsert(3), 103).unwrap();
+        assert_eq!(batch.namespace, "users");
+        assert_eq!(batch.actions.len(), 2);
+        assert_eq!(ids, vec![10, 12]);
+
+        let mut flushed: Vec<(String, Vec<i32>)> = batcher
+            .flush_all()
+            .into_iter()
+            .map(|(batch, ids)| (batch.namespace, ids))
+            .collect();
+        flushed.sort();
+        assert_eq!(
+            flushed,
+            vec![
+                ("orders".to_string(), vec![11]),
+                ("users".to_string(), vec![13])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pending_acks_wait_for_held_batches() {
+        let mut unacked = PendingAcks::default();
+        unacked.push(200);
+        unacked.push(300);
+        unacked.push(400);
+
+        // A held change at 250 belongs to a transaction committed after 200
+        assert_eq!(unacked.release(Some(250)), Some(200));
+        assert_eq!(unacked.release(Some(250)), None);
+        assert_eq!(unacked.release(None), Some(400));
+        assert_eq!(unacked.release(None), None);
+    }
+
+    #[test]
+    fn test_pauses_hold_changes_until_resumed() {
+        let mut pauses = Pauses::default();
+        let paused = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        assert!(!pauses.update(paused(&["users", "posts"])));
+        assert!(pauses.is_paused("users"));
+
+        pauses.hold("users", 300);
+        pauses.hold("users", 400);
+        pauses.hold("posts", 350);
+        assert_eq!(pauses.oldest_held(), Some(300));
+
+        // Resuming a mapping that skipped changes replays them
+        assert!(pauses.update(paused(&["posts"])));
+        assert!(!pauses.is_paused("users"));
+        assert_eq!(pauses.oldest_held(), Some(350));
+        assert!(!pauses.update(paused(&["posts"])));
+    }
+
+    #[test]
+    fn test_delete_guard_counts_a_transaction_across_batches() {
+        let mapping = Mapping::builder("posts")
+            .namespace("posts")
+            .source("public", "posts")
+            .id("id", puffgres_core::IdType::Uint)
+            .membership(puffgres_core::MembershipConfig::All)
+            .build()
+            .unwrap();
+        let router = Router::new(vec![mapping]);
+        let pauses = Pauses::default();
+        let deletes = |txid: u64, ids: std::ops::Range<i64>| -> Vec<RowEvent> {
+            ids.map(|id| RowEvent {
+                op: Operation::Delete,
+                schema: "public".into(),
+                table: "posts".into(),
+                new: None,
+                old: Some([("id".to_string(), Value::Int(id))].into_iter().collect()),
+                lsn: 100,
+                txid: Some(txid),
+                timestamp: None,
+            })
+            .collect()
+        };
+
+        let mut guard = DeleteGuard::new(Some(3));
+        assert_eq!(guard.count(&router, &pauses, &deletes(1, 0..2)), None);
+        // The rest of the same transaction
+        assert_eq!(
+            guard.count(&router, &pauses, &deletes(1, 2..4)),
+            Some(("posts".to_string(), 4))
+        );
+        // The next transaction starts over
+        assert_eq!(guard.count(&router, &pauses, &deletes(2, 0..3)), None);
+
+        let mut allowed = DeleteGuard::new(None);
+        assert_eq!(allowed.count(&router, &pauses, &deletes(3, 0..10)), None);
+    }
+
+    /// A transaction delivered in two batches is only over the threshold in
+    /// the second, so none of the first batch's deletes may be written.
+    #[tokio::test]
+    async fn test_delete_guard_holds_deletes_until_the_last_batch() {
+        let mappings = vec![Mapping::builder("users")
+            .namespace("users")
+            .source("public", "users")
+            .id("id", puffgres_core::IdType::Uint)
+            .build()
+            .unwrap()];
+        let router = Router::new(mappings.clone());
+        let pauses = Pauses::default();
+        let sinks = SinkWriters::default();
+        let health = Health::new(Duration::from_secs(60));
+        let faults = Arc::new(FaultInjector::disabled());
+        let client = Arc::new(TurbopufferClient::new("key", 0).with_sink(MockSink::default()));
+        let state_store = puffgres_state::SqliteStateStore::in_memory().unwrap();
+        let mut doc_hashes = DocHashCache::new(0);
+        let mut pipeline = WritePipeline {
+            mappings: &mappings,
+            client: Arc::clone(&client),
+            sinks: &sinks,
+            embedder: None,
+            state_store: &state_store,
+            upload_batch_size: 100,
+            faults: &faults,
+            health: &health,
+            in_flight: InFlightWrites::new(2),
+            tasks: JoinSet::new(),
+            writes: HashMap::new(),
+            dry_run: None,
+        };
+        // Every write fills its batch and is submitted at once
+        let mut batcher = TrackingBatcher::new(BatchConfig::with_max_rows(1));
+        let deletes = |ids: std::ops::Range<u64>| -> Vec<RowEvent> {
+            ids.map(|id| RowEvent {
+                op: Operation::Delete,
+                new: None,
+                old: Some(
+                    [("id".to_string(), Value::Int(id as i64))]
+                        .into_iter()
+                        .collect(),
+                ),
+                txid: Some(7),
+                ..insert(id)
+            })
+            .collect()
+        };
+
+        let mut guard = DeleteGuard::new(Some(3));
+        let first = deletes(1..4);
+        assert_eq!(guard.count(&router, &pauses, &first), None);
+        for event in &first {
+            let action = Action::Delete {
+                id: DocumentId::Uint(event.lsn),
+            };
+            if let Some(action) = guard.hold("users", "users", event, action, true) {
+                batch_write(
+                    &mut batcher,
+                    "users",
+                    event,
+                    action,
+                    &mut pipeline,
+                    "users",
+                    &mut doc_hashes,
+                )
+                .await
+                .unwrap();
+            }
+        }
+        assert!(guard.release(true).is_empty());
+        pipeline.drain(&mut doc_hashes).await.unwrap();
+
+        // The second batch trips the threshold before anything was written
+        assert_eq!(
+            guard.count(&router, &pauses, &deletes(4..6)),
+            Some(("users".to_string(), 5))
+        );
+        assert_eq!(client.stats().writes, 0);
+
+        // Under the threshold, the held deletes are written after the last
+        // batch; a document inserted again isn't deleted after it
+        let mut allowed = DeleteGuard::new(Some(10));
+        for event in &first {
+            let action = Action::Delete {
+                id: DocumentId::Uint(event.lsn),
+            };
+            assert!(allowed
+                .hold("users", "users", event, action, true)
+                .is_none());
+        }
+        assert!(allowed
+            .hold("users", "users", &insert(2), upsert(2), false)
+            .is_some());
+        let released = allowed.release(false);
+        let ids: Vec<u64> = released.iter().map(|held| held.event.lsn).collect();
+        assert_eq!(ids, vec![1, 3]);
+        for held in released {
+            batch_write(
+                &mut batcher,
+                &held.namespace,
+                &held.event,
+                held.action,
+                &mut pipeline,
+                &held.mapping,
+                &mut doc_hashes,
+            )
+            .await
+            .unwrap();
+        }
+        pipeline.drain(&mut doc_hashes).await.unwrap();
+        assert!(client.stats().writes > 0);
+    }
+
+    #[test]
+    fn test_reconnect_delay_backs_off() {
+        assert_eq!(reconnect_delay(1), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(2), Duration::from_secs(2));
+        assert_eq!(reconnect_delay(4), Duration::from_secs(8));
+        assert_eq!(reconnect_delay(7), RECONNECT_MAX_DELAY);
+        assert_eq!(reconnect_delay(40), RECONNECT_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_combined_exit() {
+        use LoopExit::{Reload, Stopped};
+        assert_eq!(combined_exit(&[Reload, Reload]), Reload);
+        assert_eq!(combined_exit(&[Reload, Stopped]), Stopped);
+        assert_eq!(combined_exit(&[Stopped]), Stopped);
+        assert_eq!(combined_exit(&[]), Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_try_join_all_collects_exits() {
+        let loops: Vec<LoopFuture<'_>> = vec![
+            Box::pin(async { Ok(LoopExit::Reload) }),
+            Box::pin(async {
+                tokio::task::yield_now().await;
+                Ok(LoopExit::Stopped)
+            }),
+            Box::pin(async { Ok(LoopExit::Reload) }),
+        ];
+        let exits = try_join_all(loops).await.unwrap();
+        assert_eq!(exits.len(), 3);
+        assert_eq!(exits.iter().filter(|&&e| e == LoopExit::Reload).count(), 2);
+        assert_eq!(combined_exit(&exits), LoopExit::Stopped);
+
+        // A failing loop fails the lot, without waiting for the others
+        let loops: Vec<LoopFuture<'_>> = vec![
+            Box::pin(std::future::pending()),
+            Box::pin(async { Err(anyhow::anyhow!("slot dropped")) }),
+        ];
+        assert!(try_join_all(loops).await.is_err());
+    }
+
+    /// Records the ids of the documents written to it. Each write takes a
+    /// moment, so writes overlap and land out of order.
+    #[derive(Clone, Default)]
+    struct MockSink {
+        written: Arc<std::sync::Mutex<HashSet<u64>>>,
+    }
+
+    impl MockSink {
+        /// Whether every document up to `id` has been written.
+        fn written_through(&self, id: u64) -> bool {
+            let written = self.written.lock().unwrap();
+            (1..=id).all(|id| written.contains(&id))
+        }
+    }
+
+    impl WriteSink for MockSink {
+        fn write(
+            &self,
+            _namespace: &str,
+            params: &rs_puff::WriteParams,
+        ) -> Pin<Box<dyn Future<Output = std::result::Result<(), rs_puff::Error>> + Send + '_>>
+        {
+            let ids: Vec<u64> = params
+                .upsert_rows
+                .iter()
+                .flatten()
+                .filter_map(|row| row.get("id")?.as_u64())
+                .collect();
+            Box::pin(async move {
+                let delay = ids.first().map_or(0, |id| id % 3);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                self.written.lock().unwrap().extend(ids);
+                Ok(())
+            })
+        }
+    }
+
+    fn insert(id: u64) -> RowEvent {
+        RowEvent {
+            op: Operation::Insert,
+            schema: "public".into(),
+            table: "users".into(),
+            new: Some(Default::default()),
+            old: None,
+            lsn: id,
+            txid: None,
+            timestamp: None,
+        }
+    }
+
+    /// Streams single-change transactions through the real write pipeline
+    /// while the fault schedule fails writes and drops the connection,
+    /// restarting from the last acknowledgment like the runner does. Neither
+    /// the acknowledgment nor the checkpoint may pass a change before it's in
+    /// the sink, and every change ends up there.
+    #[tokio::test]
+    async fn test_pipeline_acknowledges_only_written_changes() {
+        let mappings = vec![Mapping::builder("users")
+            .namespace("users")
+            .source("public", "users")
+            .id("id", puffgres_core::IdType::Uint)
+            .build()
+            .unwrap()];
+        let sinks = SinkWriters::default();
+        let health = Health::new(Duration::from_secs(60));
+        let last = 60;
+        let mut restarts = 0;
+        let mut retries = 0;
+
+        for seed in 0..4 {
+            let faults = Arc::new(FaultInjector::new(FaultConfig {
+                seed,
+                write_failure_rate: 0.25,
+                disconnect_rate: 0.02,
+                ..Default::default()
+            }));
+            let sink = MockSink::default();
+            let client = Arc::new(
+                TurbopufferClient::new("key", 1)
+                    .with_sink(sink.clone())
+                    .with_faults(Arc::clone(&faults)),
+            );
+            let state_store = puffgres_state::SqliteStateStore::in_memory().unwrap();
+            let mut doc_hashes = DocHashCache::new(0);
+            let mut acked = 0;
+
+            while acked < last {
+                restarts += 1;
+                assert!(
+                    restarts < 1000,
+                    "seed {}: pipeline never made progress",
+                    seed
+                );
+
+                let mut pipeline = WritePipeline {
+                    mappings: &mappings,
+                    client: Arc::clone(&client),
+                    sinks: &sinks,
+                    embedder: None,
+                    state_store: &state_store,
+                    upload_batch_size: 100,
+                    faults: &faults,
+                    health: &health,
+                    in_flight: InFlightWrites::new(2),
+                    tasks: JoinSet::new(),
+                    writes: HashMap::new(),
+                    dry_run: None,
+                };
+                let mut batchers = HashMap::from([(
+                    "users".to_string(),
+                    TrackingBatcher::new(BatchConfig::with_max_rows(4)),
+                )]);
+                let mut unacked = PendingAcks::default();
+                let pauses = Pauses::default();
+
+                // The slot replays everything after the last acknowledgment
+                let mut failed = false;
+                for id in acked + 1..=last {
+                    if faults.inject_disconnect() {
+                        failed = true;
+                        break;
+                    }
+                    let event = insert(id);
+                    let batcher = batchers.get_mut("users").unwrap();
+                    let written = batch_write(
+                        batcher,
+                        "users",
+                        &event,
+                        upsert(id),
+                        &mut pipeline,
+                        "users",
+                        &mut doc_hashes,
+                    )
+                    .await;
+                    if written.is_err() {
+                        failed = true;
+                        break;
+                    }
+                    unacked.push(id);
+                    if let Some(position) =
+                        written_position(&mut unacked, &batchers, &pipeline, &pauses)
+                    {
+                        assert!(
+                            sink.written_through(position),
+                            "seed {}: acknowledged {} before it was written",
+                            seed,
+                            position
+                        );
+                        acked = position;
+                    }
+                }
+                if !failed {
+                    let mut flushed =
+                        flush_batchers(&mut batchers, None, &mut pipeline, &mut doc_hashes).await;
+                    if flushed.is_ok() {
+                        flushed = pipeline.drain(&mut doc_hashes).await;
+                    }
+                    if flushed.is_ok() {
+                        let position =
+                            written_position(&mut unacked, &batchers, &pipeline, &pauses);
+                        if let Some(position) = position {
+                            assert!(sink.written_through(position));
+                            acked = position;
+                        }
+                    }
+                }
+
+                let checkpoint = state_store.get_checkpoint("users").await.unwrap();
+                let checkpoint = checkpoint.map_or(0, |c| c.lsn);
+                assert!(
+                    checkpoint == 0 || sink.written_through(checkpoint - 1),
+                    "seed {}: checkpoint at {} passed an unwritten change",
+                    seed,
+                    checkpoint
+                );
+            }
+
+            assert!(sink.written_through(last), "seed {}: lost a change", seed);
+            retries += client.stats().retries;
+        }
+
+        // Writes failed, and some passes were cut short and replayed
+        assert!(retries > 0);
+        assert!(restarts > 4);
+    }
+
+    /// A write in flight lands while the runner is busy transforming the
+    /// next changes, without waiting on the pipeline.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_pipeline_writes_while_transforming() {
+        let mappings = vec![Mapping::builder("users")
+            .namespace("users")
+            .source("public", "users")
+            .id("id", puffgres_core::IdType::Uint)
+            .build()
+            .unwrap()];
+        let sinks = SinkWriters::default();
+        let health = Health::new(Duration::from_secs(60));
+        let faults = FaultInjector::new(FaultConfig::default());
+        let sink = MockSink::default();
+        let client = Arc::new(TurbopufferClient::new("key", 0).with_sink(sink.clone()));
+        let state_store = puffgres_state::SqliteStateStore::in_memory().unwrap();
+        let mut doc_hashes = DocHashCache::new(0);
+        let mut pipeline = WritePipeline {
+            mappings: &mappings,
+            client,
+            sinks: &sinks,
+            embedder: None,
+            state_store: &state_store,
+            upload_batch_size: 100,
+            faults: &faults,
+            health: &health,
+            in_flight: InFlightWrites::new(2),
+            tasks: JoinSet::new(),
+            writes: HashMap::new(),
+            dry_run: None,
+        };
+        let mut batchers = HashMap::from([(
+            "users".to_string(),
+            TrackingBatcher::new(BatchConfig::with_max_rows(4)),
+        )]);
+
+        for id in 1..=4 {
+            let batcher = batchers.get_mut("users").unwrap();
+            batch_write(
+                batcher,
+                "users",
+                &insert(id),
+                upsert(id),
+                &mut pipeline,
+                "users",
+                &mut doc_hashes,
+            )
+            .await
+            .unwrap();
+        }
+        flush_batchers(&mut batchers, None, &mut pipeline, &mut doc_hashes)
+            .await
+            .unwrap();
+
+        // Stands in for a slow transform: the runner's task never yields
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !sink.written_through(4) && Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+        assert!(sink.written_through(4), "the write waited for the runner");
+
+        // It's only checkpointed once the runner settles it
+        assert!(state_store.get_checkpoint("users").await.unwrap().is_none());
+        pipeline.drain(&mut doc_hashes).await.unwrap();
+        let checkpoint = state_store.get_checkpoint("users").await.unwrap();
+        assert_eq!(checkpoint.map(|c| c.lsn), Some(1));
+    }
+
+    #[test]
+    fn test_split_by_size() {
+        let items = [1, 2, 3, 4, 5];
+        let split = |max_items, max_bytes| {
+            split_by_size(&items, &items, max_items, max_bytes)
+                .into_iter()
+                .map(<[usize]>::to_vec)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(split(2, 100), vec![vec![1, 2], vec![3, 4], vec![5]]);
+        assert_eq!(split(10, 6), vec![vec![1, 2, 3], vec![4], vec![5]]);
+        // An item over the limit goes on its own
+        assert_eq!(split(10, 3), vec![vec![1, 2], vec![3], vec![4], vec![5]]);
+        assert!(split_by_size(&[] as &[usize], &[], 10, 3).is_empty());
+    }
+
+    #[test]
+    fn test_dry_run_line() {
+        let mapping = Mapping::builder("users")
+            .namespace("users")
+            .source("public", "users")
+            .id("id", puffgres_core::IdType::Uint)
+            .build()
+            .unwrap();
+        let mut doc = puffgres_core::Document::new();
+        doc.insert("name".to_string(), Value::String("Ada".into()));
+        let mut batcher = Batcher::new(BatchConfig::with_max_rows(10));
+        batcher.add("users", Action::upsert(1u64, doc), 100);
+        batcher.add("users", Action::delete(2u64), 100);
+        let request = WriteRequest::from_batch(batcher.flush_all().remove(0));
+
+        let line: serde_json::Value =
+            serde_json::from_str(&dry_run_line(&mapping, &request)).unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "mapping": "users",
+                "namespace": "users",
+                "lsn": "0/64",
+                "clear": false,
+                "upserts": [{"id": 1, "name": "Ada", "__source_lsn": 100}],
+                "patches": [],
+                "deletes": [2],
+            })
+        );
+    }
+}
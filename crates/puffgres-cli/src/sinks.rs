@@ -0,0 +1,229 @@
+//! Secondary sinks: copies of a mapping's writes in other namespaces.
+//!
+//! Each sink has its own turbopuffer client, with its own retries, and a
+//! queue drained by a task of its own, so a sink that's down only holds up
+//! itself. The runner never waits on a sink: once a sink falls
+//! `QUEUE_BATCHES` behind, its new batches are dropped and logged, and its
+//! namespace needs a `puffgres backfill` to catch up. Backfill and DLQ
+//! retries wait for room instead, so they copy every row.
+//!
+//! A batch is copied once the primary write succeeds. A batch that fails
+//! there goes to the dead letter queue, and reaches the sinks when it's
+//! retried.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use puffgres_core::{Mapping, Sink, WriteRequest};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+use crate::config::ProjectConfig;
+use crate::env::get_max_retries;
+use crate::runner::write_request;
+use crate::tp::TurbopufferClient;
+
+/// Batches a sink can fall behind by before the runner drops new ones.
+const QUEUE_BATCHES: usize = 1000;
+
+/// How long the runner waits for sinks to catch up when it stops.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct SinkQueue {
+    sink: Sink,
+    sender: mpsc::Sender<WriteRequest>,
+    dropped: AtomicU64,
+}
+
+impl SinkQueue {
+    /// `request` retargeted at the sink's namespace.
+    fn copy(&self, request: &WriteRequest) -> WriteRequest {
+        let mut copy = request.clone();
+        copy.namespace = self.sink.namespace_for(&request.namespace);
+        copy
+    }
+}
+
+/// The sinks of a set of mappings, each with a task writing its queue.
+#[derive(Default)]
+pub struct SinkWriters {
+    /// Queues by mapping name.
+    queues: HashMap<String, Vec<SinkQueue>>,
+    tasks: JoinSet<()>,
+}
+
+impl SinkWriters {
+    /// Start a writer for every sink of `mappings`.
+    pub fn start(
+        config: &ProjectConfig,
+        mappings: &[Mapping],
+        upload_batch_size: usize,
+    ) -> Result<Self> {
+        let mut writers = Self::default();
+        for mapping in mappings {
+            for sink in &mapping.sinks {
+                let api_key = config.sink_api_key(sink).with_context(|| {
+                    format!("Sink '{}' of mapping '{}'", sink.name, mapping.name)
+                })?;
+                let max_retries = sink.max_retries.unwrap_or_else(get_max_retries);
+                let client = TurbopufferClient::new(api_key, max_retries);
+                let (sender, receiver) = mpsc::channel(QUEUE_BATCHES);
+                writers.tasks.spawn(drain(
+                    mapping.name.clone(),
+                    sink.name.clone(),
+                    client,
+                    receiver,
+                    mapping.source_lsn_attribute.clone(),
+                    upload_batch_size,
+                ));
+                writers
+                    .queues
+                    .entry(mapping.name.clone())
+                    .or_default()
+                    .push(SinkQueue {
+                        sink: sink.clone(),
+                        sender,
+                        dropped: AtomicU64::new(0),
+                    });
+                info!(
+                    mapping = %mapping.name,
+                    sink = %sink.name,
+                    namespace = %sink.namespace,
+                    "Writing to sink"
+                );
+            }
+        }
+        Ok(writers)
+    }
+
+    fn queues(&self, mapping: &str) -> &[SinkQueue] {
+        self.queues.get(mapping).map_or(&[], Vec::as_slice)
+    }
+
+    /// Queue a copy of `request` for each of the mapping's sinks without
+    /// waiting. A sink whose queue is full misses the batch.
+    pub fn offer(&self, mapping: &str, request: &WriteRequest) {
+        for queue in self.queues(mapping) {
+            match queue.sender.try_send(queue.copy(request)) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    let dropped = queue.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                    warn!(
+                        mapping,
+                        sink = %queue.sink.name,
+                        dropped,
+                        "Sink is behind; dropped a batch, backfill to catch it up"
+                    );
+                }
+                Err(TrySendError::Closed(_)) => {
+                    warn!(mapping, sink = %queue.sink.name, "Sink writer stopped; dropped a batch");
+                }
+            }
+        }
+    }
+
+    /// Queue a copy of `request` for each of the mapping's sinks, waiting for
+    /// room in their queues.
+    pub async fn send(&self, mapping: &str, request: &WriteRequest) {
+        for queue in self.queues(mapping) {
+            if queue.sender.send(queue.copy(request)).await.is_err() {
+                warn!(mapping, sink = %queue.sink.name, "Sink writer stopped; dropped a batch");
+            }
+        }
+    }
+
+    /// Close the queues and wait for the batches in them to be written, for
+    /// at most `timeout`. Batches still queued after that are abandoned.
+    pub async fn finish(mut self, timeout: Option<Duration>) {
+        // Dropping the senders ends each writer once its queue is empty
+        self.queues.clear();
+        let tasks = &mut self.tasks;
+        let drained = async { while tasks.join_next().await.is_some() {} };
+        match timeout {
+            Some(timeout) => {
+                if tokio::time::timeout(timeout, drained).await.is_err() {
+                    warn!(
+                        sinks = self.tasks.len(),
+                        "Sinks didn't catch up in time; abandoning their queued batches"
+                    );
+                    self.tasks.abort_all();
+                }
+            }
+            None => drained.await,
+        }
+    }
+}
+
+/// Write a sink's queued batches until its queue is closed. A failed batch
+/// is logged and skipped, so the sink moves on to the next one.
+async fn drain(
+    mapping: String,
+    sink: String,
+    client: TurbopufferClient,
+    mut receiver: mpsc::Receiver<WriteRequest>,
+    source_lsn_attribute: Option<String>,
+    upload_batch_size: usize,
+) {
+    while let Some(request) = receiver.recv().await {
+        let written = write_request(
+            &client,
+            &request,
+            source_lsn_attribute.as_deref(),
+            upload_batch_size,
+        )
+        .await;
+        if let Err(e) = written {
+            error!(
+                mapping = %mapping,
+                sink = %sink,
+                namespace = %request.namespace,
+                error = %e,
+                "Failed to write batch to sink"
+            );
+        }
+    }
+    let writes = client.stats();
+    info!(
+        mapping = %mapping,
+        sink = %sink,
+        writes = writes.writes,
+        retries = writes.retries,
+        failures = writes.failures,
+        "Sink writes"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_retargets_namespace() {
+        let (sender, _receiver) = mpsc::channel(1);
+        let queue = SinkQueue {
+            sink: Sink {
+                name: "en".to_string(),
+                namespace: "{namespace}_en".to_string(),
+                api_key: None,
+                max_retries: None,
+            },
+            sender,
+            dropped: AtomicU64::new(0),
+        };
+        let request = WriteRequest {
+            namespace: "docs_acme".to_string(),
+            upserts: vec![],
+            patches: vec![],
+            deletes: vec![],
+            clear: false,
+            lsn: 42,
+            distance_metric: None,
+        };
+        let copy = queue.copy(&request);
+        assert_eq!(copy.namespace, "docs_acme_en");
+        assert_eq!(copy.lsn, 42);
+    }
+}
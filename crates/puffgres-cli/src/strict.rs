@@ -0,0 +1,165 @@
+//! Strict mode: promote warnings that production tolerates to hard errors.
+//!
+//! By default a row whose ID can't be extracted or whose transform fails is
+//! logged and handled by the mapping's `[errors]` policy, undecodable WAL is
+//! skipped, and TRUNCATE is ignored by mappings that don't clear their
+//! namespace. That keeps a long-running sync alive, but it also means a CI
+//! smoke run passes with a broken mapping. Strict mode fails the command with
+//! a non-zero exit instead.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use anyhow::{bail, Result};
+
+/// A class of warning that strict mode can turn into an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StrictCheck {
+    /// The document ID couldn't be extracted from a row.
+    Id,
+    /// A transform failed on a row.
+    Transform,
+    /// A replication message or row couldn't be decoded.
+    Decode,
+    /// A TRUNCATE was skipped by a mapping with `on_truncate = "ignore"`.
+    Truncate,
+    /// A table's replica identity is too narrow for its mappings.
+    ReplicaIdentity,
+}
+
+impl StrictCheck {
+    pub const ALL: [StrictCheck; 5] = [
+        StrictCheck::Id,
+        StrictCheck::Transform,
+        StrictCheck::Decode,
+        StrictCheck::Truncate,
+        StrictCheck::ReplicaIdentity,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StrictCheck::Id => "id",
+            StrictCheck::Transform => "transform",
+            StrictCheck::Decode => "decode",
+            StrictCheck::Truncate => "truncate",
+            StrictCheck::ReplicaIdentity => "replica_identity",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|check| check.as_str() == name.replace('-', "_"))
+    }
+}
+
+impl fmt::Display for StrictCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The set of warnings treated as errors.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StrictMode {
+    checks: BTreeSet<StrictCheck>,
+}
+
+impl StrictMode {
+    /// Every check enabled.
+    pub fn all() -> Self {
+        Self {
+            checks: StrictCheck::ALL.into_iter().collect(),
+        }
+    }
+
+    /// Parse a `--strict` / `PUFFGRES_STRICT` value: `true`, `1` or `all` for
+    /// every check, `false`, `0` or empty for none, or a comma-separated list
+    /// of checks such as `id,transform`.
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        match value.as_str() {
+            "true" | "1" | "all" => return Ok(Self::all()),
+            "false" | "0" | "" => return Ok(Self::default()),
+            _ => {}
+        }
+
+        let mut checks = BTreeSet::new();
+        for name in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match StrictCheck::from_name(name) {
+                Some(check) => {
+                    checks.insert(check);
+                }
+                None => bail!(
+                    "Unknown strict check '{}'. Expected one of: {}",
+                    name,
+                    StrictCheck::ALL.map(|c| c.as_str()).join(", ")
+                ),
+            }
+        }
+        Ok(Self { checks })
+    }
+
+    /// Combine the `--strict` flag with `PUFFGRES_STRICT`; a check enabled in
+    /// either is enabled.
+    pub fn resolve(flag: Option<&str>, env: Option<&str>) -> Result<Self> {
+        let mut mode = Self::default();
+        for value in [env, flag].into_iter().flatten() {
+            mode.checks.extend(Self::parse(value)?.checks);
+        }
+        Ok(mode)
+    }
+
+    pub fn enabled(&self, check: StrictCheck) -> bool {
+        self.checks.contains(&check)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checks.is_empty()
+    }
+}
+
+impl fmt::Display for StrictMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.checks.iter().map(StrictCheck::as_str).collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_and_none() {
+        assert_eq!(StrictMode::parse("true").unwrap(), StrictMode::all());
+        assert_eq!(StrictMode::parse("ALL").unwrap(), StrictMode::all());
+        assert!(StrictMode::parse("false").unwrap().is_empty());
+        assert!(StrictMode::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let mode = StrictMode::parse("id, replica-identity").unwrap();
+        assert!(mode.enabled(StrictCheck::Id));
+        assert!(mode.enabled(StrictCheck::ReplicaIdentity));
+        assert!(!mode.enabled(StrictCheck::Transform));
+        assert_eq!(mode.to_string(), "id,replica_identity");
+
+        assert!(StrictMode::parse("id,typo").is_err());
+    }
+
+    #[test]
+    fn test_resolve_merges_flag_and_env() {
+        let mode = StrictMode::resolve(Some("id"), Some("truncate")).unwrap();
+        assert!(mode.enabled(StrictCheck::Id));
+        assert!(mode.enabled(StrictCheck::Truncate));
+        assert!(!mode.enabled(StrictCheck::Decode));
+
+        assert!(StrictMode::resolve(None, None).unwrap().is_empty());
+        assert_eq!(
+            StrictMode::resolve(Some("all"), None).unwrap(),
+            StrictMode::all()
+        );
+    }
+}
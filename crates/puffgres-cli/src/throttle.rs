@@ -0,0 +1,359 @@
+//! Pacing for turbopuffer writes, per namespace.
+//!
+//! A namespace can be held to a number of writes per second
+//! (`PUFFGRES_TP_MAX_REQUESTS_PER_SEC`) and a number of writes at once
+//! (`PUFFGRES_TP_MAX_CONCURRENT_WRITES`). Either way the rate adapts to
+//! turbopuffer: a 429 or 503 halves it, and every write that lands adds a
+//! little back until it's where it started. A namespace without a configured
+//! rate starts from the rate it was writing at when it was first pushed back,
+//! and stops being paced once it has recovered.
+//!
+//! Backfills and the runner write through the same client, so they share
+//! these limits.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+/// Slowest a namespace is paced to, in writes per second.
+const MIN_RATE: f64 = 0.5;
+
+/// Share of the rate kept when turbopuffer pushes back.
+const BACKOFF_FACTOR: f64 = 0.5;
+
+/// Writes per second added back for each write that lands.
+const RECOVERY_STEP: f64 = 0.5;
+
+/// How long after slowing down further push back is put down to the same
+/// burst, rather than halving the rate again.
+const BACKOFF_COOLDOWN: Duration = Duration::from_secs(1);
+
+/// Refills at `rate` tokens a second and holds up to a second's worth, at
+/// least one.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(rate: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate.max(1.0),
+            updated: now,
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Take a token, returning how long to wait before using it. A token
+    /// taken before it has refilled is owed, so callers that take one while
+    /// others wait queue up behind them.
+    pub fn take(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    /// Refill at `rate` from now on.
+    pub fn set_rate(&mut self, rate: f64, now: Instant) {
+        self.refill(now);
+        self.rate = rate;
+        self.tokens = self.tokens.min(rate.max(1.0));
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        self.updated = self.updated.max(now);
+    }
+}
+
+/// The pace of writes to one namespace.
+#[derive(Debug)]
+pub struct NamespacePace {
+    /// The configured rate, if any.
+    limit: Option<f64>,
+    /// Paces writes while limited or slowed down. `None` when neither.
+    bucket: Option<TokenBucket>,
+    /// The rate to recover to after slowing down.
+    ceiling: Option<f64>,
+    slowed_at: Option<Instant>,
+    /// When writes started in the last second, while not paced, to know how
+    /// fast the namespace was writing when it's first pushed back.
+    recent: VecDeque<Instant>,
+}
+
+impl NamespacePace {
+    pub fn new(limit: Option<f64>, now: Instant) -> Self {
+        Self {
+            limit,
+            bucket: limit.map(|rate| TokenBucket::new(rate, now)),
+            ceiling: limit,
+            slowed_at: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// The rate writes are paced to. `None` when they aren't.
+    pub fn rate(&self) -> Option<f64> {
+        self.bucket.as_ref().map(TokenBucket::rate)
+    }
+
+    /// Start a write, returning how long to wait before sending it.
+    pub fn start(&mut self, now: Instant) -> Duration {
+        match &mut self.bucket {
+            Some(bucket) => bucket.take(now),
+            None => {
+                self.recent.push_back(now);
+                while self.recent.front().is_some_and(|&start| {
+                    now.saturating_duration_since(start) > Duration::from_secs(1)
+                }) {
+                    self.recent.pop_front();
+                }
+                Duration::ZERO
+            }
+        }
+    }
+
+    /// Turbopuffer pushed back on a write: slow down, unless that was done
+    /// for the same burst already. Returns the new rate if it changed.
+    pub fn pushed_back(&mut self, now: Instant) -> Option<f64> {
+        if self
+            .slowed_at
+            .is_some_and(|at| now.saturating_duration_since(at) < BACKOFF_COOLDOWN)
+        {
+            return None;
+        }
+        let current = match &self.bucket {
+            Some(bucket) => bucket.rate(),
+            None => self.recent.len() as f64,
+        }
+        .max(MIN_RATE);
+        self.ceiling = Some(self.ceiling.unwrap_or(current));
+        let rate = (current * BACKOFF_FACTOR).max(MIN_RATE);
+        match &mut self.bucket {
+            Some(bucket) => bucket.set_rate(rate, now),
+            None => {
+                // Start empty, so the burst that was pushed back on doesn't
+                // get sent again at once
+                let mut bucket = TokenBucket::new(rate, now);
+                bucket.tokens = 0.0;
+                self.bucket = Some(bucket);
+                self.recent.clear();
+            }
+        }
+        self.slowed_at = Some(now);
+        Some(rate)
+    }
+
+    /// A write landed: speed back up towards the ceiling. Returns true once
+    /// the namespace has recovered.
+    pub fn landed(&mut self, now: Instant) -> bool {
+        let (Some(bucket), Some(ceiling)) = (&mut self.bucket, self.ceiling) else {
+            return false;
+        };
+        if self.limit.is_some_and(|limit| bucket.rate() >= limit) {
+            return false;
+        }
+        let rate = bucket.rate() + RECOVERY_STEP;
+        if rate < ceiling {
+            bucket.set_rate(rate, now);
+            return false;
+        }
+        match self.limit {
+            Some(limit) => bucket.set_rate(limit, now),
+            None => {
+                self.bucket = None;
+                self.ceiling = None;
+            }
+        }
+        self.slowed_at = None;
+        true
+    }
+}
+
+/// Limits on writes to each namespace.
+#[derive(Debug)]
+pub struct WriteThrottle {
+    max_requests_per_sec: Option<f64>,
+    max_concurrent_writes: Option<usize>,
+    namespaces: Mutex<HashMap<String, NamespaceThrottle>>,
+}
+
+#[derive(Debug)]
+struct NamespaceThrottle {
+    pace: NamespacePace,
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl WriteThrottle {
+    /// Allow each namespace up to `max_requests_per_sec` writes a second and
+    /// `max_concurrent_writes` at once; `None` for no limit.
+    pub fn new(max_requests_per_sec: Option<u32>, max_concurrent_writes: Option<usize>) -> Self {
+        Self {
+            max_requests_per_sec: max_requests_per_sec.map(f64::from),
+            max_concurrent_writes,
+            namespaces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait for a namespace's turn to write. Hold the returned permit until
+    /// the write is done.
+    pub async fn acquire(&self, namespace: &str) -> Option<OwnedSemaphorePermit> {
+        let permits = self.with(namespace, |ns| ns.permits.clone());
+        let permit = match permits {
+            Some(permits) => Some(
+                permits
+                    .acquire_owned()
+                    .await
+                    .expect("write permits are never closed"),
+            ),
+            None => None,
+        };
+        let wait = self.with(namespace, |ns| ns.pace.start(Instant::now()));
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        permit
+    }
+
+    /// Slow a namespace down after turbopuffer rate limited a write to it or
+    /// was unavailable.
+    pub fn pushed_back(&self, namespace: &str) {
+        if let Some(rate) = self.with(namespace, |ns| ns.pace.pushed_back(Instant::now())) {
+            warn!(
+                namespace = namespace,
+                writes_per_sec = rate,
+                "Turbopuffer is pushing back, slowing down writes"
+            );
+        }
+    }
+
+    /// Speed a namespace back up after a write landed.
+    pub fn landed(&self, namespace: &str) {
+        if self.with(namespace, |ns| ns.pace.landed(Instant::now())) {
+            info!(namespace = namespace, "Writes are back to full speed");
+        }
+    }
+
+    fn with<T>(&self, namespace: &str, f: impl FnOnce(&mut NamespaceThrottle) -> T) -> T {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let throttle = namespaces.entry(namespace.to_string()).or_insert_with(|| {
+            let permits = self.max_concurrent_writes.map(Semaphore::new);
+            NamespaceThrottle {
+                pace: NamespacePace::new(self.max_requests_per_sec, Instant::now()),
+                permits: permits.map(Arc::new),
+            }
+        });
+        f(throttle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn test_token_bucket_spaces_out_writes() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, now);
+        assert_eq!(bucket.take(now), Duration::ZERO);
+        assert_eq!(bucket.take(now), Duration::ZERO);
+        // Owed tokens queue up
+        assert_eq!(bucket.take(now), secs(0.5));
+        assert_eq!(bucket.take(now), secs(1.0));
+
+        // A second later two more have refilled, both owed already
+        assert_eq!(bucket.take(now + secs(1.0)), secs(0.5));
+    }
+
+    #[test]
+    fn test_token_bucket_holds_a_second() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(1.0, now);
+        assert_eq!(bucket.take(now + secs(10.0)), Duration::ZERO);
+        assert_eq!(bucket.take(now + secs(10.0)), secs(1.0));
+    }
+
+    #[test]
+    fn test_limited_namespace_slows_down_and_recovers() {
+        let now = Instant::now();
+        let mut pace = NamespacePace::new(Some(4.0), now);
+        assert_eq!(pace.rate(), Some(4.0));
+
+        assert_eq!(pace.pushed_back(now), Some(2.0));
+        // The same burst doesn't halve it again
+        assert_eq!(pace.pushed_back(now + secs(0.5)), None);
+        assert_eq!(pace.pushed_back(now + secs(2.0)), Some(1.0));
+
+        for _ in 0..5 {
+            assert!(!pace.landed(now));
+        }
+        assert_eq!(pace.rate(), Some(3.5));
+        assert!(pace.landed(now));
+        assert_eq!(pace.rate(), Some(4.0));
+        assert!(!pace.landed(now));
+    }
+
+    #[test]
+    fn test_unlimited_namespace_starts_from_its_rate() {
+        let now = Instant::now();
+        let mut pace = NamespacePace::new(None, now);
+        for i in 0..10 {
+            assert_eq!(pace.start(now + secs(i as f64 * 0.1)), Duration::ZERO);
+        }
+        assert_eq!(pace.rate(), None);
+
+        assert_eq!(pace.pushed_back(now + secs(1.0)), Some(5.0));
+        assert!(pace.start(now + secs(1.0)) > Duration::ZERO);
+
+        let mut landed = 0;
+        while !pace.landed(now) {
+            landed += 1;
+        }
+        assert_eq!(landed, 9);
+        assert_eq!(pace.rate(), None);
+        assert_eq!(pace.start(now + secs(2.0)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_rate_has_a_floor() {
+        let now = Instant::now();
+        let mut pace = NamespacePace::new(None, now);
+        assert_eq!(pace.pushed_back(now), Some(MIN_RATE));
+        assert_eq!(pace.pushed_back(now + secs(5.0)), Some(MIN_RATE));
+        assert!(pace.landed(now));
+        assert_eq!(pace.rate(), None);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writes_are_capped() {
+        let throttle = WriteThrottle::new(None, Some(1));
+        let permit = throttle.acquire("users").await;
+        assert!(permit.is_some());
+        // Another namespace has its own permits
+        assert!(throttle.acquire("orders").await.is_some());
+
+        let waiting = tokio::time::timeout(secs(0.05), throttle.acquire("users")).await;
+        assert!(waiting.is_err());
+        drop(permit);
+        assert!(throttle.acquire("users").await.is_some());
+    }
+}
@@ -0,0 +1,495 @@
+//! Turbopuffer write path shared by the CDC runner and backfill.
+//!
+//! Writes are retried with jittered exponential backoff when the failure is
+//! transient (network errors, timeouts, 5xx, rate limits) and fail immediately
+//! otherwise, e.g. when turbopuffer rejects the payload. Rate-limited writes
+//! back off from a longer base delay. rs-puff doesn't expose response headers,
+//! so `Retry-After` can't be read and the backoff is used instead.
+//!
+//! Create one client per process and share it: all namespaces then write
+//! through the same pooled HTTP client, which reuses connections (and
+//! multiplexes them over HTTP/2 when the server negotiates it) instead of
+//! opening new ones per namespace. rs-puff builds that HTTP client itself, so
+//! its pool size and keepalive can't be tuned from here; instead
+//! `PUFFGRES_TP_MAX_CONNECTIONS` caps the requests in flight to turbopuffer,
+//! which bounds the connections the pool needs. [`WriteStats`] counts the
+//! requests sent.
+//!
+//! Writes are also paced per namespace by a [`WriteThrottle`], which slows
+//! down when turbopuffer rate limits or is unavailable.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use puffgres_core::ErrorKind;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::warn;
+
+use crate::env::{
+    get_tp_max_concurrent_writes, get_tp_max_connections, get_tp_max_requests_per_sec,
+};
+use crate::faults::FaultInjector;
+use crate::throttle::WriteThrottle;
+
+/// First retry delay for transient failures.
+const BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// First retry delay after a 429.
+const RATE_LIMIT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound on a single retry delay.
+pub(crate) const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Documents read per query when exporting a namespace.
+const EXPORT_PAGE_SIZE: u64 = 1000;
+
+/// Point-in-time copy of a client's write counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteStats {
+    /// Successful write requests.
+    pub writes: u64,
+    /// Rows upserted by successful writes.
+    pub rows_upserted: u64,
+    /// Rows patched by successful writes.
+    pub rows_patched: u64,
+    /// Documents deleted by successful writes.
+    pub rows_deleted: u64,
+    /// Attempts that failed and were retried.
+    pub retries: u64,
+    /// Attempts rejected with a rate limit.
+    pub rate_limited: u64,
+    /// Writes that gave up, either on a terminal error or after all retries.
+    pub failures: u64,
+    /// HTTP requests sent to turbopuffer, including failed attempts, queries
+    /// and clears.
+    pub requests: u64,
+}
+
+#[derive(Debug, Default)]
+struct WriteMetrics {
+    writes: AtomicU64,
+    rows_upserted: AtomicU64,
+    rows_patched: AtomicU64,
+    rows_deleted: AtomicU64,
+    retries: AtomicU64,
+    rate_limited: AtomicU64,
+    failures: AtomicU64,
+    requests: AtomicU64,
+}
+
+/// Takes a client's write attempts in place of turbopuffer, so tests can
+/// drive the real write path without a network.
+#[cfg(test)]
+pub(crate) trait WriteSink: Send + Sync {
+    fn write(
+        &self,
+        namespace: &str,
+        params: &rs_puff::WriteParams,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = std::result::Result<(), rs_puff::Error>> + Send + '_>,
+    >;
+}
+
+/// A turbopuffer client with retries, error classification and write metrics.
+pub struct TurbopufferClient {
+    client: rs_puff::Client,
+    max_retries: u32,
+    faults: Option<Arc<FaultInjector>>,
+    metrics: WriteMetrics,
+    throttle: WriteThrottle,
+    /// Requests allowed in flight to turbopuffer at once, if capped.
+    connections: Option<Semaphore>,
+    #[cfg(test)]
+    sink: Option<Box<dyn WriteSink>>,
+}
+
+impl TurbopufferClient {
+    /// Create a client that retries transient failures up to `max_retries` times.
+    pub fn new(api_key: impl Into<String>, max_retries: u32) -> Self {
+        Self {
+            client: rs_puff::Client::new(api_key),
+            max_retries,
+            faults: None,
+            metrics: WriteMetrics::default(),
+            throttle: WriteThrottle::new(
+                get_tp_max_requests_per_sec(),
+                get_tp_max_concurrent_writes(),
+            ),
+            connections: get_tp_max_connections().map(Semaphore::new),
+            #[cfg(test)]
+            sink: None,
+        }
+    }
+
+    /// Inject write failures from a fault schedule (for resilience testing).
+    pub fn with_faults(mut self, faults: Arc<FaultInjector>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Send write attempts to `sink` instead of turbopuffer.
+    #[cfg(test)]
+    pub(crate) fn with_sink(mut self, sink: impl WriteSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Write to a namespace, retrying transient failures.
+    pub async fn write(&self, namespace: &str, params: rs_puff::WriteParams) -> Result<()> {
+        let upserts = params.upsert_rows.as_ref().map_or(0, |r| r.len()) as u64;
+        let patches = params.patch_rows.as_ref().map_or(0, |r| r.len()) as u64;
+        let deletes = params.deletes.as_ref().map_or(0, |d| d.len()) as u64;
+
+        for attempt in 0..=self.max_retries {
+            let permit = self.throttle.acquire(namespace).await;
+            let result = self.attempt(namespace, &params).await;
+            drop(permit);
+            let (kind, error) = match result {
+                Ok(()) => {
+                    self.throttle.landed(namespace);
+                    self.metrics.writes.fetch_add(1, Ordering::Relaxed);
+                    self.metrics
+                        .rows_upserted
+                        .fetch_add(upserts, Ordering::Relaxed);
+                    self.metrics
+                        .rows_patched
+                        .fetch_add(patches, Ordering::Relaxed);
+                    self.metrics
+                        .rows_deleted
+                        .fetch_add(deletes, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Err(failure) => failure,
+            };
+
+            if kind == ErrorKind::RateLimited {
+                self.metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+            }
+            if matches!(kind, ErrorKind::RateLimited | ErrorKind::ServiceUnavailable) {
+                self.throttle.pushed_back(namespace);
+            }
+
+            if !kind.is_retryable() {
+                self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(error).context(format!(
+                    "Turbopuffer write failed ({}), not retrying",
+                    kind.description()
+                ));
+            }
+            if attempt == self.max_retries {
+                self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(error).context("Failed to write to turbopuffer after all retries");
+            }
+
+            self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+            let delay = backoff_delay(attempt, kind, jitter());
+            warn!(
+                namespace = namespace,
+                attempt = attempt + 1,
+                max_retries = self.max_retries,
+                delay_ms = delay.as_millis() as u64,
+                kind = kind.as_str(),
+                error = %error,
+                "Turbopuffer write failed, retrying"
+            );
+
+            tokio::time::sleep(delay).await;
+        }
+
+        unreachable!()
+    }
+
+    /// Delete every document in a namespace, retrying transient failures. A
+    /// namespace that doesn't exist yet is already empty.
+    pub async fn clear(&self, namespace: &str) -> Result<()> {
+        for attempt in 0..=self.max_retries {
+            let permit = self.request_permit().await;
+            let result = self.client.namespace(namespace).delete_all().await;
+            drop(permit);
+            let error = match result {
+                Ok(_) | Err(rs_puff::Error::Api { status: 404, .. }) => return Ok(()),
+                Err(e) => e,
+            };
+            let kind = classify_error(&error);
+            if !kind.is_retryable() || attempt == self.max_retries {
+                self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(error).context(format!("Failed to clear namespace '{}'", namespace));
+            }
+
+            self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+            let delay = backoff_delay(attempt, kind, jitter());
+            warn!(
+                namespace = namespace,
+                attempt = attempt + 1,
+                max_retries = self.max_retries,
+                delay_ms = delay.as_millis() as u64,
+                kind = kind.as_str(),
+                error = %error,
+                "Clearing namespace failed, retrying"
+            );
+            tokio::time::sleep(delay).await;
+        }
+
+        unreachable!()
+    }
+
+    /// Query a namespace. A namespace that doesn't exist yet has no rows.
+    pub async fn query(
+        &self,
+        namespace: &str,
+        params: rs_puff::QueryParams,
+    ) -> Result<Vec<rs_puff::Row>> {
+        let _permit = self.request_permit().await;
+        match self.client.namespace(namespace).query(params).await {
+            Ok(response) => Ok(response.rows),
+            Err(rs_puff::Error::Api { status: 404, .. }) => Ok(vec![]),
+            Err(e) => Err(e).context(format!("Failed to query namespace '{}'", namespace)),
+        }
+    }
+
+    /// Read every document in a namespace, in id order, a page at a time.
+    /// Documents carry all their attributes with `attributes`, and only
+    /// their id otherwise. A namespace that doesn't exist yet has none.
+    pub async fn export(&self, namespace: &str, attributes: bool) -> Result<Vec<rs_puff::Row>> {
+        let mut rows = Vec::new();
+        let mut after: Option<serde_json::Value> = None;
+        loop {
+            let params = rs_puff::QueryParams {
+                rank_by: Some(rs_puff::RankBy::asc("id")),
+                top_k: Some(EXPORT_PAGE_SIZE),
+                filters: after.clone().map(|id| rs_puff::Filter::gte("id", id)),
+                include_attributes: attributes.then_some(rs_puff::IncludeAttributes::All(true)),
+                ..Default::default()
+            };
+            let page = self.query(namespace, params).await?;
+            let full_page = page.len() as u64 == EXPORT_PAGE_SIZE;
+            let last = page.last().and_then(|row| row.get("id")).cloned();
+
+            // Paging is inclusive, so the first document was on the previous page
+            rows.extend(
+                page.into_iter()
+                    .filter(|row| after.is_none() || row.get("id") != after.as_ref()),
+            );
+            if !full_page || last == after {
+                return Ok(rows);
+            }
+            after = last;
+        }
+    }
+
+    /// Current write counters.
+    pub fn stats(&self) -> WriteStats {
+        WriteStats {
+            writes: self.metrics.writes.load(Ordering::Relaxed),
+            rows_upserted: self.metrics.rows_upserted.load(Ordering::Relaxed),
+            rows_patched: self.metrics.rows_patched.load(Ordering::Relaxed),
+            rows_deleted: self.metrics.rows_deleted.load(Ordering::Relaxed),
+            retries: self.metrics.retries.load(Ordering::Relaxed),
+            rate_limited: self.metrics.rate_limited.load(Ordering::Relaxed),
+            failures: self.metrics.failures.load(Ordering::Relaxed),
+            requests: self.metrics.requests.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Wait for room under the in-flight request cap, and count the request.
+    async fn request_permit(&self) -> Option<SemaphorePermit<'_>> {
+        let permit = match &self.connections {
+            // The semaphore is never closed
+            Some(connections) => connections.acquire().await.ok(),
+            None => None,
+        };
+        self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+        permit
+    }
+
+    async fn attempt(
+        &self,
+        namespace: &str,
+        params: &rs_puff::WriteParams,
+    ) -> std::result::Result<(), (ErrorKind, anyhow::Error)> {
+        if self
+            .faults
+            .as_ref()
+            .is_some_and(|f| f.inject_write_failure())
+        {
+            return Err((
+                ErrorKind::ServiceUnavailable,
+                anyhow!("Injected fault: turbopuffer write failed"),
+            ));
+        }
+
+        let _permit = self.request_permit().await;
+        #[cfg(test)]
+        if let Some(sink) = &self.sink {
+            return sink
+                .write(namespace, params)
+                .await
+                .map_err(|e| (classify_error(&e), e.into()));
+        }
+        self.client
+            .namespace(namespace)
+            .write(params.clone())
+            .await
+            .map(|_| ())
+            .map_err(|e| (classify_error(&e), e.into()))
+    }
+}
+
+/// Classify a failed write, which may or may not come from turbopuffer: the
+/// first error in the chain that knows its kind decides.
+pub fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<rs_puff::Error>() {
+                Some(classify_error(e))
+            } else if let Some(e) = cause.downcast_ref::<puffgres_pg::PgError>() {
+                Some(e.kind())
+            } else if let Some(e) = cause.downcast_ref::<puffgres_core::Error>() {
+                Some(e.kind())
+            } else {
+                cause
+                    .downcast_ref::<puffgres_state::StateError>()
+                    .map(|e| {
+                        if e.is_retryable() {
+                            ErrorKind::ServiceUnavailable
+                        } else {
+                            ErrorKind::Unknown
+                        }
+                    })
+            }
+        })
+        .unwrap_or(ErrorKind::Unknown)
+}
+
+/// Classify a turbopuffer error as retryable or terminal.
+pub fn classify_error(error: &rs_puff::Error) -> ErrorKind {
+    match error {
+        rs_puff::Error::Api { status, .. } => classify_status(*status),
+        rs_puff::Error::Http(e) if e.is_timeout() => ErrorKind::Timeout,
+        rs_puff::Error::Http(_) => ErrorKind::NetworkError,
+        // The request went through but the response couldn't be decoded
+        rs_puff::Error::Json(_) => ErrorKind::Unknown,
+    }
+}
+
+pub(crate) fn classify_status(status: u16) -> ErrorKind {
+    match status {
+        429 => ErrorKind::RateLimited,
+        408 => ErrorKind::Timeout,
+        500..=599 => ErrorKind::ServiceUnavailable,
+        401 | 403 => ErrorKind::PermissionDenied,
+        400..=499 => ErrorKind::InvalidData,
+        _ => ErrorKind::Unknown,
+    }
+}
+
+/// Delay before retrying after the given attempt (0-based).
+///
+/// Doubles per attempt up to `MAX_DELAY`, then scales by `jitter` (in [0, 1))
+/// into the upper half of the window so concurrent writers spread out.
+pub fn backoff_delay(attempt: u32, kind: ErrorKind, jitter: f64) -> Duration {
+    let base = if kind == ErrorKind::RateLimited {
+        RATE_LIMIT_BASE_DELAY
+    } else {
+        BASE_DELAY
+    };
+
+    let exponential = base
+        .checked_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+        .unwrap_or(MAX_DELAY)
+        .min(MAX_DELAY);
+
+    exponential.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// A value in [0, 1) for retry jitter. Doesn't need to be high quality, just
+/// different between processes retrying at the same time.
+pub(crate) fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    f64::from(nanos % 1000) / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_status() {
+        assert_eq!(classify_status(429), ErrorKind::RateLimited);
+        assert_eq!(classify_status(408), ErrorKind::Timeout);
+        assert_eq!(classify_status(503), ErrorKind::ServiceUnavailable);
+        assert_eq!(classify_status(400), ErrorKind::InvalidData);
+        assert_eq!(classify_status(0), ErrorKind::Unknown);
+
+        assert!(classify_status(502).is_retryable());
+        assert!(!classify_status(422).is_retryable());
+    }
+
+    #[test]
+    fn test_classify_api_error() {
+        let error = rs_puff::Error::Api {
+            status: 429,
+            message: "slow down".into(),
+        };
+        assert_eq!(classify_error(&error), ErrorKind::RateLimited);
+    }
+
+    #[test]
+    fn test_error_kind_walks_the_chain() {
+        let error = anyhow::Error::new(puffgres_pg::PgError::SerializationConflict(
+            "could not serialize access".into(),
+        ))
+        .context("Failed to save checkpoint");
+        assert_eq!(error_kind(&error), ErrorKind::Conflict);
+
+        let error = anyhow::Error::new(rs_puff::Error::Api {
+            status: 403,
+            message: "bad key".into(),
+        })
+        .context("Failed to write batch");
+        assert_eq!(error_kind(&error), ErrorKind::PermissionDenied);
+
+        assert_eq!(error_kind(&anyhow!("plain")), ErrorKind::Unknown);
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let kind = ErrorKind::NetworkError;
+        assert_eq!(backoff_delay(0, kind, 1.0), Duration::from_millis(100));
+        assert_eq!(backoff_delay(3, kind, 1.0), Duration::from_millis(800));
+        assert_eq!(backoff_delay(20, kind, 1.0), MAX_DELAY);
+        assert_eq!(backoff_delay(40, kind, 1.0), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_backoff_jitter_range() {
+        let kind = ErrorKind::Timeout;
+        assert_eq!(backoff_delay(2, kind, 0.0), Duration::from_millis(200));
+        assert_eq!(backoff_delay(2, kind, 0.5), Duration::from_millis(300));
+        assert_eq!(backoff_delay(2, kind, 1.0), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_rate_limit_backs_off_longer() {
+        assert_eq!(
+            backoff_delay(0, ErrorKind::RateLimited, 1.0),
+            RATE_LIMIT_BASE_DELAY
+        );
+        assert!(
+            backoff_delay(0, ErrorKind::RateLimited, 0.0)
+                > backoff_delay(0, ErrorKind::ServiceUnavailable, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_stats_start_empty() {
+        let client = TurbopufferClient::new("key", 3);
+        assert_eq!(client.stats(), WriteStats::default());
+    }
+}
@@ -7,10 +7,10 @@ use std::path::Path;
 use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
 
-use puffgres_config::{IdTypeConfig, MigrationConfig};
+use puffgres_config::IdTypeConfig;
 use puffgres_pg::{IdColumnSample, LocalMigration, PostgresStateStore};
 
-use crate::config::ProjectConfig;
+use crate::config::{parse_migration, ProjectConfig};
 
 /// Validate that a table exists in the database.
 #[allow(dead_code)]
@@ -41,12 +41,15 @@ pub async fn validate_all_tables_exist(
     migrations: &[LocalMigration],
 ) -> Result<()> {
     for migration in migrations {
-        let config = MigrationConfig::parse(&migration.content).with_context(|| {
+        let config = parse_migration(&migration.content).with_context(|| {
             format!(
                 "Failed to parse migration v{} '{}'",
                 migration.version, migration.mapping_name
             )
         })?;
+        if config.source.query.is_some() {
+            continue;
+        }
 
         validate_table_exists(
             store,
@@ -67,7 +70,7 @@ pub fn get_referenced_transforms(migrations: &[LocalMigration]) -> Result<HashSe
     let mut referenced = HashSet::new();
 
     for migration in migrations {
-        let config = MigrationConfig::parse(&migration.content).with_context(|| {
+        let config = parse_migration(&migration.content).with_context(|| {
             format!(
                 "Failed to parse migration v{} '{}'",
                 migration.version, migration.mapping_name
@@ -277,6 +280,9 @@ pub fn infer_id_type(sample: &IdColumnSample) -> IdTypeConfig {
     if pg_type == "uuid" {
         return IdTypeConfig::Uuid;
     }
+    if pg_type == "bytea" {
+        return IdTypeConfig::Bytes;
+    }
 
     // Check for integer types in PostgreSQL
     let is_pg_integer = matches!(
@@ -351,7 +357,7 @@ pub fn values_match_type(sample: &IdColumnSample, configured: IdTypeConfig) -> b
             // Otherwise check if all values parse as UUID
             sample.values.iter().all(|v| uuid::Uuid::parse_str(v).is_ok())
         }
-        IdTypeConfig::Uint => {
+        IdTypeConfig::Uint | IdTypeConfig::ObfuscatedUint => {
             // Check if all values are non-negative integers
             sample.values.iter().all(|v| {
                 v.parse::<i64>()
@@ -367,6 +373,7 @@ pub fn values_match_type(sample: &IdColumnSample, configured: IdTypeConfig) -> b
             // String accepts anything
             true
         }
+        IdTypeConfig::Bytes => sample.pg_type.to_lowercase() == "bytea",
     }
 }
 
@@ -461,6 +468,8 @@ mode = "source_lsn"
             version: 1,
             mapping_name: name.to_string(),
             content,
+            shadow: false,
+            templates: Vec::new(),
         }
     }
 
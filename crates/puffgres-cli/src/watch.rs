@@ -0,0 +1,125 @@
+//! Watch transform files for changes during `puffgres run --dev`.
+//!
+//! The watcher polls file modification times rather than subscribing to OS
+//! notifications. A transforms directory holds a handful of files, so a stat
+//! per file before each transaction costs nothing next to running Node.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Minimum time between two scans of the directory.
+const SCAN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Tracks modification times of the `.ts` and `.js` files in a directory.
+pub struct TransformWatcher {
+    dir: PathBuf,
+    mtimes: HashMap<PathBuf, SystemTime>,
+    last_scan: Instant,
+}
+
+impl TransformWatcher {
+    /// Start watching `dir`. Files present now are the baseline.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let mtimes = scan(&dir);
+        Self {
+            dir,
+            mtimes,
+            last_scan: Instant::now(),
+        }
+    }
+
+    /// Return the files that were added, modified or removed since the last
+    /// call. Scans at most once per [`SCAN_INTERVAL`].
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        if self.last_scan.elapsed() < SCAN_INTERVAL {
+            return Vec::new();
+        }
+        self.last_scan = Instant::now();
+
+        let current = scan(&self.dir);
+        let mut changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|(path, mtime)| self.mtimes.get(*path) != Some(*mtime))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.mtimes
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+
+        self.mtimes = current;
+        changed
+    }
+}
+
+/// Collect modification times for transform files. A missing directory
+/// counts as empty.
+fn scan(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "ts" || ext == "js")
+        })
+        .filter_map(|path| {
+            let mtime = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, mtime))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detects_added_modified_and_removed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("users.ts"), "v1").unwrap();
+        fs::write(dir.join("orders.ts"), "v1").unwrap();
+        fs::write(dir.join("notes.md"), "ignored").unwrap();
+
+        let mut watcher = TransformWatcher::new(dir);
+        watcher.last_scan -= SCAN_INTERVAL;
+        assert!(watcher.changed().is_empty());
+
+        // Push the mtime forward explicitly; some filesystems only keep
+        // second precision
+        let users = fs::File::options()
+            .write(true)
+            .open(dir.join("users.ts"))
+            .unwrap();
+        users
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        fs::remove_file(dir.join("orders.ts")).unwrap();
+        fs::write(dir.join("posts.js"), "v1").unwrap();
+        fs::write(dir.join("notes.md"), "still ignored").unwrap();
+
+        watcher.last_scan -= SCAN_INTERVAL;
+        assert_eq!(
+            watcher.changed(),
+            vec![
+                dir.join("orders.ts"),
+                dir.join("posts.js"),
+                dir.join("users.ts"),
+            ]
+        );
+
+        watcher.last_scan -= SCAN_INTERVAL;
+        assert!(watcher.changed().is_empty());
+    }
+}
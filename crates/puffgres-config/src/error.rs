@@ -6,10 +6,18 @@ pub enum ConfigError {
     #[error("failed to parse TOML: {0}")]
     ParseError(#[from] toml::de::Error),
 
+    #[error("unknown key '{key}'{}{}", at_line(.location), did_you_mean(.suggestion))]
+    UnknownKey {
+        key: String,
+        /// Line and column, when the key is in the file itself.
+        location: Option<(usize, usize)>,
+        suggestion: Option<String>,
+    },
+
     #[error("missing required field: {field}")]
     MissingField { field: String },
 
-    #[error("invalid id type '{value}': expected one of uint, int, uuid, string")]
+    #[error("invalid id type '{value}': expected one of uint, int, uuid, string, bytes, obfuscated_uint")]
     InvalidIdType { value: String },
 
     #[error("invalid membership mode '{value}': expected one of dsl, view, lookup")]
@@ -18,12 +26,54 @@ pub enum ConfigError {
     #[error("invalid predicate syntax: {message}")]
     InvalidPredicate { message: String },
 
-    #[error("invalid versioning mode '{value}': expected one of source_lsn, column, none")]
+    #[error("invalid versioning mode '{value}': expected one of source_lsn, column, none, content_hash")]
     InvalidVersioningMode { value: String },
 
+    #[error("invalid id config: {message}")]
+    InvalidId { message: String },
+
     #[error("missing id column '{column}' in columns list")]
     IdColumnNotInColumns { column: String },
 
+    #[error("invalid rename for column '{column}': {message}")]
+    InvalidRename { column: String, message: String },
+
+    #[error("invalid type for column '{column}': {message}")]
+    InvalidType { column: String, message: String },
+
+    #[error("invalid computed attribute '{attribute}': {message}")]
+    InvalidComputed { attribute: String, message: String },
+
+    #[error("invalid vector config: {message}")]
+    InvalidVector { message: String },
+
+    #[error("invalid embed config: {message}")]
+    InvalidEmbed { message: String },
+
+    #[error("invalid namespace: {message}")]
+    InvalidNamespace { message: String },
+
+    #[error("invalid flatten config: {message}")]
+    InvalidFlatten { message: String },
+
+    #[error("invalid errors config: {message}")]
+    InvalidErrors { message: String },
+
+    #[error("invalid limits config: {message}")]
+    InvalidLimits { message: String },
+
+    #[error("invalid batch config: {message}")]
+    InvalidBatch { message: String },
+
+    #[error("invalid attributes config: {message}")]
+    InvalidAttributes { message: String },
+
+    #[error("invalid source config: {message}")]
+    InvalidSource { message: String },
+
+    #[error("invalid sink '{name}': {message}")]
+    InvalidSink { name: String, message: String },
+
     #[error("DSL membership requires 'predicate' field")]
     MissingPredicate,
 
@@ -35,6 +85,29 @@ pub enum ConfigError {
 
     #[error("transform configuration error: {0}")]
     TransformError(String),
+
+    #[error("template '{template}': {message}")]
+    TemplateError { template: String, message: String },
+
+    #[error("migration '{mapping}' is invalid after applying template '{template}': {message}")]
+    ExtendedMigrationError {
+        mapping: String,
+        template: String,
+        message: String,
+    },
+}
+
+fn at_line(location: &Option<(usize, usize)>) -> String {
+    location
+        .map(|(line, column)| format!(" at line {}, column {}", line, column))
+        .unwrap_or_default()
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    suggestion
+        .as_ref()
+        .map(|key| format!("; did you mean '{}'?", key))
+        .unwrap_or_default()
 }
 
 pub type ConfigResult<T> = Result<T, ConfigError>;
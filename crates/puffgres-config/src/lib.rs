@@ -1,9 +1,13 @@
 mod error;
 mod migration;
+mod schema;
+mod template;
 mod validation;
 
 pub use error::{ConfigError, ConfigResult};
 pub use migration::{
-    IdTypeConfig, MembershipMode, MigrationConfig, SourceConfig, TransformConfig, VersioningConfig,
+    AttributeName, AttributesConfig, BatchConfig, ColumnTypeConfig, ColumnsConfig, DistanceMetricConfig, EmbedConfig, ErrorsConfig, IdStrategyConfig, IdTypeConfig, LimitsConfig, MembershipMode, MigrationConfig, OnOversize, OnTransformError, OnTruncate, SinkConfig, SourceConfig, TransformConfig, VectorConfig, VersioningConfig,
 };
+pub use schema::{check_env_vars, check_keys, KeySchema, MIGRATION_KEYS};
+pub use template::{resolve_templates, template_sources};
 pub use validation::{to_mapping, validate_migration};
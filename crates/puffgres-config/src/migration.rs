@@ -1,23 +1,58 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 
-use crate::error::ConfigResult;
+use crate::error::{ConfigError, ConfigResult};
+use crate::schema::{check_keys, MIGRATION_KEYS};
+use crate::template::resolve_templates;
 
 /// Raw migration configuration as parsed from TOML.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MigrationConfig {
+    /// Template this migration extends (e.g., "base.toml"), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     /// Version number (monotonically increasing).
     pub version: i64,
     /// Stable identifier for this mapping.
     pub mapping_name: String,
+    /// Build this revision beside the mapping's current migration instead of
+    /// replacing it. It writes to `{namespace}__v{version}` while the current
+    /// one keeps serving, until `puffgres promote` makes it current.
+    #[serde(default)]
+    pub shadow: bool,
     /// Target turbopuffer namespace.
     pub namespace: String,
     /// Source relation configuration.
     pub source: SourceConfig,
     /// ID column configuration.
     pub id: IdConfig,
-    /// Columns to extract from the row.
+    /// Columns to extract from the row, and optional attribute renames.
     #[serde(default)]
-    pub columns: Vec<String>,
+    pub columns: ColumnsConfig,
+    /// Types forced on columns by the identity transform.
+    #[serde(default)]
+    pub types: BTreeMap<String, ColumnTypeConfig>,
+    /// Attributes computed from the row by the identity transform
+    /// (attribute -> expression).
+    ///
+    /// ```toml
+    /// [computed]
+    /// full_name = "concat(first_name, ' ', last_name)"
+    /// ```
+    #[serde(default)]
+    pub computed: BTreeMap<String, String>,
+    /// Column written as each document's vector by the identity transform.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<VectorConfig>,
+    /// Attributes embedded into each document's vector by the configured
+    /// embedding provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embed: Option<EmbedConfig>,
+    /// JSON columns to flatten into top-level attributes.
+    #[serde(default)]
+    pub flatten: FlattenConfig,
     /// Membership configuration.
     #[serde(default)]
     pub membership: MembershipConfig,
@@ -27,37 +62,176 @@ pub struct MigrationConfig {
     /// Batching configuration.
     #[serde(default)]
     pub batching: BatchingConfig,
+    /// The mapping's own batch limits, over the runner's defaults.
+    #[serde(default)]
+    pub batch: BatchConfig,
     /// Versioning configuration.
     #[serde(default)]
     pub versioning: VersioningConfig,
+    /// Write the source transaction's commit time to `__event_time`.
+    #[serde(default)]
+    pub event_time: bool,
+    /// Write the id of the `puffgres run` or `backfill` that wrote each document to `__run_id`.
+    #[serde(default)]
+    pub run_id: bool,
+    /// Names of the attributes puffgres adds to every document.
+    #[serde(default)]
+    pub attributes: AttributesConfig,
+    /// What to do with rows that fail to transform.
+    #[serde(default)]
+    pub errors: ErrorsConfig,
+    /// Size limit on transformed documents.
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Other namespaces every write is copied to, by sink name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sinks: BTreeMap<String, SinkConfig>,
 }
 
 impl MigrationConfig {
     /// Parse a migration config from a TOML string.
+    ///
+    /// Unknown keys are rejected, and errors give the line and column.
     pub fn parse(toml_str: &str) -> ConfigResult<Self> {
+        let table: toml::Table = toml::from_str(toml_str)?;
+        check_keys(toml_str, &table, &MIGRATION_KEYS)?;
+        // Parsed from the text again, rather than from the table, so a bad
+        // value's error points at it
         let config: MigrationConfig = toml::from_str(toml_str)?;
         Ok(config)
     }
+
+    /// Parse a migration config, resolving `extends` against templates in `templates_dir`.
+    ///
+    /// Errors in the merged result name the migration that declared `extends`
+    /// as well as the template, since a bad value may come from either.
+    pub fn parse_with_templates(toml_str: &str, templates_dir: &Path) -> ConfigResult<Self> {
+        Self::from_resolved(toml_str, resolve_templates(toml_str, templates_dir)?)
+    }
+
+    /// [`Self::parse_with_templates`], given the table `toml_str` resolved to.
+    pub(crate) fn from_resolved(toml_str: &str, table: toml::Table) -> ConfigResult<Self> {
+        let Some(template) = table
+            .get("extends")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+        else {
+            return Self::parse(toml_str);
+        };
+        let mapping = table
+            .get("mapping_name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("<unnamed>")
+            .to_string();
+
+        Self::from_table(toml_str, table).map_err(|e| ConfigError::ExtendedMigrationError {
+            mapping,
+            template,
+            message: e.to_string(),
+        })
+    }
+
+    /// Build a migration config from an already-resolved TOML table, parsed
+    /// from `toml_str` and whatever it extends.
+    pub(crate) fn from_table(toml_str: &str, table: toml::Table) -> ConfigResult<Self> {
+        check_keys(toml_str, &table, &MIGRATION_KEYS)?;
+        let config: MigrationConfig = toml::Value::Table(table).try_into()?;
+        Ok(config)
+    }
 }
 
 /// Source relation configuration.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SourceConfig {
     /// Schema name (e.g., "public").
+    #[serde(default)]
     pub schema: String,
     /// Table or view name.
-    #[serde(alias = "view")]
+    #[serde(default, alias = "view")]
     pub table: String,
+    /// A query whose rows are synced instead of a table's, e.g. a join or a
+    /// materialized view. Query sources aren't replicated; `puffgres refresh`
+    /// re-runs the query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// What a TRUNCATE of the table does to the namespace.
+    #[serde(default)]
+    pub on_truncate: OnTruncate,
+    /// Stream this table from its own slot and publication, shared with
+    /// other migrations in the same group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replication_group: Option<String>,
+}
+
+/// Action for a TRUNCATE of the source table.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnTruncate {
+    /// Log a warning; the truncated rows stay in turbopuffer (default).
+    #[default]
+    Ignore,
+    /// Delete every document in the namespace.
+    Clear,
+    /// Stop the runner.
+    Error,
 }
 
 /// ID column configuration (raw from TOML).
-#[derive(Debug, Deserialize, Serialize)]
+///
+/// Tables without a single unique column set `columns` instead of `column`:
+///
+/// ```toml
+/// [id]
+/// columns = ["tenant_id", "order_id"]
+/// type = "string"
+/// strategy = "concat"  # or "uuid5", with type = "uuid"
+/// separator = ":"
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct IdConfig {
-    /// Column name.
+    /// Column name. Empty for a composite ID.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub column: String,
+    /// Columns a composite ID is built from, in order. Ignored when `column`
+    /// is set, as it was before composite IDs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
     /// ID type.
     #[serde(rename = "type")]
     pub id_type: IdTypeConfig,
+    /// How a composite ID's columns are combined (default concat).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<IdStrategyConfig>,
+    /// Separator between a concatenated ID's values (default ":").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+}
+
+impl IdConfig {
+    /// The columns the ID is read from.
+    pub fn all_columns(&self) -> &[String] {
+        if self.is_composite() {
+            &self.columns
+        } else {
+            std::slice::from_ref(&self.column)
+        }
+    }
+
+    /// Whether the ID is built from several columns.
+    pub fn is_composite(&self) -> bool {
+        self.column.is_empty() && !self.columns.is_empty()
+    }
+}
+
+/// How a composite ID's columns are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdStrategyConfig {
+    /// The values joined by `separator`, as a string ID.
+    #[default]
+    Concat,
+    /// A version 5 UUID of the values.
+    Uuid5,
 }
 
 /// ID type configuration.
@@ -68,27 +242,217 @@ pub enum IdTypeConfig {
     Int,
     Uuid,
     String,
+    /// `bytea` IDs, written as a string of their hex.
+    Bytes,
+    /// Integer IDs obfuscated with the project secret before they're written.
+    #[serde(rename = "obfuscated_uint")]
+    ObfuscatedUint,
 }
 
 impl IdTypeConfig {
+    /// The core ID type. An obfuscated ID type has no secret yet; the caller
+    /// supplies one.
     pub fn to_core_type(self) -> puffgres_core::IdType {
         match self {
             IdTypeConfig::Uint => puffgres_core::IdType::Uint,
             IdTypeConfig::Int => puffgres_core::IdType::Int,
             IdTypeConfig::Uuid => puffgres_core::IdType::Uuid,
             IdTypeConfig::String => puffgres_core::IdType::String,
+            IdTypeConfig::Bytes => puffgres_core::IdType::Bytes,
+            IdTypeConfig::ObfuscatedUint => puffgres_core::IdType::ObfuscatedUint(None),
         }
     }
 }
 
+/// What a column is written to documents as, overriding the type it's
+/// decoded as.
+///
+/// ```toml
+/// [types]
+/// price = "string"
+/// created_at = "unix_ms"
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnTypeConfig {
+    String,
+    Int,
+    Float,
+    Bool,
+    /// Milliseconds since the Unix epoch, from a timestamp or date.
+    UnixMs,
+}
+
+impl ColumnTypeConfig {
+    /// The core column type.
+    pub fn to_core_type(self) -> puffgres_core::ColumnType {
+        match self {
+            ColumnTypeConfig::String => puffgres_core::ColumnType::String,
+            ColumnTypeConfig::Int => puffgres_core::ColumnType::Int,
+            ColumnTypeConfig::Float => puffgres_core::ColumnType::Float,
+            ColumnTypeConfig::Bool => puffgres_core::ColumnType::Bool,
+            ColumnTypeConfig::UnixMs => puffgres_core::ColumnType::UnixMs,
+        }
+    }
+}
+
+/// Column selection and renaming.
+///
+/// Accepts either a plain list (`columns = ["id", "title"]`) or a table:
+///
+/// ```toml
+/// [columns]
+/// include = ["id", "created_at"]
+///
+/// [columns.rename]
+/// created_at = "createdAt"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(from = "ColumnsRepr")]
+pub struct ColumnsConfig {
+    /// Columns to extract from the row (empty = all columns).
+    pub include: Vec<String>,
+    /// Map of source column name to turbopuffer attribute name.
+    pub rename: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColumnsRepr {
+    List(Vec<String>),
+    Table {
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        rename: BTreeMap<String, String>,
+    },
+}
+
+impl From<ColumnsRepr> for ColumnsConfig {
+    fn from(repr: ColumnsRepr) -> Self {
+        match repr {
+            ColumnsRepr::List(include) => Self {
+                include,
+                rename: BTreeMap::new(),
+            },
+            ColumnsRepr::Table { include, rename } => Self { include, rename },
+        }
+    }
+}
+
+/// A column written as the document vector instead of as an attribute.
+///
+/// ```toml
+/// [vector]
+/// column = "embedding"
+/// distance_metric = "cosine_distance"
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VectorConfig {
+    /// Column holding the embedding: a pgvector `vector` or an array of numbers.
+    pub column: String,
+    /// Distance metric for the namespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_metric: Option<DistanceMetricConfig>,
+}
+
+/// Attributes whose text is embedded into the document vector.
+///
+/// ```toml
+/// [embed]
+/// columns = ["title", "body"]
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmbedConfig {
+    /// Document attributes to embed, in order.
+    pub columns: Vec<String>,
+    /// Distance metric for the namespace.
+    #[serde(default = "default_embed_distance_metric")]
+    pub distance_metric: DistanceMetricConfig,
+}
+
+fn default_embed_distance_metric() -> DistanceMetricConfig {
+    DistanceMetricConfig::CosineDistance
+}
+
+/// Vector distance metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetricConfig {
+    CosineDistance,
+    EuclideanSquared,
+}
+
+impl DistanceMetricConfig {
+    /// The core distance metric.
+    pub fn to_core_type(self) -> puffgres_core::DistanceMetric {
+        match self {
+            DistanceMetricConfig::CosineDistance => puffgres_core::DistanceMetric::CosineDistance,
+            DistanceMetricConfig::EuclideanSquared => {
+                puffgres_core::DistanceMetric::EuclideanSquared
+            }
+        }
+    }
+}
+
+/// JSON column flattening.
+///
+/// ```toml
+/// [flatten]
+/// columns = ["metadata"]
+/// max_depth = 2
+/// allow = ["metadata.author", "metadata.source.site"]
+/// ```
+///
+/// With the default separator, `metadata.author` becomes `metadata_author`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FlattenConfig {
+    /// JSON/JSONB columns to flatten.
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Levels of nesting to expand.
+    #[serde(default = "default_flatten_depth")]
+    pub max_depth: usize,
+    /// Separator between path segments in attribute names.
+    #[serde(default = "default_flatten_separator")]
+    pub separator: String,
+    /// Dotted paths to keep (empty = keep all keys).
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl Default for FlattenConfig {
+    fn default() -> Self {
+        Self {
+            columns: vec![],
+            max_depth: default_flatten_depth(),
+            separator: default_flatten_separator(),
+            allow: vec![],
+        }
+    }
+}
+
+fn default_flatten_depth() -> usize {
+    1
+}
+
+fn default_flatten_separator() -> String {
+    "_".into()
+}
+
 /// Membership configuration.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct MembershipConfig {
     /// Membership mode.
     #[serde(default)]
     pub mode: MembershipMode,
     /// Predicate expression (for DSL mode).
     pub predicate: Option<String>,
+    /// Compile the predicate into the publication's row filter, so Postgres
+    /// (15+) only streams rows that belong. `puffgres setup` and `migrate`
+    /// apply it.
+    #[serde(default)]
+    pub row_filter: bool,
 }
 
 /// Membership mode.
@@ -107,7 +471,7 @@ pub enum MembershipMode {
 }
 
 /// Transform configuration.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct TransformConfig {
     /// Transform type.
     #[serde(rename = "type", default)]
@@ -132,7 +496,7 @@ pub enum TransformType {
 }
 
 /// Batching configuration.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BatchingConfig {
     /// Maximum rows per batch.
     #[serde(default = "default_max_rows")]
@@ -167,8 +531,30 @@ fn default_flush_interval() -> u64 {
     100
 }
 
+/// Batch limits for this mapping, over the runner's defaults. Without
+/// `max_wait_ms` batches are written at the end of every transaction; with
+/// it they're held across transactions until full or that old.
+///
+/// ```toml
+/// [batch]
+/// max_rows = 500
+/// max_wait_ms = 2000
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BatchConfig {
+    /// Rows per batch. Defaults to `PUFFGRES_TRANSFORM_BATCH_SIZE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows: Option<usize>,
+    /// Longest a batch is held for more rows, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_wait_ms: Option<u64>,
+    /// Approximate bytes per batch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<usize>,
+}
+
 /// Versioning configuration.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct VersioningConfig {
     /// Versioning mode.
     #[serde(default)]
@@ -188,6 +574,137 @@ pub enum VersioningMode {
     Column,
     /// No versioning.
     None,
+    /// Skip upserts whose document hash hasn't changed.
+    ContentHash,
+}
+
+/// Names of the bookkeeping attributes puffgres adds to every document.
+/// Each takes a name, or `false` to leave the attribute out.
+///
+/// ```toml
+/// [attributes]
+/// source_lsn = "_lsn"
+/// backfill = false
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AttributesConfig {
+    /// The LSN of the change behind each write. `__source_lsn` by default.
+    #[serde(default)]
+    pub source_lsn: AttributeName,
+    /// Set to `true` on documents a backfill wrote. `__backfill` by default.
+    #[serde(default)]
+    pub backfill: AttributeName,
+}
+
+/// A bookkeeping attribute's name, or whether it's written at all.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum AttributeName {
+    /// `true` for the default name, `false` to leave the attribute out.
+    Enabled(bool),
+    Name(String),
+}
+
+impl Default for AttributeName {
+    fn default() -> Self {
+        AttributeName::Enabled(true)
+    }
+}
+
+impl AttributeName {
+    /// The name written, `None` when the attribute is left out.
+    pub fn resolve<'a>(&'a self, default: &'a str) -> Option<&'a str> {
+        match self {
+            AttributeName::Enabled(true) => Some(default),
+            AttributeName::Enabled(false) => None,
+            AttributeName::Name(name) => Some(name),
+        }
+    }
+}
+
+/// Error handling for rows that can't be turned into documents.
+///
+/// ```toml
+/// [errors]
+/// on_transform_error = "dlq"
+/// max_consecutive_errors = 100
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ErrorsConfig {
+    /// What to do with a row whose ID can't be extracted or whose transform fails.
+    #[serde(default)]
+    pub on_transform_error: OnTransformError,
+    /// Stop the runner after this many failed rows in a row.
+    pub max_consecutive_errors: Option<u32>,
+}
+
+/// Action for rows that fail to transform.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnTransformError {
+    /// Send the event to the dead letter queue.
+    Dlq,
+    /// Log and skip the row (default).
+    #[default]
+    Skip,
+    /// Stop the runner.
+    Halt,
+}
+
+/// Size limit on the documents a transform returns.
+///
+/// ```toml
+/// [limits]
+/// max_document_bytes = 65536
+/// on_oversize = "truncate"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LimitsConfig {
+    /// Largest document allowed, in bytes. No limit if unset.
+    pub max_document_bytes: Option<usize>,
+    /// What to do with a document over the limit.
+    #[serde(default)]
+    pub on_oversize: OnOversize,
+}
+
+/// A secondary namespace the mapping's writes are copied to.
+///
+/// ```toml
+/// [sinks.dr]
+/// namespace = "{namespace}"
+/// api_key = "${DR_TURBOPUFFER_API_KEY}"
+/// max_retries = 10
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SinkConfig {
+    /// Namespace to write to. `{namespace}` stands for the namespace the
+    /// write went to, so partitioned mappings keep their partitions.
+    #[serde(default = "default_sink_namespace")]
+    pub namespace: String,
+    /// Turbopuffer API key for the sink, if it's in another organization.
+    /// Uses the project's key if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Retries for the sink's writes. Uses `PUFFGRES_MAX_RETRIES` if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+}
+
+fn default_sink_namespace() -> String {
+    "{namespace}".to_string()
+}
+
+/// Action for documents over the size limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnOversize {
+    /// Shorten the longest string attributes until the document fits.
+    Truncate,
+    /// Send the event to the dead letter queue.
+    Dlq,
+    /// Stop with an error (default).
+    #[default]
+    Fail,
 }
 
 #[cfg(test)]
@@ -218,6 +735,7 @@ type = "uint"
         assert_eq!(config.source.table, "users");
         assert_eq!(config.id.column, "id");
         assert_eq!(config.id.id_type, IdTypeConfig::Uint);
+        assert!(!config.shadow);
     }
 
     #[test]
@@ -226,6 +744,7 @@ type = "uint"
 version = 2
 mapping_name = "active_pages"
 namespace = "pages"
+shadow = true
 columns = ["page_id", "title", "content", "status", "deleted_at"]
 
 [source]
@@ -255,8 +774,10 @@ mode = "source_lsn"
         let config = MigrationConfig::parse(toml).unwrap();
         assert_eq!(config.version, 2);
         assert_eq!(config.mapping_name, "active_pages");
+        assert!(config.shadow);
         assert_eq!(config.id.id_type, IdTypeConfig::Uuid);
-        assert_eq!(config.columns.len(), 5);
+        assert_eq!(config.columns.include.len(), 5);
+        assert!(config.columns.rename.is_empty());
         assert_eq!(config.membership.mode, MembershipMode::Dsl);
         assert!(config.membership.predicate.is_some());
         assert_eq!(config.batching.batch_max_rows, 500);
@@ -312,6 +833,92 @@ column = "updated_at"
         assert_eq!(config.versioning.column, Some("updated_at".into()));
     }
 
+    #[test]
+    fn test_parse_column_renames() {
+        let toml = r#"
+version = 1
+mapping_name = "users"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+
+[columns]
+include = ["id", "created_at", "full_name"]
+
+[columns.rename]
+created_at = "createdAt"
+full_name = "name"
+"#;
+
+        let config = MigrationConfig::parse(toml).unwrap();
+        assert_eq!(config.columns.include, vec!["id", "created_at", "full_name"]);
+        assert_eq!(config.columns.rename.len(), 2);
+        assert_eq!(config.columns.rename["created_at"], "createdAt");
+        assert_eq!(config.columns.rename["full_name"], "name");
+    }
+
+    #[test]
+    fn test_parse_flatten() {
+        let toml = r#"
+version = 1
+mapping_name = "posts"
+namespace = "posts"
+
+[source]
+schema = "public"
+table = "posts"
+
+[id]
+column = "id"
+type = "uint"
+
+[flatten]
+columns = ["metadata"]
+max_depth = 2
+allow = ["metadata.author"]
+"#;
+
+        let config = MigrationConfig::parse(toml).unwrap();
+        assert_eq!(config.flatten.columns, vec!["metadata"]);
+        assert_eq!(config.flatten.max_depth, 2);
+        assert_eq!(config.flatten.separator, "_");
+        assert_eq!(config.flatten.allow, vec!["metadata.author"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let toml = r#"
+version = 1
+mapping_name = "invoices"
+namespace = "invoices"
+
+[source]
+schema = "billing"
+table = "invoices"
+
+[id]
+column = "id"
+type = "uint"
+
+[errors]
+on_transform_error = "halt"
+max_consecutive_errors = 5
+"#;
+
+        let config = MigrationConfig::parse(toml).unwrap();
+        assert_eq!(config.errors.on_transform_error, OnTransformError::Halt);
+        assert_eq!(config.errors.max_consecutive_errors, Some(5));
+
+        let invalid = toml.replace("\"halt\"", "\"retry\"");
+        assert!(MigrationConfig::parse(&invalid).is_err());
+    }
+
     #[test]
     fn test_id_type_conversions() {
         assert!(matches!(
@@ -322,5 +929,36 @@ column = "updated_at"
             IdTypeConfig::Uuid.to_core_type(),
             puffgres_core::IdType::Uuid
         ));
+        assert!(matches!(
+            IdTypeConfig::ObfuscatedUint.to_core_type(),
+            puffgres_core::IdType::ObfuscatedUint(None)
+        ));
+
+        let id: IdConfig = toml::from_str("column = \"id\"\ntype = \"obfuscated_uint\"").unwrap();
+        assert_eq!(id.id_type, IdTypeConfig::ObfuscatedUint);
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_key() {
+        let toml = r#"
+version = 1
+mapping_name = "users"
+namespace = "users"
+
+[source]
+table = "users"
+
+[id]
+column = "id"
+type = "uinty"
+"#;
+        let err = MigrationConfig::parse(toml).unwrap_err().to_string();
+        assert!(err.contains("line 11"), "{}", err);
+
+        let err = MigrationConfig::parse(&toml.replace("column =", "colum =")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown key 'id.colum' at line 10, column 1; did you mean 'column'?"
+        );
     }
 }
@@ -0,0 +1,337 @@
+//! Migration template inheritance.
+//!
+//! A migration can set `extends = "base.toml"` to inherit settings from a
+//! template in the `templates/` directory. Tables are merged recursively, with
+//! values in the migration taking precedence over values in the template.
+//! Templates may themselves extend other templates.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Component, Path};
+
+use toml::{Table, Value};
+
+use crate::error::{ConfigError, ConfigResult};
+
+/// Key used to reference a parent template.
+const EXTENDS_KEY: &str = "extends";
+
+/// Resolve a migration TOML string against templates in `templates_dir`.
+///
+/// Returns the merged table with the `extends` key of the migration preserved
+/// so callers can report which template a mapping came from.
+pub fn resolve_templates(toml_str: &str, templates_dir: &Path) -> ConfigResult<Table> {
+    resolve_with(toml_str, &|name| read_template(templates_dir, name))
+}
+
+/// The contents of the templates a migration TOML string extends, nearest
+/// first, so the migration can be fingerprinted with everything it inherits.
+/// Empty when it extends nothing.
+pub fn template_sources(toml_str: &str, templates_dir: &Path) -> ConfigResult<Vec<String>> {
+    sources_with(toml_str, &|name| read_template(templates_dir, name))
+}
+
+fn read_template(templates_dir: &Path, name: &str) -> ConfigResult<String> {
+    let path = templates_dir.join(name);
+    fs::read_to_string(&path).map_err(|e| ConfigError::TemplateError {
+        template: path.display().to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// [`template_sources`], loading templates with `load`.
+fn sources_with(
+    toml_str: &str,
+    load: &dyn Fn(&str) -> ConfigResult<String>,
+) -> ConfigResult<Vec<String>> {
+    let sources = RefCell::new(Vec::new());
+    resolve_with(toml_str, &|name| {
+        let content = load(name)?;
+        sources.borrow_mut().push(content.clone());
+        Ok(content)
+    })?;
+    Ok(sources.into_inner())
+}
+
+/// Resolve a migration TOML string, loading templates with `load`.
+pub(crate) fn resolve_with(
+    toml_str: &str,
+    load: &dyn Fn(&str) -> ConfigResult<String>,
+) -> ConfigResult<Table> {
+    let table: Table = toml::from_str(toml_str)?;
+    let extends = match table.get(EXTENDS_KEY) {
+        None => return Ok(table),
+        Some(Value::String(name)) => name.clone(),
+        Some(_) => {
+            return Err(ConfigError::TemplateError {
+                template: EXTENDS_KEY.to_string(),
+                message: "'extends' must be a string".to_string(),
+            })
+        }
+    };
+
+    let mut chain = Vec::new();
+    let mut merged = load_template(&extends, load, &mut chain)?;
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+/// Load a template and everything it extends, returning the merged table.
+fn load_template(
+    name: &str,
+    load: &dyn Fn(&str) -> ConfigResult<String>,
+    chain: &mut Vec<String>,
+) -> ConfigResult<Table> {
+    if chain.iter().any(|t| t == name) {
+        chain.push(name.to_string());
+        return Err(ConfigError::TemplateError {
+            template: name.to_string(),
+            message: format!("circular template inheritance: {}", chain.join(" -> ")),
+        });
+    }
+    chain.push(name.to_string());
+
+    // Templates are named relative to the templates directory and can't
+    // reach outside it
+    if !Path::new(name)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(ConfigError::TemplateError {
+            template: name.to_string(),
+            message: "must be a relative path inside the templates directory".to_string(),
+        });
+    }
+
+    let content = load(name)?;
+    let mut table: Table = toml::from_str(&content).map_err(|e| ConfigError::TemplateError {
+        template: name.to_string(),
+        message: e.to_string(),
+    })?;
+
+    match table.remove(EXTENDS_KEY) {
+        None => Ok(table),
+        Some(Value::String(parent)) => {
+            let mut merged = load_template(&parent, load, chain)?;
+            merge_tables(&mut merged, table);
+            Ok(merged)
+        }
+        Some(_) => Err(ConfigError::TemplateError {
+            template: name.to_string(),
+            message: "'extends' must be a string".to_string(),
+        }),
+    }
+}
+
+/// Merge `overlay` into `base`. Nested tables are merged key by key; any other
+/// value in `overlay` replaces the value in `base`.
+fn merge_tables(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        if let Value::Table(overlay_table) = value {
+            if let Some(Value::Table(base_table)) = base.get_mut(&key) {
+                merge_tables(base_table, overlay_table);
+                continue;
+            }
+            base.insert(key, Value::Table(overlay_table));
+        } else {
+            base.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::{IdTypeConfig, MigrationConfig, TransformType, VersioningMode};
+
+    const BASE: &str = r#"
+[id]
+column = "id"
+type = "uint"
+
+[versioning]
+mode = "source_lsn"
+
+[batching]
+batch_max_rows = 250
+
+[transform]
+type = "js"
+path = "./transforms/shared.ts"
+"#;
+
+    fn loader(name: &str) -> ConfigResult<String> {
+        match name {
+            "base.toml" => Ok(BASE.to_string()),
+            "uuid.toml" => Ok("extends = \"base.toml\"\n[id]\ntype = \"uuid\"\n".to_string()),
+            "loop_a.toml" => Ok("extends = \"loop_b.toml\"\n".to_string()),
+            "loop_b.toml" => Ok("extends = \"loop_a.toml\"\n".to_string()),
+            "broken.toml" => Ok("[id\n".to_string()),
+            other => Err(ConfigError::TemplateError {
+                template: other.to_string(),
+                message: "not found".to_string(),
+            }),
+        }
+    }
+
+    fn parse(toml: &str) -> ConfigResult<MigrationConfig> {
+        MigrationConfig::from_resolved(toml, resolve_with(toml, &loader)?)
+    }
+
+    #[test]
+    fn test_no_extends_is_unchanged() {
+        let toml = r#"
+version = 1
+mapping_name = "users"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "int"
+"#;
+        let config = parse(toml).unwrap();
+        assert_eq!(config.id.id_type, IdTypeConfig::Int);
+        assert!(config.extends.is_none());
+    }
+
+    #[test]
+    fn test_extends_inherits_shared_settings() {
+        let toml = r#"
+extends = "base.toml"
+version = 1
+mapping_name = "users"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+"#;
+        let config = parse(toml).unwrap();
+        assert_eq!(config.extends.as_deref(), Some("base.toml"));
+        assert_eq!(config.id.column, "id");
+        assert_eq!(config.id.id_type, IdTypeConfig::Uint);
+        assert_eq!(config.batching.batch_max_rows, 250);
+        assert_eq!(config.transform.transform_type, TransformType::Js);
+        assert_eq!(config.versioning.mode, VersioningMode::SourceLsn);
+    }
+
+    #[test]
+    fn test_migration_overrides_template_keys() {
+        let toml = r#"
+extends = "base.toml"
+version = 1
+mapping_name = "pages"
+namespace = "pages"
+
+[source]
+schema = "public"
+table = "pages"
+
+[id]
+column = "page_id"
+
+[batching]
+batch_max_rows = 10
+"#;
+        let config = parse(toml).unwrap();
+        // Overridden key
+        assert_eq!(config.id.column, "page_id");
+        // Sibling key from the template is kept
+        assert_eq!(config.id.id_type, IdTypeConfig::Uint);
+        assert_eq!(config.batching.batch_max_rows, 10);
+    }
+
+    #[test]
+    fn test_nested_templates() {
+        let toml = r#"
+extends = "uuid.toml"
+version = 1
+mapping_name = "docs"
+namespace = "docs"
+
+[source]
+schema = "public"
+table = "docs"
+"#;
+        let config = parse(toml).unwrap();
+        assert_eq!(config.id.id_type, IdTypeConfig::Uuid);
+        assert_eq!(config.batching.batch_max_rows, 250);
+    }
+
+    #[test]
+    fn test_template_sources() {
+        assert!(sources_with("version = 1\n", &loader).unwrap().is_empty());
+
+        let sources = sources_with("extends = \"uuid.toml\"\n", &loader).unwrap();
+        assert_eq!(sources.len(), 2);
+        assert!(sources[0].contains("type = \"uuid\""));
+        assert_eq!(sources[1], BASE);
+    }
+
+    #[test]
+    fn test_circular_templates_error() {
+        let err = resolve_with("extends = \"loop_a.toml\"\n", &loader).unwrap_err();
+        assert!(err.to_string().contains("circular"), "{}", err);
+        assert!(err
+            .to_string()
+            .contains("loop_a.toml -> loop_b.toml -> loop_a.toml"));
+    }
+
+    #[test]
+    fn test_template_errors_name_the_template() {
+        let err = resolve_with("extends = \"broken.toml\"\n", &loader).unwrap_err();
+        assert!(err.to_string().contains("broken.toml"), "{}", err);
+
+        let err = resolve_with("extends = \"missing.toml\"\n", &loader).unwrap_err();
+        assert!(err.to_string().contains("missing.toml"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_merge_names_the_migration() {
+        let toml = r#"
+extends = "base.toml"
+version = 1
+mapping_name = "users"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[batching]
+batch_max_rows = "many"
+"#;
+        let err = parse(toml).unwrap_err().to_string();
+        assert!(err.contains("migration 'users'"), "{}", err);
+        assert!(err.contains("base.toml"), "{}", err);
+    }
+
+    #[test]
+    fn test_templates_outside_the_directory_are_rejected() {
+        let outside = |name: &str| -> ConfigResult<String> { panic!("loaded {}", name) };
+        for name in ["../../x.toml", "shared/../../x.toml", "/etc/passwd"] {
+            let toml = format!("extends = \"{}\"\n", name);
+            let err = resolve_with(&toml, &outside).unwrap_err();
+            assert!(err.to_string().contains(name), "{}", err);
+        }
+
+        // Nested templates are checked too
+        let nested = |name: &str| -> ConfigResult<String> {
+            match name {
+                "base.toml" => Ok("extends = \"../x.toml\"\n".to_string()),
+                other => panic!("loaded {}", other),
+            }
+        };
+        assert!(resolve_with("extends = \"base.toml\"\n", &nested).is_err());
+
+        let current = |name: &str| -> ConfigResult<String> {
+            assert_eq!(name, "./base.toml");
+            Ok(BASE.to_string())
+        };
+        assert!(resolve_with("extends = \"./base.toml\"\n", &current).is_ok());
+    }
+}
@@ -1,15 +1,35 @@
-use puffgres_core::Predicate;
+use std::collections::HashSet;
+
+use puffgres_core::{Expression, NamespaceTemplate, Predicate};
 
 use crate::error::{ConfigError, ConfigResult};
-use crate::migration::{MembershipMode, MigrationConfig, TransformType, VersioningMode};
+use crate::migration::{
+    IdStrategyConfig, IdTypeConfig, MembershipMode, MigrationConfig, OnOversize, OnTransformError,
+    OnTruncate, TransformType, VersioningMode,
+};
+use crate::schema::check_env_vars;
 
 /// Validate a migration configuration.
 /// Returns a list of validation errors (empty if valid).
 pub fn validate_migration(config: &MigrationConfig) -> ConfigResult<()> {
     validate_version(config)?;
+    validate_namespace(config)?;
+    validate_source(config)?;
+    validate_id(config)?;
     validate_id_in_columns(config)?;
+    validate_renames(config)?;
+    validate_types(config)?;
+    validate_computed(config)?;
+    validate_vector(config)?;
+    validate_embed(config)?;
+    validate_flatten(config)?;
     validate_membership(config)?;
     validate_versioning(config)?;
+    validate_attributes(config)?;
+    validate_errors(config)?;
+    validate_limits(config)?;
+    validate_batch(config)?;
+    validate_sinks(config)?;
     Ok(())
 }
 
@@ -20,6 +40,111 @@ fn validate_version(config: &MigrationConfig) -> ConfigResult<()> {
     Ok(())
 }
 
+fn validate_namespace(config: &MigrationConfig) -> ConfigResult<()> {
+    NamespaceTemplate::parse(&config.namespace).map_err(|e| ConfigError::InvalidNamespace {
+        message: e.to_string(),
+    })?;
+    Ok(())
+}
+
+fn validate_source(config: &MigrationConfig) -> ConfigResult<()> {
+    let source = &config.source;
+    let problem = match &source.query {
+        Some(query) if query.trim().is_empty() => Some("query is empty"),
+        Some(_) if !source.table.is_empty() => Some("set either table or query, not both"),
+        // A query's rows never come through the replication stream
+        Some(_) if source.replication_group.is_some() => {
+            Some("replication_group doesn't apply to a query source")
+        }
+        Some(_) => None,
+        None if source.schema.is_empty() || source.table.is_empty() => {
+            Some("set schema and table, or a query")
+        }
+        None => None,
+    };
+    if let Some(message) = problem {
+        return Err(ConfigError::InvalidSource {
+            message: message.into(),
+        });
+    }
+
+    // A truncate has no rows to say which namespaces held the table
+    let partitioned = NamespaceTemplate::parse(&config.namespace)
+        .map(|t| t.is_partitioned())
+        .unwrap_or(false);
+    if config.source.on_truncate == OnTruncate::Clear && partitioned {
+        return Err(ConfigError::InvalidSource {
+            message: "on_truncate = \"clear\" can't be used with a partitioned namespace".into(),
+        });
+    }
+    // The group name becomes part of a slot name
+    if let Some(group) = &config.source.replication_group {
+        let valid = !group.is_empty()
+            && group.len() <= 32
+            && group
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(ConfigError::InvalidSource {
+                message: format!(
+                    "replication_group '{}' must be 1-32 lowercase letters, digits or underscores",
+                    group
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn validate_id(config: &MigrationConfig) -> ConfigResult<()> {
+    let id = &config.id;
+    let invalid = |message: String| Err(ConfigError::InvalidId { message });
+
+    if !id.is_composite() {
+        if id.column.is_empty() {
+            return invalid("set column, or columns for an id built from several columns".into());
+        }
+        if id.strategy.is_some() || id.separator.is_some() {
+            return invalid(
+                "strategy and separator apply to ids built from several columns".into(),
+            );
+        }
+        return Ok(());
+    }
+
+    if id.columns.len() < 2 {
+        return invalid("columns needs at least two columns; use column for one".into());
+    }
+    let mut seen = HashSet::new();
+    for column in &id.columns {
+        if column.is_empty() {
+            return invalid("column names cannot be empty".into());
+        }
+        if !seen.insert(column) {
+            return invalid(format!("column '{}' is listed twice", column));
+        }
+    }
+    match id.strategy.unwrap_or_default() {
+        IdStrategyConfig::Concat => {
+            if id.id_type != IdTypeConfig::String {
+                return invalid("concatenated ids have type = \"string\"".into());
+            }
+            if id.separator.as_deref() == Some("") {
+                return invalid("separator cannot be empty".into());
+            }
+        }
+        IdStrategyConfig::Uuid5 => {
+            if id.id_type != IdTypeConfig::Uuid {
+                return invalid("uuid5 ids have type = \"uuid\"".into());
+            }
+            if id.separator.is_some() {
+                return invalid("separator applies to strategy = \"concat\"".into());
+            }
+        }
+    }
+    Ok(())
+}
+
 fn validate_id_in_columns(_config: &MigrationConfig) -> ConfigResult<()> {
     // If columns are specified, the id column should typically be included
     // (though this is a warning, not an error - the transform might not need it)
@@ -27,6 +152,323 @@ fn validate_id_in_columns(_config: &MigrationConfig) -> ConfigResult<()> {
     Ok(())
 }
 
+fn validate_renames(config: &MigrationConfig) -> ConfigResult<()> {
+    let mut targets = HashSet::new();
+
+    for (column, target) in &config.columns.rename {
+        if target.is_empty() {
+            return Err(ConfigError::InvalidRename {
+                column: column.clone(),
+                message: "attribute name cannot be empty".into(),
+            });
+        }
+        // turbopuffer's document id is always written as "id"
+        if target == "id" {
+            return Err(ConfigError::InvalidRename {
+                column: column.clone(),
+                message: "'id' is reserved for the document id".into(),
+            });
+        }
+        if let Some(message) = reserved_attribute(config, target) {
+            return Err(ConfigError::InvalidRename {
+                column: column.clone(),
+                message,
+            });
+        }
+        if !targets.insert(target.as_str()) {
+            return Err(ConfigError::InvalidRename {
+                column: column.clone(),
+                message: format!("attribute '{}' is already used by another rename", target),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Why `attribute` can't be written by the identity transform, if it's one
+/// puffgres writes itself.
+fn reserved_attribute(config: &MigrationConfig, attribute: &str) -> Option<String> {
+    if config.event_time && attribute == puffgres_core::EVENT_TIME_ATTRIBUTE {
+        return Some(format!(
+            "'{}' is reserved when event_time is enabled",
+            puffgres_core::EVENT_TIME_ATTRIBUTE
+        ));
+    }
+    if config.run_id && attribute == puffgres_core::RUN_ID_ATTRIBUTE {
+        return Some(format!(
+            "'{}' is reserved when run_id is enabled",
+            puffgres_core::RUN_ID_ATTRIBUTE
+        ));
+    }
+    for (key, name) in bookkeeping_attributes(config) {
+        if attribute == name {
+            return Some(format!(
+                "'{}' is reserved for attributes.{}; rename it there or set it to false",
+                name, key
+            ));
+        }
+    }
+    if config.versioning.mode == VersioningMode::ContentHash
+        && attribute == puffgres_core::DOC_HASH_ATTRIBUTE
+    {
+        return Some(format!(
+            "'{}' is reserved when versioning.mode is content_hash",
+            puffgres_core::DOC_HASH_ATTRIBUTE
+        ));
+    }
+    None
+}
+
+/// The bookkeeping attributes the mapping writes, by their `[attributes]` key.
+fn bookkeeping_attributes(config: &MigrationConfig) -> Vec<(&'static str, &str)> {
+    let attributes = &config.attributes;
+    [
+        (
+            "source_lsn",
+            attributes
+                .source_lsn
+                .resolve(puffgres_core::SOURCE_LSN_ATTRIBUTE),
+        ),
+        (
+            "backfill",
+            attributes
+                .backfill
+                .resolve(puffgres_core::BACKFILL_ATTRIBUTE),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, name)| Some((key, name?)))
+    .collect()
+}
+
+fn validate_attributes(config: &MigrationConfig) -> ConfigResult<()> {
+    let mut names = HashSet::new();
+    for (key, name) in bookkeeping_attributes(config) {
+        let invalid = |message: String| ConfigError::InvalidAttributes {
+            message: format!("{}: {}", key, message),
+        };
+        if name.is_empty() {
+            return Err(invalid(
+                "attribute name cannot be empty; set it to false to leave it out".into(),
+            ));
+        }
+        if name == "id" || name == puffgres_core::VECTOR_ATTRIBUTE {
+            return Err(invalid(format!("'{}' is written by turbopuffer", name)));
+        }
+        if [
+            puffgres_core::EVENT_TIME_ATTRIBUTE,
+            puffgres_core::RUN_ID_ATTRIBUTE,
+            puffgres_core::DOC_HASH_ATTRIBUTE,
+        ]
+        .contains(&name)
+        {
+            return Err(invalid(format!("'{}' is written by puffgres", name)));
+        }
+        if !names.insert(name) {
+            return Err(invalid(format!(
+                "attribute '{}' is already used by another key",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn validate_types(config: &MigrationConfig) -> ConfigResult<()> {
+    for column in config.types.keys() {
+        let invalid = |message: String| ConfigError::InvalidType {
+            column: column.clone(),
+            message,
+        };
+        if config.transform.path.is_some() {
+            return Err(invalid(
+                "types apply to the identity transform; convert the value in the transform instead"
+                    .into(),
+            ));
+        }
+        if config.id.all_columns().contains(column) {
+            return Err(invalid("the id column's type is set by [id]".into()));
+        }
+        let include = &config.columns.include;
+        if !include.is_empty() && !include.contains(column) {
+            return Err(invalid("column is not in the included columns".into()));
+        }
+        if config.flatten.columns.contains(column) {
+            return Err(invalid("a flattened column can't also have a type".into()));
+        }
+    }
+    Ok(())
+}
+
+fn validate_computed(config: &MigrationConfig) -> ConfigResult<()> {
+    for (attribute, expr) in &config.computed {
+        let invalid = |message: String| ConfigError::InvalidComputed {
+            attribute: attribute.clone(),
+            message,
+        };
+        if config.transform.path.is_some() {
+            return Err(invalid(
+                "computed attributes apply to the identity transform; compute the value in the transform instead"
+                    .into(),
+            ));
+        }
+        if attribute.is_empty() {
+            return Err(invalid("attribute name cannot be empty".into()));
+        }
+        if attribute == "id" {
+            return Err(invalid("'id' is reserved for the document id".into()));
+        }
+        if let Some(message) = reserved_attribute(config, attribute) {
+            return Err(invalid(message));
+        }
+        if config
+            .columns
+            .rename
+            .values()
+            .any(|target| target == attribute)
+        {
+            return Err(invalid(format!(
+                "attribute '{}' is already used by a rename",
+                attribute
+            )));
+        }
+        Expression::parse(expr).map_err(|e| invalid(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn validate_vector(config: &MigrationConfig) -> ConfigResult<()> {
+    let Some(vector) = &config.vector else {
+        return Ok(());
+    };
+    let invalid = |message: String| ConfigError::InvalidVector { message };
+    let column = &vector.column;
+
+    if config.transform.path.is_some() {
+        return Err(invalid(
+            "[vector] applies to the identity transform; return a vector from the transform instead"
+                .into(),
+        ));
+    }
+    if column.is_empty() {
+        return Err(invalid("column cannot be empty".into()));
+    }
+    if config.id.all_columns().contains(column) {
+        return Err(invalid("the id column can't be the vector column".into()));
+    }
+    // The column becomes the vector, so it's never an attribute
+    if config.columns.rename.contains_key(column) {
+        return Err(invalid(format!(
+            "vector column '{}' can't be renamed",
+            column
+        )));
+    }
+    if config.types.contains_key(column) {
+        return Err(invalid(format!(
+            "vector column '{}' can't have a type",
+            column
+        )));
+    }
+    if config.flatten.columns.contains(column) {
+        return Err(invalid(format!(
+            "vector column '{}' can't be flattened",
+            column
+        )));
+    }
+    Ok(())
+}
+
+fn validate_embed(config: &MigrationConfig) -> ConfigResult<()> {
+    let Some(embed) = &config.embed else {
+        return Ok(());
+    };
+    let invalid = |message: String| ConfigError::InvalidEmbed { message };
+
+    if config.vector.is_some() {
+        return Err(invalid(
+            "[embed] and [vector] both set the document vector; use one".into(),
+        ));
+    }
+    if embed.columns.is_empty() {
+        return Err(invalid("columns cannot be empty".into()));
+    }
+    if embed.columns.iter().any(|c| c.is_empty()) {
+        return Err(invalid("column names cannot be empty".into()));
+    }
+
+    // With the identity transform and a column list, the attributes a
+    // document has are known, so a misspelled one can be caught
+    let columns = &config.columns;
+    if config.transform.path.is_some()
+        || columns.include.is_empty()
+        || !config.flatten.columns.is_empty()
+    {
+        return Ok(());
+    }
+    let attributes: HashSet<&str> = columns
+        .include
+        .iter()
+        .map(|c| columns.rename.get(c).unwrap_or(c).as_str())
+        .chain(config.computed.keys().map(String::as_str))
+        .collect();
+    if let Some(missing) = embed
+        .columns
+        .iter()
+        .find(|c| !attributes.contains(c.as_str()))
+    {
+        return Err(invalid(format!(
+            "'{}' isn't an attribute of the documents; embed columns name attributes, after renames",
+            missing
+        )));
+    }
+    Ok(())
+}
+
+fn validate_flatten(config: &MigrationConfig) -> ConfigResult<()> {
+    let flatten = &config.flatten;
+    if flatten.columns.is_empty() {
+        if !flatten.allow.is_empty() {
+            return Err(ConfigError::InvalidFlatten {
+                message: "'allow' requires at least one column to flatten".into(),
+            });
+        }
+        return Ok(());
+    }
+
+    if flatten.max_depth == 0 {
+        return Err(ConfigError::InvalidFlatten {
+            message: "max_depth must be at least 1".into(),
+        });
+    }
+    if flatten.separator.is_empty() {
+        return Err(ConfigError::InvalidFlatten {
+            message: "separator cannot be empty".into(),
+        });
+    }
+
+    let include = &config.columns.include;
+    for column in &flatten.columns {
+        if !include.is_empty() && !include.contains(column) {
+            return Err(ConfigError::InvalidFlatten {
+                message: format!("column '{}' is not in the included columns", column),
+            });
+        }
+    }
+
+    for path in &flatten.allow {
+        let column = path.split('.').next().unwrap_or_default();
+        if !path.contains('.') || !flatten.columns.iter().any(|c| c == column) {
+            return Err(ConfigError::InvalidFlatten {
+                message: format!(
+                    "allowed path '{}' must be '<column>.<key>' for a flattened column",
+                    path
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
 fn validate_membership(config: &MigrationConfig) -> ConfigResult<()> {
     match config.membership.mode {
         MembershipMode::Dsl => {
@@ -37,14 +479,29 @@ fn validate_membership(config: &MigrationConfig) -> ConfigResult<()> {
                 .ok_or(ConfigError::MissingPredicate)?;
 
             // Validate that the predicate parses correctly
-            Predicate::parse(predicate).map_err(|e| ConfigError::InvalidPredicate {
-                message: e.to_string(),
-            })?;
+            let predicate =
+                Predicate::parse(predicate).map_err(|e| ConfigError::InvalidPredicate {
+                    message: e.to_string(),
+                })?;
+            if config.membership.row_filter && predicate.sql_form().is_none() {
+                return Err(ConfigError::InvalidPredicate {
+                    message: "row_filter is set, but the predicate can't be written as SQL".into(),
+                });
+            }
         }
         MembershipMode::View | MembershipMode::Lookup | MembershipMode::All => {
-            // No additional validation needed
+            if config.membership.row_filter {
+                return Err(ConfigError::InvalidPredicate {
+                    message: "row_filter needs mode = \"dsl\" and a predicate".into(),
+                });
+            }
         }
     }
+    if config.membership.row_filter && config.source.query.is_some() {
+        return Err(ConfigError::InvalidPredicate {
+            message: "row_filter doesn't apply to a query source".into(),
+        });
+    }
     Ok(())
 }
 
@@ -55,6 +512,76 @@ fn validate_versioning(config: &MigrationConfig) -> ConfigResult<()> {
     Ok(())
 }
 
+fn validate_errors(config: &MigrationConfig) -> ConfigResult<()> {
+    if config.errors.max_consecutive_errors == Some(0) {
+        return Err(ConfigError::InvalidErrors {
+            message: "max_consecutive_errors must be at least 1".into(),
+        });
+    }
+    Ok(())
+}
+
+fn validate_limits(config: &MigrationConfig) -> ConfigResult<()> {
+    if config.limits.max_document_bytes == Some(0) {
+        return Err(ConfigError::InvalidLimits {
+            message: "max_document_bytes must be at least 1".into(),
+        });
+    }
+    Ok(())
+}
+
+fn validate_batch(config: &MigrationConfig) -> ConfigResult<()> {
+    let batch = &config.batch;
+    for (field, value) in [
+        ("max_rows", batch.max_rows.map(|v| v as u64)),
+        ("max_wait_ms", batch.max_wait_ms),
+        ("max_bytes", batch.max_bytes.map(|v| v as u64)),
+    ] {
+        if value == Some(0) {
+            return Err(ConfigError::InvalidBatch {
+                message: format!("{} must be at least 1", field),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn validate_sinks(config: &MigrationConfig) -> ConfigResult<()> {
+    for (name, sink) in &config.sinks {
+        let error = |message: &str| ConfigError::InvalidSink {
+            name: name.clone(),
+            message: message.into(),
+        };
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            return Err(error(
+                "name may only contain lowercase letters, digits and underscores",
+            ));
+        }
+        if sink.namespace.trim().is_empty() {
+            return Err(error("namespace is empty"));
+        }
+        // The sink is written per batch, after the row's namespace is resolved
+        let rest = sink.namespace.replace("{namespace}", "");
+        if rest.contains(['{', '}']) {
+            return Err(error("namespace may only interpolate {namespace}"));
+        }
+        if let Some(api_key) = &sink.api_key {
+            check_env_vars(api_key).map_err(|e| error(&format!("api_key: {}", e)))?;
+        }
+        let own = sink.namespace == "{namespace}" || sink.namespace == config.namespace;
+        if own && sink.api_key.is_none() {
+            return Err(error(
+                "writes to the mapping's own namespace; set a different namespace or api_key",
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// Convert a validated migration config to a core Mapping.
 pub fn to_mapping(config: &MigrationConfig) -> ConfigResult<puffgres_core::Mapping> {
     validate_migration(config)?;
@@ -82,6 +609,7 @@ pub fn to_mapping(config: &MigrationConfig) -> ConfigResult<puffgres_core::Mappi
             puffgres_core::VersioningMode::Column(col)
         }
         VersioningMode::None => puffgres_core::VersioningMode::None,
+        VersioningMode::ContentHash => puffgres_core::VersioningMode::ContentHash,
     };
 
     // Build transform config if present
@@ -106,18 +634,132 @@ pub fn to_mapping(config: &MigrationConfig) -> ConfigResult<puffgres_core::Mappi
         .namespace(&config.namespace)
         .source(&config.source.schema, &config.source.table)
         .id(&config.id.column, config.id.id_type.to_core_type())
-        .columns(config.columns.clone())
+        .columns(config.columns.include.clone())
+        .renames(
+            config
+                .columns
+                .rename
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        )
+        .types(
+            config
+                .types
+                .iter()
+                .map(|(column, ty)| (column.clone(), ty.to_core_type()))
+                .collect(),
+        )
+        .computed(
+            config
+                .computed
+                .iter()
+                .map(|(attribute, expr)| {
+                    let expr =
+                        Expression::parse(expr).map_err(|e| ConfigError::InvalidComputed {
+                            attribute: attribute.clone(),
+                            message: e.to_string(),
+                        })?;
+                    Ok((attribute.clone(), expr))
+                })
+                .collect::<ConfigResult<_>>()?,
+        )
+        .flatten(puffgres_core::FlattenConfig {
+            columns: config.flatten.columns.clone(),
+            max_depth: config.flatten.max_depth,
+            separator: config.flatten.separator.clone(),
+            allow: config.flatten.allow.clone(),
+        })
         .membership(membership)
         .batching(puffgres_core::BatchConfig {
             max_rows: config.batching.batch_max_rows,
             max_bytes: config.batching.batch_max_bytes,
             flush_interval_ms: config.batching.flush_interval_ms,
+            max_wait_ms: None,
+        })
+        .batch(puffgres_core::BatchOverrides {
+            max_rows: config.batch.max_rows,
+            max_bytes: config.batch.max_bytes,
+            max_wait_ms: config.batch.max_wait_ms,
+        })
+        .versioning(versioning)
+        .event_time(config.event_time)
+        .run_id(config.run_id)
+        .source_lsn_attribute(
+            config
+                .attributes
+                .source_lsn
+                .resolve(puffgres_core::SOURCE_LSN_ATTRIBUTE)
+                .map(str::to_string),
+        )
+        .backfill_attribute(
+            config
+                .attributes
+                .backfill
+                .resolve(puffgres_core::BACKFILL_ATTRIBUTE)
+                .map(str::to_string),
+        )
+        .errors(puffgres_core::ErrorPolicy {
+            on_transform_error: match config.errors.on_transform_error {
+                OnTransformError::Dlq => puffgres_core::TransformErrorAction::Dlq,
+                OnTransformError::Skip => puffgres_core::TransformErrorAction::Skip,
+                OnTransformError::Halt => puffgres_core::TransformErrorAction::Halt,
+            },
+            max_consecutive_errors: config.errors.max_consecutive_errors,
+        })
+        .limits(puffgres_core::DocumentLimits {
+            max_bytes: config.limits.max_document_bytes,
+            on_oversize: match config.limits.on_oversize {
+                OnOversize::Truncate => puffgres_core::OversizeAction::Truncate,
+                OnOversize::Dlq => puffgres_core::OversizeAction::Dlq,
+                OnOversize::Fail => puffgres_core::OversizeAction::Fail,
+            },
         })
-        .versioning(versioning);
+        .on_truncate(match config.source.on_truncate {
+            OnTruncate::Ignore => puffgres_core::TruncateAction::Ignore,
+            OnTruncate::Clear => puffgres_core::TruncateAction::Clear,
+            OnTruncate::Error => puffgres_core::TruncateAction::Error,
+        })
+        .row_filter(config.membership.row_filter)
+        .replication_group(config.source.replication_group.clone())
+        .source_query(config.source.query.clone())
+        .sinks(
+            config
+                .sinks
+                .iter()
+                .map(|(name, sink)| puffgres_core::Sink {
+                    name: name.clone(),
+                    namespace: sink.namespace.clone(),
+                    api_key: sink.api_key.clone(),
+                    max_retries: sink.max_retries,
+                })
+                .collect(),
+        );
 
+    if config.id.is_composite() {
+        let strategy = match config.id.strategy.unwrap_or_default() {
+            IdStrategyConfig::Concat => puffgres_core::IdStrategy::Concat {
+                separator: config.id.separator.clone().unwrap_or_else(|| ":".into()),
+            },
+            IdStrategyConfig::Uuid5 => puffgres_core::IdStrategy::Uuid5,
+        };
+        builder = builder.composite_id(config.id.columns.clone(), strategy);
+    }
     if let Some(t) = transform {
         builder = builder.transform(t);
     }
+    if let Some(vector) = &config.vector {
+        builder = builder.vector(puffgres_core::VectorColumn {
+            column: vector.column.clone(),
+            distance_metric: vector.distance_metric.map(|m| m.to_core_type()),
+        });
+    }
+    if let Some(embed) = &config.embed {
+        builder = builder.embed(puffgres_core::EmbedConfig {
+            columns: embed.columns.clone(),
+            distance_metric: embed.distance_metric.to_core_type(),
+        });
+    }
 
     let mapping = builder.build().map_err(|e| ConfigError::MissingField {
         field: e.to_string(),
@@ -173,6 +815,33 @@ type = "uint"
         assert!(matches!(result, Err(ConfigError::InvalidVersion(0))));
     }
 
+    #[test]
+    fn test_validate_namespace_template() {
+        let toml = |namespace: &str| {
+            format!(
+                r#"
+version = 1
+mapping_name = "posts"
+namespace = "{}"
+
+[source]
+schema = "public"
+table = "posts"
+
+[id]
+column = "id"
+type = "uint"
+"#,
+                namespace
+            )
+        };
+        assert!(parse_and_validate(&toml("posts_{tenant_id}")).is_ok());
+        assert!(matches!(
+            parse_and_validate(&toml("posts_{tenant_id")),
+            Err(ConfigError::InvalidNamespace { .. })
+        ));
+    }
+
     #[test]
     fn test_validate_dsl_missing_predicate() {
         let toml = r#"
@@ -268,4 +937,804 @@ predicate = "status = 'active'"
         assert_eq!(mapping.namespace, "users");
         assert!(mapping.source.matches("public", "users"));
     }
+
+    #[test]
+    fn test_to_mapping_with_renames() {
+        let toml = r#"
+version = 1
+mapping_name = "users_public"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+
+[columns]
+include = ["id", "created_at"]
+rename = { created_at = "createdAt" }
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+
+        assert_eq!(mapping.columns, vec!["id", "created_at"]);
+        assert_eq!(mapping.renames.get("created_at").unwrap(), "createdAt");
+        assert!(!mapping.event_time);
+    }
+
+    #[test]
+    fn test_to_mapping_with_types() {
+        let toml = r#"
+version = 1
+mapping_name = "products"
+namespace = "products"
+
+[source]
+schema = "public"
+table = "products"
+
+[id]
+column = "id"
+type = "uint"
+
+[types]
+price = "string"
+created_at = "unix_ms"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+        assert_eq!(mapping.types["price"], puffgres_core::ColumnType::String);
+        assert_eq!(
+            mapping.types["created_at"],
+            puffgres_core::ColumnType::UnixMs
+        );
+
+        let on_id = toml.replace("price = \"string\"", "id = \"string\"");
+        let config = MigrationConfig::parse(&on_id).unwrap();
+        assert!(matches!(
+            validate_migration(&config),
+            Err(ConfigError::InvalidType { column, .. }) if column == "id"
+        ));
+
+        let unknown = toml.replace("\"unix_ms\"", "\"date\"");
+        assert!(MigrationConfig::parse(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_to_mapping_with_event_time() {
+        let toml = r#"
+version = 1
+mapping_name = "users_public"
+namespace = "users"
+event_time = true
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        assert!(config.event_time);
+        assert!(to_mapping(&config).unwrap().event_time);
+
+        let clash = toml.replace(
+            "type = \"uint\"",
+            "type = \"uint\"\n\n[columns.rename]\nupdated_at = \"__event_time\"",
+        );
+        assert!(matches!(
+            parse_and_validate(&clash),
+            Err(ConfigError::InvalidRename { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_run_id() {
+        let toml = r#"
+version = 1
+mapping_name = "users_public"
+namespace = "users"
+run_id = true
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        assert!(to_mapping(&config).unwrap().run_id);
+
+        let clash = toml.replace(
+            "type = \"uint\"",
+            "type = \"uint\"\n\n[columns.rename]\nowner = \"__run_id\"",
+        );
+        assert!(matches!(
+            parse_and_validate(&clash),
+            Err(ConfigError::InvalidRename { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_attributes() {
+        let toml = r#"
+version = 1
+mapping_name = "users_public"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+        let mapping = to_mapping(&MigrationConfig::parse(toml).unwrap()).unwrap();
+        assert_eq!(
+            mapping.source_lsn_attribute.as_deref(),
+            Some(puffgres_core::SOURCE_LSN_ATTRIBUTE)
+        );
+        assert_eq!(
+            mapping.backfill_attribute.as_deref(),
+            Some(puffgres_core::BACKFILL_ATTRIBUTE)
+        );
+
+        let configured = format!(
+            "{}\n[attributes]\nsource_lsn = \"_lsn\"\nbackfill = false\n",
+            toml
+        );
+        let mapping = to_mapping(&MigrationConfig::parse(&configured).unwrap()).unwrap();
+        assert_eq!(mapping.source_lsn_attribute.as_deref(), Some("_lsn"));
+        assert_eq!(mapping.backfill_attribute, None);
+
+        let clash = configured.replace(
+            "type = \"uint\"",
+            "type = \"uint\"\n\n[columns.rename]\nposition = \"_lsn\"",
+        );
+        assert!(matches!(
+            parse_and_validate(&clash),
+            Err(ConfigError::InvalidRename { .. })
+        ));
+
+        for invalid in [
+            "source_lsn = \"\"",
+            "backfill = \"id\"",
+            "backfill = \"__source_lsn\"",
+        ] {
+            let config = format!("{}\n[attributes]\n{}\n", toml, invalid);
+            assert!(
+                matches!(
+                    parse_and_validate(&config),
+                    Err(ConfigError::InvalidAttributes { .. })
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_mapping_with_content_hash() {
+        let toml = r#"
+version = 1
+mapping_name = "users_public"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+
+[versioning]
+mode = "content_hash"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        assert!(matches!(
+            to_mapping(&config).unwrap().versioning,
+            puffgres_core::VersioningMode::ContentHash
+        ));
+
+        let clash = toml.replace(
+            "type = \"uint\"",
+            "type = \"uint\"\n\n[columns.rename]\nchecksum = \"__doc_hash\"",
+        );
+        assert!(matches!(
+            parse_and_validate(&clash),
+            Err(ConfigError::InvalidRename { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_errors() {
+        let toml = r#"
+version = 1
+mapping_name = "logs"
+namespace = "logs"
+
+[source]
+schema = "public"
+table = "logs"
+
+[id]
+column = "id"
+type = "uint"
+
+[errors]
+on_transform_error = "dlq"
+max_consecutive_errors = 50
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+        assert_eq!(
+            mapping.errors.on_transform_error,
+            puffgres_core::TransformErrorAction::Dlq
+        );
+        assert_eq!(mapping.errors.max_consecutive_errors, Some(50));
+
+        let zero = toml.replace("= 50", "= 0");
+        assert!(matches!(
+            parse_and_validate(&zero),
+            Err(ConfigError::InvalidErrors { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_limits() {
+        let toml = r#"
+version = 1
+mapping_name = "logs"
+namespace = "logs"
+
+[source]
+schema = "public"
+table = "logs"
+
+[id]
+column = "id"
+type = "uint"
+
+[limits]
+max_document_bytes = 65536
+on_oversize = "truncate"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+        assert_eq!(mapping.limits.max_bytes, Some(65536));
+        assert_eq!(
+            mapping.limits.on_oversize,
+            puffgres_core::OversizeAction::Truncate
+        );
+
+        let zero = toml.replace("= 65536", "= 0");
+        assert!(matches!(
+            parse_and_validate(&zero),
+            Err(ConfigError::InvalidLimits { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_batch() {
+        let toml = r#"
+version = 1
+mapping_name = "logs"
+namespace = "logs"
+
+[source]
+schema = "public"
+table = "logs"
+
+[id]
+column = "id"
+type = "uint"
+
+[batch]
+max_rows = 500
+max_wait_ms = 2000
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+        assert_eq!(
+            mapping.batch,
+            puffgres_core::BatchOverrides {
+                max_rows: Some(500),
+                max_bytes: None,
+                max_wait_ms: Some(2000),
+            }
+        );
+        let batch = mapping
+            .batch
+            .apply(puffgres_core::BatchConfig::with_max_rows(100));
+        assert_eq!(batch.max_rows, 500);
+        assert_eq!(batch.max_bytes, 4 * 1024 * 1024);
+        assert_eq!(batch.max_wait_ms, Some(2000));
+
+        let zero = toml.replace("= 2000", "= 0");
+        assert!(matches!(
+            parse_and_validate(&zero),
+            Err(ConfigError::InvalidBatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_on_truncate() {
+        let toml = r#"
+version = 1
+mapping_name = "logs"
+namespace = "logs"
+
+[source]
+schema = "public"
+table = "logs"
+on_truncate = "clear"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+        assert_eq!(mapping.on_truncate, puffgres_core::TruncateAction::Clear);
+
+        let partitioned = toml.replace(r#"namespace = "logs""#, r#"namespace = "logs_{tenant}""#);
+        assert!(matches!(
+            parse_and_validate(&partitioned),
+            Err(ConfigError::InvalidSource { .. })
+        ));
+
+        let default = toml.replace("on_truncate = \"clear\"\n", "");
+        let mapping = to_mapping(&MigrationConfig::parse(&default).unwrap()).unwrap();
+        assert_eq!(mapping.on_truncate, puffgres_core::TruncateAction::Ignore);
+    }
+
+    #[test]
+    fn test_to_mapping_with_query_source() {
+        let toml = r#"
+version = 1
+mapping_name = "active_authors"
+namespace = "authors"
+
+[source]
+query = "SELECT a.id, a.name, count(p.id) AS posts FROM authors a JOIN posts p ON p.author_id = a.id GROUP BY a.id"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+        let mapping = to_mapping(&MigrationConfig::parse(toml).unwrap()).unwrap();
+        assert!(mapping.source.query.is_some());
+
+        let both = toml.replace(
+            "[source]\n",
+            "[source]\nschema = \"public\"\ntable = \"authors\"\n",
+        );
+        assert!(matches!(
+            parse_and_validate(&both),
+            Err(ConfigError::InvalidSource { .. })
+        ));
+
+        let neither = toml.replace("query = ", "# query = ");
+        assert!(matches!(
+            parse_and_validate(&neither),
+            Err(ConfigError::InvalidSource { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_row_filter() {
+        let toml = r#"
+version = 1
+mapping_name = "active_users"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+
+[membership]
+mode = "dsl"
+predicate = "status = 'active'"
+row_filter = true
+"#;
+        let mapping = to_mapping(&MigrationConfig::parse(toml).unwrap()).unwrap();
+        assert!(mapping.row_filter);
+        assert_eq!(
+            mapping
+                .row_filter_sql(|_| Some(puffgres_core::SqlType::Text))
+                .as_deref(),
+            Some("\"status\" = 'active'")
+        );
+
+        let without_predicate = toml.replace("mode = \"dsl\"", "mode = \"all\"");
+        assert!(matches!(
+            parse_and_validate(&without_predicate),
+            Err(ConfigError::InvalidPredicate { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_replication_group() {
+        let toml = r#"
+version = 1
+mapping_name = "invoices"
+namespace = "invoices"
+
+[source]
+schema = "public"
+table = "invoices"
+replication_group = "billing"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+        let mapping = to_mapping(&MigrationConfig::parse(toml).unwrap()).unwrap();
+        assert_eq!(mapping.replication_group.as_deref(), Some("billing"));
+
+        let invalid = toml.replace("\"billing\"", "\"Billing-2\"");
+        assert!(matches!(
+            parse_and_validate(&invalid),
+            Err(ConfigError::InvalidSource { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_computed() {
+        let toml = r#"
+version = 1
+mapping_name = "users"
+namespace = "users"
+
+[source]
+schema = "public"
+table = "users"
+
+[id]
+column = "id"
+type = "uint"
+
+[computed]
+full_name = "concat(first_name, ' ', last_name)"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+
+        let expr = &mapping.computed["full_name"];
+        assert_eq!(expr.columns(), vec!["first_name", "last_name"]);
+
+        for computed in ["id = \"first_name\"", "full_name = \"concat(first_name\""] {
+            let toml = toml.replace(
+                "full_name = \"concat(first_name, ' ', last_name)\"",
+                computed,
+            );
+            let config = MigrationConfig::parse(&toml).unwrap();
+            assert!(
+                matches!(
+                    to_mapping(&config),
+                    Err(ConfigError::InvalidComputed { .. })
+                ),
+                "{}",
+                computed
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_mapping_with_vector() {
+        let toml = r#"
+version = 1
+mapping_name = "docs"
+namespace = "docs"
+
+[source]
+schema = "public"
+table = "docs"
+
+[id]
+column = "id"
+type = "uint"
+
+[vector]
+column = "embedding"
+distance_metric = "cosine_distance"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+
+        let vector = mapping.vector.unwrap();
+        assert_eq!(vector.column, "embedding");
+        assert_eq!(
+            vector.distance_metric,
+            Some(puffgres_core::DistanceMetric::CosineDistance)
+        );
+
+        for extra in [
+            "[types]\nembedding = \"string\"",
+            "[columns]\ninclude = [\"embedding\"]\nrename = { embedding = \"vec\" }",
+            "[transform]\ntype = \"js\"\npath = \"./transforms/docs.ts\"",
+        ] {
+            let config = MigrationConfig::parse(&format!("{}\n{}", toml, extra)).unwrap();
+            assert!(
+                matches!(to_mapping(&config), Err(ConfigError::InvalidVector { .. })),
+                "{}",
+                extra
+            );
+        }
+        let config = MigrationConfig::parse(&toml.replace("\"embedding\"", "\"id\"")).unwrap();
+        assert!(matches!(
+            to_mapping(&config),
+            Err(ConfigError::InvalidVector { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_mapping_with_embed() {
+        let toml = r#"
+version = 1
+mapping_name = "docs"
+namespace = "docs"
+
+[source]
+schema = "public"
+table = "docs"
+
+[id]
+column = "id"
+type = "uint"
+
+[columns]
+include = ["id", "title", "body_text"]
+rename = { body_text = "body" }
+
+[embed]
+columns = ["title", "body"]
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+
+        let embed = mapping.embed.unwrap();
+        assert_eq!(embed.columns, vec!["title", "body"]);
+        assert_eq!(
+            embed.distance_metric,
+            puffgres_core::DistanceMetric::CosineDistance
+        );
+
+        for (from, to) in [
+            ("[\"title\", \"body\"]", "[\"title\", \"body_text\"]"),
+            ("[\"title\", \"body\"]", "[]"),
+            ("[embed]", "[vector]\ncolumn = \"embedding\"\n\n[embed]"),
+        ] {
+            let config = MigrationConfig::parse(&toml.replace(from, to)).unwrap();
+            assert!(
+                matches!(to_mapping(&config), Err(ConfigError::InvalidEmbed { .. })),
+                "{}",
+                to
+            );
+        }
+
+        // A transform's attributes aren't known, so any are allowed
+        let with_transform = format!(
+            "{}\n[transform]\ntype = \"js\"\npath = \"./transforms/docs.ts\"",
+            toml.replace("\"body\"]", "\"summary\"]")
+        );
+        let config = MigrationConfig::parse(&with_transform).unwrap();
+        assert!(to_mapping(&config).is_ok());
+    }
+
+    #[test]
+    fn test_to_mapping_with_composite_id() {
+        let toml = r#"
+version = 1
+mapping_name = "orders"
+namespace = "orders"
+
+[source]
+schema = "public"
+table = "orders"
+
+[id]
+columns = ["tenant_id", "order_id"]
+type = "string"
+separator = "/"
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+        assert_eq!(mapping.id.columns(), ["tenant_id", "order_id"]);
+        assert_eq!(mapping.id.id_type, puffgres_core::IdType::String);
+        assert_eq!(
+            mapping.id.composite.unwrap().strategy,
+            puffgres_core::IdStrategy::Concat {
+                separator: "/".into()
+            }
+        );
+
+        let uuid5 = toml
+            .replace("type = \"string\"", "type = \"uuid\"\nstrategy = \"uuid5\"")
+            .replace("separator = \"/\"\n", "");
+        let mapping = to_mapping(&MigrationConfig::parse(&uuid5).unwrap()).unwrap();
+        assert_eq!(mapping.id.id_type, puffgres_core::IdType::Uuid);
+
+        for (from, to) in [
+            ("type = \"string\"", "type = \"uint\""),
+            ("\"order_id\"]", "\"tenant_id\"]"),
+            ("[\"tenant_id\", \"order_id\"]", "[\"tenant_id\"]"),
+            ("separator = \"/\"", "separator = \"\""),
+            (
+                "type = \"string\"",
+                "type = \"string\"\nstrategy = \"uuid5\"",
+            ),
+        ] {
+            let config = MigrationConfig::parse(&toml.replace(from, to)).unwrap();
+            assert!(
+                matches!(to_mapping(&config), Err(ConfigError::InvalidId { .. })),
+                "{}",
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_mapping_with_flatten() {
+        let toml = r#"
+version = 1
+mapping_name = "posts"
+namespace = "posts"
+
+[source]
+schema = "public"
+table = "posts"
+
+[id]
+column = "id"
+type = "uint"
+
+[flatten]
+columns = ["metadata"]
+allow = ["metadata.author"]
+"#;
+        let config = MigrationConfig::parse(toml).unwrap();
+        let mapping = to_mapping(&config).unwrap();
+
+        assert_eq!(mapping.flatten.columns, vec!["metadata"]);
+        assert_eq!(mapping.flatten.max_depth, 1);
+        assert!(mapping.flatten.allows("metadata.author"));
+        assert!(!mapping.flatten.allows("metadata.tags"));
+    }
+
+    #[test]
+    fn test_validate_flatten_rejects_bad_config() {
+        let base = r#"
+version = 1
+mapping_name = "test"
+namespace = "test"
+
+[source]
+schema = "public"
+table = "test"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+
+        for flatten in [
+            "columns = [\"metadata\"]\nmax_depth = 0",
+            "columns = [\"metadata\"]\nseparator = \"\"",
+            "columns = [\"metadata\"]\nallow = [\"other.key\"]",
+            "columns = [\"metadata\"]\nallow = [\"metadata\"]",
+            "allow = [\"metadata.author\"]",
+        ] {
+            let toml = format!("{}\n[flatten]\n{}\n", base, flatten);
+            assert!(
+                matches!(
+                    parse_and_validate(&toml),
+                    Err(ConfigError::InvalidFlatten { .. })
+                ),
+                "expected error for: {}",
+                flatten
+            );
+        }
+
+        let not_included = format!(
+            "{}\n[columns]\ninclude = [\"id\"]\n\n[flatten]\ncolumns = [\"metadata\"]\n",
+            base
+        );
+        assert!(matches!(
+            parse_and_validate(&not_included),
+            Err(ConfigError::InvalidFlatten { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_rename_rejects_reserved_and_duplicate_targets() {
+        let base = r#"
+version = 1
+mapping_name = "test"
+namespace = "test"
+
+[source]
+schema = "public"
+table = "test"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+
+        let reserved = format!("{}\n[columns.rename]\nuser_id = \"id\"\n", base);
+        assert!(matches!(
+            parse_and_validate(&reserved),
+            Err(ConfigError::InvalidRename { .. })
+        ));
+
+        let duplicate = format!(
+            "{}\n[columns.rename]\nfirst = \"name\"\nsecond = \"name\"\n",
+            base
+        );
+        assert!(matches!(
+            parse_and_validate(&duplicate),
+            Err(ConfigError::InvalidRename { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_sinks() {
+        let base = r#"
+version = 1
+mapping_name = "test"
+namespace = "test"
+
+[source]
+schema = "public"
+table = "test"
+
+[id]
+column = "id"
+type = "uint"
+"#;
+
+        for sink in [
+            "[sinks.DR]\nnamespace = \"test_dr\"",
+            "[sinks.dr]\nnamespace = \"\"",
+            "[sinks.dr]\nnamespace = \"{tenant_id}_dr\"",
+            "[sinks.dr]",
+            "[sinks.dr]\nnamespace = \"test_dr\"\napi_key = \"${DR_KEY\"",
+        ] {
+            let toml = format!("{}\n{}\n", base, sink);
+            assert!(
+                matches!(
+                    parse_and_validate(&toml),
+                    Err(ConfigError::InvalidSink { .. })
+                ),
+                "expected error for: {}",
+                sink
+            );
+        }
+
+        let toml = format!(
+            "{}\n[sinks.dr]\napi_key = \"${{DR_KEY}}\"\nmax_retries = 10\n\n[sinks.en]\nnamespace = \"{{namespace}}_en\"\n",
+            base
+        );
+        let mapping = to_mapping(&MigrationConfig::parse(&toml).unwrap()).unwrap();
+        assert_eq!(mapping.sinks.len(), 2);
+        assert_eq!(mapping.sinks[0].name, "dr");
+        assert_eq!(mapping.sinks[0].namespace_for("test"), "test");
+        assert_eq!(mapping.sinks[0].api_key.as_deref(), Some("${DR_KEY}"));
+        assert_eq!(mapping.sinks[0].max_retries, Some(10));
+        assert_eq!(mapping.sinks[1].namespace_for("test"), "test_en");
+    }
 }
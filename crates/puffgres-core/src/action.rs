@@ -1,11 +1,30 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::fmt;
 
 use crate::types::Value;
 
 /// A document to be written to turbopuffer.
 pub type Document = HashMap<String, Value>;
 
+/// Attribute holding the commit time of the source transaction.
+pub const EVENT_TIME_ATTRIBUTE: &str = "__event_time";
+
+/// Attribute holding the hash of a document, for `content_hash` versioning.
+pub const DOC_HASH_ATTRIBUTE: &str = "__doc_hash";
+
+/// Attribute holding the id of the puffgres run that wrote a document.
+pub const RUN_ID_ATTRIBUTE: &str = "__run_id";
+
+/// Default attribute holding the LSN of the change that wrote a document.
+pub const SOURCE_LSN_ATTRIBUTE: &str = "__source_lsn";
+
+/// Default attribute marking documents written by a backfill.
+pub const BACKFILL_ATTRIBUTE: &str = "__backfill";
+
+/// Turbopuffer's vector column.
+pub const VECTOR_ATTRIBUTE: &str = "vector";
+
 /// The result of transforming a RowEvent.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -16,15 +35,30 @@ pub enum Action {
         id: DocumentId,
         /// The document to upsert.
         doc: Document,
+        /// The document's vector, written to the namespace's vector column.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        vector: Option<Vec<f32>>,
         /// Distance metric for vector fields.
         #[serde(skip_serializing_if = "Option::is_none")]
         distance_metric: Option<rs_puff::DistanceMetric>,
     },
+    /// Set some attributes of an existing document, leaving its other
+    /// attributes and vector as they are. Turbopuffer ignores patches to
+    /// documents that don't exist.
+    Patch {
+        /// The document ID.
+        id: DocumentId,
+        /// The attributes to set.
+        attributes: Document,
+    },
     /// Delete a document from the namespace.
     Delete {
         /// The document ID to delete.
         id: DocumentId,
     },
+    /// Delete every document in the namespace, e.g. after the source table
+    /// was truncated.
+    ClearNamespace,
     /// Skip this event (no action needed).
     Skip,
     /// An error occurred during transformation.
@@ -37,15 +71,24 @@ pub enum Action {
 }
 
 /// A document ID in turbopuffer.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(untagged)]
+///
+/// UUIDs and bytes are held in binary, parsed once when the ID is extracted,
+/// and compared and hashed as such. They're written as text: a UUID
+/// hyphenated, bytes as lowercase hex. Either way they sort as their bytes
+/// do.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum DocumentId {
     Uint(u64),
     Int(i64),
-    Uuid(String),
+    Uuid([u8; 16]),
     String(String),
+    /// E.g. a `bytea` key, written as a string ID.
+    Bytes(Vec<u8>),
 }
 
+/// Positions of the hyphens in a hyphenated UUID.
+const UUID_HYPHENS: [usize; 4] = [8, 13, 18, 23];
+
 impl DocumentId {
     pub fn as_u64(&self) -> Option<u64> {
         match self {
@@ -54,6 +97,104 @@ impl DocumentId {
             _ => None,
         }
     }
+
+    /// A UUID ID from its hyphenated text, in either case.
+    pub fn parse_uuid(s: &str) -> Option<Self> {
+        let s = s.as_bytes();
+        if s.len() != 36 || UUID_HYPHENS.iter().any(|&i| s[i] != b'-') {
+            return None;
+        }
+        let mut digits = s
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !UUID_HYPHENS.contains(i))
+            .map(|(_, &c)| hex_digit(c));
+        let mut bytes = [0; 16];
+        for byte in &mut bytes {
+            *byte = (digits.next()?? << 4) | digits.next()??;
+        }
+        Some(DocumentId::Uuid(bytes))
+    }
+
+    /// An ID given as text, e.g. in JSON: a UUID when it's written the way
+    /// a UUID ID would be, so the text never changes, otherwise a string.
+    /// Bytes come back as a string of their hex, which is written the same.
+    pub fn from_text(s: String) -> Self {
+        match DocumentId::parse_uuid(&s) {
+            Some(uuid) if uuid.to_string() == s => uuid,
+            _ => DocumentId::String(s),
+        }
+    }
+
+    /// A bytes ID from Postgres's hex text for `bytea` (`\x0102`).
+    pub fn parse_bytea(s: &str) -> Option<Self> {
+        DocumentId::parse_hex(s.strip_prefix("\\x")?)
+    }
+
+    /// A bytes ID from its hex, as it's written, in either case.
+    pub fn parse_hex(s: &str) -> Option<Self> {
+        let pairs = s.as_bytes().chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return None;
+        }
+        pairs
+            .map(|pair| Some((hex_digit(pair[0])? << 4) | hex_digit(pair[1])?))
+            .collect::<Option<Vec<u8>>>()
+            .map(DocumentId::Bytes)
+    }
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+impl fmt::Display for DocumentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocumentId::Uint(u) => write!(f, "{}", u),
+            DocumentId::Int(i) => write!(f, "{}", i),
+            DocumentId::Uuid(bytes) => {
+                for (i, byte) in bytes.iter().enumerate() {
+                    if matches!(i, 4 | 6 | 8 | 10) {
+                        f.write_str("-")?;
+                    }
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            DocumentId::String(s) => f.write_str(s),
+            DocumentId::Bytes(bytes) => bytes.iter().try_for_each(|b| write!(f, "{:02x}", b)),
+        }
+    }
+}
+
+impl Serialize for DocumentId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            DocumentId::Uint(u) => serializer.serialize_u64(*u),
+            DocumentId::Int(i) => serializer.serialize_i64(*i),
+            DocumentId::String(s) => serializer.serialize_str(s),
+            DocumentId::Uuid(_) | DocumentId::Bytes(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DocumentId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Written {
+            Uint(u64),
+            Int(i64),
+            String(String),
+        }
+
+        Ok(match Written::deserialize(deserializer)? {
+            Written::Uint(u) => DocumentId::Uint(u),
+            Written::Int(i) => DocumentId::Int(i),
+            Written::String(s) => DocumentId::from_text(s),
+        })
+    }
 }
 
 impl From<u64> for DocumentId {
@@ -97,6 +238,10 @@ pub enum ErrorKind {
     SchemaError,
     /// Invalid data that cannot be serialized.
     InvalidData,
+    /// A transform returned a document turbopuffer would reject.
+    InvalidDocument,
+    /// The database role or API key isn't allowed to do this.
+    PermissionDenied,
 
     // Retryable errors - may succeed on retry
     /// Network error (connection failed, timeout).
@@ -107,6 +252,9 @@ pub enum ErrorKind {
     ServiceUnavailable,
     /// Timeout waiting for response.
     Timeout,
+    /// A concurrent transaction conflicted (serialization failure, deadlock,
+    /// replication slot in use).
+    Conflict,
 
     /// Generic/unknown error.
     Unknown,
@@ -120,11 +268,13 @@ impl ErrorKind {
     /// - Rate limits
     /// - Timeouts
     /// - Service unavailability
+    /// - Conflicts with a concurrent transaction
     ///
     /// Permanent errors should not be retried:
     /// - Schema errors
     /// - Transform errors
     /// - Invalid data
+    /// - Permission errors
     pub fn is_retryable(&self) -> bool {
         matches!(
             self,
@@ -132,6 +282,7 @@ impl ErrorKind {
                 | ErrorKind::RateLimited
                 | ErrorKind::ServiceUnavailable
                 | ErrorKind::Timeout
+                | ErrorKind::Conflict
         )
     }
 
@@ -144,10 +295,13 @@ impl ErrorKind {
             ErrorKind::PredicateFailed => "Predicate failed",
             ErrorKind::SchemaError => "Schema error",
             ErrorKind::InvalidData => "Invalid data",
+            ErrorKind::InvalidDocument => "Invalid document",
+            ErrorKind::PermissionDenied => "Permission denied",
             ErrorKind::NetworkError => "Network error",
             ErrorKind::RateLimited => "Rate limited",
             ErrorKind::ServiceUnavailable => "Service unavailable",
             ErrorKind::Timeout => "Timeout",
+            ErrorKind::Conflict => "Conflict",
             ErrorKind::Unknown => "Unknown error",
         }
     }
@@ -161,10 +315,13 @@ impl ErrorKind {
             "predicate_failed" => ErrorKind::PredicateFailed,
             "schema_error" => ErrorKind::SchemaError,
             "invalid_data" => ErrorKind::InvalidData,
+            "invalid_document" => ErrorKind::InvalidDocument,
+            "permission_denied" => ErrorKind::PermissionDenied,
             "network_error" => ErrorKind::NetworkError,
             "rate_limited" => ErrorKind::RateLimited,
             "service_unavailable" => ErrorKind::ServiceUnavailable,
             "timeout" => ErrorKind::Timeout,
+            "conflict" => ErrorKind::Conflict,
             _ => ErrorKind::Unknown,
         }
     }
@@ -178,10 +335,13 @@ impl ErrorKind {
             ErrorKind::PredicateFailed => "predicate_failed",
             ErrorKind::SchemaError => "schema_error",
             ErrorKind::InvalidData => "invalid_data",
+            ErrorKind::InvalidDocument => "invalid_document",
+            ErrorKind::PermissionDenied => "permission_denied",
             ErrorKind::NetworkError => "network_error",
             ErrorKind::RateLimited => "rate_limited",
             ErrorKind::ServiceUnavailable => "service_unavailable",
             ErrorKind::Timeout => "timeout",
+            ErrorKind::Conflict => "conflict",
             ErrorKind::Unknown => "unknown",
         }
     }
@@ -193,6 +353,7 @@ impl Action {
         Action::Upsert {
             id: id.into(),
             doc,
+            vector: None,
             distance_metric: None,
         }
     }
@@ -206,15 +367,29 @@ impl Action {
         Action::Upsert {
             id: id.into(),
             doc,
+            vector: None,
             distance_metric: Some(distance_metric),
         }
     }
 
+    /// Create a patch action.
+    pub fn patch(id: impl Into<DocumentId>, attributes: Document) -> Self {
+        Action::Patch {
+            id: id.into(),
+            attributes,
+        }
+    }
+
     /// Create a delete action.
     pub fn delete(id: impl Into<DocumentId>) -> Self {
         Action::Delete { id: id.into() }
     }
 
+    /// Create an action that deletes every document in the namespace.
+    pub fn clear_namespace() -> Self {
+        Action::ClearNamespace
+    }
+
     /// Create a skip action.
     pub fn skip() -> Self {
         Action::Skip
@@ -228,15 +403,85 @@ impl Action {
         }
     }
 
+    /// Attach a vector to an upsert. Other actions are left unchanged.
+    pub fn with_vector(mut self, vector: Vec<f32>) -> Self {
+        if let Action::Upsert { vector: v, .. } = &mut self {
+            *v = Some(vector);
+        }
+        self
+    }
+
+    /// Dimensions of the upsert's vector, if it has one.
+    pub fn vector_dims(&self) -> Option<usize> {
+        match self {
+            Action::Upsert {
+                vector: Some(v), ..
+            } => Some(v.len()),
+            _ => None,
+        }
+    }
+
     /// Check if this action requires a write to turbopuffer.
     pub fn requires_write(&self) -> bool {
-        matches!(self, Action::Upsert { .. } | Action::Delete { .. })
+        matches!(
+            self,
+            Action::Upsert { .. }
+                | Action::Patch { .. }
+                | Action::Delete { .. }
+                | Action::ClearNamespace
+        )
     }
 
     /// Check if this is an error action.
     pub fn is_error(&self) -> bool {
         matches!(self, Action::Error { .. })
     }
+
+    /// The attributes an upsert or patch writes.
+    fn attributes_mut(&mut self) -> Option<&mut Document> {
+        match self {
+            Action::Upsert { doc, .. } => Some(doc),
+            Action::Patch { attributes, .. } => Some(attributes),
+            _ => None,
+        }
+    }
+
+    /// Stamp an upsert or patch with the commit time of the transaction that
+    /// produced it.
+    ///
+    /// Other actions are left unchanged, as are events without a commit time
+    /// (e.g. rows read during backfill).
+    pub fn with_event_time(mut self, timestamp: Option<&str>) -> Self {
+        if let (Some(doc), Some(ts)) = (self.attributes_mut(), timestamp) {
+            doc.insert(
+                EVENT_TIME_ATTRIBUTE.to_string(),
+                Value::String(ts.to_string()),
+            );
+        }
+        self
+    }
+
+    /// Stamp an upsert or patch with the id of the run writing it. Other
+    /// actions are left unchanged.
+    pub fn with_run_id(mut self, run_id: &str) -> Self {
+        if let Some(doc) = self.attributes_mut() {
+            doc.insert(
+                RUN_ID_ATTRIBUTE.to_string(),
+                Value::String(run_id.to_string()),
+            );
+        }
+        self
+    }
+
+    /// Stamp an upsert with a hash of its document and vector, so unchanged
+    /// documents can be recognized. Other actions are left unchanged.
+    pub fn with_content_hash(mut self) -> Self {
+        if let Action::Upsert { doc, vector, .. } = &mut self {
+            let hash = crate::content_hash::document_hash(doc, vector.as_deref());
+            doc.insert(DOC_HASH_ATTRIBUTE.to_string(), Value::String(hash));
+        }
+        self
+    }
 }
 
 #[cfg(test)]
@@ -262,11 +507,100 @@ mod tests {
     #[test]
     fn test_action_requires_write() {
         assert!(Action::upsert(1u64, HashMap::new()).requires_write());
+        assert!(Action::patch(1u64, HashMap::new()).requires_write());
         assert!(Action::delete(1u64).requires_write());
+        assert!(Action::clear_namespace().requires_write());
         assert!(!Action::skip().requires_write());
         assert!(!Action::error(ErrorKind::Unknown, "test").requires_write());
     }
 
+    #[test]
+    fn test_with_event_time() {
+        let ts = "2024-01-15T10:30:00.000000Z";
+
+        let action = Action::upsert(1u64, HashMap::new()).with_event_time(Some(ts));
+        match action {
+            Action::Upsert { doc, .. } => {
+                assert_eq!(
+                    doc.get(EVENT_TIME_ATTRIBUTE),
+                    Some(&Value::String(ts.into()))
+                );
+            }
+            _ => panic!("Expected Upsert"),
+        }
+
+        let action = Action::upsert(1u64, HashMap::new()).with_event_time(None);
+        assert_eq!(action, Action::upsert(1u64, HashMap::new()));
+
+        let action = Action::delete(1u64).with_event_time(Some(ts));
+        assert_eq!(action, Action::delete(1u64));
+
+        let Action::Patch { attributes, .. } =
+            Action::patch(1u64, HashMap::new()).with_event_time(Some(ts))
+        else {
+            panic!("Expected Patch");
+        };
+        assert_eq!(
+            attributes.get(EVENT_TIME_ATTRIBUTE),
+            Some(&Value::String(ts.into()))
+        );
+    }
+
+    #[test]
+    fn test_with_run_id() {
+        let run_id = "01890a5d-ac96-774b-bcce-b302099a8057";
+        let Action::Upsert { doc, .. } = Action::upsert(1u64, HashMap::new()).with_run_id(run_id)
+        else {
+            panic!("Expected Upsert");
+        };
+        assert_eq!(
+            doc.get(RUN_ID_ATTRIBUTE),
+            Some(&Value::String(run_id.into()))
+        );
+
+        assert_eq!(
+            Action::delete(1u64).with_run_id(run_id),
+            Action::delete(1u64)
+        );
+    }
+
+    #[test]
+    fn test_with_content_hash() {
+        let doc: Document = [("name".into(), Value::String("test".into()))]
+            .into_iter()
+            .collect();
+        let action = Action::upsert(1u64, doc).with_content_hash();
+        let Action::Upsert { doc, .. } = &action else {
+            panic!("Expected Upsert");
+        };
+        assert!(matches!(
+            doc.get(DOC_HASH_ATTRIBUTE),
+            Some(Value::String(_))
+        ));
+
+        // Hashing again ignores the existing hash
+        assert_eq!(action.clone().with_content_hash(), action);
+        assert_eq!(
+            Action::delete(1u64).with_content_hash(),
+            Action::delete(1u64)
+        );
+    }
+
+    #[test]
+    fn test_with_vector() {
+        let action = Action::upsert(1u64, HashMap::new()).with_vector(vec![0.1, 0.2, 0.3]);
+        assert_eq!(action.vector_dims(), Some(3));
+
+        let json = serde_json::to_value(&action).unwrap();
+        assert_eq!(json["vector"].as_array().map(|v| v.len()), Some(3));
+
+        assert_eq!(Action::upsert(1u64, HashMap::new()).vector_dims(), None);
+        assert_eq!(
+            Action::delete(1u64).with_vector(vec![1.0]),
+            Action::delete(1u64)
+        );
+    }
+
     #[test]
     fn test_document_id_conversions() {
         let id: DocumentId = 42u64.into();
@@ -279,6 +613,79 @@ mod tests {
         assert!(matches!(id, DocumentId::String(_)));
     }
 
+    #[test]
+    fn test_parse_uuid() {
+        let text = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let id = DocumentId::parse_uuid(text).unwrap();
+        assert_eq!(id.to_string(), text);
+        assert_eq!(DocumentId::parse_uuid(&text.to_uppercase()), Some(id));
+
+        assert_eq!(
+            DocumentId::parse_uuid("67e5504410b1426f9247bb680e5fe0c8"),
+            None
+        );
+        assert_eq!(
+            DocumentId::parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0cg"),
+            None
+        );
+        assert_eq!(DocumentId::parse_uuid(&"x".repeat(36)), None);
+    }
+
+    #[test]
+    fn test_parse_bytea() {
+        let id = DocumentId::parse_bytea("\\x00ff0A").unwrap();
+        assert_eq!(id, DocumentId::Bytes(vec![0x00, 0xff, 0x0a]));
+        assert_eq!(id.to_string(), "00ff0a");
+        assert_eq!(
+            DocumentId::parse_bytea("\\x"),
+            Some(DocumentId::Bytes(vec![]))
+        );
+
+        assert_eq!(DocumentId::parse_bytea("00ff"), None);
+        assert_eq!(DocumentId::parse_bytea("\\x0"), None);
+        assert_eq!(DocumentId::parse_bytea("\\xzz"), None);
+        assert_eq!(DocumentId::parse_hex("00FF0a"), Some(id));
+    }
+
+    #[test]
+    fn test_document_id_ordering() {
+        let uuid = |s| DocumentId::parse_uuid(s).unwrap();
+        assert!(
+            uuid("00000000-0000-0000-0000-0000000000ff")
+                < uuid("00000000-0000-0000-0000-000000000100")
+        );
+        assert!(DocumentId::Bytes(vec![0x01]) < DocumentId::Bytes(vec![0x01, 0x00]));
+        assert!(DocumentId::Bytes(vec![0x01, 0xff]) < DocumentId::Bytes(vec![0x02]));
+    }
+
+    #[test]
+    fn test_document_id_serde() {
+        let text = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let ids = [
+            DocumentId::Uint(42),
+            DocumentId::Int(-5),
+            DocumentId::parse_uuid(text).unwrap(),
+            DocumentId::String("abc".into()),
+        ];
+        for id in ids {
+            let json = serde_json::to_string(&id).unwrap();
+            assert_eq!(serde_json::from_str::<DocumentId>(&json).unwrap(), id);
+        }
+        assert_eq!(
+            serde_json::to_value(DocumentId::parse_uuid(text).unwrap()).unwrap(),
+            serde_json::json!(text)
+        );
+
+        // A string id that looks like a UUID stays a string unless it's
+        // written back the same way
+        let upper: DocumentId =
+            serde_json::from_value(serde_json::json!(text.to_uppercase())).unwrap();
+        assert_eq!(upper, DocumentId::String(text.to_uppercase()));
+
+        let bytes = serde_json::to_value(DocumentId::Bytes(vec![0xde, 0xad])).unwrap();
+        assert_eq!(bytes, serde_json::json!("dead"));
+    }
+
     #[test]
     fn test_error_kind_retryable() {
         // Permanent errors
@@ -288,6 +695,7 @@ mod tests {
         assert!(!ErrorKind::PredicateFailed.is_retryable());
         assert!(!ErrorKind::SchemaError.is_retryable());
         assert!(!ErrorKind::InvalidData.is_retryable());
+        assert!(!ErrorKind::InvalidDocument.is_retryable());
         assert!(!ErrorKind::Unknown.is_retryable());
 
         // Retryable errors
@@ -303,6 +711,7 @@ mod tests {
             ErrorKind::MissingColumn,
             ErrorKind::InvalidType,
             ErrorKind::TransformFailed,
+            ErrorKind::InvalidDocument,
             ErrorKind::NetworkError,
             ErrorKind::RateLimited,
         ];
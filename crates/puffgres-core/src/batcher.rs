@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 use crate::action::Action;
 use crate::mapping::BatchConfig;
@@ -10,6 +11,8 @@ pub struct Batch {
     pub actions: Vec<Action>,
     pub lsn: u64,
     estimated_size: usize,
+    /// When the batch's first action was added.
+    started: Instant,
 }
 
 impl Batch {
@@ -19,6 +22,7 @@ impl Batch {
             actions: Vec::new(),
             lsn,
             estimated_size: 0,
+            started: Instant::now(),
         }
     }
 
@@ -93,6 +97,52 @@ impl Batcher {
         result
     }
 
+    /// Flush the batches that have waited `max_wait_ms` for more actions.
+    /// None are due without a `max_wait_ms`.
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<Batch> {
+        let Some(max_wait) = self.max_wait() else {
+            return Vec::new();
+        };
+        let expired: Vec<String> = self
+            .batches
+            .iter()
+            .filter(|(_, batch)| now >= batch.started + max_wait)
+            .map(|(namespace, _)| namespace.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|namespace| self.flush(namespace))
+            .collect()
+    }
+
+    /// When the oldest pending batch is due, with a `max_wait_ms`.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let max_wait = self.max_wait()?;
+        self.batches
+            .values()
+            .filter(|batch| !batch.is_empty())
+            .map(|batch| batch.started + max_wait)
+            .min()
+    }
+
+    /// The LSN of the earliest pending batch.
+    pub fn oldest_lsn(&self) -> Option<u64> {
+        self.batches
+            .values()
+            .filter(|batch| !batch.is_empty())
+            .map(|batch| batch.lsn)
+            .min()
+    }
+
+    /// The batch limits in use.
+    pub fn config(&self) -> &BatchConfig {
+        &self.config
+    }
+
+    fn max_wait(&self) -> Option<Duration> {
+        self.config.max_wait_ms.map(Duration::from_millis)
+    }
+
     /// Flush a specific namespace's batch.
     pub fn flush(&mut self, namespace: &str) -> Option<Batch> {
         self.batches.remove(namespace).filter(|b| !b.is_empty())
@@ -112,11 +162,12 @@ impl Batcher {
 /// Estimate the size of an action in bytes.
 fn estimate_action_size(action: &Action) -> usize {
     match action {
-        Action::Upsert { doc, .. } => {
-            // Rough estimate: serialize to JSON and measure
-            serde_json::to_string(doc).map(|s| s.len()).unwrap_or(100)
+        Action::Upsert { doc, vector, .. } => {
+            crate::doc_size::document_size(doc, vector.as_deref())
         }
+        Action::Patch { attributes, .. } => crate::doc_size::document_size(attributes, None),
         Action::Delete { .. } => 50, // ID only
+        Action::ClearNamespace => 0,
         Action::Skip => 0,
         Action::Error { message, .. } => message.len() + 50,
     }
@@ -127,7 +178,10 @@ fn estimate_action_size(action: &Action) -> usize {
 pub struct WriteRequest {
     pub namespace: String,
     pub upserts: Vec<UpsertDoc>,
+    pub patches: Vec<PatchDoc>,
     pub deletes: Vec<crate::action::DocumentId>,
+    /// Delete every document in the namespace before writing the rest.
+    pub clear: bool,
     pub lsn: u64,
     /// Distance metric for vector fields (from the first upsert with a metric).
     pub distance_metric: Option<rs_puff::DistanceMetric>,
@@ -138,34 +192,82 @@ pub struct WriteRequest {
 pub struct UpsertDoc {
     pub id: crate::action::DocumentId,
     pub attributes: crate::action::Document,
+    pub vector: Option<Vec<f32>>,
+}
+
+/// Attributes to set on an existing document.
+#[derive(Debug, Clone)]
+pub struct PatchDoc {
+    pub id: crate::action::DocumentId,
+    pub attributes: crate::action::Document,
 }
 
 impl WriteRequest {
     /// Build a write request from a batch.
+    ///
+    /// Patches are written after upserts, so a patch is folded into an
+    /// earlier upsert of the same document in the batch, and dropped if a
+    /// later upsert or delete replaces the document anyway. Clearing the
+    /// namespace drops everything before it in the batch.
     pub fn from_batch(batch: Batch) -> Self {
-        let mut upserts = Vec::new();
+        let mut upserts: Vec<UpsertDoc> = Vec::new();
+        let mut patches: Vec<Option<PatchDoc>> = Vec::new();
         let mut deletes = Vec::new();
+        let mut clear = false;
         let mut distance_metric = None;
+        // Where the latest upsert and pending patch of each document are
+        let mut upserted: HashMap<crate::action::DocumentId, usize> = HashMap::new();
+        let mut patched: HashMap<crate::action::DocumentId, usize> = HashMap::new();
 
         for action in batch.actions {
             match action {
                 Action::Upsert {
                     id,
                     doc,
+                    vector,
                     distance_metric: dm,
                 } => {
                     // Take the first non-None distance metric
                     if distance_metric.is_none() && dm.is_some() {
                         distance_metric = dm;
                     }
+                    if let Some(i) = patched.remove(&id) {
+                        patches[i] = None;
+                    }
+                    upserted.insert(id.clone(), upserts.len());
                     upserts.push(UpsertDoc {
                         id,
                         attributes: doc,
+                        vector,
                     });
                 }
+                Action::Patch { id, attributes } => {
+                    if let Some(&i) = upserted.get(&id) {
+                        upserts[i].attributes.extend(attributes);
+                    } else if let Some(&i) = patched.get(&id) {
+                        if let Some(patch) = &mut patches[i] {
+                            patch.attributes.extend(attributes);
+                        }
+                    } else {
+                        patched.insert(id.clone(), patches.len());
+                        patches.push(Some(PatchDoc { id, attributes }));
+                    }
+                }
                 Action::Delete { id } => {
+                    upserted.remove(&id);
+                    if let Some(i) = patched.remove(&id) {
+                        patches[i] = None;
+                    }
                     deletes.push(id);
                 }
+                Action::ClearNamespace => {
+                    clear = true;
+                    upserts.clear();
+                    patches.clear();
+                    deletes.clear();
+                    upserted.clear();
+                    patched.clear();
+                }
                 Action::Skip | Action::Error { .. } => {}
             }
         }
@@ -173,14 +275,16 @@ impl WriteRequest {
         WriteRequest {
             namespace: batch.namespace,
             upserts,
+            patches: patches.into_iter().flatten().collect(),
             deletes,
+            clear,
             lsn: batch.lsn,
             distance_metric,
         }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.upserts.is_empty() && self.deletes.is_empty()
+        self.upserts.is_empty() && self.patches.is_empty() && self.deletes.is_empty() && !self.clear
     }
 }
 
@@ -202,6 +306,7 @@ mod tests {
             max_rows: 10,
             max_bytes: 1024 * 1024,
             flush_interval_ms: 100,
+            max_wait_ms: None,
         };
         let mut batcher = Batcher::new(config);
 
@@ -222,6 +327,7 @@ mod tests {
             max_rows: 3,
             max_bytes: 1024 * 1024,
             flush_interval_ms: 100,
+            max_wait_ms: None,
         };
         let mut batcher = Batcher::new(config);
 
@@ -240,6 +346,34 @@ mod tests {
         assert_eq!(batcher.pending_count(), 1);
     }
 
+    #[test]
+    fn test_batcher_max_wait() {
+        let mut batcher = Batcher::new(BatchConfig {
+            max_wait_ms: Some(50),
+            ..Default::default()
+        });
+        assert_eq!(batcher.next_deadline(), None);
+
+        let before = Instant::now();
+        batcher.add("ns1", make_upsert(1), 100);
+        batcher.add("ns2", make_upsert(2), 90);
+        assert_eq!(batcher.oldest_lsn(), Some(90));
+        assert!(batcher.next_deadline().unwrap() >= before + Duration::from_millis(50));
+
+        assert!(batcher.flush_expired(before).is_empty());
+        let batches = batcher.flush_expired(Instant::now() + Duration::from_millis(50));
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batcher.next_deadline(), None);
+        assert_eq!(batcher.oldest_lsn(), None);
+
+        // Without a max wait nothing is ever due
+        let mut batcher = Batcher::new(BatchConfig::default());
+        batcher.add("ns1", make_upsert(1), 100);
+        assert_eq!(batcher.next_deadline(), None);
+        let later = Instant::now() + Duration::from_secs(60);
+        assert!(batcher.flush_expired(later).is_empty());
+    }
+
     #[test]
     fn test_write_request_from_batch() {
         let mut batch = Batch::new("test_ns".into(), 100);
@@ -255,6 +389,59 @@ mod tests {
         assert_eq!(request.lsn, 100);
     }
 
+    #[test]
+    fn test_write_request_folds_patches() {
+        let patch = |id: u64, value: i64| {
+            let attributes = [("count".into(), Value::Int(value))].into_iter().collect();
+            Action::patch(id, attributes)
+        };
+
+        let mut batch = Batch::new("test_ns".into(), 100);
+        // Merged into the upsert before it
+        batch.add(make_upsert(1), 50);
+        batch.add(patch(1, 1), 20);
+        // Merged with each other
+        batch.add(patch(2, 1), 20);
+        batch.add(patch(2, 2), 20);
+        // Replaced by the upsert after it
+        batch.add(patch(3, 1), 20);
+        batch.add(make_upsert(3), 50);
+        // Replaced by the delete after it
+        batch.add(patch(4, 1), 20);
+        batch.add(Action::delete(4u64), 20);
+
+        let request = WriteRequest::from_batch(batch);
+        assert_eq!(request.upserts.len(), 2);
+        assert_eq!(
+            request.upserts[0].attributes.get("count"),
+            Some(&Value::Int(1))
+        );
+        assert_eq!(request.upserts[1].attributes.get("count"), None);
+        assert_eq!(request.patches.len(), 1);
+        assert_eq!(request.patches[0].id, 2u64.into());
+        assert_eq!(
+            request.patches[0].attributes.get("count"),
+            Some(&Value::Int(2))
+        );
+        assert_eq!(request.deletes.len(), 1);
+    }
+
+    #[test]
+    fn test_write_request_clear_namespace() {
+        let mut batch = Batch::new("test_ns".into(), 100);
+        batch.add(make_upsert(1), 50);
+        batch.add(Action::delete(2u64), 20);
+        batch.add(Action::clear_namespace(), 0);
+        batch.add(make_upsert(3), 50);
+
+        let request = WriteRequest::from_batch(batch);
+        assert!(request.clear);
+        assert!(!request.is_empty());
+        assert_eq!(request.upserts.len(), 1);
+        assert_eq!(request.upserts[0].id, 3u64.into());
+        assert!(request.deletes.is_empty());
+    }
+
     #[test]
     fn test_batcher_flush_specific_namespace() {
         let config = BatchConfig::default();
@@ -0,0 +1,220 @@
+//! Column type overrides for the identity transform.
+//!
+//! Values are decoded from Postgres by type OID, which doesn't always give the
+//! attribute type a namespace wants: a `numeric` price decoded as a float loses
+//! digits, and timestamps arrive as strings that can't be compared as numbers.
+//! A mapping's `[types]` section forces what specific columns are written as.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::error::{Error, Result};
+use crate::types::Value;
+
+/// What a column is written to documents as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// Text. Numbers keep every digit Postgres has.
+    String,
+    /// A 64-bit integer.
+    Int,
+    /// A 64-bit float.
+    Float,
+    /// A boolean.
+    Bool,
+    /// A timestamp or date as milliseconds since the Unix epoch.
+    UnixMs,
+}
+
+impl ColumnType {
+    /// Name used in migrations and error messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnType::String => "string",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::UnixMs => "unix_ms",
+        }
+    }
+
+    /// Whether the column has to be read from Postgres as text. A `numeric`
+    /// decoded as a float has already lost digits by the time it could be
+    /// turned back into a string.
+    pub fn reads_text(&self) -> bool {
+        matches!(self, ColumnType::String)
+    }
+
+    /// Convert a decoded value of `column` to this type. Nulls stay null.
+    pub fn coerce(&self, column: &str, value: &Value) -> Result<Value> {
+        let coerced = match (self, value) {
+            (_, Value::Null) => Some(Value::Null),
+
+            (ColumnType::String, Value::String(s)) => Some(Value::String(s.clone())),
+            (ColumnType::String, Value::Int(i)) => Some(Value::String(i.to_string())),
+            (ColumnType::String, Value::Float(f)) => Some(Value::String(f.to_string())),
+            (ColumnType::String, Value::Bool(b)) => Some(Value::String(b.to_string())),
+            (ColumnType::String, Value::Array(_) | Value::Object(_)) => {
+                Some(Value::String(serde_json::to_string(value)?))
+            }
+
+            (ColumnType::Int, Value::Int(i)) => Some(Value::Int(*i)),
+            (ColumnType::Int, Value::Float(f)) => float_to_int(*f),
+            (ColumnType::Int, Value::Bool(b)) => Some(Value::Int(i64::from(*b))),
+            (ColumnType::Int, Value::String(s)) => match s.trim().parse::<i64>() {
+                Ok(i) => Some(Value::Int(i)),
+                Err(_) => s.trim().parse::<f64>().ok().and_then(float_to_int),
+            },
+
+            (ColumnType::Float, Value::Float(f)) => Some(Value::Float(*f)),
+            (ColumnType::Float, Value::Int(i)) => Some(Value::Float(*i as f64)),
+            (ColumnType::Float, Value::String(s)) => s.trim().parse::<f64>().ok().map(Value::Float),
+
+            (ColumnType::Bool, Value::Bool(b)) => Some(Value::Bool(*b)),
+            (ColumnType::Bool, Value::Int(0)) => Some(Value::Bool(false)),
+            (ColumnType::Bool, Value::Int(1)) => Some(Value::Bool(true)),
+            (ColumnType::Bool, Value::String(s)) => match s.trim() {
+                "t" | "true" => Some(Value::Bool(true)),
+                "f" | "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+
+            (ColumnType::UnixMs, Value::Int(ms)) => Some(Value::Int(*ms)),
+            (ColumnType::UnixMs, Value::String(s)) => parse_unix_ms(s.trim()).map(Value::Int),
+
+            _ => None,
+        };
+
+        coerced.ok_or_else(|| Error::InvalidColumnType {
+            column: column.to_string(),
+            expected: self.name().to_string(),
+            actual: describe(value),
+        })
+    }
+}
+
+/// An integer, if the float is a whole number in range.
+fn float_to_int(f: f64) -> Option<Value> {
+    (f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64)
+        .then_some(Value::Int(f as i64))
+}
+
+/// Milliseconds since the epoch for a timestamp in Postgres' text output
+/// (`2024-01-15 10:30:00.5+00`) or RFC 3339, or a date. Timestamps without
+/// an offset are taken as UTC.
+fn parse_unix_ms(s: &str) -> Option<i64> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.timestamp_millis());
+    }
+    if let Ok(dt) = DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f%#z") {
+        return Some(dt.timestamp_millis());
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f") {
+        return Some(dt.and_utc().timestamp_millis());
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().timestamp_millis())
+}
+
+/// The value, shortened, for error messages.
+fn describe(value: &Value) -> String {
+    let mut text = serde_json::to_string(value).unwrap_or_default();
+    if text.len() > 64 {
+        let mut end = 64;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push('…');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coerce(ty: ColumnType, value: Value) -> Result<Value> {
+        ty.coerce("col", &value)
+    }
+
+    #[test]
+    fn test_coerce_string_keeps_text() {
+        assert_eq!(
+            coerce(
+                ColumnType::String,
+                Value::String("19.990000000000000001".into())
+            )
+            .unwrap(),
+            Value::String("19.990000000000000001".into())
+        );
+        assert_eq!(
+            coerce(ColumnType::String, Value::Int(42)).unwrap(),
+            Value::String("42".into())
+        );
+        assert_eq!(
+            coerce(ColumnType::String, Value::Null).unwrap(),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn test_coerce_numbers_and_bools() {
+        assert_eq!(
+            coerce(ColumnType::Int, Value::String("12".into())).unwrap(),
+            Value::Int(12)
+        );
+        assert_eq!(
+            coerce(ColumnType::Int, Value::Float(3.0)).unwrap(),
+            Value::Int(3)
+        );
+        assert!(coerce(ColumnType::Int, Value::Float(3.5)).is_err());
+        assert_eq!(
+            coerce(ColumnType::Float, Value::String("0.25".into())).unwrap(),
+            Value::Float(0.25)
+        );
+        assert_eq!(
+            coerce(ColumnType::Bool, Value::String("t".into())).unwrap(),
+            Value::Bool(true)
+        );
+        assert!(coerce(ColumnType::Bool, Value::String("yes".into())).is_err());
+    }
+
+    #[test]
+    fn test_coerce_unix_ms() {
+        // 2024-01-15T10:30:00Z
+        let expected = Value::Int(1_705_314_600_000);
+        for text in [
+            "2024-01-15 10:30:00+00",
+            "2024-01-15 12:30:00+02:00",
+            "2024-01-15 10:30:00",
+            "2024-01-15T10:30:00+00:00",
+        ] {
+            assert_eq!(
+                coerce(ColumnType::UnixMs, Value::String(text.into())).unwrap(),
+                expected,
+                "{}",
+                text
+            );
+        }
+        assert_eq!(
+            coerce(
+                ColumnType::UnixMs,
+                Value::String("2024-01-15 10:30:00.25+00".into())
+            )
+            .unwrap(),
+            Value::Int(1_705_314_600_250)
+        );
+        assert_eq!(
+            coerce(ColumnType::UnixMs, Value::String("2024-01-15".into())).unwrap(),
+            Value::Int(1_705_276_800_000)
+        );
+
+        let err = coerce(ColumnType::UnixMs, Value::String("soon".into())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid column type for 'col': expected unix_ms, got \"soon\""
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Document IDs composed from several columns.
+//!
+//! Tables without a single unique column (e.g. keyed by `(tenant_id,
+//! order_id)`) still need one ID per document. A [`CompositeId`] reads each
+//! of its columns and either joins their text with a separator, giving a
+//! readable string ID, or hashes them into a name-based (version 5) UUID,
+//! giving a fixed-size ID that doesn't depend on a separator never appearing
+//! in the values.
+
+use crate::action::DocumentId;
+use crate::error::{Error, Result};
+use crate::types::{RowMap, Value};
+
+/// Namespace of the version 5 UUIDs puffgres generates. Changing it changes
+/// every generated ID.
+const UUID_NAMESPACE: [u8; 16] = [
+    0x3f, 0x6c, 0x2a, 0x0e, 0x5d, 0x41, 0x4b, 0x8e, 0x9a, 0x17, 0x62, 0xc4, 0x0d, 0x93, 0xe5, 0x21,
+];
+
+/// An ID built from several columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompositeId {
+    /// Columns read, in order.
+    pub columns: Vec<String>,
+    pub strategy: IdStrategy,
+}
+
+/// How a composite ID's column values are combined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdStrategy {
+    /// The values' text joined with a separator, as a string ID.
+    Concat { separator: String },
+    /// A version 5 UUID of the values.
+    Uuid5,
+}
+
+impl CompositeId {
+    /// Build the ID of a row. Every column must be present and non-null.
+    pub fn compose(&self, row: &RowMap) -> Result<DocumentId> {
+        let parts = self
+            .columns
+            .iter()
+            .map(|column| {
+                let value = row
+                    .get(column)
+                    .ok_or_else(|| Error::MissingColumn(column.clone()))?;
+                id_text(value).ok_or_else(|| {
+                    Error::InvalidIdType(format!(
+                        "composite id column '{}' can't be {:?}",
+                        column, value
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(match &self.strategy {
+            IdStrategy::Concat { separator } => DocumentId::String(parts.join(separator)),
+            // A JSON array, so no choice of values can run into a neighbour
+            IdStrategy::Uuid5 => {
+                let name = serde_json::to_string(&parts)?;
+                DocumentId::Uuid(uuid5(&UUID_NAMESPACE, name.as_bytes()))
+            }
+        })
+    }
+}
+
+fn id_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Int(i) => Some(i.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null | Value::Float(_) | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+/// A version 5 UUID (RFC 9562) of `name` in `namespace`.
+fn uuid5(namespace: &[u8; 16], name: &[u8]) -> [u8; 16] {
+    let mut input = namespace.to_vec();
+    input.extend_from_slice(name);
+    let digest = sha1(&input);
+
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    bytes
+}
+
+/// SHA-1, which version 5 UUIDs are defined with. Not used for security.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, state) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&state.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> RowMap {
+        [
+            ("tenant_id".to_string(), Value::String("acme".into())),
+            ("order_id".to_string(), Value::Int(42)),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn composite(strategy: IdStrategy) -> CompositeId {
+        CompositeId {
+            columns: vec!["tenant_id".into(), "order_id".into()],
+            strategy,
+        }
+    }
+
+    #[test]
+    fn test_concat() {
+        let id = composite(IdStrategy::Concat {
+            separator: ":".into(),
+        });
+        assert_eq!(
+            id.compose(&row()).unwrap(),
+            DocumentId::String("acme:42".into())
+        );
+
+        let mut row = row();
+        row.insert("order_id".into(), Value::Null);
+        assert!(id.compose(&row).is_err());
+        row.remove("order_id");
+        assert!(matches!(id.compose(&row), Err(Error::MissingColumn(_))));
+    }
+
+    #[test]
+    fn test_uuid5() {
+        // RFC 9562's example: the DNS namespace and "www.example.com"
+        let dns = [
+            0x6b, 0xa7, 0xb8, 0x10, 0x9d, 0xad, 0x11, 0xd1, 0x80, 0xb4, 0x00, 0xc0, 0x4f, 0xd4,
+            0x30, 0xc8,
+        ];
+        assert_eq!(
+            DocumentId::Uuid(uuid5(&dns, b"www.example.com")).to_string(),
+            "2ed6657d-e927-568b-95e1-2665a8aea6a2"
+        );
+
+        let id = composite(IdStrategy::Uuid5);
+        let DocumentId::Uuid(uuid) = id.compose(&row()).unwrap() else {
+            panic!("expected a uuid");
+        };
+        assert_eq!(uuid[6] >> 4, 5);
+        // Stable, and different when the values are split differently
+        assert_eq!(id.compose(&row()).unwrap(), DocumentId::Uuid(uuid));
+        let mut other = row();
+        other.insert("tenant_id".into(), Value::String("acme4".into()));
+        other.insert("order_id".into(), Value::Int(2));
+        assert_ne!(id.compose(&other).unwrap(), DocumentId::Uuid(uuid));
+    }
+}
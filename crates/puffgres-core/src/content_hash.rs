@@ -0,0 +1,323 @@
+//! Content hashes of upserted documents, used to skip no-op writes.
+//!
+//! Tables that are updated often without changing the columns a mapping
+//! writes (e.g. a `last_seen_at` the transform drops) would otherwise rewrite
+//! the same document on every update. With `versioning.mode = "content_hash"`
+//! each upsert carries a hash of its document, and the runner skips upserts
+//! whose hash matches the one it last wrote for that document.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::action::{Action, Document, DocumentId, DOC_HASH_ATTRIBUTE};
+use crate::types::Value;
+
+/// Hash a document and its vector as 16 hex digits.
+///
+/// Attributes are hashed in sorted order, so the hash doesn't depend on the
+/// order a transform built the document in. An existing
+/// [`DOC_HASH_ATTRIBUTE`] is ignored.
+pub fn document_hash(doc: &Document, vector: Option<&[f32]>) -> String {
+    let mut hasher = Fnv1a::new();
+
+    let mut keys: Vec<&String> = doc
+        .keys()
+        .filter(|k| k.as_str() != DOC_HASH_ATTRIBUTE)
+        .collect();
+    keys.sort();
+    hasher.write_len(keys.len());
+    for key in keys {
+        hasher.write_str(key);
+        hash_value(&mut hasher, &doc[key]);
+    }
+
+    match vector {
+        Some(vector) => {
+            hasher.write(&[1]);
+            hasher.write_len(vector.len());
+            for v in vector {
+                hasher.write(&v.to_bits().to_le_bytes());
+            }
+        }
+        None => hasher.write(&[0]),
+    }
+
+    format!("{:016x}", hasher.0)
+}
+
+fn hash_value(hasher: &mut Fnv1a, value: &Value) {
+    match value {
+        Value::Null => hasher.write(&[0]),
+        Value::Bool(b) => hasher.write(&[1, *b as u8]),
+        Value::Int(i) => {
+            hasher.write(&[2]);
+            hasher.write(&i.to_le_bytes());
+        }
+        Value::Float(f) => {
+            hasher.write(&[3]);
+            hasher.write(&f.to_bits().to_le_bytes());
+        }
+        Value::String(s) => {
+            hasher.write(&[4]);
+            hasher.write_str(s);
+        }
+        Value::Array(items) => {
+            hasher.write(&[5]);
+            hasher.write_len(items.len());
+            for item in items {
+                hash_value(hasher, item);
+            }
+        }
+        Value::Object(map) => {
+            hasher.write(&[6]);
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            hasher.write_len(keys.len());
+            for key in keys {
+                hasher.write_str(key);
+                hash_value(hasher, &map[key]);
+            }
+        }
+    }
+}
+
+/// 64-bit FNV-1a. Stable across platforms and releases, unlike std's
+/// `DefaultHasher`, so hashes written to turbopuffer stay comparable.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write(&(len as u64).to_le_bytes());
+    }
+
+    // Length-prefixed so adjacent strings can't run together
+    fn write_str(&mut self, s: &str) {
+        self.write_len(s.len());
+        self.write(s.as_bytes());
+    }
+}
+
+/// The hashes most recently written for each document, bounded to
+/// `capacity` documents by evicting the least recently used.
+///
+/// The cache only remembers what this process wrote, so after a restart the
+/// first change to each document is written whether or not it changed.
+#[derive(Debug)]
+pub struct DocHashCache {
+    capacity: usize,
+    entries: HashMap<String, HashMap<DocumentId, (String, u64)>>,
+    recency: BTreeMap<u64, (String, DocumentId)>,
+    tick: u64,
+}
+
+impl DocHashCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Record an action about to be written to `namespace`. Returns `true`
+    /// if it's an upsert identical to the last one written for its document,
+    /// in which case the write can be skipped.
+    ///
+    /// Upserts without a hash, patches and deletes forget the document, so a later
+    /// upsert is always written.
+    pub fn observe(&mut self, namespace: &str, action: &Action) -> bool {
+        let (id, hash) = match action {
+            Action::Upsert { id, doc, .. } => match doc.get(DOC_HASH_ATTRIBUTE) {
+                Some(Value::String(hash)) => (id, hash),
+                _ => {
+                    self.forget(namespace, id);
+                    return false;
+                }
+            },
+            // A patched document no longer matches its last hash
+            Action::Patch { id, .. } | Action::Delete { id } => {
+                self.forget(namespace, id);
+                return false;
+            }
+            Action::ClearNamespace => {
+                self.forget_namespace(namespace);
+                return false;
+            }
+            Action::Skip | Action::Error { .. } => return false,
+        };
+
+        self.tick += 1;
+        let tick = self.tick;
+        let docs = self.entries.entry(namespace.to_string()).or_default();
+        let unchanged = match docs.get_mut(id) {
+            Some((previous, last_used)) => {
+                self.recency.remove(last_used);
+                *last_used = tick;
+                let unchanged = previous == hash;
+                if !unchanged {
+                    previous.clone_from(hash);
+                }
+                unchanged
+            }
+            None => {
+                docs.insert(id.clone(), (hash.clone(), tick));
+                false
+            }
+        };
+        self.recency
+            .insert(tick, (namespace.to_string(), id.clone()));
+
+        while self.recency.len() > self.capacity {
+            let Some((_, (namespace, id))) = self.recency.pop_first() else {
+                break;
+            };
+            self.remove(&namespace, &id);
+        }
+        unchanged
+    }
+
+    /// Forget everything, e.g. after a write failed and turbopuffer may no
+    /// longer hold the documents the cache remembers.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    /// Number of documents remembered.
+    pub fn len(&self) -> usize {
+        self.recency.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recency.is_empty()
+    }
+
+    fn forget_namespace(&mut self, namespace: &str) {
+        if let Some(docs) = self.entries.remove(namespace) {
+            for (_, last_used) in docs.into_values() {
+                self.recency.remove(&last_used);
+            }
+        }
+    }
+
+    fn forget(&mut self, namespace: &str, id: &DocumentId) {
+        if let Some((_, last_used)) = self.remove(namespace, id) {
+            self.recency.remove(&last_used);
+        }
+    }
+
+    fn remove(&mut self, namespace: &str, id: &DocumentId) -> Option<(String, u64)> {
+        let docs = self.entries.get_mut(namespace)?;
+        let removed = docs.remove(id);
+        if docs.is_empty() {
+            self.entries.remove(namespace);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(values: &[(&str, Value)]) -> Document {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    fn upsert(id: u64, title: &str) -> Action {
+        Action::upsert(id, doc(&[("title", Value::String(title.into()))])).with_content_hash()
+    }
+
+    #[test]
+    fn test_document_hash() {
+        let a = doc(&[
+            ("title", Value::String("hello".into())),
+            ("views", Value::Int(3)),
+        ]);
+        let b = doc(&[
+            ("views", Value::Int(3)),
+            ("title", Value::String("hello".into())),
+        ]);
+        assert_eq!(document_hash(&a, None), document_hash(&b, None));
+        assert_eq!(document_hash(&a, None).len(), 16);
+
+        let changed = doc(&[
+            ("title", Value::String("hello".into())),
+            ("views", Value::Int(4)),
+        ]);
+        assert_ne!(document_hash(&a, None), document_hash(&changed, None));
+        assert_ne!(document_hash(&a, None), document_hash(&a, Some(&[0.5])));
+
+        // Strings don't run together across attributes
+        let ab = doc(&[
+            ("a", Value::String("xy".into())),
+            ("b", Value::String("z".into())),
+        ]);
+        let ab2 = doc(&[
+            ("a", Value::String("x".into())),
+            ("b", Value::String("yz".into())),
+        ]);
+        assert_ne!(document_hash(&ab, None), document_hash(&ab2, None));
+    }
+
+    #[test]
+    fn test_skips_unchanged_upserts() {
+        let mut cache = DocHashCache::new(10);
+        assert!(!cache.observe("posts", &upsert(1, "hello")));
+        assert!(cache.observe("posts", &upsert(1, "hello")));
+        assert!(!cache.observe("posts", &upsert(1, "changed")));
+        assert!(cache.observe("posts", &upsert(1, "changed")));
+
+        // Namespaces are tracked separately
+        assert!(!cache.observe("posts_v2", &upsert(1, "changed")));
+
+        // A delete means the next upsert has to be written
+        assert!(!cache.observe("posts", &Action::delete(1u64)));
+        assert!(!cache.observe("posts", &upsert(1, "changed")));
+
+        // As does an upsert that wasn't hashed
+        let unhashed = Action::upsert(1u64, doc(&[("title", Value::String("changed".into()))]));
+        assert!(!cache.observe("posts", &unhashed));
+        assert!(!cache.observe("posts", &upsert(1, "changed")));
+
+        // Clearing the namespace forgets every document in it, and only it
+        cache.observe("posts", &upsert(2, "other"));
+        cache.observe("users", &upsert(1, "changed"));
+        assert!(!cache.observe("posts", &Action::clear_namespace()));
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.observe("posts", &upsert(1, "changed")));
+        assert!(cache.observe("users", &upsert(1, "changed")));
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = DocHashCache::new(2);
+        cache.observe("posts", &upsert(1, "a"));
+        cache.observe("posts", &upsert(2, "b"));
+        // Touch 1 so 2 is evicted next
+        assert!(cache.observe("posts", &upsert(1, "a")));
+        cache.observe("posts", &upsert(3, "c"));
+        assert_eq!(cache.len(), 2);
+
+        assert!(cache.observe("posts", &upsert(1, "a")));
+        assert!(!cache.observe("posts", &upsert(2, "b")));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(!cache.observe("posts", &upsert(1, "a")));
+    }
+}
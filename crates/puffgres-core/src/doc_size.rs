@@ -0,0 +1,248 @@
+//! Document size accounting and per-document size limits.
+//!
+//! A transform that returns a far bigger document than intended otherwise
+//! only fails when turbopuffer rejects the write, long after the row that
+//! caused it. Sizes are measured right after the transform, so oversized
+//! documents are caught, and bloat shows up in metrics, at the source.
+
+use crate::action::{Action, Document};
+use crate::error::{Error, Result};
+use crate::mapping::{DocumentLimits, OversizeAction};
+use crate::types::Value;
+
+/// Approximate size of a document as written to turbopuffer: its attributes
+/// as JSON, plus about 10 bytes per vector component.
+pub fn document_size(doc: &Document, vector: Option<&[f32]>) -> usize {
+    let doc_size = serde_json::to_string(doc).map(|s| s.len()).unwrap_or(100);
+    doc_size + vector.map_or(0, |v| v.len() * 10)
+}
+
+/// Size of an upsert's document or a patch's attributes. Other actions have
+/// no document.
+pub fn action_size(action: &Action) -> Option<usize> {
+    match action {
+        Action::Upsert { doc, vector, .. } => Some(document_size(doc, vector.as_deref())),
+        Action::Patch { attributes, .. } => Some(document_size(attributes, None)),
+        _ => None,
+    }
+}
+
+/// Shorten the longest string attributes until the document fits in
+/// `max_bytes`. Returns `false` if it can't be made to fit this way, e.g.
+/// because most of it is a vector or numbers.
+pub fn truncate_to_fit(doc: &mut Document, vector: Option<&[f32]>, max_bytes: usize) -> bool {
+    loop {
+        let size = document_size(doc, vector);
+        if size <= max_bytes {
+            return true;
+        }
+
+        let longest = doc
+            .iter_mut()
+            .filter_map(|(_, value)| match value {
+                Value::String(s) if !s.is_empty() => Some(s),
+                _ => None,
+            })
+            .max_by_key(|s| s.len());
+        let Some(longest) = longest else {
+            return false;
+        };
+
+        // JSON escaping can make a string bigger than its length, so this
+        // may take a few rounds
+        let mut len = longest.len().saturating_sub(size - max_bytes);
+        while !longest.is_char_boundary(len) {
+            len -= 1;
+        }
+        longest.truncate(len);
+    }
+}
+
+impl DocumentLimits {
+    /// Check an action against the limit, truncating its document when the
+    /// policy is [`OversizeAction::Truncate`]. Returns an error for a
+    /// document that is still too large, for the caller to handle as the
+    /// policy says.
+    pub fn enforce(&self, action: &mut Action) -> Result<()> {
+        let Some(max) = self.max_bytes else {
+            return Ok(());
+        };
+        let (doc, vector) = match action {
+            Action::Upsert { doc, vector, .. } => (doc, vector.as_deref()),
+            Action::Patch { attributes, .. } => (attributes, None),
+            _ => return Ok(()),
+        };
+
+        let size = document_size(doc, vector);
+        if size <= max {
+            return Ok(());
+        }
+        if self.on_oversize == OversizeAction::Truncate && truncate_to_fit(doc, vector, max) {
+            return Ok(());
+        }
+        Err(Error::DocumentTooLarge { size, max })
+    }
+}
+
+/// Distribution of document sizes, in power-of-two buckets.
+#[derive(Debug, Clone)]
+pub struct SizeStats {
+    buckets: [u64; 64],
+    count: u64,
+    total: u64,
+    max: usize,
+}
+
+impl Default for SizeStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SizeStats {
+    pub fn new() -> Self {
+        Self {
+            buckets: [0; 64],
+            count: 0,
+            total: 0,
+            max: 0,
+        }
+    }
+
+    /// Record a document size.
+    pub fn record(&mut self, size: usize) {
+        self.buckets[bucket(size)] += 1;
+        self.count += 1;
+        self.total += size as u64;
+        self.max = self.max.max(size);
+    }
+
+    /// Add the sizes recorded by `other`.
+    pub fn merge(&mut self, other: &SizeStats) {
+        for (bucket, count) in self.buckets.iter_mut().zip(other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    /// Number of documents recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Largest document recorded.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Mean document size.
+    pub fn mean(&self) -> usize {
+        self.total.checked_div(self.count).unwrap_or(0) as usize
+    }
+
+    /// An upper bound on the `q` quantile (0.0 to 1.0): the top of the
+    /// bucket it falls in, so at most twice the true value.
+    pub fn quantile(&self, q: f64) -> usize {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let upper = if i >= 63 {
+                    usize::MAX
+                } else {
+                    (1usize << (i + 1)) - 1
+                };
+                return upper.min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+fn bucket(size: usize) -> usize {
+    (usize::BITS - size.leading_zeros()).saturating_sub(1) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(values: &[(&str, Value)]) -> Document {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_truncate_to_fit() {
+        let mut d = doc(&[
+            ("title", Value::String("short".into())),
+            ("body", Value::String("é".repeat(500))),
+        ]);
+        assert!(truncate_to_fit(&mut d, None, 200));
+        assert!(document_size(&d, None) <= 200);
+        // The longest attribute is shortened, on a character boundary
+        assert_eq!(d["title"], Value::String("short".into()));
+        assert!(matches!(&d["body"], Value::String(s) if !s.is_empty() && s.len() < 1000));
+
+        // Numbers and vectors can't be truncated
+        let mut d = doc(&[("n", Value::Int(1))]);
+        assert!(!truncate_to_fit(&mut d, Some(&[0.0; 100]), 200));
+    }
+
+    #[test]
+    fn test_enforce_limits() {
+        let big = || Action::upsert(1u64, doc(&[("body", Value::String("x".repeat(1000)))]));
+
+        let unlimited = DocumentLimits::default();
+        assert!(unlimited.enforce(&mut big()).is_ok());
+
+        let fail = DocumentLimits {
+            max_bytes: Some(100),
+            on_oversize: OversizeAction::Fail,
+        };
+        assert!(matches!(
+            fail.enforce(&mut big()),
+            Err(Error::DocumentTooLarge { max: 100, .. })
+        ));
+        assert!(fail.enforce(&mut Action::delete(1u64)).is_ok());
+
+        let truncate = DocumentLimits {
+            max_bytes: Some(100),
+            on_oversize: OversizeAction::Truncate,
+        };
+        let mut action = big();
+        assert!(truncate.enforce(&mut action).is_ok());
+        assert!(action_size(&action).unwrap() <= 100);
+    }
+
+    #[test]
+    fn test_size_stats() {
+        let mut stats = SizeStats::new();
+        assert_eq!(stats.quantile(0.5), 0);
+
+        for size in [100, 100, 100, 5000] {
+            stats.record(size);
+        }
+        assert_eq!(stats.count(), 4);
+        assert_eq!(stats.max(), 5000);
+        assert_eq!(stats.mean(), 1325);
+        // 100 falls in the 64..=127 bucket
+        assert_eq!(stats.quantile(0.5), 127);
+        assert_eq!(stats.quantile(0.99), 5000);
+
+        let mut merged = SizeStats::new();
+        merged.record(100);
+        merged.merge(&stats);
+        assert_eq!(merged.count(), 5);
+        assert_eq!(merged.max(), 5000);
+        assert_eq!(merged.quantile(0.5), 127);
+    }
+}
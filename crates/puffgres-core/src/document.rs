@@ -0,0 +1,303 @@
+//! Validation of the documents transforms return, against what turbopuffer
+//! accepts.
+//!
+//! Turbopuffer rejects a whole write with a 400 when one document in it is
+//! invalid, and the error rarely says which. Checking each action as it
+//! comes out of the transform pins the problem on the row that caused it,
+//! which is then handled by the mapping's error policy like any other
+//! transform error.
+
+use crate::action::{Action, Document, DocumentId};
+use crate::error::{Error, Result};
+use crate::transform::IdType;
+use crate::types::Value;
+use crate::vector::VectorDimensions;
+
+/// Longest attribute name turbopuffer accepts, in bytes.
+pub const MAX_ATTRIBUTE_NAME_BYTES: usize = 128;
+
+/// Longest string ID turbopuffer accepts, in bytes.
+pub const MAX_STRING_ID_BYTES: usize = 64;
+
+/// Deepest nesting of arrays and objects in an attribute value.
+pub const MAX_NESTING_DEPTH: usize = 8;
+
+/// Largest string attribute value, in bytes.
+pub const MAX_STRING_BYTES: usize = 4 * 1024 * 1024;
+
+/// Checks the actions a mapping produces before they're batched. Tracks
+/// vector dimensions per namespace, so one validator should see every
+/// action written to a namespace.
+#[derive(Debug, Default)]
+pub struct DocumentValidator {
+    vectors: VectorDimensions,
+}
+
+impl DocumentValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check an action bound for `namespace` from a mapping whose IDs are
+    /// `id_type`.
+    pub fn check(&mut self, namespace: &str, id_type: &IdType, action: &Action) -> Result<()> {
+        match action {
+            Action::Upsert { id, doc, .. } => {
+                check_id(id, id_type)?;
+                check_attributes(doc)?;
+            }
+            Action::Patch { id, attributes } => {
+                check_id(id, id_type)?;
+                check_attributes(attributes)?;
+            }
+            Action::Delete { id } => check_id(id, id_type)?,
+            _ => {}
+        }
+        self.vectors.check(namespace, action)
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidDocument(message)
+}
+
+/// Check that an ID has the type the mapping's `[id]` declares, since a
+/// namespace can't mix ID types.
+fn check_id(id: &DocumentId, id_type: &IdType) -> Result<()> {
+    let matches = match (id_type, id) {
+        (IdType::Uint | IdType::ObfuscatedUint(_), id) => id.as_u64().is_some(),
+        (IdType::Int, DocumentId::Int(_)) => true,
+        (IdType::Int, DocumentId::Uint(u)) => i64::try_from(*u).is_ok(),
+        (IdType::Uuid, DocumentId::Uuid(_)) | (IdType::Bytes, DocumentId::Bytes(_)) => true,
+        // Transforms return IDs as JSON, where a UUID is just a string
+        (IdType::String, DocumentId::String(_) | DocumentId::Uuid(_)) => true,
+        _ => false,
+    };
+    if !matches {
+        let id = match id {
+            DocumentId::Uint(_) | DocumentId::Int(_) => id.to_string(),
+            _ => format!("'{}'", id),
+        };
+        return Err(invalid(format!(
+            "id {} doesn't match the mapping's id type ({})",
+            id,
+            id_type_name(id_type)
+        )));
+    }
+
+    // Bytes are written as their hex
+    let len = match id {
+        DocumentId::String(s) => s.len(),
+        DocumentId::Bytes(bytes) => bytes.len() * 2,
+        _ => return Ok(()),
+    };
+    if len == 0 {
+        return Err(invalid("id is an empty string".into()));
+    }
+    if len > MAX_STRING_ID_BYTES {
+        return Err(invalid(format!(
+            "id is {} bytes, over the {} byte limit",
+            len, MAX_STRING_ID_BYTES
+        )));
+    }
+    Ok(())
+}
+
+fn id_type_name(id_type: &IdType) -> &'static str {
+    match id_type {
+        IdType::Uint | IdType::ObfuscatedUint(_) => "uint",
+        IdType::Int => "int",
+        IdType::Uuid => "uuid",
+        IdType::String => "string",
+        IdType::Bytes => "bytes",
+    }
+}
+
+fn check_attributes(doc: &Document) -> Result<()> {
+    for (name, value) in doc {
+        check_attribute_name(name)?;
+        check_value(name, value, 0)?;
+    }
+    Ok(())
+}
+
+fn check_attribute_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(invalid("attribute name is empty".into()));
+    }
+    if name.len() > MAX_ATTRIBUTE_NAME_BYTES {
+        return Err(invalid(format!(
+            "attribute name '{}...' is {} bytes, over the {} byte limit",
+            truncated(name, 32),
+            name.len(),
+            MAX_ATTRIBUTE_NAME_BYTES
+        )));
+    }
+    if name.starts_with('$') {
+        return Err(invalid(format!(
+            "attribute name '{}' starts with '$', which turbopuffer reserves",
+            name
+        )));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(invalid(format!(
+            "attribute name {:?} contains a control character",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn check_value(name: &str, value: &Value, depth: usize) -> Result<()> {
+    match value {
+        Value::String(s) if s.len() > MAX_STRING_BYTES => Err(invalid(format!(
+            "attribute '{}' is a {} byte string, over the {} byte limit",
+            name,
+            s.len(),
+            MAX_STRING_BYTES
+        ))),
+        Value::Array(_) | Value::Object(_) if depth >= MAX_NESTING_DEPTH => Err(invalid(format!(
+            "attribute '{}' is nested more than {} levels deep",
+            name, MAX_NESTING_DEPTH
+        ))),
+        Value::Array(items) => items
+            .iter()
+            .try_for_each(|item| check_value(name, item, depth + 1)),
+        Value::Object(fields) => fields
+            .values()
+            .try_for_each(|field| check_value(name, field, depth + 1)),
+        _ => Ok(()),
+    }
+}
+
+/// The first `max` bytes of `s`, cut at a character boundary.
+fn truncated(s: &str, max: usize) -> &str {
+    let mut end = max.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn doc(attributes: Vec<(&str, Value)>) -> Document {
+        attributes
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect()
+    }
+
+    fn check(id_type: IdType, action: &Action) -> Result<()> {
+        DocumentValidator::new().check("posts", &id_type, action)
+    }
+
+    #[test]
+    fn test_id_type_must_match_mapping() {
+        let uint = Action::upsert(1u64, HashMap::new());
+        let string = Action::upsert("abc", HashMap::new());
+        let uuid_text = "6f1c1b8e-2a3b-4c5d-9e0f-112233445566";
+        let uuid = Action::delete(DocumentId::parse_uuid(uuid_text).unwrap());
+
+        check(IdType::Uint, &uint).unwrap();
+        check(IdType::Int, &uint).unwrap();
+        check(IdType::String, &string).unwrap();
+        check(IdType::String, &uuid).unwrap();
+        check(IdType::Uuid, &uuid).unwrap();
+
+        let err = check(IdType::Uint, &string).unwrap_err();
+        assert!(matches!(err, Error::InvalidDocument(_)));
+        assert!(err.to_string().contains("id type (uint)"), "{}", err);
+        assert!(check(IdType::Uuid, &string).is_err());
+        assert!(check(IdType::Uint, &Action::delete(DocumentId::Int(-1))).is_err());
+        let not_a_uuid = Action::delete("x".repeat(36));
+        assert!(check(IdType::Uuid, &not_a_uuid).is_err());
+        // Text would be written as it is, so a UUID in capitals would miss
+        // the document its lowercase form was written to
+        assert!(check(IdType::Uuid, &Action::delete(uuid_text.to_uppercase())).is_err());
+
+        let bytes = Action::delete(DocumentId::Bytes(vec![1, 2]));
+        check(IdType::Bytes, &bytes).unwrap();
+        assert!(check(IdType::Bytes, &Action::delete("0102")).is_err());
+        assert!(check(IdType::String, &bytes).is_err());
+    }
+
+    #[test]
+    fn test_string_id_length() {
+        check(IdType::String, &Action::delete("a".repeat(64))).unwrap();
+        let err = check(IdType::String, &Action::delete("a".repeat(65))).unwrap_err();
+        assert!(err.to_string().contains("65 bytes"), "{}", err);
+        assert!(check(IdType::String, &Action::delete("")).is_err());
+
+        let bytes = |len| Action::delete(DocumentId::Bytes(vec![0; len]));
+        check(IdType::Bytes, &bytes(32)).unwrap();
+        assert!(check(IdType::Bytes, &bytes(33)).is_err());
+        assert!(check(IdType::Bytes, &bytes(0)).is_err());
+    }
+
+    #[test]
+    fn test_attribute_names() {
+        let ok = doc(vec![("title", Value::Null), ("tag-2.x", Value::Null)]);
+        check(IdType::Uint, &Action::upsert(1u64, ok)).unwrap();
+
+        for name in ["", "$meta", "bad\nname"] {
+            let action = Action::patch(1u64, doc(vec![(name, Value::Null)]));
+            assert!(check(IdType::Uint, &action).is_err(), "{:?}", name);
+        }
+        let long = "a".repeat(MAX_ATTRIBUTE_NAME_BYTES + 1);
+        let err = check(
+            IdType::Uint,
+            &Action::upsert(1u64, doc(vec![(&long, Value::Null)])),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("129 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        let mut value = Value::Int(1);
+        for _ in 0..MAX_NESTING_DEPTH {
+            value = Value::Array(vec![value]);
+        }
+        let action = Action::upsert(1u64, doc(vec![("matrix", value.clone())]));
+        check(IdType::Uint, &action).unwrap();
+
+        let deeper = Value::Object(HashMap::from([("inner".to_string(), value)]));
+        let action = Action::upsert(1u64, doc(vec![("matrix", deeper)]));
+        let err = check(IdType::Uint, &action).unwrap_err();
+        assert!(err.to_string().contains("'matrix'"), "{}", err);
+    }
+
+    #[test]
+    fn test_oversized_string() {
+        let big = Value::String("x".repeat(MAX_STRING_BYTES + 1));
+        let action = Action::upsert(1u64, doc(vec![("body", big)]));
+        let err = check(IdType::Uint, &action).unwrap_err();
+        assert!(err.to_string().contains("'body'"), "{}", err);
+
+        // Strings inside arrays count too
+        let nested = Value::Array(vec![Value::String("x".repeat(MAX_STRING_BYTES + 1))]);
+        let action = Action::patch(1u64, doc(vec![("tags", nested)]));
+        assert!(check(IdType::Uint, &action).is_err());
+    }
+
+    #[test]
+    fn test_vector_dimensions_per_namespace() {
+        let mut validator = DocumentValidator::new();
+        let upsert = |v: Vec<f32>| Action::upsert(1u64, HashMap::new()).with_vector(v);
+
+        validator
+            .check("posts", &IdType::Uint, &upsert(vec![0.1, 0.2]))
+            .unwrap();
+        let err = validator
+            .check("posts", &IdType::Uint, &upsert(vec![0.1]))
+            .unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::InvalidDocument);
+        validator
+            .check("users", &IdType::Uint, &upsert(vec![0.1]))
+            .unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! Embeddings generated from document attributes.
+//!
+//! A mapping with an embed config has its documents' vectors generated by an
+//! embedding provider just before they're written. The text sent for a
+//! document is its embed attributes joined in order; generating the vector
+//! itself is left to the caller, which owns the provider's client.
+
+use crate::action::Document;
+use crate::types::Value;
+
+/// Attributes embedded into a document's vector.
+#[derive(Debug, Clone)]
+pub struct EmbedConfig {
+    /// Document attributes whose text is embedded, in order.
+    pub columns: Vec<String>,
+    /// Distance metric sent with the vectors.
+    pub distance_metric: rs_puff::DistanceMetric,
+}
+
+impl EmbedConfig {
+    /// The text to embed for a document: its embed attributes separated by
+    /// blank lines, skipping missing and null ones. None when there's no
+    /// text at all.
+    pub fn text(&self, doc: &Document) -> Option<String> {
+        let parts: Vec<String> = self
+            .columns
+            .iter()
+            .filter_map(|column| doc.get(column))
+            .filter_map(value_text)
+            .filter(|text| !text.trim().is_empty())
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("\n\n"))
+    }
+}
+
+fn value_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Int(i) => Some(i.to_string()),
+        Value::Float(f) => Some(f.to_string()),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().filter_map(value_text).collect();
+            (!items.is_empty()).then(|| items.join(", "))
+        }
+        Value::Object(_) => serde_json::to_string(value).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> EmbedConfig {
+        EmbedConfig {
+            columns: vec!["title".into(), "body".into(), "tags".into()],
+            distance_metric: rs_puff::DistanceMetric::CosineDistance,
+        }
+    }
+
+    #[test]
+    fn test_embed_text() {
+        let doc: Document = [
+            ("title".to_string(), Value::String("Hello".into())),
+            ("body".to_string(), Value::String("World".into())),
+            (
+                "tags".to_string(),
+                Value::Array(vec![Value::String("a".into()), Value::Null]),
+            ),
+            ("other".to_string(), Value::String("ignored".into())),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(config().text(&doc), Some("Hello\n\nWorld\n\na".into()));
+    }
+
+    #[test]
+    fn test_embed_text_skips_missing_and_null() {
+        let doc: Document = [
+            ("title".to_string(), Value::Null),
+            ("body".to_string(), Value::String("Only body".into())),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(config().text(&doc), Some("Only body".into()));
+
+        let empty: Document = [("title".to_string(), Value::String("  ".into()))]
+            .into_iter()
+            .collect();
+        assert_eq!(config().text(&empty), None);
+    }
+}
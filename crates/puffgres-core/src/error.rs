@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::action::ErrorKind;
+
 /// Errors that can occur in puffgres-core.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -19,6 +21,9 @@ pub enum Error {
     #[error("transform error: {0}")]
     TransformError(String),
 
+    #[error("expression error: {0}")]
+    ExpressionError(String),
+
     #[error("serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
@@ -30,6 +35,41 @@ pub enum Error {
 
     #[error("invalid id type: {0}")]
     InvalidIdType(String),
+
+    #[error("invalid vector: {0}")]
+    InvalidVector(String),
+
+    #[error("invalid namespace: {0}")]
+    InvalidNamespace(String),
+
+    #[error("document is {size} bytes, over the {max} byte limit")]
+    DocumentTooLarge { size: usize, max: usize },
+
+    #[error("invalid document: {0}")]
+    InvalidDocument(String),
+}
+
+impl Error {
+    /// Classify the error, e.g. for the dead letter queue.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::MissingColumn(_) | Error::MissingId => ErrorKind::MissingColumn,
+            Error::InvalidColumnType { .. } | Error::InvalidIdType(_) => ErrorKind::InvalidType,
+            Error::PredicateError(_) => ErrorKind::PredicateFailed,
+            Error::TransformError(_) | Error::ExpressionError(_) => ErrorKind::TransformFailed,
+            Error::InvalidDocument(_) | Error::InvalidVector(_) => ErrorKind::InvalidDocument,
+            Error::SerializationError(_)
+            | Error::BatchSizeExceeded { .. }
+            | Error::InvalidNamespace(_)
+            | Error::DocumentTooLarge { .. } => ErrorKind::InvalidData,
+        }
+    }
+
+    /// Whether the same input could succeed on another attempt. Errors from
+    /// transforming a row never are: the row has to change first.
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
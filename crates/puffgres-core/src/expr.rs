@@ -0,0 +1,646 @@
+//! Expressions for computed attributes.
+//!
+//! A mapping's `[computed]` section derives attributes from the row without
+//! a JS transform, e.g. `full_name = "concat(first_name, ' ', last_name)"`.
+//! Expressions are a small SQL-like language: column names, literals, `||`
+//! to join text, `+ - * /` on numbers, parentheses, and the functions in
+//! [`Function`]. As in Postgres, NULL in an operator makes the result NULL.
+
+use crate::error::{Error, Result};
+use crate::types::{RowMap, Value};
+
+/// A parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    /// A literal value.
+    Literal(Value),
+    /// The value of a column.
+    Column(String),
+    /// Negation of a number.
+    Neg(Box<Expression>),
+    /// A binary operator.
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+    /// A function call.
+    Call(Function, Vec<Expression>),
+}
+
+/// Binary operators, from lowest to highest precedence: `||`, then `+ -`,
+/// then `* /`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// Join two values as text.
+    Concat,
+    Add,
+    Sub,
+    Mul,
+    /// Division. Dividing integers truncates, as in Postgres.
+    Div,
+}
+
+/// Functions an expression can call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    /// The arguments joined as text, skipping NULLs.
+    Concat,
+    /// The first argument that isn't NULL.
+    Coalesce,
+    /// Text in lower case.
+    Lower,
+    /// Text in upper case.
+    Upper,
+    /// Text without leading and trailing whitespace.
+    Trim,
+    /// Number of characters in the text.
+    Length,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "concat" => Some(Function::Concat),
+            "coalesce" => Some(Function::Coalesce),
+            "lower" => Some(Function::Lower),
+            "upper" => Some(Function::Upper),
+            "trim" => Some(Function::Trim),
+            "length" => Some(Function::Length),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Concat => "concat",
+            Function::Coalesce => "coalesce",
+            Function::Lower => "lower",
+            Function::Upper => "upper",
+            Function::Trim => "trim",
+            Function::Length => "length",
+        }
+    }
+
+    /// Whether the function takes exactly one argument.
+    fn is_unary(&self) -> bool {
+        !matches!(self, Function::Concat | Function::Coalesce)
+    }
+}
+
+impl Expression {
+    /// Parse an expression.
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parser = Parser::new(input)?;
+        let expr = parser.parse_expression()?;
+        if parser.current != Token::Eof {
+            return Err(Error::ExpressionError(format!(
+                "unexpected {:?} after the expression",
+                parser.current
+            )));
+        }
+        Ok(expr)
+    }
+
+    /// Columns the expression reads, in order of first appearance.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Expression::Literal(_) => {}
+            Expression::Column(column) => {
+                if !columns.contains(&column.as_str()) {
+                    columns.push(column);
+                }
+            }
+            Expression::Neg(inner) => inner.collect_columns(columns),
+            Expression::Binary(_, left, right) => {
+                left.collect_columns(columns);
+                right.collect_columns(columns);
+            }
+            Expression::Call(_, args) => {
+                for arg in args {
+                    arg.collect_columns(columns);
+                }
+            }
+        }
+    }
+
+    /// Evaluate the expression against a row. Fails with
+    /// [`Error::MissingColumn`] if the row lacks a column it reads.
+    pub fn evaluate(&self, row: &RowMap) -> Result<Value> {
+        match self {
+            Expression::Literal(value) => Ok(value.clone()),
+            Expression::Column(column) => row
+                .get(column)
+                .cloned()
+                .ok_or_else(|| Error::MissingColumn(column.clone())),
+            Expression::Neg(inner) => match inner.evaluate(row)? {
+                Value::Null => Ok(Value::Null),
+                Value::Int(i) => i
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or_else(|| Error::ExpressionError("integer out of range".into())),
+                Value::Float(f) => Ok(Value::Float(-f)),
+                other => Err(Error::ExpressionError(format!(
+                    "can't negate {}",
+                    type_name(&other)
+                ))),
+            },
+            Expression::Binary(op, left, right) => {
+                binary(*op, left.evaluate(row)?, right.evaluate(row)?)
+            }
+            Expression::Call(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(row))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(call(*function, args))
+            }
+        }
+    }
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value> {
+    if left.is_null() || right.is_null() {
+        return Ok(Value::Null);
+    }
+    if op == BinaryOp::Concat {
+        return Ok(Value::String(text(&left) + &text(&right)));
+    }
+
+    let out_of_range = || Error::ExpressionError("integer out of range".into());
+    match (&left, &right) {
+        (Value::Int(a), Value::Int(b)) => {
+            let result = match op {
+                BinaryOp::Add => a.checked_add(*b),
+                BinaryOp::Sub => a.checked_sub(*b),
+                BinaryOp::Mul => a.checked_mul(*b),
+                BinaryOp::Div if *b == 0 => {
+                    return Err(Error::ExpressionError("division by zero".into()))
+                }
+                BinaryOp::Div => a.checked_div(*b),
+                BinaryOp::Concat => unreachable!(),
+            };
+            result.map(Value::Int).ok_or_else(out_of_range)
+        }
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let (a, b) = (as_float(&left), as_float(&right));
+            let result = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div if b == 0.0 => {
+                    return Err(Error::ExpressionError("division by zero".into()))
+                }
+                BinaryOp::Div => a / b,
+                BinaryOp::Concat => unreachable!(),
+            };
+            Ok(Value::Float(result))
+        }
+        _ => Err(Error::ExpressionError(format!(
+            "can't apply {} to {} and {}",
+            op_symbol(op),
+            type_name(&left),
+            type_name(&right)
+        ))),
+    }
+}
+
+fn call(function: Function, args: Vec<Value>) -> Value {
+    match function {
+        Function::Concat => {
+            Value::String(args.iter().filter(|arg| !arg.is_null()).map(text).collect())
+        }
+        Function::Coalesce => args
+            .into_iter()
+            .find(|arg| !arg.is_null())
+            .unwrap_or(Value::Null),
+        Function::Lower | Function::Upper | Function::Trim | Function::Length => {
+            let arg = &args[0];
+            if arg.is_null() {
+                return Value::Null;
+            }
+            let s = text(arg);
+            match function {
+                Function::Lower => Value::String(s.to_lowercase()),
+                Function::Upper => Value::String(s.to_uppercase()),
+                Function::Trim => Value::String(s.trim().to_string()),
+                _ => Value::Int(s.chars().count() as i64),
+            }
+        }
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Int(i) => *i as f64,
+        Value::Float(f) => *f,
+        _ => 0.0,
+    }
+}
+
+/// A value as text: strings as they are, JSON for arrays and objects.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(_) | Value::Object(_) => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Int(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::String(_) => "text",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn op_symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Concat => "||",
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+    }
+}
+
+/// Token types for expressions.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    String(String),
+    Int(i64),
+    Float(f64),
+    True,
+    False,
+    Null,
+    Concat,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    LParen,
+    RParen,
+    Comma,
+    Eof,
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn advance(&mut self) {
+        if let Some(c) = self.peek_char() {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn read_while(&mut self, accept: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek_char().is_some_and(&accept) {
+            self.advance();
+        }
+        &self.input[start..self.pos]
+    }
+
+    fn read_number(&mut self) -> Result<Token> {
+        let digits = self.read_while(|c| c.is_ascii_digit() || c == '.');
+        let invalid = || Error::ExpressionError(format!("invalid number '{}'", digits));
+        if digits.contains('.') {
+            digits.parse().map(Token::Float).map_err(|_| invalid())
+        } else {
+            digits.parse().map(Token::Int).map_err(|_| invalid())
+        }
+    }
+
+    /// A string in single quotes, where `''` is a quote.
+    fn read_string(&mut self) -> Result<String> {
+        self.advance(); // skip opening quote
+        let mut s = String::new();
+        loop {
+            match self.peek_char() {
+                None => return Err(Error::ExpressionError("unterminated string".into())),
+                Some('\'') => {
+                    self.advance();
+                    if self.peek_char() != Some('\'') {
+                        return Ok(s);
+                    }
+                    s.push('\'');
+                    self.advance();
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token> {
+        self.read_while(char::is_whitespace);
+
+        let Some(c) = self.peek_char() else {
+            return Ok(Token::Eof);
+        };
+
+        let single = match c {
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            ',' => Some(Token::Comma),
+            '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            '*' => Some(Token::Star),
+            '/' => Some(Token::Slash),
+            _ => None,
+        };
+        if let Some(token) = single {
+            self.advance();
+            return Ok(token);
+        }
+
+        match c {
+            '|' => {
+                self.advance();
+                if self.peek_char() != Some('|') {
+                    return Err(Error::ExpressionError("expected '||'".into()));
+                }
+                self.advance();
+                Ok(Token::Concat)
+            }
+            '\'' => self.read_string().map(Token::String),
+            c if c.is_ascii_digit() => self.read_number(),
+            c if c.is_alphabetic() || c == '_' => {
+                let ident = self.read_while(|c| c.is_alphanumeric() || c == '_');
+                Ok(match ident.to_uppercase().as_str() {
+                    "TRUE" => Token::True,
+                    "FALSE" => Token::False,
+                    "NULL" => Token::Null,
+                    _ => Token::Ident(ident.to_string()),
+                })
+            }
+            c => Err(Error::ExpressionError(format!(
+                "unexpected character '{}'",
+                c
+            ))),
+        }
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    current: Token,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Self> {
+        let mut lexer = Lexer::new(input);
+        let current = lexer.next_token()?;
+        Ok(Self { lexer, current })
+    }
+
+    fn advance(&mut self) -> Result<()> {
+        self.current = self.lexer.next_token()?;
+        Ok(())
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> Result<()> {
+        if self.current != token {
+            return Err(Error::ExpressionError(format!(
+                "expected {}, got {:?}",
+                what, self.current
+            )));
+        }
+        self.advance()
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression> {
+        self.parse_concat()
+    }
+
+    fn parse_concat(&mut self) -> Result<Expression> {
+        let mut left = self.parse_additive()?;
+        while self.current == Token::Concat {
+            self.advance()?;
+            let right = self.parse_additive()?;
+            left = Expression::Binary(BinaryOp::Concat, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expression> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = match self.current {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Sub,
+                _ => return Ok(left),
+            };
+            self.advance()?;
+            let right = self.parse_multiplicative()?;
+            left = Expression::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expression> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.current {
+                Token::Star => BinaryOp::Mul,
+                Token::Slash => BinaryOp::Div,
+                _ => return Ok(left),
+            };
+            self.advance()?;
+            let right = self.parse_unary()?;
+            left = Expression::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression> {
+        if self.current == Token::Minus {
+            self.advance()?;
+            let inner = self.parse_unary()?;
+            return Ok(Expression::Neg(Box::new(inner)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression> {
+        let literal = match &self.current {
+            Token::Null => Some(Value::Null),
+            Token::True => Some(Value::Bool(true)),
+            Token::False => Some(Value::Bool(false)),
+            Token::Int(i) => Some(Value::Int(*i)),
+            Token::Float(f) => Some(Value::Float(*f)),
+            Token::String(s) => Some(Value::String(s.clone())),
+            _ => None,
+        };
+        if let Some(value) = literal {
+            self.advance()?;
+            return Ok(Expression::Literal(value));
+        }
+
+        match &self.current {
+            Token::LParen => {
+                self.advance()?;
+                let expr = self.parse_expression()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(expr)
+            }
+            Token::Ident(name) => {
+                let name = name.clone();
+                self.advance()?;
+                if self.current == Token::LParen {
+                    self.parse_call(&name)
+                } else {
+                    Ok(Expression::Column(name))
+                }
+            }
+            _ => Err(Error::ExpressionError(format!(
+                "unexpected token: {:?}",
+                self.current
+            ))),
+        }
+    }
+
+    fn parse_call(&mut self, name: &str) -> Result<Expression> {
+        let function = Function::from_name(name)
+            .ok_or_else(|| Error::ExpressionError(format!("unknown function '{}'", name)))?;
+        self.advance()?; // skip '('
+
+        let mut args = vec![self.parse_expression()?];
+        while self.current == Token::Comma {
+            self.advance()?;
+            args.push(self.parse_expression()?);
+        }
+        self.expect(Token::RParen, "')' after arguments")?;
+
+        if function.is_unary() && args.len() != 1 {
+            return Err(Error::ExpressionError(format!(
+                "{}() takes one argument, got {}",
+                function.name(),
+                args.len()
+            )));
+        }
+        Ok(Expression::Call(function, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(pairs: &[(&str, Value)]) -> RowMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    fn eval(input: &str, row: &RowMap) -> Result<Value> {
+        Expression::parse(input)?.evaluate(row)
+    }
+
+    #[test]
+    fn test_expression_text() {
+        let r = row(&[
+            ("first_name", Value::String("Ada".into())),
+            ("last_name", Value::String("Lovelace".into())),
+            ("nickname", Value::Null),
+        ]);
+
+        assert_eq!(
+            eval("concat(first_name, ' ', last_name)", &r).unwrap(),
+            Value::String("Ada Lovelace".into())
+        );
+        assert_eq!(
+            eval("upper(last_name) || ', ' || first_name", &r).unwrap(),
+            Value::String("LOVELACE, Ada".into())
+        );
+        assert_eq!(
+            eval("coalesce(nickname, first_name)", &r).unwrap(),
+            Value::String("Ada".into())
+        );
+        assert_eq!(eval("length(first_name)", &r).unwrap(), Value::Int(3));
+        assert_eq!(
+            eval("'it''s ' || lower(trim('  ADA  '))", &r).unwrap(),
+            Value::String("it's ada".into())
+        );
+
+        // NULL skips in concat() but propagates through ||
+        assert_eq!(
+            eval("concat(nickname, first_name)", &r).unwrap(),
+            Value::String("Ada".into())
+        );
+        assert_eq!(eval("nickname || first_name", &r).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_expression_arithmetic() {
+        let r = row(&[
+            ("price", Value::Int(1999)),
+            ("quantity", Value::Int(3)),
+            ("rate", Value::Float(0.5)),
+        ]);
+
+        assert_eq!(eval("price * quantity", &r).unwrap(), Value::Int(5997));
+        assert_eq!(eval("price / 100", &r).unwrap(), Value::Int(19));
+        assert_eq!(eval("price * rate", &r).unwrap(), Value::Float(999.5));
+        assert_eq!(eval("(1 + 2) * -quantity", &r).unwrap(), Value::Int(-9));
+        assert_eq!(eval("1 + 2 * 3", &r).unwrap(), Value::Int(7));
+        assert_eq!(eval("price + null", &r).unwrap(), Value::Null);
+
+        assert!(matches!(
+            eval("price / 0", &r),
+            Err(Error::ExpressionError(_))
+        ));
+        assert!(matches!(
+            eval("price + 'a'", &r),
+            Err(Error::ExpressionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_expression_columns() {
+        let expr = Expression::parse("concat(a, b) || a || coalesce(c, 'x')").unwrap();
+        assert_eq!(expr.columns(), vec!["a", "b", "c"]);
+
+        let err = expr.evaluate(&row(&[("a", Value::Int(1))])).unwrap_err();
+        assert!(matches!(err, Error::MissingColumn(column) if column == "b"));
+    }
+
+    #[test]
+    fn test_expression_parse_errors() {
+        for input in [
+            "",
+            "concat(a",
+            "a b",
+            "nope(a)",
+            "lower(a, b)",
+            "'open",
+            "a | b",
+            "a ; b",
+        ] {
+            assert!(Expression::parse(input).is_err(), "{}", input);
+        }
+    }
+}
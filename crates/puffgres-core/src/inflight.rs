@@ -0,0 +1,183 @@
+//! Bookkeeping for writes in flight to turbopuffer.
+//!
+//! Writes to one namespace can overlap as long as they touch different
+//! documents: two writes of the same document in flight at once could land in
+//! either order, leaving the older version. A source position is safe to
+//! acknowledge once every write holding a change at or before it has landed,
+//! so [`InFlightWrites::oldest_lsn`] bounds acknowledgments.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::action::DocumentId;
+use crate::batcher::WriteRequest;
+
+/// Identifies a write recorded with [`InFlightWrites::start`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WriteTicket(u64);
+
+#[derive(Debug)]
+struct InFlightWrite {
+    namespace: String,
+    lsn: u64,
+    ids: HashSet<DocumentId>,
+    clear: bool,
+}
+
+/// The writes sent but not yet landed, with a cap per namespace.
+#[derive(Debug)]
+pub struct InFlightWrites {
+    max_per_namespace: usize,
+    next_ticket: u64,
+    writes: HashMap<WriteTicket, InFlightWrite>,
+}
+
+impl InFlightWrites {
+    /// Allow up to `max_per_namespace` writes in flight to each namespace, at
+    /// least one.
+    pub fn new(max_per_namespace: usize) -> Self {
+        Self {
+            max_per_namespace: max_per_namespace.max(1),
+            next_ticket: 0,
+            writes: HashMap::new(),
+        }
+    }
+
+    /// Whether `request` can be sent now: its namespace is under the cap and
+    /// no write in flight to it touches the same documents. Clearing a
+    /// namespace waits for every write to it, and every write waits for a
+    /// clear.
+    pub fn can_start(&self, request: &WriteRequest) -> bool {
+        let mut in_namespace = 0;
+        for write in self
+            .writes
+            .values()
+            .filter(|w| w.namespace == request.namespace)
+        {
+            if write.clear || request.clear || request_ids(request).any(|id| write.ids.contains(id))
+            {
+                return false;
+            }
+            in_namespace += 1;
+        }
+        in_namespace < self.max_per_namespace
+    }
+
+    /// Record that `request` was sent.
+    pub fn start(&mut self, request: &WriteRequest) -> WriteTicket {
+        let ticket = WriteTicket(self.next_ticket);
+        self.next_ticket += 1;
+        self.writes.insert(
+            ticket,
+            InFlightWrite {
+                namespace: request.namespace.clone(),
+                lsn: request.lsn,
+                ids: request_ids(request).cloned().collect(),
+                clear: request.clear,
+            },
+        );
+        ticket
+    }
+
+    /// Record that a write landed or failed. Returns its LSN.
+    pub fn finish(&mut self, ticket: WriteTicket) -> Option<u64> {
+        self.writes.remove(&ticket).map(|w| w.lsn)
+    }
+
+    /// The LSN of the earliest change in flight. Every change before it has
+    /// been written.
+    pub fn oldest_lsn(&self) -> Option<u64> {
+        self.writes.values().map(|w| w.lsn).min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+/// Ids of the documents a request writes.
+fn request_ids(request: &WriteRequest) -> impl Iterator<Item = &DocumentId> {
+    request
+        .upserts
+        .iter()
+        .map(|doc| &doc.id)
+        .chain(request.patches.iter().map(|patch| &patch.id))
+        .chain(request.deletes.iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(namespace: &str, ids: &[u64], lsn: u64) -> WriteRequest {
+        WriteRequest {
+            namespace: namespace.to_string(),
+            upserts: Vec::new(),
+            patches: Vec::new(),
+            deletes: ids.iter().map(|&id| DocumentId::Uint(id)).collect(),
+            clear: false,
+            lsn,
+            distance_metric: None,
+        }
+    }
+
+    #[test]
+    fn test_writes_of_one_document_wait() {
+        let mut writes = InFlightWrites::new(2);
+        let first = writes.start(&request("users", &[1, 2], 100));
+
+        assert!(!writes.can_start(&request("users", &[2, 3], 200)));
+        assert!(writes.can_start(&request("users", &[3], 200)));
+        // Other namespaces hold other documents
+        assert!(writes.can_start(&request("orders", &[1], 200)));
+
+        assert_eq!(writes.finish(first), Some(100));
+        assert!(writes.can_start(&request("users", &[2, 3], 200)));
+    }
+
+    #[test]
+    fn test_namespace_cap() {
+        let mut writes = InFlightWrites::new(2);
+        writes.start(&request("users", &[1], 100));
+        let second = writes.start(&request("users", &[2], 200));
+        assert!(!writes.can_start(&request("users", &[3], 300)));
+        assert!(writes.can_start(&request("orders", &[3], 300)));
+
+        writes.finish(second);
+        assert!(writes.can_start(&request("users", &[3], 300)));
+        assert_eq!(writes.len(), 1);
+    }
+
+    #[test]
+    fn test_clear_waits_for_the_namespace() {
+        let mut writes = InFlightWrites::new(4);
+        let mut clear = request("users", &[], 200);
+        clear.clear = true;
+
+        let first = writes.start(&request("users", &[1], 100));
+        assert!(!writes.can_start(&clear));
+        writes.finish(first);
+        assert!(writes.can_start(&clear));
+
+        writes.start(&clear);
+        assert!(!writes.can_start(&request("users", &[2], 300)));
+    }
+
+    #[test]
+    fn test_oldest_lsn() {
+        let mut writes = InFlightWrites::new(4);
+        assert_eq!(writes.oldest_lsn(), None);
+        let first = writes.start(&request("users", &[1], 100));
+        let second = writes.start(&request("users", &[2], 200));
+        assert_eq!(writes.oldest_lsn(), Some(100));
+
+        // A later write landing first doesn't move past the earlier one
+        writes.finish(second);
+        assert_eq!(writes.oldest_lsn(), Some(100));
+        writes.finish(first);
+        assert_eq!(writes.oldest_lsn(), None);
+    }
+}
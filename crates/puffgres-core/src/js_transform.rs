@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use std::io::Write;
 use std::process::{Command, Stdio};
 
-use crate::action::{Action, DocumentId};
+use crate::action::{Action, DocumentId, VECTOR_ATTRIBUTE};
 use crate::error::{Error, Result};
 use crate::types::{Operation, RowEvent, Value};
 
@@ -16,6 +16,8 @@ pub struct JsTransformer {
     transform_path: String,
     /// Path to the transform runner script.
     runner_path: Option<String>,
+    /// Migration info passed to the transform as `ctx.migration`.
+    migration: Option<serde_json::Value>,
 }
 
 impl JsTransformer {
@@ -24,9 +26,20 @@ impl JsTransformer {
         Self {
             transform_path: transform_path.into(),
             runner_path: None,
+            migration: None,
         }
     }
 
+    /// Set the migration info exposed to the transform as `ctx.migration`.
+    pub fn with_migration(mut self, name: &str, namespace: &str, table: &str) -> Self {
+        self.migration = Some(serde_json::json!({
+            "name": name,
+            "namespace": namespace,
+            "table": table,
+        }));
+        self
+    }
+
     /// Set the path to the transform runner script.
     pub fn with_runner_path(mut self, path: impl Into<String>) -> Self {
         self.runner_path = Some(path.into());
@@ -49,6 +62,7 @@ impl JsTransformer {
                         Operation::Insert => "insert",
                         Operation::Update => "update",
                         Operation::Delete => "delete",
+                        Operation::Truncate => "truncate",
                     },
                     "schema": event.schema,
                     "table": event.table,
@@ -60,7 +74,8 @@ impl JsTransformer {
                 let id_json = match id {
                     DocumentId::Uint(u) => serde_json::json!(u),
                     DocumentId::Int(i) => serde_json::json!(i),
-                    DocumentId::Uuid(s) | DocumentId::String(s) => serde_json::json!(s),
+                    DocumentId::String(s) => serde_json::json!(s),
+                    DocumentId::Uuid(_) | DocumentId::Bytes(_) => serde_json::json!(id.to_string()),
                 };
 
                 serde_json::json!({
@@ -75,9 +90,13 @@ impl JsTransformer {
         let rows_json_str = serde_json::to_string(&rows_json).unwrap();
 
         // Spawn the process with stdin piped to avoid "Argument list too long" errors
-        let mut child = Command::new("npx")
-            .arg(runner_script)
-            .arg(&self.transform_path)
+        let mut command = Command::new("npx");
+        command.arg(runner_script).arg(&self.transform_path);
+        if let Some(migration) = &self.migration {
+            command.arg(migration.to_string());
+        }
+
+        let mut child = command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -179,11 +198,24 @@ fn parse_action(result: &serde_json::Value, default_id: DocumentId) -> Result<Ac
                 Error::TransformError("Upsert action must have a 'doc' field".into())
             })?;
 
-            let attributes: HashMap<String, Value> = doc
+            let mut attributes: HashMap<String, Value> = doc
                 .iter()
                 .map(|(k, v)| (k.clone(), json_to_value(v)))
                 .collect();
 
+            // The vector goes next to `doc`; a `vector` attribute inside
+            // `doc` is accepted too, as older transforms put it there
+            let vector = match obj.get(VECTOR_ATTRIBUTE) {
+                Some(v) => Some(parse_vector(v)?),
+                None => match doc.get(VECTOR_ATTRIBUTE) {
+                    Some(v) => {
+                        attributes.remove(VECTOR_ATTRIBUTE);
+                        Some(parse_vector(v)?)
+                    }
+                    None => None,
+                },
+            };
+
             // Parse distance_metric if present
             let distance_metric = obj
                 .get("distance_metric")
@@ -194,11 +226,26 @@ fn parse_action(result: &serde_json::Value, default_id: DocumentId) -> Result<Ac
                     _ => None,
                 });
 
-            if let Some(metric) = distance_metric {
-                Ok(Action::upsert_with_metric(id, attributes, metric))
+            let action = if let Some(metric) = distance_metric {
+                Action::upsert_with_metric(id, attributes, metric)
             } else {
-                Ok(Action::upsert(id, attributes))
-            }
+                Action::upsert(id, attributes)
+            };
+            Ok(match vector {
+                Some(vector) => action.with_vector(vector),
+                None => action,
+            })
+        }
+        "patch" => {
+            let id = parse_id(obj.get("id"), default_id)?;
+            let doc = obj.get("doc").and_then(|v| v.as_object()).ok_or_else(|| {
+                Error::TransformError("Patch action must have a 'doc' field".into())
+            })?;
+            let attributes = doc
+                .iter()
+                .map(|(k, v)| (k.clone(), json_to_value(v)))
+                .collect();
+            Ok(Action::patch(id, attributes))
         }
         "delete" => {
             let id = parse_id(obj.get("id"), default_id)?;
@@ -212,6 +259,21 @@ fn parse_action(result: &serde_json::Value, default_id: DocumentId) -> Result<Ac
     }
 }
 
+fn parse_vector(value: &serde_json::Value) -> Result<Vec<f32>> {
+    let values = value
+        .as_array()
+        .ok_or_else(|| Error::TransformError("'vector' must be an array of numbers".into()))?;
+
+    values
+        .iter()
+        .map(|v| {
+            v.as_f64().map(|f| f as f32).ok_or_else(|| {
+                Error::TransformError(format!("'vector' must contain only numbers, got {}", v))
+            })
+        })
+        .collect()
+}
+
 fn parse_id(id_value: Option<&serde_json::Value>, default: DocumentId) -> Result<DocumentId> {
     match id_value {
         Some(serde_json::Value::Number(n)) => {
@@ -223,14 +285,14 @@ fn parse_id(id_value: Option<&serde_json::Value>, default: DocumentId) -> Result
                 Ok(default)
             }
         }
-        Some(serde_json::Value::String(s)) => {
-            // Try to detect if it's a UUID
-            if s.len() == 36 && s.contains('-') {
-                Ok(DocumentId::Uuid(s.clone()))
-            } else {
-                Ok(DocumentId::String(s.clone()))
-            }
+        // Read like the row's own ID, so a UUID or bytes in capitals are
+        // written the one way they always are
+        Some(serde_json::Value::String(s)) => Ok(match default {
+            DocumentId::Uuid(_) => DocumentId::parse_uuid(s),
+            DocumentId::Bytes(_) => DocumentId::parse_hex(s),
+            _ => None,
         }
+        .unwrap_or_else(|| DocumentId::from_text(s.clone()))),
         _ => Ok(default),
     }
 }
@@ -315,6 +377,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_id_follows_the_row_id() {
+        let text = "6f1c1b8e-2a3b-4c5d-9e0f-112233445566";
+        let uuid = DocumentId::parse_uuid(text).unwrap();
+        let json = serde_json::json!(text.to_uppercase());
+        assert_eq!(parse_id(Some(&json), uuid.clone()).unwrap(), uuid);
+        assert_eq!(
+            parse_id(Some(&json), DocumentId::Uint(1)).unwrap(),
+            DocumentId::String(text.to_uppercase())
+        );
+
+        let bytes = DocumentId::Bytes(vec![0xab]);
+        let json = serde_json::json!("AB");
+        assert_eq!(parse_id(Some(&json), bytes.clone()).unwrap(), bytes);
+        let json = serde_json::json!("not hex");
+        assert_eq!(
+            parse_id(Some(&json), bytes).unwrap(),
+            DocumentId::String("not hex".into())
+        );
+    }
+
     #[test]
     fn test_parse_action_upsert() {
         let json = serde_json::json!({
@@ -335,4 +418,68 @@ mod tests {
             _ => panic!("Expected upsert"),
         }
     }
+
+    #[test]
+    fn test_parse_action_patch() {
+        let json = serde_json::json!({
+            "type": "patch",
+            "doc": { "views": 10 }
+        });
+        let action = parse_action(&json, DocumentId::Uint(1)).unwrap();
+        match action {
+            Action::Patch { id, attributes } => {
+                assert_eq!(id, DocumentId::Uint(1));
+                assert_eq!(attributes.get("views"), Some(&Value::Int(10)));
+            }
+            _ => panic!("Expected patch"),
+        }
+
+        let json = serde_json::json!({ "type": "patch", "id": 2 });
+        assert!(parse_action(&json, DocumentId::Uint(1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_action_upsert_with_vector() {
+        let json = serde_json::json!({
+            "type": "upsert",
+            "doc": { "title": "Test" },
+            "vector": [0.1, 0.2, 0.3],
+            "distance_metric": "cosine_distance"
+        });
+        let action = parse_action(&json, DocumentId::Uint(1)).unwrap();
+        assert_eq!(action.vector_dims(), Some(3));
+
+        // A vector inside doc is moved out of the attributes
+        let json = serde_json::json!({
+            "type": "upsert",
+            "doc": { "title": "Test", "vector": [1, 2] }
+        });
+        match parse_action(&json, DocumentId::Uint(1)).unwrap() {
+            Action::Upsert { doc, vector, .. } => {
+                assert_eq!(vector, Some(vec![1.0, 2.0]));
+                assert!(!doc.contains_key(VECTOR_ATTRIBUTE));
+                assert!(doc.contains_key("title"));
+            }
+            _ => panic!("Expected upsert"),
+        }
+
+        let json = serde_json::json!({
+            "type": "upsert",
+            "doc": {},
+            "vector": [0.1, "oops"]
+        });
+        assert!(parse_action(&json, DocumentId::Uint(1)).is_err());
+    }
+
+    #[test]
+    fn test_with_migration() {
+        let transformer = JsTransformer::new("./transforms/posts.ts");
+        assert!(transformer.migration.is_none());
+
+        let transformer = transformer.with_migration("posts", "posts_ns", "public.posts");
+        let migration = transformer.migration.unwrap();
+        assert_eq!(migration["name"], "posts");
+        assert_eq!(migration["namespace"], "posts_ns");
+        assert_eq!(migration["table"], "public.posts");
+    }
 }
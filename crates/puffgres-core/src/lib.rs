@@ -1,23 +1,51 @@
 pub mod action;
 pub mod batcher;
+pub mod coerce;
+pub mod composite_id;
+pub mod content_hash;
+pub mod doc_size;
+pub mod document;
+pub mod embed;
 pub mod error;
+pub mod expr;
+pub mod inflight;
 pub mod js_transform;
 pub mod mapping;
+pub mod namespace;
+pub mod obfuscate;
 pub mod predicate;
 pub mod router;
+pub mod source;
 pub mod transform;
 pub mod types;
+pub mod vector;
 
-pub use action::{Action, Document, DocumentId, ErrorKind};
+pub use action::{
+    Action, Document, DocumentId, ErrorKind, BACKFILL_ATTRIBUTE, DOC_HASH_ATTRIBUTE,
+    EVENT_TIME_ATTRIBUTE, RUN_ID_ATTRIBUTE, SOURCE_LSN_ATTRIBUTE, VECTOR_ATTRIBUTE,
+};
 pub use rs_puff::DistanceMetric;
-pub use batcher::{Batch, Batcher, UpsertDoc, WriteRequest};
+pub use batcher::{Batch, Batcher, PatchDoc, UpsertDoc, WriteRequest};
+pub use coerce::ColumnType;
+pub use composite_id::{CompositeId, IdStrategy};
+pub use content_hash::DocHashCache;
+pub use doc_size::SizeStats;
+pub use document::DocumentValidator;
+pub use embed::EmbedConfig;
 pub use error::{Error, Result};
+pub use expr::Expression;
+pub use inflight::{InFlightWrites, WriteTicket};
 pub use js_transform::JsTransformer;
 pub use mapping::{
-    BatchConfig, IdConfig, Mapping, MappingBuilder, MembershipConfig, Source, TransformConfig,
-    TransformType, VersioningMode,
+    shadow_name, BatchConfig, BatchOverrides, DocumentLimits, ErrorPolicy, FlattenConfig, IdConfig,
+    Mapping, MappingBuilder, MembershipConfig, OversizeAction, Sink, Source, TransformConfig,
+    TransformErrorAction, TransformType, TruncateAction, VectorColumn, VersioningMode,
 };
-pub use predicate::{Literal, Predicate};
+pub use namespace::NamespaceTemplate;
+pub use obfuscate::IdObfuscator;
+pub use predicate::{Literal, Predicate, SqlType};
 pub use router::{RoutedEvent, Router};
+pub use source::{SourceAdapter, SourceBatch};
 pub use transform::{extract_id, FnTransformer, IdType, IdentityTransformer, Transformer};
 pub use types::{Operation, RowEvent, RowMap, Value};
+pub use vector::VectorDimensions;
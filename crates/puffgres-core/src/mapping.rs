@@ -1,5 +1,14 @@
-use crate::predicate::Predicate;
+use std::collections::{HashMap, HashSet};
+
+use crate::action::{BACKFILL_ATTRIBUTE, SOURCE_LSN_ATTRIBUTE};
+use crate::coerce::ColumnType;
+use crate::composite_id::{CompositeId, IdStrategy};
+use crate::embed::EmbedConfig;
+use crate::expr::Expression;
+use crate::namespace::NamespaceTemplate;
+use crate::predicate::{Predicate, SqlType};
 use crate::transform::IdType;
+use crate::types::{Operation, RowEvent, RowMap};
 
 /// Configuration for a mapping from Postgres to turbopuffer.
 #[derive(Debug, Clone)]
@@ -8,7 +17,8 @@ pub struct Mapping {
     pub name: String,
     /// Version number (monotonically increasing).
     pub version: u32,
-    /// Target turbopuffer namespace.
+    /// Target turbopuffer namespace. May interpolate row columns, e.g.
+    /// `posts_{tenant_id}`; see [`NamespaceTemplate`].
     pub namespace: String,
     /// Source relation.
     pub source: Source,
@@ -16,14 +26,113 @@ pub struct Mapping {
     pub id: IdConfig,
     /// Columns to extract from the row.
     pub columns: Vec<String>,
+    /// Attribute renames applied by the identity transform (source column -> attribute).
+    pub renames: HashMap<String, String>,
+    /// Types forced on columns by the identity transform (source column -> type).
+    pub types: HashMap<String, ColumnType>,
+    /// JSON columns expanded into top-level attributes by the identity transform.
+    pub flatten: FlattenConfig,
+    /// Attributes computed from the row by the identity transform (attribute -> expression).
+    pub computed: HashMap<String, Expression>,
+    /// Column written as the document vector by the identity transform.
+    pub vector: Option<VectorColumn>,
+    /// Attributes embedded into the document vector before writing.
+    pub embed: Option<EmbedConfig>,
     /// Membership predicate (determines which rows belong).
     pub membership: MembershipConfig,
+    /// Compile the membership predicate into the publication's row filter
+    /// (Postgres 15+), so rows outside the mapping are never streamed.
+    pub row_filter: bool,
     /// Batching configuration.
     pub batching: BatchConfig,
+    /// The mapping's own batch limits, over the runner's defaults.
+    pub batch: BatchOverrides,
     /// Versioning mode for anti-regression.
     pub versioning: VersioningMode,
     /// Transform configuration (optional).
     pub transform: Option<TransformConfig>,
+    /// Whether to write the source commit time to `__event_time`.
+    pub event_time: bool,
+    /// Whether to write the id of the process that wrote each document to `__run_id`.
+    pub run_id: bool,
+    /// Attribute the LSN of the change behind each write goes to, `None` to
+    /// leave it out.
+    pub source_lsn_attribute: Option<String>,
+    /// Attribute set on documents written by a backfill, `None` to leave it
+    /// out.
+    pub backfill_attribute: Option<String>,
+    /// What to do with rows that can't be turned into documents.
+    pub errors: ErrorPolicy,
+    /// Size limit on transformed documents.
+    pub limits: DocumentLimits,
+    /// What a TRUNCATE of the source table does to the namespace.
+    pub on_truncate: TruncateAction,
+    /// Replication group with its own slot and publication, so a slow or
+    /// paused mapping doesn't hold back the others. `None` shares the
+    /// default slot.
+    pub replication_group: Option<String>,
+    /// Other namespaces every write is copied to.
+    pub sinks: Vec<Sink>,
+}
+
+/// A column the identity transform writes as the document's vector instead
+/// of as an attribute, such as a pgvector `vector` column.
+#[derive(Debug, Clone)]
+pub struct VectorColumn {
+    /// Source column holding the embedding.
+    pub column: String,
+    /// Distance metric sent with the vector, if any.
+    pub distance_metric: Option<rs_puff::DistanceMetric>,
+}
+
+/// JSON column flattening for the identity transform.
+///
+/// Nested keys become top-level attributes joined with `separator`, so with
+/// the defaults `metadata = {"author": "ann"}` becomes `metadata_author`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlattenConfig {
+    /// JSON columns to flatten.
+    pub columns: Vec<String>,
+    /// Levels of nesting to expand; deeper objects are kept as values.
+    pub max_depth: usize,
+    /// Separator between path segments in attribute names.
+    pub separator: String,
+    /// Dotted paths to keep (e.g. `metadata.author`). Empty keeps everything.
+    pub allow: Vec<String>,
+}
+
+impl Default for FlattenConfig {
+    fn default() -> Self {
+        Self {
+            columns: vec![],
+            max_depth: 1,
+            separator: "_".into(),
+            allow: vec![],
+        }
+    }
+}
+
+impl FlattenConfig {
+    /// Whether the column should be flattened.
+    pub fn applies_to(&self, column: &str) -> bool {
+        self.columns.iter().any(|c| c == column)
+    }
+
+    /// Whether a dotted path passes the allowlist.
+    ///
+    /// A path is kept if it is allowed itself, if it is inside an allowed path,
+    /// or if it leads to one (so intermediate objects are still expanded).
+    pub fn allows(&self, path: &str) -> bool {
+        self.allow.is_empty()
+            || self.allow.iter().any(|allowed| {
+                path == allowed || is_path_prefix(allowed, path) || is_path_prefix(path, allowed)
+            })
+    }
+}
+
+/// Whether `prefix` is a proper ancestor of `path` (`a.b` of `a.b.c`, not `a.bc`).
+fn is_path_prefix(prefix: &str, path: &str) -> bool {
+    path.len() > prefix.len() && path.starts_with(prefix) && path.as_bytes()[prefix.len()] == b'.'
 }
 
 /// Transform configuration.
@@ -47,11 +156,14 @@ pub enum TransformType {
     Js,
 }
 
-/// Source relation (table or view).
+/// Source relation (table or view), or a query.
 #[derive(Debug, Clone)]
 pub struct Source {
     pub schema: String,
     pub table: String,
+    /// Rows come from this query instead of a table. Empty `schema` and
+    /// `table`; no changes are streamed for it.
+    pub query: Option<String>,
 }
 
 impl Source {
@@ -59,20 +171,79 @@ impl Source {
         Self {
             schema: schema.into(),
             table: table.into(),
+            query: None,
         }
     }
 
+    /// A source that reads the rows of a query.
+    pub fn query(query: impl Into<String>) -> Self {
+        Self {
+            schema: String::new(),
+            table: String::new(),
+            query: Some(query.into()),
+        }
+    }
+
+    /// Whether rows come from a query rather than a table.
+    pub fn is_query(&self) -> bool {
+        self.query.is_some()
+    }
+
     /// Check if this source matches a row event.
     pub fn matches(&self, schema: &str, table: &str) -> bool {
-        self.schema == schema && self.table == table
+        !self.is_query() && self.schema == schema && self.table == table
+    }
+
+    /// `schema.table`, or `query` for a query source.
+    pub fn describe(&self) -> String {
+        if self.is_query() {
+            "query".to_string()
+        } else {
+            format!("{}.{}", self.schema, self.table)
+        }
     }
 }
 
 /// ID column configuration.
 #[derive(Debug, Clone)]
 pub struct IdConfig {
+    /// The ID column. Empty for a composite ID.
     pub column: String,
     pub id_type: IdType,
+    /// Set when the ID is built from several columns.
+    pub composite: Option<CompositeId>,
+}
+
+impl IdConfig {
+    pub fn new(column: impl Into<String>, id_type: IdType) -> Self {
+        Self {
+            column: column.into(),
+            id_type,
+            composite: None,
+        }
+    }
+
+    /// An ID built from several columns. Concatenated IDs are strings and
+    /// hashed ones UUIDs.
+    pub fn composite(columns: Vec<String>, strategy: IdStrategy) -> Self {
+        let id_type = match strategy {
+            IdStrategy::Concat { .. } => IdType::String,
+            IdStrategy::Uuid5 => IdType::Uuid,
+        };
+        Self {
+            column: String::new(),
+            id_type,
+            composite: Some(CompositeId { columns, strategy }),
+        }
+    }
+
+    /// The columns the ID is read from.
+    pub fn columns(&self) -> &[String] {
+        match &self.composite {
+            Some(composite) => &composite.columns,
+            None => std::slice::from_ref(&self.column),
+        }
+    }
 }
 
 /// Membership configuration.
@@ -103,6 +274,9 @@ pub struct BatchConfig {
     pub max_bytes: usize,
     /// Flush interval in milliseconds.
     pub flush_interval_ms: u64,
+    /// Longest a batch waits for more actions before it's written. `None`
+    /// leaves flushing to the caller.
+    pub max_wait_ms: Option<u64>,
 }
 
 impl Default for BatchConfig {
@@ -111,6 +285,7 @@ impl Default for BatchConfig {
             max_rows: 1000,
             max_bytes: 4 * 1024 * 1024, // 4MB
             flush_interval_ms: 100,
+            max_wait_ms: None,
         }
     }
 }
@@ -125,6 +300,29 @@ impl BatchConfig {
     }
 }
 
+/// Batch limits a mapping sets for itself; anything unset keeps the
+/// runner's default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchOverrides {
+    pub max_rows: Option<usize>,
+    pub max_bytes: Option<usize>,
+    /// Hold batches across transactions for up to this long, instead of
+    /// writing them at the end of every transaction.
+    pub max_wait_ms: Option<u64>,
+}
+
+impl BatchOverrides {
+    /// `defaults` with these overrides applied.
+    pub fn apply(&self, defaults: BatchConfig) -> BatchConfig {
+        BatchConfig {
+            max_rows: self.max_rows.unwrap_or(defaults.max_rows),
+            max_bytes: self.max_bytes.unwrap_or(defaults.max_bytes),
+            max_wait_ms: self.max_wait_ms.or(defaults.max_wait_ms),
+            ..defaults
+        }
+    }
+}
+
 /// Versioning mode for anti-regression.
 #[derive(Debug, Clone, Default)]
 pub enum VersioningMode {
@@ -135,6 +333,98 @@ pub enum VersioningMode {
     Column(String),
     /// No versioning (not recommended).
     None,
+    /// Hash each document into `__doc_hash` and skip upserts that wouldn't
+    /// change it.
+    ContentHash,
+}
+
+/// What the runner does with a row that fails ID extraction or its transform.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransformErrorAction {
+    /// Log a warning and move on.
+    #[default]
+    Skip,
+    /// Record the event in the dead letter queue and move on.
+    Dlq,
+    /// Stop the runner without acknowledging the transaction.
+    Halt,
+}
+
+/// Per-mapping error handling.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorPolicy {
+    pub on_transform_error: TransformErrorAction,
+    /// Stop the runner after this many failed rows in a row, even when
+    /// skipping or dead-lettering.
+    pub max_consecutive_errors: Option<u32>,
+}
+
+impl ErrorPolicy {
+    /// Whether `consecutive` failed rows should stop the runner.
+    pub fn should_halt(&self, consecutive: u32) -> bool {
+        self.on_transform_error == TransformErrorAction::Halt
+            || self
+                .max_consecutive_errors
+                .is_some_and(|max| consecutive >= max)
+    }
+}
+
+/// Per-mapping limit on the size of transformed documents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentLimits {
+    /// Largest document allowed, in bytes. `None` means no limit.
+    pub max_bytes: Option<usize>,
+    pub on_oversize: OversizeAction,
+}
+
+/// What to do with a document over the size limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizeAction {
+    /// Shorten its longest string attributes until it fits.
+    Truncate,
+    /// Send the row to the dead letter queue.
+    Dlq,
+    /// Stop with an error.
+    #[default]
+    Fail,
+}
+
+/// What to do when the source table is truncated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncateAction {
+    /// Log and skip it; the truncated rows stay in turbopuffer.
+    #[default]
+    Ignore,
+    /// Delete every document in the namespace.
+    Clear,
+    /// Stop with an error.
+    Error,
+}
+
+/// A secondary namespace a mapping's writes are copied to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sink {
+    pub name: String,
+    /// Namespace name, where `{namespace}` stands for the primary namespace.
+    pub namespace: String,
+    /// API key for the sink, which may reference environment variables as
+    /// `${VAR}`. `None` uses the project's key.
+    pub api_key: Option<String>,
+    /// Retries for the sink's writes. `None` uses the runner's setting.
+    pub max_retries: Option<u32>,
+}
+
+impl Sink {
+    /// The sink namespace for a write to `primary`.
+    pub fn namespace_for(&self, primary: &str) -> String {
+        self.namespace.replace("{namespace}", primary)
+    }
+}
+
+/// `{base}__v{version}`: the namespace a shadow revision writes to, and the
+/// name its state is kept under until it's promoted.
+pub fn shadow_name(base: &str, version: u32) -> String {
+    format!("{}__v{}", base, version)
 }
 
 impl Mapping {
@@ -142,6 +432,202 @@ impl Mapping {
     pub fn builder(name: impl Into<String>) -> MappingBuilder {
         MappingBuilder::new(name)
     }
+
+    /// Write to the shadow namespaces of this version instead, so it's built
+    /// beside the revision it replaces. Sinks that interpolate `{namespace}`
+    /// follow the primary namespace; the others get their own shadows.
+    pub fn write_to_shadow(&mut self) {
+        self.namespace = shadow_name(&self.namespace, self.version);
+        for sink in &mut self.sinks {
+            if !sink.namespace.contains("{namespace}") {
+                sink.namespace = shadow_name(&sink.namespace, self.version);
+            }
+        }
+    }
+
+    /// Slot or publication name for this mapping's replication group:
+    /// `base` itself, or `{base}_{group}` for a mapping in a group.
+    pub fn replication_name(&self, base: &str) -> String {
+        match &self.replication_group {
+            Some(group) => format!("{}_{}", base, group),
+            None => base.to_string(),
+        }
+    }
+
+    /// Whether deletes are routed on old-row columns other than the ID.
+    ///
+    /// Membership for a delete is evaluated against the old row, and Postgres
+    /// only logs the whole old row with `REPLICA IDENTITY FULL`.
+    ///
+    /// The same goes for columns interpolated into the namespace, which pick
+    /// the namespace a delete is sent to.
+    pub fn needs_full_old_row(&self) -> bool {
+        let membership = match &self.membership {
+            MembershipConfig::Dsl(predicate) => predicate.columns(),
+            MembershipConfig::View | MembershipConfig::All => vec![],
+        };
+        membership
+            .into_iter()
+            .chain(self.namespace_columns())
+            .any(|column| !self.id.columns().iter().any(|id| id == column))
+    }
+
+    /// The publication row filter for this mapping's table, given the type of
+    /// each of its columns: its membership predicate as SQL, when `row_filter`
+    /// is set and the predicate can be written as SQL for those types.
+    pub fn row_filter_sql(&self, column_type: impl Fn(&str) -> Option<SqlType>) -> Option<String> {
+        match &self.membership {
+            MembershipConfig::Dsl(predicate) if self.row_filter => {
+                predicate.to_sql(|col| Some(self.sql_type(col, column_type(col)?)))
+            }
+            _ => None,
+        }
+    }
+
+    /// How a column of type `column_type` compares in SQL against the values
+    /// the mapping sees. Columns it reads as text aren't the type Postgres
+    /// compares them as.
+    fn sql_type(&self, column: &str, column_type: SqlType) -> SqlType {
+        match self.types.get(column) {
+            Some(forced) if forced.reads_text() => SqlType::Other,
+            _ => column_type,
+        }
+    }
+
+    /// Parse the namespace name.
+    pub fn namespace_template(&self) -> crate::Result<NamespaceTemplate> {
+        NamespaceTemplate::parse(&self.namespace)
+    }
+
+    /// Whether rows are spread across namespaces by column values.
+    pub fn is_partitioned(&self) -> bool {
+        !self.namespace_columns().is_empty()
+    }
+
+    /// Columns interpolated into the namespace name.
+    pub fn namespace_columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        let mut rest = self.namespace.as_str();
+        while let Some((_, after)) = rest.split_once('{') {
+            let Some((column, after)) = after.split_once('}') else {
+                break;
+            };
+            columns.push(column);
+            rest = after;
+        }
+        columns
+    }
+
+    /// The namespace a row belongs in.
+    pub fn namespace_for_row(&self, row: &RowMap) -> crate::Result<String> {
+        if !self.is_partitioned() {
+            return Ok(self.namespace.clone());
+        }
+        self.namespace_template()?.render(row)
+    }
+
+    /// The namespace an event is written to: the new row's for inserts and
+    /// updates, the old row's for deletes.
+    pub fn namespace_for(&self, event: &RowEvent) -> crate::Result<String> {
+        if !self.is_partitioned() {
+            return Ok(self.namespace.clone());
+        }
+        match event.row() {
+            Some(row) => self.namespace_for_row(row),
+            None => Err(crate::Error::MissingColumn(
+                self.namespace_columns()[0].to_string(),
+            )),
+        }
+    }
+
+    /// For an update that moved a row to another namespace, the namespace it
+    /// was in before.
+    ///
+    /// Only detectable when the old row carries the namespace columns, which
+    /// takes `REPLICA IDENTITY FULL` unless they are part of the key.
+    pub fn previous_namespace(&self, event: &RowEvent) -> Option<String> {
+        if event.op != Operation::Update || !self.is_partitioned() {
+            return None;
+        }
+        let old = self.namespace_for_row(event.old.as_ref()?).ok()?;
+        let new = self.namespace_for(event).ok()?;
+        (old != new).then_some(old)
+    }
+
+    /// Whether an update moved a row out of the membership predicate, e.g.
+    /// `status` flipping from `'active'` to `'deleted'`, so its document
+    /// must be deleted.
+    ///
+    /// Only detectable when the old row carries the predicate's columns,
+    /// which takes `REPLICA IDENTITY FULL` unless they are part of the key.
+    pub fn left_membership(&self, event: &RowEvent) -> bool {
+        if event.op != Operation::Update || !self.source.matches(&event.schema, &event.table) {
+            return false;
+        }
+        let MembershipConfig::Dsl(predicate) = &self.membership else {
+            return false;
+        };
+        let (Some(old), Some(new)) = (&event.old, &event.new) else {
+            return false;
+        };
+        predicate.columns().iter().all(|c| old.contains_key(*c))
+            && predicate.evaluate(old)
+            && !predicate.evaluate(new)
+    }
+
+    /// Columns whose values can change the mapping's documents or where
+    /// they go. `None` when that's every column: a JS transform sees the
+    /// whole row, and an identity transform without `columns` copies it all.
+    pub fn relevant_columns(&self) -> Option<HashSet<&str>> {
+        let js = self
+            .transform
+            .as_ref()
+            .is_some_and(|t| t.transform_type == TransformType::Js);
+        if js || self.columns.is_empty() {
+            return None;
+        }
+
+        let mut columns: HashSet<&str> = self.columns.iter().map(String::as_str).collect();
+        columns.extend(self.id.columns().iter().map(String::as_str));
+        columns.extend(self.namespace_columns());
+        columns.extend(self.flatten.columns.iter().map(String::as_str));
+        columns.extend(self.computed.values().flat_map(Expression::columns));
+        if let Some(vector) = &self.vector {
+            columns.insert(&vector.column);
+        }
+        if let MembershipConfig::Dsl(predicate) = &self.membership {
+            columns.extend(predicate.columns());
+        }
+        if let VersioningMode::Column(column) = &self.versioning {
+            columns.insert(column);
+        }
+        Some(columns)
+    }
+
+    /// Whether an update left every column the mapping reads as it was, e.g.
+    /// one that only bumped `last_seen_at`, so it can't change the document.
+    ///
+    /// Only detectable when the old row carries those columns, which takes
+    /// `REPLICA IDENTITY FULL` unless they are all part of the key. A column
+    /// missing from the new row is an unchanged TOAST value.
+    pub fn is_noop_update(&self, event: &RowEvent) -> bool {
+        if event.op != Operation::Update || !self.source.matches(&event.schema, &event.table) {
+            return false;
+        }
+        let (Some(old), Some(new)) = (&event.old, &event.new) else {
+            return false;
+        };
+        let Some(columns) = self.relevant_columns() else {
+            return false;
+        };
+        columns
+            .into_iter()
+            .all(|column| match (old.get(column), new.get(column)) {
+                (_, None) => true,
+                (Some(before), Some(after)) => before == after,
+                (None, Some(_)) => false,
+            })
+    }
 }
 
 /// Builder for constructing a Mapping.
@@ -152,10 +638,27 @@ pub struct MappingBuilder {
     source: Option<Source>,
     id: Option<IdConfig>,
     columns: Vec<String>,
+    renames: HashMap<String, String>,
+    types: HashMap<String, ColumnType>,
+    flatten: FlattenConfig,
+    computed: HashMap<String, Expression>,
+    vector: Option<VectorColumn>,
+    embed: Option<EmbedConfig>,
     membership: MembershipConfig,
+    row_filter: bool,
     batching: BatchConfig,
+    batch: BatchOverrides,
     versioning: VersioningMode,
     transform: Option<TransformConfig>,
+    event_time: bool,
+    run_id: bool,
+    source_lsn_attribute: Option<String>,
+    backfill_attribute: Option<String>,
+    errors: ErrorPolicy,
+    limits: DocumentLimits,
+    on_truncate: TruncateAction,
+    replication_group: Option<String>,
+    sinks: Vec<Sink>,
 }
 
 impl MappingBuilder {
@@ -167,10 +670,27 @@ impl MappingBuilder {
             source: None,
             id: None,
             columns: vec![],
+            renames: HashMap::new(),
+            types: HashMap::new(),
+            flatten: FlattenConfig::default(),
+            computed: HashMap::new(),
+            vector: None,
+            embed: None,
             membership: MembershipConfig::All,
+            row_filter: false,
             batching: BatchConfig::default(),
+            batch: BatchOverrides::default(),
             versioning: VersioningMode::default(),
             transform: None,
+            event_time: false,
+            run_id: false,
+            source_lsn_attribute: Some(SOURCE_LSN_ATTRIBUTE.to_string()),
+            backfill_attribute: Some(BACKFILL_ATTRIBUTE.to_string()),
+            errors: ErrorPolicy::default(),
+            limits: DocumentLimits::default(),
+            on_truncate: TruncateAction::default(),
+            replication_group: None,
+            sinks: vec![],
         }
     }
 
@@ -189,11 +709,22 @@ impl MappingBuilder {
         self
     }
 
+    /// Read rows from a query instead of the `source` table.
+    pub fn source_query(mut self, query: Option<String>) -> Self {
+        if let Some(query) = query {
+            self.source = Some(Source::query(query));
+        }
+        self
+    }
+
     pub fn id(mut self, column: impl Into<String>, id_type: IdType) -> Self {
-        self.id = Some(IdConfig {
-            column: column.into(),
-            id_type,
-        });
+        self.id = Some(IdConfig::new(column, id_type));
+        self
+    }
+
+    /// Build the ID from several columns instead of one.
+    pub fn composite_id(mut self, columns: Vec<String>, strategy: IdStrategy) -> Self {
+        self.id = Some(IdConfig::composite(columns, strategy));
         self
     }
 
@@ -202,6 +733,36 @@ impl MappingBuilder {
         self
     }
 
+    pub fn renames(mut self, renames: HashMap<String, String>) -> Self {
+        self.renames = renames;
+        self
+    }
+
+    pub fn types(mut self, types: HashMap<String, ColumnType>) -> Self {
+        self.types = types;
+        self
+    }
+
+    pub fn flatten(mut self, config: FlattenConfig) -> Self {
+        self.flatten = config;
+        self
+    }
+
+    pub fn computed(mut self, computed: HashMap<String, Expression>) -> Self {
+        self.computed = computed;
+        self
+    }
+
+    pub fn vector(mut self, vector: VectorColumn) -> Self {
+        self.vector = Some(vector);
+        self
+    }
+
+    pub fn embed(mut self, embed: EmbedConfig) -> Self {
+        self.embed = Some(embed);
+        self
+    }
+
     pub fn membership(mut self, config: MembershipConfig) -> Self {
         self.membership = config;
         self
@@ -212,11 +773,21 @@ impl MappingBuilder {
         Ok(self)
     }
 
+    pub fn row_filter(mut self, row_filter: bool) -> Self {
+        self.row_filter = row_filter;
+        self
+    }
+
     pub fn batching(mut self, config: BatchConfig) -> Self {
         self.batching = config;
         self
     }
 
+    pub fn batch(mut self, overrides: BatchOverrides) -> Self {
+        self.batch = overrides;
+        self
+    }
+
     pub fn versioning(mut self, mode: VersioningMode) -> Self {
         self.versioning = mode;
         self
@@ -227,10 +798,56 @@ impl MappingBuilder {
         self
     }
 
+    pub fn event_time(mut self, enabled: bool) -> Self {
+        self.event_time = enabled;
+        self
+    }
+
+    pub fn run_id(mut self, enabled: bool) -> Self {
+        self.run_id = enabled;
+        self
+    }
+
+    pub fn source_lsn_attribute(mut self, attribute: Option<String>) -> Self {
+        self.source_lsn_attribute = attribute;
+        self
+    }
+
+    pub fn backfill_attribute(mut self, attribute: Option<String>) -> Self {
+        self.backfill_attribute = attribute;
+        self
+    }
+
+    pub fn errors(mut self, policy: ErrorPolicy) -> Self {
+        self.errors = policy;
+        self
+    }
+
+    pub fn limits(mut self, limits: DocumentLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn on_truncate(mut self, action: TruncateAction) -> Self {
+        self.on_truncate = action;
+        self
+    }
+
+    pub fn replication_group(mut self, group: Option<String>) -> Self {
+        self.replication_group = group;
+        self
+    }
+
+    pub fn sinks(mut self, sinks: Vec<Sink>) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
     pub fn build(self) -> crate::Result<Mapping> {
         let namespace = self
             .namespace
             .ok_or_else(|| crate::Error::MissingColumn("namespace".into()))?;
+        NamespaceTemplate::parse(&namespace)?;
         let source = self
             .source
             .ok_or_else(|| crate::Error::MissingColumn("source".into()))?;
@@ -245,10 +862,27 @@ impl MappingBuilder {
             source,
             id,
             columns: self.columns,
+            renames: self.renames,
+            types: self.types,
+            flatten: self.flatten,
+            computed: self.computed,
+            vector: self.vector,
+            embed: self.embed,
             membership: self.membership,
+            row_filter: self.row_filter,
             batching: self.batching,
+            batch: self.batch,
             versioning: self.versioning,
             transform: self.transform,
+            event_time: self.event_time,
+            run_id: self.run_id,
+            source_lsn_attribute: self.source_lsn_attribute,
+            backfill_attribute: self.backfill_attribute,
+            errors: self.errors,
+            limits: self.limits,
+            on_truncate: self.on_truncate,
+            replication_group: self.replication_group,
+            sinks: self.sinks,
         })
     }
 }
@@ -263,6 +897,30 @@ mod tests {
         assert!(source.matches("public", "users"));
         assert!(!source.matches("public", "posts"));
         assert!(!source.matches("private", "users"));
+
+        let source = Source::query("SELECT id FROM users");
+        assert!(source.is_query());
+        assert!(!source.matches("", ""));
+    }
+
+    #[test]
+    fn test_flatten_allowlist() {
+        let config = FlattenConfig {
+            columns: vec!["metadata".into()],
+            allow: vec!["metadata.author".into()],
+            ..Default::default()
+        };
+
+        assert!(config.applies_to("metadata"));
+        assert!(!config.applies_to("title"));
+
+        assert!(config.allows("metadata.author"));
+        assert!(config.allows("metadata.author.name"));
+        assert!(config.allows("metadata"));
+        assert!(!config.allows("metadata.tags"));
+        assert!(!config.allows("metadata.authors"));
+
+        assert!(FlattenConfig::default().allows("anything.at.all"));
     }
 
     #[test]
@@ -281,6 +939,54 @@ mod tests {
         assert!(mapping.source.matches("public", "users"));
     }
 
+    #[test]
+    fn test_replication_name() {
+        let builder = || {
+            Mapping::builder("invoices")
+                .namespace("invoices")
+                .source("public", "invoices")
+                .id("id", IdType::Uint)
+        };
+
+        let mapping = builder().build().unwrap();
+        assert_eq!(mapping.replication_name("puffgres"), "puffgres");
+
+        let mapping = builder()
+            .replication_group(Some("billing".into()))
+            .build()
+            .unwrap();
+        assert_eq!(mapping.replication_name("puffgres"), "puffgres_billing");
+        assert_eq!(
+            mapping.replication_name("puffgres_pub"),
+            "puffgres_pub_billing"
+        );
+    }
+
+    #[test]
+    fn test_write_to_shadow() {
+        let sink = |namespace: &str| Sink {
+            name: namespace.to_string(),
+            namespace: namespace.to_string(),
+            api_key: None,
+            max_retries: None,
+        };
+        let mut mapping = Mapping::builder("posts")
+            .version(2)
+            .namespace("posts_{tenant_id}")
+            .source("public", "posts")
+            .id("id", IdType::Uint)
+            .sinks(vec![sink("{namespace}_eu"), sink("archive")])
+            .build()
+            .unwrap();
+
+        mapping.write_to_shadow();
+        assert_eq!(mapping.name, "posts");
+        assert_eq!(mapping.namespace, "posts_{tenant_id}__v2");
+        assert_eq!(mapping.namespace_columns(), vec!["tenant_id"]);
+        assert_eq!(mapping.sinks[0].namespace, "{namespace}_eu");
+        assert_eq!(mapping.sinks[1].namespace, "archive__v2");
+    }
+
     #[test]
     fn test_mapping_builder_with_dsl() {
         let mapping = Mapping::builder("active_users")
@@ -293,5 +999,181 @@ mod tests {
             .unwrap();
 
         assert!(matches!(mapping.membership, MembershipConfig::Dsl(_)));
+        let column_type = |col: &str| match col {
+            "status" => Some(SqlType::Text),
+            "deleted_at" => Some(SqlType::Other),
+            _ => None,
+        };
+        assert_eq!(mapping.row_filter_sql(column_type), None);
+
+        let filtered = Mapping {
+            row_filter: true,
+            ..mapping
+        };
+        assert_eq!(
+            filtered.row_filter_sql(column_type).as_deref(),
+            Some("(\"status\" = 'active' AND \"deleted_at\" IS NULL)")
+        );
+
+        // A column read as text compares as its Postgres type in SQL
+        let text_status = Mapping {
+            types: HashMap::from([("status".to_string(), ColumnType::String)]),
+            ..filtered
+        };
+        assert_eq!(text_status.row_filter_sql(column_type), None);
+    }
+
+    #[test]
+    fn test_error_policy_should_halt() {
+        assert!(!ErrorPolicy::default().should_halt(1_000));
+
+        let halt = ErrorPolicy {
+            on_transform_error: TransformErrorAction::Halt,
+            max_consecutive_errors: None,
+        };
+        assert!(halt.should_halt(1));
+
+        let capped = ErrorPolicy {
+            on_transform_error: TransformErrorAction::Dlq,
+            max_consecutive_errors: Some(3),
+        };
+        assert!(!capped.should_halt(2));
+        assert!(capped.should_halt(3));
+    }
+
+    #[test]
+    fn test_needs_full_old_row() {
+        let mapping = |membership: MembershipConfig| {
+            Mapping::builder("users")
+                .namespace("users")
+                .source("public", "users")
+                .id("id", IdType::Uint)
+                .membership(membership)
+                .build()
+                .unwrap()
+        };
+
+        assert!(!mapping(MembershipConfig::All).needs_full_old_row());
+        assert!(!mapping(MembershipConfig::dsl("id != 0").unwrap()).needs_full_old_row());
+        assert!(mapping(MembershipConfig::dsl("status = 'active'").unwrap()).needs_full_old_row());
+
+        let partitioned = Mapping::builder("posts")
+            .namespace("posts_{tenant_id}")
+            .source("public", "posts")
+            .id("id", IdType::Uint)
+            .build()
+            .unwrap();
+        assert!(partitioned.needs_full_old_row());
+    }
+
+    #[test]
+    fn test_noop_update() {
+        use crate::types::Value;
+
+        let mapping = Mapping::builder("users")
+            .namespace("users")
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .columns(vec!["name".into()])
+            .membership_dsl("status = 'active'")
+            .unwrap()
+            .build()
+            .unwrap();
+        let row = |name: &str, status: &str, seen: i64| -> RowMap {
+            [
+                ("id".to_string(), Value::Int(1)),
+                ("name".to_string(), Value::String(name.into())),
+                ("status".to_string(), Value::String(status.into())),
+                ("last_seen_at".to_string(), Value::Int(seen)),
+            ]
+            .into_iter()
+            .collect()
+        };
+        let update = |old: Option<RowMap>, new: RowMap| RowEvent {
+            op: Operation::Update,
+            schema: "public".into(),
+            table: "users".into(),
+            new: Some(new),
+            old,
+            lsn: 100,
+            txid: None,
+            timestamp: None,
+        };
+
+        let touched = update(Some(row("ann", "active", 1)), row("ann", "active", 2));
+        assert!(mapping.is_noop_update(&touched));
+
+        // Synced and predicate columns count
+        let renamed = update(Some(row("ann", "active", 1)), row("bea", "active", 1));
+        assert!(!mapping.is_noop_update(&renamed));
+        let deactivated = update(Some(row("ann", "active", 1)), row("ann", "gone", 1));
+        assert!(!mapping.is_noop_update(&deactivated));
+
+        // Without the old row there's nothing to compare
+        assert!(!mapping.is_noop_update(&update(None, row("ann", "active", 2))));
+
+        // An unchanged TOAST value is left out of the new row
+        let mut toasted = row("ann", "active", 2);
+        toasted.remove("name");
+        assert!(mapping.is_noop_update(&update(Some(row("ann", "active", 1)), toasted)));
+
+        // A JS transform reads the whole row
+        let mut js = mapping.clone();
+        js.transform = Some(TransformConfig {
+            transform_type: TransformType::Js,
+            path: Some("transforms/users.ts".into()),
+            entry: None,
+        });
+        assert!(!js.is_noop_update(&touched));
+    }
+
+    #[test]
+    fn test_partitioned_namespace() {
+        use crate::types::Value;
+
+        let mapping = Mapping::builder("posts")
+            .namespace("posts_{tenant_id}")
+            .source("public", "posts")
+            .id("id", IdType::Uint)
+            .build()
+            .unwrap();
+        assert!(mapping.is_partitioned());
+        assert_eq!(mapping.namespace_columns(), vec!["tenant_id"]);
+
+        let row = |tenant: i64| -> RowMap {
+            [
+                ("id".to_string(), Value::Int(1)),
+                ("tenant_id".to_string(), Value::Int(tenant)),
+            ]
+            .into_iter()
+            .collect()
+        };
+        let mut event = RowEvent {
+            op: Operation::Update,
+            schema: "public".into(),
+            table: "posts".into(),
+            new: Some(row(7)),
+            old: Some(row(3)),
+            lsn: 100,
+            txid: None,
+            timestamp: None,
+        };
+        assert_eq!(mapping.namespace_for(&event).unwrap(), "posts_7");
+        assert_eq!(mapping.previous_namespace(&event).unwrap(), "posts_3");
+
+        event.old = Some(row(7));
+        assert_eq!(mapping.previous_namespace(&event), None);
+
+        // Deletes go to the namespace of the old row
+        event.op = Operation::Delete;
+        event.new = None;
+        assert_eq!(mapping.namespace_for(&event).unwrap(), "posts_7");
+
+        assert!(Mapping::builder("posts")
+            .namespace("posts_{tenant_id")
+            .source("public", "posts")
+            .id("id", IdType::Uint)
+            .build()
+            .is_err());
     }
 }
@@ -0,0 +1,238 @@
+//! Namespace names that interpolate row columns.
+//!
+//! A mapping's namespace may contain `{column}` placeholders, e.g.
+//! `posts_{tenant_id}`, to shard one table into a namespace per value of the
+//! column. Each row is written to the namespace named by its own values.
+
+use crate::error::{Error, Result};
+use crate::types::{RowMap, Value};
+
+/// Longest namespace name turbopuffer accepts.
+const MAX_NAMESPACE_LEN: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Column(String),
+}
+
+/// A parsed namespace name, with or without placeholders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceTemplate {
+    segments: Vec<Segment>,
+}
+
+impl NamespaceTemplate {
+    /// Parse a namespace name. `{` and `}` only appear around a column name.
+    pub fn parse(template: &str) -> Result<Self> {
+        let invalid =
+            |message: &str| Error::InvalidNamespace(format!("'{}': {}", template, message));
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return Err(invalid("unmatched '}'"));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|i| start + i)
+                .ok_or_else(|| invalid("unclosed '{'"))?;
+            let column = &rest[start + 1..end];
+            if column.is_empty() || column.contains('{') {
+                return Err(invalid(
+                    "placeholders must be a column name, like {tenant_id}",
+                ));
+            }
+
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            segments.push(Segment::Column(column.to_string()));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        if segments.is_empty() {
+            return Err(invalid("namespace cannot be empty"));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Whether the name depends on row values.
+    pub fn is_partitioned(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Column(_)))
+    }
+
+    /// Columns interpolated into the name, in order of appearance.
+    pub fn columns(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|s| match s {
+                Segment::Column(column) => Some(column.as_str()),
+                Segment::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    /// The fixed text before the first placeholder. For a name without
+    /// placeholders, the whole name.
+    pub fn prefix(&self) -> &str {
+        match self.segments.first() {
+            Some(Segment::Literal(literal)) => literal,
+            _ => "",
+        }
+    }
+
+    /// Build the namespace name for a row.
+    ///
+    /// Fails if a column is missing or null, or if its value isn't a string,
+    /// integer or boolean made of characters turbopuffer allows in names.
+    /// Values are never rewritten, so two rows can't collide on one name.
+    pub fn render(&self, row: &RowMap) -> Result<String> {
+        let mut name = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => name.push_str(literal),
+                Segment::Column(column) => {
+                    let value = match row.get(column) {
+                        None | Some(Value::Null) => {
+                            return Err(Error::MissingColumn(column.clone()))
+                        }
+                        Some(Value::String(s)) => s.clone(),
+                        Some(Value::Int(i)) => i.to_string(),
+                        Some(Value::Bool(b)) => b.to_string(),
+                        Some(other) => {
+                            return Err(Error::InvalidColumnType {
+                                column: column.clone(),
+                                expected: "string, integer or boolean".into(),
+                                actual: format!("{:?}", other),
+                            })
+                        }
+                    };
+                    if value.is_empty() || !value.chars().all(is_namespace_char) {
+                        return Err(Error::InvalidNamespace(format!(
+                            "column '{}' value '{}' can't be used in a namespace name \
+                             (allowed: A-Z, a-z, 0-9, '-', '_', '.')",
+                            column, value
+                        )));
+                    }
+                    name.push_str(&value);
+                }
+            }
+        }
+
+        if name.len() > MAX_NAMESPACE_LEN {
+            return Err(Error::InvalidNamespace(format!(
+                "'{}' is longer than {} characters",
+                name, MAX_NAMESPACE_LEN
+            )));
+        }
+        Ok(name)
+    }
+
+    /// Whether `name` could have been rendered from this template, e.g. to
+    /// find a partitioned mapping's namespaces in turbopuffer.
+    pub fn matches(&self, name: &str) -> bool {
+        matches_segments(&self.segments, name)
+    }
+}
+
+fn is_namespace_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')
+}
+
+fn matches_segments(segments: &[Segment], name: &str) -> bool {
+    match segments.split_first() {
+        None => name.is_empty(),
+        Some((Segment::Literal(literal), rest)) => name
+            .strip_prefix(literal.as_str())
+            .is_some_and(|name| matches_segments(rest, name)),
+        // A value is at least one allowed character; try every split point
+        Some((Segment::Column(_), rest)) => {
+            let value_len = name.find(|c| !is_namespace_char(c)).unwrap_or(name.len());
+            (1..=value_len).any(|len| matches_segments(rest, &name[len..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(values: &[(&str, Value)]) -> RowMap {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let plain = NamespaceTemplate::parse("posts").unwrap();
+        assert!(!plain.is_partitioned());
+        assert_eq!(plain.prefix(), "posts");
+
+        let template = NamespaceTemplate::parse("posts_{tenant_id}_{region}").unwrap();
+        assert!(template.is_partitioned());
+        assert_eq!(template.columns(), vec!["tenant_id", "region"]);
+        assert_eq!(template.prefix(), "posts_");
+
+        for bad in ["", "posts_{", "posts_{}", "posts}", "posts_{a{b}"] {
+            assert!(NamespaceTemplate::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = NamespaceTemplate::parse("posts_{tenant_id}").unwrap();
+        assert_eq!(
+            template
+                .render(&row(&[("tenant_id", Value::Int(42))]))
+                .unwrap(),
+            "posts_42"
+        );
+        assert_eq!(
+            template
+                .render(&row(&[("tenant_id", Value::String("acme-eu".into()))]))
+                .unwrap(),
+            "posts_acme-eu"
+        );
+
+        assert!(matches!(
+            template.render(&row(&[("tenant_id", Value::Null)])),
+            Err(Error::MissingColumn(_))
+        ));
+        assert!(matches!(
+            template.render(&row(&[("tenant_id", Value::Float(1.5))])),
+            Err(Error::InvalidColumnType { .. })
+        ));
+        assert!(matches!(
+            template.render(&row(&[("tenant_id", Value::String("a/b".into()))])),
+            Err(Error::InvalidNamespace(_))
+        ));
+        assert!(matches!(
+            template.render(&row(&[("tenant_id", Value::String("x".repeat(200)))])),
+            Err(Error::InvalidNamespace(_))
+        ));
+    }
+
+    #[test]
+    fn test_matches() {
+        let template = NamespaceTemplate::parse("posts_{tenant_id}_v2").unwrap();
+        assert!(template.matches("posts_42_v2"));
+        assert!(template.matches("posts_a_v2_v2"));
+        assert!(!template.matches("posts__v2"));
+        assert!(!template.matches("posts_42"));
+        assert!(!template.matches("comments_42_v2"));
+
+        assert!(NamespaceTemplate::parse("posts").unwrap().matches("posts"));
+        assert!(!NamespaceTemplate::parse("posts")
+            .unwrap()
+            .matches("posts_1"));
+    }
+}
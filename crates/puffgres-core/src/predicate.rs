@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 use crate::error::{Error, Result};
 use crate::types::{RowMap, Value};
 
@@ -12,6 +14,22 @@ pub enum Predicate {
     Eq(String, Literal),
     /// Column does not equal a literal value.
     NotEq(String, Literal),
+    /// Column is less than a literal value.
+    Lt(String, Literal),
+    /// Column is less than or equal to a literal value.
+    LtEq(String, Literal),
+    /// Column is greater than a literal value.
+    Gt(String, Literal),
+    /// Column is greater than or equal to a literal value.
+    GtEq(String, Literal),
+    /// Column equals one of a list of literal values. `NOT IN` parses to
+    /// `Not(In(..))`.
+    In(String, Vec<Literal>),
+    /// Column matches a LIKE pattern (`%` any run of characters, `_` any one
+    /// character, `\` escapes the next).
+    Like(String, String),
+    /// Column matches a LIKE pattern, ignoring case.
+    ILike(String, String),
     /// Column is null.
     IsNull(String),
     /// Column is not null.
@@ -47,6 +65,85 @@ impl Literal {
             _ => false,
         }
     }
+
+    /// How a value orders against the literal, or `None` if they can't be
+    /// compared (NULL, or different types). Strings compare byte-wise.
+    fn compare(&self, value: &Value) -> Option<Ordering> {
+        match (value, self) {
+            (Value::Bool(a), Literal::Bool(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Literal::Int(b)) => Some(a.cmp(b)),
+            (Value::Float(a), Literal::Float(b)) => a.partial_cmp(b),
+            (Value::Int(a), Literal::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Literal::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::String(a), Literal::String(b)) => Some(a.as_str().cmp(b.as_str())),
+            _ => None,
+        }
+    }
+
+    /// Render the literal as SQL, or `None` if it has no safe representation.
+    fn to_sql(&self) -> Option<String> {
+        match self {
+            Literal::Null => Some("NULL".to_string()),
+            Literal::Bool(b) => Some(if *b { "TRUE" } else { "FALSE" }.to_string()),
+            Literal::Int(i) => Some(i.to_string()),
+            Literal::Float(f) if f.is_finite() => Some(format!("{:?}", f)),
+            Literal::Float(_) => None,
+            // Backslashes are only literal with standard_conforming_strings on,
+            // so leave those to the Rust evaluator rather than guess.
+            Literal::String(s) if s.contains('\\') || s.contains('\0') => None,
+            Literal::String(s) => Some(format!("'{}'", s.replace('\'', "''"))),
+        }
+    }
+}
+
+/// How a column's Postgres type behaves in the SQL `to_sql` writes. A
+/// comparison is only pushed down when Postgres decides it on the same value,
+/// the same way, as `evaluate` does on the value puffgres decodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlType {
+    /// `bool`, decoded as a bool.
+    Bool,
+    /// `int2`, `int4` or `int8`, decoded as an integer.
+    Integer,
+    /// `text` or `varchar`, decoded as a string.
+    Text,
+    /// Any other type. Postgres coerces literals to these (timestamps,
+    /// numerics, uuids) and floats have NaN and rounding, while `evaluate`
+    /// compares the decoded value as is, so only NULL checks are pushed down.
+    Other,
+}
+
+impl SqlType {
+    /// The kind of a Postgres type, by its name (e.g. `int8`).
+    pub fn from_type_name(name: &str) -> Self {
+        match name {
+            "bool" => SqlType::Bool,
+            "int2" | "int4" | "int8" => SqlType::Integer,
+            "text" | "varchar" => SqlType::Text,
+            _ => SqlType::Other,
+        }
+    }
+
+    /// Whether comparing a column of this type with `lit` in SQL matches the
+    /// same rows as in `evaluate`.
+    fn accepts(self, lit: &Literal) -> bool {
+        matches!(
+            (self, lit),
+            (_, Literal::Null)
+                | (SqlType::Bool, Literal::Bool(_))
+                | (SqlType::Integer, Literal::Int(_))
+                | (SqlType::Text, Literal::String(_))
+        )
+    }
+}
+
+/// Decides whether a column may be compared with a literal (or, for `None`,
+/// checked for NULL) in the SQL being compiled.
+type SqlCheck<'a> = &'a dyn Fn(&str, Option<&Literal>) -> bool;
+
+/// Quote a column name for use in SQL (double quotes).
+fn quote_sql_ident(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
 }
 
 impl Predicate {
@@ -57,6 +154,22 @@ impl Predicate {
             Predicate::False => false,
             Predicate::Eq(col, lit) => row.get(col).map(|v| lit.matches(v)).unwrap_or(false),
             Predicate::NotEq(col, lit) => row.get(col).map(|v| !lit.matches(v)).unwrap_or(true),
+            Predicate::Lt(col, lit) => compare(row, col, lit, Ordering::is_lt),
+            Predicate::LtEq(col, lit) => compare(row, col, lit, Ordering::is_le),
+            Predicate::Gt(col, lit) => compare(row, col, lit, Ordering::is_gt),
+            Predicate::GtEq(col, lit) => compare(row, col, lit, Ordering::is_ge),
+            Predicate::In(col, lits) => row
+                .get(col)
+                .map(|v| lits.iter().any(|lit| lit.matches(v)))
+                .unwrap_or(false),
+            Predicate::Like(col, pattern) => match row.get(col) {
+                Some(Value::String(s)) => like_matches(s, pattern),
+                _ => false,
+            },
+            Predicate::ILike(col, pattern) => match row.get(col) {
+                Some(Value::String(s)) => like_matches(&s.to_lowercase(), &pattern.to_lowercase()),
+                _ => false,
+            },
             Predicate::IsNull(col) => row.get(col).map(|v| v.is_null()).unwrap_or(true),
             Predicate::IsNotNull(col) => row.get(col).map(|v| !v.is_null()).unwrap_or(false),
             Predicate::And(a, b) => a.evaluate(row) && b.evaluate(row),
@@ -65,6 +178,149 @@ impl Predicate {
         }
     }
 
+    /// Columns the predicate reads, sorted and without duplicates.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns.sort_unstable();
+        columns.dedup();
+        columns
+    }
+
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Predicate::True | Predicate::False => {}
+            Predicate::Eq(col, _)
+            | Predicate::NotEq(col, _)
+            | Predicate::Lt(col, _)
+            | Predicate::LtEq(col, _)
+            | Predicate::Gt(col, _)
+            | Predicate::GtEq(col, _)
+            | Predicate::In(col, _)
+            | Predicate::Like(col, _)
+            | Predicate::ILike(col, _)
+            | Predicate::IsNull(col)
+            | Predicate::IsNotNull(col) => columns.push(col),
+            Predicate::And(a, b) | Predicate::Or(a, b) => {
+                a.collect_columns(columns);
+                b.collect_columns(columns);
+            }
+            Predicate::Not(p) => p.collect_columns(columns),
+        }
+    }
+
+    /// Compile the predicate to a SQL boolean expression for a WHERE clause,
+    /// given the type of each column (`None` for a column the relation
+    /// doesn't have).
+    ///
+    /// The SQL selects exactly the rows `evaluate` accepts, including for
+    /// NULLs. Returns `None` if any part of the predicate can't be expressed
+    /// safely for those types, in which case callers should fall back to
+    /// evaluating in Rust.
+    pub fn to_sql(&self, column_type: impl Fn(&str) -> Option<SqlType>) -> Option<String> {
+        self.compile_sql(false, &|col, lit| {
+            column_type(col).is_some_and(|t| lit.map_or(true, |lit| t.accepts(lit)))
+        })
+    }
+
+    /// The SQL `to_sql` writes when each column has the type its literals
+    /// call for. `None` when no column types would do, e.g. for a string
+    /// with a backslash or an `ILIKE`.
+    pub fn sql_form(&self) -> Option<String> {
+        self.compile_sql(false, &|_, _| true)
+    }
+
+    /// `negated` is true under an odd number of NOTs. There, plain `=` would
+    /// yield NULL for NULL columns and `NOT NULL` would drop rows that
+    /// `evaluate` keeps, so comparisons switch to the null-safe forms.
+    fn compile_sql(&self, negated: bool, check: SqlCheck<'_>) -> Option<String> {
+        if let Some((col, lit)) = self.comparison() {
+            if !check(col, lit) {
+                return None;
+            }
+        }
+        Some(match self {
+            Predicate::True => "TRUE".to_string(),
+            Predicate::False => "FALSE".to_string(),
+            Predicate::Eq(col, Literal::Null) => format!("{} IS NULL", quote_sql_ident(col)),
+            Predicate::NotEq(col, Literal::Null) => format!("{} IS NOT NULL", quote_sql_ident(col)),
+            Predicate::Eq(col, lit) if negated => format!(
+                "{} IS NOT DISTINCT FROM {}",
+                quote_sql_ident(col),
+                lit.to_sql()?
+            ),
+            Predicate::Eq(col, lit) => format!("{} = {}", quote_sql_ident(col), lit.to_sql()?),
+            Predicate::NotEq(col, lit) => format!(
+                "{} IS DISTINCT FROM {}",
+                quote_sql_ident(col),
+                lit.to_sql()?
+            ),
+            Predicate::Lt(col, lit) => ordered_sql(col, "<", lit, negated)?,
+            Predicate::LtEq(col, lit) => ordered_sql(col, "<=", lit, negated)?,
+            Predicate::Gt(col, lit) => ordered_sql(col, ">", lit, negated)?,
+            Predicate::GtEq(col, lit) => ordered_sql(col, ">=", lit, negated)?,
+            // SQL's IN never matches NULL and yields NULL for NULL columns, so
+            // those cases go through the null-safe equality forms instead
+            Predicate::In(col, lits) if !negated && !lits.contains(&Literal::Null) => {
+                let lits = lits
+                    .iter()
+                    .map(|lit| lit.to_sql().filter(|_| check(col, Some(lit))))
+                    .collect::<Option<Vec<_>>>()?;
+                format!("{} IN ({})", quote_sql_ident(col), lits.join(", "))
+            }
+            Predicate::Like(col, pattern) => {
+                let pattern = Literal::String(pattern.clone());
+                if !check(col, Some(&pattern)) {
+                    return None;
+                }
+                null_safe(
+                    format!("{} LIKE {}", quote_sql_ident(col), pattern.to_sql()?),
+                    negated,
+                )
+            }
+            // Postgres lowercases by the database's locale and Rust by
+            // Unicode, so ILIKE can disagree outside ASCII
+            Predicate::ILike(..) => return None,
+            Predicate::In(col, lits) => {
+                let eqs = lits
+                    .iter()
+                    .map(|lit| Predicate::Eq(col.clone(), lit.clone()).compile_sql(negated, check))
+                    .collect::<Option<Vec<_>>>()?;
+                format!("({})", eqs.join(" OR "))
+            }
+            Predicate::IsNull(col) => format!("{} IS NULL", quote_sql_ident(col)),
+            Predicate::IsNotNull(col) => format!("{} IS NOT NULL", quote_sql_ident(col)),
+            Predicate::And(a, b) => format!(
+                "({} AND {})",
+                a.compile_sql(negated, check)?,
+                b.compile_sql(negated, check)?
+            ),
+            Predicate::Or(a, b) => format!(
+                "({} OR {})",
+                a.compile_sql(negated, check)?,
+                b.compile_sql(negated, check)?
+            ),
+            Predicate::Not(p) => format!("(NOT {})", p.compile_sql(!negated, check)?),
+        })
+    }
+
+    /// The column a leaf compares and the literal it compares it with, if
+    /// any. `IN` lists and `LIKE` patterns are checked as they're compiled.
+    fn comparison(&self) -> Option<(&str, Option<&Literal>)> {
+        match self {
+            Predicate::Eq(col, lit)
+            | Predicate::NotEq(col, lit)
+            | Predicate::Lt(col, lit)
+            | Predicate::LtEq(col, lit)
+            | Predicate::Gt(col, lit)
+            | Predicate::GtEq(col, lit) => Some((col, Some(lit))),
+            Predicate::IsNull(col) | Predicate::IsNotNull(col) | Predicate::In(col, _) => {
+                Some((col, None))
+            }
+            _ => None,
+        }
+    }
+
     /// Parse a predicate from a DSL string.
     pub fn parse(input: &str) -> Result<Self> {
         let mut parser = Parser::new(input);
@@ -72,6 +328,93 @@ impl Predicate {
     }
 }
 
+/// Whether the column's value orders against the literal as `accept` wants.
+/// Missing, NULL and mismatched values never match, as in SQL.
+fn compare(row: &RowMap, col: &str, lit: &Literal, accept: fn(Ordering) -> bool) -> bool {
+    row.get(col)
+        .and_then(|v| lit.compare(v))
+        .is_some_and(accept)
+}
+
+/// SQL for an ordered comparison. String literals compare with the "C"
+/// collation so Postgres orders them byte-wise like `evaluate`.
+fn ordered_sql(col: &str, op: &str, lit: &Literal, negated: bool) -> Option<String> {
+    let lit_sql = match lit {
+        Literal::String(_) => format!("{} COLLATE \"C\"", lit.to_sql()?),
+        _ => lit.to_sql()?,
+    };
+    Some(null_safe(
+        format!("{} {} {}", quote_sql_ident(col), op, lit_sql),
+        negated,
+    ))
+}
+
+/// A comparison that is NULL for NULL columns, made FALSE under negation so
+/// `NOT` keeps the rows `evaluate` keeps.
+fn null_safe(cmp: String, negated: bool) -> String {
+    if negated {
+        format!("COALESCE({}, FALSE)", cmp)
+    } else {
+        cmp
+    }
+}
+
+/// One element of a LIKE pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PatternChar {
+    /// `%`: any run of characters, including none.
+    Any,
+    /// `_`: exactly one character.
+    One,
+    /// A character that matches itself.
+    Literal(char),
+}
+
+/// Whether `text` matches a LIKE pattern, as Postgres does with the default
+/// `\` escape. A trailing `\` matches itself.
+fn like_matches(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let mut pat = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        pat.push(match c {
+            '%' => PatternChar::Any,
+            '_' => PatternChar::One,
+            '\\' => PatternChar::Literal(chars.next().unwrap_or('\\')),
+            c => PatternChar::Literal(c),
+        });
+    }
+
+    // Greedy match, backtracking to the last `%` on a mismatch
+    let (mut t, mut p) = (0, 0);
+    let mut last_any: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pat.get(p) {
+            Some(PatternChar::One) => {
+                t += 1;
+                p += 1;
+            }
+            Some(PatternChar::Literal(c)) if *c == text[t] => {
+                t += 1;
+                p += 1;
+            }
+            Some(PatternChar::Any) => {
+                last_any = Some((p, t));
+                p += 1;
+            }
+            _ => match last_any {
+                Some((any_p, any_t)) => {
+                    last_any = Some((any_p, any_t + 1));
+                    p = any_p + 1;
+                    t = any_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pat[p..].iter().all(|c| *c == PatternChar::Any)
+}
+
 /// Token types for the predicate DSL.
 #[derive(Debug, Clone, PartialEq)]
 enum Token {
@@ -84,12 +427,20 @@ enum Token {
     Null,
     Eq,
     NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
     Is,
+    In,
+    Like,
+    ILike,
     Not,
     And,
     Or,
     LParen,
     RParen,
+    Comma,
     Eof,
 }
 
@@ -192,6 +543,10 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 Token::RParen
             }
+            ',' => {
+                self.advance();
+                Token::Comma
+            }
             '=' => {
                 self.advance();
                 Token::Eq
@@ -205,6 +560,24 @@ impl<'a> Lexer<'a> {
                     Token::Not
                 }
             }
+            '<' => {
+                self.advance();
+                if self.peek_char() == Some('=') {
+                    self.advance();
+                    Token::LtEq
+                } else {
+                    Token::Lt
+                }
+            }
+            '>' => {
+                self.advance();
+                if self.peek_char() == Some('=') {
+                    self.advance();
+                    Token::GtEq
+                } else {
+                    Token::Gt
+                }
+            }
             '\'' => Token::String(self.read_string()),
             c if c.is_ascii_digit() || c == '-' => self.read_number(),
             c if c.is_alphabetic() || c == '_' => {
@@ -214,6 +587,9 @@ impl<'a> Lexer<'a> {
                     "FALSE" => Token::False,
                     "NULL" => Token::Null,
                     "IS" => Token::Is,
+                    "IN" => Token::In,
+                    "LIKE" => Token::Like,
+                    "ILIKE" => Token::ILike,
                     "NOT" => Token::Not,
                     "AND" => Token::And,
                     "OR" => Token::Or,
@@ -325,6 +701,51 @@ impl<'a> Parser<'a> {
                 let lit = self.parse_literal()?;
                 Ok(Predicate::NotEq(column, lit))
             }
+            Token::Lt => {
+                self.advance();
+                let lit = self.parse_literal()?;
+                Ok(Predicate::Lt(column, lit))
+            }
+            Token::LtEq => {
+                self.advance();
+                let lit = self.parse_literal()?;
+                Ok(Predicate::LtEq(column, lit))
+            }
+            Token::Gt => {
+                self.advance();
+                let lit = self.parse_literal()?;
+                Ok(Predicate::Gt(column, lit))
+            }
+            Token::GtEq => {
+                self.advance();
+                let lit = self.parse_literal()?;
+                Ok(Predicate::GtEq(column, lit))
+            }
+            Token::In => {
+                self.advance();
+                let lits = self.parse_literal_list()?;
+                Ok(Predicate::In(column, lits))
+            }
+            Token::Like => {
+                self.advance();
+                let pattern = self.parse_pattern()?;
+                Ok(Predicate::Like(column, pattern))
+            }
+            Token::ILike => {
+                self.advance();
+                let pattern = self.parse_pattern()?;
+                Ok(Predicate::ILike(column, pattern))
+            }
+            Token::Not => {
+                self.advance();
+                if !matches!(self.current, Token::In | Token::Like | Token::ILike) {
+                    return Err(Error::PredicateError(
+                        "expected IN, LIKE or ILIKE after NOT".into(),
+                    ));
+                }
+                let inner = self.parse_comparison(column)?;
+                Ok(Predicate::Not(Box::new(inner)))
+            }
             Token::Is => {
                 self.advance();
                 if self.current == Token::Not {
@@ -350,6 +771,39 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a parenthesized, comma-separated list of literals.
+    fn parse_literal_list(&mut self) -> Result<Vec<Literal>> {
+        if self.current != Token::LParen {
+            return Err(Error::PredicateError("expected '(' after IN".into()));
+        }
+        self.advance();
+
+        let mut lits = vec![self.parse_literal()?];
+        while self.current == Token::Comma {
+            self.advance();
+            lits.push(self.parse_literal()?);
+        }
+
+        if self.current != Token::RParen {
+            return Err(Error::PredicateError("expected ')' after IN list".into()));
+        }
+        self.advance();
+        Ok(lits)
+    }
+
+    /// Parse the string literal after LIKE or ILIKE.
+    fn parse_pattern(&mut self) -> Result<String> {
+        let Token::String(pattern) = &self.current else {
+            return Err(Error::PredicateError(format!(
+                "expected a string pattern, got {:?}",
+                self.current
+            )));
+        };
+        let pattern = pattern.clone();
+        self.advance();
+        Ok(pattern)
+    }
+
     fn parse_literal(&mut self) -> Result<Literal> {
         let lit = match &self.current {
             Token::Null => Literal::Null,
@@ -374,6 +828,17 @@ impl<'a> Parser<'a> {
 mod tests {
     use super::*;
 
+    /// Column types for the `to_sql` tests.
+    fn column_type(col: &str) -> Option<SqlType> {
+        match col {
+            "active" => Some(SqlType::Bool),
+            "view_count" | "count" | "parent_id" => Some(SqlType::Integer),
+            "status" | "name" | "slug" | "title" | "path" | "we\"ird" => Some(SqlType::Text),
+            "score" | "created_at" | "deleted_at" => Some(SqlType::Other),
+            _ => None,
+        }
+    }
+
     fn row(pairs: &[(&str, Value)]) -> RowMap {
         pairs
             .iter()
@@ -381,6 +846,14 @@ mod tests {
             .collect()
     }
 
+    #[test]
+    fn test_predicate_columns() {
+        let p = Predicate::parse("status = 'active' AND (deleted_at IS NULL OR status != 'x')")
+            .unwrap();
+        assert_eq!(p.columns(), vec!["deleted_at", "status"]);
+        assert!(Predicate::True.columns().is_empty());
+    }
+
     #[test]
     fn test_predicate_true_false() {
         let row = row(&[]);
@@ -530,4 +1003,291 @@ mod tests {
         let p = Predicate::parse("active = false").unwrap();
         assert!(!p.evaluate(&row));
     }
+
+    #[test]
+    fn test_predicate_ordered_comparisons() {
+        let row = row(&[
+            ("view_count", Value::Int(100)),
+            ("score", Value::Float(2.5)),
+            ("created_at", Value::String("2024-03-01 12:00:00+00".into())),
+            ("deleted_at", Value::Null),
+        ]);
+
+        for (input, expected) in [
+            ("view_count >= 100", true),
+            ("view_count > 100", false),
+            ("view_count < 100", false),
+            ("view_count <= 100", true),
+            ("view_count > 99.5", true),
+            ("score < 3", true),
+            ("score >= 2.5", true),
+            ("score > -1", true),
+            ("created_at > '2024-01-01'", true),
+            ("created_at < '2024-01-01'", false),
+            // NULL, missing and mismatched values never compare
+            ("deleted_at > 0", false),
+            ("deleted_at <= 0", false),
+            ("missing < 5", false),
+            ("view_count > 'abc'", false),
+            ("NOT deleted_at > 0", true),
+        ] {
+            let p = Predicate::parse(input).unwrap();
+            assert_eq!(p.evaluate(&row), expected, "{}", input);
+        }
+
+        assert_eq!(
+            Predicate::parse("a<=1 AND b>2").unwrap(),
+            Predicate::And(
+                Box::new(Predicate::LtEq("a".into(), Literal::Int(1))),
+                Box::new(Predicate::Gt("b".into(), Literal::Int(2))),
+            )
+        );
+    }
+
+    #[test]
+    fn test_to_sql_ordered_comparisons() {
+        let p = Predicate::parse("view_count >= 100 AND parent_id < 15").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(\"view_count\" >= 100 AND \"parent_id\" < 15)"
+        );
+
+        let p = Predicate::parse("name > 'm'").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "\"name\" > 'm' COLLATE \"C\""
+        );
+
+        // NOT (x > 1) must keep rows where x is NULL, as evaluate does
+        let p = Predicate::parse("NOT view_count > 1").unwrap();
+        assert!(p.evaluate(&row(&[("view_count", Value::Null)])));
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(NOT COALESCE(\"view_count\" > 1, FALSE))"
+        );
+    }
+
+    #[test]
+    fn test_predicate_in() {
+        let row = row(&[
+            ("status", Value::String("archived".into())),
+            ("count", Value::Int(3)),
+            ("deleted_at", Value::Null),
+        ]);
+
+        for (input, expected) in [
+            ("status IN ('published', 'archived')", true),
+            ("status IN ('published')", false),
+            ("status NOT IN ('published', 'draft')", true),
+            ("status NOT IN ('archived')", false),
+            ("count IN (1, 2, 3)", true),
+            ("count IN (3.0)", true),
+            // Like != , a NULL or missing column is never in the list
+            ("deleted_at IN (1, 2)", false),
+            ("deleted_at NOT IN (1, 2)", true),
+            ("missing NOT IN ('a')", true),
+            ("deleted_at IN (NULL)", true),
+        ] {
+            let p = Predicate::parse(input).unwrap();
+            assert_eq!(p.evaluate(&row), expected, "{}", input);
+        }
+
+        let p = Predicate::parse("a IN (1) OR b NOT IN ('x')").unwrap();
+        assert_eq!(p.columns(), vec!["a", "b"]);
+
+        for input in [
+            "status IN ()",
+            "status IN 'a'",
+            "status IN ('a'",
+            "status NOT 'a'",
+        ] {
+            assert!(Predicate::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_like_matches() {
+        for (text, pattern, expected) in [
+            ("hello", "hello", true),
+            ("hello", "hell", false),
+            ("hello", "he%", true),
+            ("hello", "%llo", true),
+            ("hello", "%ll%", true),
+            ("hello", "h_llo", true),
+            ("hello", "h_lo", false),
+            ("hello", "%", true),
+            ("", "%", true),
+            ("", "_", false),
+            ("abcabc", "%bc", true),
+            ("abcabd", "%bc", false),
+            ("a%b", "a\\%b", true),
+            ("axb", "a\\%b", false),
+            ("a_b", "a\\_b", true),
+            ("héllo", "h_llo", true),
+        ] {
+            assert_eq!(
+                like_matches(text, pattern),
+                expected,
+                "{} LIKE {}",
+                text,
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_predicate_like() {
+        let row = row(&[
+            ("email", Value::String("Jane@Example.com".into())),
+            ("count", Value::Int(3)),
+            ("deleted_at", Value::Null),
+        ]);
+
+        for (input, expected) in [
+            ("email LIKE 'Jane@%'", true),
+            ("email LIKE '%@example.com'", false),
+            ("email ILIKE '%@example.com'", true),
+            ("email NOT LIKE '%@example.com'", true),
+            ("email NOT ILIKE '%@example.com'", false),
+            // Only text columns match
+            ("count LIKE '3'", false),
+            ("deleted_at LIKE '%'", false),
+            ("deleted_at NOT LIKE '%'", true),
+        ] {
+            let p = Predicate::parse(input).unwrap();
+            assert_eq!(p.evaluate(&row), expected, "{}", input);
+        }
+
+        assert!(Predicate::parse("email LIKE 3").is_err());
+        assert!(Predicate::parse("email NOT = 'a'").is_err());
+    }
+
+    #[test]
+    fn test_to_sql_like() {
+        let p = Predicate::parse("slug LIKE 'blog/%'").unwrap();
+        assert_eq!(p.to_sql(column_type).unwrap(), "\"slug\" LIKE 'blog/%'");
+
+        // Postgres and Rust lowercase differently outside ASCII
+        let p = Predicate::parse("slug LIKE 'blog/%' AND title ILIKE '%rust%'").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+        assert!(p.sql_form().is_none());
+
+        let p = Predicate::parse("slug NOT LIKE 'draft/%'").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(NOT COALESCE(\"slug\" LIKE 'draft/%', FALSE))"
+        );
+    }
+
+    #[test]
+    fn test_to_sql_in() {
+        let p = Predicate::parse("status IN ('published', 'archived')").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "\"status\" IN ('published', 'archived')"
+        );
+
+        // NOT IN keeps NULL columns, as evaluate does
+        let p = Predicate::parse("count NOT IN (1, 2)").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(NOT (\"count\" IS NOT DISTINCT FROM 1 OR \"count\" IS NOT DISTINCT FROM 2))"
+        );
+
+        let p = Predicate::parse("parent_id IN (NULL, 1)").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(\"parent_id\" IS NULL OR \"parent_id\" = 1)"
+        );
+    }
+
+    #[test]
+    fn test_to_sql_comparisons() {
+        let p = Predicate::parse("status = 'active' AND deleted_at IS NULL").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(\"status\" = 'active' AND \"deleted_at\" IS NULL)"
+        );
+
+        let p = Predicate::parse("count != 3 OR parent_id = 7").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(\"count\" IS DISTINCT FROM 3 OR \"parent_id\" = 7)"
+        );
+
+        let p = Predicate::parse("active = true").unwrap();
+        assert_eq!(p.to_sql(column_type).unwrap(), "\"active\" = TRUE");
+    }
+
+    #[test]
+    fn test_to_sql_negation_is_null_safe() {
+        // NOT (x = 1) must keep rows where x is NULL, as evaluate does
+        let p = Predicate::parse("NOT status = 'archived'").unwrap();
+        assert!(p.evaluate(&row(&[("status", Value::Null)])));
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(NOT \"status\" IS NOT DISTINCT FROM 'archived')"
+        );
+
+        // Double negation goes back to plain equality
+        let p = Predicate::parse("NOT (NOT status = 'active')").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(NOT (NOT \"status\" = 'active'))"
+        );
+    }
+
+    #[test]
+    fn test_to_sql_escapes_identifiers_and_strings() {
+        let p = Predicate::Eq("we\"ird".into(), Literal::String("it's".into()));
+        assert_eq!(p.to_sql(column_type).unwrap(), "\"we\"\"ird\" = 'it''s'");
+    }
+
+    #[test]
+    fn test_to_sql_falls_back_when_not_expressible() {
+        let p = Predicate::Eq("path".into(), Literal::String("C:\\tmp".into()));
+        assert!(p.to_sql(column_type).is_none());
+
+        let p = Predicate::And(
+            Box::new(Predicate::True),
+            Box::new(Predicate::Eq("x".into(), Literal::Float(f64::NAN))),
+        );
+        assert!(p.to_sql(column_type).is_none());
+    }
+
+    #[test]
+    fn test_to_sql_only_for_compatible_column_types() {
+        // Postgres would coerce the literal and compare timestamps, while
+        // evaluate compares the decoded text
+        let p = Predicate::parse("created_at > '2024-01-01'").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+        assert_eq!(p.sql_form().unwrap(), "\"created_at\" > '2024-01-01' COLLATE \"C\"");
+
+        // Floats have NaN and rounding, numerics are decoded as floats or text
+        let p = Predicate::parse("score >= 1").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+        let p = Predicate::parse("view_count < 1.5").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+
+        // Literals of another type than the column's
+        let p = Predicate::parse("active = 'true'").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+        let p = Predicate::parse("status = 1").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+        let p = Predicate::parse("status IN ('a', 1)").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+        let p = Predicate::parse("count LIKE '1%'").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+
+        // A column the relation doesn't have
+        let p = Predicate::parse("missing IS NULL").unwrap();
+        assert!(p.to_sql(column_type).is_none());
+
+        // NULL checks work on any column
+        let p = Predicate::parse("deleted_at IS NULL AND score != NULL").unwrap();
+        assert_eq!(
+            p.to_sql(column_type).unwrap(),
+            "(\"deleted_at\" IS NULL AND \"score\" IS NOT NULL)"
+        );
+    }
 }
@@ -1,5 +1,8 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::error::Result;
 use crate::mapping::{Mapping, MembershipConfig};
-use crate::types::RowEvent;
+use crate::types::{Operation, RowEvent};
 
 /// Routes events to their matching mappings.
 pub struct Router {
@@ -20,6 +23,64 @@ impl Router {
             .collect()
     }
 
+    /// Find all mappings that match an event, along with the namespace each
+    /// one writes it to, plus the mappings an updated row stopped matching.
+    pub fn route_to_namespaces<'a>(&'a self, event: &'a RowEvent) -> Vec<RoutedEvent<'a>> {
+        self.mappings
+            .iter()
+            .filter_map(|mapping| {
+                // Nothing the mapping reads changed, so neither would its
+                // document, its namespace or its membership
+                if mapping.is_noop_update(event) {
+                    return None;
+                }
+                if self.matches(mapping, event) {
+                    Some(RoutedEvent {
+                        event,
+                        mapping,
+                        namespace: mapping.namespace_for(event),
+                        moved_from: mapping.previous_namespace(event),
+                        left_membership: false,
+                    })
+                } else if mapping.left_membership(event) {
+                    // The document is in the namespace of the row it was
+                    // written from
+                    let old = event.old.as_ref()?;
+                    Some(RoutedEvent {
+                        event,
+                        mapping,
+                        namespace: mapping.namespace_for_row(old),
+                        moved_from: None,
+                        left_membership: true,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Columns the mappings read, by `schema.table`: the union of what each
+    /// mapping of the table needs, so the decoder can skip the rest. A table
+    /// that some mapping reads whole is left out, meaning all its columns.
+    pub fn decoded_columns(&self) -> HashMap<String, HashSet<String>> {
+        let mut tables: HashMap<String, Option<HashSet<String>>> = HashMap::new();
+        for mapping in self.mappings.iter().filter(|m| !m.source.is_query()) {
+            let table = format!("{}.{}", mapping.source.schema, mapping.source.table);
+            let columns = tables.entry(table).or_insert_with(|| Some(HashSet::new()));
+            match (columns.as_mut(), mapping.relevant_columns()) {
+                (Some(columns), Some(relevant)) => {
+                    columns.extend(relevant.into_iter().map(String::from));
+                }
+                _ => *columns = None,
+            }
+        }
+        tables
+            .into_iter()
+            .filter_map(|(table, columns)| Some((table, columns?)))
+            .collect()
+    }
+
     /// Check if a mapping matches an event.
     fn matches(&self, mapping: &Mapping, event: &RowEvent) -> bool {
         // First check source relation
@@ -33,6 +94,10 @@ impl Router {
 
     /// Evaluate membership predicate against an event.
     fn evaluate_membership(&self, membership: &MembershipConfig, event: &RowEvent) -> bool {
+        // A truncate empties the table, members and all
+        if event.op == Operation::Truncate {
+            return true;
+        }
         match membership {
             MembershipConfig::All | MembershipConfig::View => true,
             MembershipConfig::Dsl(predicate) => {
@@ -53,6 +118,14 @@ impl Router {
 pub struct RoutedEvent<'a> {
     pub event: &'a RowEvent,
     pub mapping: &'a Mapping,
+    /// Namespace the event is written to. Fails for a partitioned mapping
+    /// when the row lacks a usable value for a namespace column.
+    pub namespace: Result<String>,
+    /// Namespace an updated row moved out of, whose copy must be deleted.
+    pub moved_from: Option<String>,
+    /// The update moved the row out of the mapping's membership predicate,
+    /// so its document in `namespace` must be deleted rather than written.
+    pub left_membership: bool,
 }
 
 #[cfg(test)]
@@ -62,7 +135,6 @@ mod tests {
     use crate::predicate::Predicate;
     use crate::transform::IdType;
     use crate::types::{Operation, Value};
-    use std::collections::HashMap;
 
     fn make_mapping(
         name: &str,
@@ -169,6 +241,95 @@ mod tests {
         assert!(router.route(&event).is_empty());
     }
 
+    #[test]
+    fn test_router_membership_transitions() {
+        let predicate = Predicate::parse("status = 'active'").unwrap();
+        let router = Router::new(vec![make_mapping(
+            "active_users",
+            "public",
+            "users",
+            MembershipConfig::Dsl(predicate),
+        )]);
+
+        let row = |status: &str| -> HashMap<String, Value> {
+            [
+                ("id".into(), Value::Int(1)),
+                ("status".into(), Value::String(status.into())),
+            ]
+            .into_iter()
+            .collect()
+        };
+        let update = |old: Option<HashMap<String, Value>>, new| RowEvent {
+            op: Operation::Update,
+            old,
+            ..make_event("public", "users", new)
+        };
+
+        // Leaving the predicate deletes the document
+        let event = update(Some(row("active")), row("deleted"));
+        let routed = router.route_to_namespaces(&event);
+        assert_eq!(routed.len(), 1);
+        assert!(routed[0].left_membership);
+        assert_eq!(routed[0].namespace.as_deref().unwrap(), "active_users");
+        assert!(router.route(&event).is_empty());
+
+        // Staying a member is a normal write
+        let event = update(Some(row("active")), row("active"));
+        let routed = router.route_to_namespaces(&event);
+        assert_eq!(routed.len(), 1);
+        assert!(!routed[0].left_membership);
+
+        // Never a member, or no old row to tell: nothing to do
+        let event = update(Some(row("deleted")), row("deleted"));
+        assert!(router.route_to_namespaces(&event).is_empty());
+        let event = update(None, row("deleted"));
+        assert!(router.route_to_namespaces(&event).is_empty());
+
+        // An old row with only the key can't show the row was a member
+        let key_only = [("id".into(), Value::Int(1))].into_iter().collect();
+        let event = update(Some(key_only), row("deleted"));
+        assert!(router.route_to_namespaces(&event).is_empty());
+    }
+
+    #[test]
+    fn test_router_skips_noop_updates() {
+        let synced = Mapping::builder("names")
+            .namespace("names")
+            .source("public", "users")
+            .id("id", IdType::Uint)
+            .columns(vec!["name".into()])
+            .build()
+            .unwrap();
+        let router = Router::new(vec![
+            synced,
+            make_mapping("everything", "public", "users", MembershipConfig::All),
+        ]);
+
+        let row = |name: &str, seen: i64| -> HashMap<String, Value> {
+            [
+                ("id".into(), Value::Int(1)),
+                ("name".into(), Value::String(name.into())),
+                ("last_seen_at".into(), Value::Int(seen)),
+            ]
+            .into_iter()
+            .collect()
+        };
+        let update = |old, new| RowEvent {
+            op: Operation::Update,
+            old: Some(old),
+            ..make_event("public", "users", new)
+        };
+
+        // Only the mapping that copies every column cares about last_seen_at
+        let event = update(row("ann", 1), row("ann", 2));
+        let routed = router.route_to_namespaces(&event);
+        assert_eq!(routed.len(), 1);
+        assert_eq!(routed[0].mapping.name, "everything");
+
+        let event = update(row("ann", 1), row("bea", 1));
+        assert_eq!(router.route_to_namespaces(&event).len(), 2);
+    }
+
     #[test]
     fn test_router_multiple_mappings_same_source() {
         let active_pred = Predicate::parse("status = 'active'").unwrap();
@@ -207,4 +368,68 @@ mod tests {
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].name, "deleted_users");
     }
+
+    #[test]
+    fn test_router_partitioned_namespaces() {
+        let mapping = Mapping::builder("posts")
+            .namespace("posts_{tenant_id}")
+            .source("public", "posts")
+            .id("id", IdType::Uint)
+            .build()
+            .unwrap();
+        let router = Router::new(vec![
+            mapping,
+            make_mapping("all_posts", "public", "posts", MembershipConfig::All),
+        ]);
+
+        let event = make_event(
+            "public",
+            "posts",
+            [
+                ("id".into(), Value::Int(1)),
+                ("tenant_id".into(), Value::String("acme".into())),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let routed = router.route_to_namespaces(&event);
+        assert_eq!(routed.len(), 2);
+        assert_eq!(routed[0].namespace.as_deref().unwrap(), "posts_acme");
+        assert_eq!(routed[1].namespace.as_deref().unwrap(), "all_posts");
+
+        // A row without the column can't be placed
+        let event = make_event(
+            "public",
+            "posts",
+            [("id".into(), Value::Int(2))].into_iter().collect(),
+        );
+        let routed = router.route_to_namespaces(&event);
+        assert!(routed[0].namespace.is_err());
+        assert!(routed[1].namespace.is_ok());
+    }
+
+    #[test]
+    fn test_router_decoded_columns() {
+        let narrow = |name: &str, table: &str, columns: &[&str]| {
+            Mapping::builder(name)
+                .namespace(name)
+                .source("public", table)
+                .id("id", IdType::Uint)
+                .columns(columns.iter().map(|c| c.to_string()).collect())
+                .build()
+                .unwrap()
+        };
+        let router = Router::new(vec![
+            narrow("posts", "posts", &["title"]),
+            narrow("post_bodies", "posts", &["body"]),
+            narrow("users", "users", &["name"]),
+            // Without `columns`, every column is copied
+            make_mapping("all_users", "public", "users", MembershipConfig::All),
+        ]);
+
+        let decoded = router.decoded_columns();
+        let posts: HashSet<&str> = decoded["public.posts"].iter().map(String::as_str).collect();
+        assert_eq!(posts, HashSet::from(["id", "title", "body"]));
+        assert!(!decoded.contains_key("public.users"));
+    }
 }
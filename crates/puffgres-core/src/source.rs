@@ -0,0 +1,43 @@
+//! Change sources that feed the routing/transform pipeline.
+//!
+//! Postgres logical replication is the main source, but anything that can
+//! produce [`RowEvent`]s in commit order with a resumable position can drive
+//! the same pipeline.
+
+use std::future::Future;
+
+use crate::types::RowEvent;
+
+/// A batch of row events read from a change source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceBatch {
+    /// The events in this batch, in commit order.
+    pub events: Vec<RowEvent>,
+    /// Source position to acknowledge once the batch has been processed
+    /// (an LSN for Postgres, an offset for a message log).
+    pub position: u64,
+    /// Whether the batch's transaction continues in the next batch, when it
+    /// was too large to deliver at once. The position then stays before the
+    /// transaction until its last batch.
+    pub continues: bool,
+}
+
+/// A source of row changes.
+///
+/// Sources must redeliver everything after the last acknowledged position
+/// when restarted, which is what gives the pipeline at-least-once delivery.
+pub trait SourceAdapter: Send {
+    /// Error returned when reading from the source fails.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Wait for the next batch. Returns `None` when the source has ended.
+    fn next_batch(
+        &mut self,
+    ) -> impl Future<Output = Result<Option<SourceBatch>, Self::Error>> + Send;
+
+    /// Mark everything up to `position` as processed.
+    fn acknowledge(&mut self, position: u64);
+
+    /// The last acknowledged position.
+    fn acknowledged_position(&self) -> u64;
+}
@@ -1,5 +1,11 @@
+use std::collections::HashMap;
+
 use crate::action::{Action, Document, DocumentId};
+use crate::coerce::ColumnType;
 use crate::error::{Error, Result};
+use crate::expr::Expression;
+use crate::mapping::{FlattenConfig, IdConfig, VectorColumn};
+use crate::obfuscate::IdObfuscator;
 use crate::types::{Operation, RowEvent, Value};
 
 /// Trait for transforming row events into turbopuffer actions.
@@ -21,16 +27,121 @@ pub trait Transformer: Send + Sync {
 pub struct IdentityTransformer {
     /// Columns to include in the document.
     columns: Vec<String>,
+    /// Attribute names to use instead of the source column names.
+    renames: HashMap<String, String>,
+    /// Types to convert columns to before writing them.
+    types: HashMap<String, ColumnType>,
+    /// JSON columns to expand into top-level attributes.
+    flatten: FlattenConfig,
+    /// Attributes computed from the row.
+    computed: HashMap<String, Expression>,
+    /// Column written as the document vector.
+    vector: Option<VectorColumn>,
 }
 
 impl IdentityTransformer {
     pub fn new(columns: Vec<String>) -> Self {
-        Self { columns }
+        Self {
+            columns,
+            renames: HashMap::new(),
+            types: HashMap::new(),
+            flatten: FlattenConfig::default(),
+            computed: HashMap::new(),
+            vector: None,
+        }
     }
 
     /// Create an identity transformer that includes all columns from the row.
     pub fn all() -> Self {
-        Self { columns: vec![] }
+        Self::new(vec![])
+    }
+
+    /// Rename columns in the output document (source column -> attribute name).
+    pub fn with_renames(mut self, renames: HashMap<String, String>) -> Self {
+        self.renames = renames;
+        self
+    }
+
+    /// Convert columns to the given types (source column -> type).
+    pub fn with_types(mut self, types: HashMap<String, ColumnType>) -> Self {
+        self.types = types;
+        self
+    }
+
+    /// Expand JSON columns into top-level attributes.
+    pub fn with_flatten(mut self, flatten: FlattenConfig) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    /// Add attributes computed from the row (attribute -> expression). An
+    /// attribute is left out when the row lacks a column its expression reads.
+    pub fn with_computed(mut self, computed: HashMap<String, Expression>) -> Self {
+        self.computed = computed;
+        self
+    }
+
+    /// Write a column as the document vector rather than as an attribute.
+    pub fn with_vector(mut self, vector: Option<VectorColumn>) -> Self {
+        self.vector = vector;
+        self
+    }
+
+    fn is_vector_column(&self, column: &str) -> bool {
+        self.vector.as_ref().is_some_and(|v| v.column == column)
+    }
+
+    fn attribute_name(&self, column: &str) -> String {
+        self.renames
+            .get(column)
+            .cloned()
+            .unwrap_or_else(|| column.to_string())
+    }
+
+    fn insert_column(&self, doc: &mut Document, column: &str, value: &Value) -> Result<()> {
+        if let Some(column_type) = self.types.get(column) {
+            let value = column_type.coerce(column, value)?;
+            doc.insert(self.attribute_name(column), value);
+            return Ok(());
+        }
+
+        match value {
+            Value::Object(fields) if self.flatten.applies_to(column) => {
+                self.flatten_into(doc, &self.attribute_name(column), column, fields, 1);
+            }
+            _ => {
+                doc.insert(self.attribute_name(column), value.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Insert each field of a JSON object as `<name><separator><key>`,
+    /// recursing into nested objects until `max_depth` is reached.
+    fn flatten_into(
+        &self,
+        doc: &mut Document,
+        name: &str,
+        path: &str,
+        fields: &HashMap<String, Value>,
+        depth: usize,
+    ) {
+        for (key, value) in fields {
+            let path = format!("{}.{}", path, key);
+            if !self.flatten.allows(&path) {
+                continue;
+            }
+
+            let name = format!("{}{}{}", name, self.flatten.separator, key);
+            match value {
+                Value::Object(nested) if depth < self.flatten.max_depth => {
+                    self.flatten_into(doc, &name, &path, nested, depth + 1);
+                }
+                _ => {
+                    doc.insert(name, value.clone());
+                }
+            }
+        }
     }
 }
 
@@ -46,25 +157,91 @@ impl IdentityTransformer {
     fn transform_single(&self, event: &RowEvent, id: DocumentId) -> Result<Action> {
         match event.op {
             Operation::Delete => Ok(Action::delete(id)),
+            Operation::Truncate => Err(Error::TransformError(
+                "truncate events have no row to transform".into(),
+            )),
             Operation::Insert | Operation::Update => {
                 let row = event.new.as_ref().ok_or_else(|| {
                     Error::TransformError("missing new row for insert/update".into())
                 })?;
 
-                let doc: Document = if self.columns.is_empty() {
+                let mut doc = Document::new();
+                if self.columns.is_empty() {
                     // Include all columns
-                    row.clone()
+                    for (col, v) in row {
+                        if !self.is_vector_column(col) {
+                            self.insert_column(&mut doc, col, v)?;
+                        }
+                    }
                 } else {
                     // Include only selected columns
-                    self.columns
-                        .iter()
-                        .filter_map(|col| row.get(col).map(|v| (col.clone(), v.clone())))
-                        .collect()
+                    for col in &self.columns {
+                        if let Some(v) = row.get(col).filter(|_| !self.is_vector_column(col)) {
+                            self.insert_column(&mut doc, col, v)?;
+                        }
+                    }
+                }
+
+                for (attribute, expr) in &self.computed {
+                    match expr.evaluate(row) {
+                        Ok(value) => {
+                            doc.insert(attribute.clone(), value);
+                        }
+                        Err(Error::MissingColumn(_)) => {}
+                        Err(e) => {
+                            return Err(Error::TransformError(format!(
+                                "computed attribute '{}': {}",
+                                attribute, e
+                            )))
+                        }
+                    }
+                }
+
+                let Some(vector) = &self.vector else {
+                    return Ok(Action::upsert(id, doc));
                 };
+                let action = match vector.distance_metric {
+                    Some(metric) => Action::upsert_with_metric(id, doc, metric),
+                    None => Action::upsert(id, doc),
+                };
+                match row.get(&vector.column) {
+                    None | Some(Value::Null) => Ok(action),
+                    Some(value) => Ok(action.with_vector(column_vector(&vector.column, value)?)),
+                }
+            }
+        }
+    }
+}
 
-                Ok(Action::upsert(id, doc))
+/// Read an embedding from a column: an array of numbers, or pgvector's text
+/// form (`[0.1,0.2]`) when the column wasn't decoded.
+fn column_vector(column: &str, value: &Value) -> Result<Vec<f32>> {
+    let invalid = || {
+        Error::TransformError(format!(
+            "vector column '{}' must be an array of numbers",
+            column
+        ))
+    };
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_f64().map(|f| f as f32).ok_or_else(invalid))
+            .collect(),
+        Value::String(s) => {
+            let inner = s
+                .trim()
+                .strip_prefix('[')
+                .and_then(|s| s.strip_suffix(']'))
+                .ok_or_else(invalid)?;
+            if inner.trim().is_empty() {
+                return Ok(Vec::new());
             }
+            inner
+                .split(',')
+                .map(|v| v.trim().parse::<f32>().map_err(|_| invalid()))
+                .collect()
         }
+        _ => Err(invalid()),
     }
 }
 
@@ -96,17 +273,29 @@ where
     }
 }
 
-/// Extract the document ID from a row event based on the configured id column.
-pub fn extract_id(event: &RowEvent, id_column: &str, id_type: IdType) -> Result<DocumentId> {
+/// Extract the document ID from a row event based on the configured id column(s).
+pub fn extract_id(event: &RowEvent, id: &IdConfig) -> Result<DocumentId> {
     let row = event.row().ok_or(Error::MissingId)?;
+    if let Some(composite) = &id.composite {
+        return composite.compose(row);
+    }
     let value = row
-        .get(id_column)
-        .ok_or_else(|| Error::MissingColumn(id_column.to_string()))?;
+        .get(&id.column)
+        .ok_or_else(|| Error::MissingColumn(id.column.clone()))?;
 
-    match (id_type, value) {
+    match (id.id_type, value) {
         (IdType::Uint, Value::Int(i)) if *i >= 0 => Ok(DocumentId::Uint(*i as u64)),
+        (IdType::ObfuscatedUint(Some(obfuscator)), Value::Int(i)) if *i >= 0 => {
+            Ok(DocumentId::Uint(obfuscator.encode(*i as u64)))
+        }
+        (IdType::ObfuscatedUint(None), _) => Err(Error::InvalidIdType(
+            "obfuscated_uint ids need a secret to be configured".into(),
+        )),
         (IdType::Int, Value::Int(i)) => Ok(DocumentId::Int(*i)),
-        (IdType::Uuid, Value::String(s)) => Ok(DocumentId::Uuid(s.clone())),
+        (IdType::Uuid, Value::String(s)) => DocumentId::parse_uuid(s)
+            .ok_or_else(|| Error::InvalidIdType(format!("'{}' isn't a UUID", s))),
+        (IdType::Bytes, Value::String(s)) => DocumentId::parse_bytea(s)
+            .ok_or_else(|| Error::InvalidIdType(format!("'{}' isn't bytea", s))),
         (IdType::String, Value::String(s)) => Ok(DocumentId::String(s.clone())),
         (IdType::String, Value::Int(i)) => Ok(DocumentId::String(i.to_string())),
         (expected, actual) => Err(Error::InvalidIdType(format!(
@@ -123,11 +312,17 @@ pub enum IdType {
     Int,
     Uuid,
     String,
+    /// A `bytea` column, written as a string ID of its hex.
+    Bytes,
+    /// A non-negative integer written as an obfuscated `u64`. `None` until a
+    /// secret is configured; extracting an ID fails until then.
+    ObfuscatedUint(Option<IdObfuscator>),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::composite_id::IdStrategy;
     use std::collections::HashMap;
 
     fn make_event(op: Operation, new: Option<HashMap<String, Value>>) -> RowEvent {
@@ -201,6 +396,256 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_identity_transformer_renames_columns() {
+        let renames = [("created_at".to_string(), "createdAt".to_string())]
+            .into_iter()
+            .collect();
+        let transformer = IdentityTransformer::new(vec!["name".into(), "created_at".into()])
+            .with_renames(renames);
+
+        let event = make_event(
+            Operation::Insert,
+            Some(
+                [
+                    ("id".into(), Value::Int(1)),
+                    ("name".into(), Value::String("Alice".into())),
+                    ("created_at".into(), Value::String("2024-01-01".into())),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+        );
+
+        let action = transformer.transform(&event, 1u64.into()).unwrap();
+
+        match action {
+            Action::Upsert { doc, .. } => {
+                assert_eq!(doc.len(), 2);
+                assert!(doc.contains_key("name"));
+                assert!(doc.contains_key("createdAt"));
+                assert!(!doc.contains_key("created_at"));
+            }
+            _ => panic!("Expected Upsert"),
+        }
+    }
+
+    #[test]
+    fn test_identity_transformer_coerces_types() {
+        let types = [
+            ("price".to_string(), ColumnType::String),
+            ("created_at".to_string(), ColumnType::UnixMs),
+        ]
+        .into_iter()
+        .collect();
+        let renames = [("created_at".to_string(), "createdAt".to_string())]
+            .into_iter()
+            .collect();
+        let transformer = IdentityTransformer::all()
+            .with_types(types)
+            .with_renames(renames);
+
+        let mut row: HashMap<String, Value> = [
+            ("price".into(), Value::String("19.99".into())),
+            (
+                "created_at".into(),
+                Value::String("2024-01-15 10:30:00+00".into()),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let event = make_event(Operation::Insert, Some(row.clone()));
+
+        match transformer.transform(&event, 1u64.into()).unwrap() {
+            Action::Upsert { doc, .. } => {
+                assert_eq!(doc["price"], Value::String("19.99".into()));
+                assert_eq!(doc["createdAt"], Value::Int(1_705_314_600_000));
+            }
+            _ => panic!("Expected Upsert"),
+        }
+
+        row.insert("created_at".into(), Value::String("not a time".into()));
+        let event = make_event(Operation::Insert, Some(row));
+        assert!(matches!(
+            transformer.transform(&event, 1u64.into()),
+            Err(Error::InvalidColumnType { .. })
+        ));
+    }
+
+    fn metadata() -> Value {
+        Value::Object(
+            [
+                ("author".into(), Value::String("ann".into())),
+                ("tags".into(), Value::Array(vec![Value::String("a".into())])),
+                (
+                    "source".into(),
+                    Value::Object(
+                        [("site".into(), Value::String("blog".into()))]
+                            .into_iter()
+                            .collect(),
+                    ),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+
+    fn flatten_doc(transformer: &IdentityTransformer) -> Document {
+        let event = make_event(
+            Operation::Insert,
+            Some(
+                [
+                    ("id".into(), Value::Int(1)),
+                    ("metadata".into(), metadata()),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+        );
+
+        match transformer.transform(&event, 1u64.into()).unwrap() {
+            Action::Upsert { doc, .. } => doc,
+            _ => panic!("Expected Upsert"),
+        }
+    }
+
+    #[test]
+    fn test_identity_transformer_flattens_json() {
+        let transformer =
+            IdentityTransformer::new(vec!["metadata".into()]).with_flatten(FlattenConfig {
+                columns: vec!["metadata".into()],
+                ..Default::default()
+            });
+
+        let doc = flatten_doc(&transformer);
+        assert_eq!(doc.len(), 3);
+        assert_eq!(doc["metadata_author"], Value::String("ann".into()));
+        assert!(doc.contains_key("metadata_tags"));
+        // Depth 1: nested objects are kept whole
+        assert!(matches!(doc["metadata_source"], Value::Object(_)));
+        assert!(!doc.contains_key("metadata"));
+    }
+
+    #[test]
+    fn test_identity_transformer_flatten_depth_and_allowlist() {
+        let transformer =
+            IdentityTransformer::new(vec!["metadata".into()]).with_flatten(FlattenConfig {
+                columns: vec!["metadata".into()],
+                max_depth: 2,
+                separator: "__".into(),
+                allow: vec!["metadata.author".into(), "metadata.source.site".into()],
+            });
+
+        let doc = flatten_doc(&transformer);
+        assert_eq!(doc.len(), 2);
+        assert_eq!(doc["metadata__author"], Value::String("ann".into()));
+        assert_eq!(doc["metadata__source__site"], Value::String("blog".into()));
+    }
+
+    #[test]
+    fn test_identity_transformer_flatten_uses_renamed_prefix() {
+        let renames = [("metadata".to_string(), "meta".to_string())]
+            .into_iter()
+            .collect();
+        let transformer = IdentityTransformer::all()
+            .with_renames(renames)
+            .with_flatten(FlattenConfig {
+                columns: vec!["metadata".into()],
+                allow: vec!["metadata.author".into()],
+                ..Default::default()
+            });
+
+        let doc = flatten_doc(&transformer);
+        assert_eq!(doc.len(), 2);
+        assert!(doc.contains_key("id"));
+        assert_eq!(doc["meta_author"], Value::String("ann".into()));
+    }
+
+    #[test]
+    fn test_identity_transformer_computed() {
+        let computed = [
+            ("full_name", "concat(first_name, ' ', last_name)"),
+            ("name_length", "length(nickname)"),
+        ]
+        .into_iter()
+        .map(|(name, expr)| (name.to_string(), Expression::parse(expr).unwrap()))
+        .collect();
+        let transformer =
+            IdentityTransformer::new(vec!["first_name".into()]).with_computed(computed);
+
+        let event = make_event(
+            Operation::Insert,
+            Some(
+                [
+                    ("first_name".into(), Value::String("Ada".into())),
+                    ("last_name".into(), Value::String("Lovelace".into())),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+        );
+
+        match transformer.transform(&event, 1u64.into()).unwrap() {
+            Action::Upsert { doc, .. } => {
+                assert_eq!(doc.len(), 2);
+                assert_eq!(doc["full_name"], Value::String("Ada Lovelace".into()));
+                // nickname isn't in the row, so name_length is left out
+                assert!(!doc.contains_key("name_length"));
+            }
+            _ => panic!("expected upsert"),
+        }
+    }
+
+    #[test]
+    fn test_identity_transformer_vector() {
+        let transformer = IdentityTransformer::all().with_vector(Some(VectorColumn {
+            column: "embedding".into(),
+            distance_metric: Some(rs_puff::DistanceMetric::CosineDistance),
+        }));
+        let row = |embedding: Value| {
+            make_event(
+                Operation::Insert,
+                Some(
+                    [
+                        ("title".into(), Value::String("Hello".into())),
+                        ("embedding".into(), embedding),
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+            )
+        };
+
+        let event = row(Value::Array(vec![Value::Float(0.5), Value::Int(1)]));
+        match transformer.transform(&event, 1u64.into()).unwrap() {
+            Action::Upsert {
+                doc,
+                vector,
+                distance_metric,
+                ..
+            } => {
+                assert_eq!(vector, Some(vec![0.5, 1.0]));
+                assert!(distance_metric.is_some());
+                assert!(!doc.contains_key("embedding"));
+                assert_eq!(doc.len(), 1);
+            }
+            _ => panic!("expected upsert"),
+        }
+
+        // pgvector's text form, from a column that wasn't decoded
+        let event = row(Value::String("[0.25, -2]".into()));
+        let action = transformer.transform(&event, 1u64.into()).unwrap();
+        assert_eq!(action.vector_dims(), Some(2));
+
+        let event = row(Value::Null);
+        let action = transformer.transform(&event, 1u64.into()).unwrap();
+        assert_eq!(action.vector_dims(), None);
+
+        let event = row(Value::String("not a vector".into()));
+        assert!(transformer.transform(&event, 1u64.into()).is_err());
+    }
+
     #[test]
     fn test_identity_transformer_delete() {
         let transformer = IdentityTransformer::new(vec!["name".into()]);
@@ -227,10 +672,10 @@ mod tests {
             Some([("id".into(), Value::Int(42))].into_iter().collect()),
         );
 
-        let id = extract_id(&event, "id", IdType::Uint).unwrap();
+        let id = extract_id(&event, &IdConfig::new("id", IdType::Uint)).unwrap();
         assert_eq!(id, DocumentId::Uint(42));
 
-        let id = extract_id(&event, "id", IdType::Int).unwrap();
+        let id = extract_id(&event, &IdConfig::new("id", IdType::Int)).unwrap();
         assert_eq!(id, DocumentId::Int(42));
     }
 
@@ -246,7 +691,84 @@ mod tests {
             ),
         );
 
-        let id = extract_id(&event, "id", IdType::Uuid).unwrap();
-        assert_eq!(id, DocumentId::Uuid(uuid.into()));
+        let id = extract_id(&event, &IdConfig::new("id", IdType::Uuid)).unwrap();
+        assert_eq!(Some(&id), DocumentId::parse_uuid(uuid).as_ref());
+        assert_eq!(id.to_string(), uuid);
+
+        let event = make_event(
+            Operation::Insert,
+            Some(
+                [("id".into(), Value::String("not-a-uuid".into()))]
+                    .into_iter()
+                    .collect(),
+            ),
+        );
+        assert!(extract_id(&event, &IdConfig::new("id", IdType::Uuid)).is_err());
+    }
+
+    #[test]
+    fn test_extract_id_bytes() {
+        let event = make_event(
+            Operation::Insert,
+            Some(
+                [("id".into(), Value::String("\\x0102".into()))]
+                    .into_iter()
+                    .collect(),
+            ),
+        );
+
+        let id = extract_id(&event, &IdConfig::new("id", IdType::Bytes)).unwrap();
+        assert_eq!(id, DocumentId::Bytes(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_extract_id_obfuscated() {
+        let event = make_event(
+            Operation::Insert,
+            Some([("id".into(), Value::Int(42))].into_iter().collect()),
+        );
+        let obfuscator = IdObfuscator::from_secret("test-secret");
+
+        let id = extract_id(
+            &event,
+            &IdConfig::new("id", IdType::ObfuscatedUint(Some(obfuscator))),
+        )
+        .unwrap();
+        assert_eq!(id, DocumentId::Uint(obfuscator.encode(42)));
+        assert_ne!(id, DocumentId::Uint(42));
+
+        assert!(extract_id(&event, &IdConfig::new("id", IdType::ObfuscatedUint(None))).is_err());
+    }
+
+    #[test]
+    fn test_extract_composite_id() {
+        let row: HashMap<String, Value> = [
+            ("tenant_id".into(), Value::String("acme".into())),
+            ("order_id".into(), Value::Int(7)),
+        ]
+        .into_iter()
+        .collect();
+        let columns = vec!["tenant_id".to_string(), "order_id".to_string()];
+
+        // Deletes read the old row
+        let mut event = make_event(Operation::Delete, None);
+        event.old = Some(row);
+
+        let id = IdConfig::composite(
+            columns.clone(),
+            IdStrategy::Concat {
+                separator: "/".into(),
+            },
+        );
+        assert_eq!(
+            extract_id(&event, &id).unwrap(),
+            DocumentId::String("acme/7".into())
+        );
+
+        let id = IdConfig::composite(columns, IdStrategy::Uuid5);
+        assert!(matches!(
+            extract_id(&event, &id).unwrap(),
+            DocumentId::Uuid(_)
+        ));
     }
 }
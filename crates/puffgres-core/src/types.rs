@@ -103,6 +103,8 @@ pub enum Operation {
     Insert,
     Update,
     Delete,
+    /// The whole table was emptied. Truncate events carry no rows.
+    Truncate,
 }
 
 /// A row map containing column name to value mappings.
@@ -128,7 +130,7 @@ pub struct RowEvent {
     /// Optional transaction ID.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub txid: Option<u64>,
-    /// Optional timestamp.
+    /// Commit time of the source transaction (UTC, RFC 3339), if known.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<String>,
 }
@@ -140,6 +142,7 @@ impl RowEvent {
         match self.op {
             Operation::Insert | Operation::Update => self.new.as_ref(),
             Operation::Delete => self.old.as_ref(),
+            Operation::Truncate => None,
         }
     }
 
@@ -0,0 +1,106 @@
+//! Validation of the vectors transforms attach to upserts.
+
+use std::collections::HashMap;
+
+use crate::action::Action;
+use crate::error::{Error, Result};
+
+/// Tracks the vector dimensions used by each namespace.
+///
+/// Turbopuffer fixes a namespace's dimensions with its first vector and
+/// rejects the whole write when a later one differs. Checking each action up
+/// front lets a single bad row be handled by the mapping's error policy
+/// instead of failing the batch it was in.
+#[derive(Debug, Default)]
+pub struct VectorDimensions {
+    dims: HashMap<String, usize>,
+}
+
+impl VectorDimensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check an action's vector, if any. The first vector seen for a
+    /// namespace sets the dimensions expected of the rest.
+    pub fn check(&mut self, namespace: &str, action: &Action) -> Result<()> {
+        let Action::Upsert {
+            vector: Some(vector),
+            ..
+        } = action
+        else {
+            return Ok(());
+        };
+
+        if vector.is_empty() {
+            return Err(Error::InvalidVector("vector is empty".into()));
+        }
+        if let Some(i) = vector.iter().position(|v| !v.is_finite()) {
+            return Err(Error::InvalidVector(format!(
+                "vector has a non-finite value at index {}",
+                i
+            )));
+        }
+
+        let expected = *self
+            .dims
+            .entry(namespace.to_string())
+            .or_insert(vector.len());
+        if vector.len() != expected {
+            return Err(Error::InvalidVector(format!(
+                "namespace '{}' has {}-dimensional vectors, got {}",
+                namespace,
+                expected,
+                vector.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Dimensions seen so far for a namespace.
+    pub fn get(&self, namespace: &str) -> Option<usize> {
+        self.dims.get(namespace).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn upsert(vector: Vec<f32>) -> Action {
+        Action::upsert(1u64, HashMap::new()).with_vector(vector)
+    }
+
+    #[test]
+    fn test_first_vector_sets_dimensions() {
+        let mut dims = VectorDimensions::new();
+        dims.check("posts", &upsert(vec![0.1, 0.2, 0.3])).unwrap();
+        assert_eq!(dims.get("posts"), Some(3));
+
+        dims.check("posts", &upsert(vec![0.4, 0.5, 0.6])).unwrap();
+        let err = dims.check("posts", &upsert(vec![0.1, 0.2])).unwrap_err();
+        assert!(err.to_string().contains("3-dimensional"), "{}", err);
+
+        // Other namespaces are tracked separately
+        dims.check("users", &upsert(vec![0.1, 0.2])).unwrap();
+        assert_eq!(dims.get("users"), Some(2));
+    }
+
+    #[test]
+    fn test_rejects_empty_and_non_finite_vectors() {
+        let mut dims = VectorDimensions::new();
+        assert!(dims.check("posts", &upsert(vec![])).is_err());
+        assert!(dims.check("posts", &upsert(vec![0.1, f32::NAN])).is_err());
+        assert_eq!(dims.get("posts"), None);
+    }
+
+    #[test]
+    fn test_ignores_actions_without_vectors() {
+        let mut dims = VectorDimensions::new();
+        dims.check("posts", &Action::upsert(1u64, HashMap::new()))
+            .unwrap();
+        dims.check("posts", &Action::delete(1u64)).unwrap();
+        assert_eq!(dims.get("posts"), None);
+    }
+}
@@ -74,6 +74,7 @@ fn parse_id_type(s: &str) -> IdType {
         "int" => IdType::Int,
         "uuid" => IdType::Uuid,
         "string" => IdType::String,
+        "bytes" => IdType::Bytes,
         _ => panic!("Unknown id type: {}", s),
     }
 }
@@ -167,7 +168,7 @@ fn run_fixture(fixture: &Fixture) -> Vec<Action> {
         let matched = router.route(&event);
 
         for m in matched {
-            let id = extract_id(&event, &m.id.column, m.id.id_type).unwrap();
+            let id = extract_id(&event, &m.id).unwrap();
             let action = transformer.transform(&event, id).unwrap();
             if action.requires_write() {
                 actions.push(action);
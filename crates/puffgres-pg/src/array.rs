@@ -0,0 +1,349 @@
+//! Parsing of PostgreSQL array values into `Value::Array`.
+//!
+//! Arrays come in two forms: the text literal (`{a,"b c",NULL}`) from
+//! pgoutput's text tuples, and the binary send format from binary tuples and
+//! the backfill scanner. Elements are converted by the caller, so an `int4[]`
+//! gives an array of `Value::Int`, a `text[]` an array of `Value::String`,
+//! and multidimensional arrays nest.
+//!
+//! pgvector's `vector` is decoded here too, into an array of floats.
+
+use std::io::Cursor;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use byteorder::{BigEndian, ReadBytesExt};
+use puffgres_core::Value;
+
+/// The element type of a built-in array type, by OID.
+pub(crate) fn array_element_oid(type_oid: u32) -> Option<u32> {
+    let element = match type_oid {
+        1000 => 16,   // bool[]
+        1001 => 17,   // bytea[]
+        1003 => 19,   // name[]
+        1005 => 21,   // int2[]
+        1007 => 23,   // int4[]
+        1016 => 20,   // int8[]
+        1021 => 700,  // float4[]
+        1022 => 701,  // float8[]
+        1231 => 1700, // numeric[]
+        1009 => 25,   // text[]
+        1014 => 1042, // bpchar[]
+        1015 => 1043, // varchar[]
+        199 => 114,   // json[]
+        3807 => 3802, // jsonb[]
+        2951 => 2950, // uuid[]
+        1182 => 1082, // date[]
+        1115 => 1114, // timestamp[]
+        1185 => 1184, // timestamptz[]
+        _ => return None,
+    };
+    Some(element)
+}
+
+/// Parse an array's text literal, converting each element with
+/// `parse_element`. Returns None if `s` isn't a well-formed array literal.
+pub(crate) fn parse_array_text<F>(s: &str, parse_element: F) -> Option<Value>
+where
+    F: Fn(&str) -> Value,
+{
+    let s = s.trim();
+    // Arrays with non-default bounds are prefixed with them: [0:1]={a,b}
+    let s = match s.strip_prefix('[') {
+        Some(_) => &s[s.find('=')? + 1..],
+        None => s,
+    };
+
+    let mut chars = s.chars().peekable();
+    let value = parse_text_dimension(&mut chars, &parse_element)?;
+    chars.all(char::is_whitespace).then_some(value)
+}
+
+fn parse_text_dimension<F>(chars: &mut Peekable<Chars<'_>>, parse_element: &F) -> Option<Value>
+where
+    F: Fn(&str) -> Value,
+{
+    skip_whitespace(chars);
+    if chars.next()? != '{' {
+        return None;
+    }
+    let mut items = Vec::new();
+    skip_whitespace(chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+        return Some(Value::Array(items));
+    }
+
+    loop {
+        skip_whitespace(chars);
+        let item = match chars.peek()? {
+            '{' => parse_text_dimension(chars, parse_element)?,
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next()? {
+                        '\\' => text.push(chars.next()?),
+                        '"' => break,
+                        c => text.push(c),
+                    }
+                }
+                parse_element(&text)
+            }
+            _ => {
+                let mut text = String::new();
+                let mut escaped = false;
+                while let Some(&c) = chars.peek() {
+                    if c == ',' || c == '}' {
+                        break;
+                    }
+                    chars.next();
+                    if c == '\\' {
+                        text.push(chars.next()?);
+                        escaped = true;
+                    } else {
+                        text.push(c);
+                    }
+                }
+                let text = text.trim_end();
+                // Only an unquoted, unescaped NULL is a null element
+                if !escaped && text.eq_ignore_ascii_case("null") {
+                    Value::Null
+                } else {
+                    parse_element(text)
+                }
+            }
+        };
+        items.push(item);
+
+        skip_whitespace(chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => return Some(Value::Array(items)),
+            _ => return None,
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars<'_>>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// Parse an array's binary send format, converting each element with
+/// `parse_element`, which gets the element's bytes and type OID. Returns
+/// None if the bytes aren't a well-formed array.
+pub(crate) fn parse_array_binary<F>(bytes: &[u8], parse_element: F) -> Option<Value>
+where
+    F: Fn(&[u8], u32) -> Value,
+{
+    let mut cursor = Cursor::new(bytes);
+    let ndim = usize::try_from(cursor.read_i32::<BigEndian>().ok()?).ok()?;
+    let _has_nulls = cursor.read_i32::<BigEndian>().ok()?;
+    let element_oid = cursor.read_u32::<BigEndian>().ok()?;
+    if ndim == 0 {
+        return Some(Value::Array(Vec::new()));
+    }
+
+    let mut dims = Vec::with_capacity(ndim);
+    for _ in 0..ndim {
+        dims.push(usize::try_from(cursor.read_i32::<BigEndian>().ok()?).ok()?);
+        let _lower_bound = cursor.read_i32::<BigEndian>().ok()?;
+    }
+
+    let count = dims.iter().try_fold(1usize, |n, &d| n.checked_mul(d))?;
+    let mut elements = Vec::new();
+    for _ in 0..count {
+        let len = cursor.read_i32::<BigEndian>().ok()?;
+        if len < 0 {
+            elements.push(Value::Null);
+            continue;
+        }
+        let start = cursor.position() as usize;
+        let end = start.checked_add(len as usize)?;
+        elements.push(parse_element(bytes.get(start..end)?, element_oid));
+        cursor.set_position(end as u64);
+    }
+    if cursor.position() as usize != bytes.len() {
+        return None;
+    }
+
+    Some(nest(&dims, &mut elements.into_iter()))
+}
+
+/// Parse pgvector's `vector` text form, `[0.1,0.2,0.3]`.
+pub(crate) fn parse_vector_text(s: &str) -> Option<Value> {
+    let inner = s.trim().strip_prefix('[')?.strip_suffix(']')?;
+    if inner.trim().is_empty() {
+        return Some(Value::Array(Vec::new()));
+    }
+    inner
+        .split(',')
+        .map(|v| v.trim().parse().ok().map(Value::Float))
+        .collect::<Option<Vec<_>>>()
+        .map(Value::Array)
+}
+
+/// Parse pgvector's `vector` binary form: the dimensions as an int16, an
+/// unused int16, then a float4 per dimension.
+pub(crate) fn parse_vector_binary(bytes: &[u8]) -> Option<Value> {
+    let dims = usize::from(u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]));
+    let values = bytes.get(4..)?;
+    if values.len() != dims * 4 {
+        return None;
+    }
+    let floats = values
+        .chunks_exact(4)
+        .map(|b| Value::Float(f32::from_be_bytes([b[0], b[1], b[2], b[3]]).into()))
+        .collect();
+    Some(Value::Array(floats))
+}
+
+/// Arrange a flat, row-major list of elements into nested arrays.
+fn nest(dims: &[usize], elements: &mut impl Iterator<Item = Value>) -> Value {
+    match dims {
+        [len] => Value::Array(elements.take(*len).collect()),
+        [len, rest @ ..] => Value::Array((0..*len).map(|_| nest(rest, elements)).collect()),
+        [] => Value::Array(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn int(s: &str) -> Value {
+        s.parse().map(Value::Int).unwrap_or_else(|_| text(s))
+    }
+
+    #[test]
+    fn test_parse_array_text() {
+        assert_eq!(
+            parse_array_text("{a,b,c}", text),
+            Some(Value::Array(vec![text("a"), text("b"), text("c")]))
+        );
+        assert_eq!(
+            parse_array_text("{1,NULL,3}", int),
+            Some(Value::Array(vec![
+                Value::Int(1),
+                Value::Null,
+                Value::Int(3)
+            ]))
+        );
+        assert_eq!(parse_array_text("{}", int), Some(Value::Array(vec![])));
+        assert_eq!(
+            parse_array_text("[0:1]={1,2}", int),
+            Some(Value::Array(vec![Value::Int(1), Value::Int(2)]))
+        );
+    }
+
+    #[test]
+    fn test_parse_array_text_quoting() {
+        assert_eq!(
+            parse_array_text(r#"{"a,b","say \"hi\"","NULL",back\\slash,"",  x y }"#, text),
+            Some(Value::Array(vec![
+                text("a,b"),
+                text("say \"hi\""),
+                text("NULL"),
+                text("back\\slash"),
+                text(""),
+                text("x y"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_array_text_nested() {
+        assert_eq!(
+            parse_array_text("{{1,2},{3,NULL}}", int),
+            Some(Value::Array(vec![
+                Value::Array(vec![Value::Int(1), Value::Int(2)]),
+                Value::Array(vec![Value::Int(3), Value::Null]),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_array_text_malformed() {
+        assert_eq!(parse_array_text("a,b", text), None);
+        assert_eq!(parse_array_text("{a,b", text), None);
+        assert_eq!(parse_array_text(r#"{"a}"#, text), None);
+        assert_eq!(parse_array_text("{a}x", text), None);
+    }
+
+    fn binary_array(element_oid: u32, dims: &[i32], elements: &[Option<i32>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(dims.len() as i32).to_be_bytes());
+        data.extend_from_slice(&(elements.iter().any(Option::is_none) as i32).to_be_bytes());
+        data.extend_from_slice(&element_oid.to_be_bytes());
+        for dim in dims {
+            data.extend_from_slice(&dim.to_be_bytes());
+            data.extend_from_slice(&1i32.to_be_bytes()); // lower bound
+        }
+        for element in elements {
+            match element {
+                Some(v) => {
+                    data.extend_from_slice(&4i32.to_be_bytes());
+                    data.extend_from_slice(&v.to_be_bytes());
+                }
+                None => data.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+        data
+    }
+
+    fn binary_int(bytes: &[u8], oid: u32) -> Value {
+        assert_eq!(oid, 23);
+        Value::Int(i32::from_be_bytes(bytes.try_into().unwrap()).into())
+    }
+
+    #[test]
+    fn test_parse_array_binary() {
+        let data = binary_array(23, &[3], &[Some(1), None, Some(3)]);
+        assert_eq!(
+            parse_array_binary(&data, binary_int),
+            Some(Value::Array(vec![
+                Value::Int(1),
+                Value::Null,
+                Value::Int(3)
+            ]))
+        );
+
+        let data = binary_array(23, &[2, 2], &[Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(
+            parse_array_binary(&data, binary_int),
+            Some(Value::Array(vec![
+                Value::Array(vec![Value::Int(1), Value::Int(2)]),
+                Value::Array(vec![Value::Int(3), Value::Int(4)]),
+            ]))
+        );
+
+        let empty = binary_array(23, &[], &[]);
+        assert_eq!(
+            parse_array_binary(&empty, binary_int),
+            Some(Value::Array(vec![]))
+        );
+
+        let truncated = &binary_array(23, &[2], &[Some(1), Some(2)])[..30];
+        assert_eq!(parse_array_binary(truncated, binary_int), None);
+    }
+
+    #[test]
+    fn test_parse_vector() {
+        let expected = Some(Value::Array(vec![Value::Float(0.5), Value::Float(-2.0)]));
+        assert_eq!(parse_vector_text("[0.5,-2]"), expected);
+        assert_eq!(parse_vector_text("[]"), Some(Value::Array(vec![])));
+        assert_eq!(parse_vector_text("{0.5}"), None);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&2u16.to_be_bytes()); // dimensions
+        data.extend_from_slice(&0u16.to_be_bytes()); // unused
+        data.extend_from_slice(&0.5f32.to_be_bytes());
+        data.extend_from_slice(&(-2.0f32).to_be_bytes());
+        assert_eq!(parse_vector_binary(&data), expected);
+        assert_eq!(parse_vector_binary(&data[..8]), None);
+    }
+}
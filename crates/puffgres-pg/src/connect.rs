@@ -3,14 +3,43 @@
 use std::sync::Arc;
 
 use rustls::ClientConfig;
-use tokio_postgres::Client;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_postgres::{AsyncMessage, Client, Connection, Notification};
 use tokio_postgres_rustls_improved::MakeRustlsConnect;
 
 use crate::error::{PgError, PgResult};
 
+/// The server rejecting the login (bad password, missing database) won't go
+/// away on retry, unlike failing to reach it.
+fn connect_error(e: tokio_postgres::Error) -> PgError {
+    if e.as_db_error().is_some() {
+        PgError::from(e)
+    } else {
+        PgError::Connection(e.to_string())
+    }
+}
+
 /// Connect to Postgres with appropriate TLS settings based on sslmode in connection string.
 /// Spawns the connection task and returns only the client.
 pub async fn connect_postgres(connection_string: &str) -> PgResult<Client> {
+    connect(connection_string, None).await
+}
+
+/// Connect like [`connect_postgres`], also returning the notifications the
+/// session receives once it runs `LISTEN`.
+pub async fn connect_postgres_listening(
+    connection_string: &str,
+) -> PgResult<(Client, UnboundedReceiver<Notification>)> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let client = connect(connection_string, Some(sender)).await?;
+    Ok((client, receiver))
+}
+
+async fn connect(
+    connection_string: &str,
+    notifications: Option<UnboundedSender<Notification>>,
+) -> PgResult<Client> {
     let requires_tls = requires_tls(connection_string);
 
     if requires_tls {
@@ -24,30 +53,51 @@ pub async fn connect_postgres(connection_string: &str) -> PgResult<Client> {
 
         let (client, connection) = tokio_postgres::connect(connection_string, connector)
             .await
-            .map_err(|e| PgError::Connection(e.to_string()))?;
-
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                tracing::error!(error = %e, "Postgres connection error");
-            }
-        });
+            .map_err(connect_error)?;
 
+        spawn_connection(connection, notifications);
         Ok(client)
     } else {
         let (client, connection) = tokio_postgres::connect(connection_string, tokio_postgres::NoTls)
             .await
-            .map_err(|e| PgError::Connection(e.to_string()))?;
-
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                tracing::error!(error = %e, "Postgres connection error");
-            }
-        });
+            .map_err(connect_error)?;
 
+        spawn_connection(connection, notifications);
         Ok(client)
     }
 }
 
+/// Drive the connection in the background, passing its notifications on to
+/// `notifications` if given.
+fn spawn_connection<S, T>(
+    mut connection: Connection<S, T>,
+    notifications: Option<UnboundedSender<Notification>>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            match std::future::poll_fn(|cx| connection.poll_message(cx)).await {
+                Some(Ok(AsyncMessage::Notification(notification))) => {
+                    if let Some(sender) = &notifications {
+                        let _ = sender.send(notification);
+                    }
+                }
+                Some(Ok(AsyncMessage::Notice(notice))) => {
+                    tracing::info!("{}: {}", notice.severity(), notice.message());
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    tracing::error!(error = %e, "Postgres connection error");
+                    break;
+                }
+                None => break,
+            }
+        }
+    });
+}
+
 /// Get root certificates from webpki-roots.
 fn root_certs() -> rustls::RootCertStore {
     let mut roots = rustls::RootCertStore::empty();
@@ -0,0 +1,343 @@
+//! Experimental source adapter for Debezium change events.
+//!
+//! Reads JSON messages produced by Debezium's Postgres connector (with or
+//! without the `schema`/`payload` envelope) and turns them into row events, so
+//! a Kafka topic can drive the same routing, transform and write pipeline as
+//! logical replication.
+//!
+//! The adapter doesn't talk to Kafka itself. The caller consumes the topic,
+//! sends each message with its offset down a channel, and commits offsets from
+//! the acknowledgment callback once a batch has been written.
+//!
+//! Column values are used as Debezium encodes them. Set
+//! `decimal.handling.mode=double` (or `string`) on the connector so numeric
+//! columns don't arrive as base64 bytes.
+
+use puffgres_core::{Operation, RowEvent, RowMap, SourceAdapter, SourceBatch, Value};
+use serde_json::Value as Json;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::error::{PgError, PgResult};
+
+/// Default maximum number of messages per batch.
+const DEFAULT_MAX_BATCH_SIZE: usize = 1000;
+
+/// A raw message from the change topic.
+#[derive(Debug, Clone)]
+pub struct DebeziumMessage {
+    /// Offset of the message in its partition.
+    pub offset: u64,
+    /// Message value; `None` for tombstones.
+    pub payload: Option<Vec<u8>>,
+}
+
+/// Source adapter over a channel of Debezium messages.
+pub struct DebeziumSource {
+    messages: mpsc::Receiver<DebeziumMessage>,
+    on_ack: Box<dyn FnMut(u64) + Send>,
+    acked: Option<u64>,
+    max_batch_size: usize,
+}
+
+impl DebeziumSource {
+    /// Create a source that reads from `messages` and calls `on_ack` with the
+    /// offset of each processed batch (e.g. to commit it to Kafka).
+    pub fn new(
+        messages: mpsc::Receiver<DebeziumMessage>,
+        on_ack: impl FnMut(u64) + Send + 'static,
+    ) -> Self {
+        Self {
+            messages,
+            on_ack: Box::new(on_ack),
+            acked: None,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+        }
+    }
+
+    /// Limit how many messages are collected into one batch.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+}
+
+impl SourceAdapter for DebeziumSource {
+    type Error = PgError;
+
+    /// Wait for at least one message, then take whatever else is already
+    /// queued, up to the batch size.
+    async fn next_batch(&mut self) -> PgResult<Option<SourceBatch>> {
+        let Some(first) = self.messages.recv().await else {
+            return Ok(None);
+        };
+
+        let mut position = first.offset;
+        let mut events = Vec::new();
+        push_decoded(&mut events, &first);
+
+        for _ in 1..self.max_batch_size {
+            let Ok(message) = self.messages.try_recv() else {
+                break;
+            };
+            position = position.max(message.offset);
+            push_decoded(&mut events, &message);
+        }
+
+        Ok(Some(SourceBatch {
+            events,
+            position,
+            continues: false,
+        }))
+    }
+
+    fn acknowledge(&mut self, position: u64) {
+        if self.acked.is_none_or(|acked| position > acked) {
+            self.acked = Some(position);
+            (self.on_ack)(position);
+        }
+    }
+
+    fn acknowledged_position(&self) -> u64 {
+        self.acked.unwrap_or(0)
+    }
+}
+
+/// Decode a message and add its event, skipping (with a warning) messages
+/// that can't be decoded so one bad message doesn't stall the topic.
+fn push_decoded(events: &mut Vec<RowEvent>, message: &DebeziumMessage) {
+    let Some(payload) = &message.payload else {
+        debug!(offset = message.offset, "Skipping tombstone");
+        return;
+    };
+
+    match decode_message(payload, message.offset) {
+        Ok(Some(event)) => events.push(event),
+        Ok(None) => {}
+        Err(e) => warn!(offset = message.offset, error = %e, "Failed to decode Debezium message"),
+    }
+}
+
+/// Decode one Debezium JSON change event.
+///
+/// Returns `None` for events that don't describe a row change (truncates and
+/// messages without a payload). The source LSN is used as the event position
+/// when present, otherwise the message offset.
+pub fn decode_message(bytes: &[u8], offset: u64) -> PgResult<Option<RowEvent>> {
+    let json: Json = serde_json::from_slice(bytes)?;
+
+    // With the JSON converter's schemas enabled, the event is under "payload"
+    let payload = match json.get("payload") {
+        Some(payload) if json.get("schema").is_some() => payload,
+        _ => &json,
+    };
+    if payload.is_null() {
+        return Ok(None);
+    }
+
+    let op = match payload.get("op").and_then(Json::as_str) {
+        Some("c") | Some("r") => Operation::Insert,
+        Some("u") => Operation::Update,
+        Some("d") => Operation::Delete,
+        Some("t") | Some("m") => return Ok(None),
+        other => {
+            return Err(PgError::ParseError(format!(
+                "unknown Debezium op {:?}",
+                other
+            )))
+        }
+    };
+
+    let source = payload
+        .get("source")
+        .ok_or_else(|| PgError::ParseError("Debezium event has no source block".into()))?;
+    let schema = source_str(source, "schema")?;
+    let table = source_str(source, "table")?;
+
+    let new = row_map(payload.get("after"));
+    let old = row_map(payload.get("before"));
+    match op {
+        Operation::Insert | Operation::Update if new.is_none() => {
+            return Err(PgError::ParseError(
+                "Debezium insert/update has no 'after' row".into(),
+            ));
+        }
+        Operation::Delete if old.is_none() => {
+            return Err(PgError::ParseError(
+                "Debezium delete has no 'before' row".into(),
+            ));
+        }
+        _ => {}
+    }
+
+    Ok(Some(RowEvent {
+        op,
+        schema,
+        table,
+        new,
+        old,
+        lsn: source.get("lsn").and_then(Json::as_u64).unwrap_or(offset),
+        txid: source.get("txId").and_then(Json::as_u64),
+        timestamp: source
+            .get("ts_ms")
+            .and_then(Json::as_i64)
+            .and_then(format_ts_ms),
+    }))
+}
+
+fn source_str(source: &Json, field: &str) -> PgResult<String> {
+    source
+        .get(field)
+        .and_then(Json::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| PgError::ParseError(format!("Debezium source has no '{}'", field)))
+}
+
+fn row_map(row: Option<&Json>) -> Option<RowMap> {
+    match row {
+        Some(Json::Object(fields)) => Some(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), Value::from(v.clone())))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Format epoch milliseconds like replication commit timestamps.
+fn format_ts_ms(ms: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn event(op: &str, before: Json, after: Json) -> Vec<u8> {
+        serde_json::json!({
+            "before": before,
+            "after": after,
+            "source": {
+                "connector": "postgresql",
+                "schema": "public",
+                "table": "users",
+                "txId": 770,
+                "lsn": 24023128,
+                "ts_ms": 1705314600000i64
+            },
+            "op": op,
+            "ts_ms": 1705314600123i64
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_decode_insert() {
+        let bytes = event(
+            "c",
+            Json::Null,
+            serde_json::json!({"id": 1, "name": "Alice"}),
+        );
+        let event = decode_message(&bytes, 5).unwrap().unwrap();
+
+        assert_eq!(event.op, Operation::Insert);
+        assert_eq!(event.schema, "public");
+        assert_eq!(event.table, "users");
+        assert_eq!(event.get_new("name"), Some(&Value::String("Alice".into())));
+        assert_eq!(event.lsn, 24023128);
+        assert_eq!(event.txid, Some(770));
+        assert_eq!(
+            event.timestamp.as_deref(),
+            Some("2024-01-15T10:30:00.000000Z")
+        );
+    }
+
+    #[test]
+    fn test_decode_update_delete_and_snapshot() {
+        let update = event(
+            "u",
+            serde_json::json!({"id": 1}),
+            serde_json::json!({"id": 1, "name": "Bob"}),
+        );
+        let update = decode_message(&update, 0).unwrap().unwrap();
+        assert_eq!(update.op, Operation::Update);
+        assert!(update.old.is_some());
+
+        let delete = event("d", serde_json::json!({"id": 1}), Json::Null);
+        let delete = decode_message(&delete, 0).unwrap().unwrap();
+        assert_eq!(delete.op, Operation::Delete);
+        assert_eq!(delete.get_old("id"), Some(&Value::Int(1)));
+
+        // Snapshot reads are treated as inserts
+        let read = event("r", Json::Null, serde_json::json!({"id": 2}));
+        assert_eq!(
+            decode_message(&read, 0).unwrap().unwrap().op,
+            Operation::Insert
+        );
+    }
+
+    #[test]
+    fn test_decode_schema_envelope() {
+        let inner: Json =
+            serde_json::from_slice(&event("c", Json::Null, serde_json::json!({"id": 1}))).unwrap();
+        let wrapped = serde_json::json!({"schema": {"type": "struct"}, "payload": inner});
+
+        let event = decode_message(wrapped.to_string().as_bytes(), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.get_new("id"), Some(&Value::Int(1)));
+    }
+
+    #[test]
+    fn test_decode_skips_and_rejects() {
+        let truncate = event("t", Json::Null, Json::Null);
+        assert!(decode_message(&truncate, 0).unwrap().is_none());
+
+        let missing_after = event("c", Json::Null, Json::Null);
+        assert!(decode_message(&missing_after, 0).is_err());
+
+        assert!(decode_message(b"not json", 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_source_batches_and_acks() {
+        let (tx, rx) = mpsc::channel(16);
+        let acked = Arc::new(Mutex::new(Vec::new()));
+        let acked_clone = acked.clone();
+        let mut source = DebeziumSource::new(rx, move |offset| {
+            acked_clone.lock().unwrap().push(offset);
+        })
+        .with_max_batch_size(2);
+
+        for (offset, payload) in [
+            (
+                10,
+                Some(event("c", Json::Null, serde_json::json!({"id": 1}))),
+            ),
+            (11, None),
+            (12, Some(b"garbage".to_vec())),
+        ] {
+            tx.send(DebeziumMessage { offset, payload }).await.unwrap();
+        }
+        drop(tx);
+
+        let batch = source.next_batch().await.unwrap().unwrap();
+        assert_eq!(batch.events.len(), 1);
+        assert_eq!(batch.position, 11);
+        source.acknowledge(batch.position);
+
+        // Undecodable messages are skipped but still advance the position
+        let batch = source.next_batch().await.unwrap().unwrap();
+        assert!(batch.events.is_empty());
+        assert_eq!(batch.position, 12);
+        source.acknowledge(batch.position);
+
+        assert!(source.next_batch().await.unwrap().is_none());
+        assert_eq!(source.acknowledged_position(), 12);
+        assert_eq!(*acked.lock().unwrap(), vec![11, 12]);
+    }
+}
@@ -1,3 +1,4 @@
+use puffgres_core::ErrorKind;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -8,6 +9,18 @@ pub enum PgError {
     #[error("connection failed: {0}")]
     Connection(String),
 
+    #[error("permission denied: {0}")]
+    Permission(String),
+
+    #[error("query doesn't fit the column types: {0}")]
+    TypeMismatch(String),
+
+    #[error("serialization conflict with a concurrent transaction: {0}")]
+    SerializationConflict(String),
+
+    #[error("replication slot is in use: {0}")]
+    SlotInUse(String),
+
     #[error("replication slot '{0}' does not exist")]
     SlotNotFound(String),
 
@@ -35,13 +48,136 @@ pub enum PgError {
     #[error("relation {0} not found in cache (missing Relation message)")]
     RelationNotFound(u32),
 
+    #[error(
+        "a row of {table} has {actual} columns, but its Relation message at {version} lists \
+         {expected}; the schema changed without the stream announcing it"
+    )]
+    RelationMismatch {
+        table: String,
+        /// LSN of the Relation message the row was decoded against.
+        version: String,
+        expected: usize,
+        actual: usize,
+    },
+
     #[error("publication '{0}' does not exist")]
     PublicationNotFound(String),
 
+    #[error(
+        "publication '{publication}' doesn't include {}, so their changes aren't replicated; \
+         add them on the primary with ALTER PUBLICATION ... ADD TABLE",
+        .tables.join(", ")
+    )]
+    PublicationMissingTables {
+        publication: String,
+        tables: Vec<String>,
+    },
+
+    #[error(
+        "table '{schema}.{table}' has REPLICA IDENTITY {identity}: {consequence}. \
+         Fix with ALTER TABLE {schema}.{table} REPLICA IDENTITY FULL \
+         (this logs the whole old row for every update and delete, so WAL volume grows)"
+    )]
+    ReplicaIdentity {
+        schema: String,
+        table: String,
+        identity: String,
+        consequence: String,
+    },
+
+    #[error("logical replication from a standby requires Postgres 16+, server is {version}")]
+    StandbyUnsupported { version: String },
+
+    #[error("replication slot '{slot}' is in use by another runner ({holder})")]
+    SlotLocked { slot: String, holder: String },
+
+    #[error("strict mode: {0}")]
+    Strict(String),
+
+    #[error(
+        "this database already has puffgres state in {}; pass --replace to overwrite it",
+        .0.join(", ")
+    )]
+    StateNotEmpty(Vec<String>),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+impl PgError {
+    /// Classify a server error by its SQLSTATE, so callers can tell a dropped
+    /// connection or a serialization failure from a bad query.
+    pub fn from_sqlstate(code: &str, message: String) -> Self {
+        match code {
+            // insufficient_privilege, invalid_authorization_specification,
+            // invalid_password
+            "42501" | "28000" | "28P01" => PgError::Permission(message),
+            // serialization_failure, deadlock_detected
+            "40001" | "40P01" => PgError::SerializationConflict(message),
+            // object_in_use: "replication slot ... is active for PID ..."
+            "55006" => PgError::SlotInUse(message),
+            // invalid_text_representation, numeric_value_out_of_range,
+            // invalid_datetime_format, datatype_mismatch, undefined_function
+            "22P02" | "22003" | "22007" | "42804" | "42883" => PgError::TypeMismatch(message),
+            // admin_shutdown, crash_shutdown, cannot_connect_now,
+            // too_many_connections
+            "57P01" | "57P02" | "57P03" | "53300" => PgError::Connection(message),
+            c if c.starts_with("08") => PgError::Connection(message),
+            _ => PgError::Postgres(message),
+        }
+    }
+
+    /// Classify an error from the replication protocol client, which only
+    /// hands us the server's message.
+    pub fn replication(message: String) -> Self {
+        if message.contains("is active for PID") {
+            PgError::SlotInUse(message)
+        } else if message.contains("permission denied") || message.contains("must be superuser") {
+            PgError::Permission(message)
+        } else {
+            PgError::Replication(message)
+        }
+    }
+
+    /// Whether the operation could succeed if tried again unchanged: the
+    /// connection dropped, a concurrent transaction got in the way, or another
+    /// process briefly still holds the slot.
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+
+    /// Classify the error, e.g. for the dead letter queue.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PgError::Connection(_) | PgError::Replication(_) | PgError::Io(_) => {
+                ErrorKind::NetworkError
+            }
+            // The server resends Relation messages when the stream restarts.
+            PgError::RelationNotFound(_) | PgError::RelationMismatch { .. } => {
+                ErrorKind::NetworkError
+            }
+            PgError::SerializationConflict(_) | PgError::SlotInUse(_) => ErrorKind::Conflict,
+            PgError::Permission(_) => ErrorKind::PermissionDenied,
+            PgError::TableNotFound { .. }
+            | PgError::PublicationNotFound(_)
+            | PgError::PublicationMissingTables { .. }
+            | PgError::ReplicaIdentity { .. }
+            | PgError::TypeMismatch(_) => ErrorKind::SchemaError,
+            PgError::ParseError(_)
+            | PgError::Json(_)
+            | PgError::InvalidLsn(_)
+            | PgError::PgOutput(_) => ErrorKind::InvalidData,
+            PgError::Postgres(_)
+            | PgError::SlotNotFound(_)
+            | PgError::SlotCreationFailed(_)
+            | PgError::StandbyUnsupported { .. }
+            | PgError::SlotLocked { .. }
+            | PgError::Strict(_)
+            | PgError::StateNotEmpty(_) => ErrorKind::Unknown,
+        }
+    }
+}
+
 impl From<tokio_postgres::Error> for PgError {
     fn from(e: tokio_postgres::Error) -> Self {
         // Extract database error details if available
@@ -52,11 +188,68 @@ impl From<tokio_postgres::Error> for PgError {
                 db_err.message(),
                 db_err.code().code()
             );
-            PgError::Postgres(msg)
+            PgError::from_sqlstate(db_err.code().code(), msg)
+        } else if e.is_closed()
+            || std::error::Error::source(&e).is_some_and(|s| s.is::<std::io::Error>())
+        {
+            PgError::Connection(e.to_string())
         } else {
             PgError::Postgres(e.to_string())
         }
     }
 }
 
+impl From<PgError> for puffgres_state::StateError {
+    fn from(e: PgError) -> Self {
+        if e.is_retryable() {
+            puffgres_state::StateError::Unavailable(e.to_string())
+        } else {
+            puffgres_state::StateError::Backend(e.to_string())
+        }
+    }
+}
+
 pub type PgResult<T> = Result<T, PgError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_sqlstate() {
+        let classify = |code| PgError::from_sqlstate(code, "msg".into());
+        assert!(matches!(classify("42501"), PgError::Permission(_)));
+        assert!(matches!(classify("28P01"), PgError::Permission(_)));
+        assert!(matches!(classify("40001"), PgError::SerializationConflict(_)));
+        assert!(matches!(classify("40P01"), PgError::SerializationConflict(_)));
+        assert!(matches!(classify("55006"), PgError::SlotInUse(_)));
+        assert!(matches!(classify("22P02"), PgError::TypeMismatch(_)));
+        assert!(matches!(classify("42883"), PgError::TypeMismatch(_)));
+        assert!(matches!(classify("08006"), PgError::Connection(_)));
+        assert!(matches!(classify("57P01"), PgError::Connection(_)));
+        assert!(matches!(classify("42P01"), PgError::Postgres(_)));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(PgError::Connection("reset".into()).is_retryable());
+        assert!(PgError::SerializationConflict("40001".into()).is_retryable());
+        assert!(PgError::SlotInUse("active for PID 42".into()).is_retryable());
+        assert!(PgError::RelationNotFound(16384).is_retryable());
+        assert!(!PgError::Permission("42501".into()).is_retryable());
+        assert!(!PgError::Postgres("syntax error".into()).is_retryable());
+        assert!(!PgError::Strict("decode".into()).is_retryable());
+    }
+
+    #[test]
+    fn test_replication_message() {
+        let err = PgError::replication(
+            "replication slot \"puffgres\" is active for PID 4242".into(),
+        );
+        assert!(matches!(err, PgError::SlotInUse(_)));
+        let err = PgError::replication("must be superuser or replication role".into());
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        let err = PgError::replication("connection reset by peer".into());
+        assert!(matches!(err, PgError::Replication(_)));
+    }
+}
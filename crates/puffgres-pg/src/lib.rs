@@ -1,17 +1,29 @@
+mod array;
 pub mod backfill;
 mod connect;
+mod copy;
+pub mod debezium;
 mod error;
 pub mod migrations;
 pub mod replication;
 pub mod state;
 
-pub use backfill::{BackfillConfig, BackfillProgress as BackfillScanProgress, BackfillScanner};
+pub use backfill::{
+    BackfillConfig, BackfillObserver, BackfillProgress as BackfillScanProgress, BackfillScanner,
+    IdRange,
+};
+pub use connect::{connect_postgres, connect_postgres_listening};
+pub use debezium::{DebeziumMessage, DebeziumSource};
 pub use error::{PgError, PgResult};
-pub use migrations::{compute_content_hash, LocalMigration, MigrationStatus, MigrationTracker};
+pub use migrations::{
+    compute_content_hash, LocalMigration, MigrationRevision, MigrationStatus, MigrationTracker,
+};
 pub use replication::{
-    format_lsn, parse_lsn, ReplicationStream, ReplicationStreamConfig, StreamingBatch,
+    format_lsn, notify_reload, parse_lsn, ControlListener, ReplicationStream,
+    ReplicationStreamConfig, StreamingBatch,
 };
 pub use state::{
-    AppliedMigration, BackfillProgress, Checkpoint, DlqEntry, IdColumnSample, PostgresStateStore,
-    StoredTransform,
+    AppliedMigration, BackfillPartition, BackfillProgress, BackfillVerification, Checkpoint,
+    DlqEntry, IdColumnSample, PostgresStateStore, StateSnapshot, StoredTransform,
+    STATE_SNAPSHOT_VERSION, STATE_TABLES,
 };
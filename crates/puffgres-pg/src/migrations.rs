@@ -2,6 +2,11 @@
 //!
 //! Handles applying new migrations and validating that local migration files
 //! match the hashes of already-applied migrations.
+//!
+//! Applied migrations can't change. A mapping is revised by a new migration
+//! with the same `mapping_name` and a higher version, which supersedes the
+//! earlier ones once applied. A shadow revision only supersedes them once
+//! it's promoted, and runs beside them until then.
 
 use sha2::{Digest, Sha256};
 use tracing::{info, warn};
@@ -18,6 +23,21 @@ pub struct MigrationStatus {
     pub pending: Vec<String>,
     /// Migrations that have hash mismatches (error condition).
     pub mismatched: Vec<MigrationMismatch>,
+    /// Pending migrations that revise an applied mapping.
+    pub revisions: Vec<MigrationRevision>,
+}
+
+/// A pending migration that supersedes an applied migration of the same
+/// mapping. Documents already written follow the old migration until the
+/// mapping is backfilled again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationRevision {
+    pub mapping_name: String,
+    pub version: i32,
+    /// The latest applied version it replaces.
+    pub replaces: i32,
+    /// Whether it's built beside that version until promoted.
+    pub shadow: bool,
 }
 
 /// A migration hash mismatch.
@@ -46,15 +66,30 @@ pub struct LocalMigration {
     pub version: i32,
     pub mapping_name: String,
     pub content: String,
+    /// Whether it's a shadow revision, which doesn't supersede the earlier
+    /// migrations until it's promoted.
+    pub shadow: bool,
+    /// Contents of the templates it extends, nearest first. Editing one
+    /// changes the migration's hash like editing the file itself.
+    pub templates: Vec<String>,
 }
 
 impl LocalMigration {
-    /// Compute the content hash (SHA-256).
+    /// Compute the content hash (SHA-256) of the file and the templates it
+    /// extends. A migration without templates hashes as its file alone.
     ///
     /// Line endings are normalized to LF before hashing to ensure consistent
     /// hashes across different platforms (Windows CRLF vs Unix LF).
     pub fn content_hash(&self) -> String {
-        compute_content_hash(&self.content)
+        if self.templates.is_empty() {
+            return compute_content_hash(&self.content);
+        }
+        let mut combined = self.content.clone();
+        for template in &self.templates {
+            combined.push_str("\n# extends\n");
+            combined.push_str(template);
+        }
+        compute_content_hash(&combined)
     }
 }
 
@@ -82,6 +117,7 @@ impl<'a> MigrationTracker<'a> {
             applied: Vec::new(),
             pending: Vec::new(),
             mismatched: Vec::new(),
+            revisions: Vec::new(),
         };
 
         for migration in local {
@@ -110,6 +146,22 @@ impl<'a> MigrationTracker<'a> {
                 status
                     .pending
                     .push(format!("v{} {}", migration.version, migration.mapping_name));
+
+                let replaces = applied
+                    .iter()
+                    .filter(|a| {
+                        a.mapping_name == migration.mapping_name && a.version < migration.version
+                    })
+                    .map(|a| a.version)
+                    .max();
+                if let Some(replaces) = replaces {
+                    status.revisions.push(MigrationRevision {
+                        mapping_name: migration.mapping_name.clone(),
+                        version: migration.version,
+                        replaces,
+                        shadow: migration.shadow,
+                    });
+                }
             }
         }
 
@@ -171,6 +223,11 @@ impl<'a> MigrationTracker<'a> {
                 self.store
                     .record_migration(migration.version, &migration.mapping_name, &hash)
                     .await?;
+                if !migration.shadow {
+                    self.store
+                        .supersede_migrations(&migration.mapping_name, migration.version)
+                        .await?;
+                }
 
                 info!(
                     version = migration.version,
@@ -251,6 +308,8 @@ mod tests {
             version: 1,
             mapping_name: "users".to_string(),
             content: "version = 1\nmapping_name = \"users\"".to_string(),
+            shadow: false,
+            templates: Vec::new(),
         };
 
         let hash = migration.content_hash();
@@ -262,6 +321,8 @@ mod tests {
             version: 1,
             mapping_name: "users".to_string(),
             content: "version = 1\nmapping_name = \"users\"".to_string(),
+            shadow: false,
+            templates: Vec::new(),
         };
         assert_eq!(migration.content_hash(), migration2.content_hash());
     }
@@ -272,17 +333,48 @@ mod tests {
             version: 1,
             mapping_name: "users".to_string(),
             content: "content1".to_string(),
+            shadow: false,
+            templates: Vec::new(),
         };
 
         let m2 = LocalMigration {
             version: 1,
             mapping_name: "users".to_string(),
             content: "content2".to_string(),
+            shadow: false,
+            templates: Vec::new(),
         };
 
         assert_ne!(m1.content_hash(), m2.content_hash());
     }
 
+    #[test]
+    fn test_content_hash_covers_templates() {
+        let content = "extends = \"base.toml\"\nversion = 1\n".to_string();
+        let migration = |templates: &[&str]| LocalMigration {
+            version: 1,
+            mapping_name: "users".to_string(),
+            content: content.clone(),
+            shadow: false,
+            templates: templates.iter().map(|t| t.to_string()).collect(),
+        };
+
+        let base = migration(&["batch_max_rows = 250\n"]);
+        let edited = migration(&["batch_max_rows = 500\n"]);
+        assert_ne!(base.content_hash(), edited.content_hash());
+        assert_ne!(base.content_hash(), migration(&[]).content_hash());
+        assert_eq!(
+            base.content_hash(),
+            migration(&["batch_max_rows = 250\r\n"]).content_hash()
+        );
+
+        // Without templates the hash is the file's, as before
+        assert_eq!(
+            migration(&[]).content_hash(),
+            compute_content_hash(&content)
+        );
+    }
+
     #[test]
     fn test_compute_content_hash() {
         let hash = compute_content_hash("test content");
@@ -302,12 +394,16 @@ mod tests {
             version: 1,
             mapping_name: "users".to_string(),
             content: lf_content.to_string(),
+            shadow: false,
+            templates: Vec::new(),
         };
 
         let crlf_migration = LocalMigration {
             version: 1,
             mapping_name: "users".to_string(),
             content: crlf_content.to_string(),
+            shadow: false,
+            templates: Vec::new(),
         };
 
         // Both should produce the same hash after normalization
@@ -1,22 +1,39 @@
 //! True push-based streaming replication client using pgwire-replication.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
 use pgwire_replication::{ReplicationClient, ReplicationConfig as PgwireConfig, ReplicationEvent};
-use puffgres_core::{Operation, RowEvent, Value};
+use puffgres_core::{Operation, RowEvent, SourceAdapter, SourceBatch, Value};
 use tracing::{debug, info, warn};
 
 use tokio_postgres::Client;
 
 use super::lsn::{format_lsn, parse_lsn};
-use super::pgoutput::{ColumnInfo, ColumnValue, PgOutputDecoder, PgOutputMessage};
-use super::publication::ensure_publication;
-use super::relation_cache::RelationCache;
-use super::slot::{ensure_slot, get_confirmed_flush_lsn};
+use super::pgoutput::{
+    ColumnValue, DeleteMessage, InsertMessage, PgOutputDecoder, PgOutputMessage, TruncateMessage,
+    TupleData, UpdateMessage,
+};
+use super::publication::{
+    ensure_publication, get_publication_tables, publication_exists, require_publication_tables,
+};
+use super::relation_cache::{RelationCache, RelationInfo};
+use super::slot::{advance_slot, ensure_slot, get_confirmed_flush_lsn, slot_exists};
+use super::snapshot::{create_slot_with_snapshot, SlotSnapshot};
+use super::spool::{EventSpool, SpoolReader};
+use super::standby::{
+    check_standby_settings, detect_timeline_change, get_current_wal_lsn, get_server_info,
+    ServerInfo, TimelineChange,
+};
 use super::validation::validate_all_tables_readable;
+use crate::array::{array_element_oid, parse_array_binary, parse_array_text, parse_vector_text};
 use crate::error::{PgError, PgResult};
 
+/// How many times, and how long apart, [`ReplicationStream::close`] checks
+/// whether the server has released the slot.
+const SLOT_RELEASE_ATTEMPTS: u32 = 10;
+const SLOT_RELEASE_WAIT: Duration = Duration::from_millis(200);
+
 /// Configuration for streaming replication.
 #[derive(Debug, Clone)]
 pub struct ReplicationStreamConfig {
@@ -30,6 +47,9 @@ pub struct ReplicationStreamConfig {
     pub create_slot: bool,
     /// Whether to create the publication if it doesn't exist.
     pub create_publication: bool,
+    /// Whether to add missing tables to an existing publication. When off,
+    /// missing tables are an error.
+    pub alter_publication: bool,
     /// Tables to include in the publication (if creating).
     /// Format: "schema.table"
     pub publication_tables: Vec<String>,
@@ -37,6 +57,21 @@ pub struct ReplicationStreamConfig {
     pub start_lsn: Option<u64>,
     /// Status update interval for keepalives.
     pub status_interval: Duration,
+    /// Fail instead of skipping messages and rows that can't be decoded.
+    pub fail_on_decode_error: bool,
+    /// Fail instead of skipping TRUNCATE on a published table.
+    pub fail_on_truncate: bool,
+    /// Columns to keep in Postgres' text form instead of decoding by type,
+    /// by `schema.table`. Keeps every digit of a `numeric`, for example.
+    pub text_columns: HashMap<String, HashSet<String>>,
+    /// Columns to decode, by `schema.table`. Values of the other columns are
+    /// skipped rather than decoded and held in each row. A table missing from
+    /// the map is decoded whole.
+    pub decoded_columns: HashMap<String, HashSet<String>>,
+    /// Events of one transaction held in memory before the rest go to a
+    /// temporary file. A spilled transaction is delivered in batches of this
+    /// many events.
+    pub spill_threshold: usize,
 }
 
 impl Default for ReplicationStreamConfig {
@@ -47,9 +82,15 @@ impl Default for ReplicationStreamConfig {
             publication_name: "puffgres_pub".to_string(),
             create_slot: true,
             create_publication: true,
+            alter_publication: true,
             publication_tables: vec![],
             start_lsn: None,
             status_interval: Duration::from_secs(10),
+            fail_on_decode_error: false,
+            fail_on_truncate: false,
+            text_columns: HashMap::new(),
+            decoded_columns: HashMap::new(),
+            spill_threshold: 100_000,
         }
     }
 }
@@ -61,13 +102,25 @@ pub struct StreamingBatch {
     pub events: Vec<RowEvent>,
     /// The LSN to acknowledge after processing.
     pub ack_lsn: u64,
+    /// Whether more batches of the same transaction follow.
+    pub continues: bool,
 }
 
 /// State for the current transaction being assembled.
 struct TransactionState {
     xid: u32,
     timestamp: i64,
-    events: Vec<RowEvent>,
+    events: EventSpool,
+}
+
+/// A committed transaction too large for one batch, being delivered in
+/// chunks.
+struct PendingTransaction {
+    reader: SpoolReader,
+    /// Acknowledged once the last chunk is delivered.
+    end_lsn: u64,
+    /// Commit time for events that didn't have it when they were received.
+    timestamp: Option<String>,
 }
 
 /// True push-based streaming replication client.
@@ -83,8 +136,18 @@ pub struct ReplicationStream {
     decoder: PgOutputDecoder,
     /// Current transaction being assembled.
     current_txn: Option<TransactionState>,
+    /// In-progress transactions the server is streaming, by xid.
+    streamed: HashMap<u32, TransactionState>,
+    /// The transaction of the current stream segment.
+    stream_xid: Option<u32>,
+    /// Committed transaction still being delivered.
+    pending: Option<PendingTransaction>,
     /// Last acknowledged LSN.
     ack_lsn: u64,
+    /// Configuration used to (re)open the replication connection.
+    config: ReplicationStreamConfig,
+    /// Role and timeline of the server as of the last (re)connect.
+    server: ServerInfo,
 }
 
 impl ReplicationStream {
@@ -95,6 +158,10 @@ impl ReplicationStream {
     /// 2. Ensure the publication exists (create if needed)
     /// 3. Start the streaming replication connection
     ///
+    /// The server may be a Postgres 16+ standby. In that case the publication
+    /// must already exist (it is created on the primary), and only the slot is
+    /// created here.
+    ///
     /// The `control_client` is a tokio-postgres Client used for control plane operations
     /// (creating slots, publications, querying LSN). This should be separate from the
     /// replication connection. pgwire-replication handles only the replication plane.
@@ -105,9 +172,157 @@ impl ReplicationStream {
             "Connecting for streaming replication"
         );
 
+        let server = get_server_info(control_client).await?;
+        server.check_can_decode()?;
+        if server.in_recovery {
+            info!(
+                version = server.version_num,
+                timeline = ?server.timeline_id,
+                "Streaming from a standby"
+            );
+        }
+
         // First ensure prerequisites using the control plane connection
-        Self::ensure_prerequisites(&config, control_client).await?;
+        Self::ensure_prerequisites(&config, &server, control_client).await?;
+
+        // Get start LSN
+        let start_lsn = if let Some(lsn) = config.start_lsn {
+            lsn
+        } else {
+            // Get current confirmed_flush_lsn from slot
+            Self::get_confirmed_lsn(&config, control_client)
+                .await?
+                .unwrap_or(0)
+        };
+
+        let client = Self::open(&config, start_lsn).await?;
+
+        Ok(Self {
+            client,
+            relation_cache: RelationCache::with_text_columns(config.text_columns.clone())
+                .with_decoded_columns(config.decoded_columns.clone()),
+            decoder: PgOutputDecoder::new(),
+            current_txn: None,
+            streamed: HashMap::new(),
+            stream_xid: None,
+            pending: None,
+            ack_lsn: start_lsn,
+            config,
+            server,
+        })
+    }
+
+    /// Set up the slot and publication without streaming, and return the
+    /// server's current WAL position.
+    ///
+    /// The slot retains every change committed after the returned LSN, so a
+    /// snapshot read after this call (such as a backfill) can be caught up by
+    /// streaming from it.
+    pub async fn prepare(
+        config: &ReplicationStreamConfig,
+        control_client: &Client,
+    ) -> PgResult<u64> {
+        let server = get_server_info(control_client).await?;
+        server.check_can_decode()?;
+        Self::ensure_prerequisites(config, &server, control_client).await?;
+
+        get_current_wal_lsn(control_client).await
+    }
+
+    /// Like [`prepare`](Self::prepare), but when the slot doesn't exist yet it
+    /// is created with an exported snapshot, which is returned too. The LSN
+    /// is then the slot's consistent point, so a backfill that reads through
+    /// the snapshot and streaming from the LSN neither miss nor repeat a
+    /// change.
+    ///
+    /// Existing slots and standbys get no snapshot, and behave as `prepare`.
+    pub async fn prepare_with_snapshot(
+        config: &ReplicationStreamConfig,
+        control_client: &Client,
+    ) -> PgResult<(u64, Option<SlotSnapshot>)> {
+        let server = get_server_info(control_client).await?;
+        server.check_can_decode()?;
+
+        if server.in_recovery
+            || !config.create_slot
+            || slot_exists(control_client, &config.slot_name).await?
+        {
+            Self::ensure_prerequisites(config, &server, control_client).await?;
+            let lsn = get_current_wal_lsn(control_client).await?;
+            return Ok((lsn, None));
+        }
+
+        if !config.publication_tables.is_empty() {
+            validate_all_tables_readable(control_client, &config.publication_tables).await?;
+        }
+        // Create the publication first: decoding looks it up as of each
+        // change, so it must already exist at the slot's consistent point
+        Self::ensure_publication(config, control_client).await?;
+
+        let snapshot =
+            create_slot_with_snapshot(&config.connection_string, &config.slot_name).await?;
+        Ok((snapshot.lsn, Some(snapshot)))
+    }
+
+    /// Re-open the replication connection after it was lost.
+    ///
+    /// Streaming resumes from the last acknowledged LSN, so any partially
+    /// received transaction is discarded and delivered again. Returns the
+    /// timeline change if the server was promoted or switched timelines
+    /// since the previous connection.
+    pub async fn reconnect(&mut self, control_client: &Client) -> PgResult<Option<TimelineChange>> {
+        let server = get_server_info(control_client).await?;
+        server.check_can_decode()?;
+
+        let change = detect_timeline_change(&self.server, &server);
+        match &change {
+            Some(TimelineChange::Promoted { from, to }) => {
+                warn!(
+                    from_timeline = ?from,
+                    to_timeline = ?to,
+                    "Standby was promoted, resuming on the new primary"
+                );
+            }
+            Some(TimelineChange::Switched { from, to }) => {
+                warn!(
+                    from_timeline = from,
+                    to_timeline = to,
+                    "Server switched timelines, resuming from last acknowledged LSN"
+                );
+            }
+            None => {}
+        }
 
+        if !slot_exists(control_client, &self.config.slot_name).await? {
+            return Err(PgError::SlotNotFound(self.config.slot_name.clone()));
+        }
+
+        info!(
+            lsn = %format_lsn(self.ack_lsn),
+            "Reconnecting replication stream"
+        );
+        self.client = Self::open(&self.config, self.ack_lsn).await?;
+
+        // The server resends Relation messages on a new connection
+        self.relation_cache = RelationCache::with_text_columns(self.config.text_columns.clone())
+            .with_decoded_columns(self.config.decoded_columns.clone());
+        self.decoder = PgOutputDecoder::new();
+        self.current_txn = None;
+        self.streamed.clear();
+        self.stream_xid = None;
+        self.pending = None;
+        self.server = server;
+
+        Ok(change)
+    }
+
+    /// Role and timeline of the server as of the last (re)connect.
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server
+    }
+
+    /// Open the pgwire-replication connection, streaming from `start_lsn`.
+    async fn open(config: &ReplicationStreamConfig, start_lsn: u64) -> PgResult<ReplicationClient> {
         // Parse connection string to extract host, port, user, password, database
         let conn_params = Self::parse_connection_string(&config.connection_string)?;
 
@@ -121,14 +336,7 @@ impl ReplicationStream {
             "Parsed connection parameters for pgwire-replication"
         );
 
-        // Get start LSN
-        let start_lsn = if let Some(lsn) = config.start_lsn {
-            pgwire_replication::Lsn::from(lsn)
-        } else {
-            // Get current confirmed_flush_lsn from slot
-            let lsn = Self::get_confirmed_lsn(&config, control_client).await?;
-            pgwire_replication::Lsn::from(lsn.unwrap_or(0))
-        };
+        let start_lsn = pgwire_replication::Lsn::from(start_lsn);
 
         info!(start_lsn = %format_lsn(start_lsn.into()), "Starting replication stream");
 
@@ -136,12 +344,7 @@ impl ReplicationStream {
         let tls_mode_str = conn_params.sslmode.as_deref().unwrap_or("disabled");
         debug!(sslmode = %tls_mode_str, "Configuring TLS for pgwire-replication");
 
-        let tls = match conn_params.sslmode.as_deref() {
-            Some("require") => pgwire_replication::TlsConfig::require(),
-            Some("verify-ca") => pgwire_replication::TlsConfig::verify_ca(None),
-            Some("verify-full") => pgwire_replication::TlsConfig::verify_full(None),
-            _ => pgwire_replication::TlsConfig::disabled(),
-        };
+        let tls = conn_params.tls();
 
         // Build pgwire-replication config
         let pgwire_config = PgwireConfig {
@@ -175,25 +378,25 @@ impl ReplicationStream {
                 error = %e,
                 "pgwire-replication connection failed"
             );
-            PgError::Replication(e.to_string())
+            PgError::replication(e.to_string())
         })?;
 
         info!("pgwire-replication connection established successfully");
 
-        Ok(Self {
-            client,
-            relation_cache: RelationCache::new(),
-            decoder: PgOutputDecoder::new(),
-            current_txn: None,
-            ack_lsn: start_lsn.into(),
-        })
+        Ok(client)
     }
 
     /// Receive the next batch of row events.
     ///
     /// This blocks until a complete transaction is received or the stream ends.
+    /// A transaction larger than `spill_threshold` is returned over several
+    /// calls, and only its last batch acknowledges past it.
     /// Returns None if the stream has ended.
     pub async fn recv_batch(&mut self) -> PgResult<Option<StreamingBatch>> {
+        if let Some(batch) = self.next_pending_chunk()? {
+            return Ok(Some(batch));
+        }
+
         info!("Waiting for replication events...");
 
         loop {
@@ -201,7 +404,7 @@ impl ReplicationStream {
                 .client
                 .recv()
                 .await
-                .map_err(|e| PgError::Replication(e.to_string()))?;
+                .map_err(|e| PgError::replication(e.to_string()))?;
 
             debug!("Received event: {:?}", event);
 
@@ -217,6 +420,12 @@ impl ReplicationStream {
                     // Decode pgoutput message
                     let msg = match self.decoder.decode(&data) {
                         Ok(m) => m,
+                        Err(e) if self.config.fail_on_decode_error => {
+                            return Err(PgError::Strict(format!(
+                                "failed to decode pgoutput message: {}",
+                                e
+                            )));
+                        }
                         Err(e) => {
                             warn!(error = %e, "Failed to decode pgoutput message");
                             continue;
@@ -225,6 +434,12 @@ impl ReplicationStream {
 
                     debug!(wal_end = %format_lsn(wal_end_u64), msg = ?msg, "Decoded XLogData");
 
+                    // Changes inside a stream belong to the streamed transaction
+                    let (msg, subxid) = match msg {
+                        PgOutputMessage::Streamed { xid, message } => (*message, Some(xid)),
+                        msg => (msg, None),
+                    };
+
                     // Handle Begin/Commit from XLogData (pgoutput encodes them here)
                     match &msg {
                         PgOutputMessage::Begin(begin) => {
@@ -232,43 +447,120 @@ impl ReplicationStream {
                             self.current_txn = Some(TransactionState {
                                 xid: begin.xid,
                                 timestamp: begin.timestamp,
-                                events: Vec::new(),
+                                events: EventSpool::new(self.config.spill_threshold),
                             });
                         }
                         PgOutputMessage::Commit(commit) => {
                             info!(lsn = %format_lsn(commit.end_lsn), "Transaction commit");
                             if let Some(txn) = self.current_txn.take() {
-                                return Ok(Some(StreamingBatch {
-                                    events: txn.events,
-                                    ack_lsn: commit.end_lsn,
-                                }));
+                                return self.finish_transaction(txn, commit.end_lsn, None);
+                            }
+                        }
+                        PgOutputMessage::StreamStart(start) => {
+                            debug!(xid = start.xid, first = start.first_segment, "Stream start");
+                            let threshold = self.config.spill_threshold;
+                            self.streamed
+                                .entry(start.xid)
+                                .or_insert_with(|| TransactionState {
+                                    xid: start.xid,
+                                    // Only known at commit
+                                    timestamp: 0,
+                                    events: EventSpool::new(threshold),
+                                });
+                            self.stream_xid = Some(start.xid);
+                        }
+                        PgOutputMessage::StreamStop => {
+                            self.stream_xid = None;
+                        }
+                        PgOutputMessage::StreamCommit(commit) => {
+                            info!(
+                                xid = commit.xid,
+                                lsn = %format_lsn(commit.end_lsn),
+                                "Streamed transaction commit"
+                            );
+                            if let Some(txn) = self.streamed.remove(&commit.xid) {
+                                let timestamp = format_pg_timestamp(commit.timestamp);
+                                return self.finish_transaction(
+                                    txn,
+                                    commit.end_lsn,
+                                    Some(timestamp),
+                                );
+                            }
+                        }
+                        PgOutputMessage::StreamAbort(abort) => {
+                            if abort.xid == abort.subxid {
+                                info!(xid = abort.xid, "Streamed transaction aborted");
+                                self.streamed.remove(&abort.xid);
+                            } else if let Some(txn) = self.streamed.get_mut(&abort.xid) {
+                                txn.events.abort_subtransaction(abort.subxid);
                             }
                         }
                         PgOutputMessage::Relation(rel) => {
                             debug!(table = %rel.name, "Relation metadata");
-                            self.relation_cache.update(rel);
+                            self.relation_cache.update(rel, wal_end_u64);
+                        }
+                        PgOutputMessage::Type(ty) => {
+                            debug!(name = %ty.name, oid = ty.type_id, "Type metadata");
+                            self.relation_cache.update_type(ty);
                         }
                         PgOutputMessage::Insert(insert) => {
-                            if self.current_txn.is_some() {
-                                if let Ok(event) = self.to_row_event_insert(insert, wal_end_u64) {
-                                    info!(op = "insert", table = %event.table, "Row change");
-                                    self.current_txn.as_mut().unwrap().events.push(event);
-                                }
+                            if let Some(txn_info) = self.txn_info(subxid) {
+                                let event = insert_event(
+                                    &self.relation_cache,
+                                    insert,
+                                    wal_end_u64,
+                                    txn_info,
+                                );
+                                self.push_row_event("insert", event, subxid)?;
                             }
                         }
                         PgOutputMessage::Update(update) => {
-                            if self.current_txn.is_some() {
-                                if let Ok(event) = self.to_row_event_update(update, wal_end_u64) {
-                                    info!(op = "update", table = %event.table, "Row change");
-                                    self.current_txn.as_mut().unwrap().events.push(event);
-                                }
+                            if let Some(txn_info) = self.txn_info(subxid) {
+                                let event = update_event(
+                                    &self.relation_cache,
+                                    update,
+                                    wal_end_u64,
+                                    txn_info,
+                                );
+                                self.push_row_event("update", event, subxid)?;
                             }
                         }
                         PgOutputMessage::Delete(delete) => {
-                            if self.current_txn.is_some() {
-                                if let Ok(event) = self.to_row_event_delete(delete, wal_end_u64) {
-                                    info!(op = "delete", table = %event.table, "Row change");
-                                    self.current_txn.as_mut().unwrap().events.push(event);
+                            if let Some(txn_info) = self.txn_info(subxid) {
+                                let event = delete_event(
+                                    &self.relation_cache,
+                                    delete,
+                                    wal_end_u64,
+                                    txn_info,
+                                );
+                                self.push_row_event("delete", event, subxid)?;
+                            }
+                        }
+                        PgOutputMessage::Truncate(truncate) => {
+                            let tables: Vec<String> = truncate
+                                .relation_ids
+                                .iter()
+                                .map(|id| match self.relation_cache.get(*id) {
+                                    Some(rel) => format!("{}.{}", rel.namespace, rel.name),
+                                    None => format!("oid {}", id),
+                                })
+                                .collect();
+                            if self.config.fail_on_truncate {
+                                return Err(PgError::Strict(format!(
+                                    "TRUNCATE of {} can't be replicated",
+                                    tables.join(", ")
+                                )));
+                            }
+                            // Each mapping decides what a truncate does to its namespace
+                            if let Some(txn_info) = self.txn_info(subxid) {
+                                let events = truncate_events(
+                                    &self.relation_cache,
+                                    truncate,
+                                    wal_end_u64,
+                                    txn_info,
+                                );
+                                for event in events {
+                                    self.push_row_event("truncate", event, subxid)?;
                                 }
                             }
                         }
@@ -289,133 +581,124 @@ impl ReplicationStream {
                     self.current_txn = Some(TransactionState {
                         xid,
                         timestamp: commit_time_micros,
-                        events: Vec::new(),
+                        events: EventSpool::new(self.config.spill_threshold),
                     });
                 }
                 ReplicationEvent::Commit { end_lsn, .. } => {
                     let end_lsn_u64: u64 = end_lsn.into();
                     info!(lsn = %format_lsn(end_lsn_u64), "Transaction commit (protocol event)");
                     if let Some(txn) = self.current_txn.take() {
-                        return Ok(Some(StreamingBatch {
-                            events: txn.events,
-                            ack_lsn: end_lsn_u64,
-                        }));
+                        return self.finish_transaction(txn, end_lsn_u64, None);
                     }
                 }
             }
         }
     }
 
-    fn to_row_event_insert(
-        &self,
-        insert: &super::pgoutput::InsertMessage,
-        lsn: u64,
-    ) -> PgResult<RowEvent> {
-        let relation = self
-            .relation_cache
-            .get(insert.relation_id)
-            .ok_or(PgError::RelationNotFound(insert.relation_id))?;
-
-        let new = self.tuple_to_row_map(&insert.tuple, &relation.columns)?;
-        let (txid, timestamp) = self.current_txn_info();
-
-        Ok(RowEvent {
-            op: Operation::Insert,
-            schema: relation.namespace.clone(),
-            table: relation.name.clone(),
-            new: Some(new),
-            old: None,
-            lsn,
-            txid,
-            timestamp,
-        })
-    }
-
-    fn to_row_event_update(
-        &self,
-        update: &super::pgoutput::UpdateMessage,
-        lsn: u64,
-    ) -> PgResult<RowEvent> {
-        let relation = self
-            .relation_cache
-            .get(update.relation_id)
-            .ok_or(PgError::RelationNotFound(update.relation_id))?;
-
-        let new = self.tuple_to_row_map(&update.new_tuple, &relation.columns)?;
-        let old = update
-            .old_tuple
-            .as_ref()
-            .map(|t| self.tuple_to_row_map(t, &relation.columns))
-            .transpose()?;
-        let (txid, timestamp) = self.current_txn_info();
-
-        Ok(RowEvent {
-            op: Operation::Update,
-            schema: relation.namespace.clone(),
-            table: relation.name.clone(),
-            new: Some(new),
-            old,
-            lsn,
-            txid,
+    /// Start delivering a committed transaction, returning its first batch.
+    fn finish_transaction(
+        &mut self,
+        txn: TransactionState,
+        end_lsn: u64,
+        timestamp: Option<String>,
+    ) -> PgResult<Option<StreamingBatch>> {
+        if txn.events.is_spilled() {
+            info!(
+                xid = txn.xid,
+                events = txn.events.len(),
+                "Delivering large transaction in batches"
+            );
+        }
+        self.pending = Some(PendingTransaction {
+            reader: txn.events.into_reader(self.config.spill_threshold)?,
+            end_lsn,
             timestamp,
-        })
+        });
+        self.next_pending_chunk()
     }
 
-    fn to_row_event_delete(
-        &self,
-        delete: &super::pgoutput::DeleteMessage,
-        lsn: u64,
-    ) -> PgResult<RowEvent> {
-        let relation = self
-            .relation_cache
-            .get(delete.relation_id)
-            .ok_or(PgError::RelationNotFound(delete.relation_id))?;
-
-        let old = self.tuple_to_row_map(&delete.old_tuple, &relation.columns)?;
-        let (txid, timestamp) = self.current_txn_info();
-
-        Ok(RowEvent {
-            op: Operation::Delete,
-            schema: relation.namespace.clone(),
-            table: relation.name.clone(),
-            new: None,
-            old: Some(old),
-            lsn,
-            txid,
-            timestamp,
-        })
-    }
+    /// The next batch of a committed transaction still being delivered.
+    fn next_pending_chunk(&mut self) -> PgResult<Option<StreamingBatch>> {
+        let Some(pending) = self.pending.as_mut() else {
+            return Ok(None);
+        };
+        let (mut events, last) = pending.reader.next_chunk()?;
+        if let Some(timestamp) = &pending.timestamp {
+            for event in &mut events {
+                event.timestamp.get_or_insert_with(|| timestamp.clone());
+            }
+        }
 
-    fn current_txn_info(&self) -> (Option<u64>, Option<String>) {
-        self.current_txn.as_ref().map_or((None, None), |txn| {
-            (
-                Some(txn.xid as u64),
-                Some(format_pg_timestamp(txn.timestamp)),
-            )
-        })
+        // Until the last batch, a restart has to resume before the transaction
+        let ack_lsn = if last {
+            let end_lsn = pending.end_lsn;
+            self.pending = None;
+            end_lsn
+        } else {
+            self.ack_lsn
+        };
+        Ok(Some(StreamingBatch {
+            events,
+            ack_lsn,
+            continues: !last,
+        }))
     }
 
-    fn tuple_to_row_map(
-        &self,
-        tuple: &super::pgoutput::TupleData,
-        columns: &[ColumnInfo],
-    ) -> PgResult<HashMap<String, Value>> {
-        let mut row = HashMap::new();
-
-        for (col_value, col_info) in tuple.columns.iter().zip(columns.iter()) {
-            let value = match col_value {
-                ColumnValue::Null => Value::Null,
-                ColumnValue::Unchanged => continue, // Skip unchanged TOAST values
-                ColumnValue::Text(s) => parse_text_value(s, col_info.type_oid),
-                ColumnValue::Binary(_) => {
-                    // Binary format not commonly used in pgoutput, treat as string
-                    Value::String("<binary>".to_string())
+    /// Add a converted row change to its transaction: the streamed one when
+    /// it came with a (sub)transaction xid, otherwise the current one. Rows
+    /// that can't be converted are skipped unless `fail_on_decode_error` is
+    /// set.
+    fn push_row_event(
+        &mut self,
+        op: &str,
+        event: PgResult<RowEvent>,
+        subxid: Option<u32>,
+    ) -> PgResult<()> {
+        match event {
+            Ok(event) => {
+                info!(op = op, table = %event.table, "Row change");
+                let txn = match (subxid, self.stream_xid) {
+                    (Some(_), Some(xid)) => self.streamed.get_mut(&xid),
+                    (Some(_), None) => None,
+                    (None, _) => self.current_txn.as_mut(),
+                };
+                if let Some(txn) = txn {
+                    let xid = subxid.unwrap_or(txn.xid);
+                    txn.events.push(xid, event)?;
                 }
-            };
-            row.insert(col_info.name.clone(), value);
+                Ok(())
+            }
+            // Skipping would lose the change; restarting the stream resends
+            // the relation's columns
+            Err(e @ PgError::RelationMismatch { .. }) => Err(e),
+            Err(e) if self.config.fail_on_decode_error => Err(PgError::Strict(format!(
+                "failed to convert {} row: {}",
+                op, e
+            ))),
+            Err(e) => {
+                warn!(op = op, error = %e, "Skipping row change that couldn't be converted");
+                Ok(())
+            }
         }
+    }
 
-        Ok(row)
+    /// Transaction details for a change, or None if it isn't inside one.
+    /// Streamed transactions get their timestamp at commit.
+    fn txn_info(&self, subxid: Option<u32>) -> Option<TxnInfo> {
+        match subxid {
+            Some(_) => {
+                let xid = self.stream_xid?;
+                self.streamed
+                    .contains_key(&xid)
+                    .then_some((Some(xid as u64), None))
+            }
+            None => self.current_txn.as_ref().map(|txn| {
+                (
+                    Some(txn.xid as u64),
+                    Some(format_pg_timestamp(txn.timestamp)),
+                )
+            }),
+        }
     }
 
     /// Acknowledge that events up to the given LSN have been processed.
@@ -437,27 +720,107 @@ impl ReplicationStream {
         self.ack_lsn
     }
 
+    /// End the stream cleanly and make sure the slot keeps the last
+    /// acknowledged LSN. Acknowledgements only reach the server with the
+    /// periodic status update, so once the server has let go of the slot it's
+    /// advanced to the LSN directly; if it's still held after a few seconds the
+    /// slot resumes from the last update the server received.
+    pub async fn close(&mut self, control_client: &Client) -> PgResult<()> {
+        self.client
+            .shutdown()
+            .await
+            .map_err(|e| PgError::replication(e.to_string()))?;
+        if self.ack_lsn == 0 {
+            return Ok(());
+        }
+
+        for _ in 0..SLOT_RELEASE_ATTEMPTS {
+            if advance_slot(control_client, &self.config.slot_name, self.ack_lsn).await? {
+                info!(lsn = %format_lsn(self.ack_lsn), "Replication stream closed");
+                return Ok(());
+            }
+            tokio::time::sleep(SLOT_RELEASE_WAIT).await;
+        }
+        warn!(
+            slot = %self.config.slot_name,
+            lsn = %format_lsn(self.ack_lsn),
+            "Slot still active after closing the stream; it resumes from the last status update"
+        );
+        Ok(())
+    }
+
     /// Ensure replication slot and publication exist.
-    async fn ensure_prerequisites(config: &ReplicationStreamConfig, client: &Client) -> PgResult<()> {
+    async fn ensure_prerequisites(
+        config: &ReplicationStreamConfig,
+        server: &ServerInfo,
+        client: &Client,
+    ) -> PgResult<()> {
         // First, validate that we can actually read from the tables
         // This catches issues where tables don't exist or permissions are wrong
         if !config.publication_tables.is_empty() {
             validate_all_tables_readable(client, &config.publication_tables).await?;
         }
 
+        if server.in_recovery {
+            return Self::ensure_standby_prerequisites(config, client).await;
+        }
+
         // Ensure slot exists with correct plugin
         ensure_slot(client, &config.slot_name, config.create_slot).await?;
 
         // Ensure publication exists with correct tables
+        Self::ensure_publication(config, client).await
+    }
+
+    /// Create the publication if it's missing and allowed, then add the
+    /// tables it lacks, or fail on them when altering it isn't allowed.
+    async fn ensure_publication(config: &ReplicationStreamConfig, client: &Client) -> PgResult<()> {
+        if !config.alter_publication && publication_exists(client, &config.publication_name).await? {
+            return require_publication_tables(
+                client,
+                &config.publication_name,
+                &config.publication_tables,
+            )
+            .await;
+        }
         ensure_publication(
             client,
             &config.publication_name,
             &config.publication_tables,
             config.create_publication,
         )
-        .await?;
+        .await
+    }
 
-        Ok(())
+    /// Standby variant of `ensure_prerequisites`.
+    ///
+    /// Publications can't be created or altered on a read-only standby, so
+    /// they must already exist (created on the primary) with every table.
+    async fn ensure_standby_prerequisites(
+        config: &ReplicationStreamConfig,
+        client: &Client,
+    ) -> PgResult<()> {
+        check_standby_settings(client).await?;
+
+        if config.create_slot && !slot_exists(client, &config.slot_name).await? {
+            info!(
+                slot = %config.slot_name,
+                "Creating slot on standby; this waits for activity on the primary \
+                 (run SELECT pg_log_standby_snapshot() on the primary to speed it up)"
+            );
+        }
+        ensure_slot(client, &config.slot_name, config.create_slot).await?;
+
+        let current_tables = get_publication_tables(client, &config.publication_name).await?;
+        if current_tables.is_empty() {
+            return Err(PgError::PublicationNotFound(format!(
+                "{} (publications can't be created on a standby, create it on the primary)",
+                config.publication_name
+            )));
+        }
+
+        require_publication_tables(client, &config.publication_name, &config.publication_tables)
+            .await
     }
 
     /// Get confirmed_flush_lsn for the slot.
@@ -470,7 +833,7 @@ impl ReplicationStream {
     }
 
     /// Parse connection string into components.
-    fn parse_connection_string(conn_str: &str) -> PgResult<ConnectionParams> {
+    pub(crate) fn parse_connection_string(conn_str: &str) -> PgResult<ConnectionParams> {
         // Handle both URL format (postgres://...) and key-value format
         if conn_str.starts_with("postgres://") || conn_str.starts_with("postgresql://") {
             Self::parse_url_connection_string(conn_str)
@@ -551,17 +914,195 @@ impl ReplicationStream {
     }
 }
 
-struct ConnectionParams {
-    host: String,
-    port: u16,
-    user: String,
-    password: String,
-    database: String,
-    sslmode: Option<String>,
+impl SourceAdapter for ReplicationStream {
+    type Error = PgError;
+
+    async fn next_batch(&mut self) -> PgResult<Option<SourceBatch>> {
+        Ok(self.recv_batch().await?.map(|batch| SourceBatch {
+            events: batch.events,
+            position: batch.ack_lsn,
+            continues: batch.continues,
+        }))
+    }
+
+    fn acknowledge(&mut self, position: u64) {
+        ReplicationStream::acknowledge(self, position);
+    }
+
+    fn acknowledged_position(&self) -> u64 {
+        self.ack_lsn
+    }
+}
+
+pub(crate) struct ConnectionParams {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) user: String,
+    pub(crate) password: String,
+    pub(crate) database: String,
+    pub(crate) sslmode: Option<String>,
+}
+
+impl ConnectionParams {
+    /// TLS settings for pgwire-replication from the sslmode.
+    pub(crate) fn tls(&self) -> pgwire_replication::TlsConfig {
+        match self.sslmode.as_deref() {
+            Some("require") => pgwire_replication::TlsConfig::require(),
+            Some("verify-ca") => pgwire_replication::TlsConfig::verify_ca(None),
+            Some("verify-full") => pgwire_replication::TlsConfig::verify_full(None),
+            _ => pgwire_replication::TlsConfig::disabled(),
+        }
+    }
+}
+
+/// Transaction ID and commit timestamp stamped onto row events.
+pub(crate) type TxnInfo = (Option<u64>, Option<String>);
+
+pub(crate) fn insert_event(
+    relations: &RelationCache,
+    insert: &InsertMessage,
+    lsn: u64,
+    (txid, timestamp): TxnInfo,
+) -> PgResult<RowEvent> {
+    let relation = relations
+        .get(insert.relation_id)
+        .ok_or(PgError::RelationNotFound(insert.relation_id))?;
+
+    Ok(RowEvent {
+        op: Operation::Insert,
+        schema: relation.namespace.clone(),
+        table: relation.name.clone(),
+        new: Some(tuple_to_row_map(&insert.tuple, relation)?),
+        old: None,
+        lsn,
+        txid,
+        timestamp,
+    })
+}
+
+pub(crate) fn update_event(
+    relations: &RelationCache,
+    update: &UpdateMessage,
+    lsn: u64,
+    (txid, timestamp): TxnInfo,
+) -> PgResult<RowEvent> {
+    let relation = relations
+        .get(update.relation_id)
+        .ok_or(PgError::RelationNotFound(update.relation_id))?;
+
+    Ok(RowEvent {
+        op: Operation::Update,
+        schema: relation.namespace.clone(),
+        table: relation.name.clone(),
+        new: Some(tuple_to_row_map(&update.new_tuple, relation)?),
+        old: update
+            .old_tuple
+            .as_ref()
+            .map(|t| tuple_to_row_map(t, relation))
+            .transpose()?,
+        lsn,
+        txid,
+        timestamp,
+    })
+}
+
+pub(crate) fn delete_event(
+    relations: &RelationCache,
+    delete: &DeleteMessage,
+    lsn: u64,
+    (txid, timestamp): TxnInfo,
+) -> PgResult<RowEvent> {
+    let relation = relations
+        .get(delete.relation_id)
+        .ok_or(PgError::RelationNotFound(delete.relation_id))?;
+
+    Ok(RowEvent {
+        op: Operation::Delete,
+        schema: relation.namespace.clone(),
+        table: relation.name.clone(),
+        new: None,
+        old: Some(tuple_to_row_map(&delete.old_tuple, relation)?),
+        lsn,
+        txid,
+        timestamp,
+    })
+}
+
+/// One event per truncated table.
+pub(crate) fn truncate_events(
+    relations: &RelationCache,
+    truncate: &TruncateMessage,
+    lsn: u64,
+    (txid, timestamp): TxnInfo,
+) -> Vec<PgResult<RowEvent>> {
+    truncate
+        .relation_ids
+        .iter()
+        .map(|id| {
+            let relation = relations.get(*id).ok_or(PgError::RelationNotFound(*id))?;
+            Ok(RowEvent {
+                op: Operation::Truncate,
+                schema: relation.namespace.clone(),
+                table: relation.name.clone(),
+                new: None,
+                old: None,
+                lsn,
+                txid,
+                timestamp: timestamp.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Decode a tuple against its relation's columns, skipping the ones the
+/// relation doesn't decode. A tuple with a different number of columns was
+/// written under another schema, and pairing its values with these columns
+/// would put them under the wrong names.
+fn tuple_to_row_map(
+    tuple: &TupleData,
+    relation: &RelationInfo,
+) -> PgResult<HashMap<String, Value>> {
+    if tuple.columns.len() != relation.columns.len() {
+        return Err(PgError::RelationMismatch {
+            table: format!("{}.{}", relation.namespace, relation.name),
+            version: format_lsn(relation.version),
+            expected: relation.columns.len(),
+            actual: tuple.columns.len(),
+        });
+    }
+    let mut row = HashMap::new();
+
+    for (col_value, col_info) in tuple.columns.iter().zip(relation.columns.iter()) {
+        if !relation.decodes(&col_info.name) {
+            continue;
+        }
+        let value = match col_value {
+            ColumnValue::Null => Value::Null,
+            ColumnValue::Unchanged => continue, // Skip unchanged TOAST values
+            ColumnValue::Text(s) if relation.text_columns.contains(&col_info.name) => {
+                Value::String(s.clone())
+            }
+            ColumnValue::Text(s) if relation.vector_columns.contains(&col_info.name) => {
+                parse_vector_text(s).unwrap_or_else(|| Value::String(s.clone()))
+            }
+            ColumnValue::Text(s) => parse_text_value(s, col_info.type_oid),
+            // Not sent while the stream asks for text tuples, see
+            // `parse_binary_value`
+            ColumnValue::Binary(bytes) => parse_binary_value(bytes, col_info.type_oid),
+        };
+        row.insert(col_info.name.clone(), value);
+    }
+
+    Ok(row)
 }
 
 /// Parse a text-format value based on its PostgreSQL type OID.
 fn parse_text_value(s: &str, type_oid: u32) -> Value {
+    if let Some(element_oid) = array_element_oid(type_oid) {
+        return parse_array_text(s, |element| parse_text_value(element, element_oid))
+            .unwrap_or_else(|| Value::String(s.to_string()));
+    }
+
     // Common PostgreSQL type OIDs
     match type_oid {
         16 => Value::Bool(s == "t" || s == "true"), // bool
@@ -586,24 +1127,162 @@ fn parse_text_value(s: &str, type_oid: u32) -> Value {
         }
         2950 => Value::String(s.to_string()), // uuid
         1082 | 1114 | 1184 => Value::String(s.to_string()), // date, timestamp, timestamptz
-        1009 | 1015 | 1016 => {
-            // text[], varchar[], int8[]
-            // PostgreSQL array format: {elem1,elem2,...}
-            Value::String(s.to_string()) // Keep as string for now
+        _ => Value::String(s.to_string()),    // Default to string
+    }
+}
+
+/// Parse a binary-format value (the type's send format) based on its
+/// PostgreSQL type OID, into the same value `parse_text_value` gives for its
+/// text form where the formats allow. bytea, types without a decoder here,
+/// and malformed values are kept as hex like bytea's text output (`\x0102`),
+/// so nothing is silently replaced.
+///
+/// The backfill's binary COPY decodes array elements with this. The
+/// replication stream doesn't ask for binary tuples: pgwire-replication
+/// starts pgoutput without `binary 'true'` and has no way to pass it, so
+/// tuples arrive as text. Binary tuples are only decoded here in case a
+/// server sends them.
+pub(crate) fn parse_binary_value(bytes: &[u8], type_oid: u32) -> Value {
+    let decoded = match type_oid {
+        // Arrays carry their element type
+        _ if array_element_oid(type_oid).is_some() => parse_array_binary(bytes, parse_binary_value),
+        16 => match bytes {
+            [b] => Some(Value::Bool(*b != 0)),
+            _ => None,
+        },
+        21 => <[u8; 2]>::try_from(bytes)
+            .ok()
+            .map(|b| Value::Int(i16::from_be_bytes(b).into())),
+        23 => <[u8; 4]>::try_from(bytes)
+            .ok()
+            .map(|b| Value::Int(i32::from_be_bytes(b).into())),
+        20 => <[u8; 8]>::try_from(bytes)
+            .ok()
+            .map(|b| Value::Int(i64::from_be_bytes(b))),
+        700 => <[u8; 4]>::try_from(bytes)
+            .ok()
+            .map(|b| Value::Float(f32::from_be_bytes(b).into())),
+        701 => <[u8; 8]>::try_from(bytes)
+            .ok()
+            .map(|b| Value::Float(f64::from_be_bytes(b))),
+        // text, varchar, bpchar, name and json send their text as-is
+        25 | 1043 | 1042 | 19 | 114 => std::str::from_utf8(bytes)
+            .ok()
+            .map(|s| parse_text_value(s, type_oid)),
+        // jsonb prefixes the text with a format version
+        3802 => match bytes.split_first() {
+            Some((1, json)) => std::str::from_utf8(json)
+                .ok()
+                .map(|s| parse_text_value(s, type_oid)),
+            _ => None,
+        },
+        2950 => uuid::Uuid::from_slice(bytes)
+            .ok()
+            .map(|u| Value::String(u.to_string())),
+        1082 => <[u8; 4]>::try_from(bytes)
+            .ok()
+            .and_then(|b| format_pg_date(i32::from_be_bytes(b)))
+            .map(Value::String),
+        1700 => format_pg_numeric(bytes).map(|s| parse_text_value(&s, type_oid)),
+        1114 | 1184 => <[u8; 8]>::try_from(bytes).ok().map(|b| {
+            let micros = i64::from_be_bytes(b);
+            Value::String(match micros {
+                i64::MAX => "infinity".to_string(),
+                i64::MIN => "-infinity".to_string(),
+                // timestamp has no zone, so it doesn't claim to be UTC
+                _ if type_oid == 1114 => format_pg_timestamp(micros)
+                    .trim_end_matches('Z')
+                    .to_string(),
+                _ => format_pg_timestamp(micros),
+            })
+        }),
+        _ => None,
+    };
+
+    decoded.unwrap_or_else(|| Value::String(format!("\\x{}", hex::encode(bytes))))
+}
+
+/// Format a PostgreSQL numeric's send format as its text form.
+///
+/// The format is a header of four 16-bit fields (digit count, weight of the
+/// first digit, sign, display scale) followed by base-10000 digits.
+fn format_pg_numeric(bytes: &[u8]) -> Option<String> {
+    let word = |i: usize| {
+        bytes
+            .get(2 * i..2 * i + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    };
+    let ndigits = usize::from(word(0)?);
+    let weight = i32::from(word(1)? as i16);
+    let sign = word(2)?;
+    let dscale = usize::from(word(3)?);
+    if bytes.len() != 8 + 2 * ndigits {
+        return None;
+    }
+    match sign {
+        0x0000 | 0x4000 => {}
+        0xC000 => return Some("NaN".to_string()),
+        0xD000 => return Some("Infinity".to_string()),
+        0xF000 => return Some("-Infinity".to_string()),
+        _ => return None,
+    }
+
+    // The digit with weight w is at index weight - w; any others are zero
+    let digit = |index: i32| {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| word(4 + i).filter(|_| i < ndigits))
+            .unwrap_or(0)
+    };
+
+    let mut out = String::new();
+    if sign == 0x4000 {
+        out.push('-');
+    }
+    if weight < 0 {
+        out.push('0');
+    } else {
+        out.push_str(&digit(0).to_string());
+        for index in 1..=weight {
+            out.push_str(&format!("{:04}", digit(index)));
+        }
+    }
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut index = weight + 1;
+        while fraction.len() < dscale {
+            fraction.push_str(&format!("{:04}", digit(index)));
+            index += 1;
         }
-        _ => Value::String(s.to_string()), // Default to string
+        fraction.truncate(dscale);
+        out.push('.');
+        out.push_str(&fraction);
     }
+    Some(out)
+}
+
+/// Format a PostgreSQL date (days since 2000-01-01) as `YYYY-MM-DD`.
+fn format_pg_date(days: i32) -> Option<String> {
+    match days {
+        i32::MAX => return Some("infinity".to_string()),
+        i32::MIN => return Some("-infinity".to_string()),
+        _ => {}
+    }
+    let epoch = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let date = epoch.checked_add_signed(chrono::Duration::days(days.into()))?;
+    Some(date.format("%Y-%m-%d").to_string())
 }
 
 /// Format PostgreSQL timestamp (microseconds since 2000-01-01) to ISO string.
-fn format_pg_timestamp(micros: i64) -> String {
+pub(crate) fn format_pg_timestamp(micros: i64) -> String {
     // PostgreSQL epoch is 2000-01-01 00:00:00 UTC
     // Unix epoch is 1970-01-01 00:00:00 UTC
     // Difference: 946684800 seconds
     const PG_EPOCH_OFFSET: i64 = 946_684_800;
 
-    let unix_secs = (micros / 1_000_000) + PG_EPOCH_OFFSET;
-    let nanos = ((micros % 1_000_000) * 1000) as u32;
+    // Euclidean so times before 2000 keep a positive sub-second part
+    let unix_secs = micros.div_euclid(1_000_000) + PG_EPOCH_OFFSET;
+    let nanos = (micros.rem_euclid(1_000_000) * 1000) as u32;
 
     chrono::DateTime::from_timestamp(unix_secs, nanos)
         .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string())
@@ -613,6 +1292,7 @@ fn format_pg_timestamp(micros: i64) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::replication::pgoutput::{ColumnInfo, ReplicaIdentity};
 
     #[test]
     fn test_parse_url_sslmode() {
@@ -632,6 +1312,57 @@ mod tests {
         assert_eq!(params.sslmode, None);
     }
 
+    #[test]
+    fn test_tuple_column_count_mismatch() {
+        let relation = RelationInfo {
+            namespace: "public".to_string(),
+            name: "users".to_string(),
+            columns: vec![ColumnInfo {
+                flags: 1,
+                name: "id".to_string(),
+                type_oid: 23,
+                type_modifier: -1,
+            }],
+            replica_identity: ReplicaIdentity::Default,
+            text_columns: HashSet::new(),
+            vector_columns: HashSet::new(),
+            decoded_columns: None,
+            version: 0x16B3748,
+        };
+
+        let tuple = TupleData {
+            columns: vec![ColumnValue::Text("1".to_string())],
+        };
+        let row = tuple_to_row_map(&tuple, &relation).unwrap();
+        assert_eq!(row.get("id"), Some(&Value::Int(1)));
+
+        // A column that isn't decoded is left out of the row
+        let projected = RelationInfo {
+            decoded_columns: Some(HashSet::new()),
+            ..relation.clone()
+        };
+        assert!(tuple_to_row_map(&tuple, &projected).unwrap().is_empty());
+
+        // A column added without a new Relation message
+        let tuple = TupleData {
+            columns: vec![
+                ColumnValue::Text("1".to_string()),
+                ColumnValue::Text("42".to_string()),
+            ],
+        };
+        let err = tuple_to_row_map(&tuple, &relation).unwrap_err();
+        assert!(matches!(
+            err,
+            PgError::RelationMismatch {
+                expected: 1,
+                actual: 2,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("0/16B3748"));
+        assert!(err.is_retryable());
+    }
+
     #[test]
     fn test_parse_keyvalue_sslmode() {
         let params = ReplicationStream::parse_keyvalue_connection_string(
@@ -644,4 +1375,152 @@ mod tests {
         ).unwrap();
         assert_eq!(params.sslmode, None);
     }
+
+    #[test]
+    fn test_parse_array_values() {
+        assert_eq!(
+            parse_text_value(r#"{a,"b c",NULL}"#, 1009),
+            Value::Array(vec![
+                Value::String("a".into()),
+                Value::String("b c".into()),
+                Value::Null,
+            ])
+        );
+        assert_eq!(
+            parse_text_value("{{1,2},{3,4}}", 1007),
+            Value::Array(vec![
+                Value::Array(vec![Value::Int(1), Value::Int(2)]),
+                Value::Array(vec![Value::Int(3), Value::Int(4)]),
+            ])
+        );
+        assert_eq!(
+            parse_text_value("{t,f}", 1000),
+            Value::Array(vec![Value::Bool(true), Value::Bool(false)])
+        );
+        // Not an array literal, kept as it came
+        assert_eq!(parse_text_value("oops", 1009), Value::String("oops".into()));
+
+        // int8[] {7, NULL} in binary
+        let mut data = Vec::new();
+        data.extend_from_slice(&1i32.to_be_bytes()); // dimensions
+        data.extend_from_slice(&1i32.to_be_bytes()); // has nulls
+        data.extend_from_slice(&20u32.to_be_bytes()); // element type
+        data.extend_from_slice(&2i32.to_be_bytes()); // length
+        data.extend_from_slice(&1i32.to_be_bytes()); // lower bound
+        data.extend_from_slice(&8i32.to_be_bytes());
+        data.extend_from_slice(&7i64.to_be_bytes());
+        data.extend_from_slice(&(-1i32).to_be_bytes());
+        assert_eq!(
+            parse_binary_value(&data, 1016),
+            Value::Array(vec![Value::Int(7), Value::Null])
+        );
+    }
+
+    #[test]
+    fn test_parse_binary_value() {
+        assert_eq!(parse_binary_value(&[1], 16), Value::Bool(true));
+        assert_eq!(
+            parse_binary_value(&(-5i16).to_be_bytes(), 21),
+            Value::Int(-5)
+        );
+        assert_eq!(parse_binary_value(&42i32.to_be_bytes(), 23), Value::Int(42));
+        assert_eq!(
+            parse_binary_value(&i64::MAX.to_be_bytes(), 20),
+            Value::Int(i64::MAX)
+        );
+        assert_eq!(
+            parse_binary_value(&1.5f32.to_be_bytes(), 700),
+            Value::Float(1.5)
+        );
+        assert_eq!(
+            parse_binary_value(&0.1f64.to_be_bytes(), 701),
+            Value::Float(0.1)
+        );
+        assert_eq!(
+            parse_binary_value("héllo".as_bytes(), 25),
+            Value::String("héllo".into())
+        );
+        assert_eq!(
+            parse_binary_value(b"\x01{\"a\": 1}", 3802),
+            parse_text_value("{\"a\": 1}", 3802)
+        );
+
+        let uuid = uuid::Uuid::parse_str("550e8400-e29b-41d4-a716-446655440000").unwrap();
+        assert_eq!(
+            parse_binary_value(uuid.as_bytes(), 2950),
+            Value::String(uuid.to_string())
+        );
+
+        // 2024-01-15 10:30:00.5 and 1999-12-31 23:59:59.5
+        let micros: i64 = 758_629_800_500_000;
+        assert_eq!(
+            parse_binary_value(&micros.to_be_bytes(), 1184),
+            Value::String("2024-01-15T10:30:00.500000Z".into())
+        );
+        assert_eq!(
+            parse_binary_value(&micros.to_be_bytes(), 1114),
+            Value::String("2024-01-15T10:30:00.500000".into())
+        );
+        assert_eq!(
+            parse_binary_value(&(-500_000i64).to_be_bytes(), 1184),
+            Value::String("1999-12-31T23:59:59.500000Z".into())
+        );
+        assert_eq!(
+            parse_binary_value(&i64::MAX.to_be_bytes(), 1184),
+            Value::String("infinity".into())
+        );
+        assert_eq!(
+            parse_binary_value(&8780i32.to_be_bytes(), 1082),
+            Value::String("2024-01-15".into())
+        );
+
+        // bytea, unsupported types and malformed values are kept as hex
+        assert_eq!(
+            parse_binary_value(&[0xde, 0xad], 17),
+            Value::String("\\xdead".into())
+        );
+        assert_eq!(
+            parse_binary_value(&[0, 1, 2], 1700),
+            Value::String("\\x000102".into())
+        );
+        assert_eq!(
+            parse_binary_value(&[0, 1, 2], 23),
+            Value::String("\\x000102".into())
+        );
+    }
+
+    fn numeric(weight: i16, sign: u16, dscale: u16, digits: &[u16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for word in [digits.len() as u16, weight as u16, sign, dscale]
+            .into_iter()
+            .chain(digits.iter().copied())
+        {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_format_pg_numeric() {
+        let cases = [
+            (numeric(0, 0, 2, &[123, 4500]), "123.45"),
+            (numeric(1, 0, 0, &[1234, 5678]), "12345678"),
+            (numeric(1, 0, 0, &[100]), "1000000"),
+            (numeric(-1, 0x4000, 3, &[10]), "-0.001"),
+            (numeric(-2, 0, 8, &[1234]), "0.00001234"),
+            (numeric(0, 0, 0, &[]), "0"),
+            (numeric(0, 0xC000, 0, &[]), "NaN"),
+            (numeric(0, 0xF000, 0, &[]), "-Infinity"),
+        ];
+        for (bytes, text) in cases {
+            assert_eq!(format_pg_numeric(&bytes).as_deref(), Some(text));
+        }
+
+        assert_eq!(
+            parse_binary_value(&numeric(0, 0, 2, &[123, 4500]), 1700),
+            Value::Float(123.45)
+        );
+        // The digit count must match the digits sent
+        assert_eq!(format_pg_numeric(&numeric(0, 0, 0, &[1])[..8]), None);
+    }
 }
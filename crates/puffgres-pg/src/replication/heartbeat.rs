@@ -0,0 +1,85 @@
+//! Heartbeat writes that keep an idle slot moving.
+//!
+//! The slot's confirmed_flush_lsn only advances when puffgres acknowledges a
+//! transaction it received. On a database where the mapped tables rarely
+//! change, nothing arrives to acknowledge while other writes (or just
+//! checkpoints) keep producing WAL, and the slot retains all of it.
+//! Periodically updating a row in a small published table gives the stream a
+//! transaction to acknowledge, so retention stays bounded.
+
+use puffgres_core::RowEvent;
+use tokio_postgres::Client;
+
+use crate::error::PgResult;
+
+/// Schema of the heartbeat table.
+pub const HEARTBEAT_SCHEMA: &str = "public";
+
+/// Name of the heartbeat table. It holds one row per slot.
+pub const HEARTBEAT_TABLE: &str = "__puffgres_heartbeat";
+
+/// The heartbeat table as `schema.table`, for the publication.
+pub fn heartbeat_table_ref() -> String {
+    format!("{}.{}", HEARTBEAT_SCHEMA, HEARTBEAT_TABLE)
+}
+
+/// Create the heartbeat table if it doesn't exist. Must run on the primary.
+pub async fn ensure_heartbeat_table(client: &Client) -> PgResult<()> {
+    client
+        .batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {}.{} (
+                slot_name TEXT PRIMARY KEY,
+                beat_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+            HEARTBEAT_SCHEMA, HEARTBEAT_TABLE
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Record a heartbeat for `slot_name`. Must run on the primary.
+pub async fn write_heartbeat(client: &Client, slot_name: &str) -> PgResult<()> {
+    client
+        .execute(
+            &format!(
+                "INSERT INTO {}.{} (slot_name, beat_at) VALUES ($1, now())
+                 ON CONFLICT (slot_name) DO UPDATE SET beat_at = now()",
+                HEARTBEAT_SCHEMA, HEARTBEAT_TABLE
+            ),
+            &[&slot_name],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Whether an event is a heartbeat write rather than a change to sync.
+pub fn is_heartbeat(event: &RowEvent) -> bool {
+    event.schema == HEARTBEAT_SCHEMA && event.table == HEARTBEAT_TABLE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_core::Operation;
+
+    fn event(schema: &str, table: &str) -> RowEvent {
+        RowEvent {
+            op: Operation::Update,
+            schema: schema.to_string(),
+            table: table.to_string(),
+            new: None,
+            old: None,
+            lsn: 0,
+            txid: None,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_is_heartbeat() {
+        assert!(is_heartbeat(&event("public", "__puffgres_heartbeat")));
+        assert!(!is_heartbeat(&event("public", "users")));
+        assert!(!is_heartbeat(&event("other", "__puffgres_heartbeat")));
+        assert_eq!(heartbeat_table_ref(), "public.__puffgres_heartbeat");
+    }
+}
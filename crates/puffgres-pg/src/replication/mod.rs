@@ -4,20 +4,48 @@
 //! PostgreSQL streaming replication protocol with pgoutput format.
 
 pub mod client;
+pub mod control;
+pub mod heartbeat;
+pub mod leader;
 pub mod lsn;
+pub mod notify;
 pub mod pgoutput;
 pub mod publication;
 pub mod relation_cache;
 pub mod slot;
+pub mod snapshot;
+mod spool;
+pub mod standby;
+pub mod tail;
+pub mod transactions;
 pub mod validation;
 
 pub use client::{ReplicationStream, ReplicationStreamConfig, StreamingBatch};
+pub use control::{notify_reload, ControlListener, CONTROL_CHANNEL, RELOAD_MESSAGE};
+pub use heartbeat::{
+    ensure_heartbeat_table, heartbeat_table_ref, is_heartbeat, write_heartbeat, HEARTBEAT_TABLE,
+};
+pub use leader::{release_slot, slot_lock_key, LockHolder, SlotLock};
 pub use lsn::{format_lsn, parse_lsn};
+pub use notify::{install_notify_trigger, ChangeListener, NOTIFY_CHANNEL, NOTIFY_TRIGGER};
 pub use pgoutput::{PgOutputDecoder, PgOutputMessage};
-pub use publication::{quote_ident, quote_table_name};
+pub use publication::{
+    get_column_types, quote_ident, quote_table_name, require_publication_tables, sync_publication,
+    sync_row_filters, PublicationSync, RowFilterSync, ROW_FILTER_MIN_VERSION,
+};
 pub use relation_cache::RelationCache;
-pub use slot::{ensure_slot, get_confirmed_flush_lsn, slot_exists};
+pub use slot::{
+    advance_slot, ensure_slot, get_confirmed_flush_lsn, get_slot_lag, slot_exists, SlotLag,
+};
+pub use snapshot::{create_slot_with_snapshot, SlotSnapshot};
+pub use standby::{
+    detect_timeline_change, get_current_wal_lsn, get_server_info, ServerInfo, TimelineChange,
+};
+pub use tail::{ChangeTail, TailSource};
+pub use transactions::{find_open_transactions, OpenTransaction};
 pub use validation::{
-    check_replication_setup, reset_replication, validate_all_tables_readable, ReplicationStatus,
-    SlotStatus, PublicationStatus,
+    check_replica_identity, check_replication_setup, find_replica_identity_index,
+    get_replica_identity, reset_replication, set_replica_identity, set_replica_identity_full,
+    validate_all_tables_readable, PublicationStatus, ReplicaIdentity, ReplicaIdentityFix,
+    ReplicationStatus, SlotStatus, TableReplicaIdentity,
 };
@@ -0,0 +1,97 @@
+//! A nudge for readers that poll a slot over SQL.
+//!
+//! `puffgres tail` and the like poll with `pg_logical_slot_*_changes`, so a
+//! change waits up to a poll interval before it's read. A statement-level
+//! trigger on each mapped table can `NOTIFY` a channel instead, which the
+//! poller `LISTEN`s on to poll as soon as a change commits. Notifications are
+//! only a hint: the slot is still the source of changes, and without the
+//! trigger, or if a notification is lost, the poller falls back to its
+//! interval.
+
+use std::time::Duration;
+
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_postgres::{Client, Notification};
+
+use super::publication::{quote_ident, quote_table_name};
+use crate::connect::connect_postgres_listening;
+use crate::error::PgResult;
+
+/// Channel the trigger notifies, with the changed table as the payload.
+pub const NOTIFY_CHANNEL: &str = "puffgres_changes";
+
+/// Name of the trigger, and of the function it runs.
+pub const NOTIFY_TRIGGER: &str = "__puffgres_notify";
+
+/// Create the trigger function, and a trigger on `table` (`schema.table`)
+/// that notifies [`NOTIFY_CHANNEL`] once per statement that changes it.
+/// Replaces the trigger if it exists. Must run on the primary.
+pub async fn install_notify_trigger(client: &Client, table: &str) -> PgResult<()> {
+    let trigger = quote_ident(NOTIFY_TRIGGER);
+    let table_name = quote_table_name(table);
+    client
+        .batch_execute(&format!(
+            "CREATE OR REPLACE FUNCTION public.{trigger}() RETURNS trigger
+             LANGUAGE plpgsql AS $$
+             BEGIN
+                 PERFORM pg_notify('{channel}', TG_TABLE_SCHEMA || '.' || TG_TABLE_NAME);
+                 RETURN NULL;
+             END
+             $$;
+             DROP TRIGGER IF EXISTS {trigger} ON {table_name};
+             CREATE TRIGGER {trigger}
+                 AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON {table_name}
+                 FOR EACH STATEMENT EXECUTE FUNCTION public.{trigger}();",
+            trigger = trigger,
+            channel = NOTIFY_CHANNEL,
+            table_name = table_name,
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Listens on [`NOTIFY_CHANNEL`] to wake a poller early.
+pub struct ChangeListener {
+    /// Kept so the session, and with it the `LISTEN`, stays open.
+    _client: Client,
+    notifications: UnboundedReceiver<Notification>,
+    closed: bool,
+}
+
+impl ChangeListener {
+    /// Open a session that listens for change notifications. Notifications
+    /// aren't sent to standbys, so this connects to the primary.
+    pub async fn connect(connection_string: &str) -> PgResult<Self> {
+        let (client, notifications) = connect_postgres_listening(connection_string).await?;
+        client
+            .batch_execute(&format!("LISTEN {}", NOTIFY_CHANNEL))
+            .await?;
+        Ok(Self {
+            _client: client,
+            notifications,
+            closed: false,
+        })
+    }
+
+    /// Wait until a change is announced or `timeout` passes. Returns whether
+    /// a change was announced. Notifications that arrived meanwhile are all
+    /// taken, since one poll reads every change they announce.
+    pub async fn wait(&mut self, timeout: Duration) -> bool {
+        if self.closed {
+            tokio::time::sleep(timeout).await;
+            return false;
+        }
+        let notified = match tokio::time::timeout(timeout, self.notifications.recv()).await {
+            Ok(Some(_)) => true,
+            // The session ended; fall back to the interval from now on
+            Ok(None) => {
+                tracing::warn!("Lost the change notification session; polling on the interval");
+                self.closed = true;
+                return false;
+            }
+            Err(_) => false,
+        };
+        while self.notifications.try_recv().is_ok() {}
+        notified
+    }
+}
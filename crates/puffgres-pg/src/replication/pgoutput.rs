@@ -3,6 +3,7 @@
 //! Reference: https://www.postgresql.org/docs/current/protocol-logicalrep-message-formats.html
 
 use byteorder::{BigEndian, ReadBytesExt};
+use std::cell::Cell;
 use std::io::{Cursor, Read};
 
 use crate::error::{PgError, PgResult};
@@ -20,6 +21,17 @@ pub enum PgOutputMessage {
     Truncate(TruncateMessage),
     Origin(OriginMessage),
     Message(LogicalMessage),
+    StreamStart(StreamStartMessage),
+    StreamStop,
+    StreamCommit(StreamCommitMessage),
+    StreamAbort(StreamAbortMessage),
+    /// A change of an in-progress transaction, sent between StreamStart and
+    /// StreamStop (protocol version 2). `xid` is the (sub)transaction that
+    /// made it.
+    Streamed {
+        xid: u32,
+        message: Box<PgOutputMessage>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -37,6 +49,28 @@ pub struct CommitMessage {
     pub timestamp: i64,
 }
 
+#[derive(Debug, Clone)]
+pub struct StreamStartMessage {
+    pub xid: u32,
+    pub first_segment: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamCommitMessage {
+    pub xid: u32,
+    pub flags: u8,
+    pub commit_lsn: u64,
+    pub end_lsn: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamAbortMessage {
+    pub xid: u32,
+    /// Equal to `xid` when the whole transaction aborted.
+    pub subxid: u32,
+}
+
 #[derive(Debug, Clone)]
 pub struct RelationMessage {
     pub relation_id: u32,
@@ -134,11 +168,16 @@ pub enum ColumnValue {
 }
 
 /// Decoder for pgoutput binary protocol messages.
-pub struct PgOutputDecoder;
+pub struct PgOutputDecoder {
+    /// Between StreamStart and StreamStop, where changes carry an xid.
+    in_stream: Cell<bool>,
+}
 
 impl PgOutputDecoder {
     pub fn new() -> Self {
-        Self
+        Self {
+            in_stream: Cell::new(false),
+        }
     }
 
     /// Decode a pgoutput message from raw bytes.
@@ -150,6 +189,33 @@ impl PgOutputDecoder {
         let msg_type = data[0];
         let payload = &data[1..];
 
+        if self.in_stream.get()
+            && matches!(msg_type, b'R' | b'Y' | b'I' | b'U' | b'D' | b'T' | b'M')
+        {
+            if payload.len() < 4 {
+                return Err(PgError::PgOutput("streamed message without xid".into()));
+            }
+            let xid = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            let message = self.decode_message_type(msg_type, &payload[4..])?;
+            return Ok(PgOutputMessage::Streamed {
+                xid,
+                message: Box::new(message),
+            });
+        }
+
+        match msg_type {
+            b'S' => self.decode_stream_start(payload),
+            b'E' => {
+                self.in_stream.set(false);
+                Ok(PgOutputMessage::StreamStop)
+            }
+            b'c' => self.decode_stream_commit(payload),
+            b'A' => self.decode_stream_abort(payload),
+            other => self.decode_message_type(other, payload),
+        }
+    }
+
+    fn decode_message_type(&self, msg_type: u8, payload: &[u8]) -> PgResult<PgOutputMessage> {
         match msg_type {
             b'B' => self.decode_begin(payload),
             b'C' => self.decode_commit(payload),
@@ -181,6 +247,47 @@ impl PgOutputDecoder {
         }))
     }
 
+    fn decode_stream_start(&self, data: &[u8]) -> PgResult<PgOutputMessage> {
+        let mut cursor = Cursor::new(data);
+        let xid = cursor.read_u32::<BigEndian>()?;
+        let first_segment = cursor.read_u8()? == 1;
+        self.in_stream.set(true);
+
+        Ok(PgOutputMessage::StreamStart(StreamStartMessage {
+            xid,
+            first_segment,
+        }))
+    }
+
+    fn decode_stream_commit(&self, data: &[u8]) -> PgResult<PgOutputMessage> {
+        let mut cursor = Cursor::new(data);
+        let xid = cursor.read_u32::<BigEndian>()?;
+        let flags = cursor.read_u8()?;
+        let commit_lsn = cursor.read_u64::<BigEndian>()?;
+        let end_lsn = cursor.read_u64::<BigEndian>()?;
+        let timestamp = cursor.read_i64::<BigEndian>()?;
+
+        Ok(PgOutputMessage::StreamCommit(StreamCommitMessage {
+            xid,
+            flags,
+            commit_lsn,
+            end_lsn,
+            timestamp,
+        }))
+    }
+
+    fn decode_stream_abort(&self, data: &[u8]) -> PgResult<PgOutputMessage> {
+        // Protocol version 4 appends the abort LSN and timestamp, not needed here
+        let mut cursor = Cursor::new(data);
+        let xid = cursor.read_u32::<BigEndian>()?;
+        let subxid = cursor.read_u32::<BigEndian>()?;
+
+        Ok(PgOutputMessage::StreamAbort(StreamAbortMessage {
+            xid,
+            subxid,
+        }))
+    }
+
     fn decode_commit(&self, data: &[u8]) -> PgResult<PgOutputMessage> {
         let mut cursor = Cursor::new(data);
         let flags = cursor.read_u8()?;
@@ -583,4 +690,74 @@ mod tests {
             _ => panic!("expected Delete message"),
         }
     }
+
+    #[test]
+    fn test_decode_streamed_transaction() {
+        let decoder = PgOutputDecoder::new();
+
+        let mut start = vec![b'S'];
+        start.extend_from_slice(&42u32.to_be_bytes()); // xid
+        start.push(1); // first segment
+        match decoder.decode(&start).unwrap() {
+            PgOutputMessage::StreamStart(s) => {
+                assert_eq!(s.xid, 42);
+                assert!(s.first_segment);
+            }
+            _ => panic!("expected StreamStart message"),
+        }
+
+        // Inside a stream, changes carry the xid before their usual fields
+        let mut delete = vec![b'D'];
+        delete.extend_from_slice(&43u32.to_be_bytes()); // subtransaction xid
+        delete.extend_from_slice(&16384u32.to_be_bytes()); // relation_id
+        delete.push(b'K');
+        delete.extend_from_slice(&1i16.to_be_bytes());
+        delete.push(b't');
+        delete.extend_from_slice(&1i32.to_be_bytes());
+        delete.push(b'1');
+        match decoder.decode(&delete).unwrap() {
+            PgOutputMessage::Streamed { xid, message } => {
+                assert_eq!(xid, 43);
+                assert!(
+                    matches!(*message, PgOutputMessage::Delete(ref d) if d.relation_id == 16384)
+                );
+            }
+            _ => panic!("expected Streamed message"),
+        }
+
+        assert!(matches!(
+            decoder.decode(b"E").unwrap(),
+            PgOutputMessage::StreamStop
+        ));
+
+        let mut commit = vec![b'c'];
+        commit.extend_from_slice(&42u32.to_be_bytes()); // xid
+        commit.push(0); // flags
+        commit.extend_from_slice(&100u64.to_be_bytes()); // commit_lsn
+        commit.extend_from_slice(&200u64.to_be_bytes()); // end_lsn
+        commit.extend_from_slice(&12345i64.to_be_bytes()); // timestamp
+        match decoder.decode(&commit).unwrap() {
+            PgOutputMessage::StreamCommit(c) => {
+                assert_eq!(c.xid, 42);
+                assert_eq!(c.end_lsn, 200);
+            }
+            _ => panic!("expected StreamCommit message"),
+        }
+    }
+
+    #[test]
+    fn test_decode_stream_abort() {
+        let mut data = vec![b'A'];
+        data.extend_from_slice(&42u32.to_be_bytes()); // xid
+        data.extend_from_slice(&43u32.to_be_bytes()); // subxid
+
+        let decoder = PgOutputDecoder::new();
+        match decoder.decode(&data).unwrap() {
+            PgOutputMessage::StreamAbort(a) => {
+                assert_eq!(a.xid, 42);
+                assert_eq!(a.subxid, 43);
+            }
+            _ => panic!("expected StreamAbort message"),
+        }
+    }
 }
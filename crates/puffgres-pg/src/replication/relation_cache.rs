@@ -1,12 +1,16 @@
 //! Cache for PostgreSQL relation metadata.
 //!
 //! PostgreSQL sends Relation messages before the first DML on each table
-//! in a replication session. We cache these to resolve relation_id in
-//! subsequent Insert/Update/Delete messages.
+//! in a replication session, and again before the first DML after its schema
+//! changes. We cache these to resolve relation_id in subsequent
+//! Insert/Update/Delete messages.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use super::pgoutput::{ColumnInfo, RelationMessage, ReplicaIdentity};
+use tracing::info;
+
+use super::lsn::format_lsn;
+use super::pgoutput::{ColumnInfo, RelationMessage, ReplicaIdentity, TypeMessage};
 
 /// Cached information about a PostgreSQL relation (table).
 #[derive(Debug, Clone)]
@@ -15,6 +19,16 @@ pub struct RelationInfo {
     pub name: String,
     pub columns: Vec<ColumnInfo>,
     pub replica_identity: ReplicaIdentity,
+    /// Columns to keep in their text form instead of decoding by type.
+    pub text_columns: HashSet<String>,
+    /// pgvector `vector` columns, decoded into arrays of floats.
+    pub vector_columns: HashSet<String>,
+    /// Columns to decode, or `None` for all of them. The values of the others
+    /// are left out of rows.
+    pub decoded_columns: Option<HashSet<String>>,
+    /// LSN of the Relation message this schema came from. Tuples are decoded
+    /// against the latest version.
+    pub version: u64,
 }
 
 impl From<&RelationMessage> for RelationInfo {
@@ -24,14 +38,34 @@ impl From<&RelationMessage> for RelationInfo {
             name: msg.name.clone(),
             columns: msg.columns.clone(),
             replica_identity: msg.replica_identity,
+            text_columns: HashSet::new(),
+            vector_columns: HashSet::new(),
+            decoded_columns: None,
+            version: 0,
         }
     }
 }
 
+impl RelationInfo {
+    /// Whether the column's values are decoded.
+    pub fn decodes(&self, column: &str) -> bool {
+        self.decoded_columns
+            .as_ref()
+            .map_or(true, |columns| columns.contains(column))
+    }
+}
+
 /// Cache of relation OID to table metadata mappings.
 #[derive(Debug, Default)]
 pub struct RelationCache {
     relations: HashMap<u32, RelationInfo>,
+    /// Columns kept as text, by `schema.table`.
+    text_columns: HashMap<String, HashSet<String>>,
+    /// Columns decoded, by `schema.table`. Tables not listed are decoded whole.
+    decoded_columns: HashMap<String, HashSet<String>>,
+    /// Names of types that aren't built in (whose OIDs differ between
+    /// databases), by OID.
+    type_names: HashMap<u32, String>,
 }
 
 impl RelationCache {
@@ -39,9 +73,63 @@ impl RelationCache {
         Self::default()
     }
 
-    /// Update the cache with a Relation message.
-    pub fn update(&mut self, msg: &RelationMessage) {
-        self.relations.insert(msg.relation_id, msg.into());
+    /// A cache whose relations keep these columns (by `schema.table`) as text.
+    pub fn with_text_columns(text_columns: HashMap<String, HashSet<String>>) -> Self {
+        Self {
+            relations: HashMap::new(),
+            text_columns,
+            decoded_columns: HashMap::new(),
+            type_names: HashMap::new(),
+        }
+    }
+
+    /// Only decode these columns (by `schema.table`) of the tables listed.
+    pub fn with_decoded_columns(
+        mut self,
+        decoded_columns: HashMap<String, HashSet<String>>,
+    ) -> Self {
+        self.decoded_columns = decoded_columns;
+        self
+    }
+
+    /// Record a Type message. The server sends one for each type that isn't
+    /// built in before the first Relation message that uses it.
+    pub fn update_type(&mut self, msg: &TypeMessage) {
+        self.type_names.insert(msg.type_id, msg.name.clone());
+    }
+
+    /// Update the cache with a Relation message received at `lsn`.
+    pub fn update(&mut self, msg: &RelationMessage, lsn: u64) {
+        let mut info = RelationInfo::from(msg);
+        info.version = lsn;
+        if let Some(previous) = self.relations.get(&msg.relation_id) {
+            if previous.columns.len() != info.columns.len() {
+                info!(
+                    table = format!("{}.{}", msg.namespace, msg.name),
+                    from = %format_lsn(previous.version),
+                    to = %format_lsn(lsn),
+                    columns = info.columns.len(),
+                    previous_columns = previous.columns.len(),
+                    "Relation schema changed"
+                );
+            }
+        }
+        let table = format!("{}.{}", msg.namespace, msg.name);
+        if let Some(columns) = self.text_columns.get(&table) {
+            info.text_columns = columns.clone();
+        }
+        info.decoded_columns = self.decoded_columns.get(&table).cloned();
+        info.vector_columns = msg
+            .columns
+            .iter()
+            .filter(|c| {
+                self.type_names
+                    .get(&c.type_oid)
+                    .is_some_and(|n| n == "vector")
+            })
+            .map(|c| c.name.clone())
+            .collect();
+        self.relations.insert(msg.relation_id, info);
     }
 
     /// Look up relation info by OID.
@@ -86,7 +174,7 @@ mod tests {
             }],
         };
 
-        cache.update(&msg);
+        cache.update(&msg, 100);
 
         let info = cache.get(16384).unwrap();
         assert_eq!(info.namespace, "public");
@@ -95,6 +183,120 @@ mod tests {
         assert_eq!(info.columns[0].name, "id");
     }
 
+    #[test]
+    fn test_cache_text_columns() {
+        let text_columns = HashMap::from([(
+            "public.orders".to_string(),
+            HashSet::from(["price".to_string()]),
+        )]);
+        let mut cache = RelationCache::with_text_columns(text_columns);
+
+        for (relation_id, name) in [(1, "orders"), (2, "users")] {
+            cache.update(
+                &RelationMessage {
+                    relation_id,
+                    namespace: "public".to_string(),
+                    name: name.to_string(),
+                    replica_identity: ReplicaIdentity::Default,
+                    columns: vec![],
+                },
+                100,
+            );
+        }
+
+        assert!(cache.get(1).unwrap().text_columns.contains("price"));
+        assert!(cache.get(2).unwrap().text_columns.is_empty());
+    }
+
+    #[test]
+    fn test_cache_decoded_columns() {
+        let decoded = HashMap::from([(
+            "public.orders".to_string(),
+            HashSet::from(["id".to_string(), "price".to_string()]),
+        )]);
+        let mut cache = RelationCache::new().with_decoded_columns(decoded);
+
+        for (relation_id, name) in [(1, "orders"), (2, "users")] {
+            cache.update(
+                &RelationMessage {
+                    relation_id,
+                    namespace: "public".to_string(),
+                    name: name.to_string(),
+                    replica_identity: ReplicaIdentity::Default,
+                    columns: vec![],
+                },
+                100,
+            );
+        }
+
+        let orders = cache.get(1).unwrap();
+        assert!(orders.decodes("price"));
+        assert!(!orders.decodes("description"));
+        assert!(cache.get(2).unwrap().decodes("description"));
+    }
+
+    #[test]
+    fn test_cache_vector_columns() {
+        let mut cache = RelationCache::new();
+        cache.update_type(&TypeMessage {
+            type_id: 16390,
+            namespace: "public".to_string(),
+            name: "vector".to_string(),
+        });
+
+        let column = |name: &str, type_oid| ColumnInfo {
+            flags: 0,
+            name: name.to_string(),
+            type_oid,
+            type_modifier: -1,
+        };
+        cache.update(
+            &RelationMessage {
+                relation_id: 1,
+                namespace: "public".to_string(),
+                name: "docs".to_string(),
+                replica_identity: ReplicaIdentity::Default,
+                columns: vec![column("id", 23), column("embedding", 16390)],
+            },
+            100,
+        );
+
+        let info = cache.get(1).unwrap();
+        assert_eq!(
+            info.vector_columns,
+            HashSet::from(["embedding".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_cache_version() {
+        let mut cache = RelationCache::new();
+        let relation = |columns: &[&str]| RelationMessage {
+            relation_id: 1,
+            namespace: "public".to_string(),
+            name: "users".to_string(),
+            replica_identity: ReplicaIdentity::Default,
+            columns: columns
+                .iter()
+                .map(|name| ColumnInfo {
+                    flags: 0,
+                    name: name.to_string(),
+                    type_oid: 23,
+                    type_modifier: -1,
+                })
+                .collect(),
+        };
+
+        cache.update(&relation(&["id"]), 100);
+        assert_eq!(cache.get(1).unwrap().version, 100);
+
+        // ALTER TABLE ... ADD COLUMN
+        cache.update(&relation(&["id", "age"]), 200);
+        let info = cache.get(1).unwrap();
+        assert_eq!(info.version, 200);
+        assert_eq!(info.columns.len(), 2);
+    }
+
     #[test]
     fn test_cache_miss() {
         let cache = RelationCache::new();
@@ -113,7 +315,7 @@ mod tests {
             columns: vec![],
         };
 
-        cache.update(&msg);
+        cache.update(&msg, 100);
         assert_eq!(cache.len(), 1);
 
         cache.clear();
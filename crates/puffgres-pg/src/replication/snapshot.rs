@@ -0,0 +1,278 @@
+//! Slot creation with an exported snapshot.
+//!
+//! `pg_create_logical_replication_slot` can't export a snapshot; only the
+//! replication protocol's `CREATE_REPLICATION_SLOT ... EXPORT_SNAPSHOT` can.
+//! The snapshot sees exactly the changes committed before the slot's
+//! consistent point, so a backfill that reads through it and streaming from
+//! that point neither miss nor repeat a change.
+//!
+//! The snapshot can only be imported while the connection that exported it
+//! stays open and idle, so [`SlotSnapshot`] holds on to that connection.
+
+use pgwire_replication::auth::ScramClient;
+use pgwire_replication::protocol::framing::{
+    read_backend_message, write_password_message, write_query, write_startup_message,
+};
+use pgwire_replication::protocol::{parse_auth_request, parse_error_response};
+use pgwire_replication::tls::rustls::{maybe_upgrade_to_tls, MaybeTlsStream};
+use pgwire_replication::PgWireError;
+use tokio::net::TcpStream;
+use tracing::info;
+
+use super::client::{ConnectionParams, ReplicationStream};
+use super::lsn::{format_lsn, parse_lsn};
+use crate::error::{PgError, PgResult};
+
+type WireResult<T> = Result<T, PgWireError>;
+
+/// A newly created slot's consistent point and the snapshot exported with it.
+///
+/// Import the snapshot with `SET TRANSACTION SNAPSHOT` before dropping this;
+/// dropping it closes the exporting connection and the snapshot with it.
+pub struct SlotSnapshot {
+    /// The slot's consistent point. Streaming from here picks up exactly
+    /// where the snapshot leaves off.
+    pub lsn: u64,
+    /// Name of the exported snapshot.
+    pub name: String,
+    _conn: MaybeTlsStream,
+}
+
+/// Create a pgoutput slot over a replication connection, exporting a
+/// snapshot of the database as of the slot's consistent point.
+pub async fn create_slot_with_snapshot(
+    connection_string: &str,
+    slot_name: &str,
+) -> PgResult<SlotSnapshot> {
+    let params = ReplicationStream::parse_connection_string(connection_string)?;
+    let mut conn = connect(&params)
+        .await
+        .map_err(|e| PgError::Connection(format!("Replication connection failed: {}", e)))?;
+
+    info!(slot = %slot_name, "Creating replication slot with an exported snapshot");
+    let sql = format!(
+        "CREATE_REPLICATION_SLOT {} LOGICAL pgoutput EXPORT_SNAPSHOT",
+        slot_name
+    );
+    let row = query_row(&mut conn, &sql)
+        .await
+        .map_err(|e| PgError::SlotCreationFailed(e.to_string()))?;
+
+    // Columns: slot_name, consistent_point, snapshot_name, output_plugin
+    let (Some(Some(lsn)), Some(Some(name))) = (row.get(1), row.get(2)) else {
+        return Err(PgError::SlotCreationFailed(
+            "server did not export a snapshot".to_string(),
+        ));
+    };
+    let lsn = parse_lsn(lsn)?;
+    info!(
+        slot = %slot_name,
+        snapshot = %name,
+        lsn = %format_lsn(lsn),
+        "Exported snapshot for replication slot"
+    );
+
+    Ok(SlotSnapshot {
+        lsn,
+        name: name.clone(),
+        _conn: conn,
+    })
+}
+
+/// Open a connection in logical replication mode.
+async fn connect(params: &ConnectionParams) -> WireResult<MaybeTlsStream> {
+    let tcp = TcpStream::connect((params.host.as_str(), params.port)).await?;
+    tcp.set_nodelay(true)?;
+    let mut conn = maybe_upgrade_to_tls(tcp, &params.tls(), &params.host).await?;
+
+    let startup = [
+        ("user", params.user.as_str()),
+        ("database", params.database.as_str()),
+        ("replication", "database"),
+        ("client_encoding", "UTF8"),
+        ("application_name", "puffgres"),
+    ];
+    write_startup_message(&mut conn, 196608, &startup).await?;
+    authenticate(&mut conn, params).await?;
+
+    Ok(conn)
+}
+
+/// Answer the server's authentication requests until it's ready for queries.
+async fn authenticate(conn: &mut MaybeTlsStream, params: &ConnectionParams) -> WireResult<()> {
+    loop {
+        let msg = read_backend_message(conn).await?;
+        match msg.tag {
+            b'R' => {
+                let (code, data) = parse_auth_request(&msg.payload)?;
+                match code {
+                    // AuthenticationOk
+                    0 => {}
+                    // Cleartext password
+                    3 => {
+                        let mut payload = params.password.as_bytes().to_vec();
+                        payload.push(0);
+                        write_password_message(conn, &payload).await?;
+                    }
+                    // SASL
+                    10 => scram(conn, params, data).await?,
+                    _ => {
+                        return Err(PgWireError::Auth(format!(
+                            "unsupported auth method code: {}",
+                            code
+                        )))
+                    }
+                }
+            }
+            b'E' => return Err(PgWireError::Server(parse_error_response(&msg.payload))),
+            b'Z' => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// SCRAM-SHA-256 exchange, given the mechanisms the server offered.
+async fn scram(
+    conn: &mut MaybeTlsStream,
+    params: &ConnectionParams,
+    mechanisms: &[u8],
+) -> WireResult<()> {
+    if !mechanisms.split(|&b| b == 0).any(|m| m == b"SCRAM-SHA-256") {
+        return Err(PgWireError::Auth(
+            "server doesn't offer SCRAM-SHA-256".to_string(),
+        ));
+    }
+
+    let scram = ScramClient::new(&params.user);
+    let mut initial = b"SCRAM-SHA-256\0".to_vec();
+    initial.extend_from_slice(&(scram.client_first.len() as i32).to_be_bytes());
+    initial.extend_from_slice(scram.client_first.as_bytes());
+    write_password_message(conn, &initial).await?;
+
+    let server_first = read_auth_data(conn, 11).await?;
+    let (client_final, auth_message, salted_password) =
+        scram.client_final(&params.password, &String::from_utf8_lossy(&server_first))?;
+    write_password_message(conn, client_final.as_bytes()).await?;
+
+    let server_final = read_auth_data(conn, 12).await?;
+    ScramClient::verify_server_final(
+        &String::from_utf8_lossy(&server_final),
+        &salted_password,
+        &auth_message,
+    )
+}
+
+/// Read the next authentication message, which must have the given code.
+async fn read_auth_data(conn: &mut MaybeTlsStream, expected: i32) -> WireResult<Vec<u8>> {
+    loop {
+        let msg = read_backend_message(conn).await?;
+        match msg.tag {
+            b'R' => {
+                let (code, data) = parse_auth_request(&msg.payload)?;
+                if code != expected {
+                    return Err(PgWireError::Auth(format!(
+                        "unexpected auth code {}, expected {}",
+                        code, expected
+                    )));
+                }
+                return Ok(data.to_vec());
+            }
+            b'E' => return Err(PgWireError::Server(parse_error_response(&msg.payload))),
+            _ => {}
+        }
+    }
+}
+
+/// Run a simple query and return its single row as text.
+///
+/// Waits for ReadyForQuery even on error, so the connection is left idle.
+async fn query_row(conn: &mut MaybeTlsStream, sql: &str) -> WireResult<Vec<Option<String>>> {
+    write_query(conn, sql).await?;
+
+    let mut row = None;
+    let mut error = None;
+    loop {
+        let msg = read_backend_message(conn).await?;
+        match msg.tag {
+            b'D' => row = Some(parse_data_row(&msg.payload)?),
+            b'E' => error = Some(PgWireError::Server(parse_error_response(&msg.payload))),
+            b'Z' => break,
+            _ => {}
+        }
+    }
+
+    match (error, row) {
+        (Some(e), _) => Err(e),
+        (None, Some(row)) => Ok(row),
+        (None, None) => Err(PgWireError::Protocol(format!(
+            "no row returned for: {}",
+            sql
+        ))),
+    }
+}
+
+/// Decode a DataRow message in text format.
+fn parse_data_row(payload: &[u8]) -> WireResult<Vec<Option<String>>> {
+    let truncated = || PgWireError::Protocol("truncated DataRow".to_string());
+
+    let (count, mut rest) = payload.split_at_checked(2).ok_or_else(truncated)?;
+    let count = i16::from_be_bytes([count[0], count[1]]);
+
+    let mut fields = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        let (len, tail) = rest.split_at_checked(4).ok_or_else(truncated)?;
+        let len = i32::from_be_bytes([len[0], len[1], len[2], len[3]]);
+        rest = tail;
+
+        if len < 0 {
+            fields.push(None);
+            continue;
+        }
+        let (value, tail) = rest.split_at_checked(len as usize).ok_or_else(truncated)?;
+        fields.push(Some(String::from_utf8_lossy(value).into_owned()));
+        rest = tail;
+    }
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_row(fields: &[Option<&str>]) -> Vec<u8> {
+        let mut payload = (fields.len() as i16).to_be_bytes().to_vec();
+        for field in fields {
+            match field {
+                Some(value) => {
+                    payload.extend_from_slice(&(value.len() as i32).to_be_bytes());
+                    payload.extend_from_slice(value.as_bytes());
+                }
+                None => payload.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+        payload
+    }
+
+    #[test]
+    fn test_parse_data_row() {
+        let payload = data_row(&[
+            Some("puffgres"),
+            Some("0/16B3748"),
+            Some("00000003-00000002-1"),
+            None,
+        ]);
+        assert_eq!(
+            parse_data_row(&payload).unwrap(),
+            vec![
+                Some("puffgres".to_string()),
+                Some("0/16B3748".to_string()),
+                Some("00000003-00000002-1".to_string()),
+                None,
+            ]
+        );
+
+        assert!(parse_data_row(&payload[..payload.len() - 1]).is_err());
+        assert!(parse_data_row(&[0]).is_err());
+    }
+}
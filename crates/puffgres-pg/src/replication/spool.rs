@@ -0,0 +1,266 @@
+//! Buffering for transactions too large to hold in memory.
+//!
+//! A transaction's changes only reach the pipeline once it commits, so they
+//! are held until then. Past a threshold, a transaction's events move to a
+//! temporary file, one JSON line each, and the committed transaction is read
+//! back a chunk at a time, so a 10M-row UPDATE doesn't need 10M events in
+//! memory at once.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use puffgres_core::RowEvent;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Distinguishes the spool files of one process.
+static SPOOL_FILE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// An event and the (sub)transaction that made it, which decides whether it
+/// survives a subtransaction abort.
+#[derive(Debug, Serialize, Deserialize)]
+struct SpooledEvent {
+    xid: u32,
+    event: RowEvent,
+}
+
+/// A temporary file, removed when dropped.
+struct SpoolFile {
+    path: PathBuf,
+}
+
+impl SpoolFile {
+    fn create() -> io::Result<(Self, File)> {
+        let id = SPOOL_FILE_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "puffgres-txn-{}-{}.jsonl",
+            std::process::id(),
+            id
+        ));
+        let file = File::create(&path)?;
+        Ok((Self { path }, file))
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The events of one transaction, in memory until there are more than
+/// `threshold` of them and in a temporary file after that.
+pub(crate) struct EventSpool {
+    threshold: usize,
+    memory: Vec<SpooledEvent>,
+    file: Option<(SpoolFile, BufWriter<File>)>,
+    len: usize,
+    aborted: HashSet<u32>,
+}
+
+impl EventSpool {
+    pub(crate) fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            memory: Vec::new(),
+            file: None,
+            len: 0,
+            aborted: HashSet::new(),
+        }
+    }
+
+    /// Number of events added, including any of aborted subtransactions.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the events have moved to a file.
+    pub(crate) fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Add an event made by (sub)transaction `xid`.
+    pub(crate) fn push(&mut self, xid: u32, event: RowEvent) -> io::Result<()> {
+        self.len += 1;
+        let entry = SpooledEvent { xid, event };
+        if let Some((_, writer)) = &mut self.file {
+            return write_entry(writer, &entry);
+        }
+
+        self.memory.push(entry);
+        if self.memory.len() > self.threshold {
+            let (file, handle) = SpoolFile::create()?;
+            info!(
+                events = self.memory.len(),
+                path = %file.path.display(),
+                "Transaction is large, spilling its changes to disk"
+            );
+            let mut writer = BufWriter::new(handle);
+            for entry in self.memory.drain(..) {
+                write_entry(&mut writer, &entry)?;
+            }
+            self.memory = Vec::new(); // release the capacity
+            self.file = Some((file, writer));
+        }
+        Ok(())
+    }
+
+    /// Drop the events of a subtransaction that rolled back.
+    pub(crate) fn abort_subtransaction(&mut self, xid: u32) {
+        self.memory.retain(|entry| entry.xid != xid);
+        self.aborted.insert(xid);
+    }
+
+    /// Read the events back, in order, `chunk_size` at a time.
+    pub(crate) fn into_reader(self, chunk_size: usize) -> io::Result<SpoolReader> {
+        let source = match self.file {
+            Some((file, writer)) => {
+                writer.into_inner().map_err(|e| e.into_error())?;
+                let lines = BufReader::new(File::open(&file.path)?).lines();
+                Source::File { lines, _file: file }
+            }
+            None => Source::Memory(self.memory.into_iter()),
+        };
+        Ok(SpoolReader {
+            source,
+            aborted: self.aborted,
+            chunk_size: chunk_size.max(1),
+            next: None,
+        })
+    }
+}
+
+fn write_entry(writer: &mut BufWriter<File>, entry: &SpooledEvent) -> io::Result<()> {
+    // Values round-trip through JSON, except non-finite floats, which
+    // serialize as null
+    serde_json::to_writer(&mut *writer, entry)?;
+    writer.write_all(b"\n")
+}
+
+enum Source {
+    Memory(std::vec::IntoIter<SpooledEvent>),
+    File {
+        lines: io::Lines<BufReader<File>>,
+        _file: SpoolFile,
+    },
+}
+
+/// Reads a committed transaction's events back in chunks.
+pub(crate) struct SpoolReader {
+    source: Source,
+    aborted: HashSet<u32>,
+    chunk_size: usize,
+    /// The event after the last chunk, read to know whether it was the end.
+    next: Option<RowEvent>,
+}
+
+impl SpoolReader {
+    fn read_event(&mut self) -> io::Result<Option<RowEvent>> {
+        loop {
+            let entry = match &mut self.source {
+                Source::Memory(entries) => entries.next(),
+                Source::File { lines, .. } => match lines.next() {
+                    Some(line) => Some(serde_json::from_str(&line?)?),
+                    None => None,
+                },
+            };
+            match entry {
+                Some(entry) if self.aborted.contains(&entry.xid) => continue,
+                Some(entry) => return Ok(Some(entry.event)),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The next chunk of events and whether it's the last.
+    pub(crate) fn next_chunk(&mut self) -> io::Result<(Vec<RowEvent>, bool)> {
+        let mut chunk = Vec::new();
+        if let Some(event) = self.next.take() {
+            chunk.push(event);
+        }
+        while chunk.len() < self.chunk_size {
+            match self.read_event()? {
+                Some(event) => chunk.push(event),
+                None => return Ok((chunk, true)),
+            }
+        }
+        self.next = self.read_event()?;
+        let last = self.next.is_none();
+        Ok((chunk, last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puffgres_core::{Operation, Value};
+
+    fn event(id: i64) -> RowEvent {
+        RowEvent {
+            op: Operation::Insert,
+            schema: "public".into(),
+            table: "users".into(),
+            new: Some([("id".to_string(), Value::Int(id))].into_iter().collect()),
+            old: None,
+            lsn: id as u64,
+            txid: Some(7),
+            timestamp: None,
+        }
+    }
+
+    fn read_all(spool: EventSpool, chunk_size: usize) -> Vec<Vec<u64>> {
+        let mut reader = spool.into_reader(chunk_size).unwrap();
+        let mut chunks = Vec::new();
+        loop {
+            let (chunk, last) = reader.next_chunk().unwrap();
+            chunks.push(chunk.iter().map(|e| e.lsn).collect());
+            if last {
+                return chunks;
+            }
+        }
+    }
+
+    #[test]
+    fn test_spool_stays_in_memory_under_threshold() {
+        let mut spool = EventSpool::new(10);
+        for id in 1..=3 {
+            spool.push(7, event(id)).unwrap();
+        }
+        assert!(!spool.is_spilled());
+        assert_eq!(read_all(spool, 10), vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn test_spool_spills_and_reads_back_in_chunks() {
+        let mut spool = EventSpool::new(2);
+        for id in 1..=5 {
+            spool.push(7, event(id)).unwrap();
+        }
+        assert!(spool.is_spilled());
+        assert_eq!(spool.len(), 5);
+
+        let path = spool.file.as_ref().unwrap().0.path.clone();
+        let mut reader = spool.into_reader(2).unwrap();
+        assert_eq!(reader.next_chunk().unwrap().0, vec![event(1), event(2)]);
+        assert_eq!(reader.next_chunk().unwrap().0, vec![event(3), event(4)]);
+        assert_eq!(reader.next_chunk().unwrap(), (vec![event(5)], true));
+
+        drop(reader);
+        assert!(!path.exists(), "spool file should be removed");
+    }
+
+    #[test]
+    fn test_spool_drops_aborted_subtransactions() {
+        for threshold in [100, 1] {
+            let mut spool = EventSpool::new(threshold);
+            spool.push(7, event(1)).unwrap();
+            spool.push(8, event(2)).unwrap();
+            spool.push(7, event(3)).unwrap();
+            spool.abort_subtransaction(8);
+            assert_eq!(read_all(spool, 2), vec![vec![1, 3]]);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Logical replication from physical standbys.
+//!
+//! Postgres 16 added logical decoding on standbys. A few things behave
+//! differently from streaming off the primary:
+//!
+//! - The standby is read-only, so publications must be created on the primary
+//!   (they replicate to the standby like any other catalog change).
+//! - Creating a slot on a standby waits for the primary to log a
+//!   running-transactions record, which can take a while on an idle primary.
+//! - When the standby is promoted it switches to a new timeline. The slot
+//!   survives promotion, so streaming resumes from the last acknowledged LSN
+//!   once the client reconnects.
+
+use tokio_postgres::Client;
+use tracing::{debug, warn};
+
+use super::lsn::parse_lsn;
+use crate::error::{PgError, PgResult};
+
+/// First `server_version_num` that supports logical decoding on a standby.
+pub const MIN_STANDBY_DECODING_VERSION: i32 = 160000;
+
+/// Role and timeline of the server we're replicating from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    /// Numeric server version (e.g., 160002 for 16.2).
+    pub version_num: i32,
+    /// Whether the server is a standby in recovery.
+    pub in_recovery: bool,
+    /// Current timeline, if the role is allowed to read it.
+    pub timeline_id: Option<u32>,
+}
+
+impl ServerInfo {
+    /// Whether this server can decode changes while it is a standby.
+    pub fn supports_standby_decoding(&self) -> bool {
+        self.version_num >= MIN_STANDBY_DECODING_VERSION
+    }
+
+    /// Check that logical replication from this server is possible.
+    pub fn check_can_decode(&self) -> PgResult<()> {
+        if self.in_recovery && !self.supports_standby_decoding() {
+            return Err(PgError::StandbyUnsupported {
+                version: format_version(self.version_num),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A change in server role or timeline observed across a reconnect.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimelineChange {
+    /// The standby was promoted to primary.
+    Promoted { from: Option<u32>, to: Option<u32> },
+    /// The server moved to a different timeline without a role change
+    /// (e.g., a cascading standby following a newly promoted upstream).
+    Switched { from: u32, to: u32 },
+}
+
+/// Compare server info from before and after a reconnect.
+pub fn detect_timeline_change(before: &ServerInfo, after: &ServerInfo) -> Option<TimelineChange> {
+    if before.in_recovery && !after.in_recovery {
+        return Some(TimelineChange::Promoted {
+            from: before.timeline_id,
+            to: after.timeline_id,
+        });
+    }
+
+    match (before.timeline_id, after.timeline_id) {
+        (Some(from), Some(to)) if from != to => Some(TimelineChange::Switched { from, to }),
+        _ => None,
+    }
+}
+
+/// Query the server's version, recovery state and timeline.
+pub async fn get_server_info(client: &Client) -> PgResult<ServerInfo> {
+    let row = client
+        .query_one(
+            "SELECT current_setting('server_version_num')::int, pg_is_in_recovery()",
+            &[],
+        )
+        .await?;
+
+    let version_num: i32 = row.get(0);
+    let in_recovery: bool = row.get(1);
+    let timeline_id = get_timeline_id(client, in_recovery).await;
+
+    Ok(ServerInfo {
+        version_num,
+        in_recovery,
+        timeline_id,
+    })
+}
+
+/// Best-effort lookup of the current timeline.
+///
+/// On a standby the WAL receiver knows the timeline being streamed; otherwise
+/// fall back to the last checkpoint. Both can require elevated privileges, so
+/// failures are logged and treated as unknown.
+async fn get_timeline_id(client: &Client, in_recovery: bool) -> Option<u32> {
+    let query = if in_recovery {
+        "SELECT COALESCE(
+             (SELECT received_tli FROM pg_stat_wal_receiver),
+             (SELECT timeline_id FROM pg_control_checkpoint())
+         )::bigint"
+    } else {
+        "SELECT timeline_id::bigint FROM pg_control_checkpoint()"
+    };
+
+    match client.query_opt(query, &[]).await {
+        Ok(row) => row
+            .and_then(|r| r.get::<_, Option<i64>>(0))
+            .and_then(|tli| u32::try_from(tli).ok()),
+        Err(e) => {
+            debug!(error = %e, "Could not read timeline, timeline changes won't be detected");
+            None
+        }
+    }
+}
+
+/// Current WAL position: the insert position on a primary, or the last
+/// replayed position on a standby.
+pub async fn get_current_wal_lsn(client: &Client) -> PgResult<u64> {
+    let row = client
+        .query_one(
+            "SELECT (CASE WHEN pg_is_in_recovery() THEN pg_last_wal_replay_lsn()
+                          ELSE pg_current_wal_lsn() END)::text",
+            &[],
+        )
+        .await?;
+
+    let lsn: Option<&str> = row.get(0);
+    let lsn = lsn.ok_or_else(|| PgError::Replication("Server has not replayed any WAL".into()))?;
+    parse_lsn(lsn)
+}
+
+/// Check standby settings that affect logical decoding and return warnings.
+///
+/// Without `hot_standby_feedback` the primary can vacuum away catalog rows the
+/// slot still needs, which invalidates the slot.
+pub async fn check_standby_settings(client: &Client) -> PgResult<Vec<String>> {
+    let row = client
+        .query_one(
+            "SELECT current_setting('hot_standby_feedback'), current_setting('wal_level')",
+            &[],
+        )
+        .await?;
+
+    let hot_standby_feedback: String = row.get(0);
+    let wal_level: String = row.get(1);
+
+    let mut warnings = Vec::new();
+    if hot_standby_feedback != "on" {
+        warnings.push(
+            "hot_standby_feedback is off on the standby; the primary may remove catalog rows \
+             the slot needs and invalidate it"
+                .to_string(),
+        );
+    }
+    if wal_level != "logical" {
+        warnings.push(format!(
+            "wal_level is '{}' but logical decoding requires 'logical' on the primary and standby",
+            wal_level
+        ));
+    }
+
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+
+    Ok(warnings)
+}
+
+/// Format a `server_version_num` for display (e.g., 150004 -> "15.4").
+fn format_version(version_num: i32) -> String {
+    format!("{}.{}", version_num / 10000, version_num % 10000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(version_num: i32, in_recovery: bool, timeline_id: Option<u32>) -> ServerInfo {
+        ServerInfo {
+            version_num,
+            in_recovery,
+            timeline_id,
+        }
+    }
+
+    #[test]
+    fn test_standby_requires_pg16() {
+        assert!(info(150004, false, None).check_can_decode().is_ok());
+        assert!(info(160002, true, Some(1)).check_can_decode().is_ok());
+
+        let err = info(150004, true, Some(1)).check_can_decode().unwrap_err();
+        assert!(matches!(err, PgError::StandbyUnsupported { .. }));
+        assert!(err.to_string().contains("15.4"), "{}", err);
+    }
+
+    #[test]
+    fn test_detect_promotion() {
+        let change =
+            detect_timeline_change(&info(160002, true, Some(1)), &info(160002, false, Some(2)));
+        assert_eq!(
+            change,
+            Some(TimelineChange::Promoted {
+                from: Some(1),
+                to: Some(2)
+            })
+        );
+    }
+
+    #[test]
+    fn test_detect_timeline_switch() {
+        let change =
+            detect_timeline_change(&info(160002, true, Some(2)), &info(160002, true, Some(3)));
+        assert_eq!(change, Some(TimelineChange::Switched { from: 2, to: 3 }));
+    }
+
+    #[test]
+    fn test_no_change_when_timeline_unknown_or_same() {
+        assert_eq!(
+            detect_timeline_change(&info(160002, true, Some(1)), &info(160002, true, Some(1))),
+            None
+        );
+        assert_eq!(
+            detect_timeline_change(&info(160002, false, None), &info(160002, false, Some(4))),
+            None
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Read decoded changes over a plain SQL connection, for `puffgres tail`.
+//!
+//! Changes come from `pg_logical_slot_get_binary_changes` on a temporary slot,
+//! which Postgres drops as soon as the session ends, so an interrupted tail
+//! never leaves a slot behind retaining WAL. Alternatively an existing slot can
+//! be peeked, which shows its pending changes without consuming them, or
+//! copied into a temporary slot, which reads them while the slot's own
+//! consumer keeps streaming.
+
+use puffgres_core::RowEvent;
+use tokio_postgres::Client;
+use tracing::{debug, info};
+
+use super::client::{delete_event, format_pg_timestamp, insert_event, update_event, TxnInfo};
+use super::lsn::{format_lsn, parse_lsn};
+use super::pgoutput::{PgOutputDecoder, PgOutputMessage};
+use super::relation_cache::RelationCache;
+use super::slot::slot_exists;
+use crate::error::{PgError, PgResult};
+
+/// Maximum number of changes fetched per poll.
+const MAX_CHANGES_PER_POLL: i32 = 1000;
+
+/// Where a tail reads changes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TailSource {
+    /// A temporary slot created for this session; changes are consumed.
+    Temporary(String),
+    /// An existing slot; changes are peeked and left for its consumer.
+    Peek(String),
+}
+
+impl TailSource {
+    pub fn slot_name(&self) -> &str {
+        match self {
+            TailSource::Temporary(slot) | TailSource::Peek(slot) => slot,
+        }
+    }
+}
+
+/// Polls a logical slot for changes and decodes them into row events.
+pub struct ChangeTail {
+    client: Client,
+    source: TailSource,
+    publication: String,
+    relations: RelationCache,
+    decoder: PgOutputDecoder,
+    txn: Option<TxnInfo>,
+    /// Highest LSN already returned. Peeking starts from the slot's confirmed
+    /// position every time, so earlier changes come back and are skipped.
+    seen_lsn: u64,
+}
+
+impl ChangeTail {
+    /// Create a temporary slot on `client`'s session and tail it. Only changes
+    /// committed after this call are returned.
+    pub async fn temporary(client: Client, publication: &str) -> PgResult<Self> {
+        let slot = format!("puffgres_tail_{}", std::process::id());
+        client
+            .execute(
+                "SELECT pg_create_logical_replication_slot($1, 'pgoutput', true)",
+                &[&slot],
+            )
+            .await
+            .map_err(|e| match PgError::from(e) {
+                PgError::Postgres(msg) => PgError::SlotCreationFailed(msg),
+                other => other,
+            })?;
+        info!(slot = %slot, "Created temporary replication slot");
+
+        Ok(Self::new(client, TailSource::Temporary(slot), publication))
+    }
+
+    /// Tail a temporary copy of an existing slot, for `puffgres replay`. The
+    /// copy starts from the slot's confirmed position, so it returns the
+    /// changes the slot still holds, and consuming them leaves the slot as
+    /// it was. Works while another session is streaming from the slot.
+    pub async fn copy_of(client: Client, slot: &str, publication: &str) -> PgResult<Self> {
+        if !slot_exists(&client, slot).await? {
+            return Err(PgError::SlotNotFound(slot.to_string()));
+        }
+        let copy = format!("puffgres_replay_{}", std::process::id());
+        client
+            .execute(
+                "SELECT pg_copy_logical_replication_slot($1, $2, true)",
+                &[&slot, &copy],
+            )
+            .await
+            .map_err(|e| match PgError::from(e) {
+                PgError::Postgres(msg) => PgError::SlotCreationFailed(msg),
+                other => other,
+            })?;
+        info!(slot = %slot, copy = %copy, "Copied replication slot");
+
+        Ok(Self::new(client, TailSource::Temporary(copy), publication))
+    }
+
+    /// Peek an existing slot. Fails while another session is streaming from it.
+    pub async fn peek(client: Client, slot: &str, publication: &str) -> PgResult<Self> {
+        if !slot_exists(&client, slot).await? {
+            return Err(PgError::SlotNotFound(slot.to_string()));
+        }
+
+        Ok(Self::new(
+            client,
+            TailSource::Peek(slot.to_string()),
+            publication,
+        ))
+    }
+
+    fn new(client: Client, source: TailSource, publication: &str) -> Self {
+        Self {
+            client,
+            source,
+            publication: publication.to_string(),
+            relations: RelationCache::new(),
+            decoder: PgOutputDecoder::new(),
+            txn: None,
+            seen_lsn: 0,
+        }
+    }
+
+    pub fn source(&self) -> &TailSource {
+        &self.source
+    }
+
+    /// Fetch the changes available right now. Returns an empty list when
+    /// there is nothing new.
+    pub async fn poll(&mut self) -> PgResult<Vec<RowEvent>> {
+        Ok(self.fetch(None).await?.1)
+    }
+
+    /// Fetch the next changes of transactions that committed before
+    /// `upto_lsn`. Returns `None` once there are none left. Only for a
+    /// temporary slot: a peek would return the same changes every time.
+    pub async fn poll_until(&mut self, upto_lsn: u64) -> PgResult<Option<Vec<RowEvent>>> {
+        let (read, events) = self.fetch(Some(upto_lsn)).await?;
+        Ok((read > 0).then_some(events))
+    }
+
+    /// Fetch changes, up to `upto_lsn` if given. Returns how many messages
+    /// were read, which transactions without row changes also count towards,
+    /// and the row events among them.
+    async fn fetch(&mut self, upto_lsn: Option<u64>) -> PgResult<(usize, Vec<RowEvent>)> {
+        let query = match self.source {
+            TailSource::Temporary(_) => {
+                "SELECT lsn::text, data FROM pg_logical_slot_get_binary_changes(\
+                 $1, $4::text::pg_lsn, $2, 'proto_version', '1', 'publication_names', $3)"
+            }
+            TailSource::Peek(_) => {
+                "SELECT lsn::text, data FROM pg_logical_slot_peek_binary_changes(\
+                 $1, $4::text::pg_lsn, $2, 'proto_version', '1', 'publication_names', $3)"
+            }
+        };
+        // Peeks always restart from the slot position, so don't cap them
+        let limit = match self.source {
+            TailSource::Temporary(_) => Some(MAX_CHANGES_PER_POLL),
+            TailSource::Peek(_) => None,
+        };
+
+        let upto_lsn = upto_lsn.map(format_lsn);
+        let rows = self
+            .client
+            .query(
+                query,
+                &[
+                    &self.source.slot_name(),
+                    &limit,
+                    &self.publication,
+                    &upto_lsn,
+                ],
+            )
+            .await?;
+
+        let read = rows.len();
+        let mut events = Vec::new();
+        for row in rows {
+            let lsn = parse_lsn(row.get::<_, &str>(0))?;
+            let data: Vec<u8> = row.get(1);
+            let msg = self.decoder.decode(&data)?;
+
+            // Relation and Type messages are resent on every call and must
+            // always be applied, even for changes that were already seen
+            match &msg {
+                PgOutputMessage::Relation(rel) => {
+                    self.relations.update(rel, lsn);
+                    continue;
+                }
+                PgOutputMessage::Type(ty) => {
+                    self.relations.update_type(ty);
+                    continue;
+                }
+                _ => {}
+            }
+            if lsn <= self.seen_lsn {
+                continue;
+            }
+
+            let event = match &msg {
+                PgOutputMessage::Begin(begin) => {
+                    self.txn = Some((
+                        Some(begin.xid as u64),
+                        Some(format_pg_timestamp(begin.timestamp)),
+                    ));
+                    None
+                }
+                PgOutputMessage::Commit(commit) => {
+                    self.txn = None;
+                    self.seen_lsn = self.seen_lsn.max(commit.end_lsn);
+                    None
+                }
+                PgOutputMessage::Insert(insert) => {
+                    Some(insert_event(&self.relations, insert, lsn, self.txn_info())?)
+                }
+                PgOutputMessage::Update(update) => {
+                    Some(update_event(&self.relations, update, lsn, self.txn_info())?)
+                }
+                PgOutputMessage::Delete(delete) => {
+                    Some(delete_event(&self.relations, delete, lsn, self.txn_info())?)
+                }
+                _ => None,
+            };
+            events.extend(event);
+        }
+
+        if !events.is_empty() {
+            debug!(
+                count = events.len(),
+                seen_lsn = %format_lsn(self.seen_lsn),
+                "Fetched changes"
+            );
+        }
+        Ok((read, events))
+    }
+
+    fn txn_info(&self) -> TxnInfo {
+        self.txn.clone().unwrap_or((None, None))
+    }
+}
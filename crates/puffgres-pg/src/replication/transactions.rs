@@ -0,0 +1,142 @@
+//! Detection of long-running transactions that hold back replication.
+//!
+//! Logical decoding only emits a transaction once its commit record is
+//! decoded, so changes made by a transaction left open for hours (a stuck
+//! migration, an idle-in-transaction session) don't reach turbopuffer until it
+//! finishes. The slot also can't advance its restart LSN past the transaction's
+//! first change, so WAL piles up meanwhile. From the outside both look like
+//! puffgres has stalled.
+
+use std::time::Duration;
+
+use tokio_postgres::Client;
+
+use crate::error::PgResult;
+
+/// A transaction that has written data and has been open for a while.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenTransaction {
+    /// Backend process ID; `pg_terminate_backend(pid)` ends it.
+    pub pid: i32,
+    /// Transaction ID assigned to the transaction.
+    pub xid: String,
+    /// How long the transaction has been open.
+    pub age: Duration,
+    /// Session state, e.g. `active` or `idle in transaction`.
+    pub state: Option<String>,
+    /// Role running the transaction.
+    pub user: Option<String>,
+    /// Start of the most recent statement, shortened.
+    pub query: Option<String>,
+}
+
+impl OpenTransaction {
+    /// One-line description for logs and status output.
+    pub fn describe(&self) -> String {
+        let mut description = format!(
+            "pid {} (xid {}) open for {}",
+            self.pid,
+            self.xid,
+            format_age(self.age)
+        );
+        if let Some(state) = &self.state {
+            description.push_str(&format!(", {}", state));
+        }
+        if let Some(user) = &self.user {
+            description.push_str(&format!(", user {}", user));
+        }
+        if let Some(query) = &self.query {
+            description.push_str(&format!(": {}", query));
+        }
+        description
+    }
+}
+
+/// Find transactions that have written data and been open longer than
+/// `min_age`, oldest first.
+///
+/// Only transactions in the slot's database are considered, since those are
+/// the ones whose changes the slot decodes. If the slot isn't on this server
+/// (e.g. it lives on a standby), the current database is used instead. Query
+/// the primary: a standby doesn't see the primary's sessions.
+pub async fn find_open_transactions(
+    client: &Client,
+    slot_name: &str,
+    min_age: Duration,
+) -> PgResult<Vec<OpenTransaction>> {
+    let rows = client
+        .query(
+            r#"
+            SELECT
+                pid,
+                backend_xid::text,
+                EXTRACT(EPOCH FROM now() - xact_start)::float8,
+                state,
+                usename::text,
+                left(query, 120)
+            FROM pg_stat_activity
+            WHERE backend_xid IS NOT NULL
+              AND xact_start < now() - make_interval(secs => $2)
+              AND datname = COALESCE(
+                  (SELECT database FROM pg_replication_slots WHERE slot_name = $1),
+                  current_database()
+              )
+            ORDER BY xact_start
+            "#,
+            &[&slot_name, &min_age.as_secs_f64()],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| OpenTransaction {
+            pid: row.get(0),
+            xid: row.get(1),
+            age: Duration::from_secs_f64(row.get::<_, f64>(2).max(0.0)),
+            state: row.get(3),
+            user: row.get(4),
+            query: row
+                .get::<_, Option<String>>(5)
+                .map(|q| q.split_whitespace().collect::<Vec<_>>().join(" ")),
+        })
+        .collect())
+}
+
+/// Format a duration as e.g. "2h 5m" or "45s".
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match (secs / 3600, (secs % 3600) / 60) {
+        (0, 0) => format!("{}s", secs),
+        (0, m) => format!("{}m {}s", m, secs % 60),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(45)), "45s");
+        assert_eq!(format_age(Duration::from_secs(125)), "2m 5s");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600 + 60)), "3h 1m");
+    }
+
+    #[test]
+    fn test_describe() {
+        let txn = OpenTransaction {
+            pid: 4242,
+            xid: "7781".into(),
+            age: Duration::from_secs(7200),
+            state: Some("idle in transaction".into()),
+            user: Some("app".into()),
+            query: Some("UPDATE users SET plan = 'pro'".into()),
+        };
+        assert_eq!(
+            txn.describe(),
+            "pid 4242 (xid 7781) open for 2h 0m, idle in transaction, user app: \
+             UPDATE users SET plan = 'pro'"
+        );
+    }
+}
@@ -2,7 +2,12 @@
 //!
 //! All puffgres state is stored in the user's Postgres database in __puffgres_* tables.
 
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use puffgres_core::shadow_name;
+use puffgres_state::{StateResult, StateStore};
 use serde::{Deserialize, Serialize};
 use tokio_postgres::Client;
 use tracing::{debug, info};
@@ -10,16 +15,9 @@ use tracing::{debug, info};
 use crate::connect::connect_postgres;
 use crate::error::{PgError, PgResult};
 
-/// Checkpoint state for a mapping.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct Checkpoint {
-    /// The last successfully processed LSN.
-    pub lsn: u64,
-    /// Number of events processed.
-    pub events_processed: u64,
-    /// Last update timestamp.
-    pub updated_at: Option<DateTime<Utc>>,
-}
+pub use puffgres_state::{
+    BackfillPartition, BackfillProgress, Checkpoint, DlqEntry, StoredTransform,
+};
 
 /// Applied migration record.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +27,27 @@ pub struct AppliedMigration {
     pub mapping_name: String,
     pub content_hash: String,
     pub applied_at: DateTime<Utc>,
+    /// Version of the later migration of the same mapping that replaced
+    /// this one.
+    pub superseded_by: Option<i32>,
+}
+
+/// The outcome of checking a finished backfill against its namespace, kept
+/// on the mapping's row of `__puffgres_backfill`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillVerification {
+    pub mapping_name: String,
+    /// `passed` or `failed`.
+    pub status: String,
+    /// Rows matching the mapping's predicate. `None` when they weren't
+    /// counted.
+    pub source_rows: Option<i64>,
+    /// Documents in the namespace. `None` when they weren't counted.
+    pub documents: Option<i64>,
+    /// Rows whose documents were compared attribute by attribute.
+    pub sampled: i64,
+    /// Sampled rows whose document is missing or differs.
+    pub mismatches: i64,
 }
 
 /// Result of sampling ID column values for type validation.
@@ -40,39 +59,46 @@ pub struct IdColumnSample {
     pub pg_type: String,
 }
 
-/// Dead letter queue entry.
+/// The tables a [`StateSnapshot`] copies, in the order they're imported.
+pub const STATE_TABLES: &[&str] = &[
+    "__puffgres_migrations",
+    "__puffgres_migration_content",
+    "__puffgres_transforms",
+    "__puffgres_checkpoints",
+    "__puffgres_backfill",
+    "__puffgres_backfill_partitions",
+    "__puffgres_control",
+    "__puffgres_dlq",
+];
+
+/// Tables holding the running state of a mapping, keyed by its name.
+const MAPPING_STATE_TABLES: &[&str] = &[
+    "__puffgres_checkpoints",
+    "__puffgres_backfill",
+    "__puffgres_backfill_partitions",
+    "__puffgres_control",
+];
+
+/// Tables whose `id` comes from a sequence, which has to be moved past the
+/// imported ids.
+const SERIAL_TABLES: &[&str] = &[
+    "__puffgres_migrations",
+    "__puffgres_migration_content",
+    "__puffgres_transforms",
+    "__puffgres_dlq",
+];
+
+/// Format of [`StateSnapshot`] files written by this version.
+pub const STATE_SNAPSHOT_VERSION: u32 = 1;
+
+/// Every row of the __puffgres_* state tables, for moving a project to
+/// another database.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DlqEntry {
-    pub id: i32,
-    pub mapping_name: String,
-    pub lsn: u64,
-    pub event_json: serde_json::Value,
-    pub error_message: String,
-    pub error_kind: String,
-    pub retry_count: i32,
-    pub created_at: DateTime<Utc>,
-}
-
-/// Backfill progress.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BackfillProgress {
-    pub mapping_name: String,
-    pub last_id: Option<String>,
-    pub total_rows: Option<i64>,
-    pub processed_rows: i64,
-    pub status: String,
-    pub updated_at: DateTime<Utc>,
-}
-
-/// Stored transform for immutability tracking.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct StoredTransform {
-    pub id: i32,
-    pub mapping_name: String,
-    pub version: i32,
-    pub content: String,
-    pub content_hash: String,
-    pub created_at: DateTime<Utc>,
+pub struct StateSnapshot {
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    /// Rows of each table, as JSON objects keyed by column name.
+    pub tables: BTreeMap<String, Vec<serde_json::Value>>,
 }
 
 /// PostgreSQL-backed state store.
@@ -125,7 +151,7 @@ impl PostgresStateStore {
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         // CDC checkpoints
         self.client
@@ -135,13 +161,15 @@ impl PostgresStateStore {
                     mapping_name TEXT PRIMARY KEY,
                     lsn BIGINT NOT NULL,
                     events_processed BIGINT DEFAULT 0,
-                    updated_at TIMESTAMPTZ DEFAULT NOW()
+                    updated_at TIMESTAMPTZ DEFAULT NOW(),
+                    reconnects BIGINT DEFAULT 0,
+                    last_reconnect_at TIMESTAMPTZ
                 )
                 "#,
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         // Dead letter queue
         self.client
@@ -161,7 +189,7 @@ impl PostgresStateStore {
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         // Backfill progress
         self.client
@@ -172,14 +200,75 @@ impl PostgresStateStore {
                     last_id TEXT,
                     total_rows BIGINT,
                     processed_rows BIGINT DEFAULT 0,
+                    upserted_rows BIGINT DEFAULT 0,
+                    rows_per_second DOUBLE PRECISION,
+                    status TEXT DEFAULT 'pending',
+                    updated_at TIMESTAMPTZ DEFAULT NOW()
+                )
+                "#,
+                &[],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        // Key ranges of partitioned backfills
+        self.client
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS __puffgres_backfill_partitions (
+                    mapping_name TEXT NOT NULL,
+                    partition_index INTEGER NOT NULL,
+                    range_start TEXT,
+                    range_end TEXT,
+                    last_id TEXT,
+                    processed_rows BIGINT DEFAULT 0,
+                    upserted_rows BIGINT DEFAULT 0,
                     status TEXT DEFAULT 'pending',
+                    updated_at TIMESTAMPTZ DEFAULT NOW(),
+                    PRIMARY KEY (mapping_name, partition_index)
+                )
+                "#,
+                &[],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        // Operator switches, like pausing a mapping
+        self.client
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS __puffgres_control (
+                    mapping_name TEXT PRIMARY KEY,
+                    paused BOOLEAN NOT NULL DEFAULT FALSE,
                     updated_at TIMESTAMPTZ DEFAULT NOW()
                 )
                 "#,
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
+
+        // Columns added after the table was first released
+        self.client
+            .batch_execute(
+                r#"
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS upserted_rows BIGINT DEFAULT 0;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS rows_per_second DOUBLE PRECISION;
+                ALTER TABLE __puffgres_checkpoints ADD COLUMN IF NOT EXISTS reconnects BIGINT DEFAULT 0;
+                ALTER TABLE __puffgres_checkpoints ADD COLUMN IF NOT EXISTS last_reconnect_at TIMESTAMPTZ;
+                ALTER TABLE __puffgres_dlq ADD COLUMN IF NOT EXISTS run_id TEXT;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS run_id TEXT;
+                ALTER TABLE __puffgres_migrations ADD COLUMN IF NOT EXISTS superseded_by INTEGER;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS verify_status TEXT;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS verify_source_rows BIGINT;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS verify_documents BIGINT;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS verify_sampled BIGINT;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS verify_mismatches BIGINT;
+                ALTER TABLE __puffgres_backfill ADD COLUMN IF NOT EXISTS verified_at TIMESTAMPTZ;
+                "#,
+            )
+            .await
+            .map_err(PgError::from)?;
 
         // Transform storage for immutability tracking
         self.client
@@ -198,7 +287,7 @@ impl PostgresStateStore {
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         // Migration content storage for reset functionality
         self.client
@@ -216,7 +305,7 @@ impl PostgresStateStore {
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         info!("Puffgres state schema initialized");
         Ok(())
@@ -232,19 +321,22 @@ impl PostgresStateStore {
             .client
             .query_opt(
                 r#"
-                SELECT lsn, events_processed, updated_at
+                SELECT lsn, events_processed, updated_at,
+                       COALESCE(reconnects, 0), last_reconnect_at
                 FROM __puffgres_checkpoints
                 WHERE mapping_name = $1
                 "#,
                 &[&mapping_name],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.map(|r| Checkpoint {
             lsn: r.get::<_, i64>(0) as u64,
             events_processed: r.get::<_, i64>(1) as u64,
             updated_at: r.get(2),
+            reconnects: r.get::<_, i64>(3) as u64,
+            last_reconnect_at: r.get(4),
         }))
     }
 
@@ -269,7 +361,7 @@ impl PostgresStateStore {
                 ],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(())
     }
@@ -280,14 +372,15 @@ impl PostgresStateStore {
             .client
             .query(
                 r#"
-                SELECT mapping_name, lsn, events_processed, updated_at
+                SELECT mapping_name, lsn, events_processed, updated_at,
+                       COALESCE(reconnects, 0), last_reconnect_at
                 FROM __puffgres_checkpoints
                 ORDER BY mapping_name
                 "#,
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(rows
             .into_iter()
@@ -298,19 +391,72 @@ impl PostgresStateStore {
                         lsn: r.get::<_, i64>(1) as u64,
                         events_processed: r.get::<_, i64>(2) as u64,
                         updated_at: r.get(3),
+                        reconnects: r.get::<_, i64>(4) as u64,
+                        last_reconnect_at: r.get(5),
                     },
                 )
             })
             .collect())
     }
 
+    /// Count a re-established replication connection against each mapping.
+    /// The stream is shared, so every mapping it serves is updated.
+    pub async fn record_reconnect(&self, mapping_names: &[String]) -> PgResult<()> {
+        self.client
+            .execute(
+                r#"
+                UPDATE __puffgres_checkpoints
+                SET reconnects = COALESCE(reconnects, 0) + 1, last_reconnect_at = NOW()
+                WHERE mapping_name = ANY($1)
+                "#,
+                &[&mapping_names],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(())
+    }
+
+    /// Pause or resume a mapping.
+    pub async fn set_paused(&self, mapping_name: &str, paused: bool) -> PgResult<()> {
+        self.client
+            .execute(
+                r#"
+                INSERT INTO __puffgres_control (mapping_name, paused, updated_at)
+                VALUES ($1, $2, NOW())
+                ON CONFLICT (mapping_name) DO UPDATE SET
+                    paused = EXCLUDED.paused,
+                    updated_at = NOW()
+                "#,
+                &[&mapping_name, &paused],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(())
+    }
+
+    /// Names of the paused mappings.
+    pub async fn get_paused_mappings(&self) -> PgResult<Vec<String>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT mapping_name FROM __puffgres_control WHERE paused ORDER BY mapping_name",
+                &[],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
     /// Get the minimum LSN across all mappings (safe restart point).
     pub async fn get_min_lsn(&self) -> PgResult<Option<u64>> {
         let row = self
             .client
             .query_opt("SELECT MIN(lsn) FROM __puffgres_checkpoints", &[])
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.and_then(|r| r.get::<_, Option<i64>>(0).map(|lsn| lsn as u64)))
     }
@@ -325,14 +471,14 @@ impl PostgresStateStore {
             .client
             .query(
                 r#"
-                SELECT id, version, mapping_name, content_hash, applied_at
+                SELECT id, version, mapping_name, content_hash, applied_at, superseded_by
                 FROM __puffgres_migrations
                 ORDER BY version, mapping_name
                 "#,
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(rows
             .into_iter()
@@ -342,6 +488,7 @@ impl PostgresStateStore {
                 mapping_name: r.get(2),
                 content_hash: r.get(3),
                 applied_at: r.get(4),
+                superseded_by: r.get(5),
             })
             .collect())
     }
@@ -356,14 +503,14 @@ impl PostgresStateStore {
             .client
             .query_opt(
                 r#"
-                SELECT id, version, mapping_name, content_hash, applied_at
+                SELECT id, version, mapping_name, content_hash, applied_at, superseded_by
                 FROM __puffgres_migrations
                 WHERE version = $1 AND mapping_name = $2
                 "#,
                 &[&version, &mapping_name],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.map(|r| AppliedMigration {
             id: r.get(0),
@@ -371,6 +518,7 @@ impl PostgresStateStore {
             mapping_name: r.get(2),
             content_hash: r.get(3),
             applied_at: r.get(4),
+            superseded_by: r.get(5),
         }))
     }
 
@@ -390,12 +538,157 @@ impl PostgresStateStore {
                 &[&version, &mapping_name, &content_hash],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         info!(version, mapping_name, "Recorded migration");
         Ok(())
     }
 
+    /// Mark the earlier applied migrations of a mapping as replaced by
+    /// `version`. Returns the versions marked.
+    pub async fn supersede_migrations(
+        &self,
+        mapping_name: &str,
+        version: i32,
+    ) -> PgResult<Vec<i32>> {
+        let rows = self
+            .client
+            .query(
+                r#"
+                UPDATE __puffgres_migrations
+                SET superseded_by = $2
+                WHERE mapping_name = $1 AND version < $2 AND superseded_by IS NULL
+                RETURNING version
+                "#,
+                &[&mapping_name, &version],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        let versions: Vec<i32> = rows.iter().map(|r| r.get(0)).collect();
+        if !versions.is_empty() {
+            info!(mapping_name, version, superseded = ?versions, "Superseded migrations");
+        }
+        Ok(versions)
+    }
+
+    /// Roll back an applied migration: forget the record and its stored
+    /// content and transform, in one transaction. A migration it superseded
+    /// takes over again; otherwise the mapping's checkpoint and backfill
+    /// progress are cleared too.
+    pub async fn remove_migration(&self, version: i32, mapping_name: &str) -> PgResult<()> {
+        self.client
+            .batch_execute("BEGIN")
+            .await
+            .map_err(PgError::from)?;
+        let result = self.delete_migration_rows(version, mapping_name).await;
+        self.client
+            .batch_execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" })
+            .await
+            .map_err(PgError::from)?;
+        result?;
+
+        info!(version, mapping_name, "Removed migration");
+        Ok(())
+    }
+
+    async fn delete_migration_rows(&self, version: i32, mapping_name: &str) -> PgResult<()> {
+        for table in [
+            "__puffgres_migrations",
+            "__puffgres_migration_content",
+            "__puffgres_transforms",
+        ] {
+            self.client
+                .execute(
+                    &format!(
+                        "DELETE FROM {} WHERE version = $1 AND mapping_name = $2",
+                        table
+                    ),
+                    &[&version, &mapping_name],
+                )
+                .await
+                .map_err(PgError::from)?;
+        }
+        self.client
+            .execute(
+                r#"
+                UPDATE __puffgres_migrations
+                SET superseded_by = NULL
+                WHERE mapping_name = $2 AND superseded_by = $1
+                "#,
+                &[&version, &mapping_name],
+            )
+            .await
+            .map_err(PgError::from)?;
+        let shadow = shadow_name(mapping_name, version as u32);
+        for table in MAPPING_STATE_TABLES {
+            self.client
+                .execute(
+                    &format!(
+                        "DELETE FROM {} WHERE mapping_name = $1 AND NOT EXISTS \
+                         (SELECT 1 FROM __puffgres_migrations WHERE mapping_name = $1)",
+                        table
+                    ),
+                    &[&mapping_name],
+                )
+                .await
+                .map_err(PgError::from)?;
+            // What a shadow revision was tracked under before promotion
+            self.client
+                .execute(
+                    &format!("DELETE FROM {} WHERE mapping_name = $1", table),
+                    &[&shadow],
+                )
+                .await
+                .map_err(PgError::from)?;
+        }
+        Ok(())
+    }
+
+    /// Promote a shadow revision, in one transaction: supersede the
+    /// mapping's earlier migrations with `version`, and hand the state the
+    /// shadow was tracked under (checkpoint, backfill progress, pause switch
+    /// and dead letters) to the mapping. Returns the versions superseded.
+    pub async fn promote_migration(&self, mapping_name: &str, version: i32) -> PgResult<Vec<i32>> {
+        self.client
+            .batch_execute("BEGIN")
+            .await
+            .map_err(PgError::from)?;
+        let result = self.promote_migration_rows(mapping_name, version).await;
+        self.client
+            .batch_execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" })
+            .await
+            .map_err(PgError::from)?;
+        let superseded = result?;
+
+        info!(version, mapping_name, superseded = ?superseded, "Promoted migration");
+        Ok(superseded)
+    }
+
+    async fn promote_migration_rows(&self, mapping_name: &str, version: i32) -> PgResult<Vec<i32>> {
+        let superseded = self.supersede_migrations(mapping_name, version).await?;
+        let shadow = shadow_name(mapping_name, version as u32);
+        for table in MAPPING_STATE_TABLES {
+            self.client
+                .execute(
+                    &format!("DELETE FROM {} WHERE mapping_name = $1", table),
+                    &[&mapping_name],
+                )
+                .await
+                .map_err(PgError::from)?;
+        }
+        for table in MAPPING_STATE_TABLES.iter().chain(&["__puffgres_dlq"]) {
+            self.client
+                .execute(
+                    &format!("UPDATE {} SET mapping_name = $1 WHERE mapping_name = $2", table),
+                    &[&mapping_name, &shadow],
+                )
+                .await
+                .map_err(PgError::from)?;
+        }
+        Ok(superseded)
+    }
+
     // -------------------------------------------------------------------------
     // DLQ methods
     // -------------------------------------------------------------------------
@@ -408,13 +701,14 @@ impl PostgresStateStore {
         event_json: &serde_json::Value,
         error_message: &str,
         error_kind: &str,
+        run_id: Option<&str>,
     ) -> PgResult<i32> {
         let row = self
             .client
             .query_one(
                 r#"
-                INSERT INTO __puffgres_dlq (mapping_name, lsn, event_json, error_message, error_kind)
-                VALUES ($1, $2, $3, $4, $5)
+                INSERT INTO __puffgres_dlq (mapping_name, lsn, event_json, error_message, error_kind, run_id)
+                VALUES ($1, $2, $3, $4, $5, $6)
                 RETURNING id
                 "#,
                 &[
@@ -423,10 +717,11 @@ impl PostgresStateStore {
                     &event_json,
                     &error_message,
                     &error_kind,
+                    &run_id,
                 ],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.get(0))
     }
@@ -441,7 +736,7 @@ impl PostgresStateStore {
             self.client
                 .query(
                     r#"
-                    SELECT id, mapping_name, lsn, event_json, error_message, error_kind, retry_count, created_at
+                    SELECT id, mapping_name, lsn, event_json, error_message, error_kind, retry_count, created_at, run_id
                     FROM __puffgres_dlq
                     WHERE mapping_name = $1
                     ORDER BY created_at DESC
@@ -454,7 +749,7 @@ impl PostgresStateStore {
             self.client
                 .query(
                     r#"
-                    SELECT id, mapping_name, lsn, event_json, error_message, error_kind, retry_count, created_at
+                    SELECT id, mapping_name, lsn, event_json, error_message, error_kind, retry_count, created_at, run_id
                     FROM __puffgres_dlq
                     ORDER BY created_at DESC
                     LIMIT $1
@@ -463,7 +758,7 @@ impl PostgresStateStore {
                 )
                 .await
         }
-        .map_err(|e| PgError::Postgres(e.to_string()))?;
+        .map_err(PgError::from)?;
 
         Ok(rows
             .into_iter()
@@ -476,6 +771,7 @@ impl PostgresStateStore {
                 error_kind: r.get(5),
                 retry_count: r.get(6),
                 created_at: r.get(7),
+                run_id: r.get(8),
             })
             .collect())
     }
@@ -486,14 +782,14 @@ impl PostgresStateStore {
             .client
             .query_opt(
                 r#"
-                SELECT id, mapping_name, lsn, event_json, error_message, error_kind, retry_count, created_at
+                SELECT id, mapping_name, lsn, event_json, error_message, error_kind, retry_count, created_at, run_id
                 FROM __puffgres_dlq
                 WHERE id = $1
                 "#,
                 &[&id],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.map(|r| DlqEntry {
             id: r.get(0),
@@ -504,6 +800,7 @@ impl PostgresStateStore {
             error_kind: r.get(5),
             retry_count: r.get(6),
             created_at: r.get(7),
+            run_id: r.get(8),
         }))
     }
 
@@ -515,7 +812,29 @@ impl PostgresStateStore {
                 &[&id],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
+
+        Ok(())
+    }
+
+    /// Count a failed retry of a DLQ entry, replacing its error with the new one.
+    pub async fn record_dlq_failure(
+        &self,
+        id: i32,
+        error_message: &str,
+        error_kind: &str,
+    ) -> PgResult<()> {
+        self.client
+            .execute(
+                r#"
+                UPDATE __puffgres_dlq
+                SET retry_count = retry_count + 1, error_message = $2, error_kind = $3
+                WHERE id = $1
+                "#,
+                &[&id, &error_message, &error_kind],
+            )
+            .await
+            .map_err(PgError::from)?;
 
         Ok(())
     }
@@ -525,7 +844,7 @@ impl PostgresStateStore {
         self.client
             .execute("DELETE FROM __puffgres_dlq WHERE id = $1", &[&id])
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(())
     }
@@ -542,11 +861,30 @@ impl PostgresStateStore {
         } else {
             self.client.execute("DELETE FROM __puffgres_dlq", &[]).await
         }
-        .map_err(|e| PgError::Postgres(e.to_string()))?;
+        .map_err(PgError::from)?;
 
         Ok(count)
     }
 
+    /// Count DLQ entries per mapping, for mappings that have any.
+    pub async fn count_dlq_entries(&self) -> PgResult<Vec<(String, i64)>> {
+        let rows = self
+            .client
+            .query(
+                r#"
+                SELECT mapping_name, COUNT(*)
+                FROM __puffgres_dlq
+                GROUP BY mapping_name
+                ORDER BY mapping_name
+                "#,
+                &[],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(rows.into_iter().map(|r| (r.get(0), r.get(1))).collect())
+    }
+
     // -------------------------------------------------------------------------
     // Backfill progress methods
     // -------------------------------------------------------------------------
@@ -560,59 +898,194 @@ impl PostgresStateStore {
             .client
             .query_opt(
                 r#"
-                SELECT mapping_name, last_id, total_rows, processed_rows, status, updated_at
+                SELECT mapping_name, last_id, total_rows, processed_rows,
+                       COALESCE(upserted_rows, 0), rows_per_second, status, updated_at, run_id
                 FROM __puffgres_backfill
                 WHERE mapping_name = $1
                 "#,
                 &[&mapping_name],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.map(|r| BackfillProgress {
             mapping_name: r.get(0),
             last_id: r.get(1),
             total_rows: r.get(2),
             processed_rows: r.get::<_, i64>(3),
-            status: r.get(4),
-            updated_at: r.get(5),
+            upserted_rows: r.get::<_, i64>(4),
+            rows_per_second: r.get(5),
+            status: r.get(6),
+            updated_at: r.get(7),
+            run_id: r.get(8),
         }))
     }
 
-    /// Update backfill progress.
-    pub async fn update_backfill_progress(
+    /// Get backfill progress for every mapping that has any.
+    pub async fn get_all_backfill_progress(&self) -> PgResult<Vec<BackfillProgress>> {
+        let rows = self
+            .client
+            .query(
+                r#"
+                SELECT mapping_name, last_id, total_rows, processed_rows,
+                       COALESCE(upserted_rows, 0), rows_per_second, status, updated_at, run_id
+                FROM __puffgres_backfill
+                ORDER BY mapping_name
+                "#,
+                &[],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| BackfillProgress {
+                mapping_name: r.get(0),
+                last_id: r.get(1),
+                total_rows: r.get(2),
+                processed_rows: r.get::<_, i64>(3),
+                upserted_rows: r.get::<_, i64>(4),
+                rows_per_second: r.get(5),
+                status: r.get(6),
+                updated_at: r.get(7),
+                run_id: r.get(8),
+            })
+            .collect())
+    }
+
+    /// Save backfill progress.
+    pub async fn save_backfill_progress(&self, progress: &BackfillProgress) -> PgResult<()> {
+        self.client
+            .execute(
+                r#"
+                INSERT INTO __puffgres_backfill (mapping_name, last_id, total_rows, processed_rows, upserted_rows, rows_per_second, status, updated_at, run_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), $8)
+                ON CONFLICT (mapping_name)
+                DO UPDATE SET last_id = $2, total_rows = $3, processed_rows = $4, upserted_rows = $5,
+                              rows_per_second = $6, status = $7, updated_at = NOW(), run_id = $8
+                "#,
+                &[
+                    &progress.mapping_name,
+                    &progress.last_id,
+                    &progress.total_rows,
+                    &progress.processed_rows,
+                    &progress.upserted_rows,
+                    &progress.rows_per_second,
+                    &progress.status,
+                    &progress.run_id,
+                ],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(())
+    }
+
+    /// Record the verification of a mapping's backfill, replacing any
+    /// earlier one. Its progress is left as it is.
+    pub async fn save_backfill_verification(
         &self,
-        mapping_name: &str,
-        last_id: Option<&str>,
-        total_rows: Option<i64>,
-        processed_rows: i64,
-        status: &str,
+        verification: &BackfillVerification,
     ) -> PgResult<()> {
         self.client
             .execute(
                 r#"
-                INSERT INTO __puffgres_backfill (mapping_name, last_id, total_rows, processed_rows, status, updated_at)
-                VALUES ($1, $2, $3, $4, $5, NOW())
+                INSERT INTO __puffgres_backfill (mapping_name, verify_status, verify_source_rows, verify_documents, verify_sampled, verify_mismatches, verified_at)
+                VALUES ($1, $2, $3, $4, $5, $6, NOW())
                 ON CONFLICT (mapping_name)
-                DO UPDATE SET last_id = $2, total_rows = $3, processed_rows = $4, status = $5, updated_at = NOW()
+                DO UPDATE SET verify_status = $2, verify_source_rows = $3, verify_documents = $4,
+                              verify_sampled = $5, verify_mismatches = $6, verified_at = NOW()
                 "#,
-                &[&mapping_name, &last_id, &total_rows, &processed_rows, &status],
+                &[
+                    &verification.mapping_name,
+                    &verification.status,
+                    &verification.source_rows,
+                    &verification.documents,
+                    &verification.sampled,
+                    &verification.mismatches,
+                ],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(())
     }
 
-    /// Clear backfill progress for a mapping.
+    /// Clear backfill progress for a mapping, including its partitions.
     pub async fn clear_backfill_progress(&self, mapping_name: &str) -> PgResult<()> {
+        for table in ["__puffgres_backfill", "__puffgres_backfill_partitions"] {
+            self.client
+                .execute(
+                    &format!("DELETE FROM {} WHERE mapping_name = $1", table),
+                    &[&mapping_name],
+                )
+                .await
+                .map_err(PgError::from)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the ranges of a mapping's partitioned backfill, in key order.
+    pub async fn get_backfill_partitions(
+        &self,
+        mapping_name: &str,
+    ) -> PgResult<Vec<BackfillPartition>> {
+        let rows = self
+            .client
+            .query(
+                r#"
+                SELECT mapping_name, partition_index, range_start, range_end, last_id,
+                       COALESCE(processed_rows, 0), COALESCE(upserted_rows, 0), status, updated_at
+                FROM __puffgres_backfill_partitions
+                WHERE mapping_name = $1
+                ORDER BY partition_index
+                "#,
+                &[&mapping_name],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| BackfillPartition {
+                mapping_name: r.get(0),
+                partition: r.get(1),
+                range_start: r.get(2),
+                range_end: r.get(3),
+                last_id: r.get(4),
+                processed_rows: r.get::<_, i64>(5),
+                upserted_rows: r.get::<_, i64>(6),
+                status: r.get(7),
+                updated_at: r.get(8),
+            })
+            .collect())
+    }
+
+    /// Save the progress of one range of a partitioned backfill.
+    pub async fn save_backfill_partition(&self, partition: &BackfillPartition) -> PgResult<()> {
         self.client
             .execute(
-                "DELETE FROM __puffgres_backfill WHERE mapping_name = $1",
-                &[&mapping_name],
+                r#"
+                INSERT INTO __puffgres_backfill_partitions (mapping_name, partition_index, range_start, range_end, last_id, processed_rows, upserted_rows, status, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+                ON CONFLICT (mapping_name, partition_index)
+                DO UPDATE SET range_start = $3, range_end = $4, last_id = $5, processed_rows = $6,
+                              upserted_rows = $7, status = $8, updated_at = NOW()
+                "#,
+                &[
+                    &partition.mapping_name,
+                    &partition.partition,
+                    &partition.range_start,
+                    &partition.range_end,
+                    &partition.last_id,
+                    &partition.processed_rows,
+                    &partition.upserted_rows,
+                    &partition.status,
+                ],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(())
     }
@@ -639,7 +1112,7 @@ impl PostgresStateStore {
                 &[&mapping_name, &version, &content, &content_hash],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         info!(mapping_name, version, "Stored transform");
         Ok(())
@@ -662,7 +1135,7 @@ impl PostgresStateStore {
                 &[&mapping_name, &version],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.map(|r| StoredTransform {
             id: r.get(0),
@@ -687,7 +1160,7 @@ impl PostgresStateStore {
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(rows
             .into_iter()
@@ -723,7 +1196,7 @@ impl PostgresStateStore {
                 &[&version, &mapping_name, &content],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(())
     }
@@ -745,7 +1218,7 @@ impl PostgresStateStore {
                 &[&version, &mapping_name],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.map(|r| r.get(0)))
     }
@@ -763,7 +1236,7 @@ impl PostgresStateStore {
                 &[],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(rows
             .into_iter()
@@ -788,11 +1261,33 @@ impl PostgresStateStore {
                 &[&schema, &table],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         Ok(row.is_some())
     }
 
+    /// List user tables and views as `(schema, table)`, leaving out system
+    /// schemas and puffgres' own tables.
+    pub async fn list_tables(&self) -> PgResult<Vec<(String, String)>> {
+        let rows = self
+            .client
+            .query(
+                r#"
+                SELECT table_schema, table_name
+                FROM information_schema.tables
+                WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+                  AND table_schema NOT LIKE 'pg_toast%'
+                  AND table_name NOT LIKE '\_\_puffgres\_%'
+                ORDER BY table_schema, table_name
+                "#,
+                &[],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(rows.into_iter().map(|r| (r.get(0), r.get(1))).collect())
+    }
+
     /// Validate that a table exists, returning an error with a helpful message if not.
     pub async fn validate_table_exists(&self, schema: &str, table: &str) -> PgResult<()> {
         if !self.table_exists(schema, table).await? {
@@ -804,6 +1299,50 @@ impl PostgresStateStore {
         Ok(())
     }
 
+    /// List a table's column names in declaration order. Empty if the table
+    /// doesn't exist.
+    pub async fn table_columns(&self, schema: &str, table: &str) -> PgResult<Vec<String>> {
+        let rows = self
+            .client
+            .query(
+                r#"
+                SELECT column_name
+                FROM information_schema.columns
+                WHERE table_schema = $1 AND table_name = $2
+                ORDER BY ordinal_position
+                "#,
+                &[&schema, &table],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(rows.into_iter().map(|r| r.get(0)).collect())
+    }
+
+    /// List a table's primary key columns in key order. Empty if the table
+    /// has no primary key or doesn't exist.
+    pub async fn primary_key_columns(&self, schema: &str, table: &str) -> PgResult<Vec<String>> {
+        let rows = self
+            .client
+            .query(
+                r#"
+                SELECT a.attname::text
+                FROM pg_index i
+                JOIN pg_class c ON c.oid = i.indrelid
+                JOIN pg_namespace n ON n.oid = c.relnamespace
+                JOIN LATERAL unnest(i.indkey) WITH ORDINALITY AS k(attnum, ord) ON true
+                JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = k.attnum
+                WHERE n.nspname = $1 AND c.relname = $2 AND i.indisprimary
+                ORDER BY k.ord
+                "#,
+                &[&schema, &table],
+            )
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(rows.into_iter().map(|r| r.get(0)).collect())
+    }
+
     /// Sample ID column values from a table for type validation.
     ///
     /// Returns sample values (cast to text) and the PostgreSQL data type of the column.
@@ -826,7 +1365,7 @@ impl PostgresStateStore {
                 &[&schema, &table, &column],
             )
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         let pg_type = type_row
             .map(|r| r.get::<_, String>(0))
@@ -846,7 +1385,7 @@ impl PostgresStateStore {
             .client
             .query(&query, &[&sample_size_i64])
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         let values: Vec<String> = rows
             .into_iter()
@@ -866,28 +1405,171 @@ impl PostgresStateStore {
             .client
             .execute("DELETE FROM __puffgres_checkpoints", &[])
             .await
-            .map_err(|e| PgError::Postgres(e.to_string()))?;
+            .map_err(PgError::from)?;
 
         info!(count, "Cleared all checkpoints");
         Ok(count)
     }
 
+    // -------------------------------------------------------------------------
+    // Export and import
+    // -------------------------------------------------------------------------
+
+    /// Copy every state table, read in one snapshot so checkpoints and
+    /// backfill progress agree with each other.
+    pub async fn export_state(&self) -> PgResult<StateSnapshot> {
+        self.client
+            .batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .await
+            .map_err(PgError::from)?;
+        let result = self.read_state_tables().await;
+        self.client
+            .batch_execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" })
+            .await
+            .map_err(PgError::from)?;
+
+        Ok(StateSnapshot {
+            format_version: STATE_SNAPSHOT_VERSION,
+            exported_at: Utc::now(),
+            tables: result?,
+        })
+    }
+
+    async fn read_state_tables(&self) -> PgResult<BTreeMap<String, Vec<serde_json::Value>>> {
+        let mut tables = BTreeMap::new();
+        for table in STATE_TABLES {
+            let row = self
+                .client
+                .query_one(
+                    &format!(
+                        "SELECT COALESCE(jsonb_agg(to_jsonb(t)), '[]'::jsonb) FROM {} t",
+                        table
+                    ),
+                    &[],
+                )
+                .await
+                .map_err(PgError::from)?;
+            let rows = match row.get::<_, serde_json::Value>(0) {
+                serde_json::Value::Array(rows) => rows,
+                other => vec![other],
+            };
+            tables.insert(table.to_string(), rows);
+        }
+        Ok(tables)
+    }
+
+    /// Load a snapshot into this database's state tables, in one transaction.
+    ///
+    /// Fails if any of the tables already has rows, unless `replace` is set,
+    /// in which case they're emptied first. Returns the rows imported per
+    /// table.
+    pub async fn import_state(
+        &self,
+        snapshot: &StateSnapshot,
+        replace: bool,
+    ) -> PgResult<Vec<(String, usize)>> {
+        if snapshot.format_version != STATE_SNAPSHOT_VERSION {
+            return Err(PgError::ParseError(format!(
+                "state snapshot has format version {}, this puffgres reads version {}",
+                snapshot.format_version, STATE_SNAPSHOT_VERSION
+            )));
+        }
+        if let Some(table) = snapshot
+            .tables
+            .keys()
+            .find(|t| !STATE_TABLES.contains(&t.as_str()))
+        {
+            return Err(PgError::ParseError(format!(
+                "state snapshot has unknown table '{}'",
+                table
+            )));
+        }
+
+        self.client
+            .batch_execute("BEGIN")
+            .await
+            .map_err(PgError::from)?;
+        let result = self.write_state_tables(snapshot, replace).await;
+        self.client
+            .batch_execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" })
+            .await
+            .map_err(PgError::from)?;
+        result
+    }
+
+    async fn write_state_tables(
+        &self,
+        snapshot: &StateSnapshot,
+        replace: bool,
+    ) -> PgResult<Vec<(String, usize)>> {
+        if replace {
+            self.client
+                .batch_execute(&format!("TRUNCATE {}", STATE_TABLES.join(", ")))
+                .await
+                .map_err(PgError::from)?;
+        } else {
+            let mut occupied = Vec::new();
+            for table in STATE_TABLES {
+                let row = self
+                    .client
+                    .query_one(&format!("SELECT EXISTS (SELECT 1 FROM {})", table), &[])
+                    .await
+                    .map_err(PgError::from)?;
+                if row.get::<_, bool>(0) {
+                    occupied.push(table.to_string());
+                }
+            }
+            if !occupied.is_empty() {
+                return Err(PgError::StateNotEmpty(occupied));
+            }
+        }
+
+        let mut imported = Vec::new();
+        for table in STATE_TABLES {
+            let rows = snapshot
+                .tables
+                .get(*table)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            if rows.is_empty() {
+                continue;
+            }
+            // The row type fills in columns by name, so snapshots from
+            // before a column was added import with it NULL
+            let rows_json = serde_json::Value::Array(rows.to_vec());
+            self.client
+                .execute(
+                    &format!(
+                        "INSERT INTO {table} SELECT * FROM jsonb_populate_recordset(NULL::{table}, $1)"
+                    ),
+                    &[&rows_json],
+                )
+                .await
+                .map_err(PgError::from)?;
+            if SERIAL_TABLES.contains(table) {
+                self.client
+                    .execute(
+                        &format!(
+                            "SELECT setval(pg_get_serial_sequence('{table}', 'id'), MAX(id)) FROM {table}"
+                        ),
+                        &[],
+                    )
+                    .await
+                    .map_err(PgError::from)?;
+            }
+            info!(table, rows = rows.len(), "Imported state table");
+            imported.push((table.to_string(), rows.len()));
+        }
+        Ok(imported)
+    }
+
     /// Drop all puffgres tables.
     pub async fn drop_all_tables(&self) -> PgResult<()> {
-        let tables = [
-            "__puffgres_migrations",
-            "__puffgres_checkpoints",
-            "__puffgres_dlq",
-            "__puffgres_backfill",
-            "__puffgres_transforms",
-            "__puffgres_migration_content",
-        ];
-
-        for table in &tables {
+        for table in STATE_TABLES {
             self.client
                 .execute(&format!("DROP TABLE IF EXISTS {} CASCADE", table), &[])
                 .await
-                .map_err(|e| PgError::Postgres(e.to_string()))?;
+                .map_err(PgError::from)?;
             info!(table, "Dropped table");
         }
 
@@ -901,6 +1583,148 @@ fn quote_identifier(ident: &str) -> String {
     format!("\"{}\"", ident.replace('"', "\"\""))
 }
 
+/// The runner, backfill and DLQ commands use the store through this trait;
+/// each method forwards to the inherent method of the same name.
+#[async_trait]
+impl StateStore for PostgresStateStore {
+    async fn get_checkpoint(&self, mapping_name: &str) -> StateResult<Option<Checkpoint>> {
+        Ok(PostgresStateStore::get_checkpoint(self, mapping_name).await?)
+    }
+
+    async fn save_checkpoint(
+        &self,
+        mapping_name: &str,
+        checkpoint: &Checkpoint,
+    ) -> StateResult<()> {
+        Ok(PostgresStateStore::save_checkpoint(self, mapping_name, checkpoint).await?)
+    }
+
+    async fn get_all_checkpoints(&self) -> StateResult<Vec<(String, Checkpoint)>> {
+        Ok(PostgresStateStore::get_all_checkpoints(self).await?)
+    }
+
+    async fn get_min_lsn(&self) -> StateResult<Option<u64>> {
+        Ok(PostgresStateStore::get_min_lsn(self).await?)
+    }
+
+    async fn record_reconnect(&self, mapping_names: &[String]) -> StateResult<()> {
+        Ok(PostgresStateStore::record_reconnect(self, mapping_names).await?)
+    }
+
+    async fn set_paused(&self, mapping_name: &str, paused: bool) -> StateResult<()> {
+        Ok(PostgresStateStore::set_paused(self, mapping_name, paused).await?)
+    }
+
+    async fn get_paused_mappings(&self) -> StateResult<Vec<String>> {
+        Ok(PostgresStateStore::get_paused_mappings(self).await?)
+    }
+
+    async fn add_to_dlq(
+        &self,
+        mapping_name: &str,
+        lsn: u64,
+        event_json: &serde_json::Value,
+        error_message: &str,
+        error_kind: &str,
+        run_id: Option<&str>,
+    ) -> StateResult<i32> {
+        Ok(PostgresStateStore::add_to_dlq(
+            self,
+            mapping_name,
+            lsn,
+            event_json,
+            error_message,
+            error_kind,
+            run_id,
+        )
+        .await?)
+    }
+
+    async fn get_dlq_entries(
+        &self,
+        mapping_name: Option<&str>,
+        limit: i64,
+    ) -> StateResult<Vec<DlqEntry>> {
+        Ok(PostgresStateStore::get_dlq_entries(self, mapping_name, limit).await?)
+    }
+
+    async fn get_dlq_entry(&self, id: i32) -> StateResult<Option<DlqEntry>> {
+        Ok(PostgresStateStore::get_dlq_entry(self, id).await?)
+    }
+
+    async fn increment_dlq_retry(&self, id: i32) -> StateResult<()> {
+        Ok(PostgresStateStore::increment_dlq_retry(self, id).await?)
+    }
+
+    async fn record_dlq_failure(
+        &self,
+        id: i32,
+        error_message: &str,
+        error_kind: &str,
+    ) -> StateResult<()> {
+        Ok(PostgresStateStore::record_dlq_failure(self, id, error_message, error_kind).await?)
+    }
+
+    async fn delete_dlq_entry(&self, id: i32) -> StateResult<()> {
+        Ok(PostgresStateStore::delete_dlq_entry(self, id).await?)
+    }
+
+    async fn clear_dlq(&self, mapping_name: Option<&str>) -> StateResult<u64> {
+        Ok(PostgresStateStore::clear_dlq(self, mapping_name).await?)
+    }
+
+    async fn get_backfill_progress(
+        &self,
+        mapping_name: &str,
+    ) -> StateResult<Option<BackfillProgress>> {
+        Ok(PostgresStateStore::get_backfill_progress(self, mapping_name).await?)
+    }
+
+    async fn save_backfill_progress(&self, progress: &BackfillProgress) -> StateResult<()> {
+        Ok(PostgresStateStore::save_backfill_progress(self, progress).await?)
+    }
+
+    async fn clear_backfill_progress(&self, mapping_name: &str) -> StateResult<()> {
+        Ok(PostgresStateStore::clear_backfill_progress(self, mapping_name).await?)
+    }
+
+    async fn get_backfill_partitions(
+        &self,
+        mapping_name: &str,
+    ) -> StateResult<Vec<BackfillPartition>> {
+        Ok(PostgresStateStore::get_backfill_partitions(self, mapping_name).await?)
+    }
+
+    async fn save_backfill_partition(&self, partition: &BackfillPartition) -> StateResult<()> {
+        Ok(PostgresStateStore::save_backfill_partition(self, partition).await?)
+    }
+
+    async fn store_transform(
+        &self,
+        mapping_name: &str,
+        version: i32,
+        content: &str,
+        content_hash: &str,
+    ) -> StateResult<()> {
+        Ok(
+            PostgresStateStore::store_transform(self, mapping_name, version, content, content_hash)
+                .await?,
+        )
+    }
+
+    async fn get_transform(
+        &self,
+        mapping_name: &str,
+        version: i32,
+    ) -> StateResult<Option<StoredTransform>> {
+        Ok(PostgresStateStore::get_transform(self, mapping_name, version).await?)
+    }
+
+    async fn get_all_transforms(&self) -> StateResult<Vec<StoredTransform>> {
+        Ok(PostgresStateStore::get_all_transforms(self).await?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -913,6 +1737,13 @@ mod tests {
         assert_eq!(quote_identifier("weird\"name"), "\"weird\"\"name\"");
     }
 
+    #[test]
+    fn test_serial_tables_are_exported() {
+        for table in SERIAL_TABLES {
+            assert!(STATE_TABLES.contains(table), "{} isn't exported", table);
+        }
+    }
+
     #[test]
     fn test_checkpoint_default() {
         let cp = Checkpoint::default();
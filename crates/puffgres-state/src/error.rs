@@ -10,6 +10,27 @@ pub enum StateError {
 
     #[error("serialization error: {0}")]
     Serialization(String),
+
+    #[error("state backend error: {0}")]
+    Backend(String),
+
+    #[error("state backend unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl StateError {
+    /// Whether the operation could succeed if tried again, e.g. the backend's
+    /// connection dropped or the database was briefly locked.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StateError::Unavailable(_) => true,
+            StateError::Sqlite(e) => matches!(
+                e.sqlite_error_code(),
+                Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+            ),
+            _ => false,
+        }
+    }
 }
 
 pub type StateResult<T> = Result<T, StateError>;
@@ -1,32 +1,196 @@
 mod error;
 mod sqlite;
 
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
 pub use error::{StateError, StateResult};
 pub use sqlite::SqliteStateStore;
 
 /// Checkpoint state for a mapping.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Checkpoint {
     /// The last successfully processed LSN.
     pub lsn: u64,
     /// Number of events processed.
     pub events_processed: u64,
+    /// Last update timestamp.
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Times the replication stream was re-established after a dropped connection.
+    pub reconnects: u64,
+    /// When the stream was last re-established.
+    pub last_reconnect_at: Option<DateTime<Utc>>,
+}
+
+/// Dead letter queue entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DlqEntry {
+    pub id: i32,
+    pub mapping_name: String,
+    pub lsn: u64,
+    pub event_json: serde_json::Value,
+    pub error_message: String,
+    pub error_kind: String,
+    pub retry_count: i32,
+    pub created_at: DateTime<Utc>,
+    /// The run that hit the error, for entries written since run ids were recorded.
+    pub run_id: Option<String>,
+}
+
+/// Backfill progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillProgress {
+    pub mapping_name: String,
+    pub last_id: Option<String>,
+    pub total_rows: Option<i64>,
+    pub processed_rows: i64,
+    pub upserted_rows: i64,
+    /// Read rate when progress was last saved.
+    pub rows_per_second: Option<f64>,
+    pub status: String,
+    pub updated_at: DateTime<Utc>,
+    /// The backfill run that last saved progress.
+    pub run_id: Option<String>,
+}
+
+/// Progress of one key range of a partitioned backfill.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillPartition {
+    pub mapping_name: String,
+    /// Position of the range in the key space, from 1.
+    pub partition: i32,
+    /// First id in the range, as text. `None` for the first range.
+    pub range_start: Option<String>,
+    /// Id the range stops before, as text. `None` for the last range.
+    pub range_end: Option<String>,
+    pub last_id: Option<String>,
+    pub processed_rows: i64,
+    pub upserted_rows: i64,
+    pub status: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Stored transform for immutability tracking.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredTransform {
+    pub id: i32,
+    pub mapping_name: String,
+    pub version: i32,
+    pub content: String,
+    pub content_hash: String,
+    pub created_at: DateTime<Utc>,
 }
 
 /// Trait for state storage backends.
+///
+/// Covers the state the runner and backfill read and write while syncing.
+/// Migration tracking and schema inspection stay on the Postgres store, since
+/// they need the source database anyway.
+#[async_trait]
 pub trait StateStore: Send + Sync {
     /// Get the checkpoint for a mapping.
-    fn get_checkpoint(&self, mapping_name: &str) -> StateResult<Option<Checkpoint>>;
+    async fn get_checkpoint(&self, mapping_name: &str) -> StateResult<Option<Checkpoint>>;
 
-    /// Save a checkpoint for a mapping.
-    fn save_checkpoint(&self, mapping_name: &str, checkpoint: &Checkpoint) -> StateResult<()>;
+    /// Save a checkpoint for a mapping. Reconnect counts are left as they are.
+    async fn save_checkpoint(&self, mapping_name: &str, checkpoint: &Checkpoint)
+        -> StateResult<()>;
 
-    /// Get all checkpoints.
-    fn get_all_checkpoints(&self) -> StateResult<Vec<(String, Checkpoint)>>;
+    /// Get all checkpoints, ordered by mapping name.
+    async fn get_all_checkpoints(&self) -> StateResult<Vec<(String, Checkpoint)>>;
 
     /// Get the minimum LSN across all mappings (safe restart point).
-    fn get_min_lsn(&self) -> StateResult<Option<u64>> {
-        let checkpoints = self.get_all_checkpoints()?;
+    async fn get_min_lsn(&self) -> StateResult<Option<u64>> {
+        let checkpoints = self.get_all_checkpoints().await?;
         Ok(checkpoints.iter().map(|(_, c)| c.lsn).min())
     }
+
+    /// Count a re-established replication connection against each mapping.
+    async fn record_reconnect(&self, mapping_names: &[String]) -> StateResult<()>;
+
+    /// Pause or resume a mapping. The runner skips a paused mapping's changes
+    /// without acknowledging past them.
+    async fn set_paused(&self, mapping_name: &str, paused: bool) -> StateResult<()>;
+
+    /// Names of the paused mappings.
+    async fn get_paused_mappings(&self) -> StateResult<Vec<String>>;
+
+    /// Add an entry to the dead letter queue, returning its ID.
+    async fn add_to_dlq(
+        &self,
+        mapping_name: &str,
+        lsn: u64,
+        event_json: &serde_json::Value,
+        error_message: &str,
+        error_kind: &str,
+        run_id: Option<&str>,
+    ) -> StateResult<i32>;
+
+    /// Get DLQ entries for a mapping (or all mappings), newest first.
+    async fn get_dlq_entries(
+        &self,
+        mapping_name: Option<&str>,
+        limit: i64,
+    ) -> StateResult<Vec<DlqEntry>>;
+
+    /// Get a single DLQ entry by ID.
+    async fn get_dlq_entry(&self, id: i32) -> StateResult<Option<DlqEntry>>;
+
+    /// Increment retry count for a DLQ entry.
+    async fn increment_dlq_retry(&self, id: i32) -> StateResult<()>;
+
+    /// Count a failed retry of a DLQ entry, replacing its error with the new one.
+    async fn record_dlq_failure(
+        &self,
+        id: i32,
+        error_message: &str,
+        error_kind: &str,
+    ) -> StateResult<()>;
+
+    /// Delete a DLQ entry.
+    async fn delete_dlq_entry(&self, id: i32) -> StateResult<()>;
+
+    /// Clear DLQ entries for a mapping (or all if None), returning how many were removed.
+    async fn clear_dlq(&self, mapping_name: Option<&str>) -> StateResult<u64>;
+
+    /// Get backfill progress for a mapping.
+    async fn get_backfill_progress(
+        &self,
+        mapping_name: &str,
+    ) -> StateResult<Option<BackfillProgress>>;
+
+    /// Save backfill progress. `updated_at` is set by the store.
+    async fn save_backfill_progress(&self, progress: &BackfillProgress) -> StateResult<()>;
+
+    /// Clear backfill progress for a mapping, including its partitions.
+    async fn clear_backfill_progress(&self, mapping_name: &str) -> StateResult<()>;
+
+    /// Get the ranges of a mapping's partitioned backfill, in key order. Empty
+    /// if its backfill isn't partitioned.
+    async fn get_backfill_partitions(
+        &self,
+        mapping_name: &str,
+    ) -> StateResult<Vec<BackfillPartition>>;
+
+    /// Save the progress of one range. `updated_at` is set by the store.
+    async fn save_backfill_partition(&self, partition: &BackfillPartition) -> StateResult<()>;
+
+    /// Store a transform for immutability tracking. Existing versions are kept.
+    async fn store_transform(
+        &self,
+        mapping_name: &str,
+        version: i32,
+        content: &str,
+        content_hash: &str,
+    ) -> StateResult<()>;
+
+    /// Get a stored transform by mapping name and version.
+    async fn get_transform(
+        &self,
+        mapping_name: &str,
+        version: i32,
+    ) -> StateResult<Option<StoredTransform>>;
+
+    /// Get all stored transforms.
+    async fn get_all_transforms(&self) -> StateResult<Vec<StoredTransform>>;
 }
@@ -1,11 +1,14 @@
 use std::path::Path;
 use std::sync::Mutex;
 
-use rusqlite::Connection;
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension, Row};
 use tracing::info;
 
 use crate::error::StateResult;
-use crate::{Checkpoint, StateStore};
+use crate::{
+    BackfillPartition, BackfillProgress, Checkpoint, DlqEntry, StateStore, StoredTransform,
+};
 
 /// SQLite-backed state store.
 pub struct SqliteStateStore {
@@ -19,17 +22,7 @@ impl SqliteStateStore {
         info!(path = %path.display(), "Opening state store");
 
         let conn = Connection::open(path)?;
-
-        // Create tables if they don't exist
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS checkpoints (
-                mapping_name TEXT PRIMARY KEY,
-                lsn INTEGER NOT NULL,
-                events_processed INTEGER NOT NULL DEFAULT 0,
-                updated_at TEXT DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
+        ensure_schema(&conn)?;
 
         Ok(Self {
             conn: Mutex::new(conn),
@@ -39,16 +32,7 @@ impl SqliteStateStore {
     /// Create an in-memory state store (for testing).
     pub fn in_memory() -> StateResult<Self> {
         let conn = Connection::open_in_memory()?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS checkpoints (
-                mapping_name TEXT PRIMARY KEY,
-                lsn INTEGER NOT NULL,
-                events_processed INTEGER NOT NULL DEFAULT 0,
-                updated_at TEXT DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
+        ensure_schema(&conn)?;
 
         Ok(Self {
             conn: Mutex::new(conn),
@@ -56,29 +40,166 @@ impl SqliteStateStore {
     }
 }
 
-impl StateStore for SqliteStateStore {
-    fn get_checkpoint(&self, mapping_name: &str) -> StateResult<Option<Checkpoint>> {
-        let conn = self.conn.lock().unwrap();
+/// Create tables if they don't exist.
+fn ensure_schema(conn: &Connection) -> StateResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS checkpoints (
+            mapping_name TEXT PRIMARY KEY,
+            lsn INTEGER NOT NULL,
+            events_processed INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS dlq (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            mapping_name TEXT NOT NULL,
+            lsn INTEGER NOT NULL,
+            event_json TEXT NOT NULL,
+            error_message TEXT NOT NULL,
+            error_kind TEXT NOT NULL,
+            retry_count INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS backfill (
+            mapping_name TEXT PRIMARY KEY,
+            last_id TEXT,
+            total_rows INTEGER,
+            processed_rows INTEGER NOT NULL DEFAULT 0,
+            upserted_rows INTEGER NOT NULL DEFAULT 0,
+            rows_per_second REAL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS backfill_partitions (
+            mapping_name TEXT NOT NULL,
+            partition_index INTEGER NOT NULL,
+            range_start TEXT,
+            range_end TEXT,
+            last_id TEXT,
+            processed_rows INTEGER NOT NULL DEFAULT 0,
+            upserted_rows INTEGER NOT NULL DEFAULT 0,
+            status TEXT NOT NULL DEFAULT 'pending',
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (mapping_name, partition_index)
+        );
+        CREATE TABLE IF NOT EXISTS control (
+            mapping_name TEXT PRIMARY KEY,
+            paused INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS transforms (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            mapping_name TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            content_hash TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(mapping_name, version)
+        );",
+    )?;
+
+    // Columns added after the checkpoints table was first released
+    add_column_if_missing(
+        conn,
+        "checkpoints",
+        "reconnects",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(conn, "checkpoints", "last_reconnect_at", "TEXT")?;
+    add_column_if_missing(conn, "dlq", "run_id", "TEXT")?;
+    add_column_if_missing(conn, "backfill", "run_id", "TEXT")?;
+
+    Ok(())
+}
 
-        let mut stmt = conn.prepare(
-            "SELECT lsn, events_processed FROM checkpoints WHERE mapping_name = ?1",
+/// SQLite has no `ADD COLUMN IF NOT EXISTS`, so check the table first.
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> StateResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |row| row.get(0),
+    )?;
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
         )?;
+    }
 
-        let result = stmt.query_row([mapping_name], |row| {
-            Ok(Checkpoint {
-                lsn: row.get::<_, i64>(0)? as u64,
-                events_processed: row.get::<_, i64>(1)? as u64,
-            })
-        });
+    Ok(())
+}
 
-        match result {
-            Ok(checkpoint) => Ok(Some(checkpoint)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+const CHECKPOINT_COLUMNS: &str = "lsn, events_processed, updated_at, reconnects, last_reconnect_at";
+
+fn checkpoint_from_row(row: &Row<'_>, offset: usize) -> rusqlite::Result<Checkpoint> {
+    Ok(Checkpoint {
+        lsn: row.get::<_, i64>(offset)? as u64,
+        events_processed: row.get::<_, i64>(offset + 1)? as u64,
+        updated_at: row.get(offset + 2)?,
+        reconnects: row.get::<_, i64>(offset + 3)? as u64,
+        last_reconnect_at: row.get(offset + 4)?,
+    })
+}
+
+const DLQ_COLUMNS: &str =
+    "id, mapping_name, lsn, event_json, error_message, error_kind, retry_count, created_at, run_id";
+
+fn dlq_entry_from_row(row: &Row<'_>) -> rusqlite::Result<DlqEntry> {
+    Ok(DlqEntry {
+        id: row.get(0)?,
+        mapping_name: row.get(1)?,
+        lsn: row.get::<_, i64>(2)? as u64,
+        event_json: row.get(3)?,
+        error_message: row.get(4)?,
+        error_kind: row.get(5)?,
+        retry_count: row.get(6)?,
+        created_at: row.get(7)?,
+        run_id: row.get(8)?,
+    })
+}
+
+const TRANSFORM_COLUMNS: &str = "id, mapping_name, version, content, content_hash, created_at";
+
+fn transform_from_row(row: &Row<'_>) -> rusqlite::Result<StoredTransform> {
+    Ok(StoredTransform {
+        id: row.get(0)?,
+        mapping_name: row.get(1)?,
+        version: row.get(2)?,
+        content: row.get(3)?,
+        content_hash: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+#[async_trait]
+impl StateStore for SqliteStateStore {
+    async fn get_checkpoint(&self, mapping_name: &str) -> StateResult<Option<Checkpoint>> {
+        let conn = self.conn.lock().unwrap();
+
+        let checkpoint = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM checkpoints WHERE mapping_name = ?1",
+                    CHECKPOINT_COLUMNS
+                ),
+                [mapping_name],
+                |row| checkpoint_from_row(row, 0),
+            )
+            .optional()?;
+
+        Ok(checkpoint)
     }
 
-    fn save_checkpoint(&self, mapping_name: &str, checkpoint: &Checkpoint) -> StateResult<()> {
+    async fn save_checkpoint(
+        &self,
+        mapping_name: &str,
+        checkpoint: &Checkpoint,
+    ) -> StateResult<()> {
         let conn = self.conn.lock().unwrap();
 
         conn.execute(
@@ -98,19 +219,281 @@ impl StateStore for SqliteStateStore {
         Ok(())
     }
 
-    fn get_all_checkpoints(&self) -> StateResult<Vec<(String, Checkpoint)>> {
+    async fn get_all_checkpoints(&self) -> StateResult<Vec<(String, Checkpoint)>> {
         let conn = self.conn.lock().unwrap();
 
-        let mut stmt = conn.prepare("SELECT mapping_name, lsn, events_processed FROM checkpoints")?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT mapping_name, {} FROM checkpoints ORDER BY mapping_name",
+            CHECKPOINT_COLUMNS
+        ))?;
 
         let rows = stmt.query_map([], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                Checkpoint {
-                    lsn: row.get::<_, i64>(1)? as u64,
-                    events_processed: row.get::<_, i64>(2)? as u64,
+            Ok((row.get::<_, String>(0)?, checkpoint_from_row(row, 1)?))
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            result.push(row?);
+        }
+
+        Ok(result)
+    }
+
+    async fn record_reconnect(&self, mapping_names: &[String]) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+
+        for mapping_name in mapping_names {
+            conn.execute(
+                "UPDATE checkpoints
+                 SET reconnects = reconnects + 1, last_reconnect_at = CURRENT_TIMESTAMP
+                 WHERE mapping_name = ?1",
+                [mapping_name],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_paused(&self, mapping_name: &str, paused: bool) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO control (mapping_name, paused, updated_at)
+             VALUES (?1, ?2, CURRENT_TIMESTAMP)
+             ON CONFLICT(mapping_name) DO UPDATE SET
+                paused = ?2,
+                updated_at = CURRENT_TIMESTAMP",
+            rusqlite::params![mapping_name, paused],
+        )?;
+
+        Ok(())
+    }
+
+    async fn get_paused_mappings(&self) -> StateResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt =
+            conn.prepare("SELECT mapping_name FROM control WHERE paused ORDER BY mapping_name")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            result.push(row?);
+        }
+
+        Ok(result)
+    }
+
+    async fn add_to_dlq(
+        &self,
+        mapping_name: &str,
+        lsn: u64,
+        event_json: &serde_json::Value,
+        error_message: &str,
+        error_kind: &str,
+        run_id: Option<&str>,
+    ) -> StateResult<i32> {
+        let conn = self.conn.lock().unwrap();
+
+        let id = conn.query_row(
+            "INSERT INTO dlq (mapping_name, lsn, event_json, error_message, error_kind, run_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             RETURNING id",
+            rusqlite::params![
+                mapping_name,
+                lsn as i64,
+                event_json,
+                error_message,
+                error_kind,
+                run_id
+            ],
+            |row| row.get(0),
+        )?;
+
+        Ok(id)
+    }
+
+    async fn get_dlq_entries(
+        &self,
+        mapping_name: Option<&str>,
+        limit: i64,
+    ) -> StateResult<Vec<DlqEntry>> {
+        let conn = self.conn.lock().unwrap();
+
+        // A NULL mapping name matches every entry
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM dlq
+             WHERE ?1 IS NULL OR mapping_name = ?1
+             ORDER BY created_at DESC, id DESC
+             LIMIT ?2",
+            DLQ_COLUMNS
+        ))?;
+
+        let rows = stmt.query_map(rusqlite::params![mapping_name, limit], dlq_entry_from_row)?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            result.push(row?);
+        }
+
+        Ok(result)
+    }
+
+    async fn get_dlq_entry(&self, id: i32) -> StateResult<Option<DlqEntry>> {
+        let conn = self.conn.lock().unwrap();
+
+        let entry = conn
+            .query_row(
+                &format!("SELECT {} FROM dlq WHERE id = ?1", DLQ_COLUMNS),
+                [id],
+                dlq_entry_from_row,
+            )
+            .optional()?;
+
+        Ok(entry)
+    }
+
+    async fn increment_dlq_retry(&self, id: i32) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE dlq SET retry_count = retry_count + 1 WHERE id = ?1",
+            [id],
+        )?;
+        Ok(())
+    }
+
+    async fn record_dlq_failure(
+        &self,
+        id: i32,
+        error_message: &str,
+        error_kind: &str,
+    ) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE dlq
+             SET retry_count = retry_count + 1, error_message = ?2, error_kind = ?3
+             WHERE id = ?1",
+            rusqlite::params![id, error_message, error_kind],
+        )?;
+        Ok(())
+    }
+
+    async fn delete_dlq_entry(&self, id: i32) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM dlq WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    async fn clear_dlq(&self, mapping_name: Option<&str>) -> StateResult<u64> {
+        let conn = self.conn.lock().unwrap();
+        let count = conn.execute(
+            "DELETE FROM dlq WHERE ?1 IS NULL OR mapping_name = ?1",
+            [mapping_name],
+        )?;
+        Ok(count as u64)
+    }
+
+    async fn get_backfill_progress(
+        &self,
+        mapping_name: &str,
+    ) -> StateResult<Option<BackfillProgress>> {
+        let conn = self.conn.lock().unwrap();
+
+        let progress = conn
+            .query_row(
+                "SELECT mapping_name, last_id, total_rows, processed_rows, upserted_rows,
+                        rows_per_second, status, updated_at, run_id
+                 FROM backfill
+                 WHERE mapping_name = ?1",
+                [mapping_name],
+                |row| {
+                    Ok(BackfillProgress {
+                        mapping_name: row.get(0)?,
+                        last_id: row.get(1)?,
+                        total_rows: row.get(2)?,
+                        processed_rows: row.get(3)?,
+                        upserted_rows: row.get(4)?,
+                        rows_per_second: row.get(5)?,
+                        status: row.get(6)?,
+                        updated_at: row.get(7)?,
+                        run_id: row.get(8)?,
+                    })
                 },
-            ))
+            )
+            .optional()?;
+
+        Ok(progress)
+    }
+
+    async fn save_backfill_progress(&self, progress: &BackfillProgress) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO backfill (mapping_name, last_id, total_rows, processed_rows,
+                                   upserted_rows, rows_per_second, status, updated_at, run_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, CURRENT_TIMESTAMP, ?8)
+             ON CONFLICT(mapping_name) DO UPDATE SET
+                last_id = ?2,
+                total_rows = ?3,
+                processed_rows = ?4,
+                upserted_rows = ?5,
+                rows_per_second = ?6,
+                status = ?7,
+                updated_at = CURRENT_TIMESTAMP,
+                run_id = ?8",
+            rusqlite::params![
+                progress.mapping_name,
+                progress.last_id,
+                progress.total_rows,
+                progress.processed_rows,
+                progress.upserted_rows,
+                progress.rows_per_second,
+                progress.status,
+                progress.run_id
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn clear_backfill_progress(&self, mapping_name: &str) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM backfill WHERE mapping_name = ?1",
+            [mapping_name],
+        )?;
+        conn.execute(
+            "DELETE FROM backfill_partitions WHERE mapping_name = ?1",
+            [mapping_name],
+        )?;
+        Ok(())
+    }
+
+    async fn get_backfill_partitions(
+        &self,
+        mapping_name: &str,
+    ) -> StateResult<Vec<BackfillPartition>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT mapping_name, partition_index, range_start, range_end, last_id,
+                    processed_rows, upserted_rows, status, updated_at
+             FROM backfill_partitions
+             WHERE mapping_name = ?1
+             ORDER BY partition_index",
+        )?;
+        let rows = stmt.query_map([mapping_name], |row| {
+            Ok(BackfillPartition {
+                mapping_name: row.get(0)?,
+                partition: row.get(1)?,
+                range_start: row.get(2)?,
+                range_end: row.get(3)?,
+                last_id: row.get(4)?,
+                processed_rows: row.get(5)?,
+                upserted_rows: row.get(6)?,
+                status: row.get(7)?,
+                updated_at: row.get(8)?,
+            })
         })?;
 
         let mut result = Vec::new();
@@ -120,80 +503,380 @@ impl StateStore for SqliteStateStore {
 
         Ok(result)
     }
+
+    async fn save_backfill_partition(&self, partition: &BackfillPartition) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO backfill_partitions (mapping_name, partition_index, range_start,
+                                              range_end, last_id, processed_rows,
+                                              upserted_rows, status, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, CURRENT_TIMESTAMP)
+             ON CONFLICT(mapping_name, partition_index) DO UPDATE SET
+                range_start = ?3,
+                range_end = ?4,
+                last_id = ?5,
+                processed_rows = ?6,
+                upserted_rows = ?7,
+                status = ?8,
+                updated_at = CURRENT_TIMESTAMP",
+            rusqlite::params![
+                partition.mapping_name,
+                partition.partition,
+                partition.range_start,
+                partition.range_end,
+                partition.last_id,
+                partition.processed_rows,
+                partition.upserted_rows,
+                partition.status
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn store_transform(
+        &self,
+        mapping_name: &str,
+        version: i32,
+        content: &str,
+        content_hash: &str,
+    ) -> StateResult<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO transforms (mapping_name, version, content, content_hash)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(mapping_name, version) DO NOTHING",
+            rusqlite::params![mapping_name, version, content, content_hash],
+        )?;
+
+        info!(mapping_name, version, "Stored transform");
+        Ok(())
+    }
+
+    async fn get_transform(
+        &self,
+        mapping_name: &str,
+        version: i32,
+    ) -> StateResult<Option<StoredTransform>> {
+        let conn = self.conn.lock().unwrap();
+
+        let transform = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM transforms WHERE mapping_name = ?1 AND version = ?2",
+                    TRANSFORM_COLUMNS
+                ),
+                rusqlite::params![mapping_name, version],
+                transform_from_row,
+            )
+            .optional()?;
+
+        Ok(transform)
+    }
+
+    async fn get_all_transforms(&self) -> StateResult<Vec<StoredTransform>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM transforms ORDER BY mapping_name, version",
+            TRANSFORM_COLUMNS
+        ))?;
+
+        let rows = stmt.query_map([], transform_from_row)?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            result.push(row?);
+        }
+
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_checkpoint_roundtrip() {
+    fn checkpoint(lsn: u64, events_processed: u64) -> Checkpoint {
+        Checkpoint {
+            lsn,
+            events_processed,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_roundtrip() {
         let store = SqliteStateStore::in_memory().unwrap();
 
         // Initially no checkpoint
-        assert!(store.get_checkpoint("test_mapping").unwrap().is_none());
+        assert!(store
+            .get_checkpoint("test_mapping")
+            .await
+            .unwrap()
+            .is_none());
 
         // Save checkpoint
-        let checkpoint = Checkpoint {
-            lsn: 12345,
-            events_processed: 100,
-        };
-        store.save_checkpoint("test_mapping", &checkpoint).unwrap();
+        let checkpoint = checkpoint(12345, 100);
+        store
+            .save_checkpoint("test_mapping", &checkpoint)
+            .await
+            .unwrap();
 
         // Read it back
-        let loaded = store.get_checkpoint("test_mapping").unwrap().unwrap();
+        let loaded = store.get_checkpoint("test_mapping").await.unwrap().unwrap();
         assert_eq!(loaded.lsn, 12345);
         assert_eq!(loaded.events_processed, 100);
+        assert!(loaded.updated_at.is_some());
     }
 
-    #[test]
-    fn test_checkpoint_update() {
+    #[tokio::test]
+    async fn test_checkpoint_update() {
         let store = SqliteStateStore::in_memory().unwrap();
 
         store
-            .save_checkpoint("test", &Checkpoint { lsn: 100, events_processed: 10 })
+            .save_checkpoint("test", &checkpoint(100, 10))
+            .await
             .unwrap();
 
         store
-            .save_checkpoint("test", &Checkpoint { lsn: 200, events_processed: 20 })
+            .save_checkpoint("test", &checkpoint(200, 20))
+            .await
             .unwrap();
 
-        let loaded = store.get_checkpoint("test").unwrap().unwrap();
+        let loaded = store.get_checkpoint("test").await.unwrap().unwrap();
         assert_eq!(loaded.lsn, 200);
         assert_eq!(loaded.events_processed, 20);
     }
 
-    #[test]
-    fn test_get_all_checkpoints() {
+    #[tokio::test]
+    async fn test_get_all_checkpoints() {
         let store = SqliteStateStore::in_memory().unwrap();
 
         store
-            .save_checkpoint("mapping1", &Checkpoint { lsn: 100, events_processed: 10 })
+            .save_checkpoint("mapping1", &checkpoint(100, 10))
+            .await
             .unwrap();
         store
-            .save_checkpoint("mapping2", &Checkpoint { lsn: 200, events_processed: 20 })
+            .save_checkpoint("mapping2", &checkpoint(200, 20))
+            .await
             .unwrap();
 
-        let all = store.get_all_checkpoints().unwrap();
+        let all = store.get_all_checkpoints().await.unwrap();
         assert_eq!(all.len(), 2);
     }
 
-    #[test]
-    fn test_get_min_lsn() {
+    #[tokio::test]
+    async fn test_get_min_lsn() {
         let store = SqliteStateStore::in_memory().unwrap();
 
-        assert!(store.get_min_lsn().unwrap().is_none());
+        assert!(store.get_min_lsn().await.unwrap().is_none());
 
         store
-            .save_checkpoint("mapping1", &Checkpoint { lsn: 300, events_processed: 0 })
+            .save_checkpoint("mapping1", &checkpoint(300, 0))
+            .await
+            .unwrap();
+        store
+            .save_checkpoint("mapping2", &checkpoint(100, 0))
+            .await
+            .unwrap();
+        store
+            .save_checkpoint("mapping3", &checkpoint(200, 0))
+            .await
             .unwrap();
+
+        assert_eq!(store.get_min_lsn().await.unwrap(), Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_survive_checkpoint_saves() {
+        let store = SqliteStateStore::in_memory().unwrap();
+
+        store
+            .save_checkpoint("mapping1", &checkpoint(100, 1))
+            .await
+            .unwrap();
+        store
+            .record_reconnect(&["mapping1".to_string()])
+            .await
+            .unwrap();
+        store
+            .save_checkpoint("mapping1", &checkpoint(200, 2))
+            .await
+            .unwrap();
+
+        let loaded = store.get_checkpoint("mapping1").await.unwrap().unwrap();
+        assert_eq!(loaded.reconnects, 1);
+        assert!(loaded.last_reconnect_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dlq_roundtrip() {
+        let store = SqliteStateStore::in_memory().unwrap();
+        let event = serde_json::json!({"op": "insert", "new": {"id": 1}});
+
+        let id = store
+            .add_to_dlq(
+                "users",
+                42,
+                &event,
+                "transform failed",
+                "transform_error",
+                Some("run-1"),
+            )
+            .await
+            .unwrap();
+        store
+            .add_to_dlq("orders", 43, &event, "bad id", "invalid_data", None)
+            .await
+            .unwrap();
+
+        let entry = store.get_dlq_entry(id).await.unwrap().unwrap();
+        assert_eq!(entry.mapping_name, "users");
+        assert_eq!(entry.lsn, 42);
+        assert_eq!(entry.event_json, event);
+        assert_eq!(entry.run_id.as_deref(), Some("run-1"));
+
+        store.increment_dlq_retry(id).await.unwrap();
+        assert_eq!(
+            store.get_dlq_entry(id).await.unwrap().unwrap().retry_count,
+            1
+        );
+
+        store
+            .record_dlq_failure(id, "still failing", "invalid_data")
+            .await
+            .unwrap();
+        let entry = store.get_dlq_entry(id).await.unwrap().unwrap();
+        assert_eq!(entry.retry_count, 2);
+        assert_eq!(entry.error_message, "still failing");
+        assert_eq!(entry.error_kind, "invalid_data");
+
+        assert_eq!(store.get_dlq_entries(None, 10).await.unwrap().len(), 2);
+        assert_eq!(
+            store
+                .get_dlq_entries(Some("users"), 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert_eq!(store.clear_dlq(Some("users")).await.unwrap(), 1);
+        store.delete_dlq_entry(id).await.unwrap();
+        assert_eq!(store.clear_dlq(None).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_progress_roundtrip() {
+        let store = SqliteStateStore::in_memory().unwrap();
+        let mut progress = BackfillProgress {
+            mapping_name: "users".into(),
+            last_id: Some("100".into()),
+            total_rows: Some(1000),
+            processed_rows: 100,
+            upserted_rows: 90,
+            rows_per_second: Some(50.0),
+            status: "in_progress".into(),
+            updated_at: chrono::Utc::now(),
+            run_id: Some("run-1".into()),
+        };
+        store.save_backfill_progress(&progress).await.unwrap();
+
+        progress.status = "completed".into();
+        progress.run_id = Some("run-2".into());
+        store.save_backfill_progress(&progress).await.unwrap();
+
+        let loaded = store.get_backfill_progress("users").await.unwrap().unwrap();
+        assert_eq!(loaded.last_id.as_deref(), Some("100"));
+        assert_eq!(loaded.upserted_rows, 90);
+        assert_eq!(loaded.status, "completed");
+        assert_eq!(loaded.run_id.as_deref(), Some("run-2"));
+
+        store.clear_backfill_progress("users").await.unwrap();
+        assert!(store
+            .get_backfill_progress("users")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_partitions_roundtrip() {
+        let store = SqliteStateStore::in_memory().unwrap();
+        let ranges = [(1, None, Some("500")), (2, Some("500"), None)];
+        let mut partitions: Vec<BackfillPartition> = ranges
+            .into_iter()
+            .map(|(partition, start, end)| BackfillPartition {
+                mapping_name: "users".into(),
+                partition,
+                range_start: start.map(str::to_string),
+                range_end: end.map(str::to_string),
+                last_id: None,
+                processed_rows: 0,
+                upserted_rows: 0,
+                status: "in_progress".into(),
+                updated_at: chrono::Utc::now(),
+            })
+            .collect();
+        for partition in partitions.iter().rev() {
+            store.save_backfill_partition(partition).await.unwrap();
+        }
+
+        partitions[0].last_id = Some("499".into());
+        partitions[0].processed_rows = 499;
+        partitions[0].status = "completed".into();
+        store.save_backfill_partition(&partitions[0]).await.unwrap();
+
+        let loaded = store.get_backfill_partitions("users").await.unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].partition, 1);
+        assert_eq!(loaded[0].last_id.as_deref(), Some("499"));
+        assert_eq!(loaded[0].status, "completed");
+        assert_eq!(loaded[1].range_start.as_deref(), Some("500"));
+        assert!(loaded[1].range_end.is_none());
+
+        store.clear_backfill_progress("users").await.unwrap();
+        assert!(store
+            .get_backfill_partitions("users")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let store = SqliteStateStore::in_memory().unwrap();
+        assert!(store.get_paused_mappings().await.unwrap().is_empty());
+
+        store.set_paused("users", true).await.unwrap();
+        store.set_paused("posts", true).await.unwrap();
+        assert_eq!(
+            store.get_paused_mappings().await.unwrap(),
+            vec!["posts", "users"]
+        );
+
+        store.set_paused("posts", false).await.unwrap();
+        assert_eq!(store.get_paused_mappings().await.unwrap(), vec!["users"]);
+    }
+
+    #[tokio::test]
+    async fn test_transforms_are_immutable() {
+        let store = SqliteStateStore::in_memory().unwrap();
+
         store
-            .save_checkpoint("mapping2", &Checkpoint { lsn: 100, events_processed: 0 })
+            .store_transform("users", 1, "v1", "hash1")
+            .await
             .unwrap();
         store
-            .save_checkpoint("mapping3", &Checkpoint { lsn: 200, events_processed: 0 })
+            .store_transform("users", 1, "changed", "hash2")
+            .await
             .unwrap();
 
-        assert_eq!(store.get_min_lsn().unwrap(), Some(100));
+        let stored = store.get_transform("users", 1).await.unwrap().unwrap();
+        assert_eq!(stored.content, "v1");
+        assert_eq!(store.get_all_transforms().await.unwrap().len(), 1);
     }
 }
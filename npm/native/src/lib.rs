@@ -0,0 +1,300 @@
+//! Node bindings for puffgres, built with Neon.
+//!
+//! `runBackfill` runs a mapping's backfill like `puffgres backfill`, on its
+//! own thread and tokio runtime so the Node event loop keeps going, and
+//! reports progress back to JavaScript through a Neon channel.
+//!
+//! `parsePredicate` and `evaluatePredicate` expose the membership predicate
+//! parser, so editors can check a predicate and preview which rows it keeps
+//! without a second implementation in TypeScript.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context as _, Result};
+use neon::prelude::*;
+use tracing::Instrument;
+
+use puffgres_cli::backfill::{run_backfill, BackfillLimits};
+use puffgres_cli::config::{self, ProjectConfig};
+use puffgres_cli::strict::StrictMode;
+use puffgres_cli::{env, run_id, validation};
+use puffgres_core::{Predicate, RowMap, Value};
+use puffgres_pg::{BackfillObserver, BackfillScanProgress, PostgresStateStore};
+
+/// Rows read per query unless `batchSize` is given, as for `puffgres backfill`.
+const DEFAULT_BATCH_SIZE: u32 = 1000;
+
+#[neon::main]
+fn main(mut cx: ModuleContext) -> NeonResult<()> {
+    cx.export_function("runBackfill", js_run_backfill)?;
+    cx.export_function("parsePredicate", js_parse_predicate)?;
+    cx.export_function("evaluatePredicate", js_evaluate_predicate)?;
+    Ok(())
+}
+
+/// `parsePredicate(dsl)`: the columns a predicate reads and its SQL form
+/// (null when it has none). Throws on a syntax error.
+fn js_parse_predicate(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let dsl = cx.argument::<JsString>(0)?.value(&mut cx);
+    let predicate = match Predicate::parse(&dsl) {
+        Ok(predicate) => predicate,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let object = cx.empty_object();
+    let columns = cx.empty_array();
+    for (i, column) in predicate.columns().into_iter().enumerate() {
+        let column = cx.string(column);
+        columns.set(&mut cx, i as u32, column)?;
+    }
+    object.set(&mut cx, "columns", columns)?;
+    let sql: Handle<JsValue> = match predicate.sql_form() {
+        Some(sql) => cx.string(sql).upcast(),
+        None => cx.null().upcast(),
+    };
+    object.set(&mut cx, "sql", sql)?;
+    Ok(object)
+}
+
+/// `evaluatePredicate(dsl, rowJson)`: whether the row, as JSON text, matches
+/// the predicate. The TypeScript wrapper serializes the row.
+fn js_evaluate_predicate(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let dsl = cx.argument::<JsString>(0)?.value(&mut cx);
+    let row_json = cx.argument::<JsString>(1)?.value(&mut cx);
+    let predicate = match Predicate::parse(&dsl) {
+        Ok(predicate) => predicate,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+    let row: HashMap<String, serde_json::Value> = match serde_json::from_str(&row_json) {
+        Ok(row) => row,
+        Err(e) => return cx.throw_type_error(format!("row must be an object: {}", e)),
+    };
+
+    let row: RowMap = row.into_iter().map(|(k, v)| (k, Value::from(v))).collect();
+    Ok(cx.boolean(predicate.evaluate(&row)))
+}
+
+/// `runBackfill(mapping, options?, onProgress?)`: backfill a mapping from the
+/// project in the current directory. Resolves with the final progress.
+fn js_run_backfill(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let mapping = cx.argument::<JsString>(0)?.value(&mut cx);
+    let options = BackfillOptions::from_js(&mut cx)?;
+    let on_progress = match cx.argument_opt(2) {
+        Some(value) if !value.is_a::<JsUndefined, _>(&mut cx) => Some(
+            value
+                .downcast_or_throw::<JsFunction, _>(&mut cx)?
+                .root(&mut cx),
+        ),
+        _ => None,
+    };
+
+    let channel = cx.channel();
+    let (deferred, promise) = cx.promise();
+    let observer = JsProgress {
+        channel: channel.clone(),
+        callback: on_progress.map(Arc::new),
+        last: Mutex::new(None),
+    };
+
+    std::thread::spawn(move || {
+        let result = tokio::runtime::Runtime::new()
+            .context("Failed to start the backfill runtime")
+            .and_then(|runtime| runtime.block_on(backfill(&mapping, &options, &observer)));
+        let last = observer.last.lock().unwrap().take();
+
+        deferred.settle_with(&channel, move |mut cx| {
+            // Progress calls ran before this one, so the callback is only
+            // held here, and is released on the JavaScript thread
+            if let Some(root) = observer.callback.and_then(|c| Arc::try_unwrap(c).ok()) {
+                root.drop(&mut cx);
+            }
+            if let Err(e) = result {
+                return cx.throw_error(format!("{:#}", e));
+            }
+            match last {
+                Some(progress) => Ok(progress_object(&mut cx, &progress)?.upcast::<JsValue>()),
+                None => Ok(cx.undefined().upcast()),
+            }
+        });
+    });
+
+    Ok(promise)
+}
+
+/// The `options` argument of `runBackfill`.
+struct BackfillOptions {
+    batch_size: u32,
+    resume: bool,
+    concurrency: Option<usize>,
+    /// Environment name, as with `--env`.
+    env: Option<String>,
+    strict: Option<String>,
+}
+
+impl BackfillOptions {
+    fn from_js(cx: &mut FunctionContext) -> NeonResult<Self> {
+        let mut options = Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            resume: false,
+            concurrency: None,
+            env: None,
+            strict: None,
+        };
+        let object = match cx.argument_opt(1) {
+            Some(value) if !value.is_a::<JsUndefined, _>(cx) => {
+                value.downcast_or_throw::<JsObject, _>(cx)?
+            }
+            _ => return Ok(options),
+        };
+
+        if let Some(n) = object.get_opt::<JsNumber, _, _>(cx, "batchSize")? {
+            let n = n.value(cx);
+            if n < 1.0 || n > u32::MAX as f64 {
+                return cx.throw_range_error("batchSize must be a positive integer");
+            }
+            options.batch_size = n as u32;
+        }
+        if let Some(b) = object.get_opt::<JsBoolean, _, _>(cx, "resume")? {
+            options.resume = b.value(cx);
+        }
+        if let Some(n) = object.get_opt::<JsNumber, _, _>(cx, "concurrency")? {
+            let n = n.value(cx);
+            if !(1.0..=u16::MAX as f64).contains(&n) {
+                return cx.throw_range_error("concurrency must be between 1 and 65535");
+            }
+            options.concurrency = Some(n as usize);
+        }
+        if let Some(s) = object.get_opt::<JsString, _, _>(cx, "env")? {
+            options.env = Some(s.value(cx));
+        }
+        if let Some(s) = object.get_opt::<JsString, _, _>(cx, "strict")? {
+            options.strict = Some(s.value(cx));
+        }
+        Ok(options)
+    }
+}
+
+/// Set up the project like the CLI does and run the backfill.
+async fn backfill(
+    mapping_name: &str,
+    options: &BackfillOptions,
+    observer: &JsProgress,
+) -> Result<()> {
+    env::validate_project_directory()?;
+    // Deployment scripts often set the variables themselves, so a missing
+    // .env isn't an error here; missing variables are reported when used
+    let _ = env::load_dotenv_from_ancestors(options.env.as_deref());
+    let profile = config::load_profile(Path::new("."), options.env.as_deref())?;
+    let providers = config::load_providers(Path::new("."))?;
+    let config = ProjectConfig::from_env(&profile, &providers);
+    let strict = StrictMode::resolve(options.strict.as_deref(), env::get_strict().as_deref())?;
+
+    let store = PostgresStateStore::connect(&config.postgres_connection_string()?)
+        .await
+        .context("Failed to connect to Postgres")?;
+    validation::validate_transforms(&config, &store)
+        .await
+        .context("Applied migrations have been modified locally; run `puffgres reset`")?;
+
+    let mappings = config.load_migrations()?;
+    let mapping = mappings
+        .iter()
+        .find(|m| m.name == mapping_name)
+        .with_context(|| format!("Mapping '{}' not found", mapping_name))?;
+    let (schema, table) = (&mapping.source.schema, &mapping.source.table);
+    if !store.table_exists(schema, table).await? {
+        anyhow::bail!(
+            "Table '{}.{}' referenced in mapping '{}' does not exist",
+            schema,
+            table,
+            mapping_name
+        );
+    }
+
+    run_backfill(
+        &config,
+        Arc::new(store),
+        mapping,
+        options.batch_size,
+        options.resume,
+        options.concurrency,
+        1,
+        &BackfillLimits::default(),
+        &strict,
+        None,
+        observer,
+    )
+    .instrument(run_id::span())
+    .await
+}
+
+/// Passes backfill progress to the JavaScript `onProgress` callback.
+struct JsProgress {
+    channel: Channel,
+    callback: Option<Arc<Root<JsFunction>>>,
+    /// The latest progress, which the promise resolves with.
+    last: Mutex<Option<BackfillScanProgress>>,
+}
+
+impl JsProgress {
+    fn report(&self, progress: &BackfillScanProgress) {
+        *self.last.lock().unwrap() = Some(progress.clone());
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let callback = Arc::clone(callback);
+        let progress = progress.clone();
+        self.channel.send(move |mut cx| {
+            let object = progress_object(&mut cx, &progress)?;
+            callback
+                .to_inner(&mut cx)
+                .call_with(&cx)
+                .arg(object)
+                .exec(&mut cx)
+        });
+    }
+}
+
+impl BackfillObserver for JsProgress {
+    fn on_progress(&self, progress: &BackfillScanProgress) {
+        self.report(progress);
+    }
+
+    fn on_complete(&self, progress: &BackfillScanProgress) {
+        self.report(progress);
+    }
+}
+
+/// Progress as the JavaScript `BackfillProgressEvent`.
+fn progress_object<'a>(
+    cx: &mut impl Context<'a>,
+    progress: &BackfillScanProgress,
+) -> JsResult<'a, JsObject> {
+    let object = cx.empty_object();
+
+    let processed = cx.number(progress.processed_rows as f64);
+    object.set(cx, "processed", processed)?;
+    let upserted = cx.number(progress.upserted_rows as f64);
+    object.set(cx, "upserted", upserted)?;
+    let total = optional_number(cx, progress.total_rows.map(|t| t as f64));
+    object.set(cx, "total", total)?;
+    let rate = cx.number(progress.rows_per_second);
+    object.set(cx, "rate", rate)?;
+    let percent = cx.number(progress.percent_complete);
+    object.set(cx, "percent", percent)?;
+    let elapsed = cx.number(progress.elapsed_secs);
+    object.set(cx, "elapsedSecs", elapsed)?;
+    let eta = optional_number(cx, progress.eta_secs);
+    object.set(cx, "etaSecs", eta)?;
+
+    Ok(object)
+}
+
+fn optional_number<'a>(cx: &mut impl Context<'a>, value: Option<f64>) -> Handle<'a, JsValue> {
+    match value {
+        Some(n) => cx.number(n).upcast(),
+        None => cx.null().upcast(),
+    }
+}
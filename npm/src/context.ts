@@ -1,25 +1,65 @@
 /**
  * Transform context implementation.
  *
- * Provides fetch() and lookup() capabilities to transforms.
+ * Provides fetch(), query() and lookup() capabilities to transforms.
  */
 
 import type { TransformContext, DocumentId, MigrationInfo } from '../types/index.js';
 
+/** Default number of ctx.query() calls allowed per event in a batch. */
+export const DEFAULT_QUERY_BUDGET_PER_EVENT = 5;
+
 export interface ContextConfig {
   /** Migration info */
   migration: MigrationInfo;
   /** Environment variables */
   env?: Record<string, string>;
-  /** Postgres connection string for lookup */
+  /** Postgres connection string for query() and lookup() */
   postgresConnectionString?: string;
+  /** Maximum query() calls per event (the batch budget is this times eventCount) */
+  queryBudgetPerEvent?: number;
+  /** Number of events in the batch this context is used for */
+  eventCount?: number;
+}
+
+/** Minimal shape of a pg client used by the context. */
+interface QueryClient {
+  query(sql: string, params?: unknown[]): Promise<{ rows: Record<string, unknown>[] }>;
+  end(): Promise<void>;
+}
+
+/** Lazily opened database connections, keyed by context so they can be closed. */
+const connections = new WeakMap<TransformContext, Promise<QueryClient>>();
+
+/**
+ * Open a read-only connection. Every transaction on the session is read-only,
+ * so transforms can't modify the source database.
+ */
+async function connectReadOnly(connectionString: string): Promise<QueryClient> {
+  // Dynamic import to avoid requiring pg at module load time
+  const pg = await import('pg');
+  const client = new pg.default.Client({ connectionString });
+  await client.connect();
+  await client.query('SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY');
+  return client;
+}
+
+/** Quote a possibly schema-qualified table name (e.g. public.users -> "public"."users"). */
+function quoteTableName(table: string): string {
+  return table
+    .split('.')
+    .map((part) => `"${part.replace(/"/g, '""')}"`)
+    .join('.');
 }
 
 /**
  * Create a transform context with the given configuration.
  */
 export function createTransformContext(config: ContextConfig): TransformContext {
-  return {
+  const budget = (config.queryBudgetPerEvent ?? DEFAULT_QUERY_BUDGET_PER_EVENT) * (config.eventCount ?? 1);
+  let queriesUsed = 0;
+
+  const ctx: TransformContext = {
     migration: config.migration,
     env: config.env ?? { ...process.env } as Record<string, string>,
 
@@ -27,14 +67,49 @@ export function createTransformContext(config: ContextConfig): TransformContext
       return globalThis.fetch(url, options);
     },
 
-    async lookup(table: string, id: DocumentId): Promise<Record<string, unknown> | null> {
+    async query<T = Record<string, unknown>>(sql: string, params: unknown[] = []): Promise<T[]> {
       if (!config.postgresConnectionString) {
-        throw new Error('Postgres connection not configured for lookup');
+        throw new Error('Postgres connection not configured for query()');
       }
 
-      // This would use pg or a similar library
-      // For now, throw an error indicating it's not implemented
-      throw new Error('lookup() is not yet implemented');
+      if (queriesUsed >= budget) {
+        throw new Error(
+          `Query budget exceeded: ${budget} queries for ${config.eventCount ?? 1} events ` +
+            `(${config.queryBudgetPerEvent ?? DEFAULT_QUERY_BUDGET_PER_EVENT} per event). ` +
+            'Batch lookups with WHERE id = ANY($1) or raise PUFFGRES_TRANSFORM_QUERY_BUDGET.'
+        );
+      }
+      queriesUsed++;
+
+      let connection = connections.get(ctx);
+      if (!connection) {
+        connection = connectReadOnly(config.postgresConnectionString);
+        connections.set(ctx, connection);
+      }
+
+      const client = await connection;
+      const result = await client.query(sql, params);
+      return result.rows as T[];
+    },
+
+    async lookup(table: string, id: DocumentId): Promise<Record<string, unknown> | null> {
+      const rows = await ctx.query(`SELECT * FROM ${quoteTableName(table)} WHERE id = $1 LIMIT 1`, [id]);
+      return rows[0] ?? null;
     },
   };
+
+  return ctx;
+}
+
+/**
+ * Close the database connection opened by a context, if any.
+ */
+export async function closeTransformContext(ctx: TransformContext): Promise<void> {
+  const connection = connections.get(ctx);
+  if (!connection) {
+    return;
+  }
+  connections.delete(ctx);
+  const client = await connection;
+  await client.end();
 }
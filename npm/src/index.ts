@@ -3,7 +3,7 @@
  *
  * This package provides:
  * - TypeScript types for transforms
- * - Transform context with embed(), fetch(), lookup()
+ * - Transform context with fetch(), query(), lookup()
  * - Native bindings via Neon (optional)
  */
 
@@ -20,8 +20,15 @@ export type {
   Checkpoint,
   DlqEntry,
   BackfillProgress,
+  BackfillOptions,
+  BackfillProgressEvent,
+  BackfillProgressCallback,
+  ParsedPredicate,
 } from '../types/index.js';
 
 // Transform utilities
-export { createTransformContext } from './context.js';
+export { closeTransformContext, createTransformContext } from './context.js';
 export { loadTransform } from './transform-runner.js';
+
+// Native bindings
+export { runBackfill, parsePredicate, evaluatePredicate } from './native.js';
@@ -0,0 +1,77 @@
+/**
+ * Native bindings, built from ./native with Neon.
+ *
+ * The addon is optional: it is only present after `npm run build:native`,
+ * and every binding throws a clear error when it is missing.
+ */
+
+import { createRequire } from 'node:module';
+import type {
+  BackfillOptions,
+  BackfillProgressEvent,
+  BackfillProgressCallback,
+  ParsedPredicate,
+} from '../types/index.js';
+
+interface NativeAddon {
+  runBackfill(
+    mapping: string,
+    options?: BackfillOptions,
+    onProgress?: BackfillProgressCallback
+  ): Promise<BackfillProgressEvent | undefined>;
+  parsePredicate(dsl: string): ParsedPredicate;
+  evaluatePredicate(dsl: string, rowJson: string): boolean;
+}
+
+let addon: NativeAddon | undefined;
+
+function loadAddon(): NativeAddon {
+  if (!addon) {
+    const require = createRequire(import.meta.url);
+    try {
+      addon = require('../native/index.node') as NativeAddon;
+    } catch (err) {
+      throw new Error(
+        `puffgres native bindings are not built (run \`npm run build:native\`): ${
+          err instanceof Error ? err.message : String(err)
+        }`
+      );
+    }
+  }
+  return addon;
+}
+
+/**
+ * Backfill a mapping from the puffgres project in the current directory,
+ * like `puffgres backfill <mapping>`.
+ *
+ * The backfill runs on a native worker thread. `onProgress` is called on the
+ * event loop as batches complete, and the promise resolves with the final
+ * progress once every row has been written (undefined if the table was empty).
+ */
+export function runBackfill(
+  mapping: string,
+  options: BackfillOptions = {},
+  onProgress?: BackfillProgressCallback
+): Promise<BackfillProgressEvent | undefined> {
+  return loadAddon().runBackfill(mapping, options, onProgress);
+}
+
+/**
+ * Parse a membership predicate with the same parser `puffgres` uses.
+ *
+ * Returns the columns it reads and its SQL form, or throws with the parse
+ * error (for example to underline an invalid predicate in an editor).
+ */
+export function parsePredicate(dsl: string): ParsedPredicate {
+  return loadAddon().parsePredicate(dsl);
+}
+
+/**
+ * Whether a row, keyed by column name, matches a membership predicate, as
+ * the runner would decide it. The row is passed as JSON, so a Date compares
+ * as its ISO string. Throws if the predicate doesn't parse.
+ */
+export function evaluatePredicate(dsl: string, row: Record<string, unknown>): boolean {
+  return loadAddon().evaluatePredicate(dsl, JSON.stringify(row));
+}
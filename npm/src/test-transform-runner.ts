@@ -40,7 +40,7 @@ loadEnvFromParents();
 import { faker } from '@faker-js/faker';
 import { parse as parseToml } from 'toml';
 import type { RowEvent, Action, TransformContext, DocumentId, TransformInput } from '../types/index.js';
-import { createTransformContext, type ContextConfig } from './context.js';
+import { closeTransformContext, createTransformContext, type ContextConfig } from './context.js';
 
 interface ColumnSchema {
   name: string;
@@ -534,10 +534,16 @@ async function main(): Promise<void> {
           const contextConfig: ContextConfig = {
             migration: migrationInfo,
             env: process.env as Record<string, string>,
+            postgresConnectionString: connectionString,
+            eventCount: testRows.length,
           };
           const ctx = createTransformContext(contextConfig);
           const rows: TransformInput[] = testRows.map(({ event, id }) => ({ event, id }));
-          results = await transform(rows, ctx);
+          try {
+            results = await transform(rows, ctx);
+          } finally {
+            await closeTransformContext(ctx);
+          }
         }
       }
     }
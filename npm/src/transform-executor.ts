@@ -15,7 +15,7 @@
 
 import { resolve } from 'path';
 import type { RowEvent, Action, TransformContext, DocumentId, MigrationInfo, TransformInput } from '../types/index.js';
-import { createTransformContext, type ContextConfig } from './context.js';
+import { closeTransformContext, createTransformContext, type ContextConfig } from './context.js';
 
 /**
  * Read all data from stdin.
@@ -58,14 +58,23 @@ async function main(): Promise<void> {
       throw new Error(`Transform at ${transformPath} must export a default function`);
     }
 
-    // Create context from environment
+    // Create context from environment. Lookups use a dedicated read-only URL if set.
+    const budget = parseInt(process.env.PUFFGRES_TRANSFORM_QUERY_BUDGET ?? '', 10);
     const ctx = createTransformContext({
       env: process.env as Record<string, string>,
       migration,
+      postgresConnectionString: process.env.PUFFGRES_TRANSFORM_DATABASE_URL || process.env.DATABASE_URL,
+      queryBudgetPerEvent: Number.isFinite(budget) && budget >= 0 ? budget : undefined,
+      eventCount: rows.length,
     });
 
     // Execute the transform with the batch of rows
-    const results: Action[] = await transform(rows, ctx);
+    let results: Action[];
+    try {
+      results = await transform(rows, ctx);
+    } finally {
+      await closeTransformContext(ctx);
+    }
 
     // Output the results
     console.log(JSON.stringify(results));
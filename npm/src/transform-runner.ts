@@ -105,7 +105,7 @@ export async function executeTransform(
       throw new Error(`Transform must return exactly ${rows.length} actions, got ${results.length}`);
     }
 
-    const validTypes = ['upsert', 'delete', 'skip'];
+    const validTypes = ['upsert', 'patch', 'delete', 'skip'];
 
     for (let i = 0; i < results.length; i++) {
       const result = results[i];
@@ -126,6 +126,10 @@ export async function executeTransform(
         throw new Error(`Upsert action at index ${i} must have "id" and "doc" properties`);
       }
 
+      if (result.type === 'patch' && !('id' in result && 'doc' in result)) {
+        throw new Error(`Patch action at index ${i} must have "id" and "doc" properties`);
+      }
+
       if (result.type === 'delete' && !('id' in result)) {
         throw new Error(`Delete action at index ${i} must have "id" property`);
       }
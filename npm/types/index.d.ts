@@ -34,7 +34,20 @@ export type DistanceMetric = 'cosine_distance' | 'euclidean_squared';
  * Action returned from transform function.
  */
 export type Action =
-  | { type: 'upsert'; id: DocumentId; doc: Record<string, unknown>; distance_metric?: DistanceMetric }
+  | {
+      type: 'upsert';
+      id: DocumentId;
+      doc: Record<string, unknown>;
+      /** Written to the namespace's vector column; all vectors in a namespace need the same length */
+      vector?: number[];
+      distance_metric?: DistanceMetric;
+    }
+  | {
+      type: 'patch';
+      id: DocumentId;
+      /** Attributes to set; the document's other attributes and vector are kept */
+      doc: Record<string, unknown>;
+    }
   | { type: 'delete'; id: DocumentId }
   | { type: 'skip' };
 
@@ -73,7 +86,20 @@ export interface TransformContext {
   fetch(url: string, options?: RequestInit): Promise<Response>;
 
   /**
-   * Look up a row from Postgres by ID.
+   * Run a read-only SQL query against the source database.
+   *
+   * Useful for denormalizing related data, e.g. looking up the author name
+   * for a post. Each batch gets a budget of queries per event
+   * (PUFFGRES_TRANSFORM_QUERY_BUDGET, default 5); prefer one query per batch
+   * with `WHERE id = ANY($1)` over one query per row.
+   * @param sql SQL query with $1, $2, ... placeholders
+   * @param params Query parameters
+   * @returns Result rows
+   */
+  query<T = Record<string, unknown>>(sql: string, params?: unknown[]): Promise<T[]>;
+
+  /**
+   * Look up a row from Postgres by ID. Counts against the query budget.
    * @param table Table name
    * @param id Row ID
    * @returns Row data or null if not found
@@ -171,3 +197,46 @@ export interface BackfillProgress {
   status: 'pending' | 'in_progress' | 'completed';
   updated_at: string;
 }
+
+/**
+ * Options for runBackfill().
+ */
+export interface BackfillOptions {
+  /** Rows read per query (default 1000) */
+  batchSize?: number;
+  /** Continue from the saved checkpoint instead of starting over */
+  resume?: boolean;
+  /** Concurrent turbopuffer uploads */
+  concurrency?: number;
+  /** Environment whose .env and puffgres.<env>.toml are loaded */
+  env?: string;
+  /** Strict mode, as with `--strict` */
+  strict?: string;
+}
+
+/**
+ * Progress reported by runBackfill().
+ */
+export interface BackfillProgressEvent {
+  processed: number;
+  upserted: number;
+  /** Estimated total rows, when Postgres has statistics for the table */
+  total: number | null;
+  /** Rows read per second */
+  rate: number;
+  percent: number;
+  elapsedSecs: number;
+  etaSecs: number | null;
+}
+
+export type BackfillProgressCallback = (progress: BackfillProgressEvent) => void;
+
+/**
+ * A membership predicate parsed by parsePredicate().
+ */
+export interface ParsedPredicate {
+  /** Columns the predicate reads, sorted */
+  columns: string[];
+  /** The predicate as a SQL condition, or null when it has no SQL form */
+  sql: string | null;
+}